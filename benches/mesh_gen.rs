@@ -1,94 +1,146 @@
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 use criterion::{Criterion, criterion_group, criterion_main};
 use matrixagon2::component::camera::Length3D;
-use matrixagon2::component::terrain::{BlockData, MeshType, TextureMapper, TransparencyType};
+use matrixagon2::component::terrain::{BlockData, FaceDir, MeshType, SoundGroup, TextureMapper, TransparencyType};
 use matrixagon2::component::terrain::chunk_gen_mf::ChunkGeneratorMF;
 use matrixagon2::component::terrain::terrain_gen::TerrainGenerator;
-use matrixagon2::chunk_mesh::{ChunkGeneratable, ChunkMesh, ChunkRadius, UpdateChunk};
+use matrixagon2::component::terrain::chunk_mesh::{ChunkGeneratable, ChunkMesh, ChunkPriority, ChunkRadius, ChunkUnload, UpdateChunk, VertexLimit, WorkerPool};
+use matrixagon2::component::texture::TextureIDs;
 
 
-const BLOCK_INDEX: [BlockData; 8] = [
+// in the block id order of the terrain generator (see resource/blocks.ron)
+const BLOCK_INDEX: [BlockData; 11] = [
     BlockData {
         ident: "grass_block",
         texture_id: TextureMapper::Lateral("grass_top", "dirt", "grass_side"),
         mesh: MeshType::Cube,
         transparency: TransparencyType::Opaque,
+        sound_group: SoundGroup::Grass,
+        back_face_culling: true,
+        animation: None,
     },
     BlockData {
         ident: "dirt",
         texture_id: TextureMapper::All("dirt"),
         mesh: MeshType::Cube,
         transparency: TransparencyType::Opaque,
+        sound_group: SoundGroup::Dirt,
+        back_face_culling: true,
+        animation: None,
     },
     BlockData {
         ident: "stone",
         texture_id: TextureMapper::All("stone"),
         mesh: MeshType::Cube,
         transparency: TransparencyType::Opaque,
+        sound_group: SoundGroup::Stone,
+        back_face_culling: true,
+        animation: None,
     },
     BlockData {
         ident: "sand",
         texture_id: TextureMapper::All("sand"),
         mesh: MeshType::Cube,
         transparency: TransparencyType::Opaque,
+        sound_group: SoundGroup::Sand,
+        back_face_culling: true,
+        animation: None,
     },
     BlockData {
         ident: "grass",
         texture_id: TextureMapper::All("grass_flora"),
         mesh: MeshType::XCross,
         transparency: TransparencyType::Transparent,
+        sound_group: SoundGroup::Grass,
+        back_face_culling: false,
+        animation: None,
     },
     BlockData {
         ident: "flower",
         texture_id: TextureMapper::All("flower"),
         mesh: MeshType::XCross,
         transparency: TransparencyType::Transparent,
+        sound_group: SoundGroup::Grass,
+        back_face_culling: false,
+        animation: None,
     },
     BlockData {
         ident: "water",
         texture_id: TextureMapper::All("water"),
         mesh: MeshType::Fluid,
         transparency: TransparencyType::Translucent,
+        sound_group: SoundGroup::Water,
+        back_face_culling: false,
+        animation: None,
     },
     BlockData {
         ident: "air",
         texture_id: TextureMapper::All("null"),
         mesh: MeshType::Empty,
         transparency: TransparencyType::Transparent,
+        sound_group: SoundGroup::Silent,
+        back_face_culling: true,
+        animation: None,
+    },
+    BlockData {
+        ident: "stone_slab",
+        texture_id: TextureMapper::All("stone"),
+        mesh: MeshType::Slab(false),
+        transparency: TransparencyType::Opaque,
+        sound_group: SoundGroup::Stone,
+        back_face_culling: true,
+        animation: None,
+    },
+    BlockData {
+        ident: "stone_stairs",
+        texture_id: TextureMapper::All("stone"),
+        mesh: MeshType::Stairs(FaceDir::BACK),
+        transparency: TransparencyType::Opaque,
+        sound_group: SoundGroup::Stone,
+        back_face_culling: true,
+        animation: None,
+    },
+    BlockData {
+        ident: "sandstone",
+        texture_id: TextureMapper::All("sandstone"),
+        mesh: MeshType::Cube,
+        transparency: TransparencyType::Opaque,
+        sound_group: SoundGroup::Stone,
+        back_face_culling: true,
+        animation: None,
     },
 ];
 
 
+// same MF tier as the terrain with the default 32 blox chunk size (4x larger chunks, downsampled by 2, next to the HF tier)
+fn mf_generator() -> ChunkGeneratorMF<'static> {
+    ChunkGeneratorMF::new(
+        Vec::from(BLOCK_INDEX),
+        Arc::new(TextureIDs::default()),
+        Arc::new(TerrainGenerator::new(0, 20.0, 1.0, true)),
+        128, 2, vec![1],
+    )
+}
+
 pub fn benchmark_chunk_mesh_generation(c: &mut Criterion) {
     c.bench_function(
         "Single MF Chunk @(0,0,0) - Mesh Generation",
         |b| b.iter_with_large_drop(|| {
-            let chunk_generator = ChunkGeneratorMF::new(
-                Vec::from(BLOCK_INDEX),
-                Rc::new(HashMap::from([
-                    (String::from("grass_top"), 0),
-                ])),
-                Rc::new(TerrainGenerator::new())
-            );
-            chunk_generator.generate_mesh(Length3D::origin());
+            let chunk_generator = mf_generator();
+            chunk_generator.generate_mesh(Length3D::origin())
         })
     );
 }
 
 pub fn benchmark_chunk_aggregate_mesh(c: &mut Criterion) {
     c.bench_function(
-        "Chunk Mesh Handler 4x4x4 MF - Mesh Generation & Aggregation",
+        "Chunk Mesh Handler 1 MF Radius - Mesh Generation & Aggregation",
         |b| b.iter_with_large_drop(|| {
             let mut chunk_mesh_mf = ChunkMesh::new(
                 Length3D::origin(),
-                ChunkRadius(2, 1), Some(ChunkRadius(2, 1)),
-                ChunkGeneratorMF::new(
-                    Vec::from(BLOCK_INDEX), Rc::new(HashMap::from([
-                        (String::from("grass_top"), 0),
-                    ])),
-                    Rc::new(TerrainGenerator::new())
-                ),
+                ChunkRadius(1, 1), Some(ChunkRadius(1, 1)),
+                mf_generator(),
+                WorkerPool::new(0), ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None,
             );
             chunk_mesh_mf.update(UpdateChunk::Forced);
         })
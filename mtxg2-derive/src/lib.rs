@@ -1,7 +1,7 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 
-use syn::{DeriveInput, parse_macro_input, Type, TypeArray, TypePath, Expr, ExprLit, Lit, LitInt, Ident};
+use syn::{DeriveInput, parse_macro_input, Type, TypeArray, TypePath, Expr, ExprLit, Lit, LitInt, LitStr, Attribute};
 use quote::quote;
 
 
@@ -16,16 +16,83 @@ fn convert_into_vulkan_type(data_type: String, len: usize) -> ash::vk::Format {
         ("u8", 3) => ash::vk::Format::R8G8B8_UNORM,
         ("u8", 2) => ash::vk::Format::R8G8_UNORM,
         ("u8", 1) => ash::vk::Format::R8_UNORM,
+        // integers are read as integers by the shaders (e.g. ivec/uvec inputs), not normalized
+        ("u32", 4) => ash::vk::Format::R32G32B32A32_UINT,
+        ("u32", 3) => ash::vk::Format::R32G32B32_UINT,
+        ("u32", 2) => ash::vk::Format::R32G32_UINT,
+        ("u32", 1) => ash::vk::Format::R32_UINT,
+        ("i32", 4) => ash::vk::Format::R32G32B32A32_SINT,
+        ("i32", 3) => ash::vk::Format::R32G32B32_SINT,
+        ("i32", 2) => ash::vk::Format::R32G32_SINT,
+        ("i32", 1) => ash::vk::Format::R32_SINT,
+        ("u16", 4) => ash::vk::Format::R16G16B16A16_UINT,
+        ("u16", 3) => ash::vk::Format::R16G16B16_UINT,
+        ("u16", 2) => ash::vk::Format::R16G16_UINT,
+        ("u16", 1) => ash::vk::Format::R16_UINT,
+        ("i16", 4) => ash::vk::Format::R16G16B16A16_SINT,
+        ("i16", 3) => ash::vk::Format::R16G16B16_SINT,
+        ("i16", 2) => ash::vk::Format::R16G16_SINT,
+        ("i16", 1) => ash::vk::Format::R16_SINT,
         _ => unimplemented!("Vertex Derive / Type Conversion: Unknown Possible Valid Type {:?} {:?}", data_type, len)
     }
 }
 
 
-#[proc_macro_derive(Vertex)]
+// binding index and input rate from the #[vertex(binding = 1, rate = "instance")] struct attribute,
+// defaulting to binding 0 and per vertex
+fn parse_vertex_binding(attrs: &[Attribute]) -> syn::Result<(u32, proc_macro2::TokenStream)> {
+    let mut binding = 0u32;
+    let mut rate = quote! { ash::vk::VertexInputRate::VERTEX };
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("binding") {
+                binding = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                Ok(())
+            } else if meta.path.is_ident("rate") {
+                let value = meta.value()?.parse::<LitStr>()?;
+                rate = match value.value().as_str() {
+                    "vertex" => quote! { ash::vk::VertexInputRate::VERTEX },
+                    "instance" => quote! { ash::vk::VertexInputRate::INSTANCE },
+                    _ => return Err(syn::Error::new(value.span(), "Vertex Derive / Rate: expected \"vertex\" or \"instance\"")),
+                };
+                Ok(())
+            } else {
+                Err(meta.error("Vertex Derive / Attribute: expected binding or rate"))
+            }
+        })?;
+    }
+
+    Ok((binding, rate))
+}
+
+
+// whether the field is left out of the vertex attributes with #[vertex(skip)] (e.g. padding or host-only data)
+fn parse_vertex_skip(attrs: &[Attribute]) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("Vertex Derive / Field Attribute: expected skip"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+
+#[proc_macro_derive(Vertex, attributes(vertex))]
 pub fn derive_vertex(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
     let name = input.ident;
+    let (binding, input_rate) = match parse_vertex_binding(&input.attrs) {
+        Ok(binding) => binding,
+        Err(err) => return TokenStream::from(err.to_compile_error()),
+    };
 
     let data = if let syn::Data::Struct(data) = input.data {
         data
@@ -33,7 +100,17 @@ pub fn derive_vertex(input: TokenStream) -> TokenStream {
         unimplemented!();
     };
 
-    let vertex_attribute_locations = data.fields.iter().map(|f| {
+    let mut fields = vec![];
+    for f in data.fields.iter() {
+        match parse_vertex_skip(&f.attrs) {
+            Ok(true) => {}
+            Ok(false) => fields.push(f),
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+        }
+    }
+
+    // the locations are assigned in order of the remaining fields
+    let vertex_attribute_locations = fields.into_iter().map(|f| {
         match &f.ty {
             Type::Array(TypeArray { elem, len: Expr::Lit(ExprLit {attrs: _, lit: Lit::Int(length)}), .. }) => {
                 if let Type::Path(TypePath {path, ..}) = (**elem).clone() {
@@ -45,7 +122,7 @@ pub fn derive_vertex(input: TokenStream) -> TokenStream {
                         let field_name = &f.ident.as_ref().expect("Field names are expected.");
                         quote! {
                             ash::vk::VertexInputAttributeDescription {
-                                binding: 0u32,
+                                binding: #binding,
                                 location: 0u32,
                                 format: #vk_format,
                                 offset: {
@@ -70,7 +147,7 @@ pub fn derive_vertex(input: TokenStream) -> TokenStream {
                     let field_name = &f.ident.as_ref().expect("Field names are expected.");
                     quote! {
                         ash::vk::VertexInputAttributeDescription {
-                            binding: 0u32,
+                            binding: #binding,
                             location: 0u32,
                             format: #vk_format,
                             offset: {
@@ -95,9 +172,9 @@ pub fn derive_vertex(input: TokenStream) -> TokenStream {
     let expanded = quote! {
         impl matrixagon_util::VulkanVertexState<{#field_count}> for #name {
             const BINDING_DESCRIPTION: ash::vk::VertexInputBindingDescription = ash::vk::VertexInputBindingDescription {
-                binding: 0,
+                binding: #binding,
                 stride: std::mem::size_of::<#name>() as u32,
-                input_rate: ash::vk::VertexInputRate::VERTEX,
+                input_rate: #input_rate,
             };
 
             const ATTRIBUTE_DESCRIPTION: [ash::vk::VertexInputAttributeDescription; #field_count] = unsafe {
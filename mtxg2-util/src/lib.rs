@@ -4,17 +4,71 @@ pub use matrixagon_derive::Vertex;
 
 
 
+// retired items (e.g. buffers replaced mid-run) held until the frame in flight that could still read them completes
+pub struct Garbage<T> {
+    frame_index: usize,  // of the frame recorded last
+    retired: Vec<(usize, T)>,  // along with the frame index they were retired in
+}
+
+impl<T> Garbage<T> {
+    pub fn new() -> Self {
+        Self { frame_index: 0, retired: Vec::new() }
+    }
+
+    pub fn retire(&mut self, item: T) {
+        self.retired.push((self.frame_index, item));
+    }
+
+    // once the fence of the frame index signaled, right before recording that frame again, hands back the items
+    // retired while it was the latest frame
+    pub fn collect(&mut self, frame_index: usize) -> Vec<T> {
+        self.frame_index = frame_index;
+        let (ready, retired) = std::mem::take(&mut self.retired).into_iter()
+            .partition(|(retired_frame, _)| *retired_frame == frame_index);
+        self.retired = retired;
+        ready.into_iter().map(|(_, item)| item).collect()
+    }
+
+    // every item regardless of its frame (e.g. for cleanup after the device is idle)
+    pub fn all(&self) -> impl Iterator<Item = &T> {
+        self.retired.iter().map(|(_, item)| item)
+    }
+}
+
+impl<T> Default for Garbage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
 pub struct IndexedBuffer {
     device: Rc<Device>,
 
     vbo: Option<([vk::Buffer; 1], vk::DeviceMemory)>,
     ibo: Option<(vk::Buffer, vk::DeviceMemory, u32)>,
+    // the replaced buffers, freed once the frames drawing them complete instead of waiting for the device to idle
+    garbage: Garbage<(vk::Buffer, vk::DeviceMemory)>,
+    owned: bool,  // otherwise the buffers belong to the pool of the uploader, which frees them
 }
 
 impl IndexedBuffer {
     pub fn new(device: Rc<Device>) -> Self {
         Self {
-            device, vbo: None, ibo: None
+            device, vbo: None, ibo: None, garbage: Garbage::new(), owned: true,
+        }
+    }
+
+    // of buffers written again by the uploader once no frame in flight draws them, so they are never freed here
+    pub fn pooled(device: Rc<Device>) -> Self {
+        Self {
+            owned: false, ..Self::new(device)
+        }
+    }
+
+    fn retire(&mut self, buf: vk::Buffer, mem: vk::DeviceMemory) {
+        if self.owned {
+            self.garbage.retire((buf, mem));
         }
     }
 
@@ -26,25 +80,43 @@ impl IndexedBuffer {
         }
     }
 
+    // the same buffer written again in place is kept
     pub unsafe fn recreate_vbo(&mut self, buf: [vk::Buffer; 1], mem: vk::DeviceMemory) {
-        if let Some((old_buf, old_mem)) = self.vbo {
-            self.device.device_wait_idle().unwrap();
-            self.device.destroy_buffer(old_buf[0], None);
-            self.device.free_memory(old_mem, None);
+        if let Some((old_buf, old_mem)) = self.vbo.filter(|(old_buf, _)| *old_buf != buf) {
+            self.retire(old_buf[0], old_mem);
         }
         self.vbo = Some((buf, mem));
     }
 
     pub unsafe fn recreate_ibo(&mut self, buf: vk::Buffer, mem: vk::DeviceMemory, len: u32) {
-        if let Some((old_buf, old_mem, _)) = self.ibo {
-            self.device.device_wait_idle().unwrap();
-            self.device.destroy_buffer(old_buf, None);
-            self.device.free_memory(old_mem, None);
+        if let Some((old_buf, old_mem, _)) = self.ibo.filter(|(old_buf, _, _)| *old_buf != buf) {
+            self.retire(old_buf, old_mem);
         }
         self.ibo = Some((buf, mem, len));
     }
 
+    // releases both buffers, so nothing gets drawn until they are recreated
+    pub unsafe fn clear(&mut self) {
+        if let Some((old_buf, old_mem)) = self.vbo.take() {
+            self.retire(old_buf[0], old_mem);
+        }
+        if let Some((old_buf, old_mem, _)) = self.ibo.take() {
+            self.retire(old_buf, old_mem);
+        }
+    }
+
+    // frees the buffers retired while the frame index was drawn last, once its fence signaled
+    pub unsafe fn collect_garbage(&mut self, frame_index: usize) {
+        for (buf, mem) in self.garbage.collect(frame_index) {
+            self.device.destroy_buffer(buf, None);
+            self.device.free_memory(mem, None);
+        }
+    }
+
     pub unsafe fn destroy(&self) {
+        if !self.owned {
+            return;
+        }
         if let Some((old_buf, old_mem)) = self.vbo {
             self.device.destroy_buffer(old_buf[0], None);
             self.device.free_memory(old_mem, None);
@@ -53,6 +125,10 @@ impl IndexedBuffer {
             self.device.destroy_buffer(old_buf, None);
             self.device.free_memory(old_mem, None);
         }
+        for (buf, mem) in self.garbage.all() {
+            self.device.destroy_buffer(*buf, None);
+            self.device.free_memory(*mem, None);
+        }
     }
 }
 
@@ -71,13 +147,13 @@ macro_rules! create_renderpass {
         Attachments {$(
             $attachment_names:ident: {
                 format: $format:expr,
-                samples: $samples:ident,
+                samples: $samples:tt,
                 load: $load:ident,
                 store: $store:ident,
                 stencil_load: $stencil_load:ident,
                 stencil_store: $stencil_store:ident,
                 initial: $initial:ident,
-                final: $final:ident,
+                final: $final:tt,
             }
         )*}
         Subpasses {$(
@@ -104,11 +180,11 @@ macro_rules! create_renderpass {
                 __attachment_count,
                 ash::vk::AttachmentDescription {
                     format: $format,
-                    samples: ash::vk::SampleCountFlags::$samples,
+                    samples: create_renderpass!(@SAMPLES $samples),
                     load_op: ash::vk::AttachmentLoadOp::$load, store_op: ash::vk::AttachmentStoreOp::$store,
                     stencil_load_op: ash::vk::AttachmentLoadOp::$stencil_load, stencil_store_op: ash::vk::AttachmentStoreOp::$stencil_store,
                     initial_layout: ash::vk::ImageLayout::$initial,
-                    final_layout: ash::vk::ImageLayout::$final,
+                    final_layout: create_renderpass!(@LAYOUT $final),
                     ..Default::default()
                 }
             );
@@ -185,6 +261,20 @@ macro_rules! create_renderpass {
             .build();
         $device.create_render_pass(&__renderpass_info, None).unwrap()
     }};
+    // either the name of the layout, or an expression of it in parentheses (e.g. chosen at runtime)
+    (@LAYOUT $layout:ident) => {
+        ash::vk::ImageLayout::$layout
+    };
+    (@LAYOUT ($layout:expr)) => {
+        $layout
+    };
+    // either the name of the sample count, or an expression of it in parentheses (e.g. the chosen MSAA level)
+    (@SAMPLES $samples:ident) => {
+        ash::vk::SampleCountFlags::$samples
+    };
+    (@SAMPLES ($samples:expr)) => {
+        $samples
+    };
     (@ATTACHMENT_REFS $($attachment_ref:ident~$attachment_ref_layout:ident)*) => {{
         [$(
            ash::vk::AttachmentReference {
@@ -209,3 +299,26 @@ macro_rules! create_renderpass {
         ash::vk::AccessFlags::empty() $(| ash::vk::AccessFlags::$access_flags)*
     }};
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_garbage_waits_for_its_frame() {
+        let mut garbage = Garbage::new();
+        garbage.collect(0);
+        garbage.retire("a");
+        garbage.collect(1);
+        garbage.retire("b");
+        garbage.retire("c");
+
+        // two frames in flight, each collecting what was retired while it was the latest frame
+        assert_eq!(garbage.collect(0), vec!["a"]);
+        assert_eq!(garbage.all().count(), 2);
+        assert_eq!(garbage.collect(1), vec!["b", "c"]);
+        assert!(garbage.collect(0).is_empty());
+        assert_eq!(garbage.all().count(), 0);
+    }
+}
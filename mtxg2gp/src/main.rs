@@ -1,17 +1,23 @@
 #[macro_use]
 extern crate matrixagon2;
 
-use matrixagon2::debug::DebugVisibility;
-use matrixagon2::MatrixagonApp;
+use matrixagon2::{AppConfig, MatrixagonApp, PresentMode, default_shader};
 
 fn main() {
-    let debug_visibility = DebugVisibility {
-        vk_setup_output: true,
-        vk_swapchain_output: false,
-        mtxg_output: true,
-        mtxg_render_output: false,
+    // the setup and app output by default, overridden by RUST_LOG (e.g. RUST_LOG=matrixagon2::vk_swapchain=trace)
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info,matrixagon2::vk_setup=debug,matrixagon2::mtxg=debug")
+    ).init();
+
+    let config = AppConfig::default()
+        .validate(true)
+        .present_mode(PresentMode::Mailbox);
+    let mtxg = match MatrixagonApp::init(config, default_shader) {
+        Ok(mtxg) => mtxg,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
     };
-    let mtxg = MatrixagonApp::init(true, debug_visibility, false, true);
-    // mtxg.load_shader(StandardRasterizer::new());
     mtxg.run();
 }
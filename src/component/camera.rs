@@ -2,16 +2,17 @@ use std::rc::Rc;
 use ash::{Device, vk};
 use winit::event::VirtualKeyCode;
 use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::component::physics::{BlockQuery, PhysicsBody};
 use crate::handler::VulkanInstance;
-use crate::util::{CmdBufContext, Mat4, matrix_prod};
+use crate::util::{CmdBufContext, Mat4, matrix_prod, matrix_prod_vector, matrix_transpose};
 use crate::world::{CardinalDir, WorldEvent};
-use std::{ffi, mem};
 use std::ops::{Add, Sub};
+use std::time::Duration;
 use uom::si;
 use uom::num_traits::Zero;
 use uom::si::f32::{Angle, Length};
 use crate::measurement::blox;
-use crate::util::{create_host_buffer, matrix_ident, update_buffer};
+use crate::util::{FrameUniform, matrix_ident};
 
 
 #[derive(Copy, Clone, Debug)]
@@ -64,6 +65,217 @@ impl Sub for Length3D {
 }
 
 
+// which screen axis the user supplied field of view spans, the other axis is derived from the aspect ratio
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FovAxis {
+    Vertical,
+    Horizontal,
+}
+
+impl FovAxis {
+    // the projection matrix is always built from the vertical fov (in radians)
+    pub(crate) fn vertical_fov(&self, fov: f32, aspect_ratio: f32) -> f32 {
+        match self {
+            FovAxis::Vertical => fov,
+            FovAxis::Horizontal => 2.0*((fov/2.0).tan()/aspect_ratio).atan(),
+        }
+    }
+}
+
+
+// translation speeds in blocks per second
+#[derive(Copy, Clone, Debug)]
+pub struct MovementSpeed {
+    pub walk: f32,
+    pub spectator: f32,
+    pub sprint_multiplier: f32,  // applied while the sprint key (left control) is held
+    // seconds for the spectator velocity to close most of the gap to the velocity of the held keys, instant if None
+    pub momentum: Option<f32>,
+}
+
+impl MovementSpeed {
+    // distance travelled (in blocks) within a frame
+    pub(crate) fn displacement(&self, delta: Duration, spectator: bool, sprinting: bool) -> f32 {
+        let speed = if spectator {self.spectator} else {self.walk};
+        let multiplier = if sprinting {self.sprint_multiplier} else {1.0};
+        delta.as_secs_f32()*speed*multiplier
+    }
+}
+
+// the spectator flight, sped up and slowed down with the mouse wheel and coasting with the momentum
+#[derive(Copy, Clone, Debug)]
+struct Flight {
+    scale: f32,  // of the spectator speed
+    velocity: [f32; 3],  // in blocks per second
+}
+
+impl Flight {
+    const SCALE_STEP: f32 = 1.25;  // per scrolled line
+    const MIN_SCALE: f32 = 0.125;
+    const MAX_SCALE: f32 = 16.0;
+    const REST_SPEED: f32 = 0.01;  // below which the coasting stops
+
+    fn new() -> Self {
+        Self { scale: 1.0, velocity: [0.0; 3] }
+    }
+
+    // returns whether the scale changed, so not while already at either end of the range
+    fn scroll(&mut self, lines: f32) -> bool {
+        let scale = (self.scale*Self::SCALE_STEP.powf(lines)).clamp(Self::MIN_SCALE, Self::MAX_SCALE);
+        let changed = scale != self.scale;
+        self.scale = scale;
+        changed
+    }
+
+    fn coasting(&self) -> bool {
+        self.velocity != [0.0; 3]
+    }
+
+    // eases the velocity towards the target velocity, returning the displacement (in blocks) within the frame
+    fn glide(&mut self, target: [f32; 3], delta: Duration, momentum: f32) -> [f32; 3] {
+        let dt = delta.as_secs_f32();
+        let blend = if momentum > 0.0 {1.0-(-dt/momentum).exp()} else {1.0};
+        for (v, t) in self.velocity.iter_mut().zip(target) {
+            *v += (t-*v)*blend;
+        }
+        if self.velocity.iter().map(|v| v*v).sum::<f32>().sqrt() < Self::REST_SPEED {
+            self.velocity = [0.0; 3];
+        }
+        self.velocity.map(|v| v*dt)
+    }
+}
+
+// how far away (in blocks) the user can pick blocks to break or place them
+#[derive(Copy, Clone, Debug)]
+pub struct Reach {
+    pub walk: f32,
+    pub spectator: f32,
+}
+
+impl Reach {
+    const MIN: f32 = 1.0;
+    const MAX: f32 = 128.0;  // about as far as the extreme fidelity chunks extend
+
+    pub(crate) fn distance(&self, spectator: bool) -> f32 {
+        let reach = if spectator {self.spectator} else {self.walk};
+        reach.clamp(Self::MIN, Self::MAX)
+    }
+}
+
+// how the mouse motion turns the camera
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MouseSettings {
+    pub sensitivity: f32,  // degrees per mouse count
+    pub invert_y: bool,  // moving the mouse down pitches up
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self { sensitivity: 0.05, invert_y: false }
+    }
+}
+
+impl MouseSettings {
+    pub(crate) const MIN_SENSITIVITY: f32 = 0.005;
+    pub(crate) const MAX_SENSITIVITY: f32 = 0.5;
+
+    // returns the clamped sensitivity
+    fn set_sensitivity(&mut self, sensitivity: f32) -> f32 {
+        self.sensitivity = sensitivity.clamp(Self::MIN_SENSITIVITY, Self::MAX_SENSITIVITY);
+        self.sensitivity
+    }
+
+    // yaw and pitch in degrees for the mouse motion
+    fn look(&self, (x, y): (f64, f64)) -> (f32, f32) {
+        let pitch = y as f32*self.sensitivity;
+        (x as f32*self.sensitivity, if self.invert_y {-pitch} else {pitch})
+    }
+}
+
+// field of view in degrees (along the fov axis), easing towards the set one or the zoomed in one
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FieldOfView {
+    current: f32,
+    set: f32,
+    zoomed: bool,
+}
+
+impl FieldOfView {
+    const MIN: f32 = 10.0;
+    const MAX: f32 = 120.0;
+    const ZOOM: f32 = 0.25;  // of the set fov while zoomed in
+    const EASE_RATE: f32 = 15.0;  // per second, the remaining difference shrinks by e^(-rate*t)
+    const SNAP: f32 = 0.01;  // degrees away from the target to jump to it
+    const STEP: f32 = 5.0;  // per key press
+
+    fn new(fov: f32) -> Self {
+        let fov = fov.clamp(Self::MIN, Self::MAX);
+        Self { current: fov, set: fov, zoomed: false }
+    }
+
+    // returns the clamped fov
+    fn set(&mut self, fov: f32) -> f32 {
+        self.set = fov.clamp(Self::MIN, Self::MAX);
+        self.set
+    }
+
+    fn target(&self) -> f32 {
+        if self.zoomed {self.set*Self::ZOOM} else {self.set}
+    }
+
+    // whether the current fov changed
+    fn ease(&mut self, delta: Duration) -> bool {
+        let target = self.target();
+        if self.current == target {
+            return false;
+        }
+        self.current = target+(self.current-target)*(-Self::EASE_RATE*delta.as_secs_f32()).exp();
+        if (self.current-target).abs() < Self::SNAP {
+            self.current = target;
+        }
+        true
+    }
+}
+
+// in the same space as the camera position
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Ray {
+    pub(crate) origin: Length3D,
+    pub(crate) dir: [f32; 3],  // normalized
+}
+
+// points where the dot product with the normal plus the distance is positive are on the inner side,
+// in the same space as the camera position
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Plane {
+    pub(crate) normal: [f32; 3],  // normalized
+    pub(crate) dist: f32,
+}
+
+impl Plane {
+    // from the coefficients of the plane equation
+    pub(crate) fn new(coefs: [f32; 4]) -> Self {
+        let len = (coefs[0]*coefs[0]+coefs[1]*coefs[1]+coefs[2]*coefs[2]).sqrt();
+        // the plane at infinity (e.g. the far plane of an infinite projection) has no normal, everything is inside
+        if len == 0.0 {
+            return Self { normal: [0.0; 3], dist: coefs[3].abs() };
+        }
+        Self { normal: [coefs[0]/len, coefs[1]/len, coefs[2]/len], dist: coefs[3]/len }
+    }
+
+    pub(crate) fn signed_dist(&self, p: [f32; 3]) -> f32 {
+        self.normal[0]*p[0]+self.normal[1]*p[1]+self.normal[2]*p[2]+self.dist
+    }
+
+    // whether the axis aligned box is entirely on the outer side
+    pub(crate) fn excludes(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        // the corner furthest along the normal
+        let corner = [0, 1, 2].map(|k| if self.normal[k] >= 0.0 {max[k]} else {min[k]});
+        self.signed_dist(corner) < 0.0
+    }
+}
+
+
 #[derive(Copy, Clone)]
 pub(crate) struct Rotation {
     x: Angle, y: Angle, z: Angle
@@ -79,17 +291,28 @@ impl Default for Rotation {
 }
 
 pub(crate) struct CameraComponent {
-    descriptor: CameraDescriptor,
+    descriptor: FrameUniform<CameraUBO>,
 
     // high-level parameters
-    trans_speed: f32,
-    rot_speed: f32,
+    speed: MovementSpeed,
+    flight: Flight,
+    reach: Reach,
+    mouse: MouseSettings,
+    delta: Duration,
     delta_trans_speed: f32,
+    spectator_mode: bool,
+    sprinting: bool,
+    physics_mode: bool,
+    pointer_over_ui: bool,
+    blocks: Option<Rc<dyn BlockQuery>>,  // to collide with in the physics mode
     // camera state
     t: Length3D,  // translations are in blocks
     r: Rotation,
     translations: Vec<VirtualKeyCode>,
     rotated: bool,
+    frustum_changed: bool,
+    body: PhysicsBody,
+    fell: bool,  // moved by the physics or the momentum since the last frame
     direction: CardinalDir,
     // view
     rot_x: Mat4,
@@ -97,48 +320,56 @@ pub(crate) struct CameraComponent {
     rot_z: Mat4,
     trans: Mat4,
     // projection
-    far: f32,
+    far: f32,  // f32::INFINITY projects the far plane at infinity
     near: f32,
     aspect_ratio: f32,
-    fov: f32,  // RADIANS
+    fov: FieldOfView,
+    fov_axis: FovAxis,
 }
 
 impl CameraComponent {
+    const MAX_PITCH: f32 = 89.9;  // in degrees
+
     pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>,
-                      aspect_ratio: f32, fov: f32, trans_speed: f32, rot_speed: f32,
-                      init_pos: Length3D
+                      aspect_ratio: f32, fov: f32, fov_axis: FovAxis, speed: MovementSpeed, reach: Reach, mouse: MouseSettings,
+                      init_pos: Length3D, blocks: Option<Rc<dyn BlockQuery>>,
     ) -> CameraComponent {
         // let init_rot = (180.0f32).to_radians();
         CameraComponent {
-            descriptor: unsafe { CameraDescriptor::new(vi.clone(), device.clone()) },
-            trans_speed, delta_trans_speed: trans_speed, rot_speed, t: init_pos, r: Rotation::default(),
-            translations: Vec::new(), rotated: false, direction: Self::determine_dir(Angle::zero()),
+            descriptor: unsafe { FrameUniform::new(vi.clone(), device.clone(), CameraUBO::default()) },
+            speed, flight: Flight::new(), reach, mouse, delta: Duration::ZERO, delta_trans_speed: 0.0, spectator_mode: false, sprinting: false,
+            physics_mode: false, pointer_over_ui: false, blocks,
+            t: init_pos, r: Rotation::default(),
+            translations: Vec::new(), rotated: false, frustum_changed: true, direction: Self::determine_dir(Angle::zero()),
+            body: PhysicsBody::default(), fell: false,
             rot_x: Self::rot_x_mat(0.0),
             rot_y: Self::rot_y_mat(0.0),
             rot_z: Self::rot_z_mat(0.0),
             trans: Self::trans_mat(init_pos),
-            far: 100000.0, near: 0.1, aspect_ratio, fov: fov.to_radians(),
+            far: 100000.0, near: 0.1, aspect_ratio, fov: FieldOfView::new(fov), fov_axis,
         }
     }
 
-    pub(crate) fn rotate(&mut self, mut dr: Rotation) {
+    pub(crate) fn rotate(&mut self, dr: Rotation) {
         if dr.x.get::<si::angle::radian>() != 0.0 {
-            dr.x.value *= self.rot_speed;
-            self.r.x = self.r.x+dr.x;
+            self.r.x = Self::clamp_pitch(self.r.x+dr.x);
             self.rot_x = Self::rot_x_mat(self.r.x.get::<si::angle::radian>());
         }
         if dr.y.get::<si::angle::radian>() != 0.0 {
-            dr.y.value *= self.rot_speed;
             self.r.y = self.r.y+dr.y;
             self.rot_y = Self::rot_y_mat(self.r.y.get::<si::angle::radian>());
         }
         if dr.z.get::<si::angle::radian>() != 0.0 {
-            dr.z.value *= self.rot_speed;
             self.r.z = self.r.z+dr.z;
             self.rot_z = Self::rot_z_mat(self.r.z.get::<si::angle::radian>());
         }
     }
 
+    // just short of straight up or down, past which the view would flip over
+    fn clamp_pitch(pitch: Angle) -> Angle {
+        Angle::new::<si::angle::degree>(pitch.get::<si::angle::degree>().clamp(-Self::MAX_PITCH, Self::MAX_PITCH))
+    }
+
     pub(crate) fn move_forward(&mut self, deg: Angle) {
         // by default, 0 degrees means right
         let angle = deg+Angle::new::<si::angle::degree>(90.0);
@@ -152,6 +383,46 @@ impl CameraComponent {
         self.trans = Self::trans_mat(self.t);
     }
 
+    // coasts the spectator by the momentum towards the displacement of the held keys since the start of the frame
+    fn apply_momentum(&mut self, start: Length3D, momentum: f32) {
+        let dt = self.delta.as_secs_f32();
+        if dt <= 0.0 {
+            return;
+        }
+        let target = [
+            (self.t.x-start.x).get::<blox>()/dt, (self.t.y-start.y).get::<blox>()/dt, (self.t.z-start.z).get::<blox>()/dt,
+        ];
+        let moved = self.flight.glide(target, self.delta, momentum);
+
+        self.fell |= moved != [0.0; 3];
+        self.t = Length3D::new(
+            start.x+Length::new::<blox>(moved[0]), start.y+Length::new::<blox>(moved[1]), start.z+Length::new::<blox>(moved[2]),
+        );
+        self.trans = Self::trans_mat(self.t);
+    }
+
+    fn fly_speed(&self) -> f32 {
+        self.speed.spectator*self.flight.scale
+    }
+
+    fn physics_active(&self) -> bool {
+        self.physics_mode && !self.spectator_mode && self.blocks.is_some()
+    }
+
+    // walks by the displacement since the start of the frame, falling and colliding with the blocks
+    fn apply_physics(&mut self, start: Length3D) {
+        let Some(blocks) = self.blocks.clone() else {
+            return;
+        };
+        let eye = [start.x.get::<blox>(), start.y.get::<blox>(), start.z.get::<blox>()];
+        let walk = [(self.t.x-start.x).get::<blox>(), (self.t.z-start.z).get::<blox>()];
+        let moved = self.body.step(eye, walk, self.delta, blocks.as_ref());
+
+        self.fell |= moved != eye;
+        self.t = Length3D::new(Length::new::<blox>(moved[0]), Length::new::<blox>(moved[1]), Length::new::<blox>(moved[2]));
+        self.trans = Self::trans_mat(self.t);
+    }
+
     pub(crate) fn determine_dir(angle: Angle) -> CardinalDir {
         let mod_angle = Angle::new::<si::angle::radian>(
             angle.value.rem_euclid(Angle::new::<si::angle::degree>(360.0).value)
@@ -176,21 +447,129 @@ impl CameraComponent {
         Self::local_view_mat(self.trans, self.rot_x, self.rot_y, self.rot_z)
     }
 
+    // in radians
+    fn vertical_fov(&self) -> f32 {
+        self.fov_axis.vertical_fov(self.fov.current.to_radians(), self.aspect_ratio)
+    }
+
     pub(crate) fn proj_mat(&self) -> Mat4 {
-        let focal_len = 1.0/(self.fov/2.0).tan();
+        Self::local_proj_mat(self.vertical_fov(), self.aspect_ratio, self.near, self.far)
+    }
 
+    // unprojects the clip coordinates into directions from the camera (for the skybox), ignoring the camera position
+    fn inverse_sky_mat(&self) -> Mat4 {
+        matrix_prod(
+            Self::local_inverse_proj_mat(self.vertical_fov(), self.aspect_ratio, self.near, self.far),
+            Self::local_inverse_view_mat(Length3D::origin(), self.rot_x, self.rot_y, self.rot_z),
+        )
+    }
+
+    fn ubo(&self) -> CameraUBO {
+        CameraUBO {
+            view: self.view_mat(),
+            proj: self.proj_mat(),
+            inv_sky: self.inverse_sky_mat(),
+        }
+    }
+
+    // planes of the view frustum facing inwards (left, right, bottom, top, near, far)
+    pub(crate) fn frustum(&self) -> [Plane; 6] {
+        Self::frustum_planes(matrix_prod(self.view_mat(), self.proj_mat()))
+    }
+
+    fn frustum_planes(view_proj: Mat4) -> [Plane; 6] {
+        // each row of the (column major) matrix dotted with the point gives a clip coordinate
+        let row = |r: usize| [view_proj[0][r], view_proj[1][r], view_proj[2][r], view_proj[3][r]];
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let plane = |a: [f32; 4], sign: f32, b: [f32; 4]| Plane::new([0, 1, 2, 3].map(|k| a[k]+sign*b[k]));
+
+        // within -w <= x, y <= w, and the reversed depth 0 <= z <= w
         [
-            [focal_len/self.aspect_ratio, 0.0, 0.0, 0.0],
-            [0.0,-focal_len, 0.0, 0.0],
-            [0.0, 0.0,self.near/(self.far-self.near),-1.0],
-            [0.0, 0.0,self.near*self.far/(self.far-self.near), 0.0],
+            plane(w, 1.0, x), plane(w,-1.0, x),
+            plane(w, 1.0, y), plane(w,-1.0, y),
+            plane(w,-1.0, z), Plane::new(z),
         ]
     }
 
+    // unit direction the camera looks at, unrotated it's down -z
+    fn forward_dir(rot_x: Mat4, rot_y: Mat4, rot_z: Mat4) -> [f32; 3] {
+        let inv_view = Self::local_inverse_view_mat(Length3D::origin(), rot_x, rot_y, rot_z);
+        let dir = matrix_prod_vector(inv_view, [0.0, 0.0, -1.0, 0.0]);
+        [dir[0], dir[1], dir[2]]
+    }
+
+    // unprojects the pixel (from the top left corner of the surface) into a ray from the camera
+    pub(crate) fn screen_to_ray(&self, screen_pos: (f32, f32), extent: vk::Extent2D) -> Ray {
+        let inv_view_proj = matrix_prod(
+            Self::local_inverse_proj_mat(self.vertical_fov(), self.aspect_ratio, self.near, self.far),
+            Self::local_inverse_view_mat(self.t, self.rot_x, self.rot_y, self.rot_z),
+        );
+        Self::unproject(inv_view_proj, self.t, screen_pos, extent)
+    }
+
     pub(crate) fn local_view_mat(trans: Mat4, rot_x: Mat4, rot_y: Mat4, rot_z: Mat4) -> Mat4 {
         matrix_prod(matrix_prod(matrix_prod(trans, rot_z), rot_y), rot_x)
     }
 
+    // the rotations are orthogonal, so their inverse is their transpose
+    fn local_inverse_view_mat(t: Length3D, rot_x: Mat4, rot_y: Mat4, rot_z: Mat4) -> Mat4 {
+        let inv_trans = Self::trans_mat(Length3D::origin()-t);
+        matrix_prod(matrix_prod(matrix_prod(matrix_transpose(rot_x), matrix_transpose(rot_y)), matrix_transpose(rot_z)), inv_trans)
+    }
+
+    // a point at distance d in front of the camera has the reversed depth depth_scale+depth_ofs/d, which is 1 at the
+    // near plane and 0 at the far plane (depth tested with GREATER)
+    fn depth_terms(near: f32, far: f32) -> (f32, f32) {
+        if far.is_infinite() {
+            // the limit as the far plane goes to infinity, the depth is near/d all the way to the horizon, with the
+            // float precision piling up near 0 where the distant geometry is
+            (0.0, near)
+        } else {
+            (near/(far-near), near*far/(far-near))
+        }
+    }
+
+    // reversed depth, with the near plane at depth 1 and the far plane at depth 0
+    fn local_proj_mat(vfov: f32, aspect_ratio: f32, near: f32, far: f32) -> Mat4 {
+        let focal_len = 1.0/(vfov/2.0).tan();
+        let (depth_scale, depth_ofs) = Self::depth_terms(near, far);
+
+        [
+            [focal_len/aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0,-focal_len, 0.0, 0.0],
+            [0.0, 0.0, depth_scale,-1.0],
+            [0.0, 0.0, depth_ofs, 0.0],
+        ]
+    }
+
+    fn local_inverse_proj_mat(vfov: f32, aspect_ratio: f32, near: f32, far: f32) -> Mat4 {
+        let focal_len = 1.0/(vfov/2.0).tan();
+        let (depth_scale, depth_ofs) = Self::depth_terms(near, far);
+
+        [
+            [aspect_ratio/focal_len, 0.0, 0.0, 0.0],
+            [0.0,-1.0/focal_len, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0/depth_ofs],
+            [0.0, 0.0,-1.0, depth_scale/depth_ofs],
+        ]
+    }
+
+    fn unproject(inv_view_proj: Mat4, eye: Length3D, screen_pos: (f32, f32), extent: vk::Extent2D) -> Ray {
+        let ndc_x = 2.0*screen_pos.0/extent.width as f32-1.0;
+        let ndc_y = 2.0*screen_pos.1/extent.height as f32-1.0;
+        let point_at_depth = |depth: f32| {
+            let p = matrix_prod_vector(inv_view_proj, [ndc_x, ndc_y, depth, 1.0]);
+            [p[0]/p[3], p[1]/p[3], p[2]/p[3]]
+        };
+
+        // points on the near plane and a bit behind it, to not lose precision to the far plane
+        let near = point_at_depth(1.0);
+        let behind = point_at_depth(0.5);
+        let dir = [behind[0]-near[0], behind[1]-near[1], behind[2]-near[2]];
+        let len = (dir[0]*dir[0]+dir[1]*dir[1]+dir[2]*dir[2]).sqrt();
+        Ray { origin: eye, dir: dir.map(|d| d/len) }
+    }
+
     fn trans_mat(t: Length3D) -> Mat4 {
         [
             [ 1.0, 0.0, 0.0, 0.0],
@@ -237,14 +616,64 @@ impl Component for CameraComponent {
     fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
         let mut dir_changed = false;
         let mut trans_changed = false;
+        let mut reach_changed = false;
+        let mut new_fov = false;
+        let mut start = false;
         match event {
             WorldEvent::DeltaTime(delta) => {
-                self.delta_trans_speed = delta.as_secs_f32() * self.trans_speed;
+                self.delta = delta;
+                let mut events = Vec::new();
+                if self.fov.ease(delta) {
+                    let ubo = self.ubo();
+                    self.descriptor.set(ubo);
+                    self.frustum_changed = true;
+                    events.push(WorldEvent::UserFov(self.fov.current));
+                }
+                if self.frustum_changed {
+                    self.frustum_changed = false;
+                    events.push(WorldEvent::CameraFrustum(self.frustum()));
+                    let dir = Self::forward_dir(self.rot_x, self.rot_y, self.rot_z);
+                    events.push(WorldEvent::CameraRay { origin: self.t, dir: (dir[0], dir[1], dir[2]) });
+                }
+                if self.fell {
+                    self.fell = false;
+                    events.push(WorldEvent::UserPosition(self.t));
+                }
+                return events;
             }
-            WorldEvent::MouseMotion((x, y)) => {
+            WorldEvent::SetFOV(fov) => {
+                let clamped = self.fov.set(fov);
+                if clamped != fov {
+                    log::warn!("Field of view {fov} is out of range, clamped to {clamped}");
+                }
+            }
+            WorldEvent::SpectatorMode(enabled) => {
+                self.spectator_mode = enabled;
+                self.body = PhysicsBody::default();
+                self.flight.velocity = [0.0; 3];
+                reach_changed = true;
+            }
+            WorldEvent::PhysicsMode(enabled) => {
+                self.physics_mode = enabled;
+                self.body = PhysicsBody::default();
+            }
+            WorldEvent::PointerOverUi(over_ui) => {
+                self.pointer_over_ui = over_ui;
+            }
+            WorldEvent::SetMouseSensitivity(sensitivity) => {
+                let clamped = self.mouse.set_sensitivity(sensitivity);
+                if clamped != sensitivity {
+                    log::warn!("Mouse sensitivity {sensitivity} is out of range, clamped to {clamped}");
+                }
+            }
+            WorldEvent::InvertMouseY(invert_y) => {
+                self.mouse.invert_y = invert_y;
+            }
+            WorldEvent::MouseMotion(delta) if !self.pointer_over_ui => {
+                let (yaw, pitch) = self.mouse.look(delta);
                 self.rotate(Rotation {
-                    x: Angle::new::<si::angle::degree>(y as f32),
-                    y: Angle::new::<si::angle::degree>(x as f32),
+                    x: Angle::new::<si::angle::degree>(pitch),
+                    y: Angle::new::<si::angle::degree>(yaw),
                     z: Angle::new::<si::angle::degree>(0.0)
                 });
                 self.rotated = true;
@@ -254,6 +683,11 @@ impl Component for CameraComponent {
                     dir_changed = true;
                 }
             }
+            WorldEvent::MouseScroll { delta_y, .. } if self.spectator_mode && !self.pointer_over_ui => {
+                // scrolling up flies faster
+                let changed = self.flight.scroll(delta_y);
+                return changed.then(|| WorldEvent::UserFlySpeed(self.fly_speed())).into_iter().collect();
+            }
             WorldEvent::KeyPressed(key) => {
                 match key {
                     VirtualKeyCode::W | VirtualKeyCode::A | VirtualKeyCode::S | VirtualKeyCode::D |
@@ -261,6 +695,18 @@ impl Component for CameraComponent {
                         self.translations.push(key);
                         trans_changed = true;
                     }
+                    VirtualKeyCode::LControl => {  // sprint
+                        self.sprinting = true;
+                    }
+                    VirtualKeyCode::Z => {  // zoom
+                        self.fov.zoomed = true;
+                    }
+                    VirtualKeyCode::Semicolon => {
+                        return vec![WorldEvent::SetFOV((self.fov.set-FieldOfView::STEP).max(FieldOfView::MIN))];
+                    }
+                    VirtualKeyCode::Apostrophe => {
+                        return vec![WorldEvent::SetFOV((self.fov.set+FieldOfView::STEP).min(FieldOfView::MAX))];
+                    }
                     _ => {}
                 }
             }
@@ -271,12 +717,31 @@ impl Component for CameraComponent {
                         self.translations.retain(|t| t != remove_key);
                         trans_changed = !self.translations.is_empty();
                     }
+                    VirtualKeyCode::LControl => {  // sprint
+                        self.sprinting = false;
+                    }
+                    VirtualKeyCode::Z => {  // zoom
+                        self.fov.zoomed = false;
+                    }
                     _ => {}
                 }
             }
             WorldEvent::Start => {
                 dir_changed = true;
                 trans_changed = true;
+                reach_changed = true;
+                new_fov = true;
+                start = true;
+            }
+            WorldEvent::CursorMoved(pos, extent) => {
+                return vec![WorldEvent::CursorRay(self.screen_to_ray((pos.0 as f32, pos.1 as f32), extent))];
+            }
+            WorldEvent::SurfaceResized(extent) => {
+                // the field of view and the near/far planes are kept
+                self.aspect_ratio = extent.width as f32/extent.height as f32;
+                let ubo = self.ubo();
+                self.descriptor.set(ubo);
+                return vec![WorldEvent::CameraFrustum(self.frustum())];
             }
             _ => {}
         }
@@ -288,12 +753,29 @@ impl Component for CameraComponent {
         if trans_changed {
             new_events.push(WorldEvent::UserPosition(self.t));
         }
+        if reach_changed {
+            new_events.push(WorldEvent::UserReach(self.reach.distance(self.spectator_mode)));
+        }
+        if new_fov {
+            new_events.push(WorldEvent::UserFov(self.fov.current));
+        }
+        if start {
+            new_events.push(WorldEvent::UserFlySpeed(self.fly_speed()));
+            // for the settings panel
+            new_events.push(WorldEvent::SetMouseSensitivity(self.mouse.sensitivity));
+            new_events.push(WorldEvent::InvertMouseY(self.mouse.invert_y));
+        }
 
         new_events
     }
 
     fn update(&mut self) {
-        if self.rotated || !self.translations.is_empty() {
+        let scale = if self.spectator_mode {self.flight.scale} else {1.0};
+        self.delta_trans_speed = self.speed.displacement(self.delta, self.spectator_mode, self.sprinting)*scale;
+
+        let physics = self.physics_active();
+        if self.rotated || !self.translations.is_empty() || physics || self.flight.coasting() {
+            let start = self.t;
             for key in self.translations.clone() {
                 if let VirtualKeyCode::W = key {
                     self.move_forward(Angle::new::<si::angle::degree>(180.0));
@@ -307,33 +789,42 @@ impl Component for CameraComponent {
                 if let VirtualKeyCode::D = key {
                     self.move_forward(Angle::new::<si::angle::degree>(270.0));
                 }
-                if let VirtualKeyCode::LShift = key {
+                // the vertical movement is up to the gravity in the physics mode, other than jumping
+                if let (VirtualKeyCode::LShift, false) = (key, physics) {
                     self.move_vertical(-1);
                 }
                 if let VirtualKeyCode::Space = key {
-                    self.move_vertical(1);
+                    if physics {
+                        self.body.jump();
+                    } else {
+                        self.move_vertical(1);
+                    }
                 }
             }
+            if physics {
+                self.apply_physics(start);
+            } else if let (true, Some(momentum)) = (self.spectator_mode, self.speed.momentum) {
+                self.apply_momentum(start, momentum);
+            }
 
             self.rotated = false;
+            self.frustum_changed = true;
 
-            let data = CameraUBO {
-                view: self.view_mat(),
-                proj: self.proj_mat(),
-            };
-            self.descriptor.update(data);
+            let ubo = self.ubo();
+            self.descriptor.set(ubo);
         }
     }
 
+    fn prepare_frame(&mut self, frame_index: usize) {
+        unsafe { self.descriptor.write(frame_index); }
+    }
+
     unsafe fn load_descriptors(&mut self, _: CmdBufContext) -> Vec<RenderData> {
-        let data = CameraUBO {
-            view: self.view_mat(),
-            proj: self.proj_mat(),
-        };
-        self.descriptor.update(data);
+        let ubo = self.ubo();
+        self.descriptor.set(ubo);
 
         vec![RenderData::InitialDescriptorBuffer(
-            vec![self.descriptor.descriptor_buffer_info()], RenderDataPurpose::CameraViewProjection
+            self.descriptor.descriptor_buffer_infos(), RenderDataPurpose::CameraViewProjection
         )]
     }
 
@@ -348,51 +839,291 @@ impl Component for CameraComponent {
 struct CameraUBO {
     pub(crate) view: [[f32;4];4],
     pub(crate) proj: [[f32;4];4],
+    pub(crate) inv_sky: [[f32;4];4],
 }
 
 impl Default for CameraUBO {
     fn default() -> Self {
-        Self { view: matrix_ident(), proj: matrix_ident() }
+        Self { view: matrix_ident(), proj: matrix_ident(), inv_sky: matrix_ident() }
     }
 }
 
 
-// CAMERA DESCRIPTOR
- struct CameraDescriptor {
-    device: Rc<Device>,
-    // descriptor fields of uniform buffer
-    ubo_buf: vk::Buffer,
-    ubo_mem: vk::DeviceMemory,
-    ubo_ptr: *mut ffi::c_void,
-    ubo_size: vk::DeviceSize,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_horizontal_fov_to_vertical() {
+        let aspect_ratio = 16.0/9.0;
+        let hfov = 90.0f32.to_radians();
+        let vfov = FovAxis::Horizontal.vertical_fov(hfov, aspect_ratio);
+
+        // tan(vfov/2) = tan(hfov/2)/aspect, with tan(45deg) = 1
+        assert!((vfov-2.0*(9.0f32/16.0).atan()).abs() < 1e-6);
+        assert!((vfov.to_degrees()-58.7155).abs() < 1e-3);
+        assert!(vfov < hfov);
+    }
+
+    #[test]
+    fn test_vertical_fov_passthrough() {
+        let vfov = 70.0f32.to_radians();
+        assert_eq!(FovAxis::Vertical.vertical_fov(vfov, 21.0/9.0), vfov);
+        // on a square surface both axes span the same angle
+        assert!((FovAxis::Horizontal.vertical_fov(vfov, 1.0)-vfov).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sprint_multiplies_displacement() {
+        let speed = MovementSpeed { walk: 4.0, spectator: 10.0, sprint_multiplier: 2.5, momentum: None };
+        let delta = Duration::from_millis(500);
 
-impl CameraDescriptor {
-     unsafe fn new(vi: Rc<VulkanInstance>, device: Rc<Device>) -> CameraDescriptor {
-        let (ubo_buf, ubo_mem, ubo_ptr, ubo_size) =
-            create_host_buffer(vi.clone(), device.clone(), &[CameraUBO::default()], vk::BufferUsageFlags::UNIFORM_BUFFER, false);
+        assert_eq!(speed.displacement(delta, false, false), 2.0);
+        assert_eq!(speed.displacement(delta, false, true), 5.0);
+        assert_eq!(speed.displacement(delta, true, false), 5.0);
+        assert_eq!(speed.displacement(delta, true, true), 12.5);
+        // scales with the frame time
+        assert_eq!(speed.displacement(delta*2, false, true), 10.0);
+    }
+
+    #[test]
+    fn test_fly_speed_scrolls_within_range() {
+        let mut flight = Flight::new();
+        assert!(flight.scroll(2.0));
+        assert!((flight.scale-1.5625).abs() < 1e-6);
+        assert!(flight.scroll(-2.0));
+        assert!((flight.scale-1.0).abs() < 1e-6);
+
+        assert!(flight.scroll(100.0));
+        assert_eq!(flight.scale, Flight::MAX_SCALE);
+        assert!(!flight.scroll(1.0));
+        assert!(flight.scroll(-100.0));
+        assert_eq!(flight.scale, Flight::MIN_SCALE);
+    }
+
+    #[test]
+    fn test_momentum_eases_and_coasts_to_rest() {
+        let mut flight = Flight::new();
+        let delta = Duration::from_millis(100);
+
+        // speeds up gradually towards the held keys
+        let first = flight.glide([10.0, 0.0, 0.0], delta, 0.2);
+        assert!(first[0] > 0.0 && first[0] < 1.0);
+        for _ in 0..50 {
+            flight.glide([10.0, 0.0, 0.0], delta, 0.2);
+        }
+        assert!((flight.velocity[0]-10.0).abs() < 1e-3);
 
-        CameraDescriptor {
-            device, ubo_buf, ubo_mem, ubo_ptr, ubo_size,
+        // keeps moving once released, slowing down until it stops
+        let coast = flight.glide([0.0; 3], delta, 0.2);
+        assert!(coast[0] > 0.0 && coast[0] < 1.0);
+        for _ in 0..100 {
+            flight.glide([0.0; 3], delta, 0.2);
         }
+        assert!(!flight.coasting());
+
+        // no momentum follows the keys right away
+        let instant = flight.glide([0.0, 5.0, 0.0], delta, 0.0);
+        assert!((instant[1]-0.5).abs() < 1e-6);
+        assert_eq!(flight.velocity, [0.0, 5.0, 0.0]);
+    }
+
+    #[test]
+    fn test_reach_per_mode() {
+        let reach = Reach { walk: 5.0, spectator: 20.0 };
+        let block_dist = 8.0;
+
+        // out of reach while walking, but pickable in spectator mode
+        assert!(block_dist > reach.distance(false));
+        assert!(block_dist <= reach.distance(true));
+        assert!(3.0 <= reach.distance(false));
+
+        let extreme = Reach { walk: 0.0, spectator: 10000.0 };
+        assert_eq!(extreme.distance(false), Reach::MIN);
+        assert_eq!(extreme.distance(true), Reach::MAX);
+    }
+
+    fn center_ray(t: Length3D, rot_y: f32) -> Ray {
+        let vfov = 70.0f32.to_radians();
+        let inv_view_proj = matrix_prod(
+            CameraComponent::local_inverse_proj_mat(vfov, 16.0/9.0, 0.1, 100000.0),
+            CameraComponent::local_inverse_view_mat(t, CameraComponent::rot_x_mat(0.0), CameraComponent::rot_y_mat(rot_y), CameraComponent::rot_z_mat(0.0)),
+        );
+        CameraComponent::unproject(inv_view_proj, t, (960.0, 540.0), vk::Extent2D { width: 1920, height: 1080 })
     }
 
-    fn update(&mut self, ubo: CameraUBO) {
-        unsafe {
-            update_buffer(self.ubo_ptr, &[ubo], self.ubo_size);
+    #[test]
+    fn test_center_pixel_ray_along_forward() {
+        let t = Length3D::new(Length::new::<blox>(3.0), Length::new::<blox>(40.0), Length::new::<blox>(-2.0));
+        let close = |a: [f32; 3], b: [f32; 3]| (0..3).all(|k| (a[k]-b[k]).abs() < 1e-4);
+
+        // unrotated, the camera looks down -z, which is also where it moves forward to
+        let ray = center_ray(t, 0.0);
+        assert!(close(ray.dir, [0.0, 0.0, -1.0]), "{:?}", ray.dir);
+        assert_eq!(ray.origin.y.get::<blox>(), 40.0);
+
+        let ray = center_ray(t, 90.0f32.to_radians());
+        assert!(close(ray.dir, [1.0, 0.0, 0.0]), "{:?}", ray.dir);
+
+        // the inverse projection undoes the projection
+        let proj = CameraComponent::local_proj_mat(1.2, 1.5, 0.1, 100.0);
+        let inv_proj = CameraComponent::local_inverse_proj_mat(1.2, 1.5, 0.1, 100.0);
+        let ident = matrix_prod(proj, inv_proj);
+        for (row, ident_row) in ident.iter().zip(matrix_ident()) {
+            assert!(row.iter().zip(ident_row).all(|(a, b)| (a-b).abs() < 1e-4), "{ident:?}");
         }
     }
 
-    fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
-        vk::DescriptorBufferInfo {
-            buffer: self.ubo_buf,
-            offset: 0 as vk::DeviceSize,
-            range: mem::size_of::<CameraUBO>() as vk::DeviceSize,
+    #[test]
+    fn test_forward_dir_from_yaw_and_pitch() {
+        let close = |a: [f32; 3], b: [f32; 3]| (0..3).all(|k| (a[k]-b[k]).abs() < 1e-5);
+        let forward = |pitch: f32, yaw: f32| CameraComponent::forward_dir(
+            CameraComponent::rot_x_mat(pitch.to_radians()), CameraComponent::rot_y_mat(yaw.to_radians()), CameraComponent::rot_z_mat(0.0),
+        );
+
+        assert!(close(forward(0.0, 0.0), [0.0, 0.0, -1.0]));
+        // yawing turns right, the same as the forward movement and the center pixel ray
+        assert!(close(forward(0.0, 90.0), [1.0, 0.0, 0.0]));
+        assert!(close(forward(0.0, 180.0), [0.0, 0.0, 1.0]));
+        // the mouse moving down pitches down
+        assert!(close(forward(90.0, 0.0), [0.0, -1.0, 0.0]));
+        let half = 0.5f32.sqrt();
+        assert!(close(forward(45.0, 90.0), [half, -half, 0.0]), "{:?}", forward(45.0, 90.0));
+
+        let t = Length3D::origin();
+        assert!(close(forward(0.0, 90.0), center_ray(t, 90.0f32.to_radians()).dir));
+    }
+
+    #[test]
+    fn test_frustum_contains_points_ahead() {
+        let t = Length3D::new(Length::new::<blox>(0.0), Length::new::<blox>(10.0), Length::new::<blox>(0.0));
+        let view = CameraComponent::local_view_mat(
+            CameraComponent::trans_mat(t), CameraComponent::rot_x_mat(0.0), CameraComponent::rot_y_mat(0.0), CameraComponent::rot_z_mat(0.0),
+        );
+        let frustum = CameraComponent::frustum_planes(matrix_prod(view, CameraComponent::local_proj_mat(70.0f32.to_radians(), 16.0/9.0, 0.1, 100.0)));
+        let inside = |p: [f32; 3]| frustum.iter().all(|plane| plane.signed_dist(p) >= 0.0);
+
+        // unrotated, the camera looks down -z
+        assert!(inside([0.0, 10.0, -5.0]));
+        assert!(inside([1.0, 9.0, -50.0]));
+        assert!(!inside([0.0, 10.0, 5.0]));
+        assert!(!inside([50.0, 10.0, -5.0]));
+        assert!(!inside([0.0, -20.0, -5.0]));
+        assert!(!inside([0.0, 10.0, -150.0]));
+
+        // a chunk behind the camera is culled, but not the one it stands in
+        let excluded = |min: [f32; 3], max: [f32; 3]| frustum.iter().any(|plane| plane.excludes(min, max));
+        assert!(excluded([-16.0, 0.0, 4.0], [16.0, 32.0, 36.0]));
+        assert!(!excluded([-16.0, 0.0, -16.0], [16.0, 32.0, 16.0]));
+        // straddling the side of the frustum
+        assert!(!excluded([40.0, 0.0, -40.0], [80.0, 32.0, -20.0]));
+    }
+
+    #[test]
+    fn test_mouse_look_sensitivity_and_pitch_clamp() {
+        let mut mouse = MouseSettings::default();
+        assert_eq!(mouse.look((10.0, 20.0)), (0.5, 1.0));
+        mouse.invert_y = true;
+        assert_eq!(mouse.look((10.0, 20.0)), (0.5, -1.0));
+        assert_eq!(mouse.set_sensitivity(0.1), 0.1);
+        assert_eq!(mouse.set_sensitivity(10.0), MouseSettings::MAX_SENSITIVITY);
+        assert_eq!(mouse.set_sensitivity(-1.0), MouseSettings::MIN_SENSITIVITY);
+
+        // stops short of straight up or down
+        let pitch = |deg: f32| CameraComponent::clamp_pitch(Angle::new::<si::angle::degree>(deg)).get::<si::angle::degree>();
+        assert!((pitch(45.0)-45.0).abs() < 1e-4);
+        assert!((pitch(120.0)-CameraComponent::MAX_PITCH).abs() < 1e-4);
+        assert!((pitch(-400.0)+CameraComponent::MAX_PITCH).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_fov_clamped_and_eased_into_zoom() {
+        let mut fov = FieldOfView::new(200.0);
+        assert_eq!(fov.current, FieldOfView::MAX);
+        assert_eq!(fov.set(80.0), 80.0);
+        assert_eq!(fov.set(1.0), FieldOfView::MIN);
+        fov.set(80.0);
+
+        // eases over a few frames instead of jumping
+        let frame = Duration::from_millis(16);
+        assert!(fov.ease(frame));
+        assert!(fov.current < FieldOfView::MAX && fov.current > 80.0);
+
+        fov.zoomed = true;
+        let mut prev = fov.current;
+        let mut frames = 0;
+        while fov.ease(frame) {
+            assert!(fov.current < prev);
+            prev = fov.current;
+            frames += 1;
         }
+        assert_eq!(fov.current, 80.0*FieldOfView::ZOOM);
+        assert!((10..60).contains(&frames), "{frames}");
+        // settled
+        assert!(!fov.ease(frame));
     }
 
-    unsafe fn destroy(&self) {
-        self.device.destroy_buffer(self.ubo_buf, None);
-        self.device.free_memory(self.ubo_mem, None);
+    #[test]
+    fn test_reverse_depth() {
+        let (vfov, aspect_ratio, near, far) = (70.0f32.to_radians(), 16.0/9.0, 0.1, 100000.0);
+        let proj = CameraComponent::local_proj_mat(vfov, aspect_ratio, near, far);
+        let depth = |d: f32| {
+            let clip = matrix_prod_vector(proj, [0.0, 0.0, -d, 1.0]);
+            clip[2]/clip[3]
+        };
+
+        assert!((depth(near)-1.0).abs() < 1e-6);
+        assert!(depth(far).abs() < 1e-6);
+        // closer points pass the GREATER depth test
+        assert!(depth(100.0) > depth(1000.0));
+
+        let inv_proj = CameraComponent::local_inverse_proj_mat(vfov, aspect_ratio, near, far);
+        let ident = matrix_prod(proj, inv_proj);
+        for (row, ident_row) in ident.iter().zip(matrix_ident()) {
+            assert!(row.iter().zip(ident_row).all(|(a, b)| (a-b).abs() < 1e-4), "{ident:?}");
+        }
+
+        // only what is beyond the far plane is excluded by it
+        let frustum = CameraComponent::frustum_planes(proj);
+        assert!(!frustum[5].excludes([-1.0, -1.0, -1e4], [1.0, 1.0, -1e3]));
+        assert!(frustum[5].excludes([-1.0, -1.0, -1e6], [1.0, 1.0, -2e5]));
+    }
+
+    #[test]
+    fn test_infinite_far_reverse_depth() {
+        let (vfov, aspect_ratio, near) = (70.0f32.to_radians(), 16.0/9.0, 0.1);
+        let proj = CameraComponent::local_proj_mat(vfov, aspect_ratio, near, f32::INFINITY);
+        let depth = |d: f32| {
+            let clip = matrix_prod_vector(proj, [0.0, 0.0, -d, 1.0]);
+            clip[2]/clip[3]
+        };
+
+        assert!((depth(near)-1.0).abs() < 1e-6);
+        assert!((depth(10.0)-near/10.0).abs() < 1e-6);
+        // still in front of the far plane at any distance, and closer points pass the GREATER depth test
+        assert!(depth(1e9) > 0.0);
+        assert!(depth(100.0) > depth(1000.0));
+
+        // the inverse projection undoes the infinite projection too
+        let inv_proj = CameraComponent::local_inverse_proj_mat(vfov, aspect_ratio, near, f32::INFINITY);
+        let ident = matrix_prod(proj, inv_proj);
+        for (row, ident_row) in ident.iter().zip(matrix_ident()) {
+            assert!(row.iter().zip(ident_row).all(|(a, b)| (a-b).abs() < 1e-4), "{ident:?}");
+        }
+
+        // nothing is beyond the far plane
+        let frustum = CameraComponent::frustum_planes(proj);
+        assert!(frustum.iter().all(|plane| plane.signed_dist([0.0, 0.0, -1e6]) >= 0.0));
+        assert!(!frustum[5].excludes([-1.0, -1.0, -1e7], [1.0, 1.0, -1e6]));
+    }
+
+    #[test]
+    fn test_resize_only_rescales_horizontal_projection() {
+        let vfov = 70.0f32.to_radians();
+        let square = CameraComponent::local_proj_mat(FovAxis::Vertical.vertical_fov(vfov, 1.0), 1.0, 0.1, 100000.0);
+        let wide = CameraComponent::local_proj_mat(FovAxis::Vertical.vertical_fov(vfov, 2.0), 2.0, 0.1, 100000.0);
+
+        assert!((wide[0][0]-square[0][0]/2.0).abs() < 1e-6);
+        assert_eq!(wide[1..], square[1..]);
     }
 }
@@ -1,24 +1,82 @@
 use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 use ash::{Device, vk};
-use egui::{ClippedPrimitive, Context, ImageData, Mesh, RawInput, TextureFilter, TextureId};
+use egui::{ClippedPrimitive, Color32, Context, Event, Id, ImageData, Key, LayerId, Mesh, Modifiers, Order, PointerButton, Pos2, RawInput, Rect, Sense, Shape, Stroke, TextureFilter, TextureId, Ui};
+use winit::event::VirtualKeyCode;
 use egui::epaint::{ImageDelta, Primitive, Vertex};
 use uom::fmt::DisplayStyle;
 use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::component::camera::MouseSettings;
 use crate::handler::VulkanInstance;
 use crate::measurement::blox;
 use crate::util::{CmdBufContext, create_host_buffer, create_local_image};
 use crate::world::{CardinalDir, WorldEvent};
+use crate::debug::MTXG_RENDER_LOG;
 
 
+// of the texture array the debug ui shaders sample, egui textures beyond it are drawn with the first one
+pub(crate) const MAX_UI_TEXTURES: usize = 4;
+
+// indices of a primitive within the vertex and index buffer of the debug ui, drawn within its scissor with its texture
+#[derive(Copy, Clone, Debug)]
+pub struct UiDraw {
+    pub(crate) first_index: u32,
+    pub(crate) index_count: u32,
+    pub(crate) vertex_offset: i32,
+    pub(crate) scissor: vk::Rect2D,
+    pub(crate) texture: u32,  // slot within the texture array
+}
+
+// every primitive mesh in a single vertex and index buffer, with a draw each
+fn merge_ui_meshes(meshes: &[UiMesh]) -> (Vec<Vertex>, Vec<u32>, Vec<UiDraw>) {
+    let (mut verts, mut inds, mut draws) = (Vec::new(), Vec::new(), Vec::new());
+    for (mesh_verts, mesh_inds, scissor, texture) in meshes {
+        draws.push(UiDraw {
+            first_index: inds.len() as u32,
+            index_count: mesh_inds.len() as u32,
+            vertex_offset: verts.len() as i32,
+            scissor: *scissor,
+            texture: *texture,
+        });
+        verts.extend_from_slice(mesh_verts);
+        inds.extend_from_slice(mesh_inds);
+    }
+    (verts, inds, draws)
+}
+
 #[derive(Clone)]
 pub(crate) struct DebugUIData {
     face_direction: String,
     fps: String,
     pos: String,
     spectator_mode: String,
-
-    fps_hist: VecDeque<f32>,
+    fluid_motion: String,
+    reach: String,
+    fov: String,
+    fly_speed: String,
+    tick_rate: String,
+    render_distance: String,
+    seed: String,
+    fog_density: Option<f32>,  // per blox, tuned with a slider
+    // in the settings window
+    mouse_sensitivity: Option<f32>,
+    invert_mouse_y: Option<bool>,
+    time_of_day: String,
+    present_mode: String,
+    cursor_ray: String,
+    camera_ray: String,
+    target_block: String,
+    selected_block: String,
+    uploads: String,
+    chunk_culling: String,
+    occlusion_culling: String,
+    render_stats: String,
+    block_sound: String,
+
+    fps_hist: VecDeque<f32>,  // oldest first
+    fps_low: f32,  // 1% low of the samples
+    upload_count: usize,
+    overlay: bool,  // hidden for clean screenshots
 }
 
 impl Default for DebugUIData {
@@ -28,44 +86,195 @@ impl Default for DebugUIData {
             fps: String::from(".fps: <UNDEFINED>"),
             pos: String::from(".pos: <UNDEFINED>"),
             spectator_mode: String::from(".spectator_mode: <UNDEFINED>"),
+            fluid_motion: String::from(".fluid_motion: <UNDEFINED>"),
+            reach: String::from(".reach: <UNDEFINED>"),
+            fov: String::from(".fov: <UNDEFINED>"),
+            fly_speed: String::from(".fly_speed: <UNDEFINED>"),
+            tick_rate: String::from(".tick_rate: <UNDEFINED>"),
+            render_distance: String::from(".render_distance: <UNDEFINED>"),
+            seed: String::from(".seed: <UNDEFINED>"),
+            fog_density: None,
+            mouse_sensitivity: None,
+            invert_mouse_y: None,
+            time_of_day: String::from(".time_of_day: <UNDEFINED>"),
+            present_mode: String::from(".present_mode: <UNDEFINED>"),
+            cursor_ray: String::from(".cursor_ray: <UNDEFINED>"),
+            camera_ray: String::from(".camera_ray: <UNDEFINED>"),
+            target_block: String::from(".target_block: <UNDEFINED>"),
+            selected_block: String::from(".selected_block: <UNDEFINED>"),
+            uploads: String::from(".uploads: <UNDEFINED>"),
+            chunk_culling: String::from(".chunk_culling: <UNDEFINED>"),
+            occlusion_culling: String::from(".occlusion_culling: <UNDEFINED>"),
+            render_stats: String::from(".render_stats: <UNDEFINED>"),
+            block_sound: String::from(".block_sound: <UNDEFINED>"),
             fps_hist: VecDeque::new(),
+            fps_low: 0.0,
+            upload_count: 0,
+            overlay: true,
         }
     }
 }
 
 pub(crate) struct DebugUI {
     ui_handler: EguiHandler,
-    render_data: Vec<(Vec<Vertex>, Vec<u32>, vk::Rect2D, TextureId)>,
+    render_data: Vec<UiMesh>,
     pub(crate) ui_data: DebugUIData,
+    ui_events: Vec<WorldEvent>,  // from the widgets, emitted on the next frame
+    pointer_over_ui: bool,
+    fps_samples: usize,
 }
 
 impl DebugUI {
-    const FPS_SAMPLES: usize = 200;
-
-    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, init_raw_input: RawInput) -> Self {
+    pub(crate) const FPS_SAMPLES: usize = 200;  // by default
+    const FPS_LOW_PERCENTILE: f32 = 0.01;
+    const GRAPH_HEIGHT: f32 = 60.0;  // in points
+    const HISTOGRAM_BUCKETS: usize = 32;
+    const LABEL_WIDTH: f32 = 320.0;  // in points
+    const CROSSHAIR_SIZE: u32 = 20;  // in pixels, even so the bars stay on whole pixels around the center
+    const CROSSHAIR_THICKNESS: u32 = 2;
+
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, init_raw_input: RawInput, extent: vk::Extent2D) -> Self {
         let mut s = Self {
-            ui_handler: EguiHandler::new(vi.clone(), device.clone(), init_raw_input),
+            ui_handler: EguiHandler::new(vi.clone(), device.clone(), init_raw_input, extent),
             render_data: Vec::new(),
             ui_data: DebugUIData::default(),
+            ui_events: Vec::new(),
+            pointer_over_ui: false,
+            fps_samples: Self::FPS_SAMPLES,
         };
         unsafe {
             // needs to ensure ui_handler is display() ed before to obtain texture
-            let mut render_data = s.ui_handler.display(s.ui_data.clone(), Self::ui_program());
+            let (mut render_data, _) = s.ui_handler.display(s.ui_data.clone(), Self::ui_program());
             s.render_data.append(&mut render_data);
         }
         s
     }
 
+    // the number of frames kept for the fps stats and graphs, dropping the oldest ones beyond it
+    pub(crate) fn set_fps_samples(&mut self, samples: usize) {
+        self.fps_samples = samples.max(1);
+        let excess = self.ui_data.fps_hist.len().saturating_sub(self.fps_samples);
+        self.ui_data.fps_hist.drain(..excess);
+    }
+
     // >>> UI PROGRAM <<<
-    fn ui_program() -> impl FnOnce(&Context, DebugUIData) {
+    // returns the events of the widgets that were changed
+    fn ui_program() -> impl FnOnce(&Context, DebugUIData) -> Vec<WorldEvent> {
         |ctx: &Context, data: DebugUIData| {
-            egui::CentralPanel::default().show(&ctx, |ui| {
+            let mut events = Vec::new();
+            if !data.overlay {
+                return events;
+            }
+
+            let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("crosshair")));
+            for bar in Self::crosshair_bars(ctx.screen_rect(), ctx.pixels_per_point()) {
+                painter.rect_filled(bar, 0.0, Color32::from_white_alpha(200));
+            }
+
+            egui::Area::new("debug_labels").fixed_pos(Pos2::ZERO).show(ctx, |ui| egui::Frame::central_panel(ui.style()).show(ui, |ui| {
+                ui.set_max_width(Self::LABEL_WIDTH);
                 ui.label(data.face_direction);
                 ui.label(data.fps);
+                Self::fps_graph(ui, &data.fps_hist, data.fps_low);
+                Self::frame_time_histogram(ui, &data.fps_hist);
                 ui.label(data.pos);
                 ui.label(data.spectator_mode);
+                ui.label(data.fluid_motion);
+                ui.label(data.reach);
+                ui.label(data.fov);
+                ui.label(data.fly_speed);
+                ui.label(data.tick_rate);
+                ui.label(data.render_distance);
+                ui.label(data.seed);
+                match data.fog_density {
+                    Some(mut density) => {
+                        let slider = egui::Slider::new(&mut density, 0.0..=0.05).logarithmic(true).text("Fog Density");
+                        if ui.add(slider).changed() {
+                            events.push(WorldEvent::SetFogDensity(density));
+                        }
+                    }
+                    None => {
+                        ui.label(".fog_density: <UNDEFINED>");
+                    }
+                }
+                ui.label(data.time_of_day);
+                ui.label(data.present_mode);
+                ui.label(data.cursor_ray);
+                ui.label(data.camera_ray);
+                ui.label(data.target_block);
+                ui.label(data.selected_block);
+                ui.label(data.uploads);
+                ui.label(data.chunk_culling);
+                ui.label(data.occlusion_culling);
+                ui.label(data.render_stats);
+                ui.label(data.block_sound);
+            }));
+
+            egui::Window::new("Settings").default_open(false).show(ctx, |ui| {
+                if let Some(mut sensitivity) = data.mouse_sensitivity {
+                    let range = MouseSettings::MIN_SENSITIVITY..=MouseSettings::MAX_SENSITIVITY;
+                    let slider = egui::Slider::new(&mut sensitivity, range).logarithmic(true).text("Mouse Sensitivity");
+                    if ui.add(slider).changed() {
+                        events.push(WorldEvent::SetMouseSensitivity(sensitivity));
+                    }
+                }
+                if let Some(mut invert_y) = data.invert_mouse_y {
+                    if ui.checkbox(&mut invert_y, "Invert Mouse Y").changed() {
+                        events.push(WorldEvent::InvertMouseY(invert_y));
+                    }
+                }
             });
+            events
+        }
+    }
+
+    // fps over the kept frames, along with the 1% low as a horizontal line
+    fn fps_graph(ui: &mut Ui, fps_hist: &VecDeque<f32>, fps_low: f32) {
+        let (response, painter) = ui.allocate_painter(egui::vec2(Self::LABEL_WIDTH, Self::GRAPH_HEIGHT), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_black_alpha(120));
+
+        let top = fps_hist.iter().copied().fold(1.0, f32::max)*1.1;
+        let y = |fps: f32| rect.bottom()-fps/top*rect.height();
+        let step = rect.width()/(fps_hist.len().max(2)-1) as f32;
+        let points = fps_hist.iter().enumerate()
+            .map(|(i, fps)| Pos2::new(rect.left()+i as f32*step, y(*fps)))
+            .collect::<Vec<_>>();
+        painter.add(Shape::line(points, Stroke::new(1.0, Color32::LIGHT_GREEN)));
+        painter.hline(rect.x_range(), y(fps_low), Stroke::new(1.0, Color32::LIGHT_RED));
+    }
+
+    // how many of the kept frames took how long, from 0 to the longest frame
+    fn frame_time_histogram(ui: &mut Ui, fps_hist: &VecDeque<f32>) {
+        let (response, painter) = ui.allocate_painter(egui::vec2(Self::LABEL_WIDTH, Self::GRAPH_HEIGHT), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_black_alpha(120));
+
+        let frame_times = fps_hist.iter().map(|fps| 1000.0/fps).collect::<Vec<_>>();
+        let longest = frame_times.iter().copied().fold(0.0, f32::max);
+        let buckets = frame_time_buckets(&frame_times, longest, Self::HISTOGRAM_BUCKETS);
+        let highest = buckets.iter().copied().max().unwrap_or(0).max(1);
+
+        let width = rect.width()/Self::HISTOGRAM_BUCKETS as f32;
+        for (i, count) in buckets.into_iter().enumerate() {
+            let left = rect.left()+i as f32*width;
+            let height = count as f32/highest as f32*rect.height();
+            let bar = Rect::from_min_max(Pos2::new(left, rect.bottom()-height), Pos2::new(left+width-1.0, rect.bottom()));
+            painter.rect_filled(bar, 0.0, Color32::LIGHT_BLUE);
         }
+        ui.label(format!("Frame Time: 0 - {longest:.1} ms"));
+    }
+
+    // the horizontal and vertical bars (in points) centered on the screen, snapped to whole pixels to stay crisp
+    fn crosshair_bars(screen: Rect, pixels_per_point: f32) -> [Rect; 2] {
+        let center = (screen.center().to_vec2()*pixels_per_point).round()/pixels_per_point;
+        let half_size = (Self::CROSSHAIR_SIZE/2) as f32/pixels_per_point;
+        let half_thickness = (Self::CROSSHAIR_THICKNESS/2) as f32/pixels_per_point;
+        let bar = |half_width: f32, half_height: f32| Rect::from_min_max(
+            Pos2::new(center.x-half_width, center.y-half_height),
+            Pos2::new(center.x+half_width, center.y+half_height),
+        );
+        [bar(half_size, half_thickness), bar(half_thickness, half_size)]
     }
 }
 
@@ -73,27 +282,33 @@ impl Component for DebugUI {
     fn render(&self) -> Vec<RenderData> {
         // TODO: do we need to make sure the buffer object lasts long through DebugUI?
 
-        // TODO: assuming a single render data
-        let (vert, indx, scissor, _) = &self.render_data[0];
+        let (vert, indx, draws) = merge_ui_meshes(&self.render_data);
+        if draws.is_empty() {
+            // nothing to draw while the overlay is hidden
+            return vec![RenderData::ClearBuffer(RenderDataPurpose::DebugUI)];
+        }
 
         // no staging buffer for DebugUI, since it is debug and you would want the fastest update (and its just UI)
 
         let (vertex_buffer, vertex_buffer_mem, _, _) = unsafe {
-            create_host_buffer(self.ui_handler.vi.clone(), self.ui_handler.device.clone(), vert, vk::BufferUsageFlags::VERTEX_BUFFER, true)
+            create_host_buffer(self.ui_handler.vi.clone(), self.ui_handler.device.clone(), &vert, vk::BufferUsageFlags::VERTEX_BUFFER, true)
         };
 
         let (index_buffer, index_buffer_mem, _, _) = unsafe {
-            create_host_buffer(self.ui_handler.vi.clone(), self.ui_handler.device.clone(), indx, vk::BufferUsageFlags::INDEX_BUFFER, true)
+            create_host_buffer(self.ui_handler.vi.clone(), self.ui_handler.device.clone(), &indx, vk::BufferUsageFlags::INDEX_BUFFER, true)
         };
 
         vec![
             RenderData::RecreateVertexBuffer(vertex_buffer, vertex_buffer_mem, RenderDataPurpose::DebugUI),
             RenderData::RecreateIndexBuffer(index_buffer, index_buffer_mem, indx.len() as u32, RenderDataPurpose::DebugUI),
-            RenderData::SetScissorDynamicState(*scissor, RenderDataPurpose::DebugUI),
+            RenderData::SetUiDraws(draws, RenderDataPurpose::DebugUI),
         ]
     }
 
     fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
+        self.ui_handler.modify_raw_input(&event);
+        let frame_start = matches!(event, WorldEvent::DeltaTime(_));
+
         match event {
             WorldEvent::UserFaceDir(new_dir) => {
                 let dir_name = match new_dir {
@@ -107,7 +322,7 @@ impl Component for DebugUI {
             }
             WorldEvent::DeltaTime(dur) => {
                 let sample = 1.0/dur.as_secs_f32();
-                if self.ui_data.fps_hist.len() < Self::FPS_SAMPLES {
+                if self.ui_data.fps_hist.len() < self.fps_samples {
                     self.ui_data.fps_hist.push_back(sample);
                 } else {
                     self.ui_data.fps_hist.push_back(sample);
@@ -115,8 +330,12 @@ impl Component for DebugUI {
                 }
 
                 let fps_avg = self.ui_data.fps_hist.iter().sum::<f32>()/self.ui_data.fps_hist.len() as f32;
+                let fps_min = self.ui_data.fps_hist.iter().copied().fold(f32::INFINITY, f32::min);
+                let fps_max = self.ui_data.fps_hist.iter().copied().fold(0.0, f32::max);
+                self.ui_data.fps_low = percentile(&self.ui_data.fps_hist, Self::FPS_LOW_PERCENTILE);
 
-                self.ui_data.fps = format!("FPS: {}", fps_avg.round());
+                self.ui_data.fps = format!("FPS: {} (min {}, max {}, 1% low {})",
+                                           fps_avg.round(), fps_min.round(), fps_max.round(), self.ui_data.fps_low.round());
             }
             WorldEvent::UserPosition(pos) => {
                 self.ui_data.pos = format!("Position: {} {} {}",
@@ -131,17 +350,107 @@ impl Component for DebugUI {
                     String::from("Spectator Mode: FALSE")
                 };
             }
+            WorldEvent::FluidMotion(enabled) => {
+                self.ui_data.fluid_motion = if enabled {
+                    String::from("Fluid Motion: TRUE")
+                } else {
+                    String::from("Fluid Motion: FALSE")
+                };
+            }
+            WorldEvent::SetTickRate(rate) => {
+                self.ui_data.tick_rate = format!("Tick Rate: {rate}x");
+            }
+            WorldEvent::SetRenderDistance(render_distance) => {
+                self.ui_data.render_distance = format!("Render Distance: {render_distance} chunks");
+            }
+            WorldEvent::WorldSeed(seed) => {
+                self.ui_data.seed = format!("Seed: {seed}");
+            }
+            WorldEvent::SetFogDensity(density) => {
+                self.ui_data.fog_density = Some(density);
+            }
+            WorldEvent::SetMouseSensitivity(sensitivity) => {
+                self.ui_data.mouse_sensitivity = Some(sensitivity.clamp(MouseSettings::MIN_SENSITIVITY, MouseSettings::MAX_SENSITIVITY));
+            }
+            WorldEvent::InvertMouseY(invert_y) => {
+                self.ui_data.invert_mouse_y = Some(invert_y);
+            }
+            WorldEvent::ActivePresentMode(present_mode) => {
+                self.ui_data.present_mode = format!("Present Mode: {present_mode:?}");
+            }
+            WorldEvent::TimeOfDay(time_of_day) => {
+                let minutes = (time_of_day*24.0*60.0) as u32;
+                self.ui_data.time_of_day = format!("Time of Day: {time_of_day:.2} ({:02}:{:02})", minutes/60, minutes%60);
+            }
+            WorldEvent::UserReach(reach) => {
+                self.ui_data.reach = format!("Reach: {reach} blox");
+            }
+            WorldEvent::UserFov(fov) => {
+                self.ui_data.fov = format!("FOV: {fov:.1} deg");
+            }
+            WorldEvent::UserFlySpeed(speed) => {
+                self.ui_data.fly_speed = format!("Fly Speed: {speed:.1} blox/s");
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::F1) => {
+                self.ui_data.overlay = !self.ui_data.overlay;
+            }
+            WorldEvent::SurfaceResized(extent) => {
+                self.ui_handler.resize(extent);
+            }
+            WorldEvent::CursorRay(ray) => {
+                self.ui_data.cursor_ray = format!("Cursor Ray: {:.2} {:.2} {:.2}", ray.dir[0], ray.dir[1], ray.dir[2]);
+            }
+            WorldEvent::CameraRay { origin, dir } => {
+                self.ui_data.camera_ray = format!("Camera Ray: {:.2} {:.2} {:.2} from {:.1} {:.1} {:.1}",
+                    dir.0, dir.1, dir.2, origin.x.get::<blox>(), origin.y.get::<blox>(), origin.z.get::<blox>());
+            }
+            WorldEvent::TargetBlock(target) => {
+                self.ui_data.target_block = match target {
+                    Some((pos, face)) => format!("Target Block: {} {} {} ({:?})", pos.x, pos.y, pos.z, face),
+                    None => String::from("Target Block: NONE"),
+                };
+            }
+            WorldEvent::SelectedBlockChanged(block) => {
+                self.ui_data.selected_block = format!("Selected Block: {:?}", block);
+            }
+            WorldEvent::BufferUploadComplete(purpose) => {
+                self.ui_data.upload_count += 1;
+                self.ui_data.uploads = format!("Buffer Uploads: {} (last {:?})", self.ui_data.upload_count, purpose);
+            }
+            WorldEvent::ChunkCulling { drawn, culled } => {
+                self.ui_data.chunk_culling = format!("Chunk Culling: {drawn} drawn, {culled} culled");
+            }
+            WorldEvent::OcclusionCulling(enabled) => {
+                self.ui_data.occlusion_culling = format!("Occlusion Culling: {}", if enabled {"ON"} else {"OFF"});
+            }
+            WorldEvent::OcclusionStats { tested, visible } => {
+                self.ui_data.occlusion_culling = format!("Occlusion Culling: {visible} of {tested} chunks drawn");
+            }
+            WorldEvent::RenderStats { chunks, visible, tris } => {
+                self.ui_data.render_stats = format!("Chunks: {chunks} loaded, {visible} visible, {tris} triangles");
+            }
+            WorldEvent::BlockSound { pos, block, action, sound_group } => {
+                self.ui_data.block_sound = format!("Block Sound: {:?} {:?} on {:?} at {} {} {}", sound_group, action, block,
+                                                   pos.x.get::<blox>(), pos.y.get::<blox>(), pos.z.get::<blox>());
+            }
             _ => {}
         }
 
-        // TODO: for creating raw input
-        // self.ui_handler.modify_raw_input(event);
-
-        vec![]
+        if !frame_start {
+            return vec![];
+        }
+        let mut events = std::mem::take(&mut self.ui_events);
+        if self.pointer_over_ui != self.ui_handler.pointer_captured {
+            self.pointer_over_ui = self.ui_handler.pointer_captured;
+            events.push(WorldEvent::PointerOverUi(self.pointer_over_ui));
+        }
+        events
     }
 
     fn update(&mut self) {
-        unsafe { self.render_data = self.ui_handler.display(self.ui_data.clone(), Self::ui_program()); }
+        let (render_data, mut events) = unsafe { self.ui_handler.display(self.ui_data.clone(), Self::ui_program()) };
+        self.render_data = render_data;
+        self.ui_events.append(&mut events);
     }
 
     unsafe fn load_descriptors(&mut self, ctx: CmdBufContext) -> Vec<RenderData> {
@@ -155,18 +464,22 @@ impl Component for DebugUI {
         });
         self.ui_handler.create_img_views();
 
-        self.ui_handler.textures.iter()
-            .map(|(_, txtr_descriptor)| {
-                RenderData::InitialDescriptorImage(
-                    vec![vk::DescriptorImageInfo {
-                        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                        image_view: txtr_descriptor.img_view.unwrap(),
-                        sampler: txtr_descriptor.sampler,
-                    }],
-                    RenderDataPurpose::DebugUI,
-                )
+        // every slot of the texture array is written, the unused ones with the first texture
+        let slots: Vec<vk::DescriptorImageInfo> = self.ui_handler.slots.iter()
+            .map(|txtr_id| {
+                let txtr_descriptor = &self.ui_handler.textures[txtr_id];
+                vk::DescriptorImageInfo {
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    image_view: txtr_descriptor.img_view.unwrap(),
+                    sampler: txtr_descriptor.sampler,
+                }
             })
-            .collect::<Vec<RenderData>>()
+            .collect();
+        let Some(first) = slots.first().copied() else {
+            return vec![];
+        };
+        let padding = std::iter::repeat_n(first, MAX_UI_TEXTURES-slots.len());
+        vec![RenderData::InitialDescriptorImage(slots.into_iter().chain(padding).collect(), RenderDataPurpose::DebugUI)]
     }
 
     unsafe fn destroy(&mut self) {
@@ -195,33 +508,70 @@ struct EguiHandler {
     // Egui state components
     ctx: egui::Context,
     raw_input: RawInput,
+    extent: vk::Extent2D,  // of the surface, which the egui screen covers
+    pointer: Option<Pos2>,  // in points
+    modifiers: Modifiers,
+    pointer_captured: bool,  // hovering or dragging a widget in the last displayed frame
 
     // Egui renders
     textures: HashMap<TextureId, UITextureDescriptor>,
+    slots: Vec<TextureId>,  // of the texture array, in the order the textures were created
 }
 
+// vertices and indices of a primitive, drawn within the scissor with the texture of the slot
+type UiMesh = (Vec<Vertex>, Vec<u32>, vk::Rect2D, u32);
+
 impl EguiHandler {
-    fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, init_raw_input: RawInput) -> Self {
-        Self {
+    const SCROLL_LINE: f32 = 24.0;  // points scrolled per wheel line
+
+    fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, init_raw_input: RawInput, extent: vk::Extent2D) -> Self {
+        let mut handler = Self {
             vi, device,
             ctx: egui::Context::default(),
             raw_input: init_raw_input,
+            extent,
+            pointer: None,
+            modifiers: Modifiers::default(),
+            pointer_captured: false,
             textures: HashMap::new(),
-        }
+            slots: Vec::new(),
+        };
+        handler.resize(extent);
+        handler
+    }
+
+    fn pixels_per_point(&self) -> f32 {
+        self.raw_input.viewports.get(&self.raw_input.viewport_id)
+            .and_then(|viewport| viewport.native_pixels_per_point)
+            .unwrap_or(1.0)
     }
 
-    // fn modify_raw_input(&mut self, event: WorldEvent) {
-    //
-    // }
+    fn resize(&mut self, extent: vk::Extent2D) {
+        self.extent = extent;
+        let size = egui::vec2(extent.width as f32, extent.height as f32)/self.pixels_per_point();
+        self.raw_input.screen_rect = Some(Rect::from_min_size(Pos2::ZERO, size));
+    }
+
+    // appends the mouse and key events as egui events, consumed by the next display
+    fn modify_raw_input(&mut self, event: &WorldEvent) {
+        let pixels_per_point = self.pixels_per_point();
+        let event = egui_event(event, &mut self.pointer, &mut self.modifiers, pixels_per_point);
+
+        self.raw_input.modifiers = self.modifiers;
+        self.raw_input.events.extend(event);
+    }
 
-    unsafe fn display(&mut self, data: DebugUIData, cb: impl FnOnce(&Context, DebugUIData)) -> Vec<(Vec<Vertex>, Vec<u32>, vk::Rect2D, TextureId)> {
+    unsafe fn display(&mut self, data: DebugUIData, cb: impl FnOnce(&Context, DebugUIData) -> Vec<WorldEvent>) -> (Vec<UiMesh>, Vec<WorldEvent>) {
         // TODO: aggregate all the events here and create raw input only in here
         // TODO: custom closure that also pass in dynamic info (vector of trait object that describes
         // TODO: ... what data it is and check compatibility between component updates and UI's compatibility
 
+        let mut events = Vec::new();
         let full_output = self.ctx.run(self.raw_input.clone(), |ctx| {
-            cb(ctx, data);
+            events = cb(ctx, data);
         });
+        self.raw_input.events.clear();
+        self.pointer_captured = self.ctx.wants_pointer_input() || self.ctx.is_pointer_over_area();
         // non render output from egui
         let _non_render_output = full_output.platform_output;
 
@@ -231,31 +581,47 @@ impl EguiHandler {
         // textures to create before rendering
         self.create_textures(full_output.textures_delta.set);
 
+        // a mesh per primitive, each with its own texture and clipped to its own clip rect
+        let ppp = full_output.pixels_per_point;
+        let screen = Rect::from_min_size(Pos2::ZERO, egui::vec2(self.extent.width as f32, self.extent.height as f32));
         let mut primitive_meshes = Vec::new();
         for ClippedPrimitive {clip_rect, primitive} in clipped_primitives {
-            // TODO: scissor takes in "pixels", make sure its the same as logical pixels from egui
+            let Primitive::Mesh(Mesh {indices, mut vertices, texture_id: txtr_id}) = primitive else {
+                continue;
+            };
+            let clip_rect = Rect::from_min_max((clip_rect.min.to_vec2()*ppp).floor().to_pos2(), (clip_rect.max.to_vec2()*ppp).ceil().to_pos2())
+                .intersect(screen);
+            if indices.is_empty() || clip_rect.width() <= 0.0 || clip_rect.height() <= 0.0 {
+                continue;
+            }
+            // from points to the normalized device coordinates
+            for vertex in &mut vertices {
+                vertex.pos = Pos2::new(
+                    vertex.pos.x*ppp/self.extent.width as f32*2.0-1.0,
+                    vertex.pos.y*ppp/self.extent.height as f32*2.0-1.0,
+                );
+            }
             let scissor = vk::Rect2D {
                 offset: vk::Offset2D {x: clip_rect.min.x as i32, y: clip_rect.min.y as i32},
-                extent: vk::Extent2D {
-                    width: (clip_rect.max.x-clip_rect.min.x) as u32,
-                    height: (clip_rect.max.y-clip_rect.min.y) as u32
-                },
+                extent: vk::Extent2D {width: clip_rect.width() as u32, height: clip_rect.height() as u32},
             };
-            if let Primitive::Mesh(Mesh {indices, vertices, texture_id: txtr_id}) = primitive {
-                // println!("MESH {indices:?} {vertices:?}");
-                primitive_meshes.push((vertices, indices, scissor, txtr_id));
-            }
+            primitive_meshes.push((vertices, indices, scissor, self.texture_slot(txtr_id)));
         }
 
         // textures to free/destroy will happen at the end of program
         // (assuming we dont help egui to add new texture in between execution)
 
-        primitive_meshes
+        (primitive_meshes, events)
+    }
+
+    // slot of the texture array the texture is sampled from
+    fn texture_slot(&self, txtr_id: TextureId) -> u32 {
+        self.slots.iter().position(|slot| *slot == txtr_id).unwrap_or(0) as u32
     }
 
     unsafe fn destroy(&self) {
         for (txtr_id, txtr) in &self.textures {
-            println!("FREE TEXTURE {txtr_id:?}");
+            log::trace!(target: MTXG_RENDER_LOG, "FREE TEXTURE {txtr_id:?}");
 
             self.device.destroy_sampler(txtr.sampler, None);
             if let Some(img_view) = txtr.img_view {
@@ -280,11 +646,11 @@ impl EguiHandler {
     unsafe fn create_textures(&mut self, new_txtrs: Vec<(TextureId, ImageDelta)>) {
         for (txtr_id, ImageDelta {image, options, pos} ) in new_txtrs {
             // TODO: create vk buffer image
-            println!("SET {txtr_id:?}");
+            log::trace!(target: MTXG_RENDER_LOG, "SET {txtr_id:?}");
 
             let (host_buf, host_buf_mem, local_img, local_img_mem, extent) = match image {
                 ImageData::Color(color) => {
-                    println!("COLOR");
+                    log::trace!(target: MTXG_RENDER_LOG, "COLOR");
 
                     let bytes = color.as_raw();
                     let img_extent = vk::Extent3D {
@@ -300,7 +666,7 @@ impl EguiHandler {
                     (buf, buf_mem, img, img_mem, img_extent)
                 }
                 ImageData::Font(font) => {
-                    println!("FONT");
+                    log::trace!(target: MTXG_RENDER_LOG, "FONT");
 
                     // vk::Format::R32_SFLOAT  // red pixel representing coverage (alpha)
                     let bytes = font.srgba_pixels(None)
@@ -346,6 +712,13 @@ impl EguiHandler {
             let sampler = self.device.create_sampler(&sampler_info, None)
                 .expect("Failed to create UI sampler");
 
+            if !self.slots.contains(&txtr_id) {
+                if self.slots.len() < MAX_UI_TEXTURES {
+                    self.slots.push(txtr_id);
+                } else {
+                    log::warn!("Debug UI texture {txtr_id:?} is beyond the {MAX_UI_TEXTURES} texture slots, drawn with the first texture instead");
+                }
+            }
             self.textures.insert(txtr_id, UITextureDescriptor {
                 sampler, img_view: None, host_buf, host_buf_mem, local_img, local_img_mem, extent
             });
@@ -443,3 +816,172 @@ impl EguiHandler {
         }
     }
 }
+
+// the sample at the given fraction (0 to 1) of the samples in ascending order, over whatever samples are kept
+fn percentile(samples: &VecDeque<f32>, fraction: f32) -> f32 {
+    let mut sorted = samples.iter().copied().collect::<Vec<_>>();
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted.sort_by(f32::total_cmp);
+    let rank = (fraction*sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len())-1]
+}
+
+// counts of the frame times (in ms) within each of the equal buckets spanning 0 to the longest one
+fn frame_time_buckets(frame_times: &[f32], longest: f32, buckets: usize) -> Vec<usize> {
+    let mut counts = vec![0; buckets];
+    if longest <= 0.0 {
+        return counts;
+    }
+    for time in frame_times {
+        let bucket = (time/longest*buckets as f32) as usize;
+        counts[bucket.min(buckets-1)] += 1;
+    }
+    counts
+}
+
+// egui event of a mouse or key event, tracking the pointer position (in points) and the held modifiers along the way
+fn egui_event(event: &WorldEvent, pointer: &mut Option<Pos2>, modifiers: &mut Modifiers, pixels_per_point: f32)
+    -> Option<Event> {
+    let button = |button: PointerButton, pressed: bool, pointer: Option<Pos2>, modifiers: Modifiers| {
+        pointer.map(|pos| Event::PointerButton { pos, button, pressed, modifiers })
+    };
+    match *event {
+        WorldEvent::CursorMoved((x, y), _) => {
+            let pos = Pos2::new(x as f32, y as f32)/pixels_per_point;
+            pointer.replace(pos);
+            Some(Event::PointerMoved(pos))
+        }
+        WorldEvent::LeftButtonPressed => button(PointerButton::Primary, true, *pointer, *modifiers),
+        WorldEvent::LeftButtonReleased => button(PointerButton::Primary, false, *pointer, *modifiers),
+        WorldEvent::RightButtonPressed => button(PointerButton::Secondary, true, *pointer, *modifiers),
+        WorldEvent::RightButtonReleased => button(PointerButton::Secondary, false, *pointer, *modifiers),
+        WorldEvent::MiddleButtonPressed => button(PointerButton::Middle, true, *pointer, *modifiers),
+        WorldEvent::MiddleButtonReleased => button(PointerButton::Middle, false, *pointer, *modifiers),
+        WorldEvent::MouseScroll { delta_x, delta_y } => {
+            Some(Event::Scroll(egui::vec2(delta_x, delta_y)*EguiHandler::SCROLL_LINE))
+        }
+        WorldEvent::KeyPressed(key) | WorldEvent::KeyReleased(key) => {
+            let pressed = matches!(event, WorldEvent::KeyPressed(_));
+            match key {
+                VirtualKeyCode::LShift | VirtualKeyCode::RShift => modifiers.shift = pressed,
+                VirtualKeyCode::LControl | VirtualKeyCode::RControl => {
+                    modifiers.ctrl = pressed;
+                    modifiers.command = pressed;
+                }
+                VirtualKeyCode::LAlt | VirtualKeyCode::RAlt => modifiers.alt = pressed,
+                _ => {}
+            }
+            egui_key(key).map(|key| Event::Key { key, pressed, repeat: false, modifiers: *modifiers })
+        }
+        _ => None,
+    }
+}
+
+// the keys the debug widgets respond to (e.g. the arrows nudging a focused slider)
+fn egui_key(key: VirtualKeyCode) -> Option<Key> {
+    Some(match key {
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Return => Key::Enter,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::Escape => Key::Escape,
+        _ => return None,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crosshair_centered_on_whole_pixels() {
+        for (width, height, ppp) in [(1000.0, 1000.0, 1.0), (1001.0, 767.0, 1.0), (1001.0, 767.0, 1.25), (2560.0, 1440.0, 1.5)] {
+            let screen = Rect::from_min_size(Pos2::ZERO, egui::vec2(width, height)/ppp);
+            let [horizontal, vertical] = DebugUI::crosshair_bars(screen, ppp);
+
+            for bar in [horizontal, vertical] {
+                for edge in [bar.min.x, bar.min.y, bar.max.x, bar.max.y] {
+                    assert!((edge*ppp-(edge*ppp).round()).abs() < 1e-3, "{edge} at {ppp}");
+                }
+                // within a pixel of the center
+                assert!(((bar.center().x-screen.center().x)*ppp).abs() <= 0.5+1e-3);
+                assert!(((bar.center().y-screen.center().y)*ppp).abs() <= 0.5+1e-3);
+            }
+            assert!((horizontal.width()*ppp-DebugUI::CROSSHAIR_SIZE as f32).abs() < 1e-3);
+            assert!((vertical.width()*ppp-DebugUI::CROSSHAIR_THICKNESS as f32).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_ui_meshes_drawn_apart() {
+        let scissor = |x: i32| vk::Rect2D {offset: vk::Offset2D {x, y: 0}, extent: vk::Extent2D {width: 10, height: 10}};
+        let quad = vec![Vertex::default(); 4];
+        let meshes = vec![
+            (quad.clone(), vec![0, 1, 2, 0, 2, 3], scissor(0), 0),
+            (quad.clone()[..3].to_vec(), vec![0, 1, 2], scissor(20), 2),
+            (quad, vec![0, 1, 2, 0, 2, 3], scissor(40), 0),
+        ];
+        let (verts, inds, draws) = merge_ui_meshes(&meshes);
+
+        assert_eq!(verts.len(), 11);
+        // the indices stay relative to the vertices of their own primitive
+        assert_eq!(inds[6..9], [0, 1, 2]);
+        assert_eq!(draws.iter().map(|draw| (draw.first_index, draw.index_count, draw.vertex_offset)).collect::<Vec<_>>(),
+                   vec![(0, 6, 0), (6, 3, 4), (9, 6, 7)]);
+        assert_eq!(draws.iter().map(|draw| (draw.scissor.offset.x, draw.texture)).collect::<Vec<_>>(), vec![(0, 0), (20, 2), (40, 0)]);
+        assert!(merge_ui_meshes(&[]).2.is_empty());
+    }
+
+    #[test]
+    fn test_fps_low_and_frame_time_buckets() {
+        // 198 smooth frames and 2 stutters
+        let mut fps_hist = (0..198).map(|i| 100.0+(i%10) as f32).collect::<VecDeque<_>>();
+        fps_hist.push_back(20.0);
+        fps_hist.push_back(25.0);
+        assert_eq!(percentile(&fps_hist, 0.01), 25.0);
+        assert_eq!(percentile(&fps_hist, 0.005), 20.0);
+        assert_eq!(percentile(&fps_hist, 1.0), 109.0);
+        assert_eq!(percentile(&VecDeque::new(), 0.01), 0.0);
+
+        let frame_times = fps_hist.iter().map(|fps| 1000.0/fps).collect::<Vec<_>>();
+        let buckets = frame_time_buckets(&frame_times, 50.0, 10);
+        assert_eq!(buckets.iter().sum::<usize>(), 200);
+        // the smooth frames take around 10 ms, the longest one falls in the last bucket
+        assert_eq!(buckets[1]+buckets[2], 198);
+        assert_eq!(buckets[8], 1);
+        assert_eq!(buckets[9], 1);
+    }
+
+    #[test]
+    fn test_egui_events_follow_pointer_and_modifiers() {
+        let (mut pointer, mut modifiers) = (None, Modifiers::default());
+        let mut translate = |event| egui_event(&event, &mut pointer, &mut modifiers, 2.0);
+        let extent = vk::Extent2D {width: 800, height: 600};
+        let pos = Pos2::new(150.0, 50.0);
+
+        // nowhere to press before the cursor first moves
+        assert_eq!(translate(WorldEvent::LeftButtonPressed), None);
+        assert_eq!(translate(WorldEvent::CursorMoved((300.0, 100.0), extent)), Some(Event::PointerMoved(pos)));
+        assert_eq!(translate(WorldEvent::KeyPressed(VirtualKeyCode::LShift)), None);
+        assert_eq!(translate(WorldEvent::LeftButtonPressed), Some(Event::PointerButton {
+            pos, button: PointerButton::Primary, pressed: true, modifiers: Modifiers::SHIFT,
+        }));
+        assert_eq!(translate(WorldEvent::KeyPressed(VirtualKeyCode::RControl)), None);
+        assert_eq!(translate(WorldEvent::KeyReleased(VirtualKeyCode::LShift)), None);
+        assert_eq!(translate(WorldEvent::LeftButtonReleased), Some(Event::PointerButton {
+            pos, button: PointerButton::Primary, pressed: false, modifiers: Modifiers { ctrl: true, command: true, ..Modifiers::default() },
+        }));
+        assert_eq!(translate(WorldEvent::KeyReleased(VirtualKeyCode::RControl)), None);
+        assert_eq!(modifiers, Modifiers::default());
+        assert_eq!(pointer, Some(pos));
+    }
+}
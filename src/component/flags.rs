@@ -1,15 +1,38 @@
 use winit::event::VirtualKeyCode;
 use crate::component::{Component, RenderData};
+use crate::shader::Msaa;
+use crate::swapchain::PresentMode;
 use crate::world::WorldEvent;
 
 pub(crate) struct WorldFlags {
-    spectator_mode: bool
+    spectator_mode: bool,
+    physics_mode: bool,
+    chunk_borders: bool,
+    fluid_motion: bool,
+    wireframe: bool,
+    occlusion_culling: bool,
+    present_mode: PresentMode,  // requested, the swapchain may fall back to FIFO
+    msaa: Msaa,  // requested, the shader may lower it to the supported sample counts
 }
 
 impl WorldFlags {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(chunk_borders: bool, present_mode: PresentMode, msaa: Msaa) -> Self {
         Self {
             spectator_mode: false,
+            physics_mode: false,
+            chunk_borders,
+            fluid_motion: true,
+            wireframe: false,
+            occlusion_culling: false,
+            present_mode,
+            msaa,
+        }
+    }
+
+    // the depth pyramid of the occlusion culling is only reduced from a single sampled depth
+    fn warn_msaa_exclusive(&self) {
+        if self.occlusion_culling && self.msaa != Msaa::Off {
+            log::warn!("Occlusion culling is skipped while MSAA is on ({:?}), they are exclusive", self.msaa);
         }
     }
 }
@@ -29,9 +52,76 @@ impl Component for WorldFlags {
                             WorldEvent::SpectatorMode(self.spectator_mode)
                         ]
                     }
+                    VirtualKeyCode::P => {
+                        self.physics_mode = !self.physics_mode;
+                        vec![
+                            WorldEvent::PhysicsMode(self.physics_mode)
+                        ]
+                    }
+                    VirtualKeyCode::B => {
+                        self.chunk_borders = !self.chunk_borders;
+                        vec![
+                            WorldEvent::ChunkBorders(self.chunk_borders)
+                        ]
+                    }
+                    VirtualKeyCode::M => {  // e.g. still water for screenshots
+                        self.fluid_motion = !self.fluid_motion;
+                        vec![
+                            WorldEvent::FluidMotion(self.fluid_motion)
+                        ]
+                    }
+                    VirtualKeyCode::L => {  // e.g. for meshing bugs
+                        self.wireframe = !self.wireframe;
+                        vec![
+                            WorldEvent::WireframeMode(self.wireframe)
+                        ]
+                    }
+                    VirtualKeyCode::N => {  // e.g. comparing the chunks drawn with and without it
+                        self.occlusion_culling = !self.occlusion_culling;
+                        self.warn_msaa_exclusive();
+                        vec![
+                            WorldEvent::OcclusionCulling(self.occlusion_culling)
+                        ]
+                    }
+                    VirtualKeyCode::V => {  // e.g. uncapped for measuring the frame rate
+                        self.present_mode = self.present_mode.next();
+                        vec![
+                            WorldEvent::SetPresentMode(self.present_mode)
+                        ]
+                    }
+                    VirtualKeyCode::J => {  // e.g. comparing the terrain edges with and without it
+                        self.msaa = self.msaa.next();
+                        self.warn_msaa_exclusive();
+                        vec![
+                            WorldEvent::SetMsaa(self.msaa)
+                        ]
+                    }
+                    VirtualKeyCode::R => {
+                        vec![
+                            WorldEvent::RegenerateWorld
+                        ]
+                    }
+                    VirtualKeyCode::X => {
+                        vec![
+                            WorldEvent::ExportTerrain
+                        ]
+                    }
+                    VirtualKeyCode::F5 => {
+                        vec![
+                            WorldEvent::ReloadShaders
+                        ]
+                    }
                     _ => {vec![]}
                 }
             }
+            WorldEvent::Start => {
+                vec![
+                    WorldEvent::PhysicsMode(self.physics_mode),
+                    WorldEvent::ChunkBorders(self.chunk_borders),
+                    WorldEvent::FluidMotion(self.fluid_motion),
+                    WorldEvent::OcclusionCulling(self.occlusion_culling),
+                ]
+            }
             _ => {vec![]}
         }
     }
@@ -0,0 +1,156 @@
+use std::rc::Rc;
+use ash::Device;
+use winit::event::VirtualKeyCode;
+use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::handler::VulkanInstance;
+use crate::util::{CmdBufContext, FrameUniform};
+use crate::world::WorldEvent;
+
+
+// distance fog of the terrain, in the std140 layout of the fog uniform of the chunk fragment shaders
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct FogParams {
+    pub(crate) color: [f32; 4],  // linear, so it matches the sky once converted back to sRGB
+    pub(crate) density: f32,  // per blox past the start
+    pub(crate) start: f32,  // in blox from the camera
+    pub(crate) end: f32,  // fully fogged beyond it
+    _pad: f32,
+}
+
+impl FogParams {
+    pub fn new(color: [f32; 4], density: f32, start: f32, end: f32) -> Self {
+        Self { color, density, start, end, _pad: 0.0 }
+    }
+
+    // same as the fog amount computed by the chunk fragment shaders, from 0 (clear) to 1 (the fog color)
+    pub fn amount(&self, dist: f32) -> f32 {
+        if dist >= self.end {
+            1.0
+        } else {
+            1.0-(-self.density*(dist-self.start).max(0.0)).exp()
+        }
+    }
+}
+
+// converts the sRGB color (e.g. of the sky texels) into the linear color the shaders blend with
+pub(crate) fn srgb_to_linear(color: [u8; 4]) -> [f32; 4] {
+    let channel = |c: u8| {
+        let c = c as f32/255.0;
+        if c <= 0.04045 {c/12.92} else {((c+0.055)/1.055).powf(2.4)}
+    };
+    [channel(color[0]), channel(color[1]), channel(color[2]), color[3] as f32/255.0]
+}
+
+pub(crate) struct FogComponent {
+    params: FogParams,
+
+    ubo: FrameUniform<FogParams>,
+}
+
+impl FogComponent {
+    const DENSITY_STEP: f32 = 1.25;  // scales the density per key press
+
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, params: FogParams) -> Self {
+        let ubo = unsafe { FrameUniform::new(vi, device, params) };
+
+        Self {
+            params,
+            ubo,
+        }
+    }
+
+    fn set_density(&mut self, density: f32) -> bool {
+        if !(density.is_finite() && density >= 0.0) {
+            return false;
+        }
+        self.params.density = density;
+        self.ubo.set(self.params);
+        true
+    }
+
+    fn set_color(&mut self, color: [f32; 4]) {
+        self.params.color = color;
+        self.ubo.set(self.params);
+    }
+}
+
+impl Component for FogComponent {
+    fn render(&self) -> Vec<RenderData> {
+        vec![]
+    }
+
+    fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
+        match event {
+            WorldEvent::SetFogDensity(density) => {
+                if !self.set_density(density) {
+                    log::warn!("Ignoring the invalid fog density {density}, it must not be negative");
+                }
+                vec![]
+            }
+            WorldEvent::SetFogColor(color) => {
+                self.set_color(color);
+                vec![]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::Comma) => {
+                vec![WorldEvent::SetFogDensity(self.params.density/Self::DENSITY_STEP)]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::Period) => {
+                vec![WorldEvent::SetFogDensity(self.params.density*Self::DENSITY_STEP)]
+            }
+            WorldEvent::Start => {
+                vec![WorldEvent::SetFogDensity(self.params.density)]
+            }
+            _ => {vec![]}
+        }
+    }
+
+    fn update(&mut self) {
+
+    }
+
+    fn prepare_frame(&mut self, frame_index: usize) {
+        unsafe { self.ubo.write(frame_index); }
+    }
+
+    unsafe fn load_descriptors(&mut self, _: CmdBufContext) -> Vec<RenderData> {
+        vec![RenderData::InitialDescriptorBuffer(self.ubo.descriptor_buffer_infos(), RenderDataPurpose::FogParams)]
+    }
+
+    unsafe fn destroy(&mut self) {
+        self.ubo.destroy();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use super::*;
+
+    #[test]
+    fn test_fog_amount_over_distance() {
+        let fog = FogParams::new([0.5, 0.7, 0.9, 1.0], 0.01, 64.0, 512.0);
+
+        // clear up to the start, and fully fogged from the end
+        assert_eq!(fog.amount(0.0), 0.0);
+        assert_eq!(fog.amount(64.0), 0.0);
+        assert!((fog.amount(164.0)-(1.0-(-1.0f32).exp())).abs() < 1e-6);
+        assert!(fog.amount(300.0) < fog.amount(400.0));
+        assert_eq!(fog.amount(512.0), 1.0);
+        assert_eq!(fog.amount(10000.0), 1.0);
+
+        // matches the uniform block of the shaders (a vec4 followed by three floats)
+        assert_eq!(mem::size_of::<FogParams>(), 32);
+    }
+
+    #[test]
+    fn test_srgb_to_linear() {
+        assert_eq!(srgb_to_linear([0, 0, 0, 0]), [0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(srgb_to_linear([255, 255, 255, 255]), [1.0, 1.0, 1.0, 1.0]);
+        // mid gray is darker in linear
+        let [gray, _, _, alpha] = srgb_to_linear([128, 128, 128, 128]);
+        assert!((gray-0.2158).abs() < 1e-3);
+        assert!((alpha-128.0/255.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,142 @@
+use winit::event::VirtualKeyCode;
+use crate::component::{Component, RenderData};
+use crate::component::terrain::{Block, BlockData, MeshType};
+use crate::world::WorldEvent;
+
+
+// which blocks the user can select for placement
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameMode {
+    Creative,  // every placeable block of the block index
+    Survival(Vec<&'static str>),  // only the blocks with the given identifiers
+}
+
+pub(crate) struct Hotbar {
+    blocks: Vec<Block>,
+    selected: usize,
+    spectator_mode: bool,  // the mouse wheel sets the fly speed instead
+    scrolled: f32,  // lines scrolled short of a whole slot, from touchpads
+}
+
+impl Hotbar {
+    const SLOT_KEYS: [VirtualKeyCode; 9] = [
+        VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4, VirtualKeyCode::Key5, VirtualKeyCode::Key6,
+        VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+    ];
+
+    pub(crate) fn new(mode: GameMode, block_ind: &[BlockData]) -> Self {
+        let blocks = block_ind.iter().enumerate()
+            .filter(|(_, block)| match &mode {
+                GameMode::Creative => !matches!(block.mesh, MeshType::Empty),
+                GameMode::Survival(idents) => idents.contains(&block.ident),
+            })
+            .map(|(ind, _)| Block(ind as u16))
+            .collect();
+
+        Self { blocks, selected: 0, spectator_mode: false, scrolled: 0.0 }
+    }
+
+    pub(crate) fn selected(&self) -> Option<Block> {
+        self.blocks.get(self.selected).copied()
+    }
+
+    // positive steps move towards the end of the hotbar, wrapping around at both ends
+    fn scroll(&mut self, steps: i32) -> Vec<WorldEvent> {
+        if self.blocks.is_empty() || steps == 0 {
+            return vec![];
+        }
+        self.selected = (self.selected as i32+steps).rem_euclid(self.blocks.len() as i32) as usize;
+        self.selected().map(WorldEvent::SelectedBlockChanged).into_iter().collect()
+    }
+
+    fn select(&mut self, slot: usize) -> Vec<WorldEvent> {
+        if slot >= self.blocks.len() || slot == self.selected {
+            return vec![];
+        }
+        self.selected = slot;
+        self.selected().map(WorldEvent::SelectedBlockChanged).into_iter().collect()
+    }
+}
+
+impl Component for Hotbar {
+    fn render(&self) -> Vec<RenderData> {
+        vec![]
+    }
+
+    fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
+        match event {
+            WorldEvent::MouseScroll { delta_y, .. } if !self.spectator_mode => {
+                // scrolling down moves to the next slot
+                self.scrolled -= delta_y;
+                let steps = self.scrolled.trunc();
+                self.scrolled -= steps;
+                self.scroll(steps as i32)
+            }
+            WorldEvent::KeyPressed(key) => {
+                match Self::SLOT_KEYS.iter().position(|slot_key| *slot_key == key) {
+                    Some(slot) => self.select(slot),
+                    None => vec![],
+                }
+            }
+            WorldEvent::SpectatorMode(enabled) => {
+                self.spectator_mode = enabled;
+                vec![]
+            }
+            WorldEvent::Start => {
+                self.selected().map(WorldEvent::SelectedBlockChanged).into_iter().collect()
+            }
+            _ => {vec![]}
+        }
+    }
+
+    fn update(&mut self) {
+
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::component::terrain::mesh_util::tests::test_block_ind;
+    use super::*;
+
+    fn selected_ind(events: Vec<WorldEvent>) -> Vec<u16> {
+        events.into_iter()
+            .map(|e| match e {
+                WorldEvent::SelectedBlockChanged(block) => block.0,
+                other => panic!("Unexpected event {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scrolling_wraps_around() {
+        let mut hotbar = Hotbar::new(GameMode::Survival(vec!["dirt", "stone", "water"]), &test_block_ind());
+        assert_eq!(selected_ind(hotbar.respond_event(WorldEvent::Start)), vec![1]);
+
+        assert_eq!(selected_ind(hotbar.respond_event(WorldEvent::MouseScroll { delta_x: 0.0, delta_y: -1.0 })), vec![2]);
+        assert_eq!(selected_ind(hotbar.respond_event(WorldEvent::MouseScroll { delta_x: 0.0, delta_y: -1.0 })), vec![6]);
+        assert_eq!(selected_ind(hotbar.respond_event(WorldEvent::MouseScroll { delta_x: 0.0, delta_y: -1.0 })), vec![1]);
+        assert_eq!(selected_ind(hotbar.respond_event(WorldEvent::MouseScroll { delta_x: 0.0, delta_y: 1.0 })), vec![6]);
+
+        assert_eq!(selected_ind(hotbar.respond_event(WorldEvent::KeyPressed(VirtualKeyCode::Key2))), vec![2]);
+        // no slot 5 in this hotbar
+        assert!(hotbar.respond_event(WorldEvent::KeyPressed(VirtualKeyCode::Key5)).is_empty());
+
+        // partial lines add up to a slot
+        assert!(hotbar.respond_event(WorldEvent::MouseScroll { delta_x: 0.0, delta_y: -0.5 }).is_empty());
+        assert_eq!(selected_ind(hotbar.respond_event(WorldEvent::MouseScroll { delta_x: 0.0, delta_y: -0.5 })), vec![6]);
+
+        // the wheel sets the fly speed in spectator mode
+        hotbar.respond_event(WorldEvent::SpectatorMode(true));
+        assert!(hotbar.respond_event(WorldEvent::MouseScroll { delta_x: 0.0, delta_y: -1.0 }).is_empty());
+    }
+
+    #[test]
+    fn test_creative_has_every_placeable_block() {
+        let hotbar = Hotbar::new(GameMode::Creative, &test_block_ind());
+        // everything but air
+        assert_eq!(hotbar.blocks.iter().map(|b| b.0).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5, 6, 8, 9, 10]);
+    }
+}
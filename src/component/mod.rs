@@ -4,8 +4,18 @@ pub mod texture;
 pub mod debug_ui;
 pub mod tick;
 pub mod flags;
+pub mod hotbar;
+pub mod skybox;
+pub mod fog;
+pub mod sun;
+pub mod sky;
+pub mod physics;
+pub mod point_light;
 
 use ash::vk;
+use crate::component::debug_ui::UiDraw;
+use crate::component::terrain::chunk_mesh::ChunkRange;
+use crate::debug::Diagnostics;
 use crate::util::CmdBufContext;
 use crate::world::{WorldEvent};
 
@@ -15,13 +25,22 @@ use crate::world::{WorldEvent};
 pub enum RenderDataPurpose {
     CameraViewProjection,
     BlockTextures,
+    Skybox,
+    FogParams,
+    SkyParams,
     TerrainOpaque,
     TerrainTransparent,
     TerrainTranslucent,
     PresentationInpAttachment,
+    DepthAttachment,  // sampled after the renderpass
+    GBufferInpAttachment(u32),  // nth G-buffer attachment (FBAttachmentRef::GBuffer or DepthInput), read by a later subpass
+    PointLights,  // storage buffer of the lights added by the deferred shading
     DebugUI,
     DebugUIInpAttachment,
+    DebugChunkBorders,
     Time,
+    // defined outside of the core by an id its component and shader agree on, handed to Shader::custom_render_data
+    Custom(u64),
 }
 
 
@@ -30,9 +49,33 @@ pub enum RenderDataPurpose {
 pub enum RenderData {
     InitialDescriptorBuffer(Vec<vk::DescriptorBufferInfo>, RenderDataPurpose),
     InitialDescriptorImage(Vec<vk::DescriptorImageInfo>, RenderDataPurpose),
+    UpdateDescriptorImage(Vec<vk::DescriptorImageInfo>, RenderDataPurpose),  // rewritten once the in-flight frame completes
     RecreateVertexBuffer(vk::Buffer, vk::DeviceMemory, RenderDataPurpose),
     RecreateIndexBuffer(vk::Buffer, vk::DeviceMemory, u32, RenderDataPurpose),
-    SetScissorDynamicState(vk::Rect2D, RenderDataPurpose),
+    // a chunk ranged out of a buffer shared with other chunks, its vertices (from verts.start) followed by its indices
+    // (from inds.start, relative to verts.start), the uploader owns the buffer
+    RecreateChunkBuffer(ChunkRange, vk::Buffer, RenderDataPurpose),
+    ClearChunkBuffers(Vec<u64>, RenderDataPurpose),  // by their mesh ids
+    SetUiDraws(Vec<UiDraw>, RenderDataPurpose),  // of the vertex and index buffer, each within its own scissor and texture
+    SetDrawChunks(Vec<u64>, RenderDataPurpose),  // mesh ids of the chunks to draw (e.g. within the frustum), instead of all of them
+    ClearBuffer(RenderDataPurpose),
+}
+
+impl RenderData {
+    pub fn purpose(&self) -> RenderDataPurpose {
+        match self {
+            RenderData::InitialDescriptorBuffer(_, purpose) |
+            RenderData::InitialDescriptorImage(_, purpose) |
+            RenderData::UpdateDescriptorImage(_, purpose) |
+            RenderData::RecreateVertexBuffer(_, _, purpose) |
+            RenderData::RecreateIndexBuffer(_, _, _, purpose) |
+            RenderData::RecreateChunkBuffer(_, _, purpose) |
+            RenderData::ClearChunkBuffers(_, purpose) |
+            RenderData::SetUiDraws(_, purpose) |
+            RenderData::SetDrawChunks(_, purpose) |
+            RenderData::ClearBuffer(purpose) => *purpose,
+        }
+    }
 }
 
 // using a single master trait for components, since splitting the trait into related methods
@@ -42,10 +85,13 @@ pub enum RenderData {
 pub trait Component {
     // Renderable
     fn render(&self) -> Vec<RenderData>;
+    fn prepare_frame(&mut self, _: usize) {}  // writes the per frame buffers once the frame is no longer in flight
     // Interactable
     fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent>;  // emits new event(s)
     fn update(&mut self);
     // Descriptable
     unsafe fn load_descriptors(&mut self, _: CmdBufContext) -> Vec<RenderData> {Vec::new()}
     unsafe fn destroy(&mut self) {}
+    // Diagnosable
+    fn diagnostics(&self, _: &mut Diagnostics) {}
 }
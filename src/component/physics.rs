@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+
+// solidity of the blocks for the components colliding with the terrain (e.g. the camera), in the block coordinates
+// of the render space (the terrain looks them up through terrain::world_block)
+pub(crate) trait BlockQuery {
+    fn solid(&self, block: [i32; 3]) -> bool;
+}
+
+impl<F: Fn([i32; 3]) -> bool> BlockQuery for F {
+    fn solid(&self, block: [i32; 3]) -> bool {
+        self(block)
+    }
+}
+
+// axis aligned box of the user around the eye, falling and colliding with the solid blocks
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct PhysicsBody {
+    velocity: [f32; 3],  // blocks per second
+    pub(crate) on_ground: bool,
+}
+
+impl PhysicsBody {
+    const GRAVITY: f32 = 28.0;  // blocks per second squared
+    const TERMINAL_SPEED: f32 = 60.0;
+    const JUMP_SPEED: f32 = 8.5;  // a bit over a block high
+    const HALF_WIDTH: f32 = 0.3;
+    const HEIGHT: f32 = 1.8;
+    pub(crate) const EYE_HEIGHT: f32 = 1.62;  // above the bottom of the box
+    const EPSILON: f32 = 1e-4;  // keeps the box from counting the blocks it only touches as overlapping
+
+    // only from the ground
+    pub(crate) fn jump(&mut self) {
+        if self.on_ground {
+            self.velocity[1] = Self::JUMP_SPEED;
+            self.on_ground = false;
+        }
+    }
+
+    // the new eye position after the walked displacement (in blocks) and the fall within the frame
+    pub(crate) fn step(&mut self, eye: [f32; 3], walk: [f32; 2], delta: Duration, blocks: &dyn BlockQuery) -> [f32; 3] {
+        let dt = delta.as_secs_f32();
+        self.velocity[1] = (self.velocity[1]-Self::GRAVITY*dt).max(-Self::TERMINAL_SPEED);
+
+        let mut min = [eye[0]-Self::HALF_WIDTH, eye[1]-Self::EYE_HEIGHT, eye[2]-Self::HALF_WIDTH];
+        let mut max = [eye[0]+Self::HALF_WIDTH, eye[1]-Self::EYE_HEIGHT+Self::HEIGHT, eye[2]+Self::HALF_WIDTH];
+
+        // one axis at a time, so the box slides along the walls and lands on the ground
+        let fall = self.velocity[1]*dt;
+        self.on_ground = false;
+        for (axis, displacement) in [(1, fall), (0, walk[0]), (2, walk[1])] {
+            let moved = sweep_axis(min, max, axis, displacement, blocks);
+            if axis == 1 && moved != displacement {
+                self.on_ground = displacement < 0.0;
+                self.velocity[1] = 0.0;
+            }
+            min[axis] += moved;
+            max[axis] += moved;
+        }
+
+        [min[0]+Self::HALF_WIDTH, min[1]+Self::EYE_HEIGHT, min[2]+Self::HALF_WIDTH]
+    }
+}
+
+// how far the box can move along the axis before it runs into a solid block
+fn sweep_axis(min: [f32; 3], max: [f32; 3], axis: usize, displacement: f32, blocks: &dyn BlockQuery) -> f32 {
+    let eps = PhysicsBody::EPSILON;
+    // the blocks the box covers on the other axes
+    let cross = |k: usize| (min[k]+eps).floor() as i32..=(max[k]-eps).floor() as i32;
+    let (a, b) = match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+    let layer_solid = |layer: i32| cross(a).any(|i| cross(b).any(|j| {
+        let mut block = [0; 3];
+        block[axis] = layer;
+        block[a] = i;
+        block[b] = j;
+        blocks.solid(block)
+    }));
+
+    if displacement > 0.0 {
+        // the layers of blocks entered by the leading face
+        let first = (max[axis]-eps).floor() as i32+1;
+        let last = (max[axis]+displacement).ceil() as i32-1;
+        match (first..=last).find(|layer| layer_solid(*layer)) {
+            Some(layer) => (layer as f32-max[axis]).max(0.0),
+            None => displacement,
+        }
+    } else if displacement < 0.0 {
+        let first = (min[axis]+eps).floor() as i32-1;
+        let last = (min[axis]+displacement).floor() as i32;
+        match (last..=first).rev().find(|layer| layer_solid(*layer)) {
+            Some(layer) => (layer as f32+1.0-min[axis]).min(0.0),
+            None => displacement,
+        }
+    } else {
+        0.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // solid up to and including y = 9, so the surface is at y = 10
+    fn flat(block: [i32; 3]) -> bool {
+        block[1] < 10
+    }
+
+    #[test]
+    fn test_falls_onto_flat_terrain_and_rests() {
+        let mut body = PhysicsBody::default();
+        let frame = Duration::from_millis(16);
+        let mut eye = [0.5, 30.0, 0.5];
+
+        for _ in 0..300 {
+            eye = body.step(eye, [0.0, 0.0], frame, &flat);
+        }
+        assert!(body.on_ground);
+        assert!((eye[1]-(10.0+PhysicsBody::EYE_HEIGHT)).abs() < 1e-4, "{eye:?}");
+        assert_eq!(eye[0], 0.5);
+
+        // stays on the surface, and walks along it
+        let rest = eye;
+        eye = body.step(eye, [0.2, -0.1], frame, &flat);
+        assert_eq!(eye[1], rest[1]);
+        assert!((eye[0]-0.7).abs() < 1e-6 && (eye[2]-0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fast_fall_does_not_tunnel() {
+        let mut body = PhysicsBody { velocity: [0.0, -PhysicsBody::TERMINAL_SPEED, 0.0], on_ground: false };
+        // a single long frame covering the whole drop
+        let eye = body.step([0.5, 40.0, 0.5], [0.0, 0.0], Duration::from_secs(1), &flat);
+        assert!(body.on_ground);
+        assert!((eye[1]-(10.0+PhysicsBody::EYE_HEIGHT)).abs() < 1e-4, "{eye:?}");
+    }
+
+    #[test]
+    fn test_walls_block_walking_and_jumps_leave_the_ground() {
+        // a wall at x = 2 on top of the flat terrain
+        let walled = |block: [i32; 3]| flat(block) || block[0] == 2;
+        let mut body = PhysicsBody::default();
+        let frame = Duration::from_millis(16);
+        let mut eye = body.step([0.5, 10.0+PhysicsBody::EYE_HEIGHT, 0.5], [0.0, 0.0], frame, &walled);
+        assert!(body.on_ground);
+
+        eye = body.step(eye, [5.0, 0.0], frame, &walled);
+        assert!((eye[0]-(2.0-PhysicsBody::HALF_WIDTH)).abs() < 1e-4, "{eye:?}");
+
+        body.jump();
+        let ground = eye[1];
+        eye = body.step(eye, [0.0, 0.0], frame, &walled);
+        assert!(!body.on_ground);
+        assert!(eye[1] > ground);
+        // can't jump again mid-air
+        let velocity = body.velocity[1];
+        body.jump();
+        assert_eq!(body.velocity[1], velocity);
+    }
+}
@@ -0,0 +1,106 @@
+use std::rc::Rc;
+use ash::{Device, vk};
+use winit::event::VirtualKeyCode;
+use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::handler::VulkanInstance;
+use crate::measurement::blox;
+use crate::shader::deferred::PointLight;
+use crate::util::{CmdBufContext, FrameUniform};
+use crate::world::WorldEvent;
+
+
+// lights of the storage buffer read by the lighting subpass, the slots beyond the added lights are left unlit
+pub(crate) const MAX_POINT_LIGHTS: usize = 32;
+
+// supplies the point lights of the deferred shader (ignored by the forward shader), added through AddPointLight
+pub(crate) struct PointLightComponent {
+    lights: Vec<PointLight>,
+    user_pos: [f32; 3],  // where the demo torch is dropped
+
+    ssbo: FrameUniform<[PointLight; MAX_POINT_LIGHTS]>,
+}
+
+impl PointLightComponent {
+    // warm light of a torch dropped at the camera with the I key
+    const TORCH: PointLight = PointLight { position: [0.0; 3], radius: 12.0, color: [1.0, 0.6, 0.25], intensity: 1.5 };
+
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, lights: Vec<PointLight>) -> Self {
+        let ssbo = unsafe { FrameUniform::with_usage(vi, device, [PointLight::NONE; MAX_POINT_LIGHTS], vk::BufferUsageFlags::STORAGE_BUFFER) };
+        let mut component = Self { lights: vec![], user_pos: [0.0; 3], ssbo };
+        for light in lights {
+            component.add(light);
+        }
+        component
+    }
+
+    fn add(&mut self, light: PointLight) -> bool {
+        if self.lights.len() >= MAX_POINT_LIGHTS || !light.radius.is_finite() || light.radius <= 0.0 {
+            return false;
+        }
+        self.lights.push(light);
+        self.ssbo.set(slots(&self.lights));
+        true
+    }
+}
+
+// the lights followed by the unlit placeholders filling the rest of the storage buffer
+fn slots(lights: &[PointLight]) -> [PointLight; MAX_POINT_LIGHTS] {
+    let mut slots = [PointLight::NONE; MAX_POINT_LIGHTS];
+    slots[..lights.len()].copy_from_slice(lights);
+    slots
+}
+
+impl Component for PointLightComponent {
+    fn render(&self) -> Vec<RenderData> {
+        vec![]
+    }
+
+    fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
+        match event {
+            WorldEvent::AddPointLight(light) => {
+                if !self.add(light) {
+                    log::warn!("Ignoring the point light {light:?}, either its radius isn't positive or all {MAX_POINT_LIGHTS} lights are taken");
+                }
+                vec![]
+            }
+            WorldEvent::UserPosition(pos) => {
+                self.user_pos = [pos.x.get::<blox>(), pos.y.get::<blox>(), pos.z.get::<blox>()];
+                vec![]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::I) => {
+                vec![WorldEvent::AddPointLight(PointLight { position: self.user_pos, ..Self::TORCH })]
+            }
+            _ => {vec![]}
+        }
+    }
+
+    fn update(&mut self) {
+
+    }
+
+    fn prepare_frame(&mut self, frame_index: usize) {
+        unsafe { self.ssbo.write(frame_index); }
+    }
+
+    unsafe fn load_descriptors(&mut self, _: CmdBufContext) -> Vec<RenderData> {
+        vec![RenderData::InitialDescriptorBuffer(self.ssbo.descriptor_buffer_infos(), RenderDataPurpose::PointLights)]
+    }
+
+    unsafe fn destroy(&mut self) {
+        self.ssbo.destroy();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlit_slots_after_lights() {
+        let light = PointLight { position: [1.0, 2.0, 3.0], ..PointLightComponent::TORCH };
+        let slots = slots(&[light, light]);
+        assert_eq!(slots[..2], [light, light]);
+        assert!(slots[2..].iter().all(|slot| slot.intensity == 0.0));
+    }
+}
@@ -0,0 +1,249 @@
+use std::f32::consts::PI;
+use std::rc::Rc;
+use ash::Device;
+use winit::event::VirtualKeyCode;
+use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::component::sun::SunLight;
+use crate::component::tick::TICKS_PER_SECOND;
+use crate::handler::VulkanInstance;
+use crate::util::{CmdBufContext, FrameUniform};
+use crate::world::WorldEvent;
+
+
+// sun shading of the terrain and tint of the sky, in the std140 layout of the sky uniform of the chunk and skybox
+// fragment shaders
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct SkyParams {
+    pub(crate) sun_direction: [f32; 3],  // normalized, towards the sun in the render space (+y is up)
+    pub(crate) ambient: f32,  // brightness of the faces turned away from the sun
+    pub(crate) tint: [f32; 3],  // multiplies the skybox (drawn in its daytime colors) and the fog color
+    pub(crate) diffuse: f32,  // added brightness of the faces facing the sun
+}
+
+impl SkyParams {
+    // same as sun_light of lighting.glsl, applied by the shaders drawing the terrain
+    pub fn shade(&self, normal: [f32; 3]) -> f32 {
+        let facing = normal.iter().zip(self.sun_direction).map(|(n, d)| n*d).sum::<f32>().max(0.0);
+        self.ambient+self.diffuse*facing
+    }
+}
+
+// time of day going around in a loop, 0 is midnight, 0.25 sunrise (in +x), 0.5 noon and 0.75 sunset
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct DayCycle {
+    time_of_day: f32,
+    day_length: f32,  // in seconds at a tick rate of 1
+    pinned_sun: Option<[f32; 3]>,  // overrides the direction of the sun, until the time of day is set
+}
+
+impl DayCycle {
+    const DAY_AMBIENT: f32 = 0.55;
+    const NIGHT_AMBIENT: f32 = 0.12;
+    const NIGHT_TINT: [f32; 3] = [0.05, 0.06, 0.14];
+    const SUN_TILT: f32 = 0.4;  // towards +z, so the sun isn't straight up at noon
+
+    pub(crate) fn new(time_of_day: f32, day_length: f32) -> Self {
+        Self { time_of_day: time_of_day.rem_euclid(1.0), day_length: day_length.max(f32::EPSILON), pinned_sun: None }
+    }
+
+    pub(crate) fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    pub(crate) fn set(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.0);
+        self.pinned_sun = None;
+    }
+
+    pub(crate) fn advance(&mut self, seconds: f32) {
+        self.time_of_day = (self.time_of_day+seconds/self.day_length).rem_euclid(1.0);
+    }
+
+    // the daylight follows the pinned sun, while the time of day keeps passing
+    pub(crate) fn pin_sun(&mut self, sun: SunLight) {
+        self.pinned_sun = Some(sun.direction);
+    }
+
+    fn sun_direction(&self) -> [f32; 3] {
+        if let Some(direction) = self.pinned_sun {
+            return direction;
+        }
+        let angle = 2.0*PI*(self.time_of_day-0.25);
+        let dir = [angle.cos(), angle.sin(), Self::SUN_TILT];
+        let len = dir.iter().map(|c| c*c).sum::<f32>().sqrt();
+        dir.map(|c| c/len)
+    }
+
+    // from 0 at night to 1 during the day, with a twilight while the sun is around the horizon
+    fn daylight(&self) -> f32 {
+        (self.sun_direction()[1]*4.0+0.3).clamp(0.0, 1.0)
+    }
+
+    pub(crate) fn sun_light(&self) -> SunLight {
+        let ambient = Self::NIGHT_AMBIENT+(Self::DAY_AMBIENT-Self::NIGHT_AMBIENT)*self.daylight();
+        SunLight { direction: self.sun_direction(), ambient }
+    }
+
+    pub(crate) fn sky_params(&self) -> SkyParams {
+        let daylight = self.daylight();
+        let lerp = |night: f32, day: f32| night+(day-night)*daylight;
+        let sun = self.sun_light();
+        SkyParams {
+            sun_direction: sun.direction,
+            ambient: sun.ambient,
+            tint: Self::NIGHT_TINT.map(|night| lerp(night, 1.0)),
+            diffuse: lerp(0.0, 1.0-Self::DAY_AMBIENT),
+        }
+    }
+}
+
+pub(crate) struct SkyComponent {
+    cycle: DayCycle,
+    fog_color: [f32; 4],  // during the day, tinted along with the sky
+    reported: Option<SkyParams>,  // last emitted
+
+    ubo: FrameUniform<SkyParams>,
+}
+
+impl SkyComponent {
+    const SCRUB_STEP: f32 = 1.0/24.0;  // an hour per key press
+
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, cycle: DayCycle, fog_color: [f32; 4]) -> Self {
+        let ubo = unsafe { FrameUniform::new(vi, device, cycle.sky_params()) };
+
+        Self {
+            cycle,
+            fog_color,
+            reported: None,
+            ubo,
+        }
+    }
+}
+
+impl Component for SkyComponent {
+    fn render(&self) -> Vec<RenderData> {
+        vec![]
+    }
+
+    fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
+        match event {
+            WorldEvent::Tick { count } => {
+                self.cycle.advance(count as f32/TICKS_PER_SECOND);
+                vec![]
+            }
+            WorldEvent::SetTimeOfDay(time_of_day) => {
+                self.cycle.set(time_of_day);
+                vec![]
+            }
+            WorldEvent::SetSunDirection(direction) => {
+                match SunLight::new(direction, self.cycle.sun_light().ambient) {
+                    Some(sun) => self.cycle.pin_sun(sun),
+                    None => log::warn!("Ignoring the sun direction {direction:?}, it must not be zero"),
+                }
+                vec![]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::H) => {
+                vec![WorldEvent::SetTimeOfDay(self.cycle.time_of_day()+Self::SCRUB_STEP)]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::G) => {
+                vec![WorldEvent::SetTimeOfDay(self.cycle.time_of_day()-Self::SCRUB_STEP)]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::U) => {  // freezes the sun where it is, until scrubbed again
+                vec![WorldEvent::SetSunDirection(self.cycle.sun_light().direction)]
+            }
+            WorldEvent::DeltaTime(_) => {
+                // the ticks, scrubbing and pinning of the previous frame
+                let params = self.cycle.sky_params();
+                if self.reported.replace(params) == Some(params) {
+                    return vec![];
+                }
+                self.ubo.set(params);
+                let time_of_day = self.cycle.time_of_day();
+                let [r, g, b, a] = self.fog_color;
+                vec![
+                    WorldEvent::TimeOfDay(time_of_day),
+                    WorldEvent::SetFogColor([r*params.tint[0], g*params.tint[1], b*params.tint[2], a]),
+                ]
+            }
+            _ => {vec![]}
+        }
+    }
+
+    fn update(&mut self) {
+
+    }
+
+    fn prepare_frame(&mut self, frame_index: usize) {
+        unsafe { self.ubo.write(frame_index); }
+    }
+
+    unsafe fn load_descriptors(&mut self, _: CmdBufContext) -> Vec<RenderData> {
+        vec![RenderData::InitialDescriptorBuffer(self.ubo.descriptor_buffer_infos(), RenderDataPurpose::SkyParams)]
+    }
+
+    unsafe fn destroy(&mut self) {
+        self.ubo.destroy();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use super::*;
+
+    #[test]
+    fn test_sky_shade_over_normals() {
+        let noon = DayCycle::new(0.5, 600.0).sky_params();
+        let up = [0.0, 1.0, 0.0];
+
+        // the top faces are brightest at noon, the bottom faces only get the ambient light
+        assert!(noon.shade(up) > 0.95 && noon.shade(up) <= 1.0);
+        assert_eq!(noon.shade([0.0, -1.0, 0.0]), DayCycle::DAY_AMBIENT);
+        assert_eq!(noon.tint, [1.0; 3]);
+        // matches the uniform block of the shaders (two vec3 each followed by a float)
+        assert_eq!(mem::size_of::<SkyParams>(), 32);
+    }
+
+    #[test]
+    fn test_day_cycle_darkens_at_night() {
+        let mut cycle = DayCycle::new(0.25, 600.0);
+        let sunrise = cycle.sky_params();
+        assert!(sunrise.sun_direction[0] > 0.9 && sunrise.sun_direction[1].abs() < 1e-6);
+
+        // half a day later is sunset, then midnight
+        cycle.advance(300.0);
+        assert!((cycle.time_of_day()-0.75).abs() < 1e-6);
+        cycle.advance(150.0);
+        let midnight = cycle.sky_params();
+        assert_eq!(midnight.ambient, DayCycle::NIGHT_AMBIENT);
+        assert_eq!(midnight.diffuse, 0.0);
+        assert_eq!(midnight.tint, DayCycle::NIGHT_TINT);
+        // the sun below the horizon doesn't light the bottom faces either
+        assert_eq!(midnight.shade([0.0, -1.0, 0.0]), DayCycle::NIGHT_AMBIENT);
+
+        // wraps around in both directions
+        cycle.set(1.25);
+        assert_eq!(cycle.time_of_day(), 0.25);
+        cycle.set(-0.25);
+        assert_eq!(cycle.time_of_day(), 0.75);
+    }
+
+    #[test]
+    fn test_pinned_sun_until_time_set() {
+        let mut cycle = DayCycle::new(0.5, 600.0);
+        cycle.pin_sun(SunLight::new([0.0, 1.0, 1.0], 0.0).unwrap());
+        let pinned = [0.0, 0.5f32.sqrt(), 0.5f32.sqrt()];
+
+        // the sun stays up (and the terrain lit) while the day goes on
+        cycle.advance(300.0);
+        assert_eq!(cycle.time_of_day(), 0.0);
+        let midnight = cycle.sky_params();
+        assert_eq!(midnight.sun_direction, pinned);
+        assert_eq!(midnight.ambient, DayCycle::DAY_AMBIENT);
+
+        cycle.set(0.5);
+        assert_ne!(cycle.sky_params().sun_direction, pinned);
+    }
+}
@@ -0,0 +1,302 @@
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use ash::{Device, vk};
+use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::component::texture::decode_texture;
+use crate::error::MatrixagonError;
+use crate::handler::VulkanInstance;
+use crate::util::{CmdBufContext, allocate_image, create_host_buffer};
+use crate::world::WorldEvent;
+
+
+// decoded RGBA pixels of the six square cubemap faces, in the layer order of the cube image view
+// (+x, -x, +y, -y, +z, -z) with +y being up
+pub(crate) struct CubemapFaces {
+    raw_buf: Vec<u8>,
+    size: u32,
+}
+
+impl CubemapFaces {
+    const FACES: usize = 6;
+
+    fn face_size(&self) -> usize {
+        (self.size*self.size*4) as usize
+    }
+}
+
+pub(crate) fn load_cubemap_faces(face_path: [&Path; 6]) -> io::Result<CubemapFaces> {
+    let mut raw_buf = Vec::new();
+    let mut size = None;
+    for path in face_path {
+        let (width, height, txtr_raw_buf) = decode_texture(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to load skybox face {path:?}: {e}")))?;
+        if width != height || size.is_some_and(|size| size != width) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "Skybox face {path:?} is {width}x{height}, the faces must all be the same square extent"
+            )));
+        }
+        size = Some(width);
+        raw_buf.extend_from_slice(&txtr_raw_buf[..(width*height*4) as usize]);
+    }
+    Ok(CubemapFaces { raw_buf, size: size.unwrap() })
+}
+
+// vertical gradient from the horizon color to the zenith color, the sky below the horizon is the horizon color
+pub(crate) fn gradient_cubemap_faces(size: u32, zenith: [u8; 4], horizon: [u8; 4]) -> CubemapFaces {
+    let mut raw_buf = Vec::with_capacity((size*size*4) as usize*CubemapFaces::FACES);
+    for face in 0..CubemapFaces::FACES {
+        for y in 0..size {
+            for x in 0..size {
+                // direction through the texel center, following the vulkan cubemap face orientations
+                let u = 2.0*(x as f32+0.5)/size as f32-1.0;
+                let v = 2.0*(y as f32+0.5)/size as f32-1.0;
+                let dir = match face {
+                    0 => [1.0, -v, -u],
+                    1 => [-1.0, -v, u],
+                    2 => [u, 1.0, v],
+                    3 => [u, -1.0, -v],
+                    4 => [u, -v, 1.0],
+                    _ => [-u, -v, -1.0],
+                };
+                let elevation = (dir[1]/(dir[0]*dir[0]+dir[1]*dir[1]+dir[2]*dir[2]).sqrt()).max(0.0);
+                raw_buf.extend((0..4).map(|k| {
+                    (horizon[k] as f32+(zenith[k] as f32-horizon[k] as f32)*elevation).round() as u8
+                }));
+            }
+        }
+    }
+    CubemapFaces { raw_buf, size }
+}
+
+pub(crate) struct SkyboxHandler {
+    device: Rc<Device>,
+
+    staging_buf: vk::Buffer,
+    staging_buf_mem: vk::DeviceMemory,
+    face_size: usize,
+    img: vk::Image,
+    img_mem: vk::DeviceMemory,
+    img_extent: vk::Extent3D,
+    img_view: Option<vk::ImageView>,
+    img_sampler: vk::Sampler,
+}
+
+impl SkyboxHandler {
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, face_path: [&Path; 6]) -> Result<Self, MatrixagonError> {
+        let faces = load_cubemap_faces(face_path).map_err(|e| MatrixagonError::Resource("the skybox faces", e))?;
+        Ok(Self::create(vi, device, faces))
+    }
+
+    pub(crate) fn gradient(vi: Rc<VulkanInstance>, device: Rc<Device>, zenith: [u8; 4], horizon: [u8; 4]) -> Self {
+        Self::create(vi, device, gradient_cubemap_faces(64, zenith, horizon))
+    }
+
+    fn create(vi: Rc<VulkanInstance>, device: Rc<Device>, faces: CubemapFaces) -> Self {
+        let img_extent = vk::Extent3D { width: faces.size, height: faces.size, depth: 1 };
+
+        unsafe {
+            let (buf, buf_mem, _, _) = create_host_buffer(vi.clone(), device.clone(), &faces.raw_buf, vk::BufferUsageFlags::TRANSFER_SRC, true);
+
+            let image_info = vk::ImageCreateInfo {
+                flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+                image_type: vk::ImageType::TYPE_2D,
+                extent: img_extent,
+                mip_levels: 1,
+                array_layers: CubemapFaces::FACES as u32,
+                format: vk::Format::R8G8B8A8_SRGB,
+                tiling: vk::ImageTiling::OPTIMAL,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                samples: vk::SampleCountFlags::TYPE_1,
+                sharing_mode: vk::SharingMode::EXCLUSIVE,
+                ..Default::default()
+            };
+            let (img, img_mem) = allocate_image(vi.clone(), device.clone(), &image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL);
+
+            // filtered across the face edges, so the seams between the faces don't show
+            let sampler_info = vk::SamplerCreateInfo {
+                mag_filter: vk::Filter::LINEAR,
+                min_filter: vk::Filter::LINEAR,
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+                unnormalized_coordinates: vk::FALSE,
+                compare_enable: vk::FALSE,
+                compare_op: vk::CompareOp::ALWAYS,
+                mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+                ..Default::default()
+            };
+            let sampler = device.create_sampler(&sampler_info, None)
+                .expect("Failed to create skybox sampler");
+
+            Self {
+                device,
+                staging_buf: buf,
+                staging_buf_mem: buf_mem,
+                face_size: faces.face_size(),
+                img,
+                img_mem,
+                img_extent,
+                img_view: None,
+                img_sampler: sampler,
+            }
+        }
+    }
+
+    fn record(&self) -> impl FnMut(vk::CommandBuffer)+'_ {
+        |cmd_buf| unsafe {
+            let mut barrier = vk::ImageMemoryBarrier {
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: self.img,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: CubemapFaces::FACES as u32,
+                },
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                ..Default::default()
+            };
+            self.device.cmd_pipeline_barrier(
+                cmd_buf, vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(), &[], &[], &[barrier]
+            );
+
+            let regions: Vec<vk::BufferImageCopy> = (0..CubemapFaces::FACES)
+                .map(|face| vk::BufferImageCopy {
+                    buffer_offset: (face*self.face_size) as vk::DeviceSize,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: face as u32,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D {x:0, y:0, z:0},
+                    image_extent: self.img_extent,
+                })
+                .collect();
+            self.device.cmd_copy_buffer_to_image(
+                cmd_buf, self.staging_buf, self.img, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions
+            );
+
+            barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+            barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+            barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+            barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+            self.device.cmd_pipeline_barrier(
+                cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(), &[], &[], &[barrier]
+            );
+        }
+    }
+}
+
+impl Component for SkyboxHandler {
+    fn render(&self) -> Vec<RenderData> {
+        vec![]
+    }
+
+    fn respond_event(&mut self, _: WorldEvent) -> Vec<WorldEvent> {
+        vec![]
+    }
+
+    fn update(&mut self) {
+
+    }
+
+    unsafe fn load_descriptors(&mut self, ctx: CmdBufContext) -> Vec<RenderData> {
+        ctx.record(self.record());
+
+        let img_view_info = vk::ImageViewCreateInfo {
+            image: self.img,
+            view_type: vk::ImageViewType::CUBE,
+            format: vk::Format::R8G8B8A8_SRGB,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: CubemapFaces::FACES as u32,
+            },
+            ..Default::default()
+        };
+        self.img_view.replace(
+            self.device.create_image_view(&img_view_info, None)
+                .expect("Failed to create skybox image view")
+        );
+
+        vec![RenderData::InitialDescriptorImage(
+            vec![vk::DescriptorImageInfo {
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image_view: self.img_view.unwrap(),
+                sampler: self.img_sampler,
+            }],
+            RenderDataPurpose::Skybox
+        )]
+    }
+
+    unsafe fn destroy(&mut self) {
+        self.device.destroy_sampler(self.img_sampler, None);
+        if let Some(img_view) = self.img_view {
+            self.device.destroy_image_view(img_view, None);
+        }
+
+        self.device.destroy_buffer(self.staging_buf, None);
+        self.device.free_memory(self.staging_buf_mem, None);
+
+        self.device.destroy_image(self.img, None);
+        self.device.free_memory(self.img_mem, None);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texel(faces: &CubemapFaces, face: usize, x: u32, y: u32) -> &[u8] {
+        let start = face*faces.face_size()+((y*faces.size+x)*4) as usize;
+        &faces.raw_buf[start..start+4]
+    }
+
+    #[test]
+    fn test_gradient_from_horizon_to_zenith() {
+        let zenith = [40, 80, 230, 255];
+        let horizon = [200, 220, 250, 255];
+        let faces = gradient_cubemap_faces(8, zenith, horizon);
+        assert_eq!(faces.raw_buf.len(), 6*8*8*4);
+
+        // straight up is (almost) the zenith color, the bottom face and lower half of the sides the horizon color
+        assert!(texel(&faces, 2, 3, 3).iter().zip(zenith).all(|(a, b)| a.abs_diff(b) <= 12));
+        assert_eq!(texel(&faces, 3, 3, 3), horizon);
+        for face in [0, 1, 4, 5] {
+            assert_eq!(texel(&faces, face, 3, 7), horizon);
+            // brightens towards the horizon
+            assert!(texel(&faces, face, 3, 0)[0] < texel(&faces, face, 3, 3)[0]);
+        }
+    }
+
+    #[test]
+    fn test_cubemap_faces_must_match() {
+        let path = |name: &str| format!("{}/src/resource/block_textures/{name}.png", env!("CARGO_MANIFEST_DIR"));
+        let (stone, dirt, missing) = (path("stone"), path("dirt"), path("does_not_exist"));
+
+        let faces = load_cubemap_faces([Path::new(&stone); 6]).unwrap();
+        let (_, _, stone_pixels) = decode_texture(Path::new(&stone)).unwrap();
+        assert_eq!(faces.raw_buf.len(), 6*faces.face_size());
+        assert_eq!(faces.raw_buf[5*faces.face_size()..], stone_pixels[..faces.face_size()]);
+
+        let dirt = Path::new(&dirt);
+        assert!(load_cubemap_faces([Path::new(&stone), dirt, dirt, dirt, dirt, dirt]).is_ok());
+        assert!(load_cubemap_faces([Path::new(&stone), dirt, dirt, Path::new(&missing), dirt, dirt]).is_err());
+    }
+}
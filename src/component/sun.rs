@@ -0,0 +1,33 @@
+// directional light of the terrain, its direction is pinned with SetSunDirection or follows the day cycle of the sky
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SunLight {
+    pub(crate) direction: [f32; 3],  // normalized, towards the sun in the render space (+y is up)
+    pub(crate) ambient: f32,  // brightness of the faces turned away from the sun, from 0 to 1
+}
+
+impl SunLight {
+    // None if the direction has no length
+    pub fn new(direction: [f32; 3], ambient: f32) -> Option<Self> {
+        let len = direction.iter().map(|c| c*c).sum::<f32>().sqrt();
+        if !(len.is_finite() && len > 0.0) {
+            return None;
+        }
+        Some(Self { direction: direction.map(|c| c/len), ambient: ambient.clamp(0.0, 1.0) })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sun_normalized() {
+        let sun = SunLight::new([0.0, 2.0, 0.0], 1.4).unwrap();
+        assert_eq!(sun.direction, [0.0, 1.0, 0.0]);
+        assert_eq!(sun.ambient, 1.0);
+
+        assert!(SunLight::new([0.0; 3], 0.4).is_none());
+        assert!(SunLight::new([f32::NAN, 1.0, 0.0], 0.4).is_none());
+    }
+}
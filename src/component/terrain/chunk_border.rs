@@ -0,0 +1,107 @@
+use crate::shader::chunk::ChunkBorderVertex;
+
+
+// line color of the chunk borders for each LOD tier
+pub(crate) const BORDER_COLOR_EF: [f32; 3] = [0.1, 0.9, 0.1];
+pub(crate) const BORDER_COLOR_HF: [f32; 3] = [0.9, 0.9, 0.1];
+pub(crate) const BORDER_COLOR_MF: [f32; 3] = [0.9, 0.2, 0.1];
+// loaded chunks that are not rendered (e.g. inside the inner radius) are drawn dimmed
+const HIDDEN_DIM: f32 = 0.3;
+
+// corners of a unit cube, bottom face first
+const BOX_CORNERS: [[f32; 3]; 8] = [
+    [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0],
+];
+// the 12 edges of the cube as pairs of corner indices
+const BOX_EDGES: [u32; 24] = [
+    0, 1, 1, 2, 2, 3, 3, 0,  // bottom
+    4, 5, 5, 6, 6, 7, 7, 4,  // top
+    0, 4, 1, 5, 2, 6, 3, 7,  // lateral
+];
+
+// generates a line list wireframe box for each chunk bound (south-west corner, size, visible)
+pub(crate) fn chunk_border_mesh(bounds: &[([f32; 3], [f32; 3], bool)], color: [f32; 3])
+    -> (Vec<ChunkBorderVertex>, Vec<u32>) {
+    let mut verts = Vec::with_capacity(bounds.len()*BOX_CORNERS.len());
+    let mut inds = Vec::with_capacity(bounds.len()*BOX_EDGES.len());
+
+    for (pos, size, visible) in bounds {
+        let color = if *visible {color} else {color.map(|c| c*HIDDEN_DIM)};
+        let ind_offset = verts.len() as u32;
+
+        for corner in BOX_CORNERS {
+            verts.push(ChunkBorderVertex {
+                pos: [pos[0]+corner[0]*size[0], pos[1]+corner[1]*size[1], pos[2]+corner[2]*size[2]],
+                color,
+            });
+        }
+        inds.extend(BOX_EDGES.iter().map(|i| i+ind_offset));
+    }
+
+    (verts, inds)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::component::camera::Length3D;
+    use crate::component::RenderDataPurpose;
+    use crate::component::terrain::FaceDir;
+    use crate::component::terrain::chunk_mesh::{Chunk, ChunkGeneratable, ChunkMesh, ChunkPriority, ChunkRadius, ChunkRange, ChunkUnload, Position, UpdateChunk, VertexLimit};
+    use crate::measurement::chux;
+    use crate::util::WorkerPool;
+    use super::*;
+
+    struct EmptyGenerator;
+
+    impl ChunkGeneratable for EmptyGenerator {
+        type A = chux;
+        type B = chux;
+        type V = ();
+        type I = u32;
+
+        fn generate_mesh(&self, _: Length3D) -> Vec<(Vec<()>, Vec<u32>, Option<FaceDir>, RenderDataPurpose)> {
+            vec![]
+        }
+
+        fn aggregate_mesh(&self, _: Length3D, _: &HashMap<Position<chux>, Chunk<(), u32, chux>>)
+            -> Vec<(Vec<()>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_chunk_border_box_count() {
+        // a radius of 2 chunks loads a 4x4x4 cube of chunks around the origin
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(2, 1), None, EmptyGenerator, WorkerPool::new(0),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+
+        let bounds = chunk_mesh.chunk_bounds();
+        assert_eq!(bounds.len(), 64);
+        assert!(bounds.iter().all(|(_, size, visible)| *visible && *size == [32.0, 32.0, 32.0]));
+
+        let (verts, inds) = chunk_border_mesh(&bounds, BORDER_COLOR_EF);
+        assert_eq!(verts.len(), 64*8);
+        assert_eq!(inds.len(), 64*24);
+        assert!(inds.iter().all(|i| (*i as usize) < verts.len()));
+    }
+
+    #[test]
+    fn test_chunk_border_hidden_dimmed() {
+        let bounds = [
+            ([0.0, 0.0, 0.0], [16.0, 16.0, 16.0], true),
+            ([16.0, 0.0, 0.0], [16.0, 16.0, 16.0], false),
+        ];
+        let (verts, inds) = chunk_border_mesh(&bounds, [1.0, 1.0, 1.0]);
+
+        assert_eq!(verts.len(), 16);
+        assert_eq!(inds[24], 8);  // second box indices are offset past the first box
+        assert_eq!(verts[6].pos, [16.0, 16.0, 16.0]);
+        assert_eq!(verts[8].pos, [16.0, 0.0, 0.0]);
+        assert_eq!(verts[0].color, [1.0, 1.0, 1.0]);
+        assert_eq!(verts[8].color, [HIDDEN_DIM, HIDDEN_DIM, HIDDEN_DIM]);
+    }
+}
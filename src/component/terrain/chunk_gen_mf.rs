@@ -1,28 +1,35 @@
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 use noise::{NoiseFn};
 use uom::si::f32::Length;
 use crate::component::camera::Length3D;
 use crate::component::RenderDataPurpose;
 use crate::component::terrain::{BlockData, FaceDir};
-use crate::component::terrain::chunk_mesh::{Chunk, ChunkGeneratable, Position};
-use crate::component::terrain::mesh_util::ChunkMeshUtil;
+use crate::component::terrain::chunk_mesh::{Chunk, ChunkGeneratable, ChunkRange, Position};
+use crate::component::terrain::light::LightVolume;
+use crate::component::terrain::mesh_util::{ChunkMeshUtil, chunk_range};
 use crate::component::terrain::terrain_gen::TerrainGenerator;
 use crate::component::texture::TextureIDMapper;
 use crate::measurement::{blox, chux_hf, chux_mf};
 use crate::shader::chunk::ChunkVertex;
+use crate::debug::MTXG_LOG;
 
 pub struct ChunkGeneratorMF<'b>  {
     chunk_size: u32,
+    downsample: u32,
+    neighbour_downsamples: Vec<u32>,
     block_ind: Vec<BlockData<'b>>,
     txtr_id_mapper: TextureIDMapper,
-    terrain_gen: Rc<TerrainGenerator>,
+    terrain_gen: Arc<TerrainGenerator>,
 }
 
 impl<'b> ChunkGeneratorMF<'b> {
-    pub fn new(block_ind: Vec<BlockData<'b>>, txtr_id_mapper: TextureIDMapper, terrain_gen: Rc<TerrainGenerator>) -> Self {
+    pub fn new(block_ind: Vec<BlockData<'b>>, txtr_id_mapper: TextureIDMapper, terrain_gen: Arc<TerrainGenerator>,
+               chunk_size: u32, downsample: u32, neighbour_downsamples: Vec<u32>) -> Self {
+        assert_eq!(chunk_size%downsample, 0, "Downsample factor {downsample} does not divide the chunk size {chunk_size}");
+
         Self {
-            chunk_size: Length::new::<<Self as ChunkGeneratable>::B>(1.0).get::<blox>() as u32, block_ind, txtr_id_mapper,
+            chunk_size, downsample, neighbour_downsamples, block_ind, txtr_id_mapper,
             terrain_gen
         }
     }
@@ -31,13 +38,17 @@ impl<'b> ChunkGeneratorMF<'b> {
 impl<'b> ChunkMeshUtil<'b> for ChunkGeneratorMF<'b> {
     fn chunk_size(&self) -> u32 {self.chunk_size}
 
+    fn downsample(&self) -> u32 {self.downsample}
+
+    fn neighbour_downsamples(&self) -> &[u32] {&self.neighbour_downsamples}
+
     fn texture_id_mapper(&self) -> TextureIDMapper {self.txtr_id_mapper.clone()}
 
     fn block_ind(&self, ind: usize) -> BlockData<'b> {
         self.block_ind[ind]
     }
 
-    fn terrain_gen(&self) -> Rc<TerrainGenerator> {
+    fn terrain_gen(&self) -> Arc<TerrainGenerator> {
         self.terrain_gen.clone()
     }
 }
@@ -48,6 +59,10 @@ impl ChunkGeneratable for ChunkGeneratorMF<'_> {
     type V = ChunkVertex;
     type I = u32;
 
+    fn chunk_length(&self) -> Length {
+        Length::new::<blox>(self.chunk_size as f32)
+    }
+
     fn generate_mesh(&self, pos: Length3D)
         -> Vec<(Vec<Self::V>, Vec<Self::I>, Option<FaceDir>, RenderDataPurpose)>
     {
@@ -58,15 +73,35 @@ impl ChunkGeneratable for ChunkGeneratorMF<'_> {
             -pos.z.get::<blox>()-z as f32
         );
 
-        let opaque_cube_mesh = self.voluminous_opaque_cubes_mesh(ofs, chunk_pos);
-        let transparent_floral_mesh = self.sparse_transparent_floral_mesh(ofs, chunk_pos);
-        let translucent_fluid_mesh = self.temporary_fluid_mesher(ofs, chunk_pos);
+        // the downsampled chunks are too far away for the shade to be noticeable
+        let light = if self.downsample == 1 {
+            self.sky_light(ofs)
+        } else {
+            LightVolume::full()
+        };
+
+        // the merged cells of the downsampled grid, merged further into the large flat surfaces of the far terrain
+        let opaque_cube_mesh = self.greedy_opaque_cubes_mesh(ofs, chunk_pos, &light);
+        // sparse flora is not part of the silhouette of a downsampled chunk
+        let transparent_floral_mesh = if self.downsample == 1 {
+            self.sparse_transparent_floral_mesh(ofs, chunk_pos, &light)
+        } else {
+            (vec![], vec![])
+        };
+        // neither are the placed slabs and stairs
+        let opaque_shaped_mesh = if self.downsample == 1 {
+            self.sparse_shaped_blocks_mesh(ofs, chunk_pos, &light)
+        } else {
+            (vec![], vec![])
+        };
+        let translucent_fluid_mesh = self.temporary_fluid_mesher(ofs, chunk_pos, &light);
 
         let mut all_mesh = Vec::new();
 
         for (v, i, f) in opaque_cube_mesh {
             all_mesh.push((v, i, Some(f), RenderDataPurpose::TerrainOpaque))
         }
+        all_mesh.push((opaque_shaped_mesh.0, opaque_shaped_mesh.1, None, RenderDataPurpose::TerrainOpaque));
         all_mesh.push((transparent_floral_mesh.0, transparent_floral_mesh.1, None, RenderDataPurpose::TerrainTransparent));
         all_mesh.push((translucent_fluid_mesh.0, translucent_fluid_mesh.1, None, RenderDataPurpose::TerrainTranslucent));
 
@@ -76,9 +111,9 @@ impl ChunkGeneratable for ChunkGeneratorMF<'_> {
     fn aggregate_mesh(&self,
                       central_pos: Length3D,
                       chunks: &HashMap<Position<Self::B>, Chunk<Self::V, Self::I, Self::B>>
-    ) -> Vec<(Vec<Self::V>, Vec<Self::I>, RenderDataPurpose)>
+    ) -> Vec<(Vec<Self::V>, Vec<Self::I>, Vec<ChunkRange>, RenderDataPurpose)>
     {
-        println!("[MF] GEN AGGREGATED MESH");
+        log::trace!(target: MTXG_LOG, "[MF] GEN AGGREGATED MESH");
 
         let mut opaque_verts = vec![];
         let mut opaque_inds = vec![];
@@ -89,10 +124,16 @@ impl ChunkGeneratable for ChunkGeneratorMF<'_> {
         let mut translucent_verts = vec![];
         let mut translucent_inds = vec![];
         let mut translucent_ind_count = 0;
+        let mut opaque_ranges = vec![];
+        let mut transparent_ranges = vec![];
+        let mut translucent_ranges = vec![];
 
         for chunk in chunks.values().filter(|c| c.visible()) {
+            let opaque_start = (opaque_verts.len(), opaque_inds.len() as u32);
+            let transparent_start = (transparent_verts.len(), transparent_inds.len() as u32);
+            let translucent_start = (translucent_verts.len(), translucent_inds.len() as u32);
             for (vert, raw_ind, face, purpose) in chunk.mesh.iter() {
-                println!("CHUNK: Delta Pos: {:?} Mesh Face: {:?}", central_pos-chunk.pos, face);
+                log::trace!(target: MTXG_LOG, "CHUNK: Delta Pos: {:?} Mesh Face: {:?}", central_pos-chunk.pos, face);
                 match purpose {
                     RenderDataPurpose::TerrainOpaque => {
                         let mut ind = raw_ind.clone().iter().map(|i| i+opaque_ind_count).collect();
@@ -118,12 +159,16 @@ impl ChunkGeneratable for ChunkGeneratorMF<'_> {
                     _ => {}
                 }
             }
+            opaque_ranges.extend(chunk_range(&opaque_verts, &opaque_inds, opaque_start, chunk.mesh_id));
+            transparent_ranges.extend(chunk_range(&transparent_verts, &transparent_inds, transparent_start, chunk.mesh_id));
+            translucent_ranges.extend(chunk_range(&translucent_verts, &translucent_inds, translucent_start, chunk.mesh_id));
         }
 
+        // the translucent mesh is sorted by the terrain, back to front from the camera
         vec![
-            (opaque_verts, opaque_inds, RenderDataPurpose::TerrainOpaque),
-            (transparent_verts, transparent_inds, RenderDataPurpose::TerrainTransparent),
-            (translucent_verts, translucent_inds, RenderDataPurpose::TerrainTranslucent),
+            (opaque_verts, opaque_inds, opaque_ranges, RenderDataPurpose::TerrainOpaque),
+            (transparent_verts, transparent_inds, transparent_ranges, RenderDataPurpose::TerrainTransparent),
+            (translucent_verts, translucent_inds, translucent_ranges, RenderDataPurpose::TerrainTranslucent),
         ]
     }
 }
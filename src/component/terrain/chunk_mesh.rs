@@ -1,26 +1,67 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 use uom::num_traits::Float;
 use uom::si::f32::Length;
 use uom::si::Unit;
-use crate::component::camera::{Length3D};
+use crate::component::camera::{Length3D, Plane};
 use crate::component::RenderDataPurpose;
 use crate::component::terrain::FaceDir;
+use crate::component::terrain::light::MAX_LIGHT;
+use crate::measurement::{blox, chux};
+pub use crate::util::WorkerPool;
+use crate::debug::MTXG_LOG;
 
 
 pub(crate) trait BlockLengthUnit: uom::si::length::Unit + uom::Conversion<f32, T = f32> {}
 impl<T> BlockLengthUnit for T where T: uom::si::length::Unit + uom::Conversion<f32, T = f32> {}
 
 
-pub trait ChunkGeneratable {
+// shared across the generation worker threads
+pub trait ChunkGeneratable: Sync {
     type A: BlockLengthUnit;  // border outer radius
     type B: BlockLengthUnit;  // empty inner radius
-    type V;
-    type I;
+    type V: Send + 'static;
+    type I: Send + 'static;
+    // edge of the chunks meshed, must divide the inner radius unit so the chunks tile the radii
+    fn chunk_length(&self) -> Length {
+        Length::new::<Self::B>(1.0)
+    }
     fn generate_mesh(&self, pos: Length3D) -> Vec<(Vec<Self::V>, Vec<Self::I>, Option<FaceDir>, RenderDataPurpose)>;
+    // along with the index range of each chunk within the aggregated mesh (if it can be drawn separately)
     fn aggregate_mesh(&self, central_pos: Length3D, chunks: &HashMap<Position<Self::B>, Chunk<Self::V, Self::I, Self::B>>)
-        -> Vec<(Vec<Self::V>, Vec<Self::I>, RenderDataPurpose)>;
+        -> Vec<(Vec<Self::V>, Vec<Self::I>, Vec<ChunkRange>, RenderDataPurpose)>;
+}
+
+// vertices and indices of a chunk within an aggregated mesh, along with the bounds of its vertices
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkRange {
+    pub(crate) mesh_id: u64,  // of the chunk mesh, so only the chunks meshed since are uploaded again
+    pub(crate) verts: Range<u32>,
+    pub(crate) inds: Range<u32>,
+    pub(crate) min: [f32; 3],
+    pub(crate) max: [f32; 3],
+}
+
+static NEXT_MESH_ID: AtomicU64 = AtomicU64::new(0);
+
+// unique across the tiers, a chunk meshed again gets a new one
+pub(crate) fn next_mesh_id() -> u64 {
+    NEXT_MESH_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// the chunks not entirely outside the frustum
+pub(crate) fn cull_ranges<'r>(ranges: &'r [ChunkRange], frustum: &[Plane; 6]) -> Vec<&'r ChunkRange> {
+    ranges.iter()
+        .filter(|range| !frustum.iter().any(|plane| plane.excludes(range.min, range.max)))
+        .collect()
 }
 
 
@@ -66,12 +107,34 @@ impl<M: BlockLengthUnit> From<Length3D> for Position<M> {
 }
 
 impl<M: BlockLengthUnit> Position<M> {
+    // of the chunk containing the position, for chunks of the size instead of a unit
+    pub(crate) fn within(value: Length3D, size: Length3D) -> Self {
+        Self {
+            x: (value.x.get::<blox>()/size.x.get::<blox>()).floor() as isize,
+            y: (value.y.get::<blox>()/size.y.get::<blox>()).floor() as isize,
+            z: (value.z.get::<blox>()/size.z.get::<blox>()).floor() as isize,
+            _measure: PhantomData,
+        }
+    }
+
     fn top(self) -> Self { Self { x: self.x, y: self.y+1, z: self.z, _measure: PhantomData} }
     fn bottom(self) -> Self { Self { x: self.x, y: self.y-1, z: self.z, _measure: PhantomData } }
     fn left(self) -> Self { Self { x: self.x-1, y: self.y, z: self.z, _measure: PhantomData } }
     fn right(self) -> Self { Self { x: self.x+1, y: self.y, z: self.z, _measure: PhantomData } }
     fn front(self) -> Self { Self { x: self.x, y: self.y, z: self.z+1, _measure: PhantomData } }
     fn back(self) -> Self { Self { x: self.x, y: self.y, z: self.z-1, _measure: PhantomData } }
+
+    // neighbouring position on the side of the face
+    pub(crate) fn adjacent(self, face: FaceDir) -> Self {
+        match face {
+            FaceDir::FRONT => self.front(),
+            FaceDir::RIGHT => self.right(),
+            FaceDir::BACK => self.back(),
+            FaceDir::LEFT => self.left(),
+            FaceDir::TOP => self.top(),
+            FaceDir::BOTTOM => self.bottom(),
+        }
+    }
 }
 
 
@@ -81,9 +144,50 @@ pub enum UpdateChunk {
 }
 
 // border_radius, update_radius
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ChunkRadius(pub u32, pub u32);
 
+// when the chunks (and their meshes) that moved beyond the border radius are freed
+#[derive(Copy, Clone, Debug)]
+pub enum ChunkUnload {
+    Immediate,
+    Delayed(Duration),  // in case the user comes back
+    KeepUpTo(usize),  // frees the oldest ones only once more than this many chunks are loaded
+}
+
+// order in which the newly loaded chunks are generated
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChunkPriority {
+    Scan,  // in the x, y, z loop order
+    NearestFirst,  // so the area around the user fills in before the periphery
+}
+
+// guards against a single pathological chunk (e.g. a checkerboard of blocks) producing huge buffers
+#[derive(Copy, Clone, Debug)]
+pub enum VertexLimit {
+    Unlimited,
+    Warn(usize),  // logs the chunks with more vertices than this
+    Skip(usize),  // logs and leaves those chunks empty
+}
+
+// limits a finite world (e.g. an arena) to the inclusive range of extreme fidelity chunk coordinates, the chunks
+// of any fidelity not entirely inside of it are never generated and left as void
+#[derive(Copy, Clone, Debug)]
+pub struct WorldBounds {
+    pub min: [i32; 3],
+    pub max: [i32; 3],
+}
+
+impl WorldBounds {
+    fn contains(&self, chunk_pos: Length3D, chunk_size: Length3D) -> bool {
+        let pos = [chunk_pos.x, chunk_pos.y, chunk_pos.z];
+        let size = [chunk_size.x, chunk_size.y, chunk_size.z];
+        (0..3).all(|k| {
+            Length::new::<chux>(self.min[k] as f32) <= pos[k] && pos[k]+size[k] <= Length::new::<chux>(self.max[k] as f32+1.0)
+        })
+    }
+}
+
 
 pub struct ChunkMesh<G: ChunkGeneratable> {
     pub(crate) central_pos: Length3D,
@@ -96,37 +200,83 @@ pub struct ChunkMesh<G: ChunkGeneratable> {
     // inner radius should be more than 0, or else it will keep updating and rebuilding mesh (disaster) and quite useless too
     chunk_inner_update_radius: Option<f32>,
 
-    generator: G,
+    generator: Arc<G>,
+    workers: WorkerPool,
+    // chunks being generated in the background, along with the batches of their completed meshes
+    pending_chunks: HashSet<Position<G::B>>,
+    pending: Vec<(Vec<Position<G::B>>, Receiver<(Length3D, Vec<(Vec<G::V>, Vec<G::I>, Option<FaceDir>, RenderDataPurpose)>)>)>,
+    in_range: HashSet<Position<G::B>>,  // within the border radius as of the last update
+    unload: ChunkUnload,
+    radius_shrunk: bool,  // the chunks out of range are freed on the next update whatever the unload strategy
+    priority: ChunkPriority,
+    vertex_limit: VertexLimit,
+    bounds: Option<WorldBounds>,  // None for an infinite world
     chunks: HashMap<Position<G::B>, Chunk<G::V, G::I, G::B>>,
     chunk_adjacency: Vec<ChunkAdjacency<G::B>>,
 }
 
 impl<G: ChunkGeneratable> ChunkMesh<G> {
-    pub fn new(pos: Length3D, outer: ChunkRadius, inner: Option<ChunkRadius>, generator: G) -> Self {
+    pub fn new(pos: Length3D, outer: ChunkRadius, inner: Option<ChunkRadius>, generator: G, workers: WorkerPool,
+               unload: ChunkUnload, priority: ChunkPriority, vertex_limit: VertexLimit, bounds: Option<WorldBounds>) -> Self {
+        let chunk_length = generator.chunk_length();
+        let unit = Length::new::<G::B>(1.0).get::<blox>();
+        assert_eq!(unit%chunk_length.get::<blox>(), 0.0,
+                   "Chunk size {} does not divide the {} unit", chunk_length.get::<blox>(), G::B::abbreviation());
+
         Self {
             central_pos: pos,
             inner_central_pos: pos,
             chunk_size: Length3D {
-                x: Length::new::<G::B>(1.0),
-                y: Length::new::<G::B>(1.0),
-                z: Length::new::<G::B>(1.0),
+                x: chunk_length,
+                y: chunk_length,
+                z: chunk_length,
             },
             chunk_outer_radius: outer.0 as i32,
             chunk_outer_update_radius: outer.1 as f32,
-            subchunk_outer_radius: Length::new::<G::A>(outer.0 as f32).get::<G::B>() as i32,
+            subchunk_outer_radius: Self::subchunk_radius(outer, chunk_length),
             chunk_inner_radius: inner.map(| ChunkRadius(border, _) | border as f32),
             chunk_inner_update_radius: inner.map(| ChunkRadius(_, update) | update as f32),
-            generator,
+            generator: Arc::new(generator),
+            workers,
+            pending_chunks: HashSet::new(),
+            pending: Vec::new(),
+            in_range: HashSet::new(),
+            unload,
+            radius_shrunk: false,
+            priority,
+            vertex_limit,
+            bounds,
             chunks: HashMap::new(),
             chunk_adjacency: Vec::new(),
         }
     }
 
     pub(crate) fn swap_generator(&mut self, generator: G) {
-        self.generator = generator;
+        self.generator = Arc::new(generator);
+    }
+
+    // takes effect on the next forced update, which generates the chunks within a larger border radius and
+    // unloads the ones beyond a smaller one right away (rather than keeping them around to walk back to)
+    pub(crate) fn set_radius(&mut self, outer: ChunkRadius, inner: Option<ChunkRadius>) {
+        self.radius_shrunk |= (outer.0 as i32) < self.chunk_outer_radius;
+        self.chunk_outer_radius = outer.0 as i32;
+        self.chunk_outer_update_radius = outer.1 as f32;
+        self.subchunk_outer_radius = Self::subchunk_radius(outer, self.chunk_size.x);
+        self.chunk_inner_radius = inner.map(| ChunkRadius(border, _) | border as f32);
+        self.chunk_inner_update_radius = inner.map(| ChunkRadius(_, update) | update as f32);
+    }
+
+    // border radius in chunks
+    fn subchunk_radius(outer: ChunkRadius, chunk_length: Length) -> i32 {
+        (Length::new::<G::A>(outer.0 as f32).get::<blox>()/chunk_length.get::<blox>()) as i32
+    }
+
+    // hash position of the chunk containing the position
+    fn position(&self, pos: Length3D) -> Position<G::B> {
+        Position::within(pos, self.chunk_size)
     }
 
-    pub fn update(&mut self, mode: UpdateChunk) -> bool {
+    pub fn update(&mut self, mode: UpdateChunk) -> bool where G: Send + 'static {
         let mut outer_chunk_update = false;
         let mut inner_chunk_update = false;
         let mut chunk_changed = false;
@@ -156,94 +306,235 @@ impl<G: ChunkGeneratable> ChunkMesh<G> {
                 self.reset_chunk_visibility();
             }
 
+            let mut chunk_positions = Vec::new();
             for cx in -self.subchunk_outer_radius..self.subchunk_outer_radius {
                 for cy in -self.subchunk_outer_radius..self.subchunk_outer_radius {
                     for cz in -self.subchunk_outer_radius..self.subchunk_outer_radius {
-                        let chunk_pos = Length3D::new(
-                            Length::new::<G::B>(cx as f32)+self.central_pos.x,
-                            Length::new::<G::B>(cy as f32)+self.central_pos.y,
-                            Length::new::<G::B>(cz as f32)+self.central_pos.z,
-                        );
-
-                        if outer_chunk_update {
-
-                            if let Some(chunk) = self.chunks.get_mut(&Position::from(chunk_pos)) {
-                                if !chunk.visible {
-                                    chunk.visible = true;
-                                    chunk_changed = true;
-                                }
-                            } else {
-                                // chunk at new_chunk_pos does not exist (needs to be created) for all chunks regardless whether its inner
-
-                                if chunk_pos.x.get::<G::A>() % 1.0 == 0.0 &&
-                                    chunk_pos.y.get::<G::A>() % 2.0 == 0.0 &&
-                                    chunk_pos.z.get::<G::A>() % 2.0 == 0.0 {
-                                    println!("New chunk loaded [{} {} {} <{}>]",
-                                             chunk_pos.x.get::<G::A>(),
-                                             chunk_pos.y.get::<G::A>(),
-                                             chunk_pos.z.get::<G::A>(),
-                                             G::A::abbreviation(),
-                                    );
-                                }
-
-                                self.load_chunk(chunk_pos);
-                                chunk_changed = true;
-                            }
+                        chunk_positions.push(Length3D::new(
+                            self.chunk_size.x*cx as f32+self.central_pos.x,
+                            self.chunk_size.y*cy as f32+self.central_pos.y,
+                            self.chunk_size.z*cz as f32+self.central_pos.z,
+                        ));
+                    }
+                }
+            }
+
+            if outer_chunk_update {
+                let mut new_chunks = Vec::new();
+                for chunk_pos in &chunk_positions {
+                    if let Some(chunk) = self.chunks.get_mut(&self.position(*chunk_pos)) {
+                        if !chunk.visible {
+                            chunk.visible = true;
+                            chunk_changed = true;
                         }
-                        // in the niche case when forced to start, inner chunk sets EXISTING inner chunks to invisible
-                        //  hence, it needs to be after it is generated only in this niche case
-                        if inner_chunk_update {
-                            if let Some(chunk) = self.chunks.get_mut(&Position::from(chunk_pos)) {
-                                if
-                                    Self::check_inside_radius::<G::B>(&self.inner_central_pos.x, &chunk.pos.x, self.chunk_inner_radius) &&
-                                    Self::check_inside_radius::<G::B>(&self.inner_central_pos.y, &chunk.pos.y, self.chunk_inner_radius) &&
-                                    Self::check_inside_radius::<G::B>(&self.inner_central_pos.z, &chunk.pos.z, self.chunk_inner_radius) {
-                                    // chunk inside inner radius (needs to be 'removed')
-                                    if chunk.visible {
-                                        chunk.visible = false;
-                                        chunk_changed = true;
-                                    }
-                                    // println!("Set chunk to INVISIBLE [{} {} {} <{}>]",
-                                    //          &chunk.pos.x.get::<G::A>(),
-                                    //          &chunk.pos.y.get::<G::A>(),
-                                    //          &chunk.pos.z.get::<G::A>(),
-                                    //          G::A::abbreviation(),
-                                    // );
-                                } else if !chunk.visible {
-                                    // not visible but not inside the inner radius? (needs to be added again)
-                                    // assumes all the chunks are generated since inner radius is inside the border radius
-                                    //  where all the chunk generation happens
-                                    // if not, it will just result in empty chunks somehow inside rest of the chunks
-                                    chunk.visible = true;
-                                    chunk_changed = true;
-                                }
+                    } else if !self.pending_chunks.contains(&self.position(*chunk_pos)) &&
+                        self.bounds.is_none_or(|bounds| bounds.contains(*chunk_pos, self.chunk_size)) {
+                        // chunk at new_chunk_pos does not exist (needs to be created) for all chunks regardless whether its inner
+
+                        if chunk_pos.x.get::<G::A>() % 1.0 == 0.0 &&
+                            chunk_pos.y.get::<G::A>() % 2.0 == 0.0 &&
+                            chunk_pos.z.get::<G::A>() % 2.0 == 0.0 {
+                            log::trace!(target: MTXG_LOG, "New chunk loaded [{} {} {} <{}>]",
+                                     chunk_pos.x.get::<G::A>(),
+                                     chunk_pos.y.get::<G::A>(),
+                                     chunk_pos.z.get::<G::A>(),
+                                     G::A::abbreviation(),
+                            );
+                        }
+
+                        new_chunks.push(*chunk_pos);
+                    }
+                }
+
+                // the new chunks are meshed in the background by the worker threads (which pick them up in order), and
+                // loaded as they complete
+                let new_chunks = self.generation_queue(new_chunks);
+                self.dispatch(new_chunks);
+
+                self.in_range = chunk_positions.iter().map(|pos| self.position(*pos)).collect();
+                let now = Instant::now();
+                for chunk in self.chunks.values_mut() {
+                    if self.in_range.contains(&chunk.hash_pos) {
+                        chunk.out_of_range_since = None;
+                    } else {
+                        chunk.out_of_range_since.get_or_insert(now);
+                    }
+                }
+                if mem::take(&mut self.radius_shrunk) {
+                    let out_of_range: Vec<_> = self.chunks.values()
+                        .filter(|chunk| chunk.out_of_range_since.is_some())
+                        .map(|chunk| chunk.hash_pos)
+                        .collect();
+                    for hash_pos in out_of_range {
+                        self.unload_chunk(hash_pos);
+                    }
+                }
+                self.unload_chunks(now);
+            }
+            // in the niche case when forced to start, inner chunk sets EXISTING inner chunks to invisible
+            //  hence, it needs to be after it is generated only in this niche case
+            if inner_chunk_update {
+                for chunk_pos in &chunk_positions {
+                    let inside_inner_radius = self.inside_inner_radius(*chunk_pos);
+                    if let Some(chunk) = self.chunks.get_mut(&self.position(*chunk_pos)) {
+                        if inside_inner_radius {
+                            // chunk inside inner radius (needs to be 'removed')
+                            if chunk.visible {
+                                chunk.visible = false;
+                                chunk_changed = true;
                             }
+                            // println!("Set chunk to INVISIBLE [{} {} {} <{}>]",
+                            //          &chunk.pos.x.get::<G::A>(),
+                            //          &chunk.pos.y.get::<G::A>(),
+                            //          &chunk.pos.z.get::<G::A>(),
+                            //          G::A::abbreviation(),
+                            // );
+                        } else if !chunk.visible {
+                            // not visible but not inside the inner radius? (needs to be added again)
+                            // assumes all the chunks are generated since inner radius is inside the border radius
+                            //  where all the chunk generation happens
+                            // if not, it will just result in empty chunks somehow inside rest of the chunks
+                            chunk.visible = true;
+                            chunk_changed = true;
                         }
                     }
                 }
             }
 
             if outer_chunk_update {
-                println!("CHUNK NEED UPDATE: BORDER {:?}", G::A::abbreviation());
+                log::debug!(target: MTXG_LOG, "CHUNK NEED UPDATE: BORDER {:?}", G::A::abbreviation());
             }
             if inner_chunk_update {
-                println!("CHUNK NEED UPDATE: INNER {:?}", G::A::abbreviation());
+                log::debug!(target: MTXG_LOG, "CHUNK NEED UPDATE: INNER {:?}", G::A::abbreviation());
             }
 
-            chunk_changed
+            // without any worker threads, the new chunks are already generated
+            self.poll_completed_chunks() || chunk_changed
         } else {
             false
         }
     }
 
+    fn dispatch(&mut self, new_chunks: Vec<Length3D>) where G: Send + 'static {
+        if new_chunks.is_empty() {
+            return;
+        }
+        let hash_positions: Vec<_> = new_chunks.iter().map(|pos| self.position(*pos)).collect();
+        self.pending_chunks.extend(hash_positions.iter().copied());
+        let generator = self.generator.clone();
+        self.pending.push((hash_positions, self.workers.spawn(new_chunks, move |pos| generator.generate_mesh(*pos))));
+    }
+
+    // loads the chunks generated in the background since the last poll (dropping those no longer within the border
+    // radius), returns whether any chunk was loaded
+    // the chunks of a batch whose jobs are gone without sending them back (e.g. a panicking generator) stop pending
+    pub(crate) fn poll_completed_chunks(&mut self) -> bool {
+        let mut completed = Vec::new();
+        let mut finished = Vec::new();
+        self.pending.retain_mut(|(batch, receiver)| loop {
+            match receiver.try_recv() {
+                Ok(chunk) => completed.push(chunk),
+                Err(TryRecvError::Empty) => break true,
+                Err(TryRecvError::Disconnected) => {
+                    finished.append(batch);
+                    break false;
+                }
+            }
+        });
+
+        let mut chunk_changed = false;
+        for (chunk_pos, mut mesh) in completed {
+            let hash_pos = self.position(chunk_pos);
+            self.pending_chunks.remove(&hash_pos);
+            if !self.in_range.contains(&hash_pos) {
+                continue;
+            }
+            self.limit_vertices(chunk_pos, &mut mesh);
+            self.load_chunk(chunk_pos, mesh);
+            if self.inside_inner_radius(chunk_pos) {
+                if let Some(chunk) = self.chunks.get_mut(&hash_pos) {
+                    chunk.visible = false;
+                }
+            }
+            chunk_changed = true;
+        }
+
+        for hash_pos in finished {
+            let redispatched = self.pending.iter().any(|(batch, _)| batch.contains(&hash_pos));
+            if !redispatched && self.pending_chunks.remove(&hash_pos) {
+                log::warn!(target: MTXG_LOG, "Chunk {:?} was never generated", (hash_pos.x, hash_pos.y, hash_pos.z));
+            }
+        }
+        chunk_changed
+    }
+
+    // number of chunks still being generated in the background
+    pub(crate) fn pending_chunks(&self) -> usize {
+        self.pending_chunks.len()
+    }
+
+    fn inside_inner_radius(&self, pos: Length3D) -> bool {
+        Self::check_inside_radius::<G::B>(&self.inner_central_pos.x, &pos.x, self.chunk_size.x, self.chunk_inner_radius) &&
+            Self::check_inside_radius::<G::B>(&self.inner_central_pos.y, &pos.y, self.chunk_size.y, self.chunk_inner_radius) &&
+            Self::check_inside_radius::<G::B>(&self.inner_central_pos.z, &pos.z, self.chunk_size.z, self.chunk_inner_radius)
+    }
+
+    fn generation_queue(&self, new_chunks: Vec<Length3D>) -> Vec<Length3D> {
+        if self.priority == ChunkPriority::Scan {
+            return new_chunks;
+        }
+
+        // keyed by the squared distance (in blox) from the chunk center to the central position
+        let center = [
+            self.central_pos.x.get::<blox>(), self.central_pos.y.get::<blox>(), self.central_pos.z.get::<blox>(),
+        ];
+        let half_size = [
+            self.chunk_size.x.get::<blox>()/2.0, self.chunk_size.y.get::<blox>()/2.0, self.chunk_size.z.get::<blox>()/2.0,
+        ];
+        let mut queue: BinaryHeap<Reverse<(u64, usize)>> = new_chunks.iter().enumerate()
+            .map(|(ind, pos)| {
+                let pos = [pos.x.get::<blox>(), pos.y.get::<blox>(), pos.z.get::<blox>()];
+                let dist_sq: f32 = (0..3).map(|k| (pos[k]+half_size[k]-center[k]).powi(2)).sum();
+                Reverse((dist_sq as u64, ind))
+            })
+            .collect();
+
+        let mut ordered = Vec::with_capacity(new_chunks.len());
+        while let Some(Reverse((_, ind))) = queue.pop() {
+            ordered.push(new_chunks[ind]);
+        }
+        ordered
+    }
+
+    // returns whether the chunk mesh has more vertices than the limit
+    fn limit_vertices(&self, pos: Length3D, mesh: &mut Vec<(Vec<G::V>, Vec<G::I>, Option<FaceDir>, RenderDataPurpose)>) -> bool {
+        let (max_vertices, skip) = match self.vertex_limit {
+            VertexLimit::Unlimited => return false,
+            VertexLimit::Warn(max_vertices) => (max_vertices, false),
+            VertexLimit::Skip(max_vertices) => (max_vertices, true),
+        };
+        let vertex_count: usize = mesh.iter().map(|(verts, _, _, _)| verts.len()).sum();
+        if vertex_count <= max_vertices {
+            return false;
+        }
+
+        log::warn!(target: MTXG_LOG, "Chunk [{} {} {} <{}>] has {vertex_count} vertices, over the limit of {max_vertices}{}",
+                   pos.x.get::<G::A>(), pos.y.get::<G::A>(), pos.z.get::<G::A>(), G::A::abbreviation(),
+                   if skip {", leaving it empty"} else {""});
+        if skip {
+            mesh.clear();
+        }
+        true
+    }
+
     fn reset_chunk_visibility(&mut self) {
         for v in self.chunks.values_mut() {
             v.visible = false;
         }
     }
 
-    fn load_chunk(&mut self, pos: Length3D) {
-        let hash_pos = Position::from(pos);
+    fn load_chunk(&mut self, pos: Length3D, mesh: Vec<(Vec<G::V>, Vec<G::I>, Option<FaceDir>, RenderDataPurpose)>) {
+        let hash_pos = self.position(pos);
         // println!("LOAD CHUNK / HASH POS {:?}", hash_pos);
 
         let mut adj = ChunkAdjacency::default();
@@ -272,23 +563,115 @@ impl<G: ChunkGeneratable> ChunkMesh<G> {
             c.adjacency.front.replace(hash_pos);
         }
 
-        let mesh = self.generator.generate_mesh(pos);
-
         self.chunks.insert(
             hash_pos, Chunk::new(pos, hash_pos, adj, mesh)
         );
     }
 
-    fn unload_chunk(&mut self) {
+    // drops every loaded chunk and loads them again around the current position using the current generator
+    pub(crate) fn regenerate(&mut self) -> bool where G: Send + 'static {
+        self.chunks.clear();
+        self.chunk_adjacency.clear();
+        // the chunks still being generated are from the previous generator
+        self.pending.clear();
+        self.pending_chunks.clear();
+        self.update(UpdateChunk::Forced)
+    }
+
+    fn unload_chunk(&mut self, hash_pos: Position<G::B>) {
+        let Some(chunk) = self.chunks.remove(&hash_pos) else {
+            return;
+        };
+        let adj = chunk.adjacency;
+        if let Some(c) = adj.top.and_then(|p| self.chunks.get_mut(&p)) { c.adjacency.bottom = None; }
+        if let Some(c) = adj.bottom.and_then(|p| self.chunks.get_mut(&p)) { c.adjacency.top = None; }
+        if let Some(c) = adj.left.and_then(|p| self.chunks.get_mut(&p)) { c.adjacency.right = None; }
+        if let Some(c) = adj.right.and_then(|p| self.chunks.get_mut(&p)) { c.adjacency.left = None; }
+        if let Some(c) = adj.front.and_then(|p| self.chunks.get_mut(&p)) { c.adjacency.back = None; }
+        if let Some(c) = adj.back.and_then(|p| self.chunks.get_mut(&p)) { c.adjacency.front = None; }
+    }
+
+    // frees the chunks beyond the border radius according to the unload strategy, returns how many were freed
+    // (out of range chunks are never visible, so the aggregated mesh stays the same)
+    pub(crate) fn unload_chunks(&mut self, now: Instant) -> usize {
+        let mut out_of_range: Vec<(Instant, Position<G::B>)> = self.chunks.values()
+            .filter_map(|chunk| chunk.out_of_range_since.map(|since| (since, chunk.hash_pos)))
+            .collect();
+        match self.unload {
+            ChunkUnload::Immediate => {}
+            ChunkUnload::Delayed(delay) => {
+                out_of_range.retain(|(since, _)| now.saturating_duration_since(*since) >= delay);
+            }
+            ChunkUnload::KeepUpTo(max_chunks) => {
+                out_of_range.sort_by_key(|(since, _)| *since);
+                out_of_range.truncate(self.chunks.len().saturating_sub(max_chunks));
+            }
+        }
 
+        for (_, hash_pos) in &out_of_range {
+            self.unload_chunk(*hash_pos);
+        }
+        out_of_range.len()
     }
 
     // generate the entire aggregated vertices/indices
-    pub(crate) fn generate_vertices(&mut self) -> Vec<(Vec<G::V>, Vec<G::I>, RenderDataPurpose)> {
+    pub(crate) fn generate_vertices(&mut self) -> Vec<(Vec<G::V>, Vec<G::I>, Vec<ChunkRange>, RenderDataPurpose)> {
         // TODO: pass in the chunks relative direction to central pos (for face culling)
         self.generator.aggregate_mesh(self.central_pos, &self.chunks)
     }
 
+    // meshes the loaded chunks containing the edited block or any of its neighbours again (as the faces on the
+    // chunk borders are meshed by either side), along with the ones whose light it changes: within MAX_LIGHT blocks,
+    // and the column below it that it shades (or stops shading), returns whether any chunk was meshed
+    pub(crate) fn remesh_block(&mut self, pos: Position<blox>) -> bool {
+        let reach = MAX_LIGHT as f32;
+        let block = [pos.x as f32, pos.y as f32, pos.z as f32];
+        let size = [self.chunk_size.x.get::<blox>(), self.chunk_size.y.get::<blox>(), self.chunk_size.z.get::<blox>()];
+        let dirty: Vec<Length3D> = self.chunks.values()
+            .filter(|chunk| {
+                let min = [chunk.pos.x.get::<blox>(), chunk.pos.y.get::<blox>(), chunk.pos.z.get::<blox>()];
+                [0, 2].iter().all(|&k| block[k]-reach < min[k]+size[k] && min[k] <= block[k]+reach) && min[1] <= block[1]+reach
+            })
+            .map(|chunk| chunk.pos)
+            .collect();
+
+        let generator = &*self.generator;
+        let meshes = self.workers.run(&dirty, |pos| generator.generate_mesh(*pos));
+        for (chunk_pos, mut mesh) in dirty.iter().zip(meshes) {
+            self.limit_vertices(*chunk_pos, &mut mesh);
+            if let Some(chunk) = self.chunks.get_mut(&self.position(*chunk_pos)) {
+                chunk.mesh = mesh;
+                chunk.mesh_id = next_mesh_id();
+            }
+        }
+        !dirty.is_empty()
+    }
+
+    pub(crate) fn loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // whether the chunk containing the position is loaded
+    pub(crate) fn is_loaded(&self, pos: Length3D) -> bool {
+        self.chunks.contains_key(&self.position(pos))
+    }
+
+    // south-west corner and size (in blox) of every loaded chunk, along with whether it is visible
+    pub(crate) fn chunk_bounds(&self) -> Vec<([f32; 3], [f32; 3], bool)> {
+        let size = [
+            self.chunk_size.x.get::<blox>(),
+            self.chunk_size.y.get::<blox>(),
+            self.chunk_size.z.get::<blox>(),
+        ];
+        self.chunks.values()
+            .map(|chunk| (
+                [chunk.pos.x.get::<blox>(), chunk.pos.y.get::<blox>(), chunk.pos.z.get::<blox>()],
+                size,
+                chunk.visible,
+            ))
+            .collect()
+    }
+
     // checks outward
     fn check_and_update_axis<M: BlockLengthUnit>(central_chunk_axis: &mut Length, new_point_axis: &Length, update_radius: f32) -> bool {
         if new_point_axis.floor::<M>() < *central_chunk_axis-Length::new::<M>(update_radius) {
@@ -302,11 +685,12 @@ impl<G: ChunkGeneratable> ChunkMesh<G> {
         }
     }
 
-    // checks inward
-    fn check_inside_radius<M: BlockLengthUnit>(central_chunk_axis: &Length, existing_chunk_axis: &Length, chunk_radius: Option<f32>) -> bool {
+    // checks inward, whether the whole chunk is as the chunks smaller than the unit of the radius may straddle its edge
+    fn check_inside_radius<M: BlockLengthUnit>(central_chunk_axis: &Length, existing_chunk_axis: &Length, chunk_length: Length,
+                                               chunk_radius: Option<f32>) -> bool {
         if let Some(chunk_radius) = chunk_radius {
-            *central_chunk_axis-Length::new::<M>(chunk_radius+1.0) < *existing_chunk_axis &&
-                *existing_chunk_axis < *central_chunk_axis+Length::new::<M>(chunk_radius)
+            *central_chunk_axis-Length::new::<M>(chunk_radius) <= *existing_chunk_axis &&
+                *existing_chunk_axis+chunk_length <= *central_chunk_axis+Length::new::<M>(chunk_radius)
         } else {
             false
         }
@@ -319,7 +703,9 @@ pub(crate) struct Chunk<V, I, M: BlockLengthUnit> {
     pub(crate) hash_pos: Position<M>,
     pub(crate) adjacency: ChunkAdjacency<M>,
     pub(crate) mesh: Vec<(Vec<V>, Vec<I>, Option<FaceDir>, RenderDataPurpose)>,
+    pub(crate) mesh_id: u64,
     visible: bool,
+    out_of_range_since: Option<Instant>,  // beyond the border radius, waiting to be unloaded
 }
 
 impl<V, I, M: BlockLengthUnit> Chunk<V, I, M> {
@@ -330,9 +716,485 @@ impl<V, I, M: BlockLengthUnit> Chunk<V, I, M> {
         mesh: Vec<(Vec<V>, Vec<I>, Option<FaceDir>, RenderDataPurpose)>,
     ) -> Self {
         Self {
-            pos, hash_pos, adjacency: init_adjs, mesh, visible: true,
+            pos, hash_pos, adjacency: init_adjs, mesh, mesh_id: next_mesh_id(), visible: true, out_of_range_since: None,
         }
     }
 
     pub(crate) fn visible(&self) -> bool {self.visible}
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use crate::component::terrain::{Block, FaceDir};
+    use crate::component::terrain::chunk_gen::ChunkGeneratorEF;
+    use crate::component::terrain::mesh_util::tests::{test_block_ind, test_generator};
+    use crate::component::terrain::terrain_gen::TerrainGenerator;
+    use crate::component::texture::TextureIDs;
+    use crate::util::WorkerPool;
+    use super::*;
+
+    fn flat_generator(base_height: f64) -> ChunkGeneratorEF<'static> {
+        sized_generator(base_height, 32)
+    }
+
+    fn sized_generator(base_height: f64, chunk_size: u32) -> ChunkGeneratorEF<'static> {
+        ChunkGeneratorEF::new(test_block_ind(), Arc::new(TextureIDs::default()), Arc::new(TerrainGenerator::new(0, base_height, 0.0, false)), chunk_size, 1, vec![])
+    }
+
+    fn opaque_surface_height(chunk_mesh: &mut ChunkMesh<ChunkGeneratorEF<'static>>) -> f32 {
+        chunk_mesh.generate_vertices().into_iter()
+            .filter(|(_, _, _, purpose)| *purpose == RenderDataPurpose::TerrainOpaque)
+            .flat_map(|(verts, _, _, _)| verts)
+            .map(|v| v.pos[1])
+            .fold(f32::MIN, f32::max)
+    }
+
+    fn wait_for_chunks<G: ChunkGeneratable>(chunk_mesh: &mut ChunkMesh<G>) {
+        let deadline = Instant::now()+Duration::from_secs(60);
+        while chunk_mesh.pending_chunks() > 0 {
+            assert!(Instant::now() < deadline, "{} chunks still pending", chunk_mesh.pending_chunks());
+            chunk_mesh.poll_completed_chunks();
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_regenerate_uses_new_generator() {
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, flat_generator(12.0), WorkerPool::new(2),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        wait_for_chunks(&mut chunk_mesh);
+        assert_eq!(opaque_surface_height(&mut chunk_mesh), 12.0);
+
+        // swapping the generator alone keeps the loaded chunks
+        chunk_mesh.swap_generator(flat_generator(20.0));
+        assert_eq!(opaque_surface_height(&mut chunk_mesh), 12.0);
+
+        chunk_mesh.regenerate();
+        wait_for_chunks(&mut chunk_mesh);
+        assert_eq!(opaque_surface_height(&mut chunk_mesh), 20.0);
+        assert_eq!(chunk_mesh.chunk_bounds().len(), 8);
+    }
+
+    #[test]
+    fn test_smaller_chunks_cover_the_same_region() {
+        for (chunk_size, chunks) in [(32, 8), (16, 64), (8, 512)] {
+            let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, sized_generator(12.0, chunk_size),
+                                                WorkerPool::new(0), ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+            chunk_mesh.update(UpdateChunk::Forced);
+            let bounds = chunk_mesh.chunk_bounds();
+            assert_eq!(bounds.len(), chunks);
+            assert!(bounds.iter().all(|(_, size, _)| *size == [chunk_size as f32; 3]));
+            for k in 0..3 {
+                assert_eq!(bounds.iter().map(|(min, _, _)| min[k]).fold(f32::MAX, f32::min), -32.0);
+                assert_eq!(bounds.iter().map(|(min, size, _)| min[k]+size[k]).fold(f32::MIN, f32::max), 32.0);
+            }
+            assert_eq!(opaque_surface_height(&mut chunk_mesh), 12.0);
+        }
+    }
+
+    #[test]
+    fn test_inner_radius_hides_whole_smaller_chunks() {
+        // the inner radius of a chux around the origin leaves out exactly the 16 blox chunks within [-32, 32)
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(2, 1), Some(ChunkRadius(1, 1)), sized_generator(12.0, 16),
+                                            WorkerPool::new(0), ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        let bounds = chunk_mesh.chunk_bounds();
+        assert_eq!(bounds.len(), 512);
+        for (min, _, visible) in bounds {
+            assert_eq!(visible, !min.iter().all(|m| (-32.0..32.0).contains(m)), "{min:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "does not divide")]
+    fn test_chunk_size_must_divide_the_unit() {
+        ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, sized_generator(12.0, 24),
+                       WorkerPool::new(0), ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+    }
+
+    fn opaque_vertex_counts(chunk_mesh: &ChunkMesh<ChunkGeneratorEF<'static>>) -> HashMap<Position<chux>, usize> {
+        chunk_mesh.chunks.iter()
+            .map(|(pos, chunk)| {
+                let count = chunk.mesh.iter()
+                    .filter(|(_, _, _, purpose)| *purpose == RenderDataPurpose::TerrainOpaque)
+                    .map(|(verts, _, _, _)| verts.len())
+                    .sum();
+                (*pos, count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_block_edits_remesh_only_their_chunks() {
+        let terrain_gen = Arc::new(TerrainGenerator::new(0, 12.0, 0.0, false));
+        let generator = test_generator(TextureIDs::default(), terrain_gen.clone());
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, generator, WorkerPool::new(0),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        let before = opaque_vertex_counts(&chunk_mesh);
+
+        let block_pos = |x: f32, y: f32, z: f32| Position::<blox>::from(Length3D::new(
+            Length::new::<blox>(x), Length::new::<blox>(y), Length::new::<blox>(z),
+        ));
+        let chunk_pos = |x: f32, y: f32, z: f32| Position::<chux>::from(Length3D::new(
+            Length::new::<chux>(x), Length::new::<chux>(y), Length::new::<chux>(z),
+        ));
+        let changed = |old: &HashMap<Position<chux>, usize>, new: &HashMap<Position<chux>, usize>| {
+            let mut changed: Vec<Position<chux>> = new.keys().filter(|pos| old[pos] != new[pos]).copied().collect();
+            changed.sort_by_key(|pos| pos.x);
+            changed
+        };
+
+        // a stone block placed on the ground in the middle of a chunk, covering a top face with 5 faces of its own
+        terrain_gen.set_block([5, 12, 5], Some(Block(2)));
+        assert!(chunk_mesh.remesh_block(block_pos(5.0, 12.0, 5.0)));
+        let placed = opaque_vertex_counts(&chunk_mesh);
+        assert_eq!(changed(&before, &placed), vec![chunk_pos(0.0, 0.0, 0.0)]);
+        assert_eq!(placed[&chunk_pos(0.0, 0.0, 0.0)], before[&chunk_pos(0.0, 0.0, 0.0)]+4*4);
+
+        // removing a ground block on the chunk border exposes a face of the neighbouring chunk's block too
+        terrain_gen.set_block([0, 11, 5], None);
+        assert!(chunk_mesh.remesh_block(block_pos(0.0, 11.0, 5.0)));
+        let removed = opaque_vertex_counts(&chunk_mesh);
+        assert_eq!(changed(&placed, &removed), vec![chunk_pos(-1.0, 0.0, 0.0), chunk_pos(0.0, 0.0, 0.0)]);
+        assert_eq!(removed[&chunk_pos(-1.0, 0.0, 0.0)], placed[&chunk_pos(-1.0, 0.0, 0.0)]+4);
+        assert_eq!(removed[&chunk_pos(0.0, 0.0, 0.0)], placed[&chunk_pos(0.0, 0.0, 0.0)]+3*4);
+
+        // nothing loaded there
+        assert!(!chunk_mesh.remesh_block(block_pos(500.0, 11.0, 5.0)));
+    }
+
+    #[test]
+    fn test_remeshed_chunks_get_new_mesh_ids() {
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, flat_generator(12.0), WorkerPool::new(0),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        let mesh_ids = |chunk_mesh: &ChunkMesh<ChunkGeneratorEF<'static>>| chunk_mesh.chunks.iter()
+            .map(|(pos, chunk)| (*pos, chunk.mesh_id))
+            .collect::<HashMap<_, _>>();
+        let before = mesh_ids(&chunk_mesh);
+        assert_eq!(before.values().collect::<HashSet<_>>().len(), before.len());
+
+        let origin = Position::<chux>::default();
+        assert!(chunk_mesh.remesh_block(Position::<blox>::default()));
+        let after = mesh_ids(&chunk_mesh);
+        assert_ne!(after[&origin], before[&origin]);
+        // never reusing the id of another chunk mesh
+        let old: HashSet<&u64> = before.values().collect();
+        assert!(after.iter().filter(|(pos, mesh_id)| before[pos] != **mesh_id).all(|(_, mesh_id)| !old.contains(mesh_id)));
+    }
+
+    // moves the user one chunk at a time (as the border radius follows) until the chunks around x are loaded
+    fn walk_to(chunk_mesh: &mut ChunkMesh<ChunkGeneratorEF<'static>>, x: f32) {
+        let pos = Length3D::new(Length::new::<chux>(x), Length::new::<chux>(0.5), Length::new::<chux>(0.5));
+        while chunk_mesh.update(UpdateChunk::NewPos(pos)) {}
+    }
+
+    #[test]
+    fn test_background_generation_matches_inline() {
+        let mut inline = ChunkMesh::new(Length3D::origin(), ChunkRadius(2, 1), None, flat_generator(12.0), WorkerPool::new(0),
+                                        ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        assert!(inline.update(UpdateChunk::Forced));
+        assert_eq!(inline.pending_chunks(), 0);
+
+        let mut background = ChunkMesh::new(Length3D::origin(), ChunkRadius(2, 1), None, flat_generator(12.0), WorkerPool::new(3),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        background.update(UpdateChunk::Forced);
+        wait_for_chunks(&mut background);
+        assert_eq!(opaque_vertex_counts(&background), opaque_vertex_counts(&inline));
+    }
+
+    // holds the chunk generation back until the gate is opened, or a while passed so that an update waiting on the
+    // generation fails the test instead of hanging it
+    struct GatedGenerator<G> {
+        generator: G,
+        open: Arc<AtomicBool>,
+    }
+
+    impl<G: ChunkGeneratable> ChunkGeneratable for GatedGenerator<G> {
+        type A = G::A;
+        type B = G::B;
+        type V = G::V;
+        type I = G::I;
+
+        fn chunk_length(&self) -> Length {
+            self.generator.chunk_length()
+        }
+
+        fn generate_mesh(&self, pos: Length3D) -> Vec<(Vec<G::V>, Vec<G::I>, Option<FaceDir>, RenderDataPurpose)> {
+            let deadline = Instant::now()+Duration::from_secs(10);
+            while !self.open.load(Ordering::Acquire) && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            self.generator.generate_mesh(pos)
+        }
+
+        fn aggregate_mesh(&self, central_pos: Length3D, chunks: &HashMap<Position<G::B>, Chunk<G::V, G::I, G::B>>)
+            -> Vec<(Vec<G::V>, Vec<G::I>, Vec<ChunkRange>, RenderDataPurpose)> {
+            self.generator.aggregate_mesh(central_pos, chunks)
+        }
+    }
+
+    // crossing a chunk boundary only queues the new chunks, so the main thread isn't held until they are generated
+    #[test]
+    fn test_background_generation_boundary_crossing() {
+        let open = Arc::new(AtomicBool::new(true));
+        let generator = GatedGenerator { generator: flat_generator(12.0), open: open.clone() };
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(3, 1), None, generator, WorkerPool::new(2),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        wait_for_chunks(&mut chunk_mesh);
+        let loaded = chunk_mesh.chunk_bounds().len();
+
+        open.store(false, Ordering::Release);
+        let pos = Length3D::new(Length::new::<chux>(1.5), Length::new::<chux>(0.5), Length::new::<chux>(0.5));
+        chunk_mesh.update(UpdateChunk::NewPos(pos));
+        assert!(chunk_mesh.pending_chunks() > 0);
+
+        open.store(true, Ordering::Release);
+        wait_for_chunks(&mut chunk_mesh);
+        assert_eq!(chunk_mesh.chunk_bounds().len(), loaded);
+        assert!(chunk_mesh.is_loaded(Length3D::new(Length::new::<chux>(3.5), Length::new::<chux>(0.5), Length::new::<chux>(0.5))));
+    }
+
+    // panics generating the chunk at the origin
+    struct PanickingGenerator;
+
+    impl ChunkGeneratable for PanickingGenerator {
+        type A = chux;
+        type B = chux;
+        type V = ();
+        type I = u32;
+
+        fn generate_mesh(&self, pos: Length3D) -> Vec<(Vec<()>, Vec<u32>, Option<FaceDir>, RenderDataPurpose)> {
+            assert!(pos.x.get::<blox>() != 0.0 || pos.y.get::<blox>() != 0.0 || pos.z.get::<blox>() != 0.0, "Chunk at the origin");
+            vec![]
+        }
+
+        fn aggregate_mesh(&self, _: Length3D, _: &HashMap<Position<chux>, Chunk<(), u32, chux>>)
+            -> Vec<(Vec<()>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_panicking_generation_stops_pending() {
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, PanickingGenerator, WorkerPool::new(2),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        wait_for_chunks(&mut chunk_mesh);
+
+        assert_eq!(chunk_mesh.chunks.len(), 7);
+        assert!(!chunk_mesh.is_loaded(Length3D::origin()));
+    }
+
+    #[test]
+    fn test_chunks_left_behind_while_generating_dropped() {
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, flat_generator(12.0), WorkerPool::new(2),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        // moving away (a chunk per update) before the chunks around the origin are polled
+        let pos = Length3D::new(Length::new::<chux>(10.5), Length::new::<chux>(0.5), Length::new::<chux>(0.5));
+        for _ in 0..3 {
+            chunk_mesh.update(UpdateChunk::NewPos(pos));
+        }
+        wait_for_chunks(&mut chunk_mesh);
+
+        assert_eq!(chunk_mesh.chunk_bounds().len(), 8);
+        assert!(!chunk_mesh.is_loaded(Length3D::origin()));
+    }
+
+    #[test]
+    fn test_set_radius_grows_and_shrinks() {
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, flat_generator(12.0), WorkerPool::new(2),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        wait_for_chunks(&mut chunk_mesh);
+        assert_eq!(chunk_mesh.chunks.len(), 8);
+
+        chunk_mesh.set_radius(ChunkRadius(2, 1), None);
+        assert!(chunk_mesh.update(UpdateChunk::Forced));
+        wait_for_chunks(&mut chunk_mesh);
+        assert_eq!(chunk_mesh.chunks.len(), 64);
+        assert!(chunk_mesh.chunks.values().all(|c| c.visible()));
+
+        // the chunks beyond the smaller radius are freed right away
+        chunk_mesh.set_radius(ChunkRadius(1, 1), None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        assert_eq!(chunk_mesh.chunks.len(), 8);
+        assert_eq!(chunk_mesh.pending_chunks(), 0);
+    }
+
+    #[test]
+    fn test_set_radius_shrinks_with_delayed_unload() {
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(2, 1), None, flat_generator(12.0), WorkerPool::new(0),
+                                            ChunkUnload::Delayed(Duration::from_secs(30)), ChunkPriority::Scan,
+                                            VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        assert_eq!(chunk_mesh.chunks.len(), 64);
+
+        // walking away keeps the chunks left behind for the delay
+        walk_to(&mut chunk_mesh, 1.5);
+        let walked = chunk_mesh.chunks.len();
+        assert!(walked > 64);
+
+        // shrinking the radius frees every chunk beyond it without waiting for the delay
+        chunk_mesh.set_radius(ChunkRadius(1, 1), None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        assert_eq!(chunk_mesh.chunks.len(), 8);
+
+        // growing it again keeps the delay for the chunks walked away from
+        chunk_mesh.set_radius(ChunkRadius(2, 1), None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        walk_to(&mut chunk_mesh, 2.5);
+        assert!(chunk_mesh.chunks.len() > 64);
+    }
+
+    #[test]
+    fn test_unload_strategies() {
+        let mut immediate = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, flat_generator(12.0), WorkerPool::new(0),
+                                           ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        immediate.update(UpdateChunk::Forced);
+        walk_to(&mut immediate, 5.5);
+        assert_eq!(immediate.chunks.len(), 8);
+
+        let delay = Duration::from_secs(30);
+        let mut delayed = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, flat_generator(12.0), WorkerPool::new(0),
+                                         ChunkUnload::Delayed(delay), ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        delayed.update(UpdateChunk::Forced);
+        walk_to(&mut delayed, 5.5);
+        let loaded = delayed.chunks.len();
+        assert!(loaded > 8);
+
+        // persists for the delay, then only the chunks around the user remain
+        assert_eq!(delayed.unload_chunks(Instant::now()+delay/2), 0);
+        assert_eq!(delayed.unload_chunks(Instant::now()+delay), loaded-8);
+        assert_eq!(delayed.chunks.len(), 8);
+        assert!(delayed.chunks.values().all(|c| c.visible()));
+    }
+
+    #[test]
+    fn test_chunks_outside_bounds_never_loaded() {
+        let bounds = WorldBounds { min: [-2, -1, -1], max: [1, 0, 0] };
+        let mut bounded = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, flat_generator(12.0), WorkerPool::new(0),
+                                         ChunkUnload::Delayed(Duration::from_secs(30)), ChunkPriority::Scan,
+                                         VertexLimit::Unlimited, Some(bounds));
+        bounded.update(UpdateChunk::Forced);
+        assert_eq!(bounded.chunks.len(), 8);
+
+        // walking towards and past the bound
+        for x in [1.5, 2.5, 3.5, 5.5] {
+            walk_to(&mut bounded, x);
+            assert!(bounded.chunks.keys().all(|pos| -2 <= pos.x && pos.x <= 1), "{x}");
+        }
+        let mut loaded_x: Vec<isize> = bounded.chunks.keys().map(|pos| pos.x).collect();
+        loaded_x.sort();
+        loaded_x.dedup();
+        assert_eq!(loaded_x, vec![-1, 0, 1]);
+        assert!(bounded.chunks.keys().all(|pos| [-1, 0].contains(&pos.y) && [-1, 0].contains(&pos.z)));
+    }
+
+    // records the order the chunks are generated in
+    struct RecordingGenerator {
+        generated: std::sync::Mutex<Vec<[f32; 3]>>,
+    }
+
+    impl ChunkGeneratable for RecordingGenerator {
+        type A = chux;
+        type B = chux;
+        type V = ();
+        type I = u32;
+
+        fn generate_mesh(&self, pos: Length3D) -> Vec<(Vec<()>, Vec<u32>, Option<FaceDir>, RenderDataPurpose)> {
+            self.generated.lock().unwrap().push([pos.x.get::<blox>(), pos.y.get::<blox>(), pos.z.get::<blox>()]);
+            vec![]
+        }
+
+        fn aggregate_mesh(&self, _: Length3D, _: &HashMap<Position<chux>, Chunk<(), u32, chux>>)
+            -> Vec<(Vec<()>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_nearest_chunks_generated_first() {
+        let generator = RecordingGenerator { generated: std::sync::Mutex::new(vec![]) };
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(2, 1), None, generator, WorkerPool::new(0),
+                                            ChunkUnload::Immediate, ChunkPriority::NearestFirst, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+
+        // distance of each generated chunk's center to the origin
+        let distances: Vec<f32> = chunk_mesh.generator.generated.lock().unwrap().iter()
+            .map(|pos| pos.iter().map(|p| (p+16.0).powi(2)).sum::<f32>().sqrt())
+            .collect();
+        assert_eq!(distances.len(), 64);
+        assert!(distances.windows(2).all(|d| d[0] <= d[1]), "{distances:?}");
+        // the 8 chunks touching the origin come first
+        assert!(distances[..8].iter().all(|d| *d == distances[0]));
+        assert!(distances[8] > distances[0]);
+    }
+
+    // every other block of the chunk is solid, so every face of every block is visible
+    struct CheckerboardGenerator;
+
+    impl ChunkGeneratable for CheckerboardGenerator {
+        type A = chux;
+        type B = chux;
+        type V = ();
+        type I = u32;
+
+        fn generate_mesh(&self, _: Length3D) -> Vec<(Vec<()>, Vec<u32>, Option<FaceDir>, RenderDataPurpose)> {
+            let blocks = 32*32*32/2;
+            vec![(vec![(); blocks*6*4], vec![0; blocks*6*6], None, RenderDataPurpose::TerrainOpaque)]
+        }
+
+        fn aggregate_mesh(&self, _: Length3D, _: &HashMap<Position<chux>, Chunk<(), u32, chux>>)
+            -> Vec<(Vec<()>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_worst_case_chunk_over_vertex_limit() {
+        let limit = 200_000;
+        let mut warned = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, CheckerboardGenerator, WorkerPool::new(0),
+                                        ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Warn(limit), None);
+        let mut mesh = CheckerboardGenerator.generate_mesh(Length3D::origin());
+        assert!(warned.limit_vertices(Length3D::origin(), &mut mesh));
+        assert!(!mesh.is_empty());
+        warned.update(UpdateChunk::Forced);
+        assert!(warned.chunks.values().all(|c| !c.mesh.is_empty()));
+
+        let mut skipped = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, CheckerboardGenerator, WorkerPool::new(0),
+                                         ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Skip(limit), None);
+        skipped.update(UpdateChunk::Forced);
+        assert_eq!(skipped.chunks.len(), 8);
+        assert!(skipped.chunks.values().all(|c| c.mesh.is_empty()));
+
+        // a flat chunk's worth of top faces is within the limit
+        let mut mesh = vec![(vec![(); 32*32*4], vec![0; 32*32*6], None, RenderDataPurpose::TerrainOpaque)];
+        assert!(!skipped.limit_vertices(Length3D::origin(), &mut mesh));
+    }
+
+    #[test]
+    fn test_cull_ranges_keeps_chunks_in_frustum() {
+        // only keeps what lies in front of the z = 0 plane
+        let mut frustum = [0; 6].map(|_| Plane::new([0.0, 1.0, 0.0, 1000.0]));
+        frustum[4] = Plane::new([0.0, 0.0, -1.0, 0.0]);
+        let range = |mesh_id: u64, z: f32| ChunkRange {
+            mesh_id, verts: 0..4, inds: 0..6, min: [0.0, 0.0, z], max: [32.0, 32.0, z+32.0]
+        };
+
+        let ranges = vec![range(0, -64.0), range(1, -32.0), range(2, 8.0), range(3, -32.0), range(4, 32.0)];
+        let kept: Vec<u64> = cull_ranges(&ranges, &frustum).iter().map(|range| range.mesh_id).collect();
+        assert_eq!(kept, vec![0, 1, 3]);
+
+        // straddling the plane is kept
+        assert_eq!(cull_ranges(&[range(5, -16.0)], &frustum).len(), 1);
+    }
+}
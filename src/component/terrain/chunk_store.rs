@@ -0,0 +1,464 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uom::si::f32::Length;
+use crate::component::terrain::Block;
+use crate::component::terrain::terrain_gen::TerrainGenerator;
+use crate::measurement::{blox, chux};
+
+
+const MAGIC: &[u8; 4] = b"MXRG";
+// bumped whenever the layout changes, region files of any other version are refused rather than misread
+// version 1 stored the cells as u16, which can't fit the block ids from 65534 on, so those are still read widened
+const VERSION: u16 = 2;
+const REGION_SIZE: i32 = 8;  // chunks along each axis of a region file
+const REGION_EXTENSION: &str = "mxr";
+// the dirty chunks are written at least this often, so a crash only loses the latest edits
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+// cell of each block of a chunk, the placed blocks are stored as their block id offset by 2
+const UNEDITED: u32 = 0;
+const REMOVED: u32 = 1;
+
+// the saved chunks stay a chux regardless of the size of the meshed chunks, so the saves don't depend on it
+fn chunk_size() -> i32 {
+    Length::new::<chux>(1.0).get::<blox>() as i32
+}
+
+fn encode_cell(edit: Option<Block>) -> u32 {
+    edit.map_or(REMOVED, |block| block.0 as u32+2)
+}
+
+fn decode_cell(cell: u32) -> io::Result<Option<Option<Block>>> {
+    match cell {
+        UNEDITED => Ok(None),
+        REMOVED => Ok(Some(None)),
+        cell => u16::try_from(cell-2).map(|id| Some(Some(Block(id))))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Block id {} is out of range", cell-2))),
+    }
+}
+
+// runs of the same cell as (length, cell), as the chunks are mostly unedited
+pub(crate) fn encode_runs(cells: &[u32]) -> Vec<(u16, u32)> {
+    let mut runs: Vec<(u16, u32)> = vec![];
+    for cell in cells {
+        match runs.last_mut() {
+            Some((len, run_cell)) if run_cell == cell && *len < u16::MAX => *len += 1,
+            _ => runs.push((1, *cell)),
+        }
+    }
+    runs
+}
+
+// the runs are only expanded once they add up to the chunk, so a corrupt run count can't allocate beyond it
+pub(crate) fn decode_runs(runs: &[(u16, u32)], len: usize) -> io::Result<Vec<u32>> {
+    let total: usize = runs.iter().map(|(run_len, _)| *run_len as usize).sum();
+    if total != len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Chunk has {total} blocks instead of {len}")));
+    }
+    Ok(runs.iter().flat_map(|(run_len, cell)| std::iter::repeat_n(*cell, *run_len as usize)).collect())
+}
+
+// cells of the chunk (at the chunk coordinates) by x, then y, then z
+fn chunk_cells(terrain_gen: &TerrainGenerator, chunk: [i32; 3]) -> Vec<u32> {
+    let size = chunk_size();
+    let min = chunk.map(|c| c*size);
+    let mut cells = vec![UNEDITED; (size*size*size) as usize];
+    for (pos, edit) in terrain_gen.edits_within(min, min.map(|c| c+size-1)) {
+        let [x, y, z] = [0, 1, 2].map(|k| pos[k]-min[k]);
+        cells[((x*size+y)*size+z) as usize] = encode_cell(edit);
+    }
+    cells
+}
+
+fn apply_cells(terrain_gen: &TerrainGenerator, chunk: [i32; 3], cells: &[u32]) -> io::Result<()> {
+    let size = chunk_size();
+    for (ind, cell) in cells.iter().enumerate() {
+        if let Some(edit) = decode_cell(*cell)? {
+            let ind = ind as i32;
+            let (x, y, z) = (ind/(size*size), ind/size%size, ind%size);
+            terrain_gen.set_block([chunk[0]*size+x, chunk[1]*size+y, chunk[2]*size+z], edit);
+        }
+    }
+    Ok(())
+}
+
+fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    r.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_cell(r: &mut impl Read, version: u16) -> io::Result<u32> {
+    if version == 1 {read_u16(r).map(u32::from)} else {read_u32(r)}
+}
+
+// the magic and version, followed by the number of chunks, then the index of each chunk within the region along
+// with its runs, all little endian
+pub(crate) fn write_region(w: &mut impl Write, chunks: &BTreeMap<u16, Vec<u32>>) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&(chunks.len() as u32).to_le_bytes())?;
+    for (ind, cells) in chunks {
+        let runs = encode_runs(cells);
+        w.write_all(&ind.to_le_bytes())?;
+        w.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (len, cell) in runs {
+            w.write_all(&len.to_le_bytes())?;
+            w.write_all(&cell.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read_region(r: &mut impl Read) -> io::Result<BTreeMap<u16, Vec<u32>>> {
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a region file"));
+    }
+    let version = read_u16(r)?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Region file version {version} is not {VERSION}")));
+    }
+
+    let chunk_len = (chunk_size()*chunk_size()*chunk_size()) as usize;
+    let mut chunks = BTreeMap::new();
+    for _ in 0..read_u32(r)? {
+        let ind = read_u16(r)?;
+        if ind as i32 >= REGION_SIZE*REGION_SIZE*REGION_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Chunk index {ind} is outside of the region")));
+        }
+        let runs = (0..read_u32(r)?)
+            .map(|_| Ok((read_u16(r)?, read_cell(r, version)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        chunks.insert(ind, decode_runs(&runs, chunk_len)?);
+    }
+    Ok(chunks)
+}
+
+// region of the chunk, along with the index of the chunk within it
+fn region_of(chunk: [i32; 3]) -> ([i32; 3], u16) {
+    let region = chunk.map(|c| c.div_euclid(REGION_SIZE));
+    let [x, y, z] = chunk.map(|c| c.rem_euclid(REGION_SIZE));
+    (region, ((x*REGION_SIZE+y)*REGION_SIZE+z) as u16)
+}
+
+fn chunk_in(region: [i32; 3], ind: u16) -> [i32; 3] {
+    let ind = ind as i32;
+    let local = [ind/(REGION_SIZE*REGION_SIZE), ind/REGION_SIZE%REGION_SIZE, ind%REGION_SIZE];
+    [0, 1, 2].map(|k| region[k]*REGION_SIZE+local[k])
+}
+
+// block edits of the extreme fidelity chunks saved in region files, as the rest of the terrain is generated again
+// the region files are only read once the chunks around them are generated, so a large save isn't read all at once
+pub(crate) struct ChunkStore {
+    dir: PathBuf,
+    unloaded: HashSet<[i32; 3]>,  // regions saved but not read yet
+    dirty: HashSet<[i32; 3]>,  // chunks with edits not written yet
+    last_flush: Instant,
+}
+
+impl ChunkStore {
+    pub(crate) fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf(), unloaded: HashSet::new(), dirty: HashSet::new(), last_flush: Instant::now() }
+    }
+
+    fn region_path(&self, region: [i32; 3]) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}.{REGION_EXTENSION}", region[0], region[1], region[2]))
+    }
+
+    fn parse_region_path(path: &Path) -> Option<[i32; 3]> {
+        if path.extension()? != REGION_EXTENSION {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        let coords: Vec<i32> = stem.strip_prefix("r.")?.split('.').map(str::parse).collect::<Result<_, _>>().ok()?;
+        coords.try_into().ok()
+    }
+
+    // finds the saved regions (without reading them), returns the number of regions found
+    pub(crate) fn scan(&mut self) -> io::Result<usize> {
+        if !self.dir.is_dir() {
+            return Ok(0);
+        }
+        for entry in fs::read_dir(&self.dir)? {
+            if let Some(region) = Self::parse_region_path(&entry?.path()) {
+                self.unloaded.insert(region);
+            }
+        }
+        Ok(self.unloaded.len())
+    }
+
+    // applies the saved edits of the region to the terrain generator, returns the number of chunks loaded
+    fn load_region(&mut self, terrain_gen: &TerrainGenerator, region: [i32; 3]) -> io::Result<usize> {
+        // not read again, even if it fails to be read
+        if !self.unloaded.remove(&region) {
+            return Ok(0);
+        }
+        let path = self.region_path(region);
+        let chunks = read_region(&mut BufReader::new(File::open(&path)?))
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to read region file {path:?}: {e}")))?;
+        for (ind, cells) in &chunks {
+            apply_cells(terrain_gen, chunk_in(region, *ind), cells)
+                .map_err(|e| io::Error::new(e.kind(), format!("Failed to read region file {path:?}: {e}")))?;
+        }
+        Ok(chunks.len())
+    }
+
+    // loads the saved regions overlapping the blocks between min and max (inclusive), before any chunk there is
+    // generated (as the edits are shared by the chunks of every fidelity), returns the number of chunks loaded
+    pub(crate) fn load_within(&mut self, terrain_gen: &TerrainGenerator, min: [i32; 3], max: [i32; 3]) -> io::Result<usize> {
+        let region_size = REGION_SIZE*chunk_size();
+        let [min, max] = [min, max].map(|pos| pos.map(|c| c.div_euclid(region_size)));
+        let within: Vec<[i32; 3]> = self.unloaded.iter()
+            .filter(|region| (0..3).all(|k| (min[k]..=max[k]).contains(&region[k])))
+            .copied()
+            .collect();
+
+        let mut loaded = 0;
+        for region in within {
+            loaded += self.load_region(terrain_gen, region)?;
+        }
+        Ok(loaded)
+    }
+
+    // the chunk containing the block about to be edited is written on the next flush, its region is loaded first so
+    // the edit isn't overwritten by the saved ones nor the saved ones dropped when the chunk is written
+    pub(crate) fn edit(&mut self, terrain_gen: &TerrainGenerator, block: [i32; 3]) -> io::Result<()> {
+        let chunk = block.map(|c| c.div_euclid(chunk_size()));
+        self.dirty.insert(chunk);
+        self.load_region(terrain_gen, region_of(chunk).0).map(|_| ())
+    }
+
+    // whether the dirty chunks are to be written, as it's been a while since the last flush
+    pub(crate) fn flush_due(&self, now: Instant) -> bool {
+        !self.dirty.is_empty() && now.duration_since(self.last_flush) >= FLUSH_INTERVAL
+    }
+
+    // writes the dirty chunks into their region files (dropping the chunks without edits left), returns the number of
+    // chunks written
+    pub(crate) fn flush(&mut self, terrain_gen: &TerrainGenerator) -> io::Result<usize> {
+        self.last_flush = Instant::now();
+        if self.dirty.is_empty() {
+            return Ok(0);
+        }
+        fs::create_dir_all(&self.dir)?;
+
+        let mut regions: HashMap<[i32; 3], Vec<[i32; 3]>> = HashMap::new();
+        for chunk in &self.dirty {
+            regions.entry(region_of(*chunk).0).or_default().push(*chunk);
+        }
+        for (region, chunks) in regions {
+            let path = self.region_path(region);
+            let mut saved = if path.is_file() {
+                read_region(&mut BufReader::new(File::open(&path)?))
+                    .map_err(|e| io::Error::new(e.kind(), format!("Failed to read region file {path:?}: {e}")))?
+            } else {
+                BTreeMap::new()
+            };
+            for chunk in chunks {
+                let cells = chunk_cells(terrain_gen, chunk);
+                let ind = region_of(chunk).1;
+                if cells.iter().all(|cell| *cell == UNEDITED) {
+                    saved.remove(&ind);
+                } else {
+                    saved.insert(ind, cells);
+                }
+            }
+
+            if saved.is_empty() {
+                if path.is_file() {
+                    fs::remove_file(&path)?;
+                }
+                continue;
+            }
+            // replaced only once fully written, so a failed write keeps the previous save
+            let tmp_path = path.with_extension("tmp");
+            let mut w = BufWriter::new(File::create(&tmp_path)?);
+            write_region(&mut w, &saved)?;
+            w.flush()?;
+            drop(w);
+            fs::rename(&tmp_path, &path)?;
+        }
+
+        let written = self.dirty.len();
+        self.dirty.clear();
+        Ok(written)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_length_encoding() {
+        let cells = [vec![UNEDITED; 1000], vec![REMOVED; 3], vec![4, 4, 9], vec![UNEDITED; 70000]].concat();
+        let runs = encode_runs(&cells);
+        // runs longer than a u16 are split
+        assert_eq!(runs, vec![(1000, UNEDITED), (3, REMOVED), (2, 4), (1, 9), (u16::MAX, UNEDITED), (4465, UNEDITED)]);
+        assert_eq!(decode_runs(&runs, cells.len()).unwrap(), cells);
+        assert_eq!(decode_runs(&runs, cells.len()+1).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // rejected before expanding billions of cells
+        assert_eq!(decode_runs(&vec![(u16::MAX, UNEDITED); 100000], cells.len()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_region_version_checked() {
+        let mut bytes = vec![];
+        write_region(&mut bytes, &BTreeMap::new()).unwrap();
+        assert_eq!(bytes.len(), 4+2+4);
+        assert!(read_region(&mut bytes.as_slice()).unwrap().is_empty());
+
+        bytes[4..6].copy_from_slice(&(VERSION+1).to_le_bytes());
+        assert_eq!(read_region(&mut bytes.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(read_region(&mut &b"PNG\0\0\0"[..]).is_err());
+    }
+
+    #[test]
+    fn test_region_v1_cells_widened() {
+        let mut bytes = vec![];
+        write_region(&mut bytes, &BTreeMap::new()).unwrap();
+        bytes[4..6].copy_from_slice(&1u16.to_le_bytes());
+        bytes[6..10].copy_from_slice(&1u32.to_le_bytes());
+        let len = (chunk_size()*chunk_size()*chunk_size()) as u32;
+        for value in [7u16.to_le_bytes().as_slice(), &2u32.to_le_bytes(), &1u16.to_le_bytes(), &9u16.to_le_bytes(),
+                      &(len as u16-1).to_le_bytes(), &UNEDITED.to_le_bytes()[..2]] {
+            bytes.extend_from_slice(value);
+        }
+        let chunks = read_region(&mut bytes.as_slice()).unwrap();
+        assert_eq!(chunks[&7][..2], [9, UNEDITED]);
+    }
+
+    #[test]
+    fn test_region_chunk_index_checked() {
+        let cells = vec![UNEDITED; (chunk_size()*chunk_size()*chunk_size()) as usize];
+        for (ind, valid) in [(511, true), (512, false), (u16::MAX, false)] {
+            let mut bytes = vec![];
+            write_region(&mut bytes, &BTreeMap::from([(ind, cells.clone())])).unwrap();
+            match read_region(&mut bytes.as_slice()) {
+                Ok(chunks) => assert!(valid && chunks.contains_key(&ind)),
+                Err(e) => assert!(!valid && e.kind() == io::ErrorKind::InvalidData),
+            }
+        }
+    }
+
+    fn store_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mtxg_chunk_store_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn load_all(dir: &Path, terrain_gen: &TerrainGenerator) -> usize {
+        let mut store = ChunkStore::new(dir);
+        store.scan().unwrap();
+        store.load_within(terrain_gen, [i32::MIN; 3], [i32::MAX; 3]).unwrap()
+    }
+
+    // every block of the chunk, generated along with the edits
+    fn chunk_blocks(terrain_gen: &TerrainGenerator, chunk: [i32; 3]) -> Vec<Option<u16>> {
+        let size = chunk_size();
+        (0..size*size*size)
+            .map(|ind| [ind/(size*size), ind/size%size, ind%size])
+            .map(|local| [0, 1, 2].map(|k| (chunk[k]*size+local[k]) as f64))
+            .map(|[x, y, z]| terrain_gen.get_block(x, y, z).map(|block| block.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let dir = store_dir("round_trip");
+        let terrain_gen = TerrainGenerator::new(0, 20.0, 1.0, false);
+        let mut store = ChunkStore::new(&dir);
+
+        // a few placed and removed blocks across two chunks (of two regions), one of them at negative coordinates and
+        // one with a block id too large for the cells of the previous version
+        let edits = [([3, 20, 4], Some(Block(2))), ([3, 21, 4], Some(Block(u16::MAX))), ([0, 19, 0], None), ([-1, 19, -40], Some(Block(8)))];
+        for (pos, block) in edits {
+            store.edit(&terrain_gen, pos).unwrap();
+            terrain_gen.set_block(pos, block);
+        }
+        assert_eq!(store.flush(&terrain_gen).unwrap(), 2);
+        assert_eq!(store.flush(&terrain_gen).unwrap(), 0);
+
+        let reloaded = TerrainGenerator::new(0, 20.0, 1.0, false);
+        assert_eq!(load_all(&dir, &reloaded), 2);
+        for chunk in [[0, 0, 0], [-1, 0, -2]] {
+            assert_eq!(chunk_cells(&reloaded, chunk), chunk_cells(&terrain_gen, chunk));
+            assert_eq!(chunk_blocks(&reloaded, chunk), chunk_blocks(&terrain_gen, chunk));
+        }
+        for (pos, block) in edits {
+            let [x, y, z] = pos.map(|c| c as f64);
+            assert_eq!(reloaded.get_block(x, y, z).map(|block| block.0), block.map(|block| block.0));
+        }
+
+        // saving the reloaded chunks again writes the same bytes
+        let region = dir.join("r.0.0.0.mxr");
+        let saved = fs::read(&region).unwrap();
+        assert!(saved.len() < 64);
+        let mut store = ChunkStore::new(&dir);
+        store.scan().unwrap();
+        store.edit(&reloaded, [3, 20, 4]).unwrap();
+        store.flush(&reloaded).unwrap();
+        assert_eq!(fs::read(&region).unwrap(), saved);
+
+        // removing the placed block saves the removal rather than dropping the chunk
+        let mut store = ChunkStore::new(&dir);
+        store.scan().unwrap();
+        store.edit(&terrain_gen, [-1, 19, -40]).unwrap();
+        terrain_gen.set_block([-1, 19, -40], None);
+        store.flush(&terrain_gen).unwrap();
+        assert_eq!(load_all(&dir, &TerrainGenerator::new(0, 20.0, 1.0, false)), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_regions_loaded_on_demand() {
+        let dir = store_dir("on_demand");
+        let terrain_gen = TerrainGenerator::new(0, 20.0, 1.0, false);
+        let mut store = ChunkStore::new(&dir);
+        let far = [REGION_SIZE*chunk_size()*3+5, 20, 2];
+        for pos in [[1, 20, 2], far] {
+            store.edit(&terrain_gen, pos).unwrap();
+            terrain_gen.set_block(pos, Some(Block(3)));
+        }
+        store.flush(&terrain_gen).unwrap();
+
+        let reloaded = TerrainGenerator::new(0, 20.0, 1.0, false);
+        let mut store = ChunkStore::new(&dir);
+        assert_eq!(store.scan().unwrap(), 2);
+        assert_eq!(store.load_within(&reloaded, [-64; 3], [64; 3]).unwrap(), 1);
+        assert_eq!(reloaded.get_block(1.0, 20.0, 2.0).map(|block| block.0), Some(3));
+        assert_ne!(reloaded.get_block(far[0] as f64, 20.0, 2.0).map(|block| block.0), Some(3));
+        // a region is only read once
+        assert_eq!(store.load_within(&reloaded, [-64; 3], [64; 3]).unwrap(), 0);
+
+        // editing a block of a region not loaded yet loads it first, keeping its saved edits
+        store.edit(&reloaded, [far[0], 21, 2]).unwrap();
+        reloaded.set_block([far[0], 21, 2], Some(Block(4)));
+        assert_eq!(reloaded.get_block(far[0] as f64, 20.0, 2.0).map(|block| block.0), Some(3));
+        assert!(!store.flush_due(Instant::now()));
+        assert!(store.flush_due(Instant::now()+FLUSH_INTERVAL));
+        store.flush(&reloaded).unwrap();
+        assert!(!store.flush_due(Instant::now()+FLUSH_INTERVAL));
+
+        let again = TerrainGenerator::new(0, 20.0, 1.0, false);
+        assert_eq!(load_all(&dir, &again), 2);
+        assert_eq!(chunk_blocks(&again, far.map(|c| c.div_euclid(chunk_size()))), chunk_blocks(&reloaded, far.map(|c| c.div_euclid(chunk_size()))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+use crate::component::terrain::FaceDir;
+use crate::shader::chunk::ChunkVertex;
+
+
+// of the blocks open to the sky, dimming by one per block the light spreads
+pub(crate) const MAX_LIGHT: u8 = 15;
+
+const NEIGHBOURS: [[i32; 3]; 6] = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+
+// light levels (from 0 to MAX_LIGHT) of the blocks within a box
+pub(crate) struct LightVolume {
+    min: [i32; 3],
+    size: [i32; 3],
+    levels: Vec<u8>,  // by x, then y, then z
+}
+
+impl LightVolume {
+    // fully lit everywhere, for the chunks without any shade (or meshed without light)
+    pub(crate) fn full() -> Self {
+        Self { min: [0; 3], size: [0; 3], levels: vec![] }
+    }
+
+    // sky light flooding straight down the columns open to the sky at the top of the box until the first opaque
+    // block, then spreading out from there (the light winding out of the box and back in is missed)
+    pub(crate) fn sky(min: [i32; 3], size: [i32; 3], opaque: impl Fn([i32; 3]) -> bool, open_to_sky: impl Fn(i32, i32) -> bool) -> Self {
+        let index = |[x, y, z]: [i32; 3]| ((x*size[1]+y)*size[2]+z) as usize;
+        let len = (size[0]*size[1]*size[2]) as usize;
+
+        let mut solid = vec![false; len];
+        for x in 0..size[0] {
+            for y in 0..size[1] {
+                for z in 0..size[2] {
+                    solid[index([x, y, z])] = opaque([min[0]+x, min[1]+y, min[2]+z]);
+                }
+            }
+        }
+
+        let mut levels = vec![0u8; len];
+        let mut queue = VecDeque::new();
+        for x in 0..size[0] {
+            for z in 0..size[2] {
+                if !open_to_sky(min[0]+x, min[2]+z) {
+                    continue;
+                }
+                for y in (0..size[1]).rev() {
+                    let i = index([x, y, z]);
+                    if solid[i] {
+                        break;
+                    }
+                    levels[i] = MAX_LIGHT;
+                    queue.push_back([x, y, z]);
+                }
+            }
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            let level = levels[index(pos)];
+            if level <= 1 {
+                continue;
+            }
+            for [dx, dy, dz] in NEIGHBOURS {
+                let adj = [pos[0]+dx, pos[1]+dy, pos[2]+dz];
+                if (0..3).any(|k| adj[k] < 0 || adj[k] >= size[k]) {
+                    continue;
+                }
+                let i = index(adj);
+                if !solid[i] && levels[i] < level-1 {
+                    levels[i] = level-1;
+                    queue.push_back(adj);
+                }
+            }
+        }
+
+        Self { min, size, levels }
+    }
+
+    // fully lit outside of the box
+    pub(crate) fn level(&self, pos: [i32; 3]) -> u8 {
+        let rel = [0, 1, 2].map(|k| pos[k]-self.min[k]);
+        if (0..3).any(|k| rel[k] < 0 || rel[k] >= self.size[k]) {
+            return MAX_LIGHT;
+        }
+        self.levels[((rel[0]*self.size[1]+rel[1])*self.size[2]+rel[2]) as usize]
+    }
+
+    // a face is lit by the block in front of it
+    pub(crate) fn face_level(&self, pos: [i32; 3], face: FaceDir) -> u8 {
+        let normal = face.normal().map(|n| n as i32);
+        self.level([pos[0]+normal[0], pos[1]+normal[1], pos[2]+normal[2]])
+    }
+}
+
+// as the light vertex attribute, from 0 (dark) to 1 (fully lit)
+pub(crate) fn set_light(verts: &mut [ChunkVertex], level: u8) {
+    for vert in verts {
+        vert.light = level as f32/MAX_LIGHT as f32;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sky_light_under_overhang() {
+        // ground below y 0, with a roof at y 4 over x 0 to 19
+        let opaque = |[x, y, _]: [i32; 3]| y < 0 || (y == 4 && (0..20).contains(&x));
+        let light = LightVolume::sky([-20, -2, -2], [60, 10, 5], opaque, |_, _| true);
+
+        // open to the sky and above the roof
+        assert_eq!(light.level([-5, 0, 0]), MAX_LIGHT);
+        assert_eq!(light.level([10, 5, 0]), MAX_LIGHT);
+        // dimming by one per block under the roof, away from its edge
+        assert_eq!(light.level([0, 0, 0]), MAX_LIGHT-1);
+        assert_eq!(light.level([5, 0, 0]), MAX_LIGHT-6);
+        assert_eq!(light.level([15, 0, 0]), MAX_LIGHT-5);
+        // opaque blocks are dark
+        assert_eq!(light.level([10, -1, 0]), 0);
+        assert_eq!(light.level([10, 4, 0]), 0);
+
+        // the top face of the ground under the roof is lit by the block above it
+        assert_eq!(light.face_level([5, -1, 0], FaceDir::TOP), MAX_LIGHT-6);
+        assert_eq!(light.face_level([5, -1, 0], FaceDir::BOTTOM), 0);
+        // and outside of the volume everything is lit
+        assert_eq!(light.level([1000, -1000, 0]), MAX_LIGHT);
+        assert_eq!(LightVolume::full().level([0, 0, 0]), MAX_LIGHT);
+    }
+
+    #[test]
+    fn test_sky_light_shaded_columns() {
+        // an enclosed room is pitch black, while the shaded columns are lit from the sides
+        let room = |[x, y, z]: [i32; 3]| y < 0 || ((0..5).contains(&x) && (0..5).contains(&y) && (0..5).contains(&z) &&
+            !((1..4).contains(&x) && (1..4).contains(&y) && (1..4).contains(&z)));
+        let light = LightVolume::sky([-3, -1, -3], [11, 8, 11], room, |x, z| x != -2 || z != -2);
+
+        assert_eq!(light.level([2, 2, 2]), 0);
+        assert_eq!(light.level([-2, 0, -2]), MAX_LIGHT-1);
+    }
+}
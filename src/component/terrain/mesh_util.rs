@@ -1,5 +1,8 @@
-use std::rc::Rc;
-use crate::component::terrain::{Block, BlockData, FaceDir, TextureMapper};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use crate::component::terrain::{Block, BlockData, FaceDir, MeshType, TextureAnimation, TranslucentSort, TransparencyType};
+use crate::component::terrain::chunk_mesh::ChunkRange;
+use crate::component::terrain::light::{LightVolume, MAX_LIGHT, set_light};
 use crate::component::terrain::terrain_gen::TerrainGenerator;
 use crate::component::texture::TextureIDMapper;
 use crate::shader::chunk::ChunkVertex;
@@ -12,14 +15,67 @@ pub(super) trait ChunkMeshUtil<'b> {
 
     fn block_ind(&self, ind: usize) -> BlockData<'b>;
 
-    fn terrain_gen(&self) -> Rc<TerrainGenerator>;
+    fn terrain_gen(&self) -> Arc<TerrainGenerator>;
+
+    // voxel grid downsample factor for distant chunks (1 for full resolution), must divide the chunk size
+    fn downsample(&self) -> u32 {1}
+
+    // downsample factors of the neighbouring tiers, whose chunks may border this chunk
+    fn neighbour_downsamples(&self) -> &[u32] {&[]}
+
+    fn opaque_cube(&self, block: Block) -> bool {
+        let block = self.block_ind(block.0 as usize);
+        matches!((block.mesh, block.transparency), (MeshType::Cube, TransparencyType::Opaque))
+    }
+
+    // whether the block is drawn as an opaque cube by a mesh of the downsample factor, which takes each of its cells
+    // from the block at the lowest corner
+    fn solid_at(&self, pos: [i32; 3], ds: u32) -> bool {
+        let cell = pos.map(|p| p.div_euclid(ds as i32)*ds as i32);
+        self.terrain_gen().get_block(cell[0] as f64, cell[1] as f64, cell[2] as f64).is_some_and(|block| self.opaque_cube(block))
+    }
 
     fn access(&self, x: u32, y: u32, z: u32) -> usize {
         let size = self.chunk_size();
         (y*size*size+x*size+z) as usize
     }
 
-    fn voluminous_opaque_cubes_mesh<C>(&self, ofs: (i32, i32, i32), chunk_pos: C) -> [(Vec<ChunkVertex>, Vec<u32>, FaceDir); 6]
+    // sky light around the chunk, reaching MAX_LIGHT blocks into the neighbouring chunks (as far as the light
+    // travels) so the light on the chunk borders matches the neighbours, as every chunk is meshed on its own
+    fn sky_light(&self, ofs: (i32, i32, i32)) -> LightVolume {
+        let pad = MAX_LIGHT as i32;
+        let size = self.chunk_size() as i32+2*pad;
+        let min = [ofs.0-pad, ofs.1-pad, ofs.2-pad];
+        let max = [min[0]+size-1, min[1]+size-1, min[2]+size-1];
+        let terrain_gen = self.terrain_gen();
+
+        // the generated terrain is a height map, every block above the ground is open to the sky until it is edited,
+        // except for the caves below the cave roof of the columns
+        let heights: Vec<i32> = (0..size*size)
+            .map(|i| terrain_gen.opaque_block_height_bound_test((min[0]+i/size) as f64, (min[2]+i%size) as f64).ceil() as i32)
+            .collect();
+        let caves = heights.iter().any(|hb| (min[1] as f64) < terrain_gen.cave_roof(*hb as f64));
+        let edits: HashMap<[i32; 3], Option<Block>> = terrain_gen.edits_within(min, [max[0], i32::MAX, max[2]]).into_iter().collect();
+        if edits.is_empty() && !caves {
+            return LightVolume::full();
+        }
+
+        // placed opaque blocks above the box shade the columns below them
+        let shaded: HashSet<(i32, i32)> = edits.iter()
+            .filter(|(pos, block)| pos[1] > max[1] && block.is_some_and(|block| self.opaque_cube(block)))
+            .map(|(pos, _)| (pos[0], pos[2]))
+            .collect();
+        let height = |x: i32, z: i32| heights[((x-min[0])*size+z-min[2]) as usize];
+        let opaque = |[x, y, z]: [i32; 3]| match edits.get(&[x, y, z]) {
+            Some(block) => block.is_some_and(|block| self.opaque_cube(block)),
+            None => y < height(x, z) && !(caves && terrain_gen.cave_at(x as f64, y as f64, z as f64, height(x, z) as f64)),
+        };
+        // as are the ground above the box, unless dug out down to it
+        let open_to_sky = |x: i32, z: i32| !shaded.contains(&(x, z)) && (max[1]+1..height(x, z)).all(|y| !opaque([x, y, z]));
+        LightVolume::sky(min, [size; 3], opaque, open_to_sky)
+    }
+
+    fn voluminous_opaque_cubes_mesh<C>(&self, ofs: (i32, i32, i32), chunk_pos: C, light: &LightVolume) -> [(Vec<ChunkVertex>, Vec<u32>, FaceDir); 6]
         where C: Fn(u32, u32, u32) -> (f32, f32, f32)
     {
         let mut top_verts = vec![];
@@ -41,7 +97,10 @@ pub(super) trait ChunkMeshUtil<'b> {
         let mut back_inds = vec![];
         let mut back_faces = 0u32;
 
-        let expanded_size = self.chunk_size()+1;
+        // each cell of the (possibly downsampled) grid spans `ds` blocks
+        let ds = self.downsample();
+        let size = self.chunk_size()/ds;
+        let expanded_size = size+1;
 
         let mut xy_grid: Vec<u16> = vec![0u16; (expanded_size*expanded_size) as usize];
         let mut yz_grid: Vec<u16> = vec![0u16; (expanded_size*expanded_size) as usize];
@@ -54,20 +113,37 @@ pub(super) trait ChunkMeshUtil<'b> {
         let mut xz_max_height_bounds = vec![0i32; (expanded_size*expanded_size) as usize];
         let mut min_height_bound = expanded_size;
         let mut max_height_bound = 0u32;
+        let mut caves = false;  // whether the chunk reaches below the cave roof of any column
 
         for x in 0..expanded_size {
             for z in 0..expanded_size {
-                let hb = self.terrain_gen().opaque_block_height_bound_test((ofs.0+x as i32) as f64, (ofs.2+z as i32) as f64).ceil() as i32;
+                let hb = self.terrain_gen().opaque_block_height_bound_test((ofs.0+(x*ds) as i32) as f64, (ofs.2+(z*ds) as i32) as f64).ceil() as i32;
                 xz_max_height_bounds[(x*expanded_size+z) as usize] = hb;
-                if hb-ofs.1+1 > max_height_bound as i32 {
-                    max_height_bound = (hb-ofs.1+1).clamp(0i32, expanded_size as i32) as u32;
+                caves |= (ofs.1 as f64) < self.terrain_gen().cave_roof(hb as f64);
+                // height bounds in cell units
+                let hb_max = (hb-ofs.1+ds as i32-1).div_euclid(ds as i32);
+                let hb_min = (hb-ofs.1).div_euclid(ds as i32);
+                if hb_max+1 > max_height_bound as i32 {
+                    max_height_bound = (hb_max+1).clamp(0i32, expanded_size as i32) as u32;
                 }
-                if hb-ofs.1 < min_height_bound as i32 {
-                    min_height_bound = (hb-ofs.1).clamp(0i32, expanded_size as i32) as u32;
+                if hb_min < min_height_bound as i32 {
+                    min_height_bound = hb_min.clamp(0i32, expanded_size as i32) as u32;
                 }
             }
         }
 
+        // edited blocks can be anywhere regardless of the height bounds, so the whole chunk is checked
+        let max_ofs = (expanded_size*ds) as i32;
+        let edited = self.terrain_gen().edited_within([ofs.0, ofs.1, ofs.2], [ofs.0+max_ofs, ofs.1+max_ofs, ofs.2+max_ofs]);
+        if edited {
+            min_height_bound = 0;
+            max_height_bound = expanded_size;
+        }
+        // the caves below the height bounds open and close the columns any number of times
+        if caves {
+            min_height_bound = 0;
+        }
+
         // for x == 0, set cells to start with closed
         for y in 0..min_height_bound {
             let mut xy_cell = &mut xy_grid[(0*expanded_size+y) as usize];
@@ -87,8 +163,6 @@ pub(super) trait ChunkMeshUtil<'b> {
                 // let height = opaque_block_max_height_bounds((x_ofs+x as i32) as f64, (z_ofs+z as i32) as f64).ceil() as isize;
                 let hb = xz_max_height_bounds[(x*expanded_size+z) as usize];
 
-                // TODO: multiple height bounds when we add caves, overhangs, trees/models, etc.
-
                 // for y == 0, set cells to start with closed
                 let mut xz_cell = &mut xz_grid[(x*expanded_size+z) as usize];
                 if *xz_cell%2 == 0 {
@@ -96,13 +170,26 @@ pub(super) trait ChunkMeshUtil<'b> {
                 }
 
                 for y in min_height_bound..max_height_bound {
-                    let open = ofs.1+y as i32 >= hb;
+                    let open = if edited {
+                        !self.terrain_gen().get_block(
+                            (ofs.0+(x*ds) as i32) as f64, (ofs.1+(y*ds) as i32) as f64, (ofs.2+(z*ds) as i32) as f64,
+                        ).is_some_and(|block| self.opaque_cube(block))
+                    } else {
+                        let by = ofs.1+(y*ds) as i32;
+                        by >= hb || caves && self.terrain_gen().cave_at(
+                            (ofs.0+(x*ds) as i32) as f64, by as f64, (ofs.2+(z*ds) as i32) as f64, hb as f64,
+                        )
+                    };
                     let mut xy_cell = &mut xy_grid[(x*expanded_size+y) as usize];
                     let mut yz_cell = &mut yz_grid[(y*expanded_size+z) as usize];
                     // let mut xz_cell = &mut xz_grid[(x*expanded_size+z) as usize];
 
                     let lazy_block_gen = |dx: i32, dy: i32, dz: i32| {
-                        self.terrain_gen().get_block((ofs.0+dx+x as i32) as f64, (ofs.1+dy+y as i32) as f64, (ofs.2+dz+z as i32) as f64)
+                        self.terrain_gen().get_block(
+                            (ofs.0+(dx+x as i32)*ds as i32) as f64,
+                            (ofs.1+(dy+y as i32)*ds as i32) as f64,
+                            (ofs.2+(dz+z as i32)*ds as i32) as f64,
+                        )
                     };
 
                     let mut fast_block_face_gen = |
@@ -111,14 +198,20 @@ pub(super) trait ChunkMeshUtil<'b> {
                     | {
                         if let Some(block) = block {
                             let block = self.block_ind(block.0 as usize);
-                            let txtr = block.texture_id;
 
                             // mesh assumed to be (opaque) cube
 
-                            let (mut verts, mut inds) = self.gen_face(
-                                chunk_pos((x as i32+dx) as u32,(y as i32+dy) as u32,(z as i32+dz) as u32),
-                                *total_faces*4, face_dir, txtr, false
+                            // occlusion is not noticeable on the downsampled distant terrain
+                            let occluded = |ox: i32, oy: i32, oz: i32| {
+                                lazy_block_gen(dx+ox, dy+oy, dz+oz).is_some_and(|block| self.opaque_cube(block))
+                            };
+                            let (mut verts, mut inds) = self.gen_scaled_face(
+                                chunk_pos(((x as i32+dx)*ds as i32) as u32,((y as i32+dy)*ds as i32) as u32,((z as i32+dz)*ds as i32) as u32),
+                                *total_faces*4, face_dir, &block, ds as f32, if ds == 1 {Some(&occluded)} else {None}
                             );
+                            set_light(&mut verts, light.face_level([
+                                ofs.0+(x as i32+dx)*ds as i32, ofs.1+(y as i32+dy)*ds as i32, ofs.2+(z as i32+dz)*ds as i32,
+                            ], face_dir));
                             total_verts.append(&mut verts);
                             total_inds.append(&mut inds);
                             *total_faces += 1;
@@ -126,7 +219,7 @@ pub(super) trait ChunkMeshUtil<'b> {
                     };
 
                     // prevents rendering the current face direction on the extended chunk size for the other direction's block checking
-                    if x < self.chunk_size() && y < self.chunk_size() {
+                    if x < size && y < size {
                         if *xy_cell%2 == 1 && open {
                             // current hit cell is set to closed that needs to be opened using previous block index
                             *xy_cell += 1;
@@ -150,7 +243,7 @@ pub(super) trait ChunkMeshUtil<'b> {
                         }
                     }
 
-                    if y < self.chunk_size() && z < self.chunk_size() {
+                    if y < size && z < size {
                         if *yz_cell%2 == 1 && open {
                             // current hit cell is set to closed that needs to be opened using previous block index
                             *yz_cell += 1;
@@ -174,7 +267,7 @@ pub(super) trait ChunkMeshUtil<'b> {
                         }
                     }
 
-                    if x < self.chunk_size() && z < self.chunk_size() {
+                    if x < size && z < size {
                         if *xz_cell%2 == 1 && open {
                             // current hit cell is set to closed that needs to be opened using previous block index
                             *xz_cell += 1;
@@ -202,17 +295,189 @@ pub(super) trait ChunkMeshUtil<'b> {
             }
         }
 
-        [
+        let mut mesh = [
             (top_verts, top_inds, FaceDir::TOP),
             (bottom_verts, bottom_inds, FaceDir::BOTTOM),
             (left_verts, left_inds, FaceDir::LEFT),
             (right_verts, right_inds, FaceDir::RIGHT),
             (front_verts, front_inds, FaceDir::FRONT),
             (back_verts, back_inds, FaceDir::BACK),
-        ]
+        ];
+        if !self.neighbour_downsamples().is_empty() {
+            for (verts, inds, face) in mesh.iter_mut() {
+                self.seam_faces(ofs, &chunk_pos, light, *face, verts, inds);
+                self.skirt_faces(ofs, &chunk_pos, light, *face, verts, inds);
+            }
+        }
+        mesh
     }
 
-    fn sparse_transparent_floral_mesh<C>(&self, ofs: (i32, i32, i32), chunk_pos: C) -> (Vec<ChunkVertex>, Vec<u32>)
+    // faces on a side plane of the chunk left open by a neighbour of another downsample factor, since each mesh only
+    // culls the faces on the chunk border against the blocks as sampled by itself
+    // - the far planes (+x and +z) get both sides of the border faces from the chunk meshed before them (as its
+    //   expanded chunk size reaches past the border), so only the faces facing blocks the neighbour draws as open
+    //   but this chunk samples as solid are missing there
+    // - the near planes (-x and -z) are missing the faces that the neighbour doesn't mesh for this chunk, as it
+    //   samples both sides of the border at its own downsample factor
+    // the faces are only searched around the surface (and the edited blocks), the tiers rarely meet at the caves
+    fn seam_faces<C>(&self, ofs: (i32, i32, i32), chunk_pos: C, light: &LightVolume, face: FaceDir,
+                     verts: &mut Vec<ChunkVertex>, inds: &mut Vec<u32>)
+        where C: Fn(u32, u32, u32) -> (f32, f32, f32)
+    {
+        let Some((axis, along, far)) = side_plane(face) else {
+            return;
+        };
+        let ds = self.downsample() as i32;
+        let seams: Vec<u32> = self.neighbour_downsamples().iter().copied().filter(|d| *d != ds as u32).collect();
+        if seams.is_empty() {
+            return;
+        }
+        let size = self.chunk_size() as i32;
+        let ofs = [ofs.0, ofs.1, ofs.2];
+        let terrain_gen = self.terrain_gen();
+
+        // layer of the cells of this chunk along the plane, and the layer of the neighbouring blocks
+        let (inner, outer) = if far {(ofs[axis]+size-ds, ofs[axis]+size)} else {(ofs[axis], ofs[axis]-1)};
+        let block_at = |layer: i32, u: i32, y: i32| {
+            let mut pos = [0, y, 0];
+            pos[axis] = layer;
+            pos[along] = u;
+            pos
+        };
+        let max_ds = seams.iter().fold(ds, |max, d| max.max(*d as i32));
+        let mut plane_min = block_at(outer.min(inner), ofs[along], ofs[1]);
+        let mut plane_max = block_at(outer.max(inner+ds-1), ofs[along]+size-1, ofs[1]+size-1);
+        plane_min[axis] -= max_ds;
+        plane_max[axis] += max_ds;
+        let edited = terrain_gen.edited_within(plane_min, plane_max);
+
+        for cu in 0..size/ds {
+            let u = ofs[along]+cu*ds;
+            // surface heights of the columns on both sides of the cell, as sampled at each downsample factor
+            let (mut low, mut high) = (i32::MAX, i32::MIN);
+            for (du, layer) in (0..ds).flat_map(|du| [inner, outer].map(|layer| (du, layer))) {
+                for d in seams.iter().copied().chain([1, ds as u32]) {
+                    let column = block_at(layer, u+du, 0).map(|p| p.div_euclid(d as i32)*d as i32);
+                    let hb = terrain_gen.opaque_block_height_bound_test(column[0] as f64, column[2] as f64).ceil() as i32;
+                    low = low.min(hb);
+                    high = high.max(hb);
+                }
+            }
+            let cells = if edited {
+                0..size/ds
+            } else {
+                (low-max_ds-ofs[1]).div_euclid(ds).max(0)..((high+max_ds-ofs[1]).div_euclid(ds)+1).min(size/ds)
+            };
+
+            for cy in cells {
+                let y = ofs[1]+cy*ds;
+                let cell = block_at(inner, u, y);
+                if !self.solid_at(cell, ds as u32) {
+                    continue;
+                }
+                let open = (0..ds).flat_map(|du| (0..ds).map(move |dy| (du, dy))).any(|(du, dy)| {
+                    let (inside, outside) = (block_at(if far {inner+ds-1} else {inner}, u+du, y+dy), block_at(outer, u+du, y+dy));
+                    seams.iter().any(|d| if far {
+                        self.solid_at(outside, ds as u32) && !self.solid_at(outside, *d)
+                    } else {
+                        !self.solid_at(outside, *d) && !self.solid_at(inside, *d)
+                    })
+                });
+                if !open {
+                    continue;
+                }
+
+                let block = terrain_gen.get_block(cell[0] as f64, cell[1] as f64, cell[2] as f64).unwrap();
+                let rel = [0, 1, 2].map(|k| (cell[k]-ofs[k]) as u32);
+                let (mut face_verts, mut face_inds) = self.gen_scaled_face(
+                    chunk_pos(rel[0], rel[1], rel[2]), verts.len() as u32, face, &self.block_ind(block.0 as usize), ds as f32, None,
+                );
+                set_light(&mut face_verts, light.face_level(cell, face));
+                verts.append(&mut face_verts);
+                inds.append(&mut face_inds);
+            }
+        }
+    }
+
+    // skirt hanging down from the top edge of the chunk on a side plane, hidden behind the solid blocks of the
+    // neighbour, which fills the cracks showing through the T-junctions along the border (where the vertices of the
+    // merged or downsampled faces of one side end up in the middle of the edges of the other side)
+    // - every chunk gets them, as the rings of the tiers move with the camera and any chunk can end up on a tier border
+    // - only where the neighbour is solid as sampled by this chunk and by every neighbouring tier, since the plane
+    //   already has a face (or a seam face) otherwise
+    // - deep enough to reach below the top edge of the coarsest neighbour, whose top can be lower by a cell of its own
+    fn skirt_faces<C>(&self, ofs: (i32, i32, i32), chunk_pos: C, light: &LightVolume, face: FaceDir,
+                      verts: &mut Vec<ChunkVertex>, inds: &mut Vec<u32>)
+        where C: Fn(u32, u32, u32) -> (f32, f32, f32)
+    {
+        let Some((axis, along, far)) = side_plane(face) else {
+            return;
+        };
+        let ds = self.downsample() as i32;
+        let size = self.chunk_size() as i32;
+        let ofs = [ofs.0, ofs.1, ofs.2];
+        let terrain_gen = self.terrain_gen();
+        let depth = self.neighbour_downsamples().iter().fold(ds, |max, d| max.max(*d as i32))/ds;
+
+        let (inner, outer) = if far {(ofs[axis]+size-ds, ofs[axis]+size)} else {(ofs[axis], ofs[axis]-1)};
+        let block_at = |layer: i32, u: i32, y: i32| {
+            let mut pos = [0, y, 0];
+            pos[axis] = layer;
+            pos[along] = u;
+            pos
+        };
+
+        for cu in 0..size/ds {
+            let u = ofs[along]+cu*ds;
+            // the top cell of the column as meshed from the height bounds (ignoring the edits and caves)
+            let column = block_at(inner, u, 0);
+            let hb = terrain_gen.opaque_block_height_bound_test(column[0] as f64, column[2] as f64).ceil() as i32;
+            let top = (hb-1-ofs[1]).div_euclid(ds);
+            // lit like the top face it hangs from, which surrounds the cracks
+            let level = light.level(block_at(inner, u, ofs[1]+(top+1)*ds));
+
+            for cy in (top-depth+1..=top).filter(|cy| (0..size/ds).contains(cy)) {
+                let y = ofs[1]+cy*ds;
+                let cell = block_at(inner, u, y);
+                if !self.solid_at(cell, ds as u32) {
+                    continue;
+                }
+                let hidden = (0..ds).flat_map(|du| (0..ds).map(move |dy| (du, dy))).all(|(du, dy)| {
+                    let outside = block_at(outer, u+du, y+dy);
+                    self.neighbour_downsamples().iter().copied().chain([ds as u32]).all(|d| self.solid_at(outside, d))
+                });
+                if !hidden {
+                    continue;
+                }
+
+                let block = terrain_gen.get_block(cell[0] as f64, cell[1] as f64, cell[2] as f64).unwrap();
+                let rel = [0, 1, 2].map(|k| (cell[k]-ofs[k]) as u32);
+                let (mut face_verts, mut face_inds) = self.gen_scaled_face(
+                    chunk_pos(rel[0], rel[1], rel[2]), verts.len() as u32, face, &self.block_ind(block.0 as usize), ds as f32, None,
+                );
+                set_light(&mut face_verts, level);
+                verts.append(&mut face_verts);
+                inds.append(&mut face_inds);
+            }
+        }
+    }
+
+    // same faces as the voluminous mesh, with the coplanar adjacent faces of the same texture merged into larger quads
+    fn greedy_opaque_cubes_mesh<C>(&self, ofs: (i32, i32, i32), chunk_pos: C, light: &LightVolume) -> [(Vec<ChunkVertex>, Vec<u32>, FaceDir); 6]
+        where C: Fn(u32, u32, u32) -> (f32, f32, f32)
+    {
+        let mesh = self.voluminous_opaque_cubes_mesh(ofs, chunk_pos, light);
+        // textures can't repeat across a merged face within an atlas
+        if self.texture_id_mapper().is_atlas() {
+            return mesh;
+        }
+        mesh.map(|(verts, inds, face)| {
+            let (verts, inds) = greedy_merge_faces(&verts, &inds, face);
+            (verts, inds, face)
+        })
+    }
+
+    fn sparse_transparent_floral_mesh<C>(&self, ofs: (i32, i32, i32), chunk_pos: C, light: &LightVolume) -> (Vec<ChunkVertex>, Vec<u32>)
         where C: Fn(u32, u32, u32) -> (f32, f32, f32)
     {
         let mut transparent_verts = vec![];
@@ -227,15 +492,17 @@ pub(super) trait ChunkMeshUtil<'b> {
                         if let Some(block)
                             = self.terrain_gen().get_block((ofs.0+x as i32) as f64, (y as i32) as f64, (ofs.2+z as i32) as f64)
                         {
-                            // assumes floral mesh
-
                             let block = self.block_ind(block.0 as usize);
-
-                            let txtr = block.texture_id;
+                            // a block could have been placed there instead
+                            if !matches!(block.mesh, MeshType::XCross) {
+                                continue;
+                            }
 
                             let (mut xcross_verts, mut xcross_inds) = self.gen_xcross(
-                                chunk_pos(x, (y as i32-ofs.1) as u32, z), transparent_faces*4, txtr,
+                                chunk_pos(x, (y as i32-ofs.1) as u32, z), transparent_faces*4, &block,
                             );
+                            // lit by the block it is in
+                            set_light(&mut xcross_verts, light.level([ofs.0+x as i32, y as i32, ofs.2+z as i32]));
                             transparent_verts.append(&mut xcross_verts);
                             transparent_inds.append(&mut xcross_inds);
                             transparent_faces += 2;
@@ -248,9 +515,51 @@ pub(super) trait ChunkMeshUtil<'b> {
         (transparent_verts, transparent_inds)
     }
 
+    // slabs and stairs can only be placed, so only the placed blocks of the chunk are checked
+    // the shaped blocks are assumed opaque, while not being opaque cubes that hide the faces of their neighbors
+    fn sparse_shaped_blocks_mesh<C>(&self, ofs: (i32, i32, i32), chunk_pos: C, light: &LightVolume) -> (Vec<ChunkVertex>, Vec<u32>)
+        where C: Fn(u32, u32, u32) -> (f32, f32, f32)
+    {
+        let mut shaped_verts = vec![];
+        let mut shaped_inds = vec![];
+
+        let max_ofs = self.chunk_size() as i32-1;
+        let placed = self.terrain_gen().placed_within([ofs.0, ofs.1, ofs.2], [ofs.0+max_ofs, ofs.1+max_ofs, ofs.2+max_ofs]);
+        for (pos, block) in placed {
+            let block = self.block_ind(block.0 as usize);
+
+            // faces on the border of the block are hidden by the adjacent opaque cubes
+            let culled = |face: FaceDir| {
+                let (dx, dy, dz) = match face {
+                    FaceDir::FRONT => (0, 0, 1),
+                    FaceDir::BACK => (0, 0,-1),
+                    FaceDir::RIGHT => (1, 0, 0),
+                    FaceDir::LEFT => (-1, 0, 0),
+                    FaceDir::TOP => (0, 1, 0),
+                    FaceDir::BOTTOM => (0,-1, 0),
+                };
+                self.terrain_gen().get_block((pos[0]+dx) as f64, (pos[1]+dy) as f64, (pos[2]+dz) as f64)
+                    .is_some_and(|block| self.opaque_cube(block))
+            };
+            let loc = chunk_pos((pos[0]-ofs.0) as u32, (pos[1]-ofs.1) as u32, (pos[2]-ofs.2) as u32);
+            let ind_ofs = shaped_verts.len() as u32;
+            let (mut verts, mut inds) = match block.mesh {
+                MeshType::Slab(top) => self.gen_slab(loc, ind_ofs, &block, top, &culled),
+                MeshType::Stairs(dir) => self.gen_stairs(loc, ind_ofs, &block, dir, &culled),
+                _ => continue,
+            };
+            // the shaped blocks let the light through, so their faces are lit by the block they are in
+            set_light(&mut verts, light.level(pos));
+            shaped_verts.append(&mut verts);
+            shaped_inds.append(&mut inds);
+        }
+
+        (shaped_verts, shaped_inds)
+    }
+
 
     // TODO: TEMPORARY
-    fn temporary_fluid_mesher<C>(&self, ofs: (i32, i32, i32), chunk_pos: C) -> (Vec<ChunkVertex>, Vec<u32>)
+    fn temporary_fluid_mesher<C>(&self, ofs: (i32, i32, i32), chunk_pos: C, light: &LightVolume) -> (Vec<ChunkVertex>, Vec<u32>)
         where C: Fn(u32, u32, u32) -> (f32, f32, f32)
     {
         let mut translucent_verts = vec![];
@@ -304,14 +613,16 @@ pub(super) trait ChunkMeshUtil<'b> {
                         | {
                             if let Some(block) = block {
                                 let block = self.block_ind(block.0 as usize);
-                                let txtr = block.texture_id;
 
                                 // mesh assumed to be (translucent) fluid
 
                                 let (mut verts, mut inds) = self.gen_face(
                                     chunk_pos((x as i32+dx) as u32,(y as i32+dy) as u32,(z as i32+dz) as u32),
-                                    *total_faces*4, face_dir, txtr, false
+                                    *total_faces*4, face_dir, &block, MeshType::Fluid, None
                                 );
+                                set_light(&mut verts, light.face_level([
+                                    ofs.0+x as i32+dx, ofs.1+y as i32+dy, ofs.2+z as i32+dz,
+                                ], face_dir));
                                 total_verts.append(&mut verts);
                                 total_inds.append(&mut inds);
                                 *total_faces += 1;
@@ -350,26 +661,33 @@ pub(super) trait ChunkMeshUtil<'b> {
         (translucent_verts, translucent_inds)
     }
 
-    fn gen_face(&self, loc: (f32, f32, f32), ind_ofs: u32, face: FaceDir, txtr_mapping: TextureMapper, fluid: bool) -> (Vec<ChunkVertex>, Vec<u32>) {
-        let txtr_mapper = |name: &str| *self.texture_id_mapper().get(name).unwrap_or(&0) as f32;
+    // faces are wound outward, or on both sides depending on the block
+    // the ambient occlusion of each vertex is from whether the blocks (relative to the block of the face) occlude it
+    fn gen_face(&self, loc: (f32, f32, f32), ind_ofs: u32, face: FaceDir, block: &BlockData, mesh: MeshType,
+                occluded: Option<&dyn Fn(i32, i32, i32) -> bool>) -> (Vec<ChunkVertex>, Vec<u32>) {
+        let txtr_ids = self.texture_id_mapper();
+        let txtr_mapper = |name: &str| txtr_ids.index(name) as f32;
+        let txtr_mapping = block.texture_id.at(loc);
+        let anim = TextureAnimation::attribute(block.animation);
 
         // TODO: encode indent height into the shader itself
-        let hgt = if fluid {
+        let hgt = if let MeshType::Fluid = mesh {
             0.9
         } else {
             1.0
         };
 
+        let normal = face.normal();
         let (v, i) = match face {
             FaceDir::FRONT => {
                 let txtr = txtr_mapper(txtr_mapping.front());
 
                 (
                     vec![
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2+0.0], uv: [0.0, 0.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [0.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 0.0], txtr },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2+0.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
                     ],
                     vec![0,1,2,3,1,0]
                 )
@@ -379,10 +697,10 @@ pub(super) trait ChunkMeshUtil<'b> {
 
                 (
                     vec![
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 0.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
                     ],
                     vec![0,2,1,3,1,2]
                 )}
@@ -391,10 +709,10 @@ pub(super) trait ChunkMeshUtil<'b> {
 
                 (
                     vec![
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [1.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2-1.0], uv: [1.0, 0.0], txtr },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2-1.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
                     ],
                     vec![1,0,3,2,3,0]
                 )}
@@ -403,10 +721,10 @@ pub(super) trait ChunkMeshUtil<'b> {
 
                 (
                     vec![
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 0.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
                     ],
                     vec![2,0,3,1,3,0]
                 )}
@@ -415,10 +733,10 @@ pub(super) trait ChunkMeshUtil<'b> {
 
                 (
                     vec![
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2+0.0], uv: [0.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2-1.0], uv: [1.0, 0.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2+0.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2+0.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+hgt, -loc.2-1.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+hgt, -loc.2-1.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
                     ],
                     vec![0,1,2,3,2,1]
                 )}
@@ -427,47 +745,1031 @@ pub(super) trait ChunkMeshUtil<'b> {
 
                 (
                     vec![
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [0.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr },
-                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 0.0], txtr },
-                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [1.0, 0.0], txtr },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
+                        ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal },
                     ],
                     vec![1,0,3,2,3,0]
                 )}
         };
-        let i = i.into_iter()
+        // fluids are not occluded
+        let occluded = occluded.filter(|_| matches!(mesh, MeshType::Cube));
+        let v = v.into_iter()
+            .map(|vert| ChunkVertex {
+                uv: txtr_ids.uv(vert.txtr as u32, vert.uv),
+                ao: occluded.map_or(0.0, |occluded| vertex_occlusion(loc, vert.pos, face, occluded)),
+                ..vert
+            })
+            .collect();
+        let i = block.winding().apply(i).into_iter()
             .map(|ind| ind+ind_ofs)
             .collect();
 
         (v,i)
     }
 
-    fn gen_xcross(&self, loc: (f32, f32, f32), ind_ofs: u32, txtr_mapping: TextureMapper) -> (Vec<ChunkVertex>, Vec<u32>) {
-        let txtr_mapper = |name: &str| *self.texture_id_mapper().get(name).unwrap_or(&0) as f32;
-        let txtr = txtr_mapper(txtr_mapping.default());
+    // cube face spanning `scale` blocks for each axis, used by the downsampled meshes
+    fn gen_scaled_face(&self, loc: (f32, f32, f32), ind_ofs: u32, face: FaceDir, block: &BlockData, scale: f32,
+                       occluded: Option<&dyn Fn(i32, i32, i32) -> bool>) -> (Vec<ChunkVertex>, Vec<u32>) {
+        let (mut v, i) = self.gen_face(loc, ind_ofs, face, block, MeshType::Cube, occluded);
+        if scale != 1.0 {
+            // the min corner of the block, which spans from -loc.2-1 to -loc.2 along z
+            let anchor = [loc.0, loc.1, -loc.2-1.0];
+            for vert in v.iter_mut() {
+                for (pos, anchor) in vert.pos.iter_mut().zip(anchor) {
+                    *pos = anchor+(*pos-anchor)*scale;
+                }
+            }
+        }
+        (v, i)
+    }
 
+    fn gen_xcross(&self, loc: (f32, f32, f32), ind_ofs: u32, block: &BlockData) -> (Vec<ChunkVertex>, Vec<u32>) {
+        let txtr_ids = self.texture_id_mapper();
+        let txtr = txtr_ids.index(block.texture_id.at(loc).default()) as f32;
+        let anim = TextureAnimation::attribute(block.animation);
+
+        // outward from the counter-clockwise side of each diagonal quad
+        let diag = 0.5f32.sqrt();
+        let [n0, n1] = [[-diag, 0.0, -diag], [-diag, 0.0, diag]];
         let v = [
             // -x +z to +x -z
-            ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr },
-            ChunkVertex { pos: [loc.0+0.0, loc.1+1.0, -loc.2+0.0], uv: [1.0, 0.0], txtr },
-            ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr },
-            ChunkVertex { pos: [loc.0+1.0, loc.1+1.0, -loc.2-1.0], uv: [0.0, 0.0], txtr },
+            ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal: n0 },
+            ChunkVertex { pos: [loc.0+0.0, loc.1+1.0, -loc.2+0.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal: n0 },
+            ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal: n0 },
+            ChunkVertex { pos: [loc.0+1.0, loc.1+1.0, -loc.2-1.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal: n0 },
 
             // +x +z to -x -z
-            ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr },
-            ChunkVertex { pos: [loc.0+1.0, loc.1+1.0, -loc.2+0.0], uv: [1.0, 0.0], txtr },
-            ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr },
-            ChunkVertex { pos: [loc.0+0.0, loc.1+1.0, -loc.2-1.0], uv: [0.0, 0.0], txtr },
+            ChunkVertex { pos: [loc.0+1.0, loc.1+0.0, -loc.2+0.0], uv: [1.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal: n1 },
+            ChunkVertex { pos: [loc.0+1.0, loc.1+1.0, -loc.2+0.0], uv: [1.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal: n1 },
+            ChunkVertex { pos: [loc.0+0.0, loc.1+0.0, -loc.2-1.0], uv: [0.0, 1.0], txtr, anim, ao: 0.0, light: 1.0, normal: n1 },
+            ChunkVertex { pos: [loc.0+0.0, loc.1+1.0, -loc.2-1.0], uv: [0.0, 0.0], txtr, anim, ao: 0.0, light: 1.0, normal: n1 },
         ];
-        let i = [
+        let i = vec![
             0,1,2,2,1,3,
             4,5,6,6,5,7,
         ];
 
-        let i = i.into_iter()
+        let i = block.winding().apply(i).into_iter()
             .map(|ind| ind+ind_ofs)
             .collect();
+        let v = v.map(|vert| ChunkVertex { uv: txtr_ids.uv(vert.txtr as u32, vert.uv), ..vert });
         (v.to_vec(),i)
     }
+
+    // faces of the boxes within the block, each box spanning from the min to the max corner along x, y and the depth
+    // from the front face (all within 0 to 1), the faces on the border of the block are skipped when culled
+    fn gen_box_faces(&self, loc: (f32, f32, f32), ind_ofs: u32, block: &BlockData, faces: &[(FaceDir, [f32; 3], [f32; 3])],
+                     culled: &dyn Fn(FaceDir) -> bool) -> (Vec<ChunkVertex>, Vec<u32>) {
+        let txtr_ids = self.texture_id_mapper();
+        let anchor = [loc.0, loc.1, -loc.2];
+
+        let mut verts = vec![];
+        let mut inds = vec![];
+        for (face, min, max) in faces.iter().copied() {
+            let border = match face {
+                FaceDir::FRONT => min[2] == 0.0,
+                FaceDir::BACK => max[2] == 1.0,
+                FaceDir::RIGHT => max[0] == 1.0,
+                FaceDir::LEFT => min[0] == 0.0,
+                FaceDir::TOP => max[1] == 1.0,
+                FaceDir::BOTTOM => min[1] == 0.0,
+            };
+            if border && culled(face) {
+                continue;
+            }
+
+            // shrinks the cube face onto the box, cropping the texture instead of squashing it
+            let (v, mut i) = self.gen_face(loc, ind_ofs+verts.len() as u32, face, block, MeshType::Cube, None);
+            for vert in v {
+                let unit = [vert.pos[0]-anchor[0], vert.pos[1]-anchor[1], anchor[2]-vert.pos[2]];
+                let [x, y, d] = [0, 1, 2].map(|k| min[k]+unit[k]*(max[k]-min[k]));
+                let uv = match face {
+                    FaceDir::FRONT | FaceDir::BACK => [x, 1.0-y],
+                    FaceDir::RIGHT | FaceDir::LEFT => [1.0-d, 1.0-y],
+                    FaceDir::TOP => [1.0-x, 1.0-d],
+                    FaceDir::BOTTOM => [x, 1.0-d],
+                };
+                verts.push(ChunkVertex {
+                    pos: [anchor[0]+x, anchor[1]+y, anchor[2]-d],
+                    uv: txtr_ids.uv(vert.txtr as u32, uv),
+                    ..vert
+                });
+            }
+            inds.append(&mut i);
+        }
+
+        (verts, inds)
+    }
+
+    fn gen_slab(&self, loc: (f32, f32, f32), ind_ofs: u32, block: &BlockData, top: bool,
+                culled: &dyn Fn(FaceDir) -> bool) -> (Vec<ChunkVertex>, Vec<u32>) {
+        let (min, max) = if top {
+            ([0.0, 0.5, 0.0], [1.0, 1.0, 1.0])
+        } else {
+            ([0.0, 0.0, 0.0], [1.0, 0.5, 1.0])
+        };
+        let faces = [FaceDir::FRONT, FaceDir::RIGHT, FaceDir::BACK, FaceDir::LEFT, FaceDir::TOP, FaceDir::BOTTOM]
+            .map(|face| (face, min, max));
+        self.gen_box_faces(loc, ind_ofs, block, &faces, culled)
+    }
+
+    // lower half of the block with the upper half on the ascending side
+    fn gen_stairs(&self, loc: (f32, f32, f32), ind_ofs: u32, block: &BlockData, dir: FaceDir,
+                  culled: &dyn Fn(FaceDir) -> bool) -> (Vec<ChunkVertex>, Vec<u32>) {
+        // footprints (along x and the depth) of the upper half and the step, the faces facing the step, and the sides
+        let (upper, step, riser, sides) = match dir {
+            FaceDir::FRONT => ([0.0, 1.0, 0.0, 0.5], [0.0, 1.0, 0.5, 1.0], FaceDir::BACK, [FaceDir::RIGHT, FaceDir::LEFT]),
+            FaceDir::BACK => ([0.0, 1.0, 0.5, 1.0], [0.0, 1.0, 0.0, 0.5], FaceDir::FRONT, [FaceDir::RIGHT, FaceDir::LEFT]),
+            FaceDir::RIGHT => ([0.5, 1.0, 0.0, 1.0], [0.0, 0.5, 0.0, 1.0], FaceDir::LEFT, [FaceDir::FRONT, FaceDir::BACK]),
+            FaceDir::LEFT => ([0.0, 0.5, 0.0, 1.0], [0.5, 1.0, 0.0, 1.0], FaceDir::RIGHT, [FaceDir::FRONT, FaceDir::BACK]),
+            // no side to ascend towards
+            FaceDir::TOP | FaceDir::BOTTOM => return self.gen_slab(loc, ind_ofs, block, false, culled),
+        };
+        let boxed = |[x0, x1, d0, d1]: [f32; 4], y0: f32, y1: f32| ([x0, y0, d0], [x1, y1, d1]);
+        let (lower_min, lower_max) = boxed([0.0, 1.0, 0.0, 1.0], 0.0, 0.5);
+        let (upper_min, upper_max) = boxed(upper, 0.5, 1.0);
+        let (step_min, step_max) = boxed(step, 0.0, 0.5);
+
+        let faces = [
+            (FaceDir::BOTTOM, lower_min, lower_max),
+            (FaceDir::TOP, step_min, step_max),
+            (FaceDir::TOP, upper_min, upper_max),
+            (dir, [0.0; 3], [1.0; 3]),
+            (riser, lower_min, lower_max),
+            (riser, upper_min, upper_max),
+            (sides[0], lower_min, lower_max),
+            (sides[0], upper_min, upper_max),
+            (sides[1], lower_min, lower_max),
+            (sides[1], upper_min, upper_max),
+        ];
+        self.gen_box_faces(loc, ind_ofs, block, &faces, culled)
+    }
+}
+
+
+// normal and in-plane horizontal axes of the side plane of the chunk the face lies on, and whether the plane is at the
+// far side of the chunk
+fn side_plane(face: FaceDir) -> Option<(usize, usize, bool)> {
+    match face {
+        FaceDir::RIGHT => Some((0, 2, true)),
+        FaceDir::LEFT => Some((0, 2, false)),
+        FaceDir::FRONT => Some((2, 0, true)),
+        FaceDir::BACK => Some((2, 0, false)),
+        FaceDir::TOP | FaceDir::BOTTOM => None,
+    }
+}
+
+// number of the 2 side and 1 corner blocks in front of the face that occludes the vertex, where both sides
+// fully occlude the vertex regardless of the corner
+fn vertex_occlusion(loc: (f32, f32, f32), pos: [f32; 3], face: FaceDir, occluded: &dyn Fn(i32, i32, i32) -> bool) -> f32 {
+    let (normal, normal_axis) = match face {
+        FaceDir::FRONT => (1, 2),
+        FaceDir::BACK => (-1, 2),
+        FaceDir::RIGHT => (1, 0),
+        FaceDir::LEFT => (-1, 0),
+        FaceDir::TOP => (1, 1),
+        FaceDir::BOTTOM => (-1, 1),
+    };
+    // direction of the vertex from the center of the block, the chunk vertices have their z axis flipped
+    let mut corner = [
+        if pos[0]-loc.0 > 0.5 {1} else {-1},
+        if pos[1]-loc.1 > 0.5 {1} else {-1},
+        if pos[2]+loc.2 < -0.5 {-1} else {1},
+    ];
+    corner[normal_axis] = normal;
+
+    let side = |axis: usize| {
+        let mut ofs = [0; 3];
+        ofs[normal_axis] = normal;
+        ofs[axis] = corner[axis];
+        occluded(ofs[0], ofs[1], ofs[2])
+    };
+    let (a, b) = ((normal_axis+1)%3, (normal_axis+2)%3);
+    let (side_a, side_b) = (side(a), side(b));
+    if side_a && side_b {
+        3.0
+    } else {
+        (side_a as u8+side_b as u8+occluded(corner[0], corner[1], corner[2]) as u8) as f32
+    }
+}
+
+// merges the adjacent quads (of 4 vertices each, in order) facing the same direction on the same plane, with the same
+// texture, winding and light, into rectangles, the texture coordinates are scaled to keep tiling the texture once per quad
+fn greedy_merge_faces(verts: &[ChunkVertex], inds: &[u32], face: FaceDir) -> (Vec<ChunkVertex>, Vec<u32>) {
+    // the in-plane axes
+    let (a, b) = match face {
+        FaceDir::LEFT | FaceDir::RIGHT => (1, 2),
+        FaceDir::TOP | FaceDir::BOTTOM => (0, 2),
+        FaceDir::FRONT | FaceDir::BACK => (0, 1),
+    };
+    let normal = 3-a-b;
+
+    // triangle indices of each quad, relative to its first vertex
+    let mut quad_inds = vec![vec![]; verts.len()/4];
+    for ind in inds {
+        quad_inds[*ind as usize/4].push(ind%4);
+    }
+
+    let min = |quad: &[ChunkVertex], axis: usize| quad.iter().map(|v| v.pos[axis]).fold(f32::MAX, f32::min);
+    let max = |quad: &[ChunkVertex], axis: usize| quad.iter().map(|v| v.pos[axis]).fold(f32::MIN, f32::max);
+
+    // quads that can be merged, by their cell in the plane
+    let mut group_keys = HashMap::new();
+    let mut groups: Vec<HashMap<(i32, i32), usize>> = vec![];
+    for (q, quad) in verts.chunks_exact(4).enumerate() {
+        let size = (max(quad, a)-min(quad, a), max(quad, b)-min(quad, b));
+        let key = (
+            quad[0].pos[normal].to_bits(), size.0.to_bits(), size.1.to_bits(),
+            quad[0].txtr.to_bits(), quad[0].anim.map(f32::to_bits), quad_inds[q].clone(),
+            // unevenly occluded quads are left as is
+            quad.iter().all(|v| v.ao == quad[0].ao).then_some(quad[0].ao.to_bits()).ok_or(q),
+            quad[0].light.to_bits(),
+        );
+        let group = *group_keys.entry(key).or_insert_with(|| {
+            groups.push(HashMap::new());
+            groups.len()-1
+        });
+        let cell = ((min(quad, a)/size.0).round() as i32, (min(quad, b)/size.1).round() as i32);
+        groups[group].insert(cell, q);
+    }
+
+    let mut merged_verts = vec![];
+    let mut merged_inds = vec![];
+    for group in groups {
+        let mut cells: Vec<(i32, i32)> = group.keys().copied().collect();
+        cells.sort_by_key(|(ca, cb)| (*cb, *ca));
+
+        let mut merged = HashSet::new();
+        for (ca, cb) in cells {
+            if merged.contains(&(ca, cb)) {
+                continue;
+            }
+            let free = |merged: &HashSet<(i32, i32)>, cell: (i32, i32)| group.contains_key(&cell) && !merged.contains(&cell);
+            let mut w = 1;
+            while free(&merged, (ca+w, cb)) {
+                w += 1;
+            }
+            let mut h = 1;
+            while (ca..ca+w).all(|x| free(&merged, (x, cb+h))) {
+                h += 1;
+            }
+            for x in ca..ca+w {
+                for y in cb..cb+h {
+                    merged.insert((x, y));
+                }
+            }
+
+            // stretches the vertices on the far sides of the first quad across the rectangle
+            let q = group[&(ca, cb)];
+            let quad = &verts[q*4..q*4+4];
+            let (min_a, min_b) = (min(quad, a), min(quad, b));
+            let size = (max(quad, a)-min_a, max(quad, b)-min_b);
+            let far = |v: &ChunkVertex| (v.pos[a] > min_a+size.0/2.0, v.pos[b] > min_b+size.1/2.0);
+            // whether each texture coordinate runs along the first in-plane axis
+            let uv_along_a = [0, 1].map(|c| {
+                quad.iter().any(|v| quad.iter().any(|u| far(u).1 == far(v).1 && far(u).0 != far(v).0 && u.uv[c] != v.uv[c]))
+            });
+
+            let ind_ofs = merged_verts.len() as u32;
+            for v in quad {
+                let mut v = *v;
+                let (far_a, far_b) = far(&v);
+                if far_a {
+                    v.pos[a] += (w-1) as f32*size.0;
+                }
+                if far_b {
+                    v.pos[b] += (h-1) as f32*size.1;
+                }
+                for (uv, along_a) in v.uv.iter_mut().zip(uv_along_a) {
+                    *uv *= if along_a {w} else {h} as f32;
+                }
+                merged_verts.push(v);
+            }
+            merged_inds.extend(quad_inds[q].iter().map(|ind| ind+ind_ofs));
+        }
+    }
+
+    (merged_verts, merged_inds)
+}
+
+// vertices and indices appended to the aggregated mesh since the start (vertex and index count) as the range of a
+// chunk, if any
+pub(super) fn chunk_range(verts: &[ChunkVertex], inds: &[u32], start: (usize, u32), mesh_id: u64) -> Option<ChunkRange> {
+    let (vert_start, ind_start) = start;
+    if inds.len() as u32 == ind_start {
+        return None;
+    }
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vert in &verts[vert_start..] {
+        for k in 0..3 {
+            min[k] = min[k].min(vert.pos[k]);
+            max[k] = max[k].max(vert.pos[k]);
+        }
+    }
+    Some(ChunkRange {
+        mesh_id, verts: vert_start as u32..verts.len() as u32, inds: ind_start..inds.len() as u32, min, max
+    })
+}
+
+// indices of the aggregated translucent mesh ordered back to front from the eye, the ranges are of each chunk
+// within the indices as aggregated
+pub(super) fn sort_translucent(verts: &[ChunkVertex], inds: &[u32], ranges: &[ChunkRange], eye: [f32; 3], sort: TranslucentSort)
+    -> Vec<u32>
+{
+    let dist2 = |p: [f32; 3]| (p[0]-eye[0]).powi(2)+(p[1]-eye[1]).powi(2)+(p[2]-eye[2]).powi(2);
+
+    match sort {
+        TranslucentSort::Unsorted => inds.to_vec(),
+        TranslucentSort::PerChunk => {
+            let center = |range: &ChunkRange| [0, 1, 2].map(|k| (range.min[k]+range.max[k])/2.0);
+            // farthest first, chunks at the same distance keep their order
+            let mut ranges: Vec<&ChunkRange> = ranges.iter().collect();
+            ranges.sort_by(|a, b| dist2(center(b)).total_cmp(&dist2(center(a))));
+            ranges.into_iter()
+                .flat_map(|range| inds[range.inds.start as usize..range.inds.end as usize].iter().copied())
+                .collect()
+        }
+        TranslucentSort::PerFace => {
+            let centroid = |tri: &[u32]| {
+                let mut c = [0.0; 3];
+                for ind in tri {
+                    for (coord, pos) in c.iter_mut().zip(verts[*ind as usize].pos) {
+                        *coord += pos/3.0;
+                    }
+                }
+                c
+            };
+            let mut tris: Vec<(f32, &[u32])> = inds.chunks(3).map(|tri| (dist2(centroid(tri)), tri)).collect();
+            tris.sort_by(|a, b| b.0.total_cmp(&a.0));
+            tris.into_iter().flat_map(|(_, tri)| tri.iter().copied()).collect()
+        }
+    }
 }
 
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use crate::component::terrain::{Block, BlockData, FaceWinding, SoundGroup, TextureMapper, TransparencyType};
+    use uom::si::f32::Length;
+    use crate::component::RenderDataPurpose;
+    use crate::component::camera::Length3D;
+    use crate::component::terrain::chunk_gen::ChunkGeneratorEF;
+    use crate::component::terrain::chunk_gen_hf::ChunkGeneratorHF;
+    use crate::component::terrain::chunk_gen_mf::ChunkGeneratorMF;
+    use crate::component::terrain::chunk_mesh::ChunkGeneratable;
+    use crate::measurement::blox;
+    use crate::component::texture::TextureIDs;
+    use super::*;
+
+    fn block(ident: &'static str, mesh: MeshType, transparency: TransparencyType, sound_group: SoundGroup) -> BlockData<'static> {
+        let back_face_culling = matches!(mesh, MeshType::Empty | MeshType::Cube | MeshType::Slab(_) | MeshType::Stairs(_));
+        BlockData { ident, texture_id: TextureMapper::All(ident), mesh, transparency, sound_group, back_face_culling, animation: None }
+    }
+
+    pub(crate) fn test_block_ind() -> Vec<BlockData<'static>> {
+        vec![
+            block("grass_block", MeshType::Cube, TransparencyType::Opaque, SoundGroup::Grass),
+            block("dirt", MeshType::Cube, TransparencyType::Opaque, SoundGroup::Dirt),
+            block("stone", MeshType::Cube, TransparencyType::Opaque, SoundGroup::Stone),
+            block("sand", MeshType::Cube, TransparencyType::Opaque, SoundGroup::Sand),
+            block("grass", MeshType::XCross, TransparencyType::Transparent, SoundGroup::Grass),
+            block("flower", MeshType::XCross, TransparencyType::Transparent, SoundGroup::Grass),
+            block("water", MeshType::Fluid, TransparencyType::Translucent, SoundGroup::Water),
+            block("air", MeshType::Empty, TransparencyType::Transparent, SoundGroup::Silent),
+            block("stone_slab", MeshType::Slab(false), TransparencyType::Opaque, SoundGroup::Stone),
+            block("stone_stairs", MeshType::Stairs(FaceDir::FRONT), TransparencyType::Opaque, SoundGroup::Stone),
+            block("sandstone", MeshType::Cube, TransparencyType::Opaque, SoundGroup::Stone),
+        ]
+    }
+
+    // the generator of 32 block chunks of the test blocks, without any downsampling
+    pub(crate) fn test_generator(txtr_ids: TextureIDs, terrain_gen: impl Into<Arc<TerrainGenerator>>) -> ChunkGeneratorEF<'static> {
+        ChunkGeneratorEF::new(test_block_ind(), Arc::new(txtr_ids), terrain_gen.into(), 32, 1, vec![])
+    }
+
+    fn face_count(mesh: &[(Vec<ChunkVertex>, Vec<u32>, FaceDir)]) -> usize {
+        mesh.iter().map(|(_, inds, _)| inds.len()/6).sum()
+    }
+
+    fn triangle_normals(verts: &[ChunkVertex], inds: &[u32]) -> Vec<([f32; 3], [f32; 3])> {
+        inds.chunks(3).map(|tri| {
+            let [a, b, c] = [0, 1, 2].map(|k| verts[tri[k] as usize].pos);
+            let e1 = [b[0]-a[0], b[1]-a[1], b[2]-a[2]];
+            let e2 = [c[0]-a[0], c[1]-a[1], c[2]-a[2]];
+            let normal = [e1[1]*e2[2]-e1[2]*e2[1], e1[2]*e2[0]-e1[0]*e2[2], e1[0]*e2[1]-e1[1]*e2[0]];
+            (a, normal)
+        }).collect()
+    }
+
+    // number of triangles left after back face culling when viewed from `eye`
+    fn visible_triangles(verts: &[ChunkVertex], inds: &[u32], eye: [f32; 3]) -> usize {
+        triangle_normals(verts, inds).into_iter()
+            .filter(|(a, n)| n[0]*(eye[0]-a[0])+n[1]*(eye[1]-a[1])+n[2]*(eye[2]-a[2]) > 0.0)
+            .count()
+    }
+
+    #[test]
+    fn test_cube_faces_wound_outward() {
+        let generator = test_generator(TextureIDs::default(), TerrainGenerator::new(0, 20.0, 1.0, false));
+        let faces = [
+            (FaceDir::FRONT, [0.0, 0.0, 1.0]),
+            (FaceDir::RIGHT, [1.0, 0.0, 0.0]),
+            (FaceDir::BACK, [0.0, 0.0, -1.0]),
+            (FaceDir::LEFT, [-1.0, 0.0, 0.0]),
+            (FaceDir::TOP, [0.0, 1.0, 0.0]),
+            (FaceDir::BOTTOM, [0.0, -1.0, 0.0]),
+        ];
+        for (face, expected) in faces {
+            let (verts, inds) = generator.gen_face((0.0, 0.0, 0.0), 0, face, &test_block_ind()[2], MeshType::Cube, None);
+            assert_eq!(inds.len(), 6, "{face:?}");
+            for (_, normal) in triangle_normals(&verts, &inds) {
+                assert_eq!(normal, expected, "{face:?}");
+            }
+            // the shaded normal agrees with the winding
+            assert!(verts.iter().all(|vert| vert.normal == expected), "{face:?}");
+        }
+    }
+
+    #[test]
+    fn test_xcross_normals_follow_winding() {
+        let generator = test_generator(TextureIDs::default(), TerrainGenerator::new(0, 20.0, 1.0, false));
+        let (verts, inds) = generator.gen_xcross((0.0, 0.0, 0.0), 0, &test_block_ind()[4]);
+        // the counter-clockwise triangles come first, whether or not the reversed ones follow
+        for (tri, (_, normal)) in inds.chunks(3).zip(triangle_normals(&verts, &inds)).take(4) {
+            let shaded = verts[tri[0] as usize].normal;
+            let len = normal.iter().map(|c| c*c).sum::<f32>().sqrt();
+            for axis in 0..3 {
+                assert!((shaded[axis]-normal[axis]/len).abs() < 1e-6, "{shaded:?} {normal:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_vertex_occlusion_of_top_face() {
+        let generator = test_generator(TextureIDs::default(), TerrainGenerator::new(0, 20.0, 1.0, false));
+        let ao = |occluded: &dyn Fn(i32, i32, i32) -> bool| {
+            let (verts, _) = generator.gen_face((0.0, 0.0, 0.0), 0, FaceDir::TOP, &test_block_ind()[2], MeshType::Cube, Some(occluded));
+            // by the corner of each vertex along x and z
+            verts.iter().map(|v| ((v.pos[0] > 0.5, v.pos[2] > -0.5), v.ao)).collect::<HashMap<_, _>>()
+        };
+
+        assert!(ao(&|_, _, _| false).values().all(|ao| *ao == 0.0));
+        // a block above the +x side
+        let ledge = ao(&|x, y, _| x == 1 && y == 1);
+        assert_eq!(ledge[&(true, true)], 2.0);
+        assert_eq!(ledge[&(true, false)], 2.0);
+        assert_eq!(ledge[&(false, true)], 0.0);
+        // blocks above the +x and +z sides, the +x +z vertex is fully occluded without the corner block
+        let inner_corner = ao(&|x, y, z| y == 1 && (x, z) != (1, 1) && (x == 1 || z == 1));
+        assert_eq!(inner_corner[&(true, true)], 3.0);
+        assert_eq!(inner_corner[&(false, false)], 0.0);
+
+        // fluids are not occluded
+        let (verts, _) = generator.gen_face((0.0, 0.0, 0.0), 0, FaceDir::TOP, &test_block_ind()[6], MeshType::Fluid, Some(&|_, _, _| true));
+        assert!(verts.iter().all(|v| v.ao == 0.0));
+    }
+
+    #[test]
+    fn test_fluid_top_face_faces_up() {
+        let generator = test_generator(TextureIDs::default(), TerrainGenerator::new(0, 20.0, 1.0, false));
+        let (verts, inds) = generator.gen_face((0.0, 0.0, 0.0), 0, FaceDir::TOP, &test_block_ind()[6], MeshType::Fluid, None);
+
+        // the outward triangles come first and face +Y
+        let normals = triangle_normals(&verts, &inds);
+        for (_, normal) in &normals[..2] {
+            assert_eq!(*normal, [0.0, 1.0, 0.0]);
+        }
+
+        // visible from above, and from underwater
+        assert_eq!(visible_triangles(&verts, &inds, [0.5, 10.0, -0.5]), 2);
+        assert_eq!(visible_triangles(&verts, &inds, [0.5, -10.0, -0.5]), 2);
+
+        // while an opaque top face is culled from below
+        let (verts, inds) = generator.gen_face((0.0, 0.0, 0.0), 0, FaceDir::TOP, &test_block_ind()[2], MeshType::Cube, None);
+        assert_eq!(visible_triangles(&verts, &inds, [0.5, 10.0, -0.5]), 2);
+        assert_eq!(visible_triangles(&verts, &inds, [0.5, -10.0, -0.5]), 0);
+    }
+
+    #[test]
+    fn test_translucent_back_face_culling_per_block() {
+        let generator = test_generator(TextureIDs::default(), TerrainGenerator::new(0, 20.0, 1.0, false));
+        let ice = BlockData {
+            ident: "ice", texture_id: TextureMapper::All("ice"), mesh: MeshType::Cube,
+            transparency: TransparencyType::Translucent, sound_group: SoundGroup::Stone, back_face_culling: true, animation: None,
+        };
+        let water = test_block_ind()[6];
+        assert_eq!(ice.winding(), FaceWinding::Outward);
+        assert_eq!(water.winding(), FaceWinding::DoubleSided);
+
+        // both translucent, yet only the water surface is seen from below
+        let (verts, inds) = generator.gen_face((0.0, 0.0, 0.0), 0, FaceDir::TOP, &ice, ice.mesh, None);
+        assert_eq!(visible_triangles(&verts, &inds, [0.5, -10.0, -0.5]), 0);
+        let (verts, inds) = generator.gen_face((0.0, 0.0, 0.0), 0, FaceDir::TOP, &water, water.mesh, None);
+        assert_eq!(visible_triangles(&verts, &inds, [0.5, -10.0, -0.5]), 2);
+    }
+
+    #[test]
+    fn test_single_bottom_slab() {
+        let generator = test_generator(TextureIDs::default(), TerrainGenerator::new(0, 20.0, 1.0, false));
+        let (verts, inds) = generator.gen_slab((0.0, 0.0, 0.0), 0, &test_block_ind()[8], false, &|_| false);
+
+        // a whole box, leaving the upper half empty
+        assert_eq!(verts.len(), 24);
+        assert_eq!(inds.len(), 36);
+        assert!(verts.iter().all(|v| (0.0..=0.5).contains(&v.pos[1])));
+        // with its top face visible from above
+        assert_eq!(visible_triangles(&verts, &inds, [0.5, 10.0, -0.5]), 2);
+        // the front face shows the lower half of the texture
+        assert!(verts[..4].iter().all(|v| v.uv[1] >= 0.5));
+    }
+
+    #[test]
+    fn test_placed_shaped_blocks_culling() {
+        let terrain_gen = Arc::new(TerrainGenerator::new(0, 12.0, 0.0, false));
+        let generator = test_generator(TextureIDs::default(), terrain_gen.clone());
+        let chunk_pos = |x: u32, y: u32, z: u32| (x as f32, y as f32, -(z as f32));
+        let surface = terrain_gen.opaque_block_height_bound_test(0.0, 0.0).ceil() as i32;
+
+        // a slab and a stairs block on the ground, both hiding only their bottom face
+        terrain_gen.set_block([4, surface, 4], Some(Block(8)));
+        terrain_gen.set_block([10, surface, 10], Some(Block(9)));
+        let (verts, inds) = generator.sparse_shaped_blocks_mesh((0, 0, 0), chunk_pos, &LightVolume::full());
+        assert_eq!(verts.len(), (5+9)*4);
+        assert_eq!(inds.len(), (5+9)*6);
+        assert_eq!(verts.iter().map(|v| v.pos[1]).fold(f32::MIN, f32::max), surface as f32+1.0);
+
+        // the ground below keeps its top faces
+        let (top_verts, _, _) = &generator.voluminous_opaque_cubes_mesh((0, 0, 0), chunk_pos, &LightVolume::full())[0];
+        assert_eq!(top_verts.len(), 32*32*4);
+    }
+
+    #[test]
+    fn test_sky_light_under_roof() {
+        let terrain_gen = Arc::new(TerrainGenerator::new(0, 12.0, 0.0, false));
+        let generator = test_generator(TextureIDs::default(), terrain_gen.clone());
+        let chunk_pos = |x: u32, y: u32, z: u32| (x as f32, y as f32, -(z as f32));
+        let surface = terrain_gen.opaque_block_height_bound_test(0.0, 0.0).ceil() as i32;
+
+        // nothing shades the ground
+        assert_eq!(generator.sky_light((0, 0, 0)).level([16, surface, 16]), MAX_LIGHT);
+
+        // a stone roof over the middle of the chunk, a few blocks above the ground
+        for x in 8..24 {
+            for z in 8..24 {
+                terrain_gen.set_block([x, surface+3, z], Some(Block(2)));
+            }
+        }
+        let light = generator.sky_light((0, 0, 0));
+        let (top_verts, _, _) = &generator.voluminous_opaque_cubes_mesh((0, 0, 0), chunk_pos, &light)[0];
+
+        // light of the top face of the ground, by the block along x and z (the vertices have their z axis flipped)
+        let ground_light: HashMap<(i32, i32), f32> = top_verts.chunks_exact(4)
+            .filter(|quad| quad[0].pos[1] == surface as f32)
+            .map(|quad| {
+                let x = quad.iter().map(|v| v.pos[0]).fold(f32::MAX, f32::min) as i32;
+                let z = quad.iter().map(|v| v.pos[2]).fold(f32::MIN, f32::max) as i32;
+                ((x, z), quad[0].light)
+            })
+            .collect();
+        assert_eq!(ground_light[&(0, 0)], 1.0);
+        assert_eq!(ground_light[&(8, 15)], (MAX_LIGHT-1) as f32/MAX_LIGHT as f32);
+        // 8 blocks from the nearest edge of the roof
+        assert_eq!(ground_light[&(15, 15)], (MAX_LIGHT-8) as f32/MAX_LIGHT as f32);
+    }
+
+    #[test]
+    fn test_sky_light_dark_caves() {
+        // chunk buried well below the flat surface
+        let terrain_gen = Arc::new(TerrainGenerator::new(0, 100.0, 0.0, true));
+        let generator = test_generator(TextureIDs::default(), terrain_gen.clone());
+        let light = generator.sky_light((0, 32, 0));
+        let carved: Vec<[i32; 3]> = (0..32).flat_map(|x| (32..64).flat_map(move |y| (0..32).map(move |z| [x, y, z])))
+            .filter(|[x, y, z]| terrain_gen.get_block(*x as f64, *y as f64, *z as f64).is_none())
+            .collect();
+        assert!(!carved.is_empty());
+        assert!(carved.iter().all(|pos| light.level(*pos) == 0));
+
+        // a shaft dug down from the surface lets the light into the cave
+        let [x, y, z] = carved[0];
+        for shaft_y in y+1..=100 {
+            terrain_gen.set_block([x, shaft_y, z], None);
+        }
+        let light = generator.sky_light((0, 32, 0));
+        assert_eq!(light.level([x, y, z]), MAX_LIGHT);
+    }
+
+    fn quad_area(quad: &[ChunkVertex], face: FaceDir) -> f32 {
+        let (a, b) = match face {
+            FaceDir::LEFT | FaceDir::RIGHT => (1, 2),
+            FaceDir::TOP | FaceDir::BOTTOM => (0, 2),
+            FaceDir::FRONT | FaceDir::BACK => (0, 1),
+        };
+        let extent = |axis: usize| {
+            quad.iter().map(|v| v.pos[axis]).fold(f32::MIN, f32::max)-quad.iter().map(|v| v.pos[axis]).fold(f32::MAX, f32::min)
+        };
+        extent(a)*extent(b)
+    }
+
+    #[test]
+    fn test_greedy_mesh_merges_flat_surface() {
+        let generator = test_generator(TextureIDs::default(), TerrainGenerator::new(0, 12.0, 0.0, false));
+        let chunk_pos = |x: u32, y: u32, z: u32| (x as f32, y as f32, -(z as f32));
+
+        let voluminous = generator.voluminous_opaque_cubes_mesh((0, 0, 0), chunk_pos, &LightVolume::full());
+        let greedy = generator.greedy_opaque_cubes_mesh((0, 0, 0), chunk_pos, &LightVolume::full());
+        assert!(face_count(&greedy) < face_count(&voluminous));
+
+        // the whole sandy top surface becomes a single quad, tiling the texture once per block
+        let (top_verts, top_inds, _) = &greedy[0];
+        assert_eq!(top_verts.len(), 4);
+        assert_eq!(top_inds.len(), 6);
+        assert_eq!(quad_area(top_verts, FaceDir::TOP), 32.0*32.0);
+        assert_eq!(top_verts.iter().map(|v| v.uv[0]).fold(f32::MIN, f32::max), 32.0);
+        assert_eq!(top_verts.iter().map(|v| v.uv[1]).fold(f32::MIN, f32::max), 32.0);
+        assert_eq!(visible_triangles(top_verts, top_inds, [16.0, 40.0, -16.0]), 2);
+
+        // covering the same area
+        for ((v_verts, _, face), (g_verts, _, _)) in voluminous.iter().zip(&greedy) {
+            let area = |verts: &Vec<ChunkVertex>| verts.chunks(4).map(|quad| quad_area(quad, *face)).sum::<f32>();
+            assert_eq!(area(v_verts), area(g_verts), "{face:?}");
+        }
+    }
+
+    #[test]
+    fn test_smaller_chunk_sizes() {
+        for chunk_size in [8, 16] {
+            let generator = ChunkGeneratorEF::new(test_block_ind(), Arc::new(TextureIDs::default()), Arc::new(TerrainGenerator::new(0, 4.0, 0.0, false)), chunk_size, 1, vec![]);
+
+            // a cell of its own for every block of the chunk
+            let cells: HashSet<usize> = (0..chunk_size).flat_map(|y| (0..chunk_size).flat_map(move |x| (0..chunk_size).map(move |z| (x, y, z))))
+                .map(|(x, y, z)| generator.access(x, y, z))
+                .collect();
+            assert_eq!(cells.len(), (chunk_size*chunk_size*chunk_size) as usize);
+            assert!(cells.iter().all(|cell| *cell < cells.len()));
+
+            // the top surface spans exactly the chunk
+            let chunk_pos = |x: u32, y: u32, z: u32| (x as f32, y as f32, -(z as f32));
+            let greedy = generator.greedy_opaque_cubes_mesh((0, 0, 0), chunk_pos, &LightVolume::full());
+            let (top_verts, _, _) = &greedy[0];
+            assert_eq!(top_verts.len(), 4);
+            assert_eq!(quad_area(top_verts, FaceDir::TOP), (chunk_size*chunk_size) as f32);
+        }
+    }
+
+    #[test]
+    fn test_greedy_mesh_keeps_face_textures() {
+        let txtr_mapper = HashMap::from([
+            (String::from("grass_top"), 1), (String::from("dirt"), 2), (String::from("grass_side"), 3), (String::from("sand"), 4),
+        ]);
+        let generator = test_generator(TextureIDs::from(txtr_mapper), TerrainGenerator::new(0, 12.0, 0.0, false));
+        let grass_block = BlockData { texture_id: TextureMapper::Lateral("grass_top", "dirt", "grass_side"), ..test_block_ind()[0] };
+        let sand = test_block_ind()[3];
+
+        // a row of three grass blocks followed by a sand block
+        let faces = |face: FaceDir| {
+            let mut verts = vec![];
+            let mut inds = vec![];
+            for (x, block) in [grass_block, grass_block, grass_block, sand].iter().enumerate() {
+                let (mut v, mut i) = generator.gen_face((x as f32, 0.0, 0.0), verts.len() as u32, face, block, MeshType::Cube, None);
+                verts.append(&mut v);
+                inds.append(&mut i);
+            }
+            greedy_merge_faces(&verts, &inds, face)
+        };
+
+        let (top_verts, top_inds) = faces(FaceDir::TOP);
+        assert_eq!(top_verts.len(), 8);
+        assert_eq!(top_inds.len(), 12);
+        assert!(top_verts[..4].iter().all(|v| v.txtr == 1.0));
+        assert_eq!(quad_area(&top_verts[..4], FaceDir::TOP), 3.0);
+        assert!(top_verts[4..].iter().all(|v| v.txtr == 4.0));
+
+        let (front_verts, _) = faces(FaceDir::FRONT);
+        assert_eq!(front_verts.len(), 8);
+        assert!(front_verts[..4].iter().all(|v| v.txtr == 3.0));
+        assert_eq!(quad_area(&front_verts[..4], FaceDir::FRONT), 3.0);
+        // the texture repeats along the row, but not vertically
+        assert_eq!(front_verts[..4].iter().map(|v| v.uv[0]).fold(f32::MIN, f32::max), 3.0);
+        assert_eq!(front_verts[..4].iter().map(|v| v.uv[1]).fold(f32::MIN, f32::max), 1.0);
+    }
+
+    #[test]
+    fn test_stone_floor_texture_variants() {
+        let txtr_mapper = HashMap::from([
+            (String::from("stone"), 1), (String::from("stone_90"), 2), (String::from("stone_180"), 3), (String::from("stone_270"), 4),
+        ]);
+        let generator = test_generator(TextureIDs::from(txtr_mapper), TerrainGenerator::new(0, 12.0, 0.0, false));
+        let stone = BlockData {
+            texture_id: TextureMapper::Variants(&["stone", "stone_90", "stone_180", "stone_270"]), ..test_block_ind()[2]
+        };
+
+        let floor = || {
+            let mut txtrs = vec![];
+            for x in 0..4 {
+                for z in 0..4 {
+                    let (verts, _) = generator.gen_face((x as f32, 0.0, -(z as f32)), 0, FaceDir::TOP, &stone, MeshType::Cube, None);
+                    assert!(verts.iter().all(|v| v.txtr == verts[0].txtr));
+                    txtrs.push(verts[0].txtr);
+                }
+            }
+            txtrs
+        };
+        let txtrs = floor();
+        // remeshing the floor picks the same variants
+        assert_eq!(txtrs, floor());
+        let mut variants = txtrs.clone();
+        variants.sort_by(f32::total_cmp);
+        variants.dedup();
+        assert!(variants.len() > 1, "{txtrs:?}");
+    }
+
+    #[test]
+    fn test_atlas_uvs_within_texture_rect() {
+        let txtr_ids = TextureIDs::atlas(
+            HashMap::from([(String::from("sand"), 1)]),
+            vec![[0.0, 0.0, 0.5, 0.5], [0.5, 0.25, 0.25, 0.5]],
+        );
+        let generator = test_generator(txtr_ids, TerrainGenerator::new(0, 12.0, 0.0, false));
+
+        let (verts, _) = generator.gen_face((0.0, 0.0, 0.0), 0, FaceDir::TOP, &test_block_ind()[3], MeshType::Cube, None);
+        let mut uvs: Vec<[f32; 2]> = verts.iter().map(|v| v.uv).collect();
+        uvs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(uvs, vec![[0.5, 0.25], [0.5, 0.75], [0.75, 0.25], [0.75, 0.75]]);
+
+        // textures can't repeat within an atlas, so faces aren't merged
+        let chunk_pos = |x: u32, y: u32, z: u32| (x as f32, y as f32, -(z as f32));
+        let voluminous = generator.voluminous_opaque_cubes_mesh((0, 0, 0), chunk_pos, &LightVolume::full());
+        let greedy = generator.greedy_opaque_cubes_mesh((0, 0, 0), chunk_pos, &LightVolume::full());
+        assert_eq!(face_count(&greedy), face_count(&voluminous));
+    }
+
+    #[test]
+    fn test_downsampled_mesh_fewer_faces() {
+        let terrain_gen = Arc::new(TerrainGenerator::new(0, 20.0, 1.0, false));
+        let full = test_generator(TextureIDs::default(), terrain_gen.clone());
+        let half = ChunkGeneratorEF::new(test_block_ind(), Arc::new(TextureIDs::default()), terrain_gen.clone(), 32, 2, vec![]);
+
+        // chunk straddling the terrain surface
+        let surface = terrain_gen.opaque_block_height_bound_test(0.0, 0.0).floor() as i32;
+        let ofs = (0, surface-(full.chunk_size()/2) as i32, 0);
+        let chunk_pos = |x: u32, y: u32, z: u32| (
+            (ofs.0+x as i32) as f32, (ofs.1+y as i32) as f32, -(ofs.2+z as i32) as f32
+        );
+
+        let full_mesh = full.voluminous_opaque_cubes_mesh(ofs, chunk_pos, &LightVolume::full());
+        let half_mesh = half.voluminous_opaque_cubes_mesh(ofs, chunk_pos, &LightVolume::full());
+
+        let full_faces = face_count(&full_mesh);
+        let half_faces = face_count(&half_mesh);
+        assert!(half_faces > 0);
+        // the top surface scales with the area (1/4), while the lateral faces scale with the length (1/2)
+        assert!(half_faces*2 <= full_faces, "{half_faces} faces downsampled vs {full_faces} faces at full resolution");
+
+        // every downsampled top face spans 2x2 blocks
+        let (top_verts, _, _) = &half_mesh[0];
+        for face in top_verts.chunks(4) {
+            let min_x = face.iter().map(|v| v.pos[0]).fold(f32::MAX, f32::min);
+            let max_x = face.iter().map(|v| v.pos[0]).fold(f32::MIN, f32::max);
+            assert_eq!(max_x-min_x, 2.0);
+        }
+    }
+
+    fn blox_pos(x: i32, y: i32, z: i32) -> Length3D {
+        Length3D::new(Length::new::<blox>(x as f32), Length::new::<blox>(y as f32), Length::new::<blox>(z as f32))
+    }
+
+    // quads of the opaque cube faces of the chunk meshes, along with their direction
+    fn opaque_quads(meshes: &[Vec<(Vec<ChunkVertex>, Vec<u32>, Option<FaceDir>, RenderDataPurpose)>]) -> Vec<(FaceDir, Vec<ChunkVertex>)> {
+        meshes.iter().flatten()
+            .filter_map(|(verts, _, face, purpose)| face.filter(|_| *purpose == RenderDataPurpose::TerrainOpaque).map(|face| (face, verts)))
+            .flat_map(|(face, verts)| verts.chunks(4).map(move |quad| (face, quad.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn test_mf_mesh_fewer_faces_than_hf() {
+        let terrain_gen = Arc::new(TerrainGenerator::new(0, 60.0, 0.05, false));
+        let hf = ChunkGeneratorHF::new(test_block_ind(), Arc::new(TextureIDs::default()), terrain_gen.clone(), 32, 1, vec![2]);
+        let mf = ChunkGeneratorMF::new(test_block_ind(), Arc::new(TextureIDs::default()), terrain_gen.clone(), 128, 2, vec![1]);
+        // the surface stays within the MF chunk at the origin
+        for (x, z) in (0..128).step_by(4).flat_map(|x| (0..128).step_by(4).map(move |z| (x as f64, z as f64))) {
+            let height = terrain_gen.opaque_block_height_bound_test(x, z);
+            assert!(8.0 < height && height < 120.0, "{height}");
+        }
+
+        // the HF chunks covering the same region
+        let hf_meshes: Vec<_> = (0..4).flat_map(|x| (0..4).flat_map(move |y| (0..4).map(move |z| (x, y, z))))
+            .map(|(x, y, z)| hf.generate_mesh(blox_pos(x*32, y*32, z*32)))
+            .collect();
+        let mf_mesh = mf.generate_mesh(Length3D::origin());
+
+        let hf_faces = opaque_quads(&hf_meshes).len();
+        let mf_faces = opaque_quads(&[mf_mesh]).len();
+        assert!(mf_faces > 0);
+        assert!(mf_faces*2 <= hf_faces, "{mf_faces} MF faces vs {hf_faces} HF faces over the same region");
+    }
+
+    #[test]
+    fn test_hf_mf_seams_closed() {
+        let terrain_gen = Arc::new(TerrainGenerator::new(3, 60.0, 0.3, false));
+        // the layers of the chunks around the surface, within the MF chunk
+        let surface = terrain_gen.opaque_block_height_bound_test(0.0, 16.0) as i32;
+        let mf_y = surface.div_euclid(128)*128;
+        let hf_ys: Vec<_> = [-32, 0, 32].into_iter().map(|dy| surface.div_euclid(32)*32+dy)
+            .filter(|y| (mf_y..mf_y+128).contains(y))
+            .collect();
+
+        // HF chunks on both sides of an MF chunk
+        let seams = |with_seams: bool| {
+            let hf = ChunkGeneratorHF::new(test_block_ind(), Arc::new(TextureIDs::default()), terrain_gen.clone(), 32, 1,
+                                           if with_seams {vec![2]} else {vec![]});
+            let mf = ChunkGeneratorMF::new(test_block_ind(), Arc::new(TextureIDs::default()), terrain_gen.clone(), 128, 2,
+                                           if with_seams {vec![1]} else {vec![]});
+            let mut meshes: Vec<_> = hf_ys.iter().flat_map(|y| [-32, 128].map(|x| hf.generate_mesh(blox_pos(x, *y, 0)))).collect();
+            meshes.push(mf.generate_mesh(blox_pos(0, mf_y, 0)));
+            let quads = opaque_quads(&meshes);
+
+            // blocks along the two borders drawn solid on one side and open on the other, without a face between
+            let mut mismatched = 0;
+            let mut holes = 0;
+            for (plane, y, z) in [0, 128].into_iter().flat_map(|plane| hf_ys.iter().flat_map(|y| *y..*y+32).flat_map(move |y| (0..32).map(move |z| (plane, y, z)))) {
+                let (fine, coarse) = if plane == 0 {([plane-1, y, z], [plane, y, z])} else {([plane, y, z], [plane-1, y, z])};
+                let (fine_solid, coarse_solid) = (hf.solid_at(fine, 1), mf.solid_at(coarse, 2));
+                if fine_solid == coarse_solid {
+                    continue;
+                }
+                mismatched += 1;
+                // facing out of the solid side
+                let solid_side = if fine_solid {fine[0]} else {coarse[0]};
+                let face = if solid_side < plane {FaceDir::RIGHT} else {FaceDir::LEFT};
+                let covered = quads.iter().any(|(quad_face, quad)| {
+                    let extent = |axis: usize| quad.iter().map(|v| v.pos[axis]).fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p), hi.max(p)));
+                    // the vertices of a block at z span from z-1 to z
+                    let ((x0, x1), (y0, y1), (z0, z1)) = (extent(0), extent(1), extent(2));
+                    matches!((quad_face, face), (FaceDir::RIGHT, FaceDir::RIGHT) | (FaceDir::LEFT, FaceDir::LEFT)) && x0 == plane as f32 && x1 == plane as f32
+                        && y0 <= y as f32 && y as f32+1.0 <= y1 && z0 <= z as f32-1.0 && z as f32 <= z1
+                });
+                if !covered {
+                    holes += 1;
+                }
+            }
+            (mismatched, holes)
+        };
+
+        // the tiers sample the borders differently, which leaves holes unless the seams are meshed
+        let (mismatched, holes) = seams(false);
+        assert!(mismatched > 0 && holes > 0, "{mismatched} mismatched blocks, {holes} holes");
+        assert_eq!(seams(true), (mismatched, 0));
+    }
+
+    #[test]
+    fn test_skirts_along_chunk_borders() {
+        // the chunk at the layer of the surface
+        let side_faces = |terrain_gen: &Arc<TerrainGenerator>, neighbours: Vec<u32>| {
+            let y_ofs = (terrain_gen.opaque_block_height_bound_test(0.0, 16.0) as i32).div_euclid(32)*32;
+            let chunk_pos = |x: u32, y: u32, z: u32| (x as f32, (y_ofs+y as i32) as f32, -(z as f32));
+            let generator = ChunkGeneratorHF::new(test_block_ind(), Arc::new(TextureIDs::default()), terrain_gen.clone(), 32, 1, neighbours);
+            let mesh = generator.voluminous_opaque_cubes_mesh((0, y_ofs, 0), chunk_pos, &LightVolume::full());
+            mesh.into_iter().skip(2).map(|(verts, _, face)| (face, verts)).collect::<Vec<_>>()
+        };
+
+        // flat terrain has no side faces, apart from the skirts below the top edges deep enough for the MF neighbours
+        let flat = Arc::new(TerrainGenerator::new(0, 40.0, 0.0, false));
+        let top = flat.opaque_block_height_bound_test(0.0, 0.0).ceil() as f32;
+        assert!(side_faces(&flat, vec![]).iter().all(|(_, verts)| verts.is_empty()));
+        for (face, verts) in side_faces(&flat, vec![1, 2]) {
+            assert_eq!(verts.len()/4, 32*2, "{face:?}");
+            assert!(verts.iter().all(|v| top-2.0 <= v.pos[1] && v.pos[1] <= top));
+            if matches!(face, FaceDir::LEFT | FaceDir::RIGHT) {
+                let plane = if matches!(face, FaceDir::RIGHT) {32.0} else {0.0};
+                assert!(verts.iter().all(|v| v.pos[0] == plane));
+            }
+        }
+
+        // the skirts never end up on top of the faces already on the border
+        let hilly = Arc::new(TerrainGenerator::new(3, 60.0, 0.3, false));
+        let (without, with) = (side_faces(&hilly, vec![]), side_faces(&hilly, vec![1, 2]));
+        for ((face, verts), (_, skirted)) in without.iter().zip(&with) {
+            assert!(skirted.len() > verts.len(), "{face:?}");
+            let corners: Vec<_> = skirted.chunks(4).map(|quad| quad.iter().map(|v| v.pos.map(|p| p as i32)).min().unwrap()).collect();
+            let mut unique = corners.clone();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), corners.len(), "{face:?}");
+        }
+    }
+
+    #[test]
+    fn test_cave_mesh_transitions() {
+        // chunk buried well below the flat surface
+        let chunk_pos = |x: u32, y: u32, z: u32| (x as i32 as f32, (32+y as i32) as f32, -(z as i32 as f32));
+        let buried = |caves: bool| {
+            let terrain_gen = Arc::new(TerrainGenerator::new(0, 100.0, 0.0, caves));
+            let generator = test_generator(TextureIDs::default(), terrain_gen.clone());
+            (terrain_gen, generator.voluminous_opaque_cubes_mesh((0, 32, 0), chunk_pos, &LightVolume::full()))
+        };
+        assert_eq!(face_count(&buried(false).1), 0);
+
+        // a top face wherever a column opens up above a block, any number of times within the column
+        let (terrain_gen, mesh) = buried(true);
+        let open = |x: i32, y: i32, z: i32| terrain_gen.get_block(x as f64, (32+y) as f64, z as f64).is_none();
+        let mut top_faces = 0;
+        for x in 0..32 {
+            for z in 0..32 {
+                top_faces += (0..=32).filter(|&y| open(x, y, z) && !open(x, y-1, z)).count();
+            }
+        }
+        assert!(top_faces > 0);
+        assert_eq!(mesh[0].0.len()/4, top_faces);
+    }
+
+    fn vert(pos: [f32; 3]) -> ChunkVertex {
+        ChunkVertex { pos, uv: [0.0, 0.0], txtr: 0.0, anim: [1.0, 1.0], ao: 0.0, light: 1.0, normal: [0.0, 1.0, 0.0] }
+    }
+
+    // aggregates the meshes of the chunks the same way the chunk generators do
+    fn aggregate(chunks: &[(Vec<ChunkVertex>, Vec<u32>)]) -> (Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>) {
+        let (mut verts, mut inds, mut ranges) = (vec![], vec![], vec![]);
+        for (chunk_verts, chunk_inds) in chunks {
+            let start = (verts.len(), inds.len() as u32);
+            let ind_count = verts.len() as u32;
+            inds.extend(chunk_inds.iter().map(|i| i+ind_count));
+            verts.extend_from_slice(chunk_verts);
+            ranges.extend(chunk_range(&verts, &inds, start, ranges.len() as u64));
+        }
+        (verts, inds, ranges)
+    }
+
+    #[test]
+    fn test_translucent_per_chunk_sort() {
+        // two triangles per chunk, the second one nearer to the eye than the first
+        let chunk = |x: f32| (vec![
+            vert([x, 0.0, 0.0]), vert([x+1.0, 0.0, 0.0]), vert([x, 1.0, 0.0]),
+            vert([x, 0.0, 5.0]), vert([x+1.0, 0.0, 5.0]), vert([x, 1.0, 5.0]),
+        ], vec![0, 1, 2, 3, 4, 5]);
+        let (verts, inds, ranges) = aggregate(&[chunk(32.0), chunk(0.0), chunk(64.0)]);
+        let eye = [0.0, 0.0, 10.0];
+
+        let sorted_inds = sort_translucent(&verts, &inds, &ranges, eye, TranslucentSort::PerChunk);
+        let first_x: Vec<f32> = sorted_inds.chunks(6).map(|group| verts[group[0] as usize].pos[0]).collect();
+        assert_eq!(first_x, vec![64.0, 32.0, 0.0]);
+        // faces within each chunk stay in their meshed order
+        for (group, ofs) in sorted_inds.chunks(6).zip([12, 0, 6]) {
+            assert_eq!(group, &[ofs, ofs+1, ofs+2, ofs+3, ofs+4, ofs+5]);
+        }
+
+        let unsorted_inds = sort_translucent(&verts, &inds, &ranges, eye, TranslucentSort::Unsorted);
+        assert_eq!(unsorted_inds, (0..18).collect::<Vec<u32>>());
+        let face_inds = sort_translucent(&verts, &inds, &ranges, eye, TranslucentSort::PerFace);
+        // every triangle nearer to the eye is drawn after the ones behind it
+        let dists: Vec<f32> = face_inds.chunks(3)
+            .map(|tri| (verts[tri[0] as usize].pos[0]).powi(2)+(verts[tri[0] as usize].pos[2]-10.0).powi(2))
+            .collect();
+        assert!(dists.windows(2).all(|d| d[0] >= d[1]), "{dists:?}");
+    }
+
+    #[test]
+    fn test_stacked_water_blends_back_to_front() {
+        // the surfaces of two stacked water bodies as a block face each, meshed in either order
+        let surface = |y: f32| {
+            let (mut verts, mut inds) = (vec![], vec![]);
+            for (x, z) in (0..4).flat_map(|x| (0..4).map(move |z| (x as f32, z as f32))) {
+                let ofs = verts.len() as u32;
+                verts.extend([[x, y, z], [x+1.0, y, z], [x, y, z+1.0], [x+1.0, y, z+1.0]].map(vert));
+                inds.extend([0, 1, 2, 2, 1, 3].map(|i| i+ofs));
+            }
+            (verts, inds)
+        };
+        // the position in the drawing order of the triangles of each surface, by the block column they cover
+        let draw_order = |verts: &[ChunkVertex], inds: &[u32], y: f32| {
+            let mut order: HashMap<[i32; 2], Vec<usize>> = HashMap::new();
+            for (i, tri) in inds.chunks(3).enumerate().filter(|(_, tri)| verts[tri[0] as usize].pos[1] == y) {
+                let min = |k: usize| tri.iter().map(|i| verts[*i as usize].pos[k]).fold(f32::MAX, f32::min) as i32;
+                order.entry([min(0), min(2)]).or_default().push(i);
+            }
+            order
+        };
+
+        for chunks in [[surface(10.0), surface(20.0)], [surface(20.0), surface(10.0)]] {
+            let (verts, inds, ranges) = aggregate(&chunks);
+            for sort in [TranslucentSort::PerChunk, TranslucentSort::PerFace] {
+                for (eye, upper_last) in [([-6.0, 40.0, -3.0], true), ([-6.0, -5.0, -3.0], false)] {
+                    let sorted_inds = sort_translucent(&verts, &inds, &ranges, eye, sort);
+                    let (lower, upper) = (draw_order(&verts, &sorted_inds, 10.0), draw_order(&verts, &sorted_inds, 20.0));
+                    // seen from above at an angle, the water below is drawn first and blended over by the water
+                    // on top of it, and the other way around from below
+                    for (column, lower_order) in lower {
+                        for (u, l) in upper[&column].iter().flat_map(|u| lower_order.iter().map(move |l| (u, l))) {
+                            assert_eq!(u > l, upper_last, "{sort:?} from {eye:?} at {column:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -4,24 +4,51 @@ pub mod chunk_gen_mf;
 pub mod mesh_util;
 pub mod terrain_gen;
 pub mod chunk_mesh;
+pub mod chunk_border;
+pub mod obj_export;
+pub mod raycast;
+pub mod light;
+pub mod registry;
+pub mod chunk_store;
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::{ffi, mem};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 use std::rc::Rc;
+use std::time::Instant;
+use std::sync::Arc;
 use ash::{Device, vk};
 use noise::NoiseFn;
-use crate::component::terrain::chunk_mesh::{ChunkGeneratable, ChunkMesh, ChunkRadius, UpdateChunk};
+use serde::Deserialize;
+use winit::event::VirtualKeyCode;
+use crate::component::terrain::chunk_mesh::{ChunkGeneratable, ChunkMesh, ChunkPriority, ChunkRadius, ChunkRange, ChunkUnload, Position, UpdateChunk, VertexLimit, WorldBounds, cull_ranges};
 use crate::component::{Component, RenderData, RenderDataPurpose};
-use crate::component::camera::Length3D;
+use crate::component::camera::{Length3D, Plane, Ray};
+use crate::component::physics::{BlockQuery, PhysicsBody};
+use uom::si::f32::Length;
+use crate::measurement::{blox, chux, chux_hf};
+use crate::component::terrain::chunk_border::{BORDER_COLOR_EF, BORDER_COLOR_HF, BORDER_COLOR_MF, chunk_border_mesh};
 use crate::component::terrain::chunk_gen::ChunkGeneratorEF;
+use crate::component::terrain::chunk_store::ChunkStore;
 use crate::component::terrain::chunk_gen_hf::ChunkGeneratorHF;
 use crate::component::terrain::chunk_gen_mf::ChunkGeneratorMF;
-use crate::component::terrain::terrain_gen::TerrainGenerator;
+use crate::component::terrain::mesh_util::sort_translucent;
+use crate::component::terrain::obj_export::{ExportRegion, write_obj};
+use crate::component::terrain::raycast::voxel_raycast;
+use crate::component::terrain::terrain_gen::{TerrainGenerator, TerrainParams};
+use crate::component::texture::TextureIDMapper;
+use crate::debug::{Diagnostics, MTXG_RENDER_LOG};
 use crate::handler::VulkanInstance;
 use crate::shader::chunk::ChunkVertex;
-use crate::util::{CmdBufContext, create_host_buffer, create_local_buffer};
+use crate::util::{BufferPool, CmdBufContext, PendingUploads, SubAllocation, SubAllocator, WorkerPool, allocate_buffer, create_host_buffer, create_local_buffer, update_buffer};
 use crate::world::WorldEvent;
 
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Deserialize)]
 pub enum FaceDir {
     FRONT,
     RIGHT,
@@ -31,26 +58,101 @@ pub enum FaceDir {
     BOTTOM
 }
 
-#[derive(Copy, Clone, Debug)]
+impl FaceDir {
+    // outward unit normal in the render space of the meshes (where z is negated)
+    pub(crate) fn normal(self) -> [f32; 3] {
+        match self {
+            FaceDir::FRONT => [0.0, 0.0, 1.0],
+            FaceDir::RIGHT => [1.0, 0.0, 0.0],
+            FaceDir::BACK => [0.0, 0.0, -1.0],
+            FaceDir::LEFT => [-1.0, 0.0, 0.0],
+            FaceDir::TOP => [0.0, 1.0, 0.0],
+            FaceDir::BOTTOM => [0.0, -1.0, 0.0],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
 pub enum MeshType {
     Empty,
     Cube,
     XCross,
-    Fluid
+    Fluid,
+    Slab(bool),  // upper half of the block if true, lower half otherwise
+    Stairs(FaceDir),  // ascending towards the given lateral side, the step is on the opposite side
 }
 
-#[derive(Copy, Clone, Debug)]
+impl MeshType {
+    // the slabs and stairs collide as full blocks
+    pub(crate) fn collides(&self) -> bool {
+        matches!(self, MeshType::Cube | MeshType::Slab(_) | MeshType::Stairs(_))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum FaceWinding {
+    Outward,  // counter-clockwise when looking at the face from outside the block
+    DoubleSided,  // outward triangles followed by the same triangles reversed
+}
+
+impl FaceWinding {
+    pub(crate) fn apply(&self, inds: Vec<u32>) -> Vec<u32> {
+        match self {
+            FaceWinding::Outward => inds,
+            FaceWinding::DoubleSided => {
+                let reversed: Vec<u32> = inds.chunks(3)
+                    .flat_map(|tri| tri.iter().rev().copied())
+                    .collect();
+                inds.into_iter().chain(reversed).collect()
+            }
+        }
+    }
+}
+
+// how finely the translucent faces are ordered back to front from the camera before drawing, the terrain sorts
+// them again (and uploads the translucent mesh again) whenever the camera moves a block away
+// sorting is used over order independent transparency (e.g. dual depth peeling, which needs extra depth attachments
+// and a geometry pass per peeled layer), it is only exact for faces that don't intersect, which holds for the axis
+// aligned fluid faces, but costs a sort and an upload on the CPU as the camera moves
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TranslucentSort {
+    Unsorted,
+    PerChunk,  // whole chunks are ordered, the faces within a chunk are kept as meshed
+    PerFace,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
 pub enum TransparencyType {
     Opaque,
     Transparent,  // full opacity or no opacity
     Translucent,  // partial opacity
 }
 
+// family of sounds played when a block is placed, broken or stepped on
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum SoundGroup {
+    Silent,
+    Stone,
+    Dirt,
+    Grass,
+    Sand,
+    Wood,
+    Water,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlockAction {
+    Place,
+    Break,
+    Step,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum TextureMapper<'s> {
     All(&'s str),
     Lateral(&'s str, &'s str, &'s str),  // top, bottom, lateral
     Unique(&'s str, &'s str, &'s str, &'s str, &'s str, &'s str),  // top, bottom, E (right), S (front), W (left), N (back)
+    Variants(&'s [&'s str]),  // on every face, one of them picked by the position of the block to break up the tiling
 }
 
 impl<'s> TextureMapper<'s> {
@@ -58,12 +160,32 @@ impl<'s> TextureMapper<'s> {
         self.top()
     }
 
+    // resolves the variants for the block at the location, the same variant every time the block is meshed
+    pub(crate) fn at(self, loc: (f32, f32, f32)) -> Self {
+        match self {
+            TextureMapper::Variants(variants) if !variants.is_empty() => {
+                let hash = variant_hash(loc.0.floor() as i32, loc.1.floor() as i32, loc.2.floor() as i32);
+                TextureMapper::All(variants[hash as usize%variants.len()])
+            }
+            mapper => mapper,
+        }
+    }
+
+    // every texture the block may use
+    pub(crate) fn names(&self) -> Vec<&'s str> {
+        match self {
+            TextureMapper::Variants(variants) => variants.to_vec(),
+            _ => vec![self.top(), self.bottom(), self.right(), self.front(), self.left(), self.back()],
+        }
+    }
+
     // front facing texture and so on ...
     fn front(&self) -> &'s str {
         match self {
             TextureMapper::All(t) => {t}
             TextureMapper::Lateral(_, _, t) => {t}
             TextureMapper::Unique(_, _, _, t, _, _) => {t}
+            TextureMapper::Variants(t) => {t[0]}
         }
     }
 
@@ -72,6 +194,7 @@ impl<'s> TextureMapper<'s> {
             TextureMapper::All(t) => {t}
             TextureMapper::Lateral(_, _, t) => {t}
             TextureMapper::Unique(_, _, _, _, _, t) => {t}
+            TextureMapper::Variants(t) => {t[0]}
         }
     }
 
@@ -80,6 +203,7 @@ impl<'s> TextureMapper<'s> {
             TextureMapper::All(t) => {t}
             TextureMapper::Lateral(_, _, t) => {t}
             TextureMapper::Unique(_, _, _, _, t, _) => {t}
+            TextureMapper::Variants(t) => {t[0]}
         }
     }
 
@@ -88,6 +212,7 @@ impl<'s> TextureMapper<'s> {
             TextureMapper::All(t) => {t}
             TextureMapper::Lateral(_, _, t) => {t}
             TextureMapper::Unique(_, _, t, _, _, _) => {t}
+            TextureMapper::Variants(t) => {t[0]}
         }
     }
 
@@ -96,6 +221,7 @@ impl<'s> TextureMapper<'s> {
             TextureMapper::All(t) => {t}
             TextureMapper::Lateral(t, _, _) => {t}
             TextureMapper::Unique(t, _, _, _, _, _) => {t}
+            TextureMapper::Variants(t) => {t[0]}
         }
     }
 
@@ -104,21 +230,246 @@ impl<'s> TextureMapper<'s> {
             TextureMapper::All(t) => {t}
             TextureMapper::Lateral(_, t, _) => {t}
             TextureMapper::Unique(_, t, _, _, _, _) => {t}
+            TextureMapper::Variants(t) => {t[0]}
         }
     }
 }
 
+// integer hash of a block position, well spread for neighbouring blocks
+fn variant_hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut hash = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841) ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    hash
+}
+
+// frames stacked vertically in the texture(s) of a block, each frame shown for the interval in a loop
+// (the time uniform wraps around at 2 pi, so the loop restarts from the first frame there)
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub struct TextureAnimation {
+    pub frames: u32,
+    pub interval: f32,  // in units of the time uniform
+}
+
+impl TextureAnimation {
+    // frame count and interval vertex attribute, a single frame for still textures
+    pub(crate) fn attribute(animation: Option<TextureAnimation>) -> [f32; 2] {
+        animation.map_or([1.0, 1.0], |anim| [anim.frames.max(1) as f32, anim.interval])
+    }
+
+    // same as the frame (texture layer offset) computed by the chunk vertex shaders
+    pub fn frame(&self, time: f32) -> u32 {
+        (time/self.interval).floor() as u32 % self.frames.max(1)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct BlockData<'s> {
     pub ident: &'s str,
     pub texture_id: TextureMapper<'s>,
     pub mesh: MeshType,
     pub transparency: TransparencyType,
+    pub sound_group: SoundGroup,
+    // only the front of the faces is drawn, independent of the transparency (e.g. foliage and water are
+    // seen from both sides, while ice can be single sided)
+    pub back_face_culling: bool,
+    pub animation: Option<TextureAnimation>,
+}
+
+impl BlockData<'_> {
+    // which side(s) of a face are visible, as the chunk pipelines cull clockwise (back) faces
+    pub(crate) fn winding(&self) -> FaceWinding {
+        if self.back_face_culling {
+            FaceWinding::Outward
+        } else {
+            FaceWinding::DoubleSided
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default)]
-pub struct Block(u16);
+pub struct Block(pub(crate) u16);  // index into the block index
+
+// sound event of the action done to the block at the given position, silent blocks emit nothing
+pub(crate) fn block_sound(block_ind: &[BlockData], pos: Length3D, block: Block, action: BlockAction) -> Option<WorldEvent> {
+    let sound_group = block_ind[block.0 as usize].sound_group;
+    (sound_group != SoundGroup::Silent).then_some(WorldEvent::BlockSound { pos, block, action, sound_group })
+}
+
+
+// makes the uploaded vertices and indices visible to the frames submitted after the upload
+unsafe fn vertex_upload_barrier(device: &Device, cmd_buf: vk::CommandBuffer) {
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ)
+        .build();
+    device.cmd_pipeline_barrier(
+        cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::VERTEX_INPUT,
+        vk::DependencyFlags::empty(), &[barrier], &[], &[],
+    );
+}
+
+
+// an in-flight mesh upload, the staging buffers are freed once the transfer completes
+struct TerrainUpload {
+    cmd_buf: vk::CommandBuffer,
+    staging: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    purpose: RenderDataPurpose,
+    render_data: Vec<RenderData>,
+    pooled: bool,  // the local buffers belong to the buffer pool, which hands them over once the upload completes
+    cleared: bool,  // the buffers of the purpose were cleared meanwhile, so the uploaded ones aren't drawn
+    retired: Vec<u64>,  // mesh ids of the chunk buffers to free once the uploaded chunks reach the shader
+    allocations: Vec<(u64, SubAllocation)>,  // of the uploaded chunks, by their mesh ids
+}
+
+// the chunks of the aggregated mesh not uploaded (or being uploaded) yet, and the mesh ids of the uploaded chunks no
+// longer in it, which become the mesh ids of the aggregated mesh
+fn diff_chunks(uploaded: &mut HashSet<u64>, ranges: Vec<ChunkRange>) -> (Vec<ChunkRange>, Vec<u64>) {
+    let meshed: HashSet<u64> = ranges.iter().map(|range| range.mesh_id).collect();
+    let retired = uploaded.difference(&meshed).copied().collect();
+    let new = ranges.into_iter().filter(|range| !uploaded.contains(&range.mesh_id)).collect();
+    *uploaded = meshed;
+    (new, retired)
+}
+
+// progress of the initial terrain generation, from the forced chunk generation until its buffers reach the shader
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum InitialGeneration {
+    Waiting,  // for the texture mapper
+    Meshing,
+    Uploading,
+    Complete,
+    Reported,
+}
+
+impl InitialGeneration {
+    fn forced(&mut self) {
+        if *self == InitialGeneration::Waiting {
+            *self = InitialGeneration::Meshing;
+        }
+    }
+
+    fn meshed(&mut self) {
+        if *self == InitialGeneration::Meshing {
+            *self = InitialGeneration::Uploading;
+        }
+    }
+
+    fn uploads_polled(&mut self, uploads_pending: bool) {
+        if *self == InitialGeneration::Uploading && !uploads_pending {
+            *self = InitialGeneration::Complete;
+        }
+    }
+
+    // true only the first time it is called after the generation completed
+    fn report(&mut self) -> bool {
+        if *self == InitialGeneration::Complete {
+            *self = InitialGeneration::Reported;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+const EXPORT_PATH: &str = "terrain.obj";
+const RESORT_DISTANCE: f32 = 1.0;  // in blox the camera moves before the translucent faces are sorted again
+const AMPLITUDE_STEP: f64 = 1.25;  // scales the amplitude of the terrain height per key press (F3 raises, F2 flattens)
+const CHUNK_BLOCK_SIZE: vk::DeviceSize = 16 << 20;  // of the first chunk block of a purpose, each later one at least doubles it
+
+// border and inner radii of the EF, HF, and MF tiers, for the render distance in extreme fidelity chunks
+// each tier leaves out the chunks covered by the finer tier before it, so its inner radius (in the chunk unit of the
+// finer tier) must match the border radius of that tier, or else there are holes or overlapping chunks between them
+// the MF border at half the render distance still reaches past the HF border, as MF chunks are 4x larger
+fn lod_radii(render_distance: u32) -> [(ChunkRadius, Option<ChunkRadius>); 3] {
+    let radius = |border: u32| ChunkRadius(border.max(1), (border/2).max(1));
+    let ef = radius(render_distance);
+    let hf = radius(render_distance);
+    let mf = radius(render_distance/2);
+    [(ef, None), (hf, Some(ef)), (mf, Some(hf))]
+}
+
+// edge (in blox) of the chunks of the EF, HF, and MF tiers for the configured EF chunk size, the HF tier meshes chunks
+// of the EF size and the MF chunks stay 4x larger, so each tier still tiles the inner radius of the next one
+pub(crate) fn tier_chunk_sizes(chunk_size: u32) -> [u32; 3] {
+    [chunk_size, chunk_size, Length::new::<chux_hf>(1.0).get::<chux>() as u32*chunk_size]
+}
+
+// downsample factors of the EF, HF, and MF tiers next to the tier, as its chunks border theirs
+fn neighbour_downsamples(lod_downsample: [u32; 3], tier: usize) -> Vec<u32> {
+    let mut neighbours: Vec<u32> = [tier.checked_sub(1), Some(tier+1)].into_iter().flatten()
+        .filter_map(|neighbour| lod_downsample.get(neighbour).copied())
+        .collect();
+    neighbours.dedup();
+    neighbours
+}
+
+// the chunk meshes draw the world block z between the render z-1 and z (gen_face negates the z of chunk_pos, itself
+// negated), so the render space of the vertices and the camera is a block behind the world space along z
+pub(crate) fn world_pos(render: [f32; 3]) -> [f32; 3] {
+    [render[0], render[1], render[2]+1.0]
+}
+
+// world block containing the render space point, or of the render space block with it as its min corner
+pub(crate) fn world_block(render: [f32; 3]) -> [i32; 3] {
+    world_pos(render).map(|c| c.floor() as i32)
+}
+
+// first non-empty block along the ray from the render space origin (e.g. of the CameraRay) within the loaded chunks,
+// in world blocks like every other block position of the terrain (edits, targets and sounds)
+fn pick_block(terrain_gen: &TerrainGenerator, block_ind: &[BlockData], origin: Length3D, dir: (f32, f32, f32), max_dist: f32,
+              pass_through: bool, loaded: impl Fn(Length3D) -> bool) -> Option<(Position<blox>, FaceDir)> {
+    let block_pos = |block: [i32; 3]| Length3D::new(
+        Length::new::<blox>(block[0] as f32),
+        Length::new::<blox>(block[1] as f32),
+        Length::new::<blox>(block[2] as f32),
+    );
+    // the traversal steps through the render space blocks
+    let solid = |block: [i32; 3]| {
+        let block = world_block(block.map(|c| c as f32));
+        loaded(block_pos(block)) &&
+            terrain_gen.get_block(block[0] as f64, block[1] as f64, block[2] as f64)
+                .is_some_and(|block| !matches!(block_ind[block.0 as usize].mesh, MeshType::Empty))
+    };
 
+    let origin = [origin.x.get::<blox>(), origin.y.get::<blox>(), origin.z.get::<blox>()];
+    voxel_raycast(origin, [dir.0, dir.1, dir.2], max_dist, pass_through, solid)
+        .map(|(block, face)| (Position::from(block_pos(world_block(block.map(|c| c as f32)))), face))
+}
+
+// world block right below the feet of the user with the render space eye position
+fn block_underfoot(eye: Length3D) -> [i32; 3] {
+    const BELOW_FEET: f32 = 0.05;
+    world_block([eye.x.get::<blox>(), eye.y.get::<blox>()-PhysicsBody::EYE_HEIGHT-BELOW_FEET, eye.z.get::<blox>()])
+}
+
+// the solidity of the generated and edited blocks, outliving the borrowed block data of the terrain
+pub(crate) struct TerrainBlocks {
+    terrain_gen: Rc<RefCell<Arc<TerrainGenerator>>>,  // swapped by the terrain along with its params
+    solid: Vec<bool>,  // per block index
+}
+
+impl BlockQuery for TerrainBlocks {
+    fn solid(&self, block: [i32; 3]) -> bool {
+        let block = world_block(block.map(|c| c as f32));
+        self.terrain_gen.borrow().get_block(block[0] as f64, block[1] as f64, block[2] as f64)
+            .is_some_and(|block| self.solid[block.0 as usize])
+    }
+}
+
+// how the terrain tiers are meshed, ordered and streamed
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct TerrainConfig {
+    pub(crate) lod_downsample: [u32; 3],  // voxel grid downsample factor of the EF, HF, and MF tiers
+    pub(crate) chunk_size: u32,  // edge (in blox) of the extreme fidelity chunks
+    pub(crate) render_distance: u32,  // in extreme fidelity chunks
+    pub(crate) translucent_sort: TranslucentSort,
+    pub(crate) chunk_unload: ChunkUnload,
+    pub(crate) chunk_priority: ChunkPriority,
+    pub(crate) vertex_limit: VertexLimit,
+    pub(crate) world_bounds: Option<WorldBounds>,  // None for an infinite world
+}
 
 pub(crate) struct Terrain<'b> {
     vi: Rc<VulkanInstance>,
@@ -127,7 +478,19 @@ pub(crate) struct Terrain<'b> {
 
     block_ind: Vec<BlockData<'b>>,
 
-    terrain_gen: Rc<TerrainGenerator>,
+    terrain_gen: Arc<TerrainGenerator>,
+    shared_gen: Rc<RefCell<Arc<TerrainGenerator>>>,  // of the TerrainBlocks handed out
+    chunk_store: Option<ChunkStore>,  // block edits saved across sessions
+    lod_downsample: [u32; 3],  // voxel grid downsample factor of the EF, HF, and MF tiers
+    chunk_sizes: [u32; 3],  // edge of the chunks of the EF, HF, and MF tiers
+    render_distance: u32,  // in extreme fidelity chunks
+    translucent_sort: TranslucentSort,
+    workers: WorkerPool,
+    chunk_unload: ChunkUnload,
+    chunk_priority: ChunkPriority,
+    vertex_limit: VertexLimit,
+    world_bounds: Option<WorldBounds>,
+    txtr_mapper: Option<TextureIDMapper>,
 
     chunk_mesh_ef: Option<ChunkMesh<ChunkGeneratorEF<'b>>>,
     chunk_mesh_hf: Option<ChunkMesh<ChunkGeneratorHF<'b>>>,
@@ -135,38 +498,691 @@ pub(crate) struct Terrain<'b> {
     chunk_update_ef: bool,
     chunk_update_hf: bool,
     chunk_update_mf: bool,
-    chunk_rd_ef: Vec<(Vec<ChunkVertex>, Vec<u32>, RenderDataPurpose)>,
-    chunk_rd_hf: Vec<(Vec<ChunkVertex>, Vec<u32>, RenderDataPurpose)>,
-    chunk_rd_mf: Vec<(Vec<ChunkVertex>, Vec<u32>, RenderDataPurpose)>,
+    chunk_rd_ef: Vec<(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)>,
+    chunk_rd_hf: Vec<(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)>,
+    chunk_rd_mf: Vec<(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)>,
     to_render: Vec<RenderData>,
+    clear_chunk_buffers: bool,
+    // chunks outside of the camera frustum are not drawn
+    frustum: Option<[Plane; 6]>,
+    chunk_ranges: [Vec<ChunkRange>; 2],  // of the opaque and transparent chunk buffers in the shader
+    uploaded_chunks: [HashSet<u64>; 2],  // mesh ids of the opaque and transparent chunks uploaded or being uploaded
+    cull_update: bool,
+    chunk_culling: Option<(usize, usize)>,  // drawn and culled opaque chunks, to be reported
+    buffer_indices: [usize; 3],  // of the opaque, transparent and translucent buffers in the shader
+    drawn_ranges: [Option<(usize, usize)>; 2],  // chunks and indices drawn of the culled buffers, None draws them whole
+    render_stats: Option<(usize, usize, usize)>,  // last reported
+    translucent_mesh: Option<(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>)>,  // as aggregated across the tiers, unsorted
+    eye: [f32; 3],  // camera position
+    sorted_eye: Option<[f32; 3]>,  // camera position the translucent mesh was last sorted from
 
     spectator_mode: bool,
+    pointer_over_ui: bool,  // the buttons are used by the debug widgets instead
+    reach: f32,  // for the targeted block under the cursor
+    cursor_ray: Option<Ray>,
+    selected_block: Option<Block>,  // placed with the right button
+    step_block: Option<[i32; 3]>,  // block below the user, to play its step sound when walking onto another block
+    chunk_borders: bool,
+    chunk_borders_update: bool,
+
+    pending_uploads: PendingUploads<TerrainUpload>,
+    buffer_pool: BufferPool<(RenderDataPurpose, vk::BufferUsageFlags)>,  // the local vertex and index buffers
+    chunk_allocators: [SubAllocator; 2],  // of the opaque and transparent chunk buffers
+    chunk_allocations: [HashMap<u64, SubAllocation>; 2],  // of the chunks in the shader, by their mesh ids
+    completed_uploads: Vec<RenderDataPurpose>,
+    initial_generation: InitialGeneration,
 }
 
 impl<'b> Terrain<'b> {
-    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, ctx: CmdBufContext, block_ind: Vec<BlockData<'b>>) -> Self {
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, ctx: CmdBufContext, block_ind: Vec<BlockData<'b>>,
+                      terrain_gen: TerrainGenerator, config: TerrainConfig, workers: WorkerPool,
+                      chunk_store: Option<ChunkStore>) -> Self {
+        let TerrainConfig {
+            lod_downsample, chunk_size, render_distance, translucent_sort, chunk_unload, chunk_priority, vertex_limit,
+            world_bounds,
+        } = config;
+        let mut chunk_store = chunk_store;
+        if let Some(ref mut store) = chunk_store {
+            match store.scan() {
+                Ok(0) => {}
+                Ok(found) => log::info!("Found {found} saved regions, loaded once the chunks reach them"),
+                Err(e) => log::warn!("Failed to find the saved chunks: {e}"),
+            }
+        }
+
+        let terrain_gen = Arc::new(terrain_gen);
         Self {
             vi, device, ctx: ctx.clone(),
             block_ind,
-            terrain_gen: Rc::new(TerrainGenerator::new()),
+            shared_gen: Rc::new(RefCell::new(terrain_gen.clone())),
+            terrain_gen,
+            chunk_store,
+            lod_downsample,
+            chunk_sizes: tier_chunk_sizes(chunk_size),
+            render_distance,
+            translucent_sort,
+            workers,
+            chunk_unload,
+            chunk_priority,
+            vertex_limit,
+            world_bounds,
+            txtr_mapper: None,
             chunk_mesh_ef: None, chunk_mesh_mf: None, chunk_mesh_hf: None,
             chunk_update_ef: true, chunk_update_hf: true, chunk_update_mf: true,
             chunk_rd_ef: vec![], chunk_rd_hf: vec![], chunk_rd_mf: vec![],
             to_render: vec![],
+            clear_chunk_buffers: false,
+            frustum: None,
+            chunk_ranges: [vec![], vec![]],
+            uploaded_chunks: [HashSet::new(), HashSet::new()],
+            cull_update: false,
+            chunk_culling: None,
+            buffer_indices: [0; 3],
+            drawn_ranges: [None; 2],
+            render_stats: None,
+            translucent_mesh: None,
+            eye: [0.0; 3],
+            sorted_eye: None,
             spectator_mode: false,
+            pointer_over_ui: false,
+            reach: 0.0,
+            cursor_ray: None,
+            selected_block: None,
+            step_block: None,
+            chunk_borders: false,
+            chunk_borders_update: false,
+            pending_uploads: PendingUploads::new(),
+            buffer_pool: BufferPool::new(),
+            chunk_allocators: [
+                SubAllocator::new(CHUNK_BLOCK_SIZE, mem::size_of::<ChunkVertex>() as vk::DeviceSize),
+                SubAllocator::new(CHUNK_BLOCK_SIZE, mem::size_of::<ChunkVertex>() as vk::DeviceSize),
+            ],
+            chunk_allocations: [HashMap::new(), HashMap::new()],
+            completed_uploads: vec![],
+            initial_generation: InitialGeneration::Waiting,
+        }
+    }
+
+    // chunks of every fidelity still being generated in the background
+    fn pending_chunks(&self) -> usize {
+        [
+            self.chunk_mesh_ef.as_ref().map(ChunkMesh::pending_chunks),
+            self.chunk_mesh_hf.as_ref().map(ChunkMesh::pending_chunks),
+            self.chunk_mesh_mf.as_ref().map(ChunkMesh::pending_chunks),
+        ].into_iter().flatten().sum()
+    }
+
+    // writes the aggregated terrain mesh within the region as a wavefront OBJ, along with an MTL file next
+    // to it with a material per texture, returns the number of vertices written
+    pub(crate) fn export_obj(&self, path: &Path, region: ExportRegion) -> io::Result<usize> {
+        let mtl_path = path.with_extension("mtl");
+        let mtl_name = mtl_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let txtr_names = self.txtr_mapper.iter()
+            .flat_map(|txtr_mapper| txtr_mapper.ids())
+            .map(|(name, ind)| (ind, name))
+            .collect();
+        let meshes = [&self.chunk_rd_ef[..], &self.chunk_rd_hf[..], &self.chunk_rd_mf[..]].concat();
+
+        let mut obj = BufWriter::new(File::create(path)?);
+        let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+        let vertex_count = write_obj(&mut obj, Some((&mut mtl, &mtl_name)), &meshes, region, &txtr_names)?;
+        obj.flush()?;
+        mtl.flush()?;
+        Ok(vertex_count)
+    }
+
+    // region spanned by all the loaded extreme fidelity chunks
+    fn ef_region(&self) -> Option<ExportRegion> {
+        let bounds = self.chunk_mesh_ef.as_ref()?.chunk_bounds();
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for (pos, size, _) in bounds {
+            for k in 0..3 {
+                min[k] = min[k].min(pos[k]);
+                max[k] = max[k].max(pos[k]+size[k]);
+            }
+        }
+        let corner = |p: [f32; 3]| Length3D::new(Length::new::<blox>(p[0]), Length::new::<blox>(p[1]), Length::new::<blox>(p[2]));
+        Some(ExportRegion { min: corner(min), max: corner(max) })
+    }
+
+    // first solid block along the ray within the loaded extreme fidelity chunks, along with the struck face,
+    // in spectator mode the ray passes through the blocks the user is flying inside of
+    pub(crate) fn raycast(&self, origin: Length3D, dir: (f32, f32, f32), max_dist: f32) -> Option<(Position<blox>, FaceDir)> {
+        let chunk_mesh = self.chunk_mesh_ef.as_ref()?;
+        pick_block(&self.terrain_gen, &self.block_ind, origin, dir, max_dist, self.spectator_mode, |pos| chunk_mesh.is_loaded(pos))
+    }
+
+    // shares the blocks along with their edits (e.g. for the camera to collide with)
+    pub(crate) fn blocks(&self) -> TerrainBlocks {
+        TerrainBlocks {
+            terrain_gen: self.shared_gen.clone(),
+            solid: self.block_ind.iter().map(|block| block.mesh.collides()).collect(),
+        }
+    }
+
+    // places the block, or removes the block there if it's empty (e.g. air), meshing only the chunks around it again
+    pub(crate) fn set_block(&mut self, pos: Position<blox>, block: Block) {
+        let block = (!matches!(self.block_ind[block.0 as usize].mesh, MeshType::Empty)).then_some(block);
+        if let Some(ref mut store) = self.chunk_store {
+            if let Err(e) = store.edit(&self.terrain_gen, [pos.x as i32, pos.y as i32, pos.z as i32]) {
+                log::warn!("Failed to load the saved chunks around the edited block: {e}");
+            }
+        }
+        self.terrain_gen.set_block([pos.x as i32, pos.y as i32, pos.z as i32], block);
+
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_ef {
+            self.chunk_update_ef |= chunk_mesh.remesh_block(pos);
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_hf {
+            self.chunk_update_hf |= chunk_mesh.remesh_block(pos);
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_mf {
+            self.chunk_update_mf |= chunk_mesh.remesh_block(pos);
+        }
+    }
+
+    // loads the saved edits as far as the chunks of every tier may reach from the position (in render space), the
+    // chunks are generated around a center lagging behind the position by up to the update radius
+    fn load_saved_around(&mut self, pos: Length3D, render_distance: u32) {
+        let Some(ref mut store) = self.chunk_store else {
+            return;
+        };
+        let reach = lod_radii(render_distance).iter().zip(self.chunk_sizes)
+            .map(|((outer, _), size)| ((outer.0+outer.1+1)*size) as i32)
+            .max().unwrap_or(0);
+        let center = world_pos([pos.x.get::<blox>(), pos.y.get::<blox>(), pos.z.get::<blox>()]).map(|c| c.floor() as i32);
+        match store.load_within(&self.terrain_gen, center.map(|c| c-reach), center.map(|c| c+reach)) {
+            Ok(0) => {}
+            Ok(loaded) => log::info!("Loaded the block edits of {loaded} saved chunks"),
+            Err(e) => log::warn!("Failed to load the saved chunks: {e}"),
+        }
+    }
+
+    fn save_edits(&mut self) {
+        if let Some(ref mut store) = self.chunk_store {
+            match store.flush(&self.terrain_gen) {
+                Ok(0) => {}
+                Ok(written) => log::info!("Saved {written} edited chunks"),
+                Err(e) => log::warn!("Failed to save the edited chunks: {e}"),
+            }
+        }
+    }
+
+    fn target_block(&self) -> Option<(Position<blox>, FaceDir)> {
+        let ray = self.cursor_ray?;
+        self.raycast(ray.origin, (ray.dir[0], ray.dir[1], ray.dir[2]), self.reach)
+    }
+
+    fn break_target(&mut self) -> Vec<WorldEvent> {
+        let Some((pos, _)) = self.target_block() else {
+            return vec![];
+        };
+        let Some(block) = self.terrain_gen.get_block(pos.x as f64, pos.y as f64, pos.z as f64) else {
+            return vec![];
+        };
+        let Some(empty) = self.block_ind.iter().position(|block| matches!(block.mesh, MeshType::Empty)) else {
+            return vec![];
+        };
+        self.set_block(pos, Block(empty as u16));
+        self.edit_events(pos, block, BlockAction::Break)
+    }
+
+    // against the targeted face
+    fn place_selected(&mut self) -> Vec<WorldEvent> {
+        let (Some((target, face)), Some(block)) = (self.target_block(), self.selected_block) else {
+            return vec![];
+        };
+        let pos = target.adjacent(face);
+        self.set_block(pos, block);
+        self.edit_events(pos, block, BlockAction::Place)
+    }
+
+    // the target after the edit, along with the sound of the edited block
+    fn edit_events(&self, pos: Position<blox>, block: Block, action: BlockAction) -> Vec<WorldEvent> {
+        let block_pos = Length3D::new(
+            Length::new::<blox>(pos.x as f32), Length::new::<blox>(pos.y as f32), Length::new::<blox>(pos.z as f32),
+        );
+        let mut events = vec![WorldEvent::TargetBlock(self.target_block())];
+        events.extend(block_sound(&self.block_ind, block_pos, block, action));
+        events
+    }
+
+    fn step_sound(&mut self, pos: Length3D) -> Option<WorldEvent> {
+        let below = block_underfoot(pos);
+        if self.step_block == Some(below) {
+            return None;
+        }
+        self.step_block = Some(below);
+
+        let block = self.terrain_gen.get_block(below[0] as f64, below[1] as f64, below[2] as f64)?;
+        let block_pos = Length3D::new(
+            Length::new::<blox>(below[0] as f32),
+            Length::new::<blox>(below[1] as f32),
+            Length::new::<blox>(below[2] as f32),
+        );
+        block_sound(&self.block_ind, block_pos, block, BlockAction::Step)
+    }
+
+    // uploads the vertices and indices into device local buffers of the pool through a staging buffer, the render
+    // data is only handed to the shader once the transfer fence signals
+    // the pool only hands out buffers no frame in flight draws, so a buffer written again is never read meanwhile
+    // without vertices only the indices are uploaded (e.g. sorted again), drawing the vertices uploaded before
+    fn upload_indexed<V: Copy>(&mut self, verts: Option<&[V]>, inds: &[u32], purpose: RenderDataPurpose) -> (vk::Fence, TerrainUpload) {
+        let mut local_buffer = |size, usage| {
+            let (vi, device) = (self.vi.clone(), self.device.clone());
+            self.buffer_pool.acquire((purpose, usage), size, |capacity| {
+                let (buf, mem, _) = unsafe {
+                    create_local_buffer(vi, device, capacity, vk::BufferUsageFlags::TRANSFER_DST | usage)
+                };
+                (buf, mem)
+            })
+        };
+
+        let mut staging = vec![];
+        let mut copies = vec![];
+        let mut render_data = vec![];
+        if let Some(verts) = verts {
+            let (host_vbo, host_vmo, _, host_vbo_size) = unsafe {
+                create_host_buffer(self.vi.clone(), self.device.clone(), verts, vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::VERTEX_BUFFER, true)
+            };
+            let (local_vbo, local_vmo, vbo_reused) = local_buffer(host_vbo_size, vk::BufferUsageFlags::VERTEX_BUFFER);
+            log::trace!(target: MTXG_RENDER_LOG, "UPLOAD {purpose:?} VBO (REUSED {vbo_reused})");
+            staging.push((host_vbo, host_vmo));
+            copies.push((host_vbo, local_vbo, host_vbo_size));
+            render_data.push(RenderData::RecreateVertexBuffer(local_vbo, local_vmo, purpose));
+        }
+        let (host_ibo, host_imo, _, host_ibo_size) = unsafe {
+            create_host_buffer(self.vi.clone(), self.device.clone(), inds, vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::INDEX_BUFFER, true)
+        };
+        let (local_ibo, local_imo, ibo_reused) = local_buffer(host_ibo_size, vk::BufferUsageFlags::INDEX_BUFFER);
+        log::trace!(target: MTXG_RENDER_LOG, "UPLOAD {purpose:?} IBO (REUSED {ibo_reused})");
+        staging.push((host_ibo, host_imo));
+        copies.push((host_ibo, local_ibo, host_ibo_size));
+        render_data.push(RenderData::RecreateIndexBuffer(local_ibo, local_imo, inds.len() as u32, purpose));
+
+        let (cmd_buf, fence) = unsafe { self.ctx.record_fenced(|cmd_buf| {
+            for (host_buf, local_buf, size) in &copies {
+                let buf_region = [vk::BufferCopy {src_offset: 0, dst_offset: 0, size: *size}];
+                self.device.cmd_copy_buffer(cmd_buf, *host_buf, *local_buf, &buf_region);
+            }
+            vertex_upload_barrier(&self.device, cmd_buf);
+        }) };
+
+        (fence, TerrainUpload {
+            cmd_buf,
+            staging,
+            purpose,
+            render_data,
+            pooled: true,
+            cleared: false,
+            retired: vec![],
+            allocations: vec![],
+        })
+    }
+
+    // uploads each of the new chunks of the aggregated mesh into a range of the chunk blocks of the purpose (its
+    // vertices followed by its indices) through a single staging buffer, so only the chunks meshed again are uploaded,
+    // the ranges of the retired chunks are freed once the new ones reach the shader
+    fn upload_chunks(&mut self, verts: &[ChunkVertex], inds: &[u32], chunks: Vec<ChunkRange>, retired: Vec<u64>,
+                     purpose: RenderDataPurpose) -> (vk::Fence, TerrainUpload) {
+        let vert_size = mem::size_of::<ChunkVertex>() as vk::DeviceSize;
+        let ind_size = mem::size_of::<u32>() as vk::DeviceSize;
+        let chunk_size = |range: &ChunkRange| range.verts.len() as vk::DeviceSize*vert_size + range.inds.len() as vk::DeviceSize*ind_size;
+        let staging_size: vk::DeviceSize = chunks.iter().map(chunk_size).sum();
+        let allocator = &mut self.chunk_allocators[Self::culled_purpose_index(purpose).unwrap()];
+
+        let mut staging = vec![];
+        let mut copies = vec![];
+        let mut render_data = vec![];
+        let mut allocations = vec![];
+        if staging_size > 0 {
+            let (host_buf, host_mem, _) = unsafe {
+                allocate_buffer(self.vi.clone(), self.device.clone(), staging_size, vk::BufferUsageFlags::TRANSFER_SRC,
+                                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)
+            };
+            let host_ptr = unsafe {
+                self.device.map_memory(host_mem, 0, staging_size, vk::MemoryMapFlags::empty()).unwrap() as *mut u8
+            };
+            let mut offset = 0;
+            for range in chunks {
+                let size = chunk_size(&range);
+                let chunk_verts = &verts[range.verts.start as usize..range.verts.end as usize];
+                let chunk_inds: Vec<u32> = inds[range.inds.start as usize..range.inds.end as usize].iter()
+                    .map(|ind| ind-range.verts.start)
+                    .collect();
+                let verts_size = chunk_verts.len() as vk::DeviceSize*vert_size;
+                unsafe {
+                    update_buffer(host_ptr.add(offset as usize) as *mut ffi::c_void, chunk_verts, verts_size);
+                    update_buffer(host_ptr.add((offset+verts_size) as usize) as *mut ffi::c_void, &chunk_inds, size-verts_size);
+                }
+
+                // the ranges are aligned to the vertex size, so the vertices are drawn from a vertex offset
+                let allocation = allocator.allocate(size, |capacity| {
+                    let (buf, mem, _) = unsafe {
+                        create_local_buffer(self.vi.clone(), self.device.clone(), capacity,
+                                            vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER)
+                    };
+                    (buf, mem)
+                });
+                let first_vert = (allocation.offset/vert_size) as u32;
+                let first_ind = ((allocation.offset+verts_size)/ind_size) as u32;
+                copies.push((allocation.buf, vk::BufferCopy {src_offset: offset, dst_offset: allocation.offset, size}));
+                allocations.push((range.mesh_id, allocation));
+                render_data.push(RenderData::RecreateChunkBuffer(ChunkRange {
+                    verts: first_vert..first_vert+range.verts.len() as u32,
+                    inds: first_ind..first_ind+range.inds.len() as u32,
+                    ..range
+                }, allocation.buf, purpose));
+                offset += size;
+            }
+            unsafe { self.device.unmap_memory(host_mem); }
+            staging.push((host_buf, host_mem));
+        }
+
+        // recorded even without any new chunks, so the retired ones are freed in order with the uploads before
+        let (cmd_buf, fence) = unsafe { self.ctx.record_fenced(|cmd_buf| {
+            for (local_buf, region) in &copies {
+                self.device.cmd_copy_buffer(cmd_buf, staging[0].0, *local_buf, &[*region]);
+            }
+            if !copies.is_empty() {
+                vertex_upload_barrier(&self.device, cmd_buf);
+            }
+        }) };
+
+        (fence, TerrainUpload {
+            cmd_buf,
+            staging,
+            purpose,
+            pooled: false,
+            render_data,
+            cleared: false,
+            retired,
+            allocations,
+        })
+    }
+
+    // frees the staging buffers of the uploads whose transfer completed, and releases their render data
+    fn poll_uploads(&mut self) {
+        let ctx = self.ctx.clone();
+        let device = self.device.clone();
+        let to_render = &mut self.to_render;
+        let completed_uploads = &mut self.completed_uploads;
+        let chunk_ranges = &mut self.chunk_ranges;
+        let cull_update = &mut self.cull_update;
+        let buffer_indices = &mut self.buffer_indices;
+        let buffer_pool = &mut self.buffer_pool;
+        let chunk_allocators = &mut self.chunk_allocators;
+        let chunk_allocations = &mut self.chunk_allocations;
+
+        self.pending_uploads.poll(
+            |fence| unsafe { ctx.fence_signaled(fence) },
+            |fence, upload| unsafe {
+                ctx.finalize(upload.cmd_buf, fence);
+                for (buf, mem) in upload.staging {
+                    device.destroy_buffer(buf, None);
+                    device.free_memory(mem, None);
+                }
+                completed_uploads.push(upload.purpose);
+                if let Some(ind) = Self::culled_purpose_index(upload.purpose) {
+                    if upload.cleared {
+                        // meshed before the chunk buffers of the purpose were cleared, so never drawn
+                        for (_, allocation) in upload.allocations {
+                            chunk_allocators[ind].free(allocation);
+                        }
+                        return;
+                    }
+                    for mesh_id in &upload.retired {
+                        if let Some(allocation) = chunk_allocations[ind].remove(mesh_id) {
+                            chunk_allocators[ind].retire(allocation);
+                        }
+                    }
+                    chunk_allocations[ind].extend(upload.allocations);
+                    let retired: HashSet<u64> = upload.retired.iter().copied().collect();
+                    let ranges = &mut chunk_ranges[ind];
+                    ranges.retain(|range| !retired.contains(&range.mesh_id));
+                    ranges.extend(upload.render_data.iter().filter_map(|render_data| match render_data {
+                        RenderData::RecreateChunkBuffer(range, _, _) => Some(range.clone()),
+                        _ => None,
+                    }));
+                    buffer_indices[ind] = ranges.iter().map(|range| range.inds.len()).sum();
+                    if !upload.retired.is_empty() {
+                        to_render.push(RenderData::ClearChunkBuffers(upload.retired, upload.purpose));
+                    }
+                    *cull_update = true;
+                }
+                if upload.pooled {
+                    for render_data in &upload.render_data {
+                        if let RenderData::RecreateVertexBuffer(buf, _, _) | RenderData::RecreateIndexBuffer(buf, _, _, _) = render_data {
+                            if upload.cleared {
+                                buffer_pool.release(*buf);
+                            } else {
+                                buffer_pool.hand_over(*buf);
+                            }
+                        }
+                    }
+                    if upload.cleared {
+                        return;
+                    }
+                }
+                to_render.extend(upload.render_data.into_iter()
+                    .inspect(|render_data| if let RenderData::RecreateIndexBuffer(_, _, len, purpose) = render_data {
+                        if let Some(ind) = Self::drawn_purpose_index(*purpose) {
+                            buffer_indices[ind] = *len as usize;
+                        }
+                    })
+                );
+            }
+        );
+    }
+
+    // the shader frees the buffers of the purpose, so they can't be written again
+    fn clear_buffers(&mut self, purpose: RenderDataPurpose) {
+        self.to_render.push(RenderData::ClearBuffer(purpose));
+        if let Some(ind) = Self::drawn_purpose_index(purpose) {
+            self.buffer_indices[ind] = 0;
+        }
+        if let Some(ind) = Self::culled_purpose_index(purpose) {
+            self.drawn_ranges[ind] = None;
+            for (_, allocation) in self.chunk_allocations[ind].drain() {
+                self.chunk_allocators[ind].retire(allocation);
+            }
+        }
+        for usage in [vk::BufferUsageFlags::VERTEX_BUFFER, vk::BufferUsageFlags::INDEX_BUFFER] {
+            self.buffer_pool.clear(&(purpose, usage));
+        }
+        for upload in self.pending_uploads.iter_mut().filter(|upload| upload.purpose == purpose) {
+            upload.cleared = true;
+        }
+    }
+
+    // index into the chunk ranges of the purposes drawn per chunk
+    fn culled_purpose_index(purpose: RenderDataPurpose) -> Option<usize> {
+        match purpose {
+            RenderDataPurpose::TerrainOpaque => Some(0),
+            RenderDataPurpose::TerrainTransparent => Some(1),
+            _ => None,
+        }
+    }
+
+    // index into the index counts of the terrain buffers
+    fn drawn_purpose_index(purpose: RenderDataPurpose) -> Option<usize> {
+        match purpose {
+            RenderDataPurpose::TerrainTranslucent => Some(2),
+            _ => Self::culled_purpose_index(purpose),
+        }
+    }
+
+    // loaded chunks across the tiers, the opaque chunks drawn, and the triangles drawn of all the terrain buffers
+    fn render_stats(&self) -> (usize, usize, usize) {
+        let chunks = self.chunk_mesh_ef.as_ref().map_or(0, ChunkMesh::loaded_chunks)
+            + self.chunk_mesh_hf.as_ref().map_or(0, ChunkMesh::loaded_chunks)
+            + self.chunk_mesh_mf.as_ref().map_or(0, ChunkMesh::loaded_chunks);
+        let visible = self.drawn_ranges[0].map_or(self.chunk_ranges[0].len(), |(chunks, _)| chunks);
+        let indices = self.drawn_ranges.iter().zip(self.buffer_indices)
+            .map(|(drawn, buffer)| drawn.map_or(buffer, |(_, indices)| indices))
+            .sum::<usize>() + self.buffer_indices[2];
+        (chunks, visible, indices/3)
+    }
+
+    // the camera position to sort the translucent mesh from, when it changed or the camera moved far enough since
+    fn translucent_resort(&self) -> Option<[f32; 3]> {
+        let (verts, inds, _) = self.translucent_mesh.as_ref()?;
+        if verts.is_empty() || inds.is_empty() {
+            return None;
+        }
+        match self.sorted_eye {
+            None => Some(self.eye),
+            Some(_) if self.translucent_sort == TranslucentSort::Unsorted => None,
+            Some(sorted) => {
+                let moved2 = (0..3).map(|k| (self.eye[k]-sorted[k]).powi(2)).sum::<f32>();
+                (moved2 >= RESORT_DISTANCE*RESORT_DISTANCE).then_some(self.eye)
+            }
+        }
+    }
+
+    // draws only the chunks within the camera frustum
+    fn cull_chunks(&mut self) {
+        let Some(frustum) = self.frustum else {
+            return;
+        };
+        for purpose in [RenderDataPurpose::TerrainOpaque, RenderDataPurpose::TerrainTransparent] {
+            let ind = Self::culled_purpose_index(purpose).unwrap();
+            let ranges = &self.chunk_ranges[ind];
+            let drawn = cull_ranges(ranges, &frustum);
+            if purpose == RenderDataPurpose::TerrainOpaque {
+                self.chunk_culling.replace((drawn.len(), ranges.len()-drawn.len()));
+            }
+            let indices = drawn.iter().map(|range| range.inds.len()).sum();
+            self.drawn_ranges[ind].replace((drawn.len(), indices));
+            let mesh_ids = drawn.iter().map(|range| range.mesh_id).collect();
+            self.to_render.push(RenderData::SetDrawChunks(mesh_ids, purpose));
+        }
+    }
+
+    // wireframe of all loaded chunks, color-coded by their LOD tier
+    fn chunk_borders_upload(&mut self) -> Option<(vk::Fence, TerrainUpload)> {
+        let mut verts = Vec::new();
+        let mut inds = Vec::new();
+
+        let mut append_borders = |bounds: Vec<([f32; 3], [f32; 3], bool)>, color: [f32; 3]| {
+            let (mut v, i) = chunk_border_mesh(&bounds, color);
+            let ind_count = verts.len() as u32;
+            inds.extend(i.iter().map(|i| i+ind_count));
+            verts.append(&mut v);
+        };
+        if let Some(ref chunk_mesh) = self.chunk_mesh_ef {
+            append_borders(chunk_mesh.chunk_bounds(), BORDER_COLOR_EF);
+        }
+        if let Some(ref chunk_mesh) = self.chunk_mesh_hf {
+            append_borders(chunk_mesh.chunk_bounds(), BORDER_COLOR_HF);
+        }
+        if let Some(ref chunk_mesh) = self.chunk_mesh_mf {
+            append_borders(chunk_mesh.chunk_bounds(), BORDER_COLOR_MF);
+        }
+
+        if verts.is_empty() {
+            None
+        } else {
+            Some(self.upload_indexed(Some(&verts), &inds, RenderDataPurpose::DebugChunkBorders))
         }
     }
 }
 
+// chunks are generated on the worker threads in the background, outliving any borrowed block data
+impl Terrain<'static> {
+    // drops all loaded chunks and generates them again with the terrain generator shaped by the new params (keeping
+    // the seed and the edits), or the current one, the old chunk buffers are freed before the regenerated ones are uploaded
+    pub(crate) fn regenerate_all(&mut self, params: Option<TerrainParams>) {
+        if let Some(params) = params {
+            self.terrain_gen = Arc::new(self.terrain_gen.with_params(params));
+            self.shared_gen.replace(self.terrain_gen.clone());
+        }
+        let Some(txtr_mapper) = self.txtr_mapper.clone() else {
+            return;  // nothing generated yet
+        };
+
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_ef {
+            chunk_mesh.swap_generator(ChunkGeneratorEF::new(
+                self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone(), self.chunk_sizes[0], self.lod_downsample[0],
+                neighbour_downsamples(self.lod_downsample, 0),
+            ));
+            chunk_mesh.regenerate();
+            self.chunk_update_ef = true;
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_hf {
+            chunk_mesh.swap_generator(ChunkGeneratorHF::new(
+                self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone(), self.chunk_sizes[1], self.lod_downsample[1],
+                neighbour_downsamples(self.lod_downsample, 1),
+            ));
+            chunk_mesh.regenerate();
+            self.chunk_update_hf = true;
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_mf {
+            chunk_mesh.swap_generator(ChunkGeneratorMF::new(
+                self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone(), self.chunk_sizes[2], self.lod_downsample[2],
+                neighbour_downsamples(self.lod_downsample, 2),
+            ));
+            chunk_mesh.regenerate();
+            self.chunk_update_mf = true;
+        }
+        self.clear_chunk_buffers = true;
+    }
+
+    // resizes the border and inner radii of every tier, generating the chunks within a larger render distance in
+    // the background, while a smaller one frees the chunk buffers before the remaining chunks are uploaded again
+    pub(crate) fn set_render_distance(&mut self, render_distance: u32) {
+        if render_distance == self.render_distance {
+            return;
+        }
+        if let Some(center) = self.chunk_mesh_ef.as_ref().map(|chunk_mesh| chunk_mesh.central_pos) {
+            self.load_saved_around(center, render_distance);
+        }
+        let [(outer_ef, inner_ef), (outer_hf, inner_hf), (outer_mf, inner_mf)] = lod_radii(render_distance);
+
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_ef {
+            chunk_mesh.set_radius(outer_ef, inner_ef);
+            self.chunk_update_ef |= chunk_mesh.update(UpdateChunk::Forced);
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_hf {
+            chunk_mesh.set_radius(outer_hf, inner_hf);
+            self.chunk_update_hf |= chunk_mesh.update(UpdateChunk::Forced);
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_mf {
+            chunk_mesh.set_radius(outer_mf, inner_mf);
+            self.chunk_update_mf |= chunk_mesh.update(UpdateChunk::Forced);
+        }
+        // a purpose left without any faces is not uploaded at all, which would leave its old buffer drawn
+        self.clear_chunk_buffers |= render_distance < self.render_distance && self.txtr_mapper.is_some();
+        self.chunk_borders_update |= self.chunk_borders;
+        self.render_distance = render_distance;
+    }
+}
+
 impl Component for Terrain<'static> {
     fn render(&self) -> Vec<RenderData> {
         // println!("RENDER() {}", self.to_render.len());
         self.to_render.clone()
     }
 
+    fn prepare_frame(&mut self, frame_index: usize) {
+        self.buffer_pool.collect(frame_index);
+        for allocator in &mut self.chunk_allocators {
+            allocator.collect(frame_index);
+        }
+        let emptied = self.chunk_allocators.iter_mut().flat_map(SubAllocator::take_emptied);
+        for (buf, mem) in self.buffer_pool.take_outgrown().into_iter().chain(emptied) {
+            unsafe {
+                self.device.destroy_buffer(buf, None);
+                self.device.free_memory(mem, None);
+            }
+        }
+    }
+
     fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
         match event {
             WorldEvent::UserPosition(pos) if !self.spectator_mode => {
+                self.load_saved_around(pos, self.render_distance);
                 if let Some(ref mut chunk_mesh) = self.chunk_mesh_ef {
                     let need_update = chunk_mesh.update(UpdateChunk::NewPos(pos));
                     self.chunk_update_ef = self.chunk_update_ef || need_update;
@@ -179,69 +1195,234 @@ impl Component for Terrain<'static> {
                     let need_update = chunk_mesh.update(UpdateChunk::NewPos(pos));
                     self.chunk_update_mf = self.chunk_update_mf || need_update;
                 }
+
+                return self.step_sound(pos).into_iter().collect();
             }
             WorldEvent::NewTextureMapper(txtr_mapper) => {
+                self.load_saved_around(Length3D::origin(), self.render_distance);
+                let [(outer_ef, inner_ef), (outer_hf, inner_hf), (outer_mf, inner_mf)] = lod_radii(self.render_distance);
+
                 let mut chunk_mesh_ef = ChunkMesh::new(
                     Length3D::origin(),
-                    ChunkRadius(4, 2), None,
+                    outer_ef, inner_ef,
                     ChunkGeneratorEF::new(
-                        self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone()
+                        self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone(), self.chunk_sizes[0], self.lod_downsample[0],
+                        neighbour_downsamples(self.lod_downsample, 0),
                     ),
+                    self.workers.clone(), self.chunk_unload, self.chunk_priority, self.vertex_limit, self.world_bounds,
                 );
                 chunk_mesh_ef.update(UpdateChunk::Forced);
                 self.chunk_mesh_ef.replace(chunk_mesh_ef);
 
                 let mut chunk_mesh_hf = ChunkMesh::new(
                     Length3D::origin(),
-                    ChunkRadius(4, 2), Some(ChunkRadius(4, 2)),
+                    outer_hf, inner_hf,
                     ChunkGeneratorHF::new(
-                        self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone()
+                        self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone(), self.chunk_sizes[1], self.lod_downsample[1],
+                        neighbour_downsamples(self.lod_downsample, 1),
                     ),
+                    self.workers.clone(), self.chunk_unload, self.chunk_priority, self.vertex_limit, self.world_bounds,
                 );
                 chunk_mesh_hf.update(UpdateChunk::Forced);
                 self.chunk_mesh_hf.replace(chunk_mesh_hf);
 
                 let mut chunk_mesh_mf = ChunkMesh::new(
                     Length3D::origin(),
-                    ChunkRadius(2, 1), Some(ChunkRadius(4, 2)),
+                    outer_mf, inner_mf,
                     ChunkGeneratorMF::new(
-                        self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone()
+                        self.block_ind.clone(), txtr_mapper.clone(), self.terrain_gen.clone(), self.chunk_sizes[2], self.lod_downsample[2],
+                        neighbour_downsamples(self.lod_downsample, 2),
                     ),
+                    self.workers.clone(), self.chunk_unload, self.chunk_priority, self.vertex_limit, self.world_bounds,
                 );
                 chunk_mesh_mf.update(UpdateChunk::Forced);
                 self.chunk_mesh_mf.replace(chunk_mesh_mf);
+
+                self.txtr_mapper.replace(txtr_mapper);
+                self.initial_generation.forced();
+            }
+            WorldEvent::RegenerateWorld => {
+                self.regenerate_all(None);
+            }
+            WorldEvent::SetTerrainParams(params) => {
+                self.regenerate_all(Some(params));
+            }
+            // e.g. tuning the height of the terrain at runtime
+            WorldEvent::KeyPressed(key @ (VirtualKeyCode::F2 | VirtualKeyCode::F3)) => {
+                let params = self.terrain_gen.params();
+                let step = if key == VirtualKeyCode::F3 {AMPLITUDE_STEP} else {1.0/AMPLITUDE_STEP};
+                return vec![WorldEvent::SetTerrainParams(TerrainParams { amplitude: params.amplitude*step, ..params })];
+            }
+            WorldEvent::SetRenderDistance(render_distance) => {
+                if render_distance == 0 {
+                    log::warn!("Ignoring the render distance of 0, it must be at least a chunk");
+                } else {
+                    self.set_render_distance(render_distance);
+                }
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd) => {
+                return vec![WorldEvent::SetRenderDistance(self.render_distance+1)];
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract) => {
+                return vec![WorldEvent::SetRenderDistance((self.render_distance-1).max(1))];
+            }
+            WorldEvent::Start => {
+                return vec![WorldEvent::SetRenderDistance(self.render_distance), WorldEvent::WorldSeed(self.terrain_gen.seed())];
+            }
+            WorldEvent::ExportTerrain => {
+                if let Some(region) = self.ef_region() {
+                    match self.export_obj(Path::new(EXPORT_PATH), region) {
+                        Ok(vertex_count) => log::info!("Exported {vertex_count} terrain vertices to {EXPORT_PATH}"),
+                        Err(e) => log::warn!("Failed to export the terrain to {EXPORT_PATH}: {e}"),
+                    }
+                }
             }
             WorldEvent::SpectatorMode(enabled) => {
                 self.spectator_mode = enabled;
             }
+            WorldEvent::UserReach(reach) => {
+                self.reach = reach;
+            }
+            WorldEvent::CameraRay { origin, .. } => {
+                self.eye = [origin.x.get::<blox>(), origin.y.get::<blox>(), origin.z.get::<blox>()];
+            }
+            WorldEvent::CameraFrustum(frustum) => {
+                self.frustum.replace(frustum);
+                self.cull_update = true;
+            }
+            WorldEvent::DeltaTime(_) => {
+                // only counted, so cheap enough for every frame
+                let stats = self.render_stats();
+                if self.render_stats.replace(stats) != Some(stats) {
+                    let (chunks, visible, tris) = stats;
+                    return vec![WorldEvent::RenderStats { chunks, visible, tris }];
+                }
+            }
+            WorldEvent::CursorRay(ray) => {
+                self.cursor_ray.replace(ray);
+                return vec![WorldEvent::TargetBlock(self.target_block())];
+            }
+            WorldEvent::SelectedBlockChanged(block) => {
+                self.selected_block.replace(block);
+            }
+            WorldEvent::PointerOverUi(over_ui) => {
+                self.pointer_over_ui = over_ui;
+            }
+            WorldEvent::LeftButtonPressed if !self.pointer_over_ui => {
+                return self.break_target();
+            }
+            WorldEvent::RightButtonPressed if !self.pointer_over_ui => {
+                return self.place_selected();
+            }
+            WorldEvent::ChunkBorders(enabled) => {
+                self.chunk_borders = enabled;
+                self.chunk_borders_update = true;
+            }
+            WorldEvent::Tick { .. } => {
+                // for the delayed unloading, since the chunks are otherwise only updated when the user moves
+                let now = Instant::now();
+                if let Some(ref mut chunk_mesh) = self.chunk_mesh_ef {
+                    chunk_mesh.unload_chunks(now);
+                }
+                if let Some(ref mut chunk_mesh) = self.chunk_mesh_hf {
+                    chunk_mesh.unload_chunks(now);
+                }
+                if let Some(ref mut chunk_mesh) = self.chunk_mesh_mf {
+                    chunk_mesh.unload_chunks(now);
+                }
+
+                let mut events: Vec<WorldEvent> = self.completed_uploads.drain(..)
+                    .map(WorldEvent::BufferUploadComplete)
+                    .collect();
+                if self.initial_generation.report() {
+                    events.push(WorldEvent::InitialGenerationComplete);
+                }
+                if let Some((drawn, culled)) = self.chunk_culling.take() {
+                    events.push(WorldEvent::ChunkCulling { drawn, culled });
+                }
+                return events;
+            }
             _ => {}
         }
 
         vec![]
     }
 
+    fn diagnostics(&self, diagnostics: &mut Diagnostics) {
+        // loaded (visible) chunks, and the vertices of their meshes
+        let chunks = |chunk_bounds: Option<Vec<([f32; 3], [f32; 3], bool)>>| match chunk_bounds {
+            Some(chunk_bounds) => {
+                let visible = chunk_bounds.iter().filter(|(_, _, visible)| *visible).count();
+                format!("{} ({visible} visible)", chunk_bounds.len())
+            }
+            None => String::from("none"),
+        };
+        let vertices = |rd: &[(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)]| {
+            rd.iter().map(|(verts, _, _, _)| verts.len()).sum::<usize>().to_string()
+        };
+
+        diagnostics.section("terrain", vec![
+            ("chunks_ef", chunks(self.chunk_mesh_ef.as_ref().map(ChunkMesh::chunk_bounds))),
+            ("chunks_hf", chunks(self.chunk_mesh_hf.as_ref().map(ChunkMesh::chunk_bounds))),
+            ("chunks_mf", chunks(self.chunk_mesh_mf.as_ref().map(ChunkMesh::chunk_bounds))),
+            ("vertices_ef", vertices(&self.chunk_rd_ef)),
+            ("vertices_hf", vertices(&self.chunk_rd_hf)),
+            ("vertices_mf", vertices(&self.chunk_rd_mf)),
+            ("pending_chunks", self.pending_chunks().to_string()),
+            ("pending_uploads", (!self.pending_uploads.is_empty()).to_string()),
+            ("buffer_allocations", self.buffer_pool.allocations().to_string()),
+            ("chunk_block_allocations", self.chunk_allocators.iter().map(SubAllocator::allocations).sum::<usize>().to_string()),
+        ]);
+    }
+
     fn update(&mut self) {
         self.to_render.clear();
 
+        if self.chunk_store.as_ref().is_some_and(|store| store.flush_due(Instant::now())) {
+            self.save_edits();
+        }
+
+        if self.clear_chunk_buffers {
+            for purpose in [RenderDataPurpose::TerrainOpaque, RenderDataPurpose::TerrainTransparent, RenderDataPurpose::TerrainTranslucent] {
+                self.clear_buffers(purpose);
+            }
+            self.chunk_ranges = [vec![], vec![]];
+            self.uploaded_chunks = [HashSet::new(), HashSet::new()];
+            self.translucent_mesh = None;
+            self.sorted_eye = None;
+            self.clear_chunk_buffers = false;
+        }
+
+        // chunks finished generating in the background
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_ef {
+            self.chunk_update_ef |= chunk_mesh.poll_completed_chunks();
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_hf {
+            self.chunk_update_hf |= chunk_mesh.poll_completed_chunks();
+        }
+        if let Some(ref mut chunk_mesh) = self.chunk_mesh_mf {
+            self.chunk_update_mf |= chunk_mesh.poll_completed_chunks();
+        }
+
         let mut any_chunk_update = false;
-        let mut render_data: Vec<(Vec<ChunkVertex>, Vec<u32>, RenderDataPurpose)> = Vec::new();
-
-        let mut data_aggregator = |rd: Vec<(Vec<ChunkVertex>, Vec<u32>, RenderDataPurpose)>| {
-            for (mut verts, inds, purpose) in rd {
-                let mut appended = false;
-                for (v, i, p) in render_data.iter_mut() {
-                    if *p == purpose {
-                        let ind_count = v.len() as u32;
-                        let mut offsetted_ind = inds.iter().map(|i| i+ind_count).collect();
-
-                        v.append(&mut verts);
-                        i.append(&mut offsetted_ind);
-                        appended = true;
-                        break;
-                    }
-                }
-                if !appended {
-                    render_data.push((verts, inds, purpose));
+        let mut render_data: Vec<(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)> = Vec::new();
+
+        let mut data_aggregator = |rd: Vec<(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)>| {
+            for (mut verts, inds, ranges, purpose) in rd {
+                if let Some((v, i, r, _)) = render_data.iter_mut().find(|(_, _, _, p)| *p == purpose) {
+                    let ind_count = v.len() as u32;
+                    let mut offsetted_ind = inds.iter().map(|i| i+ind_count).collect();
+                    let ind_ofs = i.len() as u32;
+                    r.extend(ranges.into_iter().map(|range| ChunkRange {
+                        verts: range.verts.start+ind_count..range.verts.end+ind_count,
+                        inds: range.inds.start+ind_ofs..range.inds.end+ind_ofs,
+                        ..range
+                    }));
+
+                    v.append(&mut verts);
+                    i.append(&mut offsetted_ind);
+                } else {
+                    render_data.push((verts, inds, ranges, purpose));
                 }
             }
         };
@@ -273,50 +1454,272 @@ impl Component for Terrain<'static> {
             data_aggregator(self.chunk_rd_hf.clone());
             data_aggregator(self.chunk_rd_mf.clone());
 
-            self.to_render = render_data.iter()
-                .filter(|(verts, inds, purpose)| {
-                    println!("RENDER DATA: {:?} {:?} {:?}", verts.len(), inds.len(), purpose);
+            // uploaded once sorted from the camera
+            if let Some(ind) = render_data.iter().position(|(_, _, _, purpose)| *purpose == RenderDataPurpose::TerrainTranslucent) {
+                let (verts, inds, ranges, _) = render_data.swap_remove(ind);
+                self.translucent_mesh.replace((verts, inds, ranges));
+                self.sorted_eye = None;
+            }
 
-                    verts.len() != 0 && inds.len() != 0
-                })
-                .flat_map(|(verts, inds, purpose)| {
-                    let (host_vbo, host_vmo, _, host_vbo_size) = unsafe {
-                        create_host_buffer(self.vi.clone(), self.device.clone(), &verts, vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::VERTEX_BUFFER, true)
-                    };
-                    let (host_ibo, host_imo, _, host_ibo_size) = unsafe {
-                        create_host_buffer(self.vi.clone(), self.device.clone(), &inds, vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::INDEX_BUFFER, true)
-                    };
-                    let (local_vbo, local_vmo, _) = unsafe {
-                        create_local_buffer(self.vi.clone(), self.device.clone(), host_vbo_size, vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER)
-                    };
-                    let (local_ibo, local_imo, _) = unsafe {
-                        create_local_buffer(self.vi.clone(), self.device.clone(), host_ibo_size, vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER)
-                    };
+            let mut uploads: Vec<(vk::Fence, TerrainUpload)> = Vec::new();
+            for (verts, inds, ranges, purpose) in render_data {
+                log::trace!(target: MTXG_RENDER_LOG, "RENDER DATA: {:?} {:?} {:?}", verts.len(), inds.len(), purpose);
 
-                    unsafe { self.ctx.record(|cmd_buf| {
-                        let vert_buf_region = [vk::BufferCopy {src_offset: 0, dst_offset: 0, size: host_vbo_size}];
-                        self.device.cmd_copy_buffer(cmd_buf, host_vbo, local_vbo, &vert_buf_region);
-                        let indx_buf_region = [vk::BufferCopy {src_offset: 0, dst_offset: 0, size: host_ibo_size}];
-                        self.device.cmd_copy_buffer(cmd_buf, host_ibo, local_ibo, &indx_buf_region);
-                    }); }
-
-                    unsafe {
-                        self.device.destroy_buffer(host_vbo, None);
-                        self.device.free_memory(host_vmo, None);
-                        self.device.destroy_buffer(host_ibo, None);
-                        self.device.free_memory(host_imo, None);
+                if let Some(ind) = Self::culled_purpose_index(purpose) {
+                    // only the chunks meshed since, a purpose left without any chunks still retires the uploaded ones
+                    let (chunks, retired) = diff_chunks(&mut self.uploaded_chunks[ind], ranges);
+                    if !chunks.is_empty() || !retired.is_empty() {
+                        uploads.push(self.upload_chunks(&verts, &inds, chunks, retired, purpose));
                     }
+                } else if verts.len() != 0 && inds.len() != 0 {
+                    uploads.push(self.upload_indexed(Some(&verts), &inds, purpose));
+                }
+            }
+            for (fence, upload) in uploads {
+                self.pending_uploads.push(fence, upload);
+            }
+            // the initial terrain is only meshed once every chunk has been generated
+            if self.pending_chunks() == 0 {
+                self.initial_generation.meshed();
+            }
+        }
+
+        if let Some(eye) = self.translucent_resort() {
+            let (verts, inds, ranges) = self.translucent_mesh.take().unwrap();
+            let sorted_inds = sort_translucent(&verts, &inds, &ranges, eye, self.translucent_sort);
+            // the vertices of a mesh sorted before are already uploaded, only their order changed
+            let new_verts = self.sorted_eye.is_none().then_some(verts.as_slice());
+            let (fence, upload) = self.upload_indexed(new_verts, &sorted_inds, RenderDataPurpose::TerrainTranslucent);
+            self.pending_uploads.push(fence, upload);
+            self.translucent_mesh.replace((verts, inds, ranges));
+            self.sorted_eye.replace(eye);
+        }
+
+        if self.chunk_borders_update || (self.chunk_borders && any_chunk_update) {
+            match self.chunk_borders.then(|| self.chunk_borders_upload()).flatten() {
+                Some((fence, upload)) => self.pending_uploads.push(fence, upload),
+                None => self.clear_buffers(RenderDataPurpose::DebugChunkBorders),
+            }
+            self.chunk_borders_update = false;
+        }
+
+        // a mesh is only drawn once the streamed textures it was meshed with are submitted for upload
+        let txtrs_streaming = self.txtr_mapper.as_ref().is_some_and(|txtr_mapper| txtr_mapper.streaming());
+        if !self.pending_uploads.is_empty() && !txtrs_streaming {
+            self.poll_uploads();
+        }
+        if self.cull_update {
+            self.cull_chunks();
+            self.cull_update = false;
+        }
+        self.initial_generation.uploads_polled(!self.pending_uploads.is_empty());
+    }
+
+    unsafe fn destroy(&mut self) {
+        self.save_edits();
+
+        // uploads that never reached the shader still own their local buffers
+        self.device.device_wait_idle().unwrap();
+        for (fence, upload) in self.pending_uploads.drain() {
+            self.ctx.finalize(upload.cmd_buf, fence);
+            for (buf, mem) in upload.staging {
+                self.device.destroy_buffer(buf, None);
+                self.device.free_memory(mem, None);
+            }
+            // the pooled vertex and index buffers and the chunk blocks are freed along with their allocators
+        }
+        let chunk_blocks = self.chunk_allocators.iter_mut().flat_map(SubAllocator::drain);
+        for (buf, mem) in self.buffer_pool.drain().into_iter().chain(chunk_blocks) {
+            self.device.destroy_buffer(buf, None);
+            self.device.free_memory(mem, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::component::terrain::mesh_util::ChunkMeshUtil;
+    use crate::component::terrain::mesh_util::tests::{test_block_ind, test_generator};
+    use crate::component::texture::TextureIDs;
+    use crate::measurement::{chux, chux_hf, chux_mf};
+    use super::*;
+
+    // bounds of the vertices gen_face draws for the face of the world block, placed as chunk_pos would
+    fn drawn_face(terrain_gen: &Arc<TerrainGenerator>, block: [i32; 3], face: FaceDir) -> ([f32; 3], [f32; 3]) {
+        let generator = test_generator(TextureIDs::default(), terrain_gen.clone());
+        let loc = (block[0] as f32, block[1] as f32, -block[2] as f32);
+        let (verts, _) = generator.gen_face(loc, 0, face, &test_block_ind()[2], MeshType::Cube, None);
+        let min = [0, 1, 2].map(|k| verts.iter().map(|v| v.pos[k]).fold(f32::MAX, f32::min));
+        let max = [0, 1, 2].map(|k| verts.iter().map(|v| v.pos[k]).fold(f32::MIN, f32::max));
+        (min, max)
+    }
+
+    // a lone stone block floating above the flat ground at y = 12
+    fn floating_block(block: [i32; 3]) -> Arc<TerrainGenerator> {
+        let terrain_gen = Arc::new(TerrainGenerator::new(0, 12.0, 0.0, false));
+        terrain_gen.set_block(block, Some(Block(2)));
+        terrain_gen
+    }
+
+    #[test]
+    fn test_picks_drawn_block() {
+        let terrain_gen = floating_block([3, 40, 5]);
+        let (min, max) = drawn_face(&terrain_gen, [3, 40, 5], FaceDir::FRONT);
+        let pick = |origin: [f32; 3], dir| {
+            let origin = Length3D::new(Length::new::<blox>(origin[0]), Length::new::<blox>(origin[1]), Length::new::<blox>(origin[2]));
+            pick_block(&terrain_gen, &test_block_ind(), origin, dir, 20.0, false, |_| true)
+                .map(|(pos, face)| ([pos.x, pos.y, pos.z], face))
+        };
+
+        // at the middle of the drawn front face, from in front of it
+        let center = [(min[0]+max[0])/2.0, (min[1]+max[1])/2.0, min[2]];
+        let hit = pick([center[0], center[1], center[2]+5.0], (0.0, 0.0, -1.0));
+        assert!(matches!(hit, Some(([3, 40, 5], FaceDir::FRONT))), "{hit:?}");
+
+        // and straight down onto its drawn top face
+        let (min, max) = drawn_face(&terrain_gen, [3, 40, 5], FaceDir::TOP);
+        let hit = pick([(min[0]+max[0])/2.0, 45.0, (min[2]+max[2])/2.0], (0.0, -1.0, 0.0));
+        assert!(matches!(hit, Some(([3, 40, 5], FaceDir::TOP))), "{hit:?}");
+    }
+
+    #[test]
+    fn test_camera_lands_on_drawn_block() {
+        let terrain_gen = floating_block([3, 40, 5]);
+        let blocks = TerrainBlocks {
+            terrain_gen: Rc::new(RefCell::new(terrain_gen.clone())),
+            solid: test_block_ind().iter().map(|block| block.mesh.collides()).collect(),
+        };
+        let (min, max) = drawn_face(&terrain_gen, [3, 40, 5], FaceDir::TOP);
+
+        // dropped onto the middle of the drawn top face, instead of falling past it to the ground
+        let mut body = PhysicsBody::default();
+        let mut eye = [(min[0]+max[0])/2.0, 50.0, (min[2]+max[2])/2.0];
+        for _ in 0..300 {
+            eye = body.step(eye, [0.0, 0.0], Duration::from_millis(16), &blocks);
+        }
+        assert!(body.on_ground);
+        assert!((eye[1]-(max[1]+PhysicsBody::EYE_HEIGHT)).abs() < 1e-4, "{eye:?}");
+    }
+
+    #[test]
+    fn test_texture_animation_frame_advances() {
+        let anim = TextureAnimation { frames: 4, interval: 0.25 };
+        let frames: Vec<u32> = [0.0, 0.1, 0.25, 0.6, 0.75, 0.99, 1.0, 1.3].into_iter()
+            .map(|time| anim.frame(time))
+            .collect();
+        // loops back to the first frame after the last one
+        assert_eq!(frames, vec![0, 0, 1, 2, 3, 3, 0, 1]);
+
+        // still textures stay on their first layer
+        assert_eq!(TextureAnimation::attribute(None), [1.0, 1.0]);
+        assert_eq!(TextureAnimation::attribute(Some(anim)), [4.0, 0.25]);
+    }
+
+    #[test]
+    fn test_diff_chunks_uploads_only_new_meshes() {
+        let range = |mesh_id: u64| ChunkRange { mesh_id, verts: 0..4, inds: 0..6, min: [0.0; 3], max: [1.0; 3] };
+        let mut uploaded = HashSet::new();
+
+        let (new, retired) = diff_chunks(&mut uploaded, vec![range(0), range(1), range(2)]);
+        assert_eq!(new.len(), 3);
+        assert!(retired.is_empty());
+
+        // chunk 1 meshed again (as 3), chunk 2 unloaded
+        let (new, mut retired) = diff_chunks(&mut uploaded, vec![range(0), range(3)]);
+        assert_eq!(new, vec![range(3)]);
+        retired.sort();
+        assert_eq!(retired, vec![1, 2]);
+        assert_eq!(uploaded, HashSet::from([0, 3]));
+
+        let (new, retired) = diff_chunks(&mut uploaded, vec![]);
+        assert!(new.is_empty());
+        assert_eq!(retired.len(), 2);
+    }
+
+    #[test]
+    fn test_initial_generation_reported_once() {
+        let mut initial_generation = InitialGeneration::Waiting;
+
+        // nothing to report before the forced generation
+        initial_generation.uploads_polled(false);
+        assert!(!initial_generation.report());
+
+        initial_generation.forced();
+        initial_generation.meshed();
+        initial_generation.uploads_polled(true);
+        assert!(!initial_generation.report());
+
+        initial_generation.uploads_polled(false);
+        assert!(initial_generation.report());
+        assert!(!initial_generation.report());
+
+        // regenerating (e.g. on a new texture mapper) does not report again
+        initial_generation.forced();
+        initial_generation.meshed();
+        initial_generation.uploads_polled(false);
+        assert!(!initial_generation.report());
+    }
+
+    #[test]
+    fn test_lod_radii_tiers_adjoin() {
+        // the radii the tiers were tuned at
+        assert_eq!(lod_radii(4), [
+            (ChunkRadius(4, 2), None),
+            (ChunkRadius(4, 2), Some(ChunkRadius(4, 2))),
+            (ChunkRadius(2, 1), Some(ChunkRadius(4, 2))),
+        ]);
+
+        for render_distance in 1..=16 {
+            let [(outer_ef, inner_ef), (outer_hf, inner_hf), (outer_mf, inner_mf)] = lod_radii(render_distance);
+            assert_eq!(inner_ef, None);
+            assert_eq!(inner_hf, Some(outer_ef));
+            assert_eq!(inner_mf, Some(outer_hf));
+            // every tier reaches beyond the one it surrounds
+            assert!(Length::new::<chux_hf>(outer_hf.0 as f32) > Length::new::<chux>(outer_ef.0 as f32));
+            assert!(Length::new::<chux_mf>(outer_mf.0 as f32) > Length::new::<chux_hf>(outer_hf.0 as f32));
+            for ChunkRadius(border, update) in [outer_ef, outer_hf, outer_mf] {
+                assert!(0 < update && update <= border);
+            }
+        }
+    }
 
-                    [
-                        RenderData::RecreateVertexBuffer(
-                            local_vbo, local_vmo, *purpose
-                        ),
-                        RenderData::RecreateIndexBuffer(
-                            local_ibo, local_imo, inds.len() as u32, *purpose
-                        )
-                    ]
-                })
-                .collect();
+    #[test]
+    fn test_steps_on_drawn_block() {
+        let terrain_gen = floating_block([2, 30, 5]);
+        terrain_gen.set_block([2, 30, 4], Some(Block(0)));
+        let (min, max) = drawn_face(&terrain_gen, [2, 30, 5], FaceDir::TOP);
+
+        // standing on the middle of the drawn top face of the stone, next to the grass block behind it
+        let eye = Length3D::new(
+            Length::new::<blox>((min[0]+max[0])/2.0),
+            Length::new::<blox>(max[1]+PhysicsBody::EYE_HEIGHT),
+            Length::new::<blox>((min[2]+max[2])/2.0),
+        );
+        let below = block_underfoot(eye);
+        assert_eq!(below, [2, 30, 5]);
+        let block = terrain_gen.get_block(below[0] as f64, below[1] as f64, below[2] as f64).unwrap();
+        assert!(matches!(
+            block_sound(&test_block_ind(), eye, block, BlockAction::Step),
+            Some(WorldEvent::BlockSound { sound_group: SoundGroup::Stone, .. })
+        ));
+    }
+
+    #[test]
+    fn test_breaking_grass_emits_grass_sound() {
+        let block_ind = test_block_ind();
+        let pos = Length3D::new(Length::new::<blox>(3.0), Length::new::<blox>(20.0), Length::new::<blox>(-7.0));
+
+        match block_sound(&block_ind, pos, Block(0), BlockAction::Break) {
+            Some(WorldEvent::BlockSound { pos: sound_pos, block, action, sound_group }) => {
+                assert_eq!(sound_pos.y.get::<blox>(), 20.0);
+                assert_eq!(block.0, 0);
+                assert_eq!(action, BlockAction::Break);
+                assert_eq!(sound_group, SoundGroup::Grass);
+            }
+            other => panic!("Expected a block sound, got {other:?}"),
         }
+        // air makes no sound
+        assert!(block_sound(&block_ind, pos, Block(7), BlockAction::Break).is_none());
     }
 }
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use crate::component::camera::Length3D;
+use crate::component::RenderDataPurpose;
+use crate::component::terrain::chunk_mesh::ChunkRange;
+use crate::component::terrain::world_pos;
+use crate::measurement::blox;
+use crate::shader::chunk::ChunkVertex;
+
+
+// axis aligned box of the world (in world blocks) to export, only triangles entirely inside of it are written
+#[derive(Copy, Clone, Debug)]
+pub struct ExportRegion {
+    pub min: Length3D,
+    pub max: Length3D,
+}
+
+impl ExportRegion {
+    // of the chunk vertices in render space
+    fn contains(&self, pos: [f32; 3]) -> bool {
+        let pos = world_pos(pos);
+        let min = [self.min.x.get::<blox>(), self.min.y.get::<blox>(), self.min.z.get::<blox>()];
+        let max = [self.max.x.get::<blox>(), self.max.y.get::<blox>(), self.max.z.get::<blox>()];
+        (0..3).all(|k| min[k] <= pos[k] && pos[k] <= max[k])
+    }
+}
+
+// writes the meshes within the region as a wavefront OBJ, with a material per texture referencing the
+// given MTL file (if any), returns the number of vertices written
+pub(super) fn write_obj(obj: &mut impl Write, mtl: Option<(&mut dyn Write, &str)>,
+                        meshes: &[(Vec<ChunkVertex>, Vec<u32>, Vec<ChunkRange>, RenderDataPurpose)], region: ExportRegion,
+                        txtr_names: &HashMap<u32, String>) -> io::Result<usize> {
+    // triangles along with their texture, indexing into the written OBJ vertices
+    let mut triangles: Vec<(u32, [u32; 3])> = Vec::new();
+    let mut vertex_count = 0;
+
+    if let Some((_, mtl_name)) = &mtl {
+        writeln!(obj, "mtllib {mtl_name}")?;
+    }
+    for (verts, inds, _, _) in meshes {
+        let mut obj_ind: HashMap<u32, u32> = HashMap::new();
+        for tri in inds.chunks_exact(3) {
+            if !tri.iter().all(|i| region.contains(verts[*i as usize].pos)) {
+                continue;
+            }
+            let mut obj_tri = [0; 3];
+            for (k, i) in tri.iter().enumerate() {
+                obj_tri[k] = match obj_ind.get(i) {
+                    Some(ind) => *ind,
+                    None => {
+                        let v = verts[*i as usize];
+                        // texture coordinates of OBJ start at the bottom
+                        writeln!(obj, "v {} {} {}", v.pos[0], v.pos[1], v.pos[2])?;
+                        writeln!(obj, "vt {} {}", v.uv[0], 1.0-v.uv[1])?;
+                        vertex_count += 1;
+                        obj_ind.insert(*i, vertex_count as u32);  // OBJ indices start at 1
+                        vertex_count as u32
+                    }
+                };
+            }
+            triangles.push((verts[tri[0] as usize].txtr as u32, obj_tri));
+        }
+    }
+
+    triangles.sort_by_key(|(txtr, _)| *txtr);
+    let mut current_txtr = None;
+    for (txtr, [a, b, c]) in &triangles {
+        if current_txtr != Some(*txtr) {
+            current_txtr = Some(*txtr);
+            writeln!(obj, "usemtl {}", material_name(txtr_names, *txtr))?;
+        }
+        writeln!(obj, "f {a}/{a} {b}/{b} {c}/{c}")?;
+    }
+
+    if let Some((mtl, _)) = mtl {
+        let mut txtrs: Vec<u32> = triangles.iter().map(|(txtr, _)| *txtr).collect();
+        txtrs.dedup();
+        for txtr in txtrs {
+            let name = material_name(txtr_names, txtr);
+            writeln!(mtl, "newmtl {name}")?;
+            writeln!(mtl, "Kd 1 1 1")?;
+            writeln!(mtl, "map_Kd {name}.png")?;
+        }
+    }
+
+    Ok(vertex_count)
+}
+
+fn material_name(txtr_names: &HashMap<u32, String>, txtr: u32) -> String {
+    txtr_names.get(&txtr).cloned().unwrap_or_else(|| format!("texture_{txtr}"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use uom::si::f32::Length;
+    use crate::component::terrain::chunk_mesh::{ChunkMesh, ChunkPriority, ChunkRadius, ChunkUnload, UpdateChunk, VertexLimit};
+    use crate::component::terrain::mesh_util::tests::test_generator;
+    use crate::component::terrain::terrain_gen::TerrainGenerator;
+    use crate::component::texture::TextureIDs;
+    use crate::util::WorkerPool;
+    use super::*;
+
+    fn blox_pos(x: f32, y: f32, z: f32) -> Length3D {
+        Length3D::new(Length::new::<blox>(x), Length::new::<blox>(y), Length::new::<blox>(z))
+    }
+
+    #[test]
+    fn test_export_flat_region() {
+        let txtr_mapper = HashMap::from([(String::from("sand"), 3)]);
+        let generator = test_generator(TextureIDs::from(txtr_mapper), TerrainGenerator::new(0, 12.0, 0.0, false));
+        let mut chunk_mesh = ChunkMesh::new(Length3D::origin(), ChunkRadius(1, 1), None, generator, WorkerPool::new(0),
+                                            ChunkUnload::Immediate, ChunkPriority::Scan, VertexLimit::Unlimited, None);
+        chunk_mesh.update(UpdateChunk::Forced);
+        let meshes = chunk_mesh.generate_vertices();
+
+        // the top faces of a 4x4 patch of the flat (sandy) ground
+        let region = ExportRegion { min: blox_pos(0.0, 1.0, 0.0), max: blox_pos(4.0, 32.0, 4.0) };
+        let mut obj = Vec::new();
+        let mut mtl = Vec::new();
+        let txtr_names = HashMap::from([(3, String::from("sand"))]);
+        let vertex_count = write_obj(&mut obj, Some((&mut mtl, "terrain.mtl")), &meshes, region, &txtr_names).unwrap();
+
+        let obj = String::from_utf8(obj).unwrap();
+        let lines = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).collect::<Vec<_>>();
+        assert_eq!(vertex_count, 16*4);
+        assert_eq!(lines("v ").len(), vertex_count);
+        assert_eq!(lines("vt ").len(), vertex_count);
+        assert_eq!(lines("f ").len(), 16*2);
+        // the blocks 0 to 3 along z, drawn a block behind them
+        let zs: Vec<f32> = lines("v ").iter().map(|v| v.split_whitespace().nth(3).unwrap().parse().unwrap()).collect();
+        assert_eq!(zs.iter().copied().fold(f32::MAX, f32::min), -1.0);
+        assert_eq!(zs.iter().copied().fold(f32::MIN, f32::max), 3.0);
+        for face in lines("f ") {
+            for vert in face.split_whitespace().skip(1) {
+                let ind: usize = vert.split('/').next().unwrap().parse().unwrap();
+                assert!(1 <= ind && ind <= vertex_count, "{face}");
+            }
+        }
+        assert_eq!(lines("mtllib "), vec!["mtllib terrain.mtl"]);
+        assert_eq!(lines("usemtl "), vec!["usemtl sand"]);
+        assert!(String::from_utf8(mtl).unwrap().contains("map_Kd sand.png"));
+    }
+}
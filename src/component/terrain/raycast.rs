@@ -0,0 +1,116 @@
+use crate::component::terrain::FaceDir;
+
+
+// voxel DDA traversal from the origin (in blocks) along the direction, returning the first solid block within
+// the max distance along with the face of the block that was struck
+// a ray starting inside solid blocks hits nothing, unless `pass_through` them (e.g. a spectator flying inside
+// the terrain) where the first solid block after leaving them is hit instead
+pub(super) fn voxel_raycast(origin: [f32; 3], dir: [f32; 3], max_dist: f32, pass_through: bool,
+                            solid: impl Fn([i32; 3]) -> bool) -> Option<([i32; 3], FaceDir)> {
+    let len = (dir[0]*dir[0]+dir[1]*dir[1]+dir[2]*dir[2]).sqrt();
+    if len == 0.0 || !len.is_finite() {
+        return None;
+    }
+    let dir = dir.map(|d| d/len);
+
+    let mut block = origin.map(|p| p.floor() as i32);
+    let step = dir.map(|d| if d > 0.0 {1} else if d < 0.0 {-1} else {0});
+    // distance along the ray to cross a whole block along each axis
+    let t_delta = dir.map(|d| if d != 0.0 {1.0/d.abs()} else {f32::INFINITY});
+    // distance along the ray to the first block boundary along each axis
+    let mut t_max = [f32::INFINITY; 3];
+    for k in 0..3 {
+        match step[k] {
+            1 => t_max[k] = (block[k] as f32+1.0-origin[k])*t_delta[k],
+            -1 => t_max[k] = (origin[k]-block[k] as f32)*t_delta[k],
+            _ => {}
+        }
+    }
+
+    let mut inside = solid(block);
+    if inside && !pass_through {
+        return None;
+    }
+
+    loop {
+        let k = (0..3).min_by(|a, b| t_max[*a].total_cmp(&t_max[*b])).unwrap();
+        if t_max[k] > max_dist {
+            return None;
+        }
+        block[k] += step[k];
+        t_max[k] += t_delta[k];
+
+        match (solid(block), inside) {
+            (true, false) => return Some((block, entered_face(k, step[k]))),
+            (false, true) => inside = false,
+            _ => {}
+        }
+    }
+}
+
+// face of the block entered by stepping along the axis
+fn entered_face(axis: usize, step: i32) -> FaceDir {
+    match (axis, step > 0) {
+        (0, true) => FaceDir::LEFT,
+        (0, false) => FaceDir::RIGHT,
+        (1, true) => FaceDir::BOTTOM,
+        (1, false) => FaceDir::TOP,
+        (_, true) => FaceDir::BACK,
+        (_, false) => FaceDir::FRONT,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::component::terrain::MeshType;
+    use crate::component::terrain::mesh_util::tests::test_block_ind;
+    use crate::component::terrain::terrain_gen::TerrainGenerator;
+    use super::*;
+
+    // flat sandy ground, with the top blocks at y = 11
+    fn flat_solid(block: [i32; 3]) -> bool {
+        let terrain_gen = TerrainGenerator::new(0, 12.0, 0.0, false);
+        terrain_gen.get_block(block[0] as f64, block[1] as f64, block[2] as f64)
+            .is_some_and(|block| !matches!(test_block_ind()[block.0 as usize].mesh, MeshType::Empty))
+    }
+
+    #[test]
+    fn test_raycast_down_flat_columns() {
+        for (x, z) in [(0.5, 0.5), (-3.2, 7.9), (31.5, -40.25)] {
+            let (block, face) = voxel_raycast([x, 20.0, z], [0.0, -1.0, 0.0], 32.0, false, flat_solid).unwrap();
+            assert_eq!(block, [x.floor() as i32, 11, z.floor() as i32]);
+            assert!(matches!(face, FaceDir::TOP), "{face:?}");
+        }
+
+        // out of reach
+        assert!(voxel_raycast([0.5, 20.0, 0.5], [0.0, -1.0, 0.0], 5.0, false, flat_solid).is_none());
+        // looking up
+        assert!(voxel_raycast([0.5, 20.0, 0.5], [0.0, 1.0, 0.0], 32.0, false, flat_solid).is_none());
+    }
+
+    #[test]
+    fn test_raycast_diagonal_hits_struck_face() {
+        // going down a 45 degree slope towards +x, the ray crosses y = 12 at x = 8.5
+        let (block, face) = voxel_raycast([0.5, 20.0, 0.5], [1.0, -1.0, 0.0], 32.0, false, flat_solid).unwrap();
+        assert_eq!(block, [8, 11, 0]);
+        assert!(matches!(face, FaceDir::TOP), "{face:?}");
+
+        // a wall at x = 4 struck from the -x side
+        let wall = |block: [i32; 3]| block[0] >= 4;
+        let (block, face) = voxel_raycast([0.5, 20.0, 0.5], [1.0, 0.1, 0.2], 32.0, false, wall).unwrap();
+        assert_eq!(block[0], 4);
+        assert!(matches!(face, FaceDir::LEFT), "{face:?}");
+    }
+
+    #[test]
+    fn test_raycast_passes_through_starting_blocks() {
+        // starting underground, below a floating layer at y = 20
+        let solid = |block: [i32; 3]| flat_solid(block) || block[1] == 20;
+        assert!(voxel_raycast([0.5, 5.5, 0.5], [0.0, 1.0, 0.0], 32.0, false, solid).is_none());
+
+        let (block, face) = voxel_raycast([0.5, 5.5, 0.5], [0.0, 1.0, 0.0], 32.0, true, solid).unwrap();
+        assert_eq!(block, [0, 20, 0]);
+        assert!(matches!(face, FaceDir::BOTTOM), "{face:?}");
+    }
+}
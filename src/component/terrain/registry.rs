@@ -0,0 +1,254 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::Deserialize;
+use crate::component::terrain::{BlockData, MeshType, SoundGroup, TextureAnimation, TextureMapper, TransparencyType};
+use crate::component::texture::{TextureIDs, MISSING_TEXTURE};
+
+
+// same as the TextureMapper, but with owned texture names
+#[derive(Clone, Debug, Deserialize)]
+enum TextureMapperDef {
+    All(String),
+    Lateral(String, String, String),  // top, bottom, lateral
+    Unique(String, String, String, String, String, String),  // top, bottom, E (right), S (front), W (left), N (back)
+    Variants(Vec<String>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct BlockDef {
+    ident: String,
+    texture_id: TextureMapperDef,
+    mesh: MeshType,
+    transparency: TransparencyType,
+    sound_group: SoundGroup,
+    back_face_culling: Option<bool>,  // defaults to culling for the solid mesh types
+    animation: Option<TextureAnimation>,
+}
+
+// the registry is loaded once for the lifetime of the app, so its strings are leaked to hand out BlockData<'static>
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+impl BlockDef {
+    fn into_block_data(self) -> BlockData<'static> {
+        let texture_id = match self.texture_id {
+            TextureMapperDef::All(t) => TextureMapper::All(leak(t)),
+            TextureMapperDef::Lateral(t, b, l) => TextureMapper::Lateral(leak(t), leak(b), leak(l)),
+            TextureMapperDef::Unique(t, b, r, f, l, k) => TextureMapper::Unique(
+                leak(t), leak(b), leak(r), leak(f), leak(l), leak(k),
+            ),
+            TextureMapperDef::Variants(variants) => TextureMapper::Variants(
+                Box::leak(variants.into_iter().map(leak).collect())
+            ),
+        };
+        let back_face_culling = self.back_face_culling.unwrap_or(match self.mesh {
+            MeshType::Empty | MeshType::Cube | MeshType::Slab(_) | MeshType::Stairs(_) => true,
+            MeshType::XCross | MeshType::Fluid => false,
+        });
+
+        BlockData {
+            ident: leak(self.ident),
+            texture_id,
+            mesh: self.mesh,
+            transparency: self.transparency,
+            sound_group: self.sound_group,
+            back_face_culling,
+            animation: self.animation,
+        }
+    }
+}
+
+// block definitions loaded from a RON file, the position of a block in the file is its block id
+#[derive(Clone, Debug)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockData<'static>>,
+}
+
+impl BlockRegistry {
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to read block registry {path:?}: {e}")))?;
+        Self::parse(&source)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to parse block registry {path:?}: {e}")))
+    }
+
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let defs: Vec<BlockDef> = ron::from_str(source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if defs.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} blocks don't fit in a block id", defs.len())));
+        }
+        if let Some(def) = defs.iter().find(|def| matches!(&def.texture_id, TextureMapperDef::Variants(v) if v.is_empty())) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Block {:?} has no texture variants", def.ident)));
+        }
+
+        Ok(Self {
+            blocks: defs.into_iter().map(BlockDef::into_block_data).collect(),
+        })
+    }
+
+    pub fn blocks(&self) -> &[BlockData<'static>] {
+        &self.blocks
+    }
+
+    // files of the texture pack directory for every texture the blocks refer to, along with the missing texture the
+    // textures not found fall back to
+    pub(crate) fn texture_files(&self) -> Vec<String> {
+        let mut names = vec![MISSING_TEXTURE];
+        for name in self.blocks.iter().flat_map(|block| block.texture_id.names()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names.into_iter().map(|name| format!("{name}.png")).collect()
+    }
+
+    // every texture a block refers to has to be loaded by the texture handler
+    pub(crate) fn validate_textures(&self, txtr_mapper: &TextureIDs) -> io::Result<()> {
+        let missing: Vec<String> = self.blocks.iter()
+            .flat_map(|block| {
+                block.texture_id.names()
+                    .into_iter()
+                    .filter(|name| !txtr_mapper.contains(name))
+                    .map(move |name| format!("{} ({})", name, block.ident))
+            })
+            .fold(Vec::new(), |mut missing, name| {
+                if !missing.contains(&name) {
+                    missing.push(name);
+                }
+                missing
+            });
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, format!("Missing block textures: {}", missing.join(", "))))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::component::terrain::FaceDir;
+    use super::*;
+
+    const SAMPLE_REGISTRY: &str = r#"[
+        (
+            ident: "grass_block",
+            texture_id: Lateral("grass_top", "dirt", "grass_side"),
+            mesh: Cube,
+            transparency: Opaque,
+            sound_group: Grass,
+        ),
+        (
+            ident: "lava",
+            texture_id: All("lava"),
+            mesh: Fluid,
+            transparency: Translucent,
+            sound_group: Silent,
+            animation: Some((frames: 4, interval: 0.25)),
+        ),
+        (
+            ident: "glass",
+            texture_id: All("glass"),
+            mesh: Cube,
+            transparency: Transparent,
+            sound_group: Stone,
+            back_face_culling: Some(false),
+        ),
+        (
+            ident: "oak_stairs",
+            texture_id: All("oak_planks"),
+            mesh: Stairs(LEFT),
+            transparency: Opaque,
+            sound_group: Wood,
+        ),
+    ]"#;
+
+    fn mapper(names: &[&str]) -> TextureIDs {
+        TextureIDs::from(names.iter().enumerate().map(|(i, name)| (name.to_string(), i as u32)).collect::<HashMap<_, _>>())
+    }
+
+    #[test]
+    fn test_sample_registry() {
+        let registry = BlockRegistry::parse(SAMPLE_REGISTRY).unwrap();
+        let blocks = registry.blocks();
+
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[0].ident, "grass_block");
+        assert_eq!(blocks[0].texture_id.top(), "grass_top");
+        assert_eq!(blocks[0].texture_id.front(), "grass_side");
+        assert!(blocks[0].back_face_culling);
+        // fluids default to being seen from both sides
+        assert!(!blocks[1].back_face_culling);
+        assert_eq!(blocks[1].animation, Some(TextureAnimation { frames: 4, interval: 0.25 }));
+        assert!(!blocks[2].back_face_culling);
+        assert!(matches!(blocks[3].mesh, MeshType::Stairs(FaceDir::LEFT)));
+        assert_eq!(blocks[3].sound_group, SoundGroup::Wood);
+
+        assert!(BlockRegistry::parse("[(ident: \"dirt\")]").is_err());
+    }
+
+    #[test]
+    fn test_validate_textures() {
+        let registry = BlockRegistry::parse(SAMPLE_REGISTRY).unwrap();
+
+        assert!(registry.validate_textures(&mapper(&["grass_top", "dirt", "grass_side", "lava", "glass", "oak_planks"])).is_ok());
+
+        let err = registry.validate_textures(&mapper(&["grass_top", "dirt", "lava", "glass"])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "Missing block textures: grass_side (grass_block), oak_planks (oak_stairs)");
+    }
+
+    #[test]
+    fn test_texture_variants() {
+        let registry = BlockRegistry::parse(r#"[(
+            ident: "cobblestone",
+            texture_id: Variants(["cobble", "cobble_90", "cobble_180"]),
+            mesh: Cube,
+            transparency: Opaque,
+            sound_group: Stone,
+        )]"#).unwrap();
+        let txtr = registry.blocks()[0].texture_id;
+        assert_eq!(txtr.names(), ["cobble", "cobble_90", "cobble_180"]);
+        assert!(registry.validate_textures(&mapper(&["cobble", "cobble_90"])).is_err());
+
+        // the same variant every time a block is meshed, but not the same one across a floor
+        let mut used = Vec::new();
+        for x in 0..8 {
+            for z in 0..8 {
+                let loc = (x as f32, 4.0, -(z as f32));
+                assert_eq!(txtr.at(loc).top(), txtr.at(loc).top());
+                assert_eq!(txtr.at(loc).top(), txtr.at(loc).left());
+                if !used.contains(&txtr.at(loc).top()) {
+                    used.push(txtr.at(loc).top());
+                }
+            }
+        }
+        assert_eq!(used.len(), 3);
+
+        assert!(BlockRegistry::parse(r#"[(
+            ident: "cobblestone", texture_id: Variants([]), mesh: Cube, transparency: Opaque, sound_group: Stone,
+        )]"#).is_err());
+    }
+
+    #[test]
+    fn test_default_registry() {
+        let registry = BlockRegistry::from_file(Path::new(crate::DEFAULT_BLOCK_REGISTRY)).unwrap();
+        let idents: Vec<&str> = registry.blocks().iter().map(|block| block.ident).collect();
+
+        // the terrain generator relies on the ids of the generated blocks
+        assert_eq!(idents, [
+            "grass_block", "dirt", "stone", "sand", "grass", "flower", "water", "air", "stone_slab", "stone_stairs", "sandstone",
+        ]);
+        let files = registry.texture_files();
+        assert_eq!(files[..4], ["null.png", "grass_top.png", "dirt.png", "grass_side.png"]);
+        assert!(files.iter().all(|file| Path::new(crate::DEFAULT_TEXTURE_PACK).join(file).is_file()));
+        let textures: Vec<&str> = files.iter().map(|file| file.trim_end_matches(".png")).collect();
+        assert!(registry.validate_textures(&mapper(&textures)).is_ok());
+    }
+}
@@ -1,43 +1,252 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use noise::{NoiseFn, Perlin, Simplex};
 use crate::component::terrain::{Block};
 
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Biome {
+    Plains,
+    Desert,  // no flora
+}
+
+impl Biome {
+    // top block of the terrain
+    fn surface(&self) -> Block {
+        match self {
+            Biome::Plains => Block(0),
+            Biome::Desert => Block(3),
+        }
+    }
+
+    // the few blocks below the surface
+    fn subsurface(&self) -> Block {
+        match self {
+            Biome::Plains => Block(1),
+            Biome::Desert => Block(10),
+        }
+    }
+
+    // everything further below
+    fn filler(&self) -> Block {
+        match self {
+            Biome::Plains | Biome::Desert => Block(2),
+        }
+    }
+
+    // scale applied on top of the amplitude of the terrain generator
+    fn amplitude(&self) -> f64 {
+        match self {
+            Biome::Plains => 1.0,
+            Biome::Desert => 0.4,
+        }
+    }
+}
+
+// seed of each noise, derived from the world seed so that no two noises share one
+fn noise_seed(seed: u32, noise: u32) -> u32 {
+    let mut x = seed ^ noise.wrapping_add(1).wrapping_mul(0x9E37_79B9);
+    x = (x ^ (x >> 16)).wrapping_mul(0x85EB_CA6B);
+    x = (x ^ (x >> 13)).wrapping_mul(0xC2B2_AE35);
+    x ^ (x >> 16)
+}
+
+// the edits bucketed by the region they are in, so the ranges of a chunk only look through the edits within and
+// around the chunk instead of every edit of the world
+#[derive(Default)]
+struct EditIndex {
+    buckets: HashMap<[i32; 3], HashMap<[i32; 3], Option<Block>>>,
+}
+
+impl EditIndex {
+    const BUCKET_SIZE: i32 = 32;  // edge of the bucket regions (in blocks)
+
+    fn bucket(pos: [i32; 3]) -> [i32; 3] {
+        pos.map(|c| c.div_euclid(Self::BUCKET_SIZE))
+    }
+
+    fn insert(&mut self, pos: [i32; 3], block: Option<Block>) {
+        self.buckets.entry(Self::bucket(pos)).or_default().insert(pos, block);
+    }
+
+    fn get(&self, pos: [i32; 3]) -> Option<Option<Block>> {
+        self.buckets.get(&Self::bucket(pos))?.get(&pos).copied()
+    }
+
+    // the edits within the inclusive range, from the buckets it overlaps (or every bucket, for the ranges overlapping
+    // more buckets than there are)
+    fn within(&self, min: [i32; 3], max: [i32; 3]) -> impl Iterator<Item = ([i32; 3], Option<Block>)> + '_ {
+        let (lo, hi) = (Self::bucket(min), Self::bucket(max));
+        let spanned = (0..3).map(|k| (hi[k] as i64-lo[k] as i64+1).max(0) as u64).fold(1u64, u64::saturating_mul);
+        let buckets: Box<dyn Iterator<Item = &HashMap<[i32; 3], Option<Block>>>> = if spanned > self.buckets.len() as u64 {
+            Box::new(self.buckets.iter()
+                .filter(move |(bucket, _)| (0..3).all(|k| lo[k] <= bucket[k] && bucket[k] <= hi[k]))
+                .map(|(_, edits)| edits))
+        } else {
+            Box::new((lo[0]..=hi[0])
+                .flat_map(move |x| (lo[1]..=hi[1]).flat_map(move |y| (lo[2]..=hi[2]).map(move |z| [x, y, z])))
+                .filter_map(|bucket| self.buckets.get(&bucket)))
+        };
+        buckets.flat_map(|edits| edits.iter())
+            .filter(move |(pos, _)| (0..3).all(|k| min[k] <= pos[k] && pos[k] <= max[k]))
+            .map(|(pos, block)| (*pos, *block))
+    }
+}
 
 // will need a bounding region area context (probably in HF chunks) making this a stateful struct
 //  - especially for random structural placements (trees, grasses) using Poisson disk
 //      - will have to be truly random or else there would probably be noticeable seams between chunk borders
 // independent by bounding region area
 pub struct TerrainGenerator {
+    seed: u32,  // every noise is seeded from it
+    base_height: f64,  // height the terrain noise oscillates around (in blocks)
+    amplitude: f64,  // scale applied to the height noise, lower for flatter plains and higher for taller mountains
     height_noise: Perlin,
     humidity_noise: Perlin,
     temperature_noise: Perlin,
     floral_noise: Perlin,  // TODO: temporary to be removed
+    biome_noise: Perlin,
+    cave_noise: Perlin,
+    caves: bool,  // whether caves are carved below the surface
+    // blocks placed (Some) or removed (None) by the user, taking precedence over the generated terrain,
+    // shared by the generators of every fidelity and kept by the generators with other params
+    edits: Arc<RwLock<EditIndex>>,
+}
+
+// the shape of the generated terrain, tunable at runtime by regenerating the world with them
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TerrainParams {
+    pub base_height: f64,
+    pub amplitude: f64,
+    pub caves: bool,
 }
 
 impl TerrainGenerator {
     const SEA_LEVEL: f64 = 10.0;
     const SAND_LEVEL: f64 = 13.0;
+    const BIOME_SCALE: f64 = 1200.0;  // rough width of a biome (in blocks)
+    // width of the biome noise over which the height of the two biomes is blended
+    const BIOME_BLEND: f64 = 0.1;
+    const CAVE_SCALE: f64 = 48.0;  // rough length of a cave tunnel segment (in blocks)
+    const CAVE_THRESHOLD: f64 = 0.85;  // cave density above which the block is carved out
+    const CAVE_ROOF: f64 = 4.0;  // least number of blocks between a cave and the surface
 
-    pub fn new() -> Self {
+    pub fn new(seed: u32, base_height: f64, amplitude: f64, caves: bool) -> Self {
         Self {
-            height_noise: Perlin::new(50),
-            humidity_noise: Perlin::new(23),
-            temperature_noise: Perlin::new(47),
-            floral_noise: Perlin::new(23),
+            seed,
+            base_height,
+            amplitude,
+            height_noise: Perlin::new(noise_seed(seed, 0)),
+            humidity_noise: Perlin::new(noise_seed(seed, 1)),
+            temperature_noise: Perlin::new(noise_seed(seed, 2)),
+            floral_noise: Perlin::new(noise_seed(seed, 3)),
+            biome_noise: Perlin::new(noise_seed(seed, 4)),
+            cave_noise: Perlin::new(noise_seed(seed, 5)),
+            caves,
+            edits: Arc::new(RwLock::new(EditIndex::default())),
+        }
+    }
+
+    pub(crate) fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub(crate) fn params(&self) -> TerrainParams {
+        TerrainParams { base_height: self.base_height, amplitude: self.amplitude, caves: self.caves }
+    }
+
+    // the same seed and edits shaped by the params
+    pub(crate) fn with_params(&self, params: TerrainParams) -> Self {
+        let TerrainParams { base_height, amplitude, caves } = params;
+        Self { edits: self.edits.clone(), ..Self::new(self.seed, base_height, amplitude, caves) }
+    }
+
+    pub(super) fn set_block(&self, pos: [i32; 3], block: Option<Block>) {
+        self.edits.write().unwrap().insert(pos, block);
+    }
+
+    // None if the block at the position was never edited
+    pub(super) fn edited_block(&self, x: f64, y: f64, z: f64) -> Option<Option<Block>> {
+        self.edits.read().unwrap().get([x.floor() as i32, y.floor() as i32, z.floor() as i32])
+    }
+
+    // whether any block within the inclusive range was edited
+    pub(super) fn edited_within(&self, min: [i32; 3], max: [i32; 3]) -> bool {
+        self.edits.read().unwrap().within(min, max).next().is_some()
+    }
+
+    // blocks placed (Some) or removed (None) within the inclusive range
+    pub(super) fn edits_within(&self, min: [i32; 3], max: [i32; 3]) -> Vec<([i32; 3], Option<Block>)> {
+        self.edits.read().unwrap().within(min, max).collect()
+    }
+
+    // blocks placed within the inclusive range
+    pub(super) fn placed_within(&self, min: [i32; 3], max: [i32; 3]) -> Vec<([i32; 3], Block)> {
+        self.edits_within(min, max).into_iter()
+            .filter_map(|(pos, block)| block.map(|block| (pos, block)))
+            .collect()
+    }
+
+    // from 0 for plains to 1 for desert, in between only around the border of the biomes
+    fn desert_weight(&self, x: f64, z: f64) -> f64 {
+        let t = (self.biome_noise.get([(x+700.0)/Self::BIOME_SCALE, (z-300.0)/Self::BIOME_SCALE])/Self::BIOME_BLEND+0.5).clamp(0.0, 1.0);
+        t*t*(3.0-2.0*t)
+    }
+
+    // the border is jittered so the blocks do not change along a smooth curve
+    pub(super) fn biome_at(&self, x: f64, z: f64) -> Biome {
+        let jitter = self.biome_noise.get([x/3.7, z/3.7])*0.3;
+        if self.desert_weight(x, z)+jitter >= 0.5 {
+            Biome::Desert
+        } else {
+            Biome::Plains
         }
     }
 
     fn get_base_level(&self, x: f64, z: f64) -> f64 {
-        20.0
-            +self.height_noise.get([x/987.0, z/987.0])*512.0
+        let w = self.desert_weight(x, z);
+        let amplitude = Biome::Plains.amplitude()*(1.0-w)+Biome::Desert.amplitude()*w;
+        self.base_height + self.amplitude*amplitude*(
+            self.height_noise.get([x/987.0, z/987.0])*512.0
             +self.height_noise.get([(-x+1567.0)/577.0, (z-987.0)/577.0])*256.0
             +self.height_noise.get([(-x+1000.0)/153.0, (z-500.0)/153.0])*128.0
             +self.height_noise.get([(x-500.0)/73.0, (-z+250.0)/73.0])*64.0
             +self.height_noise.get([(-x+250.0)/37.0, (-z-125.0)/37.0])*32.0
+        )
+    }
+
+    // ridged noise of two fields, close to 1 only where both fields are close to 0, which traces out tunnels
+    pub(super) fn cave_density(&self, x: f64, y: f64, z: f64) -> f64 {
+        let ridge = |n: f64| 1.0-n.abs();
+        let (x, y, z) = (x/Self::CAVE_SCALE, y/(Self::CAVE_SCALE*0.5), z/Self::CAVE_SCALE);
+        ridge(self.cave_noise.get([x+0.37, y+0.21, z+0.73]))*ridge(self.cave_noise.get([x-17.19, y+5.43, z-11.61]))
+    }
+
+    // whether the block is carved out by a cave, given the opaque height bound of its column
+    pub(super) fn cave_at(&self, x: f64, y: f64, z: f64, height_bound: f64) -> bool {
+        self.caves && y < self.cave_roof(height_bound) && self.cave_density(x, y, z) > Self::CAVE_THRESHOLD
+    }
+
+    // the blocks from the opaque height bound down to here are never carved out
+    // the height bound is ceiled as by the mesher, so the cave roof is the same whichever passes the bound
+    pub(super) fn cave_roof(&self, height_bound: f64) -> f64 {
+        if self.caves {
+            height_bound.ceil()-Self::CAVE_ROOF
+        } else {
+            f64::MIN
+        }
     }
 
     pub(super) fn get_block(&self, x: f64, y: f64, z: f64) -> Option<Block> {
+        if let Some(block) = self.edited_block(x, y, z) {
+            return block;
+        }
+
         let base_level = self.get_base_level(x, z);
+        if self.cave_at(x, y, z, base_level) {
+            return None;
+        }
+        let biome = self.biome_at(x, z);
         let floralness = self.floral_noise.get([x/40.0, z/40.0]);
 
         if y >= base_level+1.0 {
@@ -49,7 +258,7 @@ impl TerrainGenerator {
         } else if y >= base_level {
             if y <= Self::SEA_LEVEL {
                 Some(Block(6))
-            } else if 0.8 <= floralness && floralness <= 0.9 {
+            } else if biome != Biome::Desert && 0.8 <= floralness && floralness <= 0.9 {
                 if 0.84 <= floralness && floralness <= 0.86 {
                     Some(Block(5))
                 } else {
@@ -61,16 +270,17 @@ impl TerrainGenerator {
         } else if y <= Self::SAND_LEVEL {
             Some(Block(3))
         } else if y >= base_level-1.0 {
-            Some(Block(0))
+            Some(biome.surface())
         } else if y >= base_level-3.0 {
-            Some(Block(1))
+            Some(biome.subsurface())
         } else {
-            Some(Block(2))
+            Some(biome.filler())
         }
     }
 
     // opaque block height-NBT
     // WHEN THE TERRAIN BEGINS TO BE NOTHING (AFTER OPAQUE BREAK)
+    // the blocks below are opaque except for the caves (see cave_at)
     pub(super) fn opaque_block_height_bound_test(&self, x: f64, z: f64) -> f64 {
         let base_level = self.get_base_level(x, z);
 
@@ -82,7 +292,7 @@ impl TerrainGenerator {
         let base_level = self.get_base_level(x, z);
         let floralness = self.floral_noise.get([x/40.0, z/40.0]);
 
-        if base_level > Self::SEA_LEVEL {
+        if base_level > Self::SEA_LEVEL && self.biome_at(x, z) != Biome::Desert {
             if 0.8 <= floralness && floralness <= 0.9 {
                 if 0.84 <= floralness && floralness <= 0.86 {
                     Some(base_level)
@@ -110,3 +320,167 @@ impl TerrainGenerator {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn height_range(terrain_gen: &TerrainGenerator) -> (f64, f64) {
+        let mut min = f64::MAX;
+        let mut max = f64::MIN;
+        for x in (-512..512).step_by(16) {
+            for z in (-512..512).step_by(16) {
+                let h = terrain_gen.opaque_block_height_bound_test(x as f64, z as f64);
+                min = min.min(h);
+                max = max.max(h);
+            }
+        }
+        (min, max)
+    }
+
+    // a column of each biome, away from the sea
+    fn biome_columns(terrain_gen: &TerrainGenerator) -> [(f64, f64); 2] {
+        [Biome::Plains, Biome::Desert].map(|biome| (-4096..4096).step_by(8)
+            .flat_map(|x| (-4096..4096).step_by(64).map(move |z| (x as f64, z as f64)))
+            .find(|&(x, z)| terrain_gen.biome_at(x, z) == biome && terrain_gen.opaque_block_height_bound_test(x, z) > 25.0)
+            .unwrap()
+        )
+    }
+
+    #[test]
+    fn test_amplitude_scales_height_range() {
+        let (min, max) = height_range(&TerrainGenerator::new(0, 20.0, 1.0, false));
+        let (min2, max2) = height_range(&TerrainGenerator::new(0, 20.0, 2.0, false));
+
+        assert!(max-min > 0.0);
+        assert!(((max2-min2)/(max-min)-2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_base_height_offsets_terrain() {
+        let low = TerrainGenerator::new(0, 20.0, 1.0, false);
+        let high = TerrainGenerator::new(0, 60.0, 1.0, false);
+        let flat = TerrainGenerator::new(0, 20.0, 0.0, false);
+
+        for (x, z) in [(0.0, 0.0), (123.0, -456.0), (-789.0, 321.0)] {
+            let diff = high.opaque_block_height_bound_test(x, z)-low.opaque_block_height_bound_test(x, z);
+            assert!((diff-40.0).abs() < 1e-9);
+            assert_eq!(flat.opaque_block_height_bound_test(x, z), 20.0);
+        }
+    }
+
+    #[test]
+    fn test_params_swapped_with_edits() {
+        let terrain_gen = TerrainGenerator::new(7, 20.0, 1.0, false);
+        terrain_gen.set_block([3, 90, 4], Some(Block(2)));
+        let params = TerrainParams { base_height: 50.0, ..terrain_gen.params() };
+        let raised = terrain_gen.with_params(params);
+        assert_eq!(raised.params(), params);
+        assert_eq!(raised.seed(), 7);
+
+        // the same terrain raised by the new base height, along with the edits
+        let diff = raised.opaque_block_height_bound_test(10.0, 20.0)-terrain_gen.opaque_block_height_bound_test(10.0, 20.0);
+        assert!((diff-30.0).abs() < 1e-9);
+        assert_eq!(raised.get_block(3.0, 90.0, 4.0).map(|block| block.0), Some(2));
+        // the voxels between the two surfaces are filled only by the raised terrain
+        let y = terrain_gen.opaque_block_height_bound_test(10.0, 20.0).ceil()+10.0;
+        assert!(terrain_gen.get_block(10.0, y, 20.0).is_none());
+        assert!(raised.get_block(10.0, y, 20.0).is_some());
+    }
+
+    #[test]
+    fn test_biome_blocks() {
+        let terrain_gen = TerrainGenerator::new(0, 20.0, 1.0, false);
+        assert_eq!(terrain_gen.biome_at(0.0, 0.0), Biome::Plains);
+
+        for ((x, z), layers) in biome_columns(&terrain_gen).into_iter().zip([[0, 1, 2], [3, 10, 2]]) {
+            let base_level = terrain_gen.opaque_block_height_bound_test(x, z);
+            let block = |y: f64| terrain_gen.get_block(x, y, z).map(|block| block.0);
+            assert_eq!([block(base_level-0.5), block(base_level-2.0), block(base_level-8.0)], layers.map(Some));
+        }
+        // no flora in the desert
+        let (x, z) = biome_columns(&terrain_gen)[1];
+        assert!(terrain_gen.floral_existence_bound_test(x, z).is_none());
+    }
+
+    #[test]
+    fn test_biome_border_height_blended() {
+        let terrain_gen = TerrainGenerator::new(0, 20.0, 1.0, false);
+        let [plains, desert] = biome_columns(&terrain_gen);
+
+        // walking from one biome to the other (a tenth of a block at a time), the height never jumps at the border
+        let steps = 10*((desert.0-plains.0).abs()+(desert.1-plains.1).abs()) as usize;
+        let height = |i: usize| {
+            let t = i as f64/steps as f64;
+            terrain_gen.opaque_block_height_bound_test(plains.0+(desert.0-plains.0)*t, plains.1+(desert.1-plains.1)*t)
+        };
+        for i in 0..steps {
+            assert!((height(i+1)-height(i)).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_caves_below_surface() {
+        let terrain_gen = TerrainGenerator::new(0, 20.0, 1.0, true);
+        let mut carved = 0;
+        for x in (-256..256).step_by(16) {
+            for z in (-256..256).step_by(16) {
+                let (x, z) = (x as f64, z as f64);
+                let base_level = terrain_gen.opaque_block_height_bound_test(x, z);
+                for y in (base_level-64.0) as i32..base_level.floor() as i32 {
+                    if terrain_gen.get_block(x, y as f64, z).is_none() {
+                        // never breaking through the surface
+                        assert!((y as f64) < base_level.ceil()-TerrainGenerator::CAVE_ROOF, "cave at {y} below the surface at {base_level}");
+                        carved += 1;
+                    }
+                }
+            }
+        }
+        assert!(carved > 0);
+
+        // the same blocks are carved out every time, and none without caves
+        let again = TerrainGenerator::new(0, 20.0, 1.0, true);
+        let flat = TerrainGenerator::new(0, 20.0, 1.0, false);
+        let base_level = terrain_gen.opaque_block_height_bound_test(0.0, 0.0);
+        for y in (base_level-64.0) as i32..base_level.floor() as i32 {
+            assert_eq!(again.get_block(0.0, y as f64, 0.0).is_none(), terrain_gen.get_block(0.0, y as f64, 0.0).is_none());
+            assert!(flat.get_block(0.0, y as f64, 0.0).is_some());
+        }
+    }
+
+    #[test]
+    fn test_edits_within_range() {
+        let terrain_gen = TerrainGenerator::new(0, 20.0, 1.0, false);
+        for pos in [[0, 0, 0], [31, 5, -1], [32, 5, -33], [-1, -1, -1], [500, 40, 500]] {
+            terrain_gen.set_block(pos, Some(Block(2)));
+        }
+        terrain_gen.set_block([32, 5, -33], None);
+
+        let mut within = terrain_gen.edits_within([-1, -1, -40], [32, 5, 0]);
+        within.sort_by_key(|(pos, _)| *pos);
+        assert_eq!(within.iter().map(|(pos, block)| (*pos, block.map(|block| block.0))).collect::<Vec<_>>(), vec![
+            ([-1, -1, -1], Some(2)), ([0, 0, 0], Some(2)), ([31, 5, -1], Some(2)), ([32, 5, -33], None),
+        ]);
+        // the same through the buckets, as through the ranges spanning more of them than there are
+        assert_eq!(terrain_gen.edits_within([-1, i32::MIN, -40], [32, i32::MAX, 0]).len(), 4);
+        assert!(terrain_gen.edited_within([499, 0, 499], [501, 100, 501]));
+        assert!(!terrain_gen.edited_within([1, 0, 1], [30, 100, 30]));
+        assert!(matches!(terrain_gen.edited_block(31.5, 5.2, -0.5), Some(Some(Block(2)))));
+    }
+
+    #[test]
+    fn test_seed_reproducible() {
+        let blocks = |seed: u32| {
+            let terrain_gen = TerrainGenerator::new(seed, 20.0, 1.0, true);
+            (-64..64).step_by(4)
+                .flat_map(|x| (-64..64).step_by(4).map(move |z| (x as f64, z as f64)))
+                .flat_map(|(x, z)| (-20..60).map(move |y| (x, y as f64, z)))
+                .map(|(x, y, z)| terrain_gen.get_block(x, y, z).map(|block| block.0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(blocks(7), blocks(7));
+        assert_ne!(blocks(7), blocks(8));
+        assert_eq!(TerrainGenerator::new(7, 20.0, 1.0, true).seed(), 7);
+    }
+}
@@ -1,16 +1,558 @@
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::{fmt, fs, mem, ptr};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
 use ash::{Device, vk};
 use png;
 use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::debug::{Diagnostics, MTXG_LOG};
 use crate::handler::VulkanInstance;
-use crate::util::{CmdBufContext, create_host_buffer, create_local_image};
+use crate::util::{CmdBufContext, PendingUploads, allocate_buffer, create_host_buffer, create_local_image};
 use crate::world::WorldEvent;
 
 
-pub(crate) type TextureIDMapper = Rc<HashMap<String, u32>>;
+pub(crate) type TextureIDMapper = Arc<TextureIDs>;
+
+// how the block textures are laid out in the sampled image, chosen when constructing the texture handler
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureLayout {
+    Array,  // a layer per texture (frame), all textures must share the same extent
+    Atlas,  // textures of any extent packed into a single 2D image
+}
+
+// texture index of each texture name, along with the atlas rectangle of each index (if packed into an atlas)
+// streamed textures get their layers reserved once first meshed instead
+#[derive(Clone, Debug, Default)]
+pub struct TextureIDs {
+    ids: HashMap<String, u32>,
+    atlas_rects: Option<Vec<[f32; 4]>>,  // uv offset and size
+    streamed: Option<Arc<StreamedLayers>>,
+}
+
+impl TextureIDs {
+    pub(crate) fn atlas(ids: HashMap<String, u32>, atlas_rects: Vec<[f32; 4]>) -> Self {
+        Self { ids, atlas_rects: Some(atlas_rects), streamed: None }
+    }
+
+    pub(crate) fn streamed(layers: StreamedLayers) -> Self {
+        Self { ids: HashMap::new(), atlas_rects: None, streamed: Some(Arc::new(layers)) }
+    }
+
+    // of the textures with a layer, only the reserved ones when streamed
+    pub(crate) fn ids(&self) -> HashMap<String, u32> {
+        match &self.streamed {
+            Some(streamed) => streamed.reserved(),
+            None => self.ids.clone(),
+        }
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        match &self.streamed {
+            Some(streamed) => streamed.layers.contains_key(name),
+            None => self.ids.contains_key(name),
+        }
+    }
+
+    // texture array index of the texture name, reserving the layers of a streamed texture the first time
+    pub(crate) fn index(&self, name: &str) -> u32 {
+        match &self.streamed {
+            Some(streamed) => streamed.reserve(name),
+            None => texture_index(&self.ids, name),
+        }
+    }
+
+    // layers of the streamed texture array
+    fn streamed_capacity(&self) -> Option<u32> {
+        self.streamed.as_ref().map(|streamed| streamed.capacity)
+    }
+
+    // the streamed textures decoded since, along with their first layer and number of frames
+    fn take_requested(&self) -> Vec<(u32, u32, Vec<u8>)> {
+        self.streamed.as_ref()
+            .map(|streamed| mem::take(&mut streamed.reserving.lock().unwrap().requested))
+            .unwrap_or_default()
+    }
+
+    // whether a streamed texture was reserved but its upload not submitted yet, so a mesh using it can't be drawn yet
+    pub(crate) fn streaming(&self) -> bool {
+        self.streamed.as_ref().is_some_and(|streamed| streamed.reserving.lock().unwrap().pending > 0)
+    }
+
+    // the upload of the given number of requested textures got submitted
+    fn uploaded(&self, textures: u32) {
+        if let Some(streamed) = &self.streamed {
+            streamed.reserving.lock().unwrap().pending -= textures;
+        }
+    }
+
+    pub(crate) fn is_atlas(&self) -> bool {
+        self.atlas_rects.is_some()
+    }
+
+    // maps the uv of a face to the texture within the atlas, unchanged for texture arrays
+    pub(crate) fn uv(&self, txtr: u32, uv: [f32; 2]) -> [f32; 2] {
+        match self.atlas_rects.as_ref().and_then(|rects| rects.get(txtr as usize)) {
+            Some([x, y, w, h]) => [x+uv[0]*w, y+uv[1]*h],
+            None => uv,
+        }
+    }
+}
+
+impl From<HashMap<String, u32>> for TextureIDs {
+    fn from(ids: HashMap<String, u32>) -> Self {
+        Self { ids, atlas_rects: None, streamed: None }
+    }
+}
+
+// first layer of a streamed texture not reserved yet, or that didn't fit and falls back to the missing texture
+const UNRESERVED: u32 = u32::MAX;
+const FALLBACK: u32 = u32::MAX-1;
+
+// layers of the streamed texture array, reserved in the order the textures are first meshed until the capacity runs out
+// the layer of every face is looked up without locking, only reserving a texture locks and decodes it on the meshing thread
+#[derive(Debug, Default)]
+pub(crate) struct StreamedLayers {
+    layers: HashMap<String, AtomicU32>,  // first layer of every texture in the texture pack
+    frames: HashMap<String, u32>,
+    capacity: u32,
+    sources: TextureSources,
+    reserving: Mutex<Reservations>,
+}
+
+#[derive(Debug, Default)]
+struct Reservations {
+    next_layer: u32,
+    requested: Vec<(u32, u32, Vec<u8>)>,  // decoded but not handed to the upload yet
+    pending: u32,  // reserved but not submitted for upload yet
+    full: bool,  // warned about the capacity
+}
+
+impl StreamedLayers {
+    // the missing texture takes the first layers, so the textures that don't fit fall back to it
+    pub(crate) fn new(mut frames: HashMap<String, u32>, capacity: u32, sources: TextureSources) -> Self {
+        let missing_frames = *frames.entry(MISSING_TEXTURE.to_string()).or_insert(1);
+        let layers = frames.keys().map(|name| (name.clone(), AtomicU32::new(UNRESERVED))).collect();
+        let layers = Self { layers, frames, capacity, sources, ..Default::default() };
+        layers.layers[MISSING_TEXTURE].store(0, Ordering::Release);
+        layers.reserving.lock().unwrap().next_layer = missing_frames;
+        layers
+    }
+
+    fn reserved(&self) -> HashMap<String, u32> {
+        self.layers.iter()
+            .map(|(name, layer)| (name.clone(), layer.load(Ordering::Acquire)))
+            .filter(|(_, layer)| *layer < FALLBACK)
+            .collect()
+    }
+
+    fn reserve(&self, name: &str) -> u32 {
+        let Some(layer) = self.layers.get(name) else {
+            warn_missing_texture(name);
+            return 0;
+        };
+        match layer.load(Ordering::Acquire) {
+            UNRESERVED => {}
+            FALLBACK => return 0,
+            reserved => return reserved,
+        }
+
+        let frames = self.frames[name];
+        let mut reserving = self.reserving.lock().unwrap();
+        // reserved by another meshing thread meanwhile
+        match layer.load(Ordering::Acquire) {
+            UNRESERVED => {}
+            FALLBACK => return 0,
+            reserved => return reserved,
+        }
+        if reserving.next_layer+frames > self.capacity {
+            if !reserving.full {
+                log::warn!("Streamed texture array of {} layers is full, using the {MISSING_TEXTURE:?} texture for {name:?}", self.capacity);
+                reserving.full = true;
+            }
+            layer.store(FALLBACK, Ordering::Release);
+            return 0;
+        }
+
+        let first = reserving.next_layer;
+        reserving.next_layer += frames;
+        reserving.pending += 1;
+        layer.store(first, Ordering::Release);
+        drop(reserving);
+
+        let pixels = self.sources.decode(name, frames);
+        self.reserving.lock().unwrap().requested.push((first, frames, pixels));
+        first
+    }
+}
+
+// the texture files of the streamed textures, rescaled to the texture array extent once decoded
+#[derive(Debug, Default)]
+pub(crate) struct TextureSources {
+    paths: HashMap<String, PathBuf>,
+    extent: (u32, u32),
+    missing_pixels: Vec<u8>,  // a single frame
+}
+
+impl TextureSources {
+    // the frames rescaled to the texture array extent, or the missing texture when the file can no longer be loaded
+    fn decode(&self, name: &str, frames: u32) -> Vec<u8> {
+        let decoded = self.paths.get(name).map(|path| decode_texture(path));
+        match decoded {
+            Some(Ok((width, height, txtr_raw_buf))) if height == width*frames => {
+                let mut pixels = Vec::with_capacity(self.missing_pixels.len()*frames as usize);
+                push_frames(&mut pixels, &mut Vec::new(), width, &txtr_raw_buf, self.extent.0);
+                pixels
+            }
+            decoded => {
+                if let Some(path) = self.paths.get(name) {
+                    log::warn!("Failed to stream texture {path:?}, using the {MISSING_TEXTURE:?} texture instead: {:?}",
+                               decoded.map(|txtr| txtr.map(|(width, height, _)| (width, height))));
+                }
+                self.missing_pixels.repeat(frames as usize)
+            }
+        }
+    }
+}
+
+// texture used in place of any texture name missing from the mapper
+pub(crate) const MISSING_TEXTURE: &str = "null";
+
+// texture names already warned about, so a missing texture is only logged once rather than per face
+static WARNED_MISSING_TEXTURES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// width and number of frames of the texture from the header of its file, without decoding it
+fn png_frames(path: &Path) -> io::Result<(u32, u32)> {
+    let reader = png::Decoder::new(fs::File::open(path)?).read_info()?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    if width == 0 || height == 0 || height%width != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{width}x{height} is not a strip of square frames")));
+    }
+    Ok((width, height/width))
+}
+
+fn warn_missing_texture(name: &str) {
+    let mut warned = WARNED_MISSING_TEXTURES.lock().unwrap();
+    if !warned.iter().any(|n| n == name) {
+        log::warn!("Texture {name:?} not found, using the {MISSING_TEXTURE:?} texture instead");
+        warned.push(name.to_string());
+    }
+}
+
+// texture array index of the given texture name, falling back to the missing texture
+pub(crate) fn texture_index(txtr_mapper: &HashMap<String, u32>, name: &str) -> u32 {
+    if let Some(ind) = txtr_mapper.get(name) {
+        return *ind;
+    }
+    warn_missing_texture(name);
+
+    match txtr_mapper.get(MISSING_TEXTURE) {
+        Some(ind) => *ind,
+        None => {
+            log::warn!("Missing texture {MISSING_TEXTURE:?} is not loaded either, using the first texture");
+            0
+        }
+    }
+}
+
+// how texture files that cannot be loaded at startup are handled
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureLoading {
+    Strict,  // fails to launch
+    Lenient,  // logs a warning and substitutes the missing texture in its layer
+}
+
+// extent and color of the generated missing texture, when the missing texture file itself can't be loaded
+const FALLBACK_EXTENT: (u32, u32) = (16, 16);
+const FALLBACK_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+// decoded RGBA pixels of all the texture array layers, in the order of their paths
+pub(crate) struct TextureLayers {
+    raw_buf: Vec<u8>,
+    offsets: Vec<usize>,
+    extent: (u32, u32),
+    txtr_mapper: HashMap<String, u32>,
+}
+
+pub(crate) fn decode_texture(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    decode_png(fs::File::open(path)?)
+}
+
+// e.g. of the bytes embedded with include_bytes!
+fn decode_png(png: impl io::Read) -> io::Result<(u32, u32, Vec<u8>)> {
+    let decoder = png::Decoder::new(png);
+    let mut reader = decoder.read_info()?;
+    let mut txtr_raw_buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut txtr_raw_buf)?;
+    log::trace!(target: MTXG_LOG, "\tTEXTURE FORMAT {:?}", info.color_type);
+    Ok((info.width, info.height, txtr_raw_buf))
+}
+
+// width, height and RGBA pixels of each texture, None for the ones that failed to load
+type DecodedTextures = Vec<Option<(u32, u32, Vec<u8>)>>;
+
+// textures that fail to load are None in lenient mode
+fn decode_textures(txtr_path: &[&Path], loading: TextureLoading) -> io::Result<Vec<Option<(u32, u32, Vec<u8>)>>> {
+    let mut decoded = Vec::with_capacity(txtr_path.len());
+    for (ind, path) in txtr_path.iter().enumerate() {
+        log::debug!(target: MTXG_LOG, "LOADING TEXTURE [{}]: {:?}", ind, path.file_stem().unwrap());
+        decoded.push(lenient_texture(path, decode_texture(path), loading)?);
+    }
+    Ok(decoded)
+}
+
+// of the PNG bytes keyed by their texture name, along with the names
+fn decode_texture_bytes(txtr_bytes: &[(&str, &[u8])], loading: TextureLoading) -> io::Result<(Vec<String>, DecodedTextures)> {
+    let mut decoded = Vec::with_capacity(txtr_bytes.len());
+    for (ind, (name, bytes)) in txtr_bytes.iter().enumerate() {
+        log::debug!(target: MTXG_LOG, "LOADING TEXTURE [{}]: {:?} FROM BYTES", ind, name);
+        decoded.push(lenient_texture(name, decode_png(*bytes), loading)?);
+    }
+    Ok((txtr_bytes.iter().map(|(name, _)| name.to_string()).collect(), decoded))
+}
+
+fn lenient_texture<T>(source: &dyn fmt::Debug, txtr: io::Result<T>, loading: TextureLoading) -> io::Result<Option<T>> {
+    match txtr {
+        Ok(txtr) => Ok(Some(txtr)),
+        Err(e) if loading == TextureLoading::Lenient => {
+            log::warn!("Failed to load texture {source:?}, using the {MISSING_TEXTURE:?} texture instead: {e}");
+            Ok(None)
+        }
+        Err(e) => Err(io::Error::new(e.kind(), format!("Failed to load texture {source:?}: {e}"))),
+    }
+}
+
+// where the block textures are loaded from
+#[derive(Copy, Clone, Debug)]
+pub enum TexturePack<'a> {
+    Directory(&'a Path),  // of a PNG file per texture, named after it
+    Embedded(&'a [(&'a str, &'a [u8])]),  // PNG bytes keyed by their texture name, e.g. embedded with include_bytes!
+}
+
+// paths of the texture files within the texture pack directory
+pub(crate) fn texture_pack_paths(root: &Path, files: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
+    if !root.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("Texture pack directory {root:?} not found")));
+    }
+    Ok(files.iter().map(|file| root.join(file)).collect())
+}
+
+// anisotropy_enable and max_anisotropy of the sampler, falling back to plain filtering on the devices created without
+// the sampler anisotropy feature
+fn sampler_anisotropy(supported: bool, max_sampler_anisotropy: f32) -> (vk::Bool32, f32) {
+    if supported {(vk::TRUE, max_sampler_anisotropy)} else {(vk::FALSE, 1.0)}
+}
+
+// mipmap levels of the texture array and the bias added to the sampled level, a negative bias keeps distant blocks
+// sharper at the cost of more shimmering at grazing angles
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct TextureMipmaps {
+    pub(crate) levels: u32,
+    pub(crate) lod_bias: f32,
+}
+
+// down to a single texel, floor(log2(max(width, height)))+1
+fn max_mip_levels((width, height): (u32, u32)) -> u32 {
+    u32::BITS-width.max(height).max(1).leading_zeros()
+}
+
+fn validate_mip_levels(levels: u32, extent: (u32, u32)) -> io::Result<u32> {
+    let max_levels = max_mip_levels(extent);
+    if levels == 0 || levels > max_levels {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "{levels} mipmap levels requested, textures of {}x{} have 1 to {max_levels}", extent.0, extent.1
+        )));
+    }
+    Ok(levels)
+}
+
+fn texture_name(path: &Path) -> String {
+    String::from(path.file_stem().unwrap().to_str().unwrap())
+}
+
+// resizes a square frame to the target edge with the nearest texel, keeping the blocky look of the textures
+fn rescale_frame(frame: &[u8], width: u32, target: u32) -> Vec<u8> {
+    let mut rescaled = Vec::with_capacity((target*target*4) as usize);
+    for y in 0..target {
+        let src_y = y*width/target;
+        for x in 0..target {
+            let src = ((src_y*width+x*width/target)*4) as usize;
+            rescaled.extend_from_slice(&frame[src..src+4]);
+        }
+    }
+    rescaled
+}
+
+// the frames of the texture, rescaled to the target edge unless they already match it
+fn push_frames(raw_buf: &mut Vec<u8>, offsets: &mut Vec<usize>, width: u32, pixels: &[u8], target: u32) {
+    for frame in pixels.chunks((width*width*4) as usize) {
+        offsets.push(raw_buf.len());
+        if width == target {
+            raw_buf.extend_from_slice(frame);
+        } else {
+            raw_buf.extend(rescale_frame(frame, width, target));
+        }
+    }
+}
+
+// in lenient mode, textures that fail to load keep their layer (so the other indices stay the same) filled
+// with the missing texture, and their name maps to the missing texture layer
+// animated textures are a vertical strip of square frames, each frame is loaded into its own consecutive layer
+// textures of differing extents are rescaled to the largest one, the ones that aren't a strip of square frames are
+// all reported at once (or substituted in lenient mode)
+pub(crate) fn load_texture_layers(txtr_path: &[&Path], loading: TextureLoading) -> io::Result<TextureLayers> {
+    let decoded = decode_textures(txtr_path, loading)?;
+    let names: Vec<String> = txtr_path.iter().map(|path| texture_name(path)).collect();
+    layer_textures(&names, decoded, loading)
+}
+
+fn layer_textures(names: &[String], mut decoded: Vec<Option<(u32, u32, Vec<u8>)>>, loading: TextureLoading) -> io::Result<TextureLayers> {
+    let mut malformed = Vec::new();
+    for (name, txtr) in names.iter().zip(decoded.iter_mut()) {
+        if let Some((width, height, _)) = txtr {
+            if *width == 0 || *height == 0 || *height%*width != 0 {
+                malformed.push(format!("{name:?} ({width}x{height})"));
+                *txtr = None;
+            }
+        }
+    }
+    if !malformed.is_empty() {
+        let msg = format!("Textures not made of square frames: {}", malformed.join(", "));
+        match loading {
+            TextureLoading::Strict => return Err(io::Error::new(io::ErrorKind::InvalidData, msg)),
+            TextureLoading::Lenient => log::warn!("{msg}, using the {MISSING_TEXTURE:?} texture instead"),
+        }
+    }
+
+    let extent = decoded.iter().flatten().map(|(w, _, _)| *w).max().map_or(FALLBACK_EXTENT, |w| (w, w));
+    let frame_size = (extent.0*extent.1*4) as usize;
+
+    // layer of the first frame of each texture
+    let mut first_layers = Vec::with_capacity(names.len());
+    let mut layer_count = 0;
+    for txtr in &decoded {
+        first_layers.push(layer_count);
+        layer_count += txtr.as_ref().map_or(1, |(width, height, _)| (height/width) as usize);
+    }
+
+    let missing_txtr = names.iter().zip(&decoded)
+        .position(|(name, txtr)| txtr.is_some() && name == MISSING_TEXTURE);
+    let missing_pixels = match missing_txtr {
+        Some(ind) => {
+            let (width, _, txtr_raw_buf) = decoded[ind].as_ref().unwrap();
+            let mut missing_pixels = Vec::with_capacity(frame_size);
+            push_frames(&mut missing_pixels, &mut Vec::new(), *width, &txtr_raw_buf[..(width*width*4) as usize], extent.0);
+            missing_pixels
+        }
+        None => FALLBACK_COLOR.repeat((extent.0*extent.1) as usize),
+    };
+    let missing_layer = missing_txtr.map(|ind| first_layers[ind]);
+
+    let mut raw_buf = Vec::with_capacity(frame_size*layer_count);
+    let mut offsets = Vec::with_capacity(layer_count);
+    let mut txtr_mapper = HashMap::new();
+    for ((name, txtr), first_layer) in names.iter().zip(decoded).zip(first_layers) {
+        match txtr {
+            Some((width, _, txtr_raw_buf)) => {
+                push_frames(&mut raw_buf, &mut offsets, width, &txtr_raw_buf, extent.0);
+                txtr_mapper.insert(name.clone(), first_layer as u32);
+            }
+            None => {
+                offsets.push(raw_buf.len());
+                raw_buf.extend_from_slice(&missing_pixels);
+                txtr_mapper.insert(name.clone(), missing_layer.unwrap_or(first_layer) as u32);
+            }
+        }
+    }
+
+    Ok(TextureLayers { raw_buf, offsets, extent, txtr_mapper })
+}
+
+// decoded RGBA pixels of the texture atlas, along with the uv rectangle of each texture in the order of their paths
+pub(crate) struct TextureAtlas {
+    raw_buf: Vec<u8>,
+    extent: (u32, u32),
+    txtr_mapper: HashMap<String, u32>,
+    rects: Vec<[f32; 4]>,
+}
+
+// packs the rectangles into rows, tallest first, returning the atlas extent and the offset of each rectangle
+fn pack_atlas(extents: &[(u32, u32)]) -> ((u32, u32), Vec<(u32, u32)>) {
+    let area: u32 = extents.iter().map(|(w, h)| w*h).sum();
+    let width = extents.iter().map(|(w, _)| *w).max().unwrap_or(1)
+        .max((area as f32).sqrt().ceil() as u32)
+        .next_power_of_two();
+
+    let mut order: Vec<usize> = (0..extents.len()).collect();
+    order.sort_by_key(|ind| Reverse(extents[*ind].1));
+
+    let mut offsets = vec![(0, 0); extents.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for ind in order {
+        let (w, h) = extents[ind];
+        if x+w > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        offsets[ind] = (x, y);
+        x += w;
+        row_height = row_height.max(h);
+    }
+
+    ((width, (y+row_height).max(1)), offsets)
+}
+
+// textures that fail to load are handled as with the texture layers, animated textures only pack their first frame
+pub(crate) fn load_texture_atlas(txtr_path: &[&Path], loading: TextureLoading) -> io::Result<TextureAtlas> {
+    let decoded = decode_textures(txtr_path, loading)?;
+    let names: Vec<String> = txtr_path.iter().map(|path| texture_name(path)).collect();
+    Ok(atlas_textures(&names, decoded))
+}
+
+fn atlas_textures(names: &[String], decoded: Vec<Option<(u32, u32, Vec<u8>)>>) -> TextureAtlas {
+    let missing_txtr = names.iter().zip(&decoded)
+        .position(|(name, txtr)| txtr.is_some() && name == MISSING_TEXTURE);
+
+    let mut txtr_mapper = HashMap::new();
+    let mut txtrs = Vec::with_capacity(decoded.len());
+    for (ind, (name, txtr)) in names.iter().zip(decoded).enumerate() {
+        match txtr {
+            Some((width, height, txtr_raw_buf)) => {
+                let height = if height > width && height%width == 0 {width} else {height};
+                txtrs.push((width, height, txtr_raw_buf[..(width*height*4) as usize].to_vec()));
+                txtr_mapper.insert(name.clone(), ind as u32);
+            }
+            None => {
+                let (width, height) = FALLBACK_EXTENT;
+                txtrs.push((width, height, FALLBACK_COLOR.repeat((width*height) as usize)));
+                txtr_mapper.insert(name.clone(), missing_txtr.unwrap_or(ind) as u32);
+            }
+        }
+    }
+
+    let extents: Vec<(u32, u32)> = txtrs.iter().map(|(w, h, _)| (*w, *h)).collect();
+    let ((atlas_width, atlas_height), offsets) = pack_atlas(&extents);
+
+    let mut raw_buf = vec![0; (atlas_width*atlas_height*4) as usize];
+    let mut rects = Vec::with_capacity(txtrs.len());
+    for ((width, height, txtr_raw_buf), (x, y)) in txtrs.iter().zip(offsets) {
+        let row_size = (width*4) as usize;
+        for (row, pixels) in txtr_raw_buf.chunks(row_size).enumerate() {
+            let start = (((y+row as u32)*atlas_width+x)*4) as usize;
+            raw_buf[start..start+row_size].copy_from_slice(pixels);
+        }
+        rects.push([
+            x as f32/atlas_width as f32, y as f32/atlas_height as f32,
+            *width as f32/atlas_width as f32, *height as f32/atlas_height as f32,
+        ]);
+    }
+
+    TextureAtlas { raw_buf, extent: (atlas_width, atlas_height), txtr_mapper, rects }
+}
 
 pub(crate) struct TextureHandler {
     vi: Rc<VulkanInstance>,
@@ -30,49 +572,110 @@ pub(crate) struct TextureHandler {
 
     txtr_mapper: TextureIDMapper,
     txtr_len: u32,
+    layout: TextureLayout,
+    mip_levels: u32,
+    stream: Option<TextureStream>,
 }
 
 impl TextureHandler {
-    const TEXTURE_MIPMAP_LEVELS: u32 = 4;
-    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, txtr_path: Vec<&Path>) -> Self {
-        let mut raw_buf = Vec::new();
-        let mut offsets = Vec::with_capacity(txtr_path.len());
-        let mut mapper = HashMap::new();
-        offsets.push(0);
+    // fails when the mipmap levels go past a single texel of the texture extent
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, txtr_path: Vec<&Path>, loading: TextureLoading,
+                      mipmaps: TextureMipmaps) -> io::Result<Self> {
+        let TextureLayers { raw_buf, offsets, extent, txtr_mapper } = load_texture_layers(&txtr_path, loading)?;
+        let mip_levels = validate_mip_levels(mipmaps.levels, extent)?;
+        Ok(Self::create(vi, device, raw_buf, offsets, extent, TextureIDs::from(txtr_mapper), TextureLayout::Array,
+                        mip_levels, mipmaps.lod_bias))
+    }
 
-        let img_fmt = vk::Format::R8G8B8A8_SRGB;
-        let mut img_extent = None;
-        let mut txtr_mapper = HashMap::new();
-        for (ind, path) in txtr_path.iter().enumerate() {
-            println!("LOADING TEXTURE [{}]: {:?}", ind, path.file_stem().unwrap());
-            txtr_mapper.insert(String::from(path.file_stem().unwrap().to_str().unwrap()), ind as u32);
-
-            let decoder = png::Decoder::new(
-                fs::File::open(path).unwrap()
-            );
-            let mut reader = decoder.read_info().unwrap();
-            let mut txtr_raw_buf = vec![0; reader.output_buffer_size()];
-            let info = reader.next_frame(&mut txtr_raw_buf).unwrap();
-            println!("\tTEXTURE FORMAT {:?}", info.color_type);
-
-            if let None = img_extent {
-                img_extent.replace(vk::Extent3D {
-                    width: info.width, height: info.height, depth: 1
-                });
-                raw_buf.reserve(reader.output_buffer_size()*txtr_path.len());
-            } else if let Some(extent) = img_extent {
-                if extent.width != info.width || extent.height != info.height {
-                    panic!("Texture <{:?}> has a different extent compared to the first", path);
+    // of PNG bytes keyed by their texture name (e.g. embedded with include_bytes!), instead of the texture files
+    pub(crate) fn from_bytes(vi: Rc<VulkanInstance>, device: Rc<Device>, txtr_bytes: Vec<(&str, &[u8])>, loading: TextureLoading,
+                             mipmaps: TextureMipmaps) -> io::Result<Self> {
+        let (names, decoded) = decode_texture_bytes(&txtr_bytes, loading)?;
+        let TextureLayers { raw_buf, offsets, extent, txtr_mapper } = layer_textures(&names, decoded, loading)?;
+        let mip_levels = validate_mip_levels(mipmaps.levels, extent)?;
+        Ok(Self::create(vi, device, raw_buf, offsets, extent, TextureIDs::from(txtr_mapper), TextureLayout::Array,
+                        mip_levels, mipmaps.lod_bias))
+    }
+
+    // for textures of differing extents, the atlas has no mipmaps so neighbouring textures don't bleed into each other
+    // (the mipmap levels are ignored)
+    pub(crate) fn new_atlas(vi: Rc<VulkanInstance>, device: Rc<Device>, txtr_path: Vec<&Path>, loading: TextureLoading,
+                            mipmaps: TextureMipmaps) -> io::Result<Self> {
+        let TextureAtlas { raw_buf, extent, txtr_mapper, rects } = load_texture_atlas(&txtr_path, loading)?;
+        Ok(Self::create(vi, device, raw_buf, vec![0], extent, TextureIDs::atlas(txtr_mapper, rects), TextureLayout::Atlas,
+                        1, mipmaps.lod_bias))
+    }
+
+    pub(crate) fn from_bytes_atlas(vi: Rc<VulkanInstance>, device: Rc<Device>, txtr_bytes: Vec<(&str, &[u8])>,
+                                   loading: TextureLoading, mipmaps: TextureMipmaps) -> io::Result<Self> {
+        let (names, decoded) = decode_texture_bytes(&txtr_bytes, loading)?;
+        let TextureAtlas { raw_buf, extent, txtr_mapper, rects } = atlas_textures(&names, decoded);
+        Ok(Self::create(vi, device, raw_buf, vec![0], extent, TextureIDs::atlas(txtr_mapper, rects), TextureLayout::Atlas,
+                        1, mipmaps.lod_bias))
+    }
+
+    // only allocates the texture array (of capacity layers), a texture is decoded by the meshing thread first meshing it
+    // and uploaded right after
+    // the textures are checked to be a strip of square frames upfront, from the headers of their files
+    pub(crate) fn new_streamed(vi: Rc<VulkanInstance>, device: Rc<Device>, txtr_path: Vec<&Path>, loading: TextureLoading,
+                               mipmaps: TextureMipmaps, capacity: u32) -> io::Result<Self> {
+        let mut frames = HashMap::new();
+        let mut sources = HashMap::new();
+        for path in &txtr_path {
+            // textures that fail to load still get a layer of their own, filled with the missing texture
+            let (name, txtr_frames) = match lenient_texture(path, png_frames(path), loading)? {
+                Some((width, txtr_frames)) => {
+                    sources.insert(texture_name(path), (path.to_path_buf(), width));
+                    (texture_name(path), txtr_frames)
                 }
-            }
+                None => (texture_name(path), 1),
+            };
+            frames.insert(name, txtr_frames);
+        }
+
+        let width = sources.values().map(|(_, width)| *width).max().unwrap_or(FALLBACK_EXTENT.0);
+        let extent = (width, width);
+        let mip_levels = validate_mip_levels(mipmaps.levels, extent)?;
+        let capacity = capacity.clamp(1, unsafe { vi.get_physical_device_properties().limits.max_image_array_layers });
 
-            if offsets.len() < txtr_path.len() {
-                // since the offsets starts with an initial length of 1 at 0 offset
-                offsets.push(offsets.last().unwrap()+reader.output_buffer_size());
+        let paths: HashMap<String, PathBuf> = sources.into_iter().map(|(name, (path, _))| (name, path)).collect();
+        let missing_pixels = match paths.get(MISSING_TEXTURE).map(|path| decode_texture(path)) {
+            Some(Ok((width, _, txtr_raw_buf))) => {
+                let mut missing_pixels = Vec::new();
+                push_frames(&mut missing_pixels, &mut Vec::new(), width, &txtr_raw_buf[..(width*width*4) as usize], extent.0);
+                missing_pixels
             }
-            raw_buf.append(&mut txtr_raw_buf);
-            mapper.insert(path.file_stem().unwrap(), ind);
-        }
+            _ => FALLBACK_COLOR.repeat((extent.0*extent.1) as usize),
+        };
+        let frame_size = missing_pixels.len();
+
+        // the missing texture is uploaded along with the texture array
+        let slots = frames.values().copied().max().unwrap_or(1).max(STREAM_STAGING_SLOTS);
+        let layers = StreamedLayers::new(frames, capacity, TextureSources { paths, extent, missing_pixels });
+        let missing_frames = layers.frames[MISSING_TEXTURE];
+        let raw_buf = layers.sources.decode(MISSING_TEXTURE, missing_frames);
+        let offsets = (0..missing_frames as usize).map(|frame| frame*frame_size).collect();
+        let stream = TextureStream {
+            ctx: None, frame_size,
+            staging_buf: vk::Buffer::null(), staging_mem: vk::DeviceMemory::null(), staging_ptr: ptr::null_mut(),
+            slots, free_slots: Vec::new(), uploads: PendingUploads::new(), queued: VecDeque::new(),
+        };
+
+        let mut handler = Self::create(vi, device, raw_buf, offsets, extent, TextureIDs::streamed(layers), TextureLayout::Array,
+                                       mip_levels, mipmaps.lod_bias);
+        handler.stream.replace(stream);
+        Ok(handler)
+    }
+
+    pub(crate) fn txtr_mapper(&self) -> &TextureIDs {
+        &self.txtr_mapper
+    }
+
+    fn create(vi: Rc<VulkanInstance>, device: Rc<Device>, raw_buf: Vec<u8>, offsets: Vec<usize>, (width, height): (u32, u32),
+              txtr_mapper: TextureIDs, layout: TextureLayout, mip_levels: u32, lod_bias: f32) -> Self {
+        let img_fmt = vk::Format::R8G8B8A8_SRGB;
+        let img_extent = vk::Extent3D { width, height, depth: 1 };
+        let txtr_len = txtr_mapper.streamed_capacity().unwrap_or(offsets.len() as u32);
 
         // let decoder = png::Decoder::new(
         //     fs::File::open("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/grass_side.png").unwrap()
@@ -93,34 +696,38 @@ impl TextureHandler {
             let (buf, buf_mem, _, _) = create_host_buffer(vi.clone(), device.clone(), &raw_buf, vk::BufferUsageFlags::TRANSFER_SRC, true);
 
             let (img, img_mem) = create_local_image(
-                vi.clone(), device.clone(), img_extent.unwrap(), Self::TEXTURE_MIPMAP_LEVELS, img_fmt,
+                vi.clone(), device.clone(), img_extent, mip_levels, img_fmt,
                 vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-                Some(txtr_path.len() as u32),
+                Some(txtr_len),
             );
 
+            let anisotropy = sampler_anisotropy(
+                vi.get_physical_device_features().sampler_anisotropy == vk::TRUE,
+                vi.get_physical_device_properties().limits.max_sampler_anisotropy,
+            );
+            let max_lod_bias = vi.get_physical_device_properties().limits.max_sampler_lod_bias;
             let sampler_info = vk::SamplerCreateInfo {
                 mag_filter: vk::Filter::NEAREST,
                 min_filter: vk::Filter::NEAREST,
                 address_mode_u: vk::SamplerAddressMode::REPEAT,
                 address_mode_v: vk::SamplerAddressMode::REPEAT,
                 address_mode_w: vk::SamplerAddressMode::REPEAT,
-                anisotropy_enable: vk::TRUE,
-                max_anisotropy: vi.get_physical_device_properties().limits.max_sampler_anisotropy,
+                anisotropy_enable: anisotropy.0,
+                max_anisotropy: anisotropy.1,
                 border_color: vk::BorderColor::INT_OPAQUE_BLACK,
                 unnormalized_coordinates: vk::FALSE,
                 compare_enable: vk::FALSE,
                 compare_op: vk::CompareOp::ALWAYS,
                 mipmap_mode: vk::SamplerMipmapMode::LINEAR,
-                mip_lod_bias: 0.0,
+                mip_lod_bias: lod_bias.clamp(-max_lod_bias, max_lod_bias),
                 min_lod: 0.0,
-                max_lod: Self::TEXTURE_MIPMAP_LEVELS as f32,
+                max_lod: mip_levels as f32,
                 ..Default::default()
             };
             let sampler = device.create_sampler(&sampler_info, None)
                 .expect("Failed to create sampler");
 
-            let txtr_len = offsets.clone().len() as u32;
-            println!("TEXTURE ARRAY IMAGE LAYERS: {}", txtr_len);
+            log::debug!(target: MTXG_LOG, "TEXTURE {:?} IMAGE LAYERS: {}", layout, txtr_len);
 
             Self {
                 vi, device,
@@ -130,11 +737,14 @@ impl TextureHandler {
                 img,
                 img_fmt,
                 img_mem,
-                img_extent: img_extent.unwrap(),
+                img_extent,
                 img_view: None,
                 img_sampler: sampler,
-                txtr_mapper: Rc::new(txtr_mapper) as TextureIDMapper,
+                txtr_mapper: Arc::new(txtr_mapper) as TextureIDMapper,
                 txtr_len,
+                layout,
+                mip_levels,
+                stream: None,
             }
         }
     }
@@ -152,7 +762,7 @@ impl TextureHandler {
                 subresource_range: vk::ImageSubresourceRange {
                     aspect_mask: vk::ImageAspectFlags::COLOR,
                     base_mip_level: 0,
-                    level_count: Self::TEXTURE_MIPMAP_LEVELS,
+                    level_count: self.mip_levels,
                     base_array_layer: 0,
                     layer_count: self.txtr_len,
                 },
@@ -165,6 +775,14 @@ impl TextureHandler {
                 vk::DependencyFlags::empty(), &[], &[], &[transfer_barrier]
             );
 
+            // the layers of the streamed textures not uploaded yet stay transparent
+            if self.stream.is_some() {
+                self.device.cmd_clear_color_image(
+                    cmd_buf, self.img, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &vk::ClearColorValue { float32: [0.0; 4] },
+                    &[transfer_barrier.subresource_range],
+                );
+            }
+
             // copy buffer to image
 
             let mut region_layers = Vec::with_capacity(self.staging_buf_offsets.len());
@@ -190,83 +808,164 @@ impl TextureHandler {
 
             // generating mipmaps & transitioning each mipmap level to be read by shaders
             let prop = self.vi.get_physical_device_format_properties(self.img_fmt);
-            if !prop.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
+            if self.mip_levels > 1 && !prop.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
                 panic!("This device does not support linear blitting for mipmaps");
             }
 
-            let mut mipmap_barrier = vk::ImageMemoryBarrier {
-                image: self.img,
-                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-                subresource_range: vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: self.txtr_len,
-                },
-                ..Default::default()
-            };
-            let mut mip_width = self.img_extent.width.clone();
-            let mut mip_height = self.img_extent.height.clone();
-
-            // for layer in 0..self.staging_buf_offsets.len() {
-            //
-            // }
-            for i in 1..Self::TEXTURE_MIPMAP_LEVELS {
-                mipmap_barrier.subresource_range.base_mip_level = i-1;
-                mipmap_barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-                mipmap_barrier.new_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
-                mipmap_barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-                mipmap_barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
-
-                self.device.cmd_pipeline_barrier(
-                    cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::TRANSFER,
-                    vk::DependencyFlags::empty(), &[], &[], &[mipmap_barrier]
-                );
+            self.record_mipmaps(cmd_buf, 0, self.txtr_len);
+        }
+    }
 
-                let src_offset = vk::Offset3D {x: mip_width as i32, y: mip_height as i32, z: 1};
-                if mip_width > 1 {mip_width /= 2}
-                if mip_height > 1 {mip_height /= 2}
-                let dst_offset = vk::Offset3D {x: mip_width as i32, y: mip_height as i32, z: 1};
+    // uploads the streamed textures decoded since through the free slots of the staging buffer, the rest wait for
+    // the slots of the uploads before to be freed once their transfer completed
+    // the terrain holds back the meshes until every reserved texture is submitted, so the upload of a texture is
+    // submitted before the first frame drawing it
+    unsafe fn stream_textures(&mut self) {
+        let Some(mut stream) = self.stream.take() else {
+            return;
+        };
+        let Some(ctx) = stream.ctx.clone() else {
+            self.stream.replace(stream);
+            return;
+        };
+        let free_slots = &mut stream.free_slots;
+        stream.uploads.poll(
+            |fence| ctx.fence_signaled(fence),
+            |fence, (cmd_buf, slots)| {
+                ctx.finalize(cmd_buf, fence);
+                free_slots.extend(slots);
+            },
+        );
+        stream.queued.extend(self.txtr_mapper.take_requested());
 
-                let blit = vk::ImageBlit {
-                    src_offsets: [vk::Offset3D {x: 0, y: 0, z: 0}, src_offset],
-                    src_subresource: vk::ImageSubresourceLayers {
+        let frame_size = stream.frame_size;
+        let mut textures = Vec::new();
+        let mut regions = Vec::new();
+        let mut slots = Vec::new();
+        while stream.queued.front().is_some_and(|(_, frames, _)| *frames as usize <= stream.free_slots.len()) {
+            let (layer, frames, pixels) = stream.queued.pop_front().unwrap();
+            for (frame, frame_pixels) in pixels.chunks(frame_size).enumerate() {
+                let slot = stream.free_slots.pop().unwrap();
+                let offset = slot as usize*frame_size;
+                stream.staging_ptr.add(offset).copy_from_nonoverlapping(frame_pixels.as_ptr(), frame_size);
+                regions.push(vk::BufferImageCopy {
+                    buffer_offset: offset as vk::DeviceSize,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
                         aspect_mask: vk::ImageAspectFlags::COLOR,
-                        mip_level: i-1,
-                        base_array_layer: 0,
-                        layer_count: self.txtr_len,
+                        mip_level: 0,
+                        base_array_layer: layer+frame as u32,
+                        layer_count: 1,
                     },
-                    dst_offsets: [vk::Offset3D {x: 0, y: 0, z: 0}, dst_offset],
-                    dst_subresource: vk::ImageSubresourceLayers {
+                    image_offset: vk::Offset3D {x:0, y:0, z:0},
+                    image_extent: self.img_extent,
+                });
+                slots.push(slot);
+            }
+            textures.push((layer, frames));
+        }
+
+        if !regions.is_empty() {
+            log::debug!(target: MTXG_LOG, "STREAMING {} TEXTURE LAYERS", regions.len());
+            let (cmd_buf, fence) = ctx.record_fenced(|cmd_buf| {
+                let barriers: Vec<vk::ImageMemoryBarrier> = textures.iter().map(|(layer, frames)| vk::ImageMemoryBarrier {
+                    old_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    image: self.img,
+                    subresource_range: vk::ImageSubresourceRange {
                         aspect_mask: vk::ImageAspectFlags::COLOR,
-                        mip_level: i,
-                        base_array_layer: 0,
-                        layer_count: self.txtr_len,
+                        base_mip_level: 0,
+                        level_count: self.mip_levels,
+                        base_array_layer: *layer,
+                        layer_count: *frames,
                     },
-                };
-
-                self.device.cmd_blit_image(
-                    cmd_buf, self.img, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-                    self.img, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[blit], vk::Filter::LINEAR
-                );
-
-                mipmap_barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
-                mipmap_barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-                mipmap_barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
-                mipmap_barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
-
+                    src_access_mask: vk::AccessFlags::SHADER_READ,
+                    dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    ..Default::default()
+                }).collect();
                 self.device.cmd_pipeline_barrier(
-                    cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER,
-                    vk::DependencyFlags::empty(), &[], &[], &[mipmap_barrier]
+                    cmd_buf, vk::PipelineStageFlags::FRAGMENT_SHADER, vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(), &[], &[], &barriers
                 );
-            }
+                self.device.cmd_copy_buffer_to_image(
+                    cmd_buf, stream.staging_buf, self.img, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions
+                );
+                for (layer, frames) in &textures {
+                    self.record_mipmaps(cmd_buf, *layer, *frames);
+                }
+            });
+            stream.uploads.push(fence, (cmd_buf, slots));
+            self.txtr_mapper.uploaded(textures.len() as u32);
+        }
+        self.stream.replace(stream);
+    }
 
-            mipmap_barrier.subresource_range.base_mip_level = Self::TEXTURE_MIPMAP_LEVELS-1;
+    // generates the mipmaps of the layers from their first level, leaving every level to be read by shaders
+    unsafe fn record_mipmaps(&self, cmd_buf: vk::CommandBuffer, first_layer: u32, layer_count: u32) {
+        let mut mipmap_barrier = vk::ImageMemoryBarrier {
+            image: self.img,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: first_layer,
+                layer_count,
+            },
+            ..Default::default()
+        };
+        let mut mip_width = self.img_extent.width.clone();
+        let mut mip_height = self.img_extent.height.clone();
+
+        // for layer in 0..self.staging_buf_offsets.len() {
+        //
+        // }
+        for i in 1..self.mip_levels {
+            mipmap_barrier.subresource_range.base_mip_level = i-1;
             mipmap_barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-            mipmap_barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+            mipmap_barrier.new_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
             mipmap_barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+            mipmap_barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
+
+            self.device.cmd_pipeline_barrier(
+                cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(), &[], &[], &[mipmap_barrier]
+            );
+
+            let src_offset = vk::Offset3D {x: mip_width as i32, y: mip_height as i32, z: 1};
+            if mip_width > 1 {mip_width /= 2}
+            if mip_height > 1 {mip_height /= 2}
+            let dst_offset = vk::Offset3D {x: mip_width as i32, y: mip_height as i32, z: 1};
+
+            let blit = vk::ImageBlit {
+                src_offsets: [vk::Offset3D {x: 0, y: 0, z: 0}, src_offset],
+                src_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: i-1,
+                    base_array_layer: first_layer,
+                    layer_count,
+                },
+                dst_offsets: [vk::Offset3D {x: 0, y: 0, z: 0}, dst_offset],
+                dst_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: i,
+                    base_array_layer: first_layer,
+                    layer_count,
+                },
+            };
+
+            self.device.cmd_blit_image(
+                cmd_buf, self.img, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.img, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[blit], vk::Filter::LINEAR
+            );
+
+            mipmap_barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+            mipmap_barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+            mipmap_barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
             mipmap_barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
 
             self.device.cmd_pipeline_barrier(
@@ -274,6 +973,17 @@ impl TextureHandler {
                 vk::DependencyFlags::empty(), &[], &[], &[mipmap_barrier]
             );
         }
+
+        mipmap_barrier.subresource_range.base_mip_level = self.mip_levels-1;
+        mipmap_barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+        mipmap_barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        mipmap_barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+        mipmap_barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+
+        self.device.cmd_pipeline_barrier(
+            cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(), &[], &[], &[mipmap_barrier]
+        );
     }
 }
 
@@ -296,22 +1006,37 @@ impl Component for TextureHandler {
     }
 
     fn update(&mut self) {
+        unsafe { self.stream_textures(); }
+    }
 
+    fn diagnostics(&self, diagnostics: &mut Diagnostics) {
+        diagnostics.section("textures", vec![
+            ("layout", format!("{:?}", self.layout)),
+            ("textures", self.txtr_mapper.ids().len().to_string()),
+            ("layers", self.txtr_len.to_string()),
+            ("extent", format!("{}x{}", self.img_extent.width, self.img_extent.height)),
+        ]);
     }
 
     unsafe fn load_descriptors(&mut self, ctx: CmdBufContext) -> Vec<RenderData> {
         ctx.record(self.record());
+        if let Some(stream) = self.stream.as_mut() {
+            stream.allocate_staging(self.vi.clone(), self.device.clone(), ctx.clone());
+        }
 
         let img_view_info = vk::ImageViewCreateInfo {
             image: self.img,
-            view_type: vk::ImageViewType::TYPE_2D_ARRAY,
+            view_type: match self.layout {
+                TextureLayout::Array => vk::ImageViewType::TYPE_2D_ARRAY,
+                TextureLayout::Atlas => vk::ImageViewType::TYPE_2D,
+            },
             format: vk::Format::R8G8B8A8_SRGB,
             subresource_range: vk::ImageSubresourceRange {
                 aspect_mask: vk::ImageAspectFlags::COLOR,
                 base_mip_level: 0,
-                level_count: Self::TEXTURE_MIPMAP_LEVELS,
+                level_count: self.mip_levels,
                 base_array_layer: 0,
-                layer_count: self.staging_buf_offsets.len() as u32,
+                layer_count: self.txtr_len,
             },
             ..Default::default()
         };
@@ -341,5 +1066,294 @@ impl Component for TextureHandler {
 
         self.device.destroy_image(self.img, None);
         self.device.free_memory(self.img_mem, None);
+
+        if let Some(stream) = self.stream.as_mut() {
+            stream.destroy(&self.device);
+        }
+    }
+}
+
+// frames of the staging buffer of the streamed textures, more are allocated for textures with more frames
+const STREAM_STAGING_SLOTS: u32 = 16;
+
+// the staging of the streamed textures into the texture array once decoded
+struct TextureStream {
+    ctx: Option<CmdBufContext>,  // once the descriptors are loaded
+    frame_size: usize,  // of every slot
+    staging_buf: vk::Buffer,
+    staging_mem: vk::DeviceMemory,
+    staging_ptr: *mut u8,
+    slots: u32,
+    free_slots: Vec<u32>,
+    uploads: PendingUploads<(vk::CommandBuffer, Vec<u32>)>,  // with the slots they read from
+    queued: VecDeque<(u32, u32, Vec<u8>)>,  // decoded textures waiting for free slots
+}
+
+impl TextureStream {
+    unsafe fn allocate_staging(&mut self, vi: Rc<VulkanInstance>, device: Rc<Device>, ctx: CmdBufContext) {
+        let size = (self.frame_size*self.slots as usize) as vk::DeviceSize;
+        let (buf, mem, _) = allocate_buffer(
+            vi, device.clone(), size, vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+        );
+        self.staging_ptr = device.map_memory(mem, 0, size, vk::MemoryMapFlags::empty()).unwrap() as *mut u8;
+        self.staging_buf = buf;
+        self.staging_mem = mem;
+        self.free_slots = (0..self.slots).collect();
+        self.ctx.replace(ctx);
+    }
+
+    unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(ctx) = &self.ctx {
+            for (fence, (cmd_buf, _)) in self.uploads.drain() {
+                ctx.finalize(cmd_buf, fence);
+            }
+            device.destroy_buffer(self.staging_buf, None);
+            device.free_memory(self.staging_mem, None);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, Log, Metadata, Record};
+    use super::*;
+
+    static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct WarningLogger;
+
+    impl Log for WarningLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Warn
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                WARNINGS.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_unknown_texture_maps_to_missing_texture() {
+        let _ = log::set_logger(&WarningLogger);
+        log::set_max_level(log::LevelFilter::Warn);
+
+        let txtr_mapper = HashMap::from([
+            (String::from("dirt"), 0),
+            (String::from(MISSING_TEXTURE), 1),
+            (String::from("stone"), 2),
+        ]);
+
+        assert_eq!(texture_index(&txtr_mapper, "stone"), 2);
+        assert_eq!(texture_index(&txtr_mapper, "stnoe"), 1);
+        assert_eq!(texture_index(&txtr_mapper, "stnoe"), 1);
+
+        let warnings: Vec<String> = WARNINGS.lock().unwrap().iter()
+            .filter(|w| w.contains("\"stnoe\""))
+            .cloned()
+            .collect();
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+    }
+
+    fn texture_path(name: &str) -> String {
+        format!("{}/src/resource/block_textures/{name}.png", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn test_unloadable_texture_handling() {
+        let paths = [texture_path("null"), texture_path("does_not_exist"), texture_path("stone")];
+        let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+
+        // the missing texture keeps its layer, but maps to the null texture
+        let layers = load_texture_layers(&paths, TextureLoading::Lenient).unwrap();
+        assert_eq!(layers.offsets.len(), 3);
+        assert_eq!(layers.txtr_mapper["null"], 0);
+        assert_eq!(layers.txtr_mapper["does_not_exist"], 0);
+        assert_eq!(layers.txtr_mapper["stone"], 2);
+        let layer_size = layers.offsets[1];
+        assert_eq!(layers.raw_buf[layer_size..2*layer_size], layers.raw_buf[..layer_size]);
+
+        assert!(load_texture_layers(&paths, TextureLoading::Strict).is_err());
+    }
+
+    // RGBA PNG of the pixels, as it would be embedded with include_bytes!
+    fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_texture_layers_from_bytes() {
+        let red_green: Vec<u8> = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 255, 0, 255], [255, 0, 0, 255]].concat();
+        let red_green = encode_png(2, 2, &red_green);
+        let stone = fs::read(texture_path("stone")).unwrap();
+        let txtr_bytes: [(&str, &[u8]); 3] = [("checker", &red_green), ("corrupt", &[1, 2, 3]), ("stone", &stone)];
+
+        let (names, decoded) = decode_texture_bytes(&txtr_bytes, TextureLoading::Lenient).unwrap();
+        let layers = layer_textures(&names, decoded, TextureLoading::Lenient).unwrap();
+        assert_eq!(layers.offsets.len(), 3);
+        assert_eq!(layers.txtr_mapper["checker"], 0);
+        assert_eq!(layers.txtr_mapper["stone"], 2);
+
+        // the 2x2 texture is scaled up to the extent of the stone texture
+        let (width, _, _) = decode_texture(Path::new(&texture_path("stone"))).unwrap();
+        assert_eq!(layers.extent, (width, width));
+        assert_eq!(layers.raw_buf[..4], [255, 0, 0, 255]);
+        let last = layers.offsets[1]-4;
+        assert_eq!(layers.raw_buf[last..last+4], [255, 0, 0, 255]);
+
+        let err = decode_texture_bytes(&txtr_bytes, TextureLoading::Strict).err().unwrap();
+        assert!(err.to_string().contains("corrupt"), "{err}");
+    }
+
+    #[test]
+    fn test_streamed_layers_reserved_once_meshed() {
+        let frames = HashMap::from([
+            (String::from("null"), 1), (String::from("stone"), 1), (String::from("water"), 4), (String::from("dirt"), 1),
+        ]);
+        let txtr_ids = TextureIDs::streamed(StreamedLayers::new(frames, 6, TextureSources::default()));
+        assert!(txtr_ids.take_requested().is_empty());
+        assert!(!txtr_ids.streaming());
+        assert!(txtr_ids.contains("dirt"));
+        assert_eq!(txtr_ids.ids().len(), 1);
+
+        // a single layer after the initial upload, requested once
+        assert_eq!(txtr_ids.index("stone"), 1);
+        assert_eq!(txtr_ids.index("stone"), 1);
+        assert_eq!(txtr_ids.take_requested(), vec![(1, 1, vec![])]);
+        assert!(txtr_ids.take_requested().is_empty());
+
+        // the frames of an animated texture are consecutive
+        assert_eq!(txtr_ids.index("water"), 2);
+        // the array is full, so the texture falls back to the missing texture without being requested
+        assert_eq!(txtr_ids.index("dirt"), 0);
+        assert_eq!(txtr_ids.index("dirt"), 0);
+        assert_eq!(txtr_ids.take_requested(), vec![(2, 4, vec![])]);
+        assert_eq!(txtr_ids.index("unknown"), 0);
+        assert_eq!(txtr_ids.ids(), HashMap::from([
+            (String::from("null"), 0), (String::from("stone"), 1), (String::from("water"), 2),
+        ]));
+    }
+
+    #[test]
+    fn test_streamed_texture_decoded_until_uploaded() {
+        let paths = HashMap::from([
+            (String::from("null"), PathBuf::from(texture_path("null"))),
+            (String::from("stone"), PathBuf::from(texture_path("stone"))),
+        ]);
+        let (width, _) = png_frames(&paths["stone"]).unwrap();
+        let missing_pixels = FALLBACK_COLOR.repeat((width*width) as usize);
+        let sources = TextureSources { paths, extent: (width, width), missing_pixels: missing_pixels.clone() };
+        let frames = HashMap::from([(String::from("null"), 1), (String::from("stone"), 1), (String::from("gone"), 1)]);
+        let txtr_ids = TextureIDs::streamed(StreamedLayers::new(frames, 4, sources));
+
+        // reserving a layer after the texture array got uploaded decodes the texture right away
+        assert_eq!(txtr_ids.index("stone"), 1);
+        assert_eq!(txtr_ids.index("gone"), 2);
+        assert!(txtr_ids.streaming());
+        let (_, _, txtr_raw_buf) = decode_texture(Path::new(&texture_path("stone"))).unwrap();
+        let mut stone = Vec::new();
+        push_frames(&mut stone, &mut Vec::new(), width, &txtr_raw_buf, width);
+        assert_eq!(txtr_ids.take_requested(), vec![(1, 1, stone), (2, 1, missing_pixels)]);
+
+        // the meshes using them are held back until their upload is submitted
+        assert!(txtr_ids.streaming());
+        txtr_ids.uploaded(2);
+        assert!(!txtr_ids.streaming());
+    }
+
+    #[test]
+    fn test_rescale_frame_nearest() {
+        let texel = |v: u8| [v, v, v, 255];
+        let frame: Vec<u8> = [1, 2, 3, 4].into_iter().flat_map(texel).collect();
+
+        // each texel of the 2x2 frame becomes a 2x2 block
+        let upscaled = rescale_frame(&frame, 2, 4);
+        let rows: Vec<u8> = upscaled.chunks(4).map(|t| t[0]).collect();
+        assert_eq!(rows, [1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+
+        // non power of two targets pick the nearest texel below
+        let downscaled = rescale_frame(&upscaled, 4, 3);
+        let rows: Vec<u8> = downscaled.chunks(4).map(|t| t[0]).collect();
+        assert_eq!(rows, [1, 1, 2, 1, 1, 2, 3, 3, 4]);
+        assert_eq!(rescale_frame(&frame, 2, 2), frame);
+    }
+
+    #[test]
+    fn test_sampler_anisotropy_fallback() {
+        assert_eq!(sampler_anisotropy(true, 16.0), (vk::TRUE, 16.0));
+        // the limit is ignored (and may be 1.0 already) without the feature
+        assert_eq!(sampler_anisotropy(false, 16.0), (vk::FALSE, 1.0));
+        assert_eq!(sampler_anisotropy(false, 1.0), (vk::FALSE, 1.0));
+    }
+
+    #[test]
+    fn test_mip_levels_validated_against_extent() {
+        assert_eq!(max_mip_levels((16, 16)), 5);
+        assert_eq!(max_mip_levels((16, 64)), 7);
+        assert_eq!(max_mip_levels((17, 1)), 5);
+        assert_eq!(max_mip_levels((1, 1)), 1);
+
+        assert_eq!(validate_mip_levels(4, (16, 16)).unwrap(), 4);
+        assert_eq!(validate_mip_levels(5, (16, 16)).unwrap(), 5);
+        assert_eq!(validate_mip_levels(6, (16, 16)).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(validate_mip_levels(0, (16, 16)).is_err());
+    }
+
+    #[test]
+    fn test_texture_pack_paths() {
+        let root = format!("{}/src/resource/block_textures", env!("CARGO_MANIFEST_DIR"));
+        let paths = texture_pack_paths(Path::new(&root), &["stone.png", "dirt.png"]).unwrap();
+        assert_eq!(paths, vec![Path::new(&root).join("stone.png"), Path::new(&root).join("dirt.png")]);
+        assert_eq!(texture_name(&paths[0]), "stone");
+
+        let err = texture_pack_paths(Path::new(&root).join("does_not_exist").as_path(), &["stone.png"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("does_not_exist"));
+    }
+
+    #[test]
+    fn test_pack_atlas_without_overlaps() {
+        let extents = [(16, 16), (32, 8), (8, 32), (16, 16), (4, 4), (64, 16)];
+        let ((width, height), offsets) = pack_atlas(&extents);
+        assert!(width.is_power_of_two());
+
+        let rects: Vec<_> = extents.iter().zip(&offsets).map(|((w, h), (x, y))| (*x, *y, x+w, y+h)).collect();
+        for (ind, (x0, y0, x1, y1)) in rects.iter().enumerate() {
+            assert!(*x1 <= width && *y1 <= height, "{:?} outside of {width}x{height}", extents[ind]);
+            for (ox0, oy0, ox1, oy1) in &rects[ind+1..] {
+                assert!(x1 <= ox0 || ox1 <= x0 || y1 <= oy0 || oy1 <= y0, "{:?} overlaps", extents[ind]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_atlas_texture_rects() {
+        let paths = [texture_path("null"), texture_path("does_not_exist"), texture_path("stone")];
+        let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+
+        let atlas = load_texture_atlas(&paths, TextureLoading::Lenient).unwrap();
+        assert_eq!(atlas.rects.len(), 3);
+        assert_eq!(atlas.txtr_mapper["does_not_exist"], 0);
+
+        // the top left pixel of the stone texture is copied to the corner of its rect
+        let (_, _, stone) = decode_texture(paths[2]).unwrap();
+        let [x, y, _, _] = atlas.rects[atlas.txtr_mapper["stone"] as usize];
+        let (x, y) = ((x*atlas.extent.0 as f32) as usize, (y*atlas.extent.1 as f32) as usize);
+        let start = (y*atlas.extent.0 as usize+x)*4;
+        assert_eq!(atlas.raw_buf[start..start+4], stone[..4]);
+
+        let txtr_ids = TextureIDs::atlas(atlas.txtr_mapper, atlas.rects.clone());
+        let [x, y, w, h] = atlas.rects[2];
+        assert_eq!(txtr_ids.uv(2, [1.0, 0.5]), [x+w, y+h*0.5]);
     }
 }
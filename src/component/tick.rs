@@ -1,56 +1,93 @@
-use std::{ffi, mem};
 use std::rc::Rc;
-use ash::{Device, vk};
+use ash::Device;
 use uom::num_traits::FloatConst;
+use winit::event::VirtualKeyCode;
 use crate::component::{Component, RenderData, RenderDataPurpose};
 use crate::handler::VulkanInstance;
-use crate::util::{CmdBufContext, create_host_buffer, update_buffer};
+use crate::util::{CmdBufContext, FrameUniform};
 use crate::world::WorldEvent;
 
-pub(crate) struct TickSync {
-    device: Rc<Device>,
+pub(crate) const TICKS_PER_SECOND: f32 = 60.0;  // at a rate of 1.0
 
-    tick: bool,
+// simulation clock, the rate scales both the animation time and the number of ticks per second
+struct TickClock {
+    rate: f32,
     time: f32,
-    increment: f32,
-
-    ubo_buf: vk::Buffer,
-    ubo_mem: vk::DeviceMemory,
-    ubo_ptr: *mut ffi::c_void,
-    ubo_size: vk::DeviceSize,
+    pending: f32,  // fraction of a tick carried over to the next frame
 }
 
-impl TickSync {
-    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, speed: f32) -> Self {
-        let time = 0.0;
+impl TickClock {
+    const MAX_TICKS_PER_FRAME: u32 = 5;  // so a long frame doesn't stall on catching up
 
-        let (ubo_buf, ubo_mem, ubo_ptr, ubo_size) = unsafe {
-            create_host_buffer(vi.clone(), device.clone(), &[time], vk::BufferUsageFlags::UNIFORM_BUFFER, false)
-        };
+    fn new(rate: f32) -> Self {
+        Self { rate, time: 0.0, pending: 0.0 }
+    }
 
-        Self {
-            device,
-            tick: false,
-            time,
-            increment: speed,
-            ubo_buf,
-            ubo_mem,
-            ubo_ptr,
-            ubo_size
+    fn set_rate(&mut self, rate: f32) -> bool {
+        if !(rate.is_finite() && rate > 0.0) {
+            return false;
         }
+        self.rate = rate;
+        true
     }
 
-    fn update_animation_time(&mut self, delta: f32) {
+    // returns the number of ticks elapsed
+    fn advance(&mut self, delta: f32) -> u32 {
         if self.time >= 2.0*f32::PI() {  // makes looping animation using trig easier
             self.time = 0.0;
         } else {
-            self.time += self.increment*delta;
+            self.time += self.rate*delta;
         }
 
-        unsafe {
-            update_buffer(self.ubo_ptr, &[self.time], self.ubo_size);
+        self.pending += self.rate*delta*TICKS_PER_SECOND;
+        let ticks = self.pending.floor();
+        self.pending -= ticks;
+        (ticks as u32).min(Self::MAX_TICKS_PER_FRAME)
+    }
+}
+
+// in the layout of the time uniform of the chunk shaders
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct TimeUBO {
+    time: f32,
+    fluid_motion: f32,  // 1 for waving and flowing fluids, 0 for still fluids
+}
+
+impl TimeUBO {
+    fn new(clock: &TickClock, fluid_motion: bool) -> Self {
+        Self { time: clock.time, fluid_motion: if fluid_motion {1.0} else {0.0} }
+    }
+}
+
+pub(crate) struct TickSync {
+    tick: bool,
+    clock: TickClock,
+    fluid_motion: bool,
+
+    ubo: FrameUniform<TimeUBO>,
+}
+
+impl TickSync {
+    pub(crate) fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, rate: f32) -> Self {
+        let clock = TickClock::new(rate);
+
+        let ubo = unsafe { FrameUniform::new(vi, device, TimeUBO::new(&clock, true)) };
+
+        Self {
+            tick: false,
+            clock,
+            fluid_motion: true,
+            ubo,
         }
     }
+
+    fn update_animation_time(&mut self, delta: f32) -> u32 {
+        let ticks = self.clock.advance(delta);
+
+        self.ubo.set(TimeUBO::new(&self.clock, self.fluid_motion));
+        ticks
+    }
 }
 
 impl Component for TickSync {
@@ -63,11 +100,32 @@ impl Component for TickSync {
             WorldEvent::DeltaTime(dur) => {
                 self.tick = false;
 
-                self.update_animation_time(dur.as_secs_f32());
+                let ticks = self.update_animation_time(dur.as_secs_f32());
 
-                vec![
-                    WorldEvent::Tick,
-                ]
+                if ticks > 0 {
+                    vec![WorldEvent::Tick { count: ticks }]
+                } else {
+                    vec![]
+                }
+            }
+            WorldEvent::SetTickRate(rate) => {
+                if !self.clock.set_rate(rate) {
+                    log::warn!("Ignoring the invalid tick rate {rate}, it must be positive");
+                }
+                vec![]
+            }
+            WorldEvent::FluidMotion(enabled) => {
+                self.fluid_motion = enabled;
+                vec![]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::LBracket) => {
+                vec![WorldEvent::SetTickRate(self.clock.rate/2.0)]
+            }
+            WorldEvent::KeyPressed(VirtualKeyCode::RBracket) => {
+                vec![WorldEvent::SetTickRate(self.clock.rate*2.0)]
+            }
+            WorldEvent::Start => {
+                vec![WorldEvent::SetTickRate(self.clock.rate)]
             }
             _ => {vec![]}
         }
@@ -77,21 +135,58 @@ impl Component for TickSync {
         self.tick = true;
     }
 
+    fn prepare_frame(&mut self, frame_index: usize) {
+        unsafe { self.ubo.write(frame_index); }
+    }
+
     unsafe fn load_descriptors(&mut self, _: CmdBufContext) -> Vec<RenderData> {
-        vec![
-            RenderData::InitialDescriptorBuffer(
-                vec![vk::DescriptorBufferInfo {
-                    buffer: self.ubo_buf,
-                    offset: 0 as vk::DeviceSize,
-                    range: mem::size_of::<f32>() as vk::DeviceSize,
-                }],
-                RenderDataPurpose::Time
-            )
-        ]
+        vec![RenderData::InitialDescriptorBuffer(self.ubo.descriptor_buffer_infos(), RenderDataPurpose::Time)]
     }
 
     unsafe fn destroy(&mut self) {
-        self.device.destroy_buffer(self.ubo_buf, None);
-        self.device.free_memory(self.ubo_mem, None);
+        self.ubo.destroy();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use super::*;
+
+    // ticks emitted over a second of 60 fps frames
+    fn ticks_over_second(clock: &mut TickClock) -> u32 {
+        (0..60).map(|_| clock.advance(1.0/60.0)).sum()
+    }
+
+    #[test]
+    fn test_tick_rate_changes_ticks_emitted() {
+        let mut clock = TickClock::new(1.0);
+        assert!((59..=61).contains(&ticks_over_second(&mut clock)));
+
+        assert!(clock.set_rate(0.5));
+        assert!((29..=31).contains(&ticks_over_second(&mut clock)));
+
+        // fast forwarding is bounded by the ticks per frame
+        assert!(clock.set_rate(2.0));
+        assert!((119..=121).contains(&ticks_over_second(&mut clock)));
+        assert!(clock.set_rate(100.0));
+        assert_eq!(ticks_over_second(&mut clock), 60*TickClock::MAX_TICKS_PER_FRAME);
+
+        assert!(!clock.set_rate(0.0));
+        assert!(!clock.set_rate(-1.0));
+        assert!(!clock.set_rate(f32::NAN));
+        assert_eq!(clock.rate, 100.0);
+    }
+
+    #[test]
+    fn test_still_fluids_keep_the_time() {
+        let mut clock = TickClock::new(1.0);
+        clock.advance(0.5);
+
+        assert_eq!(TimeUBO::new(&clock, true), TimeUBO { time: 0.5, fluid_motion: 1.0 });
+        // only the fluids stop, the texture animations carry on
+        assert_eq!(TimeUBO::new(&clock, false), TimeUBO { time: 0.5, fluid_motion: 0.0 });
+        assert_eq!(mem::size_of::<TimeUBO>(), 2*mem::size_of::<f32>());
     }
 }
@@ -0,0 +1,311 @@
+use std::path::Path;
+use std::time::Duration;
+use crate::{DEFAULT_BLOCK_REGISTRY, DEFAULT_SEED, DEFAULT_TEXTURE_PACK};
+use crate::component::camera::{FovAxis, Reach};
+use crate::component::terrain::{tier_chunk_sizes, TranslucentSort};
+use crate::component::terrain::chunk_mesh::{ChunkUnload, VertexLimit, WorldBounds};
+use crate::component::texture::{TextureLayout, TextureMipmaps, TexturePack};
+use crate::debug::DebugVisibility;
+use crate::error::MatrixagonError;
+use crate::handler::DeviceOverride;
+use crate::shader::Msaa;
+use crate::swapchain::{DepthFormat, PresentMode};
+use crate::world::World;
+
+
+// what the app is initialized with, the defaults are overridden by chaining the setters, e.g.
+// AppConfig::default().seed(7).msaa(Msaa::X4)
+pub struct AppConfig<'a> {
+    pub(crate) validate: bool,
+    pub(crate) debug_visibility: DebugVisibility,
+    pub(crate) fullscreen: bool,
+    pub(crate) mouse_lock: bool,
+    pub(crate) pause_unfocused: bool,
+    pub(crate) generation_workers: Option<usize>,
+    pub(crate) max_fps: Option<u32>,
+    pub(crate) present_mode: PresentMode,
+    pub(crate) msaa: Msaa,
+    pub(crate) depth_formats: Vec<DepthFormat>,
+    pub(crate) device: Option<DeviceOverride>,
+    pub(crate) texture_pack: TexturePack<'a>,
+    pub(crate) texture_layout: TextureLayout,
+    pub(crate) texture_streaming: Option<u32>,
+    pub(crate) mipmaps: TextureMipmaps,
+    pub(crate) block_registry: &'a Path,
+    pub(crate) skybox_faces: Option<[&'a Path; 6]>,
+    pub(crate) seed: u32,
+    pub(crate) lod_downsample: [u32; 3],
+    pub(crate) chunk_size: u32,
+    pub(crate) world_bounds: Option<WorldBounds>,
+    pub(crate) translucent_sort: TranslucentSort,
+    pub(crate) chunk_unload: ChunkUnload,
+    pub(crate) vertex_limit: VertexLimit,
+    pub(crate) fov: (f32, FovAxis),
+    pub(crate) reach: Reach,
+    pub(crate) event_history_frames: usize,
+    pub(crate) on_initial_generation: Option<Box<dyn FnOnce()>>,
+}
+
+impl Default for AppConfig<'_> {
+    fn default() -> Self {
+        Self {
+            validate: false,
+            debug_visibility: DebugVisibility::default(),
+            fullscreen: false,
+            mouse_lock: true,
+            pause_unfocused: true,
+            generation_workers: None,
+            max_fps: None,
+            present_mode: PresentMode::Fifo,
+            msaa: Msaa::Off,
+            depth_formats: Vec::new(),
+            device: None,
+            texture_pack: TexturePack::Directory(Path::new(DEFAULT_TEXTURE_PACK)),
+            texture_layout: TextureLayout::Array,
+            texture_streaming: None,
+            mipmaps: TextureMipmaps { levels: 4, lod_bias: 0.0 },
+            block_registry: Path::new(DEFAULT_BLOCK_REGISTRY),
+            skybox_faces: None,
+            seed: DEFAULT_SEED,
+            lod_downsample: [1, 1, 2],
+            chunk_size: 32,
+            world_bounds: None,
+            translucent_sort: TranslucentSort::PerChunk,
+            chunk_unload: ChunkUnload::Delayed(Duration::from_secs(30)),
+            vertex_limit: VertexLimit::Warn(200_000),
+            fov: (70.0, FovAxis::Vertical),
+            reach: Reach { walk: 5.0, spectator: 32.0 },
+            event_history_frames: World::DEFAULT_EVENT_HISTORY_FRAMES,
+            on_initial_generation: None,
+        }
+    }
+}
+
+impl<'a> AppConfig<'a> {
+    // enables the Vulkan validation layers
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    pub fn debug_visibility(mut self, debug_visibility: DebugVisibility) -> Self {
+        self.debug_visibility = debug_visibility;
+        self
+    }
+
+    // borderless on the current monitor, instead of a 1000x1000 window
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    // whether the cursor is locked to the window once it's focused
+    pub fn mouse_lock(mut self, mouse_lock: bool) -> Self {
+        self.mouse_lock = mouse_lock;
+        self
+    }
+
+    // whether the rendering also pauses while the window isn't focused (by default), and not just while it's occluded
+    // or minimized
+    pub fn pause_unfocused(mut self, pause_unfocused: bool) -> Self {
+        self.pause_unfocused = pause_unfocused;
+        self
+    }
+
+    // defaults to the available parallelism minus one, 0 generates chunks on the main thread
+    pub fn generation_workers(mut self, workers: usize) -> Self {
+        self.generation_workers = Some(workers);
+        self
+    }
+
+    // caps the frame rate when the present mode doesn't (uncapped by default)
+    pub fn max_fps(mut self, max_fps: u32) -> Self {
+        self.max_fps = Some(max_fps);
+        self
+    }
+
+    // falls back to FIFO (vsync) when the surface doesn't support it, cycled through with V at runtime
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    // lowered to the sample counts the device supports, cycled through with J at runtime, the occlusion culling
+    // (toggled with N) is skipped unless it is off
+    pub fn msaa(mut self, msaa: Msaa) -> Self {
+        self.msaa = msaa;
+        self
+    }
+
+    // tried in order before the default ones (D32 first)
+    pub fn depth_formats(mut self, depth_formats: &[DepthFormat]) -> Self {
+        self.depth_formats = depth_formats.to_vec();
+        self
+    }
+
+    // forces a physical device, instead of the best fitting one (discrete, then integrated, virtual and CPU)
+    pub fn device(mut self, device: DeviceOverride) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    // the directory of the block texture files (DEFAULT_TEXTURE_PACK by default), or their embedded PNG bytes
+    pub fn texture_pack(mut self, texture_pack: TexturePack<'a>) -> Self {
+        self.texture_pack = texture_pack;
+        self
+    }
+
+    // a texture array by default, or an atlas for block textures of different extents, the texture handler and the
+    // chunk shaders are both created with it
+    pub fn texture_layout(mut self, texture_layout: TextureLayout) -> Self {
+        self.texture_layout = texture_layout;
+        self
+    }
+
+    // layers of the texture array the texture files are streamed into once first meshed, instead of loading all of
+    // them upfront (only texture arrays of the texture pack directory are streamed)
+    pub fn texture_streaming(mut self, layers: u32) -> Self {
+        self.texture_streaming = Some(layers);
+        self
+    }
+
+    // mipmap levels of the block textures (4 by default, at most a level per halving of the texture extent), a negative
+    // bias trades aliasing at grazing angles for sharper distant blocks and a positive one the other way around
+    pub fn mipmaps(mut self, levels: u32, lod_bias: f32) -> Self {
+        self.mipmaps = TextureMipmaps { levels, lod_bias };
+        self
+    }
+
+    // the RON file of the block definitions (DEFAULT_BLOCK_REGISTRY by default)
+    pub fn block_registry(mut self, block_registry: &'a Path) -> Self {
+        self.block_registry = block_registry;
+        self
+    }
+
+    // cubemap face images (+x, -x, +y, -y, +z, -z) of the skybox, instead of the generated gradient sky
+    pub fn skybox_faces(mut self, faces: [&'a Path; 6]) -> Self {
+        self.skybox_faces = Some(faces);
+        self
+    }
+
+    // generates the same terrain every time it's used (DEFAULT_SEED by default)
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // blocks merged along each axis by the extreme, high and medium fidelity tiers ([1, 1, 2] by default), trading the
+    // detail of the distant tiers for fewer vertices, each has to divide the chunk edge of its tier
+    pub fn lod_downsample(mut self, lod_downsample: [u32; 3]) -> Self {
+        self.lod_downsample = lod_downsample;
+        self
+    }
+
+    // edge (in blox) of the extreme and high fidelity chunks (32 by default), a divisor of 32 and a multiple of their
+    // downsample factors
+    // larger chunks mean fewer draw calls, buffers and chunks to track, while smaller ones remesh faster on a block edit
+    // and are culled more tightly
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    // limits a finite world (e.g. an arena) to the inclusive range of extreme fidelity chunk coordinates, the terrain
+    // is infinite by default
+    pub fn world_bounds(mut self, world_bounds: WorldBounds) -> Self {
+        self.world_bounds = Some(world_bounds);
+        self
+    }
+
+    // how the translucent faces are ordered back to front (per chunk by default), sorting per face is exact for the
+    // fluid faces but costs a sort and an upload as the camera moves
+    pub fn translucent_sort(mut self, translucent_sort: TranslucentSort) -> Self {
+        self.translucent_sort = translucent_sort;
+        self
+    }
+
+    // when the chunks that moved beyond the render distance are freed (30 seconds later by default, in case the user
+    // comes back)
+    pub fn chunk_unload(mut self, chunk_unload: ChunkUnload) -> Self {
+        self.chunk_unload = chunk_unload;
+        self
+    }
+
+    // guards against a single pathological chunk (e.g. a checkerboard of blocks) producing huge buffers, by default the
+    // chunks over 200,000 vertices are only logged
+    pub fn vertex_limit(mut self, vertex_limit: VertexLimit) -> Self {
+        self.vertex_limit = vertex_limit;
+        self
+    }
+
+    // field of view in degrees (clamped to 10 to 120) across the given screen axis, the other one follows the aspect
+    // ratio (70 degrees vertically by default)
+    pub fn fov(mut self, degrees: f32, axis: FovAxis) -> Self {
+        self.fov = (degrees, axis);
+        self
+    }
+
+    // how far away (in blocks, clamped to 1 to 128) blocks can be broken or placed while walking and in spectator
+    // mode (5 and 32 by default)
+    pub fn reach(mut self, walk: f32, spectator: f32) -> Self {
+        self.reach = Reach { walk, spectator };
+        self
+    }
+
+    // frames of dispatched events kept around, e.g. for the frame times of the diagnostics (300 by default, about 5
+    // seconds at 60 fps), 0 disables the history
+    pub fn event_history_frames(mut self, frames: usize) -> Self {
+        self.event_history_frames = frames;
+        self
+    }
+
+    // called once the terrain around the spawn is first meshed and uploaded (e.g. to hide a loading screen, or to
+    // spawn the player once there is ground to stand on), even if the terrain is regenerated later
+    pub fn on_initial_generation(mut self, hook: impl FnOnce() + 'static) -> Self {
+        self.on_initial_generation = Some(Box::new(hook));
+        self
+    }
+
+    // the terrain options that only work together, checked before anything is created with them
+    pub(crate) fn check_terrain(&self) -> Result<(), MatrixagonError> {
+        if self.chunk_size == 0 || 32%self.chunk_size != 0 {
+            return Err(MatrixagonError::InvalidConfig(format!("chunk size {} is not a divisor of 32", self.chunk_size)));
+        }
+        for (size, factor) in tier_chunk_sizes(self.chunk_size).into_iter().zip(self.lod_downsample) {
+            if factor == 0 || size%factor != 0 {
+                return Err(MatrixagonError::InvalidConfig(format!(
+                    "downsample factor {factor} does not divide the chunk size {size} of its tier"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lod_downsample_checked() {
+        assert!(AppConfig::default().check_terrain().is_ok());
+        assert!(AppConfig::default().lod_downsample([2, 4, 8]).check_terrain().is_ok());
+        // the medium fidelity chunks are larger, so they take factors the others don't
+        assert!(AppConfig::default().lod_downsample([1, 1, 128]).check_terrain().is_ok());
+        assert!(AppConfig::default().lod_downsample([1, 64, 2]).check_terrain().is_err());
+        assert!(AppConfig::default().lod_downsample([1, 3, 2]).check_terrain().is_err());
+        assert!(AppConfig::default().lod_downsample([0, 1, 2]).check_terrain().is_err());
+    }
+
+    #[test]
+    fn test_chunk_size_checked() {
+        assert!(AppConfig::default().chunk_size(16).check_terrain().is_ok());
+        assert!(AppConfig::default().chunk_size(8).lod_downsample([1, 2, 4]).check_terrain().is_ok());
+        assert!(AppConfig::default().chunk_size(24).check_terrain().is_err());
+        assert!(AppConfig::default().chunk_size(64).check_terrain().is_err());
+        assert!(AppConfig::default().chunk_size(0).check_terrain().is_err());
+        // the factors of the extreme and high fidelity tiers have to divide the smaller chunks too
+        assert!(AppConfig::default().chunk_size(2).lod_downsample([4, 1, 2]).check_terrain().is_err());
+    }
+}
@@ -1,8 +1,130 @@
-#[derive(Clone, Copy)]
+use std::time::Duration;
+use ash::vk;
+
+
+#[derive(Clone, Copy, Default)]
 pub struct DebugVisibility {
-    pub vk_setup_output: bool,
-    pub mtxg_output: bool,
-    pub vk_swapchain_output: bool,
-    pub mtxg_render_output: bool,
+    pub chunk_borders: bool,  // initial state of the chunk border overlay (toggled by B)
+    pub panic_on_validation_error: bool,  // panics after the frame (or setup) that raised a validation error
+    pub ignored_validation_messages: &'static [&'static str],  // message id names (e.g. VUID-...) left out entirely
+}
+
+// log targets of the debug output, picked with env_logger (e.g. RUST_LOG=matrixagon2::vk_setup=debug), all of them
+// under the target of the crate
+pub(crate) const VK_SETUP_LOG: &str = "matrixagon2::vk_setup";  // instance, device and queue selection
+pub(crate) const VK_SWAPCHAIN_LOG: &str = "matrixagon2::vk_swapchain";  // surface support, swapchain and framebuffers
+pub(crate) const VK_VALIDATION_LOG: &str = "matrixagon2::vk_validation";  // validation layer messages
+pub(crate) const MTXG_LOG: &str = "matrixagon2::mtxg";  // window, resources and chunk generation
+pub(crate) const MTXG_RENDER_LOG: &str = "matrixagon2::mtxg_render";  // per frame render data and buffer uploads
+
+
+
+// report of the app state for attaching to bug reports, as named sections of key-value pairs
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    sections: Vec<(&'static str, Vec<(&'static str, String)>)>,
 }
 
+impl Diagnostics {
+    pub(crate) fn section(&mut self, name: &'static str, entries: Vec<(&'static str, String)>) {
+        self.sections.push((name, entries));
+    }
+
+    pub(crate) fn gpu(&mut self, props: &vk::PhysicalDeviceProperties, instance_exts: &[String], device_exts: &[String],
+                      layers: &[String]) {
+        let device_name: Vec<u8> = props.device_name.iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as u8)
+            .collect();
+        let version = |v: u32| format!("{}.{}.{}", vk::api_version_major(v), vk::api_version_minor(v), vk::api_version_patch(v));
+        let limits = &props.limits;
+
+        self.section("gpu", vec![
+            ("device_name", String::from_utf8_lossy(&device_name).into_owned()),
+            ("device_type", format!("{:?}", props.device_type)),
+            ("vendor_id", format!("{:#06x}", props.vendor_id)),
+            ("device_id", format!("{:#06x}", props.device_id)),
+            ("api_version", version(props.api_version)),
+            ("driver_version", props.driver_version.to_string()),
+            ("max_image_dimension_2d", limits.max_image_dimension2_d.to_string()),
+            ("max_image_array_layers", limits.max_image_array_layers.to_string()),
+            ("max_sampler_anisotropy", limits.max_sampler_anisotropy.to_string()),
+            ("max_memory_allocation_count", limits.max_memory_allocation_count.to_string()),
+            ("instance_extensions", instance_exts.join(", ")),
+            ("device_extensions", device_exts.join(", ")),
+            ("layers", layers.join(", ")),
+        ]);
+    }
+
+    pub(crate) fn swapchain(&mut self, fmt: vk::SurfaceFormatKHR, depth_fmt: vk::Format, prsnt: vk::PresentModeKHR,
+                            extent: vk::Extent2D) {
+        self.section("swapchain", vec![
+            ("format", format!("{:?}", fmt.format)),
+            ("color_space", format!("{:?}", fmt.color_space)),
+            ("depth_format", format!("{:?}", depth_fmt)),
+            ("present_mode", format!("{:?}", prsnt)),
+            ("extent", format!("{}x{}", extent.width, extent.height)),
+        ]);
+    }
+
+    // frame times of the recent frames, oldest first
+    pub(crate) fn frames(&mut self, frame_times: &[Duration]) {
+        let ms = |dur: Duration| format!("{:.2}", dur.as_secs_f64()*1000.0);
+        let total: Duration = frame_times.iter().sum();
+        let mut entries = vec![("recent_frames", frame_times.len().to_string())];
+        if !frame_times.is_empty() {
+            entries.extend([
+                ("average_frame_ms", ms(total/frame_times.len() as u32)),
+                ("min_frame_ms", ms(*frame_times.iter().min().unwrap())),
+                ("max_frame_ms", ms(*frame_times.iter().max().unwrap())),
+            ]);
+        }
+        self.section("frames", entries);
+    }
+
+    pub(crate) fn report(&self) -> String {
+        let mut report = String::new();
+        for (name, entries) in &self.sections {
+            report.push_str(&format!("[{name}]\n"));
+            for (key, value) in entries {
+                report.push_str(&format!("{key} = {value}\n"));
+            }
+            report.push('\n');
+        }
+        report
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_includes_device_and_swapchain() {
+        let mut props = vk::PhysicalDeviceProperties {
+            device_type: vk::PhysicalDeviceType::DISCRETE_GPU,
+            api_version: vk::make_api_version(0, 1, 3, 250),
+            ..Default::default()
+        };
+        for (c, b) in props.device_name.iter_mut().zip(b"Test GPU 9000") {
+            *c = *b as _;
+        }
+
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.gpu(&props, &[String::from("VK_KHR_surface")], &[String::from("VK_KHR_swapchain")], &[]);
+        diagnostics.swapchain(
+            vk::SurfaceFormatKHR { format: vk::Format::B8G8R8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
+            vk::Format::D32_SFLOAT, vk::PresentModeKHR::MAILBOX, vk::Extent2D { width: 1000, height: 800 },
+        );
+        diagnostics.frames(&[Duration::from_millis(10), Duration::from_millis(20)]);
+        let report = diagnostics.report();
+
+        assert!(report.contains("[gpu]\ndevice_name = Test GPU 9000\n"), "{report}");
+        assert!(report.contains("api_version = 1.3.250\n"), "{report}");
+        assert!(report.contains("device_extensions = VK_KHR_swapchain\n"), "{report}");
+        assert!(report.contains("[swapchain]\nformat = B8G8R8A8_SRGB\n"), "{report}");
+        assert!(report.contains("present_mode = MAILBOX\n"), "{report}");
+        assert!(report.contains("average_frame_ms = 15.00\n"), "{report}");
+    }
+}
@@ -0,0 +1,48 @@
+use std::{error, fmt, io};
+use ash::vk;
+use crate::handler::DeviceOverride;
+
+
+// failures of setting up the app, so the crate can be embedded without aborting the host (e.g. without a Vulkan
+// device able to render)
+#[derive(Debug)]
+pub enum MatrixagonError {
+    Window(winit::error::OsError),
+    SurfaceCreation(vk::Result),
+    NoSuitableDevice,
+    NoPresentSupport,  // devices that can render, but none to the surface of the window
+    DeviceNotFound(DeviceOverride),  // no device to force
+    Vulkan(&'static str, vk::Result),  // what failed, and the result of the call
+    Resource(&'static str, io::Error),  // what failed to load
+    ShaderCreation(String),  // e.g. the shaders failing to compile
+    InvalidConfig(String),  // options of the AppConfig that can't be used together
+}
+
+impl fmt::Display for MatrixagonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixagonError::Window(e) => write!(f, "Failed to create the window: {e}"),
+            MatrixagonError::SurfaceCreation(e) => write!(f, "Failed to create the window surface: {e}"),
+            MatrixagonError::NoSuitableDevice => write!(f, "No Vulkan device supports the required features"),
+            MatrixagonError::NoPresentSupport => write!(f, "No Vulkan device can present to the window surface"),
+            MatrixagonError::DeviceNotFound(DeviceOverride::Index(ind)) => write!(f, "No Vulkan device at index {ind}"),
+            MatrixagonError::DeviceNotFound(DeviceOverride::Name(name)) => write!(f, "No Vulkan device named {name:?}"),
+            MatrixagonError::Vulkan(context, e) => write!(f, "Failed to {context}: {e}"),
+            MatrixagonError::Resource(context, e) => write!(f, "Failed to load {context}: {e}"),
+            MatrixagonError::ShaderCreation(e) => write!(f, "Failed to create the shader: {e}"),
+            MatrixagonError::InvalidConfig(e) => write!(f, "Invalid config: {e}"),
+        }
+    }
+}
+
+impl error::Error for MatrixagonError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            MatrixagonError::Window(e) => Some(e),
+            MatrixagonError::SurfaceCreation(e) | MatrixagonError::Vulkan(_, e) => Some(e),
+            MatrixagonError::Resource(_, e) => Some(e),
+            MatrixagonError::NoSuitableDevice | MatrixagonError::NoPresentSupport | MatrixagonError::DeviceNotFound(_)
+            | MatrixagonError::ShaderCreation(_) | MatrixagonError::InvalidConfig(_) => None,
+        }
+    }
+}
@@ -1,18 +1,26 @@
 use std::rc::Rc;
 use ash::{Device, vk};
 use crate::component::{RenderData, RenderDataPurpose};
-use crate::debug::DebugVisibility;
+use crate::debug::VK_SWAPCHAIN_LOG;
+use crate::error::MatrixagonError;
 use crate::handler::VulkanInstance;
-use crate::util::create_local_image;
+use crate::util::{create_local_image, create_multisampled_image};
 
 
 // presentation attachment is not included
 // all AttachmentRef refers to all elective attachments (including depth/stencil)
 #[derive(Copy, Clone, Debug)]
-pub(crate) enum FBAttachmentRef {
+pub enum FBAttachmentRef {
     Color,
     ColorInput,  // color attachment that can be also used as input (attachments)
     Depth, // uses its own depth format
+    SampledDepth,  // depth attachment that can be also sampled after the renderpass (e.g. for the occlusion culling)
+    // read as input attachments by a later subpass (e.g. a G-buffer), handed to the shader in order as GBufferInpAttachment
+    GBuffer(vk::Format),  // color attachment of its own format
+    DepthInput,
+    // rendered with the sample count and resolved within the renderpass (e.g. into the presentation attachment)
+    MultisampledColor(vk::SampleCountFlags),
+    MultisampledDepth(vk::SampleCountFlags),
 }
 
 
@@ -24,6 +32,8 @@ pub(crate) struct FramebufferManager {
     attachment_imgvs: Vec<vk::ImageView>,
     attachment_imgms: Vec<vk::DeviceMemory>,
     inp_attachment_imgvs: Vec<vk::ImageView>,
+    sampled_depth_imgv: Option<vk::ImageView>,
+    gbuffer_imgvs: Vec<(vk::ImageView, vk::ImageLayout)>,  // along with their layout while read
     pub(crate) framebuffers: Vec<vk::Framebuffer>,  // duplicated to the same amount as presentation images
 }
 
@@ -35,31 +45,37 @@ impl FramebufferManager {
     // }
 
     pub(crate) unsafe fn new_swapchain_bounded(
-        dbv: DebugVisibility, vi: Rc<VulkanInstance>, device: Rc<Device>, renderpass: vk::RenderPass,
+        vi: Rc<VulkanInstance>, device: Rc<Device>, renderpass: vk::RenderPass,
         attachments: Vec<FBAttachmentRef>, prsnt_imgs: Vec<vk::Image>,
         color_fmt: vk::Format, depth_fmt: vk::Format, extent: vk::Extent2D,
         prsnt_inp: bool,
-    ) -> Self {
+    ) -> Result<Self, MatrixagonError> {
         let mut attachment_imgs = Vec::new();
         let mut attachment_imgvs = Vec::new();
         let mut attachment_imgms = Vec::new();
         let mut inp_attachment_imgvs = Vec::new();
+        let mut sampled_depth_imgv = None;
+        let mut gbuffer_imgvs = Vec::new();
 
-        if dbv.vk_swapchain_output {
-            println!("NEW FB ATTACHMENTS {attachments:?}");
-        }
+        log::debug!(target: VK_SWAPCHAIN_LOG, "NEW FB ATTACHMENTS {attachments:?}");
         for attachment in attachments {
-            if dbv.vk_swapchain_output {
-                println!("FB ATTACHMENT {attachment:?}");
-            }
+            log::trace!(target: VK_SWAPCHAIN_LOG, "FB ATTACHMENT {attachment:?}");
             match attachment {
-                FBAttachmentRef::Depth => {
+                FBAttachmentRef::Depth | FBAttachmentRef::SampledDepth | FBAttachmentRef::DepthInput => {
                     // TODO: maybe we can but the image format (color, depth, etc.) separate
                     // TODO: when creating image buffer
+                    let sampled = matches!(attachment, FBAttachmentRef::SampledDepth);
+                    let input = matches!(attachment, FBAttachmentRef::DepthInput);
                     let (depth_img, depth_img_mem) = create_local_image(
                         vi.clone(), device.clone(),
                         vk::Extent3D {width: extent.width, height: extent.height, depth: 1},
-                        1, depth_fmt, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, None
+                        1, depth_fmt,
+                        match attachment {
+                            FBAttachmentRef::SampledDepth => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                            FBAttachmentRef::DepthInput => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT,
+                            _ => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                        },
+                        None
                     );
 
                     let depth_imgv_create_info = vk::ImageViewCreateInfo {
@@ -82,11 +98,91 @@ impl FramebufferManager {
                         ..Default::default()
                     };
                     let depth_view = device.create_image_view(&depth_imgv_create_info, None)
-                        .expect("Failed to create image view");
+                        .map_err(|e| MatrixagonError::Vulkan("create the attachment image views", e))?;
 
                     attachment_imgs.push(depth_img);
                     attachment_imgvs.push(depth_view);
                     attachment_imgms.push(depth_img_mem);
+                    if sampled {
+                        sampled_depth_imgv.replace(depth_view);
+                    }
+                    if input {
+                        gbuffer_imgvs.push((depth_view, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL));
+                    }
+                }
+                FBAttachmentRef::MultisampledColor(samples) | FBAttachmentRef::MultisampledDepth(samples) => {
+                    let (format, usage, aspect_mask) = match attachment {
+                        FBAttachmentRef::MultisampledColor(_) => {
+                            (color_fmt, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR)
+                        }
+                        _ => (depth_fmt, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, vk::ImageAspectFlags::DEPTH),
+                    };
+                    let (ms_img, ms_img_mem) = create_multisampled_image(
+                        vi.clone(), device.clone(),
+                        vk::Extent3D {width: extent.width, height: extent.height, depth: 1},
+                        format, usage, samples
+                    );
+
+                    let ms_imgv_create_info = vk::ImageViewCreateInfo {
+                        image: ms_img,
+                        view_type: vk::ImageViewType::TYPE_2D,
+                        format,
+                        components: vk::ComponentMapping {
+                            r: vk::ComponentSwizzle::IDENTITY,
+                            g: vk::ComponentSwizzle::IDENTITY,
+                            b: vk::ComponentSwizzle::IDENTITY,
+                            a: vk::ComponentSwizzle::IDENTITY,
+                        },
+                        subresource_range: vk::ImageSubresourceRange {
+                            aspect_mask,
+                            base_mip_level: 0,
+                            level_count: 1,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        },
+                        ..Default::default()
+                    };
+                    let ms_view = device.create_image_view(&ms_imgv_create_info, None)
+                        .map_err(|e| MatrixagonError::Vulkan("create the attachment image views", e))?;
+
+                    attachment_imgs.push(ms_img);
+                    attachment_imgvs.push(ms_view);
+                    attachment_imgms.push(ms_img_mem);
+                }
+                FBAttachmentRef::GBuffer(format) => {
+                    let (gbuffer_img, gbuffer_img_mem) = create_local_image(
+                        vi.clone(), device.clone(),
+                        vk::Extent3D {width: extent.width, height: extent.height, depth: 1},
+                        1, format, vk::ImageUsageFlags::INPUT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                        None
+                    );
+
+                    let gbuffer_imgv_create_info = vk::ImageViewCreateInfo {
+                        image: gbuffer_img,
+                        view_type: vk::ImageViewType::TYPE_2D,
+                        format,
+                        components: vk::ComponentMapping {
+                            r: vk::ComponentSwizzle::IDENTITY,
+                            g: vk::ComponentSwizzle::IDENTITY,
+                            b: vk::ComponentSwizzle::IDENTITY,
+                            a: vk::ComponentSwizzle::IDENTITY,
+                        },
+                        subresource_range: vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: 0,
+                            level_count: 1,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        },
+                        ..Default::default()
+                    };
+                    let gbuffer_view = device.create_image_view(&gbuffer_imgv_create_info, None)
+                        .map_err(|e| MatrixagonError::Vulkan("create the attachment image views", e))?;
+
+                    attachment_imgs.push(gbuffer_img);
+                    attachment_imgvs.push(gbuffer_view);
+                    attachment_imgms.push(gbuffer_img_mem);
+                    gbuffer_imgvs.push((gbuffer_view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
                 }
                 FBAttachmentRef::Color => {
                     let (color_img, color_img_mem) = create_local_image(
@@ -115,7 +211,7 @@ impl FramebufferManager {
                         ..Default::default()
                     };
                     let color_view = device.create_image_view(&color_imgv_create_info, None)
-                        .expect("Failed to create image view");
+                        .map_err(|e| MatrixagonError::Vulkan("create the attachment image views", e))?;
 
                     attachment_imgs.push(color_img);
                     attachment_imgvs.push(color_view);
@@ -150,7 +246,7 @@ impl FramebufferManager {
                         ..Default::default()
                     };
                     let color_view = device.create_image_view(&color_imgv_create_info, None)
-                        .expect("Failed to create image view");
+                        .map_err(|e| MatrixagonError::Vulkan("create the attachment image views", e))?;
 
                     attachment_imgs.push(color_img);
                     attachment_imgvs.push(color_view);
@@ -184,7 +280,7 @@ impl FramebufferManager {
             };
 
             let view = device.create_image_view(&prsnt_view_create_info, None)
-                .expect("Failed to create image view");
+                .map_err(|e| MatrixagonError::Vulkan("create the presentation image views", e))?;
 
             let mut attachments = attachment_imgvs.clone();
             attachments.insert(0, view);
@@ -198,7 +294,7 @@ impl FramebufferManager {
                 .build();
 
             let fb = device.create_framebuffer(&framebuffer_info, None)
-                .expect("Failed to create framebuffer");
+                .map_err(|e| MatrixagonError::Vulkan("create the framebuffers", e))?;
 
             prsnt_imgvs.push(view);
             if prsnt_inp {
@@ -207,13 +303,34 @@ impl FramebufferManager {
             framebuffers.push(fb);
         }
 
-        Self {
+        Ok(Self {
             device,prsnt_imgvs, attachment_imgs, attachment_imgvs, attachment_imgms,
-            inp_attachment_imgvs, framebuffers
-        }
+            inp_attachment_imgvs, sampled_depth_imgv, gbuffer_imgvs, framebuffers
+        })
     }
 
-    pub(crate) unsafe fn get_input_attachment_descriptors(&self) -> Vec<RenderData> {
+    // of the input attachments (along with the G-buffer), and the depth attachment if it's sampled
+    pub(crate) unsafe fn get_attachment_descriptors(&self) -> Vec<RenderData> {
+        let gbuffer = self.gbuffer_imgvs.iter().enumerate().map(|(ind, (imgv, layout))| {
+            RenderData::InitialDescriptorImage(
+                vec![vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: *imgv,
+                    image_layout: *layout,
+                }],
+                RenderDataPurpose::GBufferInpAttachment(ind as u32),
+            )
+        });
+        let depth = self.sampled_depth_imgv.map(|imgv| {
+            RenderData::InitialDescriptorImage(
+                vec![vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: imgv,
+                    image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                }],
+                RenderDataPurpose::DepthAttachment,
+            )
+        });
         self.inp_attachment_imgvs.iter()
             .map(|imgv| {
                 RenderData::InitialDescriptorImage(
@@ -225,6 +342,8 @@ impl FramebufferManager {
                     RenderDataPurpose::PresentationInpAttachment,  // TODO: change to variable
                 )
             })
+            .chain(depth)
+            .chain(gbuffer)
             .collect()
     }
 
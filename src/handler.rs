@@ -2,17 +2,20 @@ use std::borrow::Cow;
 use std::ffi::{c_char, CStr};
 use std::os::raw::c_void;
 use std::rc::Rc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
 use ash::extensions::ext::DebugUtils;
 use ash::extensions::khr::Surface;
 use ash::{Device, Instance, vk};
-use ash::vk::DeviceQueueCreateFlags;
 use ash_window::create_surface;
 use winit::event_loop::EventLoop;
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use winit::window::Window;
-use crate::debug::DebugVisibility;
-use crate::shader::Shader;
-use crate::swapchain::{query_swapchain_support, SwapchainManager};
+use crate::debug::{DebugVisibility, Diagnostics, VK_SETUP_LOG, VK_VALIDATION_LOG};
+use crate::error::MatrixagonError;
+use crate::offscreen::OffscreenTarget;
+use crate::shader::{Msaa, Shader};
+use crate::swapchain::{query_swapchain_support, PresentMode, SwapchainManager};
 use crate::util::CmdBufContext;
 
 
@@ -24,37 +27,68 @@ const VALIDATION_LYRS: &[*const c_char] = &[
     // unsafe {CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_LUNARG_api_dump\0").as_ptr()},
 ];
 
+// frames recorded while the previous ones are still drawn by the GPU
+pub(crate) const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+// one of each per frame in flight
 struct SyncMTXG {
-    image_available_smph: [vk::Semaphore; 1],
-    render_finished_smph: [vk::Semaphore; 1],
-    in_flight_fence: [vk::Fence; 1],
+    image_available_smph: [vk::Semaphore; MAX_FRAMES_IN_FLIGHT],
+    render_finished_smph: [vk::Semaphore; MAX_FRAMES_IN_FLIGHT],
+    in_flight_fence: [vk::Fence; MAX_FRAMES_IN_FLIGHT],
+}
+
+// shared with the validation callback through its user data, so boxed to keep its address for the messenger
+struct ValidationState {
+    panic_on_error: bool,
+    ignored_messages: &'static [&'static str],
+    errors: AtomicU32,  // since last checked
+    last_error: Mutex<String>,
+}
+
+impl ValidationState {
+    fn ignores(&self, message_id_name: &str) -> bool {
+        self.ignored_messages.contains(&message_id_name)
+    }
 }
 
 pub struct VulkanHandler {
-    debug_output: DebugVisibility,
     validate: bool,
     debug_loader: Option<DebugUtils>,
     debug: Option<vk::DebugUtilsMessengerEXT>,
+    validation: Box<ValidationState>,
 
     pub(crate) vi: Rc<VulkanInstance>,
     pub(crate) device: Rc<Device>,
+    instance_exts: Vec<String>,
+    layers: Vec<String>,
     gfxs_queue: vk::Queue,
     prsnt_queue: vk::Queue,
     pub(crate) swapchain: Option<SwapchainManager>,
+    offscreen: Option<OffscreenTarget>,  // drawn into instead of the swapchain when headless
     cmd_pool: vk::CommandPool,
     transient_cmd_pool: vk::CommandPool,
 
-    render_cmd_buf: [vk::CommandBuffer; 1],
+    render_cmd_buf: [vk::CommandBuffer; MAX_FRAMES_IN_FLIGHT],
     sync: SyncMTXG,
+    current_frame: usize,  // index into the frames in flight, rotating every drawn frame
 
     shader: Option<Box<dyn Shader>>,
 }
 
 impl VulkanHandler {
-    pub(crate) fn init(event_loop: &EventLoop<()>, window: &Window, validate: bool, debug_output: DebugVisibility) -> Self
+    // headless without a window, drawing into an offscreen target instead of a swapchain
+    pub(crate) fn init(window: Option<(&EventLoop<()>, &Window)>, validate: bool, debug_visibility: &DebugVisibility,
+                       device_override: Option<&DeviceOverride>) -> Result<Self, MatrixagonError>
     {
+        let validation = Box::new(ValidationState {
+            panic_on_error: debug_visibility.panic_on_validation_error,
+            ignored_messages: debug_visibility.ignored_validation_messages,
+            errors: AtomicU32::new(0),
+            last_error: Mutex::new(String::new()),
+        });
         let debug_loader;
         let debug;
+        let instance_exts;
         let vi;
         let device;
         let gfxs_queue;
@@ -65,16 +99,23 @@ impl VulkanHandler {
         let sync;
         unsafe {
             let entry = ash::Entry::linked(); // ash::Entry::load().expect("VK Entry failed to load");
-            let mut surf_exts = ash_window::enumerate_required_extensions(event_loop.raw_display_handle())
-                .expect("Enumerate required extensions for raw display handle failed")
-                .to_vec();
+            let mut surf_exts = match window {
+                Some((event_loop, _)) => ash_window::enumerate_required_extensions(event_loop.raw_display_handle())
+                    .map_err(|e| MatrixagonError::Vulkan("enumerate the surface extensions", e))?
+                    .to_vec(),
+                None => vec![],
+            };
             if validate {
                 surf_exts.push(CStr::from_bytes_with_nul_unchecked(b"VK_EXT_debug_utils\0").as_ptr());
             }
 
-            if debug_output.vk_setup_output {
-                println!("Instance required extensions: {:?}", surf_exts);
-                println!("Instance required layers: {:?}", VALIDATION_LYRS);
+            instance_exts = surf_exts.iter()
+                .map(|ext| CStr::from_ptr(*ext).to_string_lossy().into_owned())
+                .collect();
+
+            log::debug!(target: VK_SETUP_LOG, "Instance required extensions: {:?}", instance_exts);
+            if validate {
+                log::debug!(target: VK_SETUP_LOG, "Instance required layers: {:?}", c_str_names(VALIDATION_LYRS));
             }
 
             let app_info = vk::ApplicationInfo::builder()
@@ -94,12 +135,14 @@ impl VulkanHandler {
                     vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE |
                     vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
                 pfn_user_callback: Some(vulkan_validation_debug_callback),
+                p_user_data: &*validation as *const ValidationState as *mut c_void,
                 ..Default::default()
             };
 
-            if debug_output.vk_setup_output {
-                let exts = entry.enumerate_instance_extension_properties(None).unwrap();
-                println!("Instance supported extensions: {exts:?}");
+            if log::log_enabled!(target: VK_SETUP_LOG, log::Level::Trace) {
+                let exts = entry.enumerate_instance_extension_properties(None)
+                    .map_err(|e| MatrixagonError::Vulkan("enumerate the instance extensions", e))?;
+                log::trace!(target: VK_SETUP_LOG, "Instance supported extensions: {exts:?}");
             }
 
             let inst_create_info = if validate {
@@ -117,99 +160,109 @@ impl VulkanHandler {
             };
 
             let inst = entry.create_instance(&inst_create_info, None)
-                .expect("Failed to create instance from create info");
+                .map_err(|e| MatrixagonError::Vulkan("create the instance", e))?;
 
             debug_loader = if validate {Some(DebugUtils::new(&entry, &inst))} else {None};
-            debug = if validate {
-                Some(
-                    debug_loader.clone().unwrap().create_debug_utils_messenger(&debug_cinfo, None)
-                        .expect("Failed to create debug messenger")
-                )
-            } else {None};
-
-            let surf = create_surface(
-                &entry,
-                &inst,
-                window.raw_display_handle(),
-                window.raw_window_handle(),
-                None
-            )
-                .expect("Surface creation failed");
-
-            let surf_loader = Surface::new(&entry, &inst);
-            if debug_output.vk_setup_output {
-                println!("Surface Object: {:?}", surf);
-            }
+            debug = match &debug_loader {
+                Some(debug_loader) => Some(
+                    debug_loader.create_debug_utils_messenger(&debug_cinfo, None)
+                        .map_err(|e| MatrixagonError::Vulkan("create the debug messenger", e))?
+                ),
+                None => None,
+            };
 
-            vi = Rc::new(VulkanInstance::new(debug_output, inst, surf, surf_loader));
+            let surface = match window {
+                Some((_, window)) => {
+                    let surf = create_surface(
+                        &entry,
+                        &inst,
+                        window.raw_display_handle(),
+                        window.raw_window_handle(),
+                        None
+                    )
+                        .map_err(MatrixagonError::SurfaceCreation)?;
+                    Some((surf, Surface::new(&entry, &inst)))
+                }
+                None => None,
+            };
+            log::debug!(target: VK_SETUP_LOG, "Surface Object: {:?}", surface.as_ref().map(|(surf, _)| surf));
+
+            vi = Rc::new(VulkanInstance::new(inst, surface, device_override)?);
 
             // CREATING GRAPHICS AND PRESENTATION QUEUES
 
-            let queue_fam_ind = find_queue_families(debug_output, &vi).unwrap();
+            let (gfxs_fam_ind, prsnt_fam_ind) = find_queue_families(&vi).indices()
+                .ok_or(MatrixagonError::NoPresentSupport)?;
             let priorities = [1.0];
-            let gfxs_queue_create_info = vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(queue_fam_ind)
-                .queue_priorities(&priorities)
-                .build();
-
-            // since our presentation queue will be the same as the graphics queue
-            // let prsnt_queue_create_info = vk::DeviceQueueCreateInfo {
-            //     queue_family_index: queue_fam_ind,
-            //     queue_count: 1,
-            //     p_queue_priorities: &1.0,
-            //     ..Default::default()
-            // };
+            let mut queues = vec![
+                vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(gfxs_fam_ind)
+                    .queue_priorities(&priorities)
+                    .build()
+            ];
+            // the presentation queue is the graphics queue, unless its family can't present
+            if prsnt_fam_ind != gfxs_fam_ind {
+                queues.push(
+                    vk::DeviceQueueCreateInfo::builder()
+                        .queue_family_index(prsnt_fam_ind)
+                        .queue_priorities(&priorities)
+                        .build()
+                );
+            }
 
             // LOGICAL DEVICE CREATION
+            // line polygon mode (for the wireframe debug view), anisotropic filtering and multi-draw indirect (for the
+            // indirect chunk draws) only where the device supports it
+            let supported_feats = vi.get_physical_device_features();
+            if supported_feats.sampler_anisotropy == vk::FALSE {
+                log::warn!("Sampler anisotropy is not supported, the textures are sampled without anisotropic filtering");
+            }
+            if supported_feats.multi_draw_indirect == vk::FALSE {
+                // otherwise the chunks sharing a buffer are drawn by a single indirect draw
+                log::warn!("Multi-draw indirect is not supported, the chunks are drawn with an indirect draw each");
+            }
+            let phys_devc_feats = enabled_features(supported_feats);
 
-            let phys_devc_feats = vk::PhysicalDeviceFeatures {
-                sampler_anisotropy: vk::TRUE,
-                ..Default::default()
-            };
-
-            let queues = [gfxs_queue_create_info/*, prsnt_queue_create_info*/ ];
             let device_create_info = vk::DeviceCreateInfo::builder()
                 .queue_create_infos(&queues)
                 .enabled_features(&phys_devc_feats)
-                .enabled_extension_names(&DEVICE_EXTS)
+                .enabled_extension_names(vi.device_extensions())
                 .build();
 
             device = Rc::new(vi.inst.create_device(vi.phys_devc, &device_create_info, None)
-                .expect("Failed to create logical device"));
+                .map_err(|e| MatrixagonError::Vulkan("create the logical device", e))?);
 
-            if debug_output.vk_setup_output {
-                println!("(Logical) Device Object: {:?}", device.handle());
-            }
-            gfxs_queue = device.clone().get_device_queue(queue_fam_ind,0);
-            prsnt_queue = device.clone().get_device_queue(queue_fam_ind,0);
+            log::debug!(target: VK_SETUP_LOG, "(Logical) Device Object: {:?}", device.handle());
+            gfxs_queue = device.clone().get_device_queue(gfxs_fam_ind,0);
+            prsnt_queue = device.clone().get_device_queue(prsnt_fam_ind,0);
 
             // COMMAND BUFFER
 
-            let ind = find_queue_families(debug_output, &vi).unwrap();
             let cmd_pool_info = vk::CommandPoolCreateInfo {
                 flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
-                queue_family_index: ind,
+                queue_family_index: gfxs_fam_ind,
                 ..Default::default()
             };
             cmd_pool = device.create_command_pool(&cmd_pool_info, None)
-                .expect("Failed to create command pool");
+                .map_err(|e| MatrixagonError::Vulkan("create the command pool", e))?;
 
             let transient_cmd_pool_info = vk::CommandPoolCreateInfo {
                 flags: vk::CommandPoolCreateFlags::TRANSIENT,
-                queue_family_index: ind,
+                queue_family_index: gfxs_fam_ind,
                 ..Default::default()
             };
             transient_cmd_pool = device.create_command_pool(&transient_cmd_pool_info, None)
-                .expect("Failed to create transient command pool");
+                .map_err(|e| MatrixagonError::Vulkan("create the transient command pool", e))?;
 
             let cmd_alloc_info = vk::CommandBufferAllocateInfo {
                 command_pool: cmd_pool,
                 level: vk::CommandBufferLevel::PRIMARY,
-                command_buffer_count: 1,
+                command_buffer_count: MAX_FRAMES_IN_FLIGHT as u32,
                 ..Default::default()
             };
             render_cmd_buf = device.allocate_command_buffers(&cmd_alloc_info)
-                .expect("Failed to allocate command buffers");
+                .map_err(|e| MatrixagonError::Vulkan("allocate the command buffers", e))?
+                .try_into().unwrap();
 
             // RENDER FRAME SYNCS
 
@@ -217,31 +270,52 @@ impl VulkanHandler {
             let fence_info = vk::FenceCreateInfo::builder()
                 .flags(vk::FenceCreateFlags::SIGNALED).build();
 
-            let image_available_smph = device.create_semaphore(&semaphore_info, None)
-                .expect("Failed to create image available semaphore");
-            let render_finished_smph = device.create_semaphore(&semaphore_info, None)
-                .expect("Failed to create render finished semaphore");
-            let in_flight_fence = device.create_fence(&fence_info, None)
-                .expect("Failed to create in flight fence");
-
+            let semaphores = || (0..MAX_FRAMES_IN_FLIGHT)
+                .map(|_| device.create_semaphore(&semaphore_info, None))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|smphs| smphs.try_into().unwrap())
+                .map_err(|e| MatrixagonError::Vulkan("create the frame semaphores", e));
             sync = SyncMTXG {
-                image_available_smph: [image_available_smph],
-                render_finished_smph: [render_finished_smph],
-                in_flight_fence: [in_flight_fence],
+                image_available_smph: semaphores()?,
+                render_finished_smph: semaphores()?,
+                in_flight_fence: (0..MAX_FRAMES_IN_FLIGHT)
+                    .map(|_| device.create_fence(&fence_info, None))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|fences| fences.try_into().unwrap())
+                    .map_err(|e| MatrixagonError::Vulkan("create the frame fences", e))?,
             }
         }
 
-        VulkanHandler {
-            debug_output, validate, debug_loader, debug,
-            vi: vi.clone(), device, gfxs_queue, prsnt_queue,
-            swapchain: None, cmd_pool, transient_cmd_pool,
-            render_cmd_buf: [render_cmd_buf[0]], sync, shader: None,
+        let layers = if validate {c_str_names(VALIDATION_LYRS)} else {vec![]};
+
+        let handler = VulkanHandler {
+            validate, debug_loader, debug, validation,
+            vi: vi.clone(), device, instance_exts, layers, gfxs_queue, prsnt_queue,
+            swapchain: None, offscreen: None, cmd_pool, transient_cmd_pool,
+            render_cmd_buf, sync, current_frame: 0, shader: None,
+        };
+        handler.check_validation();
+        Ok(handler)
+    }
+
+    // the callback can't unwind into the layers, so the errors it counted panic here instead
+    pub(crate) fn check_validation(&self) {
+        let errors = self.validation.errors.swap(0, Ordering::Relaxed);
+        if errors > 0 && self.validation.panic_on_error {
+            panic!("{errors} Vulkan validation error(s), the last one:\n{}", self.validation.last_error.lock().unwrap());
         }
     }
 
-    pub(crate) fn load_shader(&mut self, shader: impl Shader + 'static) {
-        // self.shader = Some(Box::new(shader) as Box<dyn Shader>);
-        self.shader.replace(Box::new(shader) as Box<dyn Shader>);
+    pub(crate) fn diagnostics(&self, diagnostics: &mut Diagnostics) {
+        let props = unsafe { self.vi.get_physical_device_properties() };
+        diagnostics.gpu(&props, &self.instance_exts, &c_str_names(self.vi.device_extensions()), &self.layers);
+        if let Some(swapchain) = &self.swapchain {
+            swapchain.diagnostics(diagnostics);
+        }
+    }
+
+    pub(crate) fn load_shader(&mut self, shader: Box<dyn Shader>) {
+        self.shader.replace(shader);
     }
 
     pub(crate) fn obtain_shader_mut_ref(&mut self) -> &mut Box<dyn Shader> {
@@ -252,27 +326,96 @@ impl VulkanHandler {
         self.swapchain.replace(swapchain_manager);
     }
 
+    pub(crate) fn load_offscreen(&mut self, offscreen: OffscreenTarget) {
+        self.offscreen.replace(offscreen);
+    }
+
     pub(crate) fn get_cmd_buf_context(&self) -> CmdBufContext {
         CmdBufContext(self.device.clone(), self.transient_cmd_pool, self.gfxs_queue)
     }
 
+    // recreates the swapchain with the requested extent, along with the shader state sized or bound to its images,
+    // returning the extent the swapchain ended up with
+    pub(crate) unsafe fn recreate_swapchain(&mut self, requested_extent: vk::Extent2D) -> vk::Extent2D {
+        let swapchain = self.swapchain.as_mut()
+            .expect("Attempted to recreate swapchain when it has not initialized yet!");
+        swapchain.recreate(requested_extent);
+
+        if let Some(shader) = self.shader.as_mut() {
+            shader.update_extent(swapchain.extent);
+            // the input (and sampled depth) attachments were recreated along with the framebuffers
+            shader.write_descriptors(swapchain.fbm.get_attachment_descriptors());
+        }
+        swapchain.extent
+    }
+
+    // recreates the swapchain with the present mode, returning the one used (FIFO without support of the requested one)
+    pub(crate) unsafe fn set_present_mode(&mut self, present_mode: PresentMode) -> vk::PresentModeKHR {
+        let swapchain = self.swapchain.as_mut()
+            .expect("Attempted to set the present mode when swapchain has not initialized yet!");
+        swapchain.present_mode = present_mode;
+        let extent = swapchain.extent;
+        self.recreate_swapchain(extent);
+        self.swapchain.as_ref().unwrap().prsnt
+    }
+
+    // recreates the framebuffers when the shader recreated its renderpass for the sample count
+    pub(crate) unsafe fn set_msaa(&mut self, msaa: Msaa) {
+        let shader = self.shader.as_mut()
+            .expect("Attempted to set the MSAA when shader has not initialized yet!");
+        if shader.set_msaa(msaa) {
+            let swapchain = self.swapchain.as_mut()
+                .expect("Attempted to set the MSAA when swapchain has not initialized yet!");
+            swapchain.set_renderpass(shader.renderpass(), shader.attachments());
+            let extent = swapchain.extent;
+            self.recreate_swapchain(extent);
+        }
+    }
+
+    // waits until the frame drawn next is no longer in flight, so its per frame buffers can be written before it is
+    // drawn, returning its index
+    pub(crate) unsafe fn wait_frame(&self) -> usize {
+        let frame = self.current_frame;
+        self.device.wait_for_fences(&[self.sync.in_flight_fence[frame]], true, u64::MAX).unwrap();
+        frame
+    }
+
+    // waits until no frame is in flight, so the render data can reach the shader without drawing a frame (e.g. while
+    // paused), returning the index of the frame drawn next
+    pub(crate) unsafe fn wait_frames_idle(&self) -> usize {
+        self.device.wait_for_fences(&self.sync.in_flight_fence, true, u64::MAX).unwrap();
+        self.current_frame
+    }
+
     pub(crate) unsafe fn draw_frame(&mut self) {
         let swapchain = self.swapchain.as_mut()
             .expect("Attempted to draw frame when swapchain has not initialized yet!");
 
-        self.device.wait_for_fences(&self.sync.in_flight_fence, true, u64::MAX).unwrap();
+        let extent = swapchain.extent;
+        let frame = self.current_frame;
+        let in_flight_fence = [self.sync.in_flight_fence[frame]];
+        let image_available_smph = [self.sync.image_available_smph[frame]];
+        let render_finished_smph = [self.sync.render_finished_smph[frame]];
+        let render_cmd_buf = [self.render_cmd_buf[frame]];
+
+        // only waits on the frame last drawn with the same sync objects, the other frames may still be in flight
+        self.device.wait_for_fences(&in_flight_fence, true, u64::MAX).unwrap();
+
+        // the descriptor sets of this frame are no longer read by any frame in flight
+        self.shader.as_mut().unwrap().flush_descriptors(frame);
+        // the buffers replaced since this frame was last drawn are no longer drawn by any frame
+        self.shader.as_mut().unwrap().collect_garbage(frame);
+        self.shader.as_mut().unwrap().prepare_frame(frame);
 
-        let acquisition = swapchain.loader.acquire_next_image(swapchain.swapchain[0], u64::MAX, self.sync.image_available_smph[0], vk::Fence::null());
+        let acquisition = swapchain.loader.acquire_next_image(swapchain.swapchain[0], u64::MAX, image_available_smph[0], vk::Fence::null());
         let img_inds = match acquisition {
             // swapchain suboptimal
             Ok((_, true)) => {
-                // self.recreate_swapchain();
-                swapchain.recreate();
+                self.recreate_swapchain(extent);
                 return;
             }
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                // self.recreate_swapchain();
-                swapchain.recreate();
+                self.recreate_swapchain(extent);
                 return;
             }
             Err(e) => {
@@ -283,67 +426,117 @@ impl VulkanHandler {
             }
         };
 
-        self.device.reset_fences(&self.sync.in_flight_fence).unwrap();
+        self.device.reset_fences(&in_flight_fence).unwrap();
 
-        self.device.reset_command_buffer(self.render_cmd_buf[0], vk::CommandBufferResetFlags::empty()).unwrap();
+        self.device.reset_command_buffer(render_cmd_buf[0], vk::CommandBufferResetFlags::empty()).unwrap();
 
         // COMMAND RECORDING
 
         let cmd_begin_info = vk::CommandBufferBeginInfo {
             ..Default::default()
         };
-        self.device.begin_command_buffer(self.render_cmd_buf[0], &cmd_begin_info)
+        self.device.begin_command_buffer(render_cmd_buf[0], &cmd_begin_info)
             .expect("Failed to begin recording command buffers");
 
         self.shader.as_ref().unwrap()
-            .draw_command(self.render_cmd_buf[0], swapchain.fbm.framebuffers[img_inds[0] as usize]);
+            .draw_command(render_cmd_buf[0], swapchain.fbm.framebuffers[img_inds[0] as usize]);
 
-        self.device.end_command_buffer(self.render_cmd_buf[0])
+        self.device.end_command_buffer(render_cmd_buf[0])
             .expect("Failed to record command buffers");
 
         let dst_stage_masks = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
         let submit_infos = [
             vk::SubmitInfo::builder()
-                .wait_semaphores(&self.sync.image_available_smph)
+                .wait_semaphores(&image_available_smph)
                 .wait_dst_stage_mask(&dst_stage_masks)
-                .command_buffers(&self.render_cmd_buf)
-                .signal_semaphores(&self.sync.render_finished_smph)
+                .command_buffers(&render_cmd_buf)
+                .signal_semaphores(&render_finished_smph)
                 .build()
         ];
 
-        self.device.queue_submit(self.gfxs_queue, &submit_infos, self.sync.in_flight_fence[0])
+        self.device.queue_submit(self.gfxs_queue, &submit_infos, in_flight_fence[0])
             .expect("Failed to submit draw command buffer to graphics queue");
+        self.current_frame = (frame+1)%MAX_FRAMES_IN_FLIGHT;
 
         let prsnt_info = vk::PresentInfoKHR::builder()
-            .wait_semaphores(&self.sync.render_finished_smph)
+            .wait_semaphores(&render_finished_smph)
             .swapchains(&swapchain.swapchain)
             .image_indices(&img_inds).build();
         let swapchain_result = swapchain.loader.queue_present(self.prsnt_queue, &prsnt_info);
         match swapchain_result {
             // swapchain suboptimal
             Ok(true) => {
-                // self.recreate_swapchain();
-                swapchain.recreate();
+                self.recreate_swapchain(extent);
             }
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                // self.recreate_swapchain();
-                swapchain.recreate();
+                self.recreate_swapchain(extent);
             }
             Err(e) => {
                 panic!("{}", e);
             }
             _ => {}
         }
+        self.check_validation();
+    }
+
+    // draws a frame into the offscreen target and waits for it, returning the pixels of the color attachment
+    pub(crate) unsafe fn draw_offscreen(&mut self) -> Vec<u8> {
+        let offscreen = self.offscreen.as_ref()
+            .expect("Attempted to draw offscreen when the offscreen target has not initialized yet!");
+
+        let frame = self.current_frame;
+        let in_flight_fence = [self.sync.in_flight_fence[frame]];
+        let render_cmd_buf = [self.render_cmd_buf[frame]];
+
+        // every frame is waited on below, so none are in flight by now
+        self.device.wait_for_fences(&in_flight_fence, true, u64::MAX).unwrap();
+        self.shader.as_mut().unwrap().flush_descriptors(frame);
+        self.shader.as_mut().unwrap().collect_garbage(frame);
+        self.shader.as_mut().unwrap().prepare_frame(frame);
+
+        self.device.reset_fences(&in_flight_fence).unwrap();
+        self.device.reset_command_buffer(render_cmd_buf[0], vk::CommandBufferResetFlags::empty()).unwrap();
+
+        let cmd_begin_info = vk::CommandBufferBeginInfo {
+            ..Default::default()
+        };
+        self.device.begin_command_buffer(render_cmd_buf[0], &cmd_begin_info)
+            .expect("Failed to begin recording command buffers");
+
+        self.shader.as_ref().unwrap()
+            .draw_command(render_cmd_buf[0], offscreen.fbm.framebuffers[0]);
+        offscreen.record_readback(render_cmd_buf[0]);
+
+        self.device.end_command_buffer(render_cmd_buf[0])
+            .expect("Failed to record command buffers");
+
+        let submit_infos = [
+            vk::SubmitInfo::builder()
+                .command_buffers(&render_cmd_buf)
+                .build()
+        ];
+        self.device.queue_submit(self.gfxs_queue, &submit_infos, in_flight_fence[0])
+            .expect("Failed to submit draw command buffer to graphics queue");
+        self.current_frame = (frame+1)%MAX_FRAMES_IN_FLIGHT;
+
+        self.device.wait_for_fences(&in_flight_fence, true, u64::MAX).unwrap();
+        self.check_validation();
+        offscreen.pixels()
     }
 
     pub(crate) unsafe fn destroy(&self) {
         if let Some(swapchain) = &self.swapchain {
             swapchain.destroy();
         }
+        if let Some(offscreen) = &self.offscreen {
+            offscreen.destroy();
+        }
 
-        self.device.destroy_semaphore(self.sync.image_available_smph[0], None);
-        self.device.destroy_semaphore(self.sync.render_finished_smph[0], None);
-        self.device.destroy_fence(self.sync.in_flight_fence[0], None);
+        for frame in 0..MAX_FRAMES_IN_FLIGHT {
+            self.device.destroy_semaphore(self.sync.image_available_smph[frame], None);
+            self.device.destroy_semaphore(self.sync.render_finished_smph[frame], None);
+            self.device.destroy_fence(self.sync.in_flight_fence[frame], None);
+        }
 
         self.device.destroy_command_pool(self.transient_cmd_pool, None);
         self.device.destroy_command_pool(self.cmd_pool, None);
@@ -358,37 +551,157 @@ impl VulkanHandler {
         if self.validate {
             self.debug_loader.as_ref().unwrap().destroy_debug_utils_messenger(self.debug.unwrap(), None);
         }
-        self.vi.surf_loader.destroy_surface(self.vi.surf, None);
+        if let Some((surf, surf_loader)) = &self.vi.surface {
+            surf_loader.destroy_surface(*surf, None);
+        }
         self.vi.inst.destroy_instance(None);
     }
 }
 
-unsafe fn find_queue_families(dbgv: DebugVisibility, vi: &VulkanInstance) -> Option<u32> {
-    let queue_fams = vi.get_physical_device_queue_family_properties();
+// the features the logical device is created with, out of the supported ones
+pub(crate) fn enabled_features(supported: vk::PhysicalDeviceFeatures) -> vk::PhysicalDeviceFeatures {
+    vk::PhysicalDeviceFeatures {
+        sampler_anisotropy: supported.sampler_anisotropy,
+        fill_mode_non_solid: supported.fill_mode_non_solid,
+        multi_draw_indirect: supported.multi_draw_indirect,
+        ..Default::default()
+    }
+}
+
+fn c_str_names(names: &[*const c_char]) -> Vec<String> {
+    names.iter()
+        .map(|name| unsafe { CStr::from_ptr(*name) }.to_string_lossy().into_owned())
+        .collect()
+}
+
+// queue family indices of the physical device
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub(crate) struct QueueFamilies {
+    pub(crate) graphics: Option<u32>,
+    pub(crate) present: Option<u32>,  // same as the graphics family whenever a family supports both
+}
+
+impl QueueFamilies {
+    // from the queue flags and the surface support of each family
+    fn select(families: &[(vk::QueueFlags, bool)]) -> Self {
+        let family = |found: &dyn Fn(vk::QueueFlags, bool) -> bool| {
+            families.iter().position(|(flags, present)| found(*flags, *present)).map(|ind| ind as u32)
+        };
+
+        // a single family keeps the swapchain images exclusive to it
+        if let Some(ind) = family(&|flags, present| flags.contains(vk::QueueFlags::GRAPHICS) && present) {
+            return Self { graphics: Some(ind), present: Some(ind) };
+        }
+        Self {
+            graphics: family(&|flags, _| flags.contains(vk::QueueFlags::GRAPHICS)),
+            present: family(&|_, present| present),
+        }
+    }
+
+    // graphics and present family indices, if both were found
+    pub(crate) fn indices(&self) -> Option<(u32, u32)> {
+        self.graphics.zip(self.present)
+    }
+
+    // sharing mode of the swapchain images and the families sharing them, concurrent across separate families
+    pub(crate) fn sharing(&self) -> (vk::SharingMode, Vec<u32>) {
+        match self.indices() {
+            Some((gfxs, prsnt)) if gfxs != prsnt => (vk::SharingMode::CONCURRENT, vec![gfxs, prsnt]),
+            _ => (vk::SharingMode::EXCLUSIVE, vec![]),
+        }
+    }
+}
+
+// physical device used instead of the best fitting one (e.g. on multi-GPU systems)
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceOverride {
+    Index(usize),  // in the order the devices are enumerated (listed with the vk setup output)
+    Name(String),  // the first device with the name containing it, ignoring case
+}
 
-    let mut ind = 0;
-    for queue_fam in queue_fams {
-        if dbgv.vk_setup_output {
-            println!("Queue family properties: {:?}", queue_fam);
+impl DeviceOverride {
+    fn find(&self, names: &[String]) -> Option<usize> {
+        match self {
+            DeviceOverride::Index(ind) => (*ind < names.len()).then_some(*ind),
+            DeviceOverride::Name(name) => {
+                let name = name.to_lowercase();
+                names.iter().position(|devc_name| devc_name.to_lowercase().contains(&name))
+            }
         }
+    }
+}
 
-        let supported = vi.get_physical_device_surface_support(ind);
+// how well a physical device fits the renderer, from the worst
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum DeviceFit {
+    Unsupported,
+    NoPresent,  // renders, but can't present to the surface
+    // the supported devices, ranked by their type
+    Other,
+    Cpu,  // software rendering (e.g. lavapipe)
+    Virtual,
+    Integrated,
+    Discrete,
+}
 
-        if queue_fam.queue_flags.contains(vk::QueueFlags::GRAPHICS) && supported {
-            return Some(ind);
+impl DeviceFit {
+    fn supported(device_type: vk::PhysicalDeviceType) -> Self {
+        match device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => DeviceFit::Discrete,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => DeviceFit::Integrated,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => DeviceFit::Virtual,
+            vk::PhysicalDeviceType::CPU => DeviceFit::Cpu,
+            _ => DeviceFit::Other,
         }
+    }
 
-        ind += 1;
+    // index of the forced device, or else the first of the best fitting devices
+    fn select(fits: &[DeviceFit], forced: Option<usize>) -> Result<usize, MatrixagonError> {
+        let best = match forced {
+            Some(ind) => fits[ind],
+            None => fits.iter().copied().max().unwrap_or(DeviceFit::Unsupported),
+        };
+        match best {
+            DeviceFit::Unsupported => Err(MatrixagonError::NoSuitableDevice),
+            DeviceFit::NoPresent => Err(MatrixagonError::NoPresentSupport),
+            _ => Ok(forced.unwrap_or_else(|| fits.iter().position(|fit| *fit == best).unwrap())),
+        }
     }
+}
+
+pub(crate) unsafe fn find_queue_families(vi: &VulkanInstance) -> QueueFamilies {
+    let queue_fams = vi.get_physical_device_queue_family_properties();
 
-    None
+    let mut families = vec![];
+    for (ind, queue_fam) in queue_fams.into_iter().enumerate() {
+        log::trace!(target: VK_SETUP_LOG, "Queue family properties: {:?}", queue_fam);
+
+        // nothing is presented when headless, so the graphics family is used for both
+        let present = vi.surface.is_none() || vi.get_physical_device_surface_support(ind as u32);
+        families.push((queue_fam.queue_flags, present));
+    }
+
+    QueueFamilies::select(&families)
+}
+
+// the info messages of the loader and layers are too chatty for the info level
+fn validation_log_level(msg_severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> log::Level {
+    if msg_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        log::Level::Error
+    } else if msg_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        log::Level::Warn
+    } else if msg_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        log::Level::Debug
+    } else {
+        log::Level::Trace
+    }
 }
 
 unsafe extern "system" fn vulkan_validation_debug_callback(
     msg_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     msg_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
+    p_user_data: *mut c_void,
 ) -> vk::Bool32 {
     let callback_data = *p_callback_data;
     let message_id_number: i32 = callback_data.message_id_number as i32;
@@ -405,123 +718,158 @@ unsafe extern "system" fn vulkan_validation_debug_callback(
         CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
-    println!(
-        "[{:?}] {:?}: {} ({}):\n{}",
-        msg_severity,
+    let validation = (p_user_data as *const ValidationState).as_ref();
+    if validation.is_some_and(|validation| validation.ignores(&message_id_name)) {
+        return vk::FALSE;
+    }
+
+    let level = validation_log_level(msg_severity);
+    log::log!(
+        target: VK_VALIDATION_LOG,
+        level,
+        "{:?}: {} ({}):\n{}",
         msg_type,
         message_id_name,
         &message_id_number.to_string(),
         message,
     );
 
+    if let Some(validation) = validation.filter(|_| level == log::Level::Error) {
+        validation.errors.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut last_error) = validation.last_error.lock() {
+            *last_error = format!("{message_id_name} ({message_id_number}): {message}");
+        }
+    }
+
     vk::FALSE
 }
 
 
 pub(crate) struct VulkanInstance {
     pub(crate) inst: Instance,
-    pub(crate) surf: vk::SurfaceKHR,
-    pub(crate) surf_loader: Surface,
+    pub(crate) surface: Option<(vk::SurfaceKHR, Surface)>,  // None when headless
     pub(crate) phys_devc: vk::PhysicalDevice,
 }
 
 impl VulkanInstance {
-    pub(crate) unsafe fn new(dbgv: DebugVisibility, inst: Instance, surf: vk::SurfaceKHR, surf_loader: Surface) -> Self {
+    pub(crate) unsafe fn new(inst: Instance, surface: Option<(vk::SurfaceKHR, Surface)>, device_override: Option<&DeviceOverride>)
+        -> Result<Self, MatrixagonError> {
         let mut s = Self {
             inst,
-            surf,
-            surf_loader,
+            surface,
             phys_devc: vk::PhysicalDevice::null(),
         };
-        s.find_physical_device(dbgv);
-        s
+        s.find_physical_device(device_override)?;
+        Ok(s)
+    }
+
+    pub(crate) fn surf(&self) -> vk::SurfaceKHR {
+        self.surface.as_ref().expect("No surface when headless").0
+    }
+
+    fn surf_loader(&self) -> &Surface {
+        &self.surface.as_ref().expect("No surface when headless").1
     }
 
-    unsafe fn find_physical_device(&mut self, dbgv: DebugVisibility) {
+    // the swapchain extension is only needed to present to the surface
+    pub(crate) fn device_extensions(&self) -> &'static [*const c_char] {
+        if self.surface.is_some() {DEVICE_EXTS} else {&[]}
+    }
+
+    unsafe fn find_physical_device(&mut self, device_override: Option<&DeviceOverride>) -> Result<(), MatrixagonError> {
         let phys_devcs = self.inst.enumerate_physical_devices()
-            .expect("Failed to enumerate physical device");
+            .map_err(|e| MatrixagonError::Vulkan("enumerate the physical devices", e))?;
 
-        if dbgv.vk_setup_output {
-            println!("Available physical devices: {:?}", phys_devcs);
-        }
+        log::debug!(target: VK_SETUP_LOG, "Available physical devices: {:?}", phys_devcs);
 
-        // let mut phys_devc_o = None;
-        for phys_devc_i in phys_devcs {
+        let mut fits = vec![];
+        let mut names = vec![];
+        for &phys_devc_i in &phys_devcs {
             self.phys_devc = phys_devc_i;
-            if self.is_device_suitable(dbgv, &self.inst, phys_devc_i) {
-                // phys_devc_o = Some(phys_devc_i);
-                if dbgv.vk_setup_output {
-                    println!("Suitable physical device found!");
-                }
-                break;
-            }
+            fits.push(self.device_fit(&self.inst, phys_devc_i)?);
+            let props = self.inst.get_physical_device_properties(phys_devc_i);
+            names.push(CStr::from_ptr(props.device_name.as_ptr()).to_string_lossy().into_owned());
         }
-        // self.phys_devc = phys_devc_o.expect("No suitable physical device found");
-
-        if dbgv.vk_setup_output {
-            println!("Selected physical device: {:?}", self.phys_devc);
+        for (ind, (name, fit)) in names.iter().zip(&fits).enumerate() {
+            log::debug!(target: VK_SETUP_LOG, "Physical device {ind}: {name} ({fit:?})");
         }
+
+        let forced = match device_override {
+            Some(device_override) => Some(device_override.find(&names)
+                .ok_or_else(|| MatrixagonError::DeviceNotFound(device_override.clone()))?),
+            None => None,
+        };
+        let ind = DeviceFit::select(&fits, forced)?;
+        self.phys_devc = phys_devcs[ind];
+        log::info!("Using the physical device {} ({:?})", names[ind], fits[ind]);
+
+        log::debug!(target: VK_SETUP_LOG, "Selected physical device: {:?}", self.phys_devc);
+        Ok(())
     }
 
-    unsafe fn is_device_suitable(&self, dbgv: DebugVisibility, inst: &Instance, device: vk::PhysicalDevice) -> bool {
+    unsafe fn device_fit(&self, inst: &Instance, device: vk::PhysicalDevice)
+        -> Result<DeviceFit, MatrixagonError> {
         let props = inst.get_physical_device_properties(device);
         let feats = inst.get_physical_device_features(device);
 
         let device_ext_props = inst.enumerate_device_extension_properties(device)
-            .expect("Failed to enumerate device extension props");
+            .map_err(|e| MatrixagonError::Vulkan("enumerate the device extensions", e))?;
 
-        if dbgv.vk_setup_output {
-            println!("Physical device: {:?}", device);
-            println!("\tProperties: {:?}", props);
-            println!("\tFeatures: {:?}", feats);
+        log::debug!(target: VK_SETUP_LOG, "Physical device: {:?}", device);
+        log::trace!(target: VK_SETUP_LOG, "\tProperties: {:?}", props);
+        log::trace!(target: VK_SETUP_LOG, "\tFeatures: {:?}", feats);
+
+        let queue_fams = find_queue_families(&self);
+        if queue_fams.graphics.is_none() {
+            return Ok(DeviceFit::Unsupported);
         }
 
         // FIND IF EXTENSIONS SUPPORTED
-        for &device_ext in DEVICE_EXTS {
+        // (only the swapchain extension, to present to the surface)
+        for &device_ext in self.device_extensions() {
             let mut has = false;
             for device_ext_prop in &device_ext_props {
-                if dbgv.vk_setup_output {
-                    println!("\tSupported device extension: {:?}", device_ext_prop);
-                }
+                log::trace!(target: VK_SETUP_LOG, "\tSupported device extension: {:?}", device_ext_prop);
                 if CStr::from_ptr(device_ext) == CStr::from_ptr(device_ext_prop.extension_name.as_ptr()){
-                    if dbgv.vk_setup_output {
-                        println!("\t^^^ Required device extension found ^^^");
-                    }
+                    log::trace!(target: VK_SETUP_LOG, "\t^^^ Required device extension found ^^^");
                     has = true;
                     break;
                 }
             }
             if !has {
-                return false;
+                return Ok(DeviceFit::NoPresent);
             }
         }
 
-        let (_, formats, present_modes) = query_swapchain_support(dbgv, &self);
+        let presentable = self.surface.is_none() || {
+            let (_, formats, present_modes) = query_swapchain_support(&self);
+            !formats.is_empty() && !present_modes.is_empty()
+        };
+        if queue_fams.present.is_none() || !presentable {
+            return Ok(DeviceFit::NoPresent);
+        }
 
-        props.device_type == vk::PhysicalDeviceType::DISCRETE_GPU &&
-            find_queue_families(dbgv, &self).is_some() &&
-            !formats.is_empty() &&
-            !present_modes.is_empty() &&
-            feats.sampler_anisotropy != 0
+        log::debug!(target: VK_SETUP_LOG, "Suitable physical device found!");
+        Ok(DeviceFit::supported(props.device_type))
     }
 
     pub(crate) unsafe fn get_physical_device_surface_present_modes(&self) -> Vec<vk::PresentModeKHR> {
-        self.surf_loader.get_physical_device_surface_present_modes(self.phys_devc, self.surf)
+        self.surf_loader().get_physical_device_surface_present_modes(self.phys_devc, self.surf())
             .unwrap()
     }
 
     pub(crate) unsafe fn get_physical_device_surface_formats(&self) -> Vec<vk::SurfaceFormatKHR> {
-        self.surf_loader.get_physical_device_surface_formats(self.phys_devc, self.surf)
+        self.surf_loader().get_physical_device_surface_formats(self.phys_devc, self.surf())
             .unwrap()
     }
 
     pub(crate) unsafe fn get_physical_device_surface_capabilities(&self) -> vk::SurfaceCapabilitiesKHR {
-        self.surf_loader.get_physical_device_surface_capabilities(self.phys_devc, self.surf)
+        self.surf_loader().get_physical_device_surface_capabilities(self.phys_devc, self.surf())
             .unwrap()
     }
 
     pub(crate) unsafe fn get_physical_device_surface_support(&self, queue_family_index: u32) -> bool {
-        self.surf_loader.get_physical_device_surface_support(self.phys_devc, queue_family_index, self.surf)
+        self.surf_loader().get_physical_device_surface_support(self.phys_devc, queue_family_index, self.surf())
             .unwrap()
     }
 
@@ -550,3 +898,75 @@ impl VulkanInstance {
         self.inst.get_physical_device_format_properties(self.phys_devc, format)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_families_prefer_single_family() {
+        let compute = vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER;
+        let graphics = vk::QueueFlags::GRAPHICS | compute;
+
+        // the second family does both
+        let families = QueueFamilies::select(&[(graphics, false), (graphics, true), (compute, true)]);
+        assert_eq!(families.indices(), Some((1, 1)));
+        assert_eq!(families.sharing(), (vk::SharingMode::EXCLUSIVE, vec![]));
+
+        // presenting only from a separate family
+        let families = QueueFamilies::select(&[(compute, true), (graphics, false)]);
+        assert_eq!(families.indices(), Some((1, 0)));
+        assert_eq!(families.sharing(), (vk::SharingMode::CONCURRENT, vec![1, 0]));
+
+        // unsuitable without presentation
+        assert_eq!(QueueFamilies::select(&[(graphics, false)]).indices(), None);
+    }
+
+    #[test]
+    fn test_device_fit_ranks_device_types() {
+        use DeviceFit::*;
+        assert_eq!(DeviceFit::select(&[Other, Unsupported, Discrete, Discrete], None).unwrap(), 2);
+        assert_eq!(DeviceFit::select(&[Cpu, Integrated, Virtual], None).unwrap(), 1);
+        // software rendering is still picked over the devices that can't present
+        assert_eq!(DeviceFit::select(&[NoPresent, Cpu], None).unwrap(), 1);
+
+        // the reason of the best device is reported when none fits
+        assert!(matches!(DeviceFit::select(&[Unsupported, NoPresent], None), Err(MatrixagonError::NoPresentSupport)));
+        assert!(matches!(DeviceFit::select(&[Unsupported], None), Err(MatrixagonError::NoSuitableDevice)));
+        assert!(matches!(DeviceFit::select(&[], None), Err(MatrixagonError::NoSuitableDevice)));
+
+        // a forced device is used even if a better one fits, but has to fit
+        assert_eq!(DeviceFit::select(&[Discrete, Cpu], Some(1)).unwrap(), 1);
+        assert!(matches!(DeviceFit::select(&[Discrete, Unsupported], Some(1)), Err(MatrixagonError::NoSuitableDevice)));
+    }
+
+    #[test]
+    fn test_device_override_finds_device() {
+        let names = ["NVIDIA GeForce RTX 3070".to_string(), "llvmpipe (LLVM 15.0.7, 256 bits)".to_string()];
+        assert_eq!(DeviceOverride::Index(1).find(&names), Some(1));
+        assert_eq!(DeviceOverride::Index(2).find(&names), None);
+        assert_eq!(DeviceOverride::Name("LLVMpipe".to_string()).find(&names), Some(1));
+        assert_eq!(DeviceOverride::Name("rtx".to_string()).find(&names), Some(0));
+        assert_eq!(DeviceOverride::Name("radeon".to_string()).find(&names), None);
+    }
+
+    #[test]
+    fn test_validation_severity_and_ignored_messages() {
+        type Severity = vk::DebugUtilsMessageSeverityFlagsEXT;
+        assert_eq!(validation_log_level(Severity::ERROR), log::Level::Error);
+        assert_eq!(validation_log_level(Severity::WARNING), log::Level::Warn);
+        assert_eq!(validation_log_level(Severity::INFO), log::Level::Debug);
+        assert_eq!(validation_log_level(Severity::VERBOSE), log::Level::Trace);
+
+        let validation = ValidationState {
+            panic_on_error: true,
+            ignored_messages: &["VUID-vkCmdDraw-None-02699"],
+            errors: AtomicU32::new(0),
+            last_error: Mutex::new(String::new()),
+        };
+        assert!(validation.ignores("VUID-vkCmdDraw-None-02699"));
+        assert!(!validation.ignores("VUID-vkCmdDraw-None-02697"));
+        assert!(!validation.ignores(""));
+    }
+}
@@ -20,8 +20,10 @@
 extern crate uom;
 extern crate alloc;
 
-use std::path::Path;
-use std::time::Instant;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 use ash::vk;
 use egui::{Id, Modifiers, Pos2, RawInput, Rect, ViewportId, ViewportIdMap, ViewportInfo};
 use egui::ahash::HashMapExt;
@@ -30,19 +32,39 @@ use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::{DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Fullscreen, Window, WindowBuilder};
-use crate::component::camera::{CameraComponent, Length3D};
+use crate::component::camera::{CameraComponent, Length3D, MouseSettings, MovementSpeed};
+pub use crate::component::camera::FovAxis;
 use crate::component::debug_ui::{DebugUI};
 use crate::component::flags::WorldFlags;
-use crate::debug::DebugVisibility;
+use crate::component::hotbar::{GameMode, Hotbar};
+use crate::component::skybox::SkyboxHandler;
+use crate::component::fog::{FogComponent, FogParams, srgb_to_linear};
+use crate::component::sky::{DayCycle, SkyComponent};
+use crate::debug::{Diagnostics, MTXG_LOG, VK_SETUP_LOG, VK_SWAPCHAIN_LOG};
 use crate::handler::VulkanHandler;
 use crate::world::{World, WorldEvent};
-use crate::component::terrain::{BlockData, MeshType, Terrain, TextureMapper, TransparencyType};
-use crate::component::texture::TextureHandler;
+use crate::component::terrain::{Terrain, TerrainConfig};
+pub use crate::component::terrain::TranslucentSort;
+use crate::component::terrain::registry::BlockRegistry;
+use crate::component::terrain::chunk_store::ChunkStore;
+use crate::component::terrain::chunk_mesh::ChunkPriority;
+use crate::component::terrain::terrain_gen::TerrainGenerator;
+use crate::component::texture::{texture_pack_paths, TextureHandler, TextureLoading};
+pub use crate::component::texture::{TextureLayout, TexturePack};
+pub use crate::component::terrain::chunk_mesh::{ChunkUnload, VertexLimit, WorldBounds};
 use crate::component::tick::TickSync;
+use crate::component::point_light::PointLightComponent;
 use crate::measurement::{blox};
-use crate::shader::chunk::ChunkRasterizer;
-use crate::shader::Shader;
+use crate::offscreen::{best_offscreen_color_and_depth_format, OffscreenTarget};
+use crate::shader::forward_custom;
+pub use crate::shader::{Msaa, Shader, ShaderInit, default_shader, deferred_shader};
+pub use crate::framebuffer::FBAttachmentRef;
 use crate::swapchain::{best_surface_color_and_depth_format, SwapchainManager};
+pub use crate::swapchain::{DepthFormat, PresentMode};
+pub use crate::error::MatrixagonError;
+pub use crate::config::AppConfig;
+pub use crate::handler::DeviceOverride;
+use crate::util::{FramePacer, MouseLock, RenderPause, WorkerPool};
 
 mod handler;
 pub mod debug;
@@ -53,26 +75,57 @@ mod util;
 mod measurement;
 mod swapchain;
 mod framebuffer;
-
+mod offscreen;
+mod error;
+mod config;
+
+
+// written when F12 is released
+const DIAGNOSTICS_PATH: &str = "diagnostics.txt";
+// block edits are saved here on exit (in a directory per seed), and loaded back on start
+const WORLD_SAVE_DIR: &str = "world";
+// texture pack bundled with the crate
+pub const DEFAULT_TEXTURE_PACK: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/resource/block_textures");
+// block definitions bundled with the crate
+pub const DEFAULT_BLOCK_REGISTRY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/resource/blocks.ron");
+// seed of the world generated by default
+pub const DEFAULT_SEED: u32 = 50;
+// of the gradient sky, the fog fades the terrain into the horizon color
+const SKY_ZENITH: [u8; 4] = [51, 77, 230, 255];
+const SKY_HORIZON: [u8; 4] = [190, 215, 245, 255];
+// of every headless frame, so the rendered frames don't depend on how long they took
+const HEADLESS_FRAME_TIME: Duration = Duration::from_micros(16_667);
+// between the world updates while the rendering is paused, waking up early on any window event
+const PAUSED_FRAME_TIME: Duration = Duration::from_millis(100);
+// longest frame time the world is updated with, so it doesn't jump ahead after stalls or resuming
+const MAX_DELTA_TIME: Duration = Duration::from_millis(100);
 
 pub struct MatrixagonApp {
-    // Debug
-    debug_visibility: DebugVisibility,
     // Window management
     event_loop: EventLoop<()>,
     window: Window,
-    window_render: bool,
-    mouse_lock: bool,
+    window_render: bool,  // the surface has an extent, so not while minimized
+    render_pause: RenderPause,
+    mouse_lock: MouseLock,
     // Main app fields
     world: World,
     handler: VulkanHandler,
     // Misc
     frame_time: Instant,
+    frame_pacer: Option<FramePacer>,
 }
 
 impl MatrixagonApp {
-    pub fn init(validate: bool, debug_visibility: DebugVisibility, fullscreen: bool, mouse_lock: bool) -> MatrixagonApp {
+    // config holds the window, device and world options (see AppConfig for their defaults)
+    // shader creates the renderer drawing the world (e.g. default_shader, or deferred_shader for many lights), the
+    // components hand it their descriptors and buffers by their RenderDataPurpose
+    // fails without a Vulkan device able to render to the window, when the resources can't be loaded, or when the shader
+    // can't be created (e.g. without glslc to compile its shaders)
+    pub fn init(mut config: AppConfig,
+                shader: impl FnOnce(ShaderInit) -> Result<Box<dyn Shader>, MatrixagonError>) -> Result<MatrixagonApp, MatrixagonError> {
+        config.check_terrain()?;
         let prsnt_inp = true;
+        let fullscreen = config.fullscreen;
 
         let initial_extent = if fullscreen {
             vk::Extent2D {
@@ -95,17 +148,20 @@ impl MatrixagonApp {
             .with_visible(true)
             .with_title("Matrixagon 2")
             .build(&event_loop)
-            .expect("Window builder failed");
+            .map_err(MatrixagonError::Window)?;
+
+        let render_pause = RenderPause::new(config.pause_unfocused, window.has_focus());
+        let mouse_lock = MouseLock::new(config.mouse_lock, window.has_focus());
+        if mouse_lock.locked() {
+            window.set_cursor_position(PhysicalPosition::new(
+                initial_extent.width as f32/2.0, initial_extent.height as f32/2.0
+            )).unwrap();
+        }
 
-        window.set_cursor_position(PhysicalPosition::new(
-            initial_extent.width as f32/2.0, initial_extent.height as f32/2.0
-        )).unwrap();
+        let mut handler = VulkanHandler::init(Some((&event_loop, &window)), config.validate, &config.debug_visibility,
+                                              config.device.as_ref())?;
 
-        let mut handler = VulkanHandler::init(&event_loop, &window, validate, debug_visibility);
-
-        if debug_visibility.mtxg_output {
-            println!("WINDOW SCALE FACTOR {:?}", window.scale_factor() as f32);
-        }
+        log::debug!(target: MTXG_LOG, "WINDOW SCALE FACTOR {:?}", window.scale_factor() as f32);
 
         // let mut ui_handler = EguiHandler::new(handler.vi.clone(), handler.device.clone());
         let init_raw_input = RawInput {
@@ -136,122 +192,120 @@ impl MatrixagonApp {
                 );
                 m
             },
-            screen_rect: Some(Rect {
-                min: Pos2::from((0.0, 0.0)),
-                max: Pos2::from((160.0, 500.0))
-            }),
             modifiers: Modifiers::default(),
             events: Vec::new(),
             focused: true,
             ..Default::default()
         };
 
-        let ratio = initial_extent.width as f32/initial_extent.height as f32;
-        let mut world = World::new(debug_visibility, vec![
-            Box::new(TickSync::new(
-                handler.vi.clone(), handler.device.clone(), 1.5,
-            )),
-            Box::new(WorldFlags::new()),
-            Box::new(TextureHandler::new(handler.vi.clone(), handler.device.clone(), vec![
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/null.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/stone.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/grass_top.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/grass_side.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/dirt.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/sand.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/grass_flora.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/grass_top.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/flower.png"),
-                Path::new("C:/Users/andrewshen/documents/matrixagon2/src/resource/block_textures/water.png"),
-            ])),
-            Box::new(CameraComponent::new(
-                handler.vi.clone(), handler.device.clone(), ratio, 70.0, 1.0, 0.05,
-                Length3D::new(
-                    Length::new::<blox>(0.0),
-                    Length::new::<blox>(40.0),
-                    Length::new::<blox>(0.0),
-                )
-            )),
-            Box::new(Terrain::new(handler.vi.clone(), handler.device.clone(), handler.get_cmd_buf_context(), vec![
-                BlockData {
-                    ident: "grass_block",
-                    texture_id: TextureMapper::Lateral("grass_top", "dirt", "grass_side"),
-                    mesh: MeshType::Cube,
-                    transparency: TransparencyType::Opaque,
-                },
-                BlockData {
-                    ident: "dirt",
-                    texture_id: TextureMapper::All("dirt"),
-                    mesh: MeshType::Cube,
-                    transparency: TransparencyType::Opaque,
-                },
-                BlockData {
-                    ident: "stone",
-                    texture_id: TextureMapper::All("stone"),
-                    mesh: MeshType::Cube,
-                    transparency: TransparencyType::Opaque,
-                },
-                BlockData {
-                    ident: "sand",
-                    texture_id: TextureMapper::All("sand"),
-                    mesh: MeshType::Cube,
-                    transparency: TransparencyType::Opaque,
-                },
-                BlockData {
-                    ident: "grass",
-                    texture_id: TextureMapper::All("grass_flora"),
-                    mesh: MeshType::XCross,
-                    transparency: TransparencyType::Transparent,
-                },
-                BlockData {
-                    ident: "flower",
-                    texture_id: TextureMapper::All("flower"),
-                    mesh: MeshType::XCross,
-                    transparency: TransparencyType::Transparent,
-                },
-                BlockData {
-                    ident: "water",
-                    texture_id: TextureMapper::All("water"),
-                    mesh: MeshType::Fluid,
-                    transparency: TransparencyType::Translucent,
-                },
-                BlockData {
-                    ident: "air",
-                    texture_id: TextureMapper::All("null"),
-                    mesh: MeshType::Empty,
-                    transparency: TransparencyType::Transparent,
-                },
-            ])),
-            Box::new(DebugUI::new(handler.vi.clone(), handler.device.clone(), init_raw_input)),
-        ]);
-
-        let format = best_surface_color_and_depth_format(debug_visibility, handler.vi.clone());
-        let mut shader = unsafe {
-            ChunkRasterizer::new(handler.device.clone(), initial_extent, format.0, format.1)
-        };
+        let mut debug_ui = DebugUI::new(handler.vi.clone(), handler.device.clone(), init_raw_input, initial_extent);
+        if let Some(max_fps) = config.max_fps {
+            // a few seconds of frames at the capped frame rate
+            debug_ui.set_fps_samples((max_fps as usize*3).max(DebugUI::FPS_SAMPLES));
+        }
+        let mut world = create_world(&handler, &config, initial_extent, debug_ui,
+            WorkerPool::new(config.generation_workers.unwrap_or_else(WorkerPool::default_workers)),
+            Some(ChunkStore::new(&Path::new(WORLD_SAVE_DIR).join(config.seed.to_string()))),
+        )?;
+        if let Some(hook) = config.on_initial_generation.take() {
+            world.on_initial_generation(hook);
+        }
+
+        let format = best_surface_color_and_depth_format(handler.vi.clone(), &config.depth_formats);
+        let mut shader = shader(ShaderInit {
+            vi: handler.vi.clone(), device: handler.device.clone(), extent: initial_extent,
+            color_format: format.0, depth_format: format.1, texture_layout: config.texture_layout, presented: true,
+            msaa: config.msaa,
+        })?;
 
         let mut descriptors = unsafe {
             world.load_descriptors(handler.get_cmd_buf_context())
         };
         let swpc = unsafe {
-            SwapchainManager::new(debug_visibility, handler.vi.clone(), handler.device.clone(), shader.renderpass(), shader.attachments(), prsnt_inp)
+            SwapchainManager::new(handler.vi.clone(), handler.device.clone(), shader.renderpass(), shader.attachments(), prsnt_inp,
+                                  format.1, config.present_mode)?
         };
-        descriptors.append(&mut unsafe { swpc.fbm.get_input_attachment_descriptors() });
+        world.add_window_event(WorldEvent::ActivePresentMode(swpc.prsnt));
+        descriptors.append(&mut unsafe { swpc.fbm.get_attachment_descriptors() });
+        let descriptors = forward_custom(shader.as_mut(), descriptors);
         unsafe { shader.write_descriptors(descriptors); }
 
         handler.load_swapchain(swpc);
         handler.load_shader(shader);
 
-        MatrixagonApp {
-            debug_visibility,
+        Ok(MatrixagonApp {
             event_loop,
             window,
             window_render: false,
+            render_pause,
             mouse_lock,
             world,
             handler,
             frame_time: Instant::now(),
+            frame_pacer: config.max_fps.map(FramePacer::new),
+        })
+    }
+
+    // renders into an offscreen image of the extent (width, height) without a window or swapchain, with the chunks
+    // generated on the main thread and no saved world loaded, so the same frames are rendered every time (e.g. for the
+    // golden image tests), the window options and the generation workers of the config are left out
+    // shader creates the renderer as with init
+    pub fn init_headless(extent: (u32, u32), mut config: AppConfig,
+                         shader: impl FnOnce(ShaderInit) -> Result<Box<dyn Shader>, MatrixagonError>) -> Result<HeadlessApp, MatrixagonError> {
+        config.check_terrain()?;
+        let extent = vk::Extent2D { width: extent.0, height: extent.1 };
+
+        let mut handler = VulkanHandler::init(None, config.validate, &config.debug_visibility, config.device.as_ref())?;
+
+        let init_raw_input = RawInput {
+            screen_rect: Some(Rect {
+                min: Pos2::from((0.0, 0.0)),
+                max: Pos2::from((extent.width as f32, extent.height as f32)),
+            }),
+            focused: true,
+            ..Default::default()
+        };
+        let debug_ui = DebugUI::new(handler.vi.clone(), handler.device.clone(), init_raw_input, extent);
+        let mut world = create_world(&handler, &config, extent, debug_ui, WorkerPool::new(0), None)?;
+        if let Some(hook) = config.on_initial_generation.take() {
+            world.on_initial_generation(hook);
         }
+
+        let format = best_offscreen_color_and_depth_format(handler.vi.clone(), &config.depth_formats);
+        let mut shader = shader(ShaderInit {
+            vi: handler.vi.clone(), device: handler.device.clone(), extent,
+            color_format: format.0, depth_format: format.1, texture_layout: config.texture_layout, presented: false,
+            msaa: config.msaa,
+        })?;
+
+        let mut descriptors = unsafe {
+            world.load_descriptors(handler.get_cmd_buf_context())
+        };
+        let offscreen = unsafe {
+            OffscreenTarget::new(handler.vi.clone(), handler.device.clone(), shader.renderpass(),
+                                 shader.attachments(), true, format.1, extent)?
+        };
+        descriptors.append(&mut unsafe { offscreen.fbm.get_attachment_descriptors() });
+        let descriptors = forward_custom(shader.as_mut(), descriptors);
+        unsafe { shader.write_descriptors(descriptors); }
+
+        handler.load_offscreen(offscreen);
+        handler.load_shader(shader);
+
+        Ok(HeadlessApp { world, handler, extent })
+    }
+
+    // report of the GPU, swapchain and component states for attaching to bug reports
+    pub fn dump_diagnostics(&self) -> String {
+        Self::diagnostics_report(&self.handler, &self.world)
+    }
+
+    // separate from the app, as the event loop is moved out of it while running
+    fn diagnostics_report(handler: &VulkanHandler, world: &World) -> String {
+        let mut diagnostics = Diagnostics::default();
+        handler.diagnostics(&mut diagnostics);
+        world.diagnostics(&mut diagnostics);
+        diagnostics.report()
     }
 
     pub fn run(self) {
@@ -263,13 +317,13 @@ impl MatrixagonApp {
             Event::NewEvents(_) => {
                 // begin events (for benchmarking)
                 let now = Instant::now();
-                let delta = now-app.frame_time;
+                let delta = (now-app.frame_time).min(MAX_DELTA_TIME);
                 app.world.add_window_event(WorldEvent::DeltaTime(delta));
 
                 app.frame_time = now;
             }
             Event::MainEventsCleared => {
-                if app.mouse_lock {
+                if app.mouse_lock.locked() {
                     let _ = app.window.set_cursor_position(PhysicalPosition::new(
                         app.handler.swapchain.as_ref().unwrap().extent.width as f32 / 2.0,
                         app.handler.swapchain.as_ref().unwrap().extent.height as f32 / 2.0
@@ -280,40 +334,77 @@ impl MatrixagonApp {
                 app.world.update();
             }
             Event::RedrawRequested(_) => {
-                if app.debug_visibility.mtxg_output {
-                    println!("REDRAW REQUESTED");
-                }
+                log::trace!(target: MTXG_LOG, "REDRAW REQUESTED");
             }
             Event::RedrawEventsCleared => unsafe {
                 // final event (for drawing and benchmarking)
+                let paused = app.render_pause.paused();
+                if paused || !app.window_render {
+                    // nothing is drawn, but the render data of the updates (e.g. the uploaded chunks) still has to
+                    // reach the shader, as the components only keep it until their next update
+                    let frame = app.handler.wait_frames_idle();
+                    app.world.render(app.handler.obtain_shader_mut_ref(), frame);
+                }
+                if paused {
+                    // the world keeps updating (e.g. loading chunks) at a slower pace in the meantime
+                    ctrl_flow.set_wait_until(Instant::now()+PAUSED_FRAME_TIME);
+                    return;
+                }
+                ctrl_flow.set_poll();
                 if app.window_render {
-                    app.world.render(app.handler.obtain_shader_mut_ref());
+                    if let Some(present_mode) = app.world.take_present_mode() {
+                        let prsnt = app.handler.set_present_mode(present_mode);
+                        app.world.add_window_event(WorldEvent::ActivePresentMode(prsnt));
+                    }
+                    if let Some(msaa) = app.world.take_msaa() {
+                        app.handler.set_msaa(msaa);
+                    }
+                    let frame = app.handler.wait_frame();
+                    app.world.render(app.handler.obtain_shader_mut_ref(), frame);
 
                     // app.ui_handler.handle_output();
 
                     app.handler.draw_frame();
                 }
+                if let Some(frame_pacer) = app.frame_pacer {
+                    frame_pacer.wait(app.frame_time);
+                }
             }
             Event::WindowEvent {event: win_event, window_id: _} => {
                 match win_event {
                     WindowEvent::CloseRequested => {
                         *ctrl_flow = ControlFlow::Exit;
                     }
+                    WindowEvent::Focused(focused) => {
+                        app.mouse_lock.set_focused(focused);
+                        app.render_pause.set_focused(focused);
+                    }
                     WindowEvent::Occluded(occluded) => {
-                        if app.debug_visibility.vk_setup_output {
-                            println!("OCCLUDED? {occluded}")
-                        }
+                        log::debug!(target: VK_SETUP_LOG, "OCCLUDED? {occluded}");
+                        app.render_pause.set_occluded(occluded);
                     }
                     WindowEvent::Resized(size) => {
-                        if app.debug_visibility.vk_swapchain_output {
-                            println!("RESIZED? {size:?}");
-                        }
+                        log::debug!(target: VK_SWAPCHAIN_LOG, "RESIZED? {size:?}");
+                        // minimized, rendering pauses until the window is restored
                         if size.height == 0 || size.width == 0 {
                             app.window_render = false;
                         } else {
                             app.window_render = true;
+                            let extent = unsafe {
+                                app.handler.recreate_swapchain(vk::Extent2D { width: size.width, height: size.height })
+                            };
+                            app.world.add_window_event(WorldEvent::SurfaceResized(extent));
                         }
                     }
+                    WindowEvent::CursorMoved {position, ..} => {
+                        if let Some(swapchain) = app.handler.swapchain.as_ref() {
+                            app.world.add_window_event(WorldEvent::CursorMoved((position.x, position.y), swapchain.extent));
+                        }
+                    }
+                    // unlike the device wheel events (which would count each scroll twice), only while focused
+                    WindowEvent::MouseWheel {delta, ..} => {
+                        app.world.add_window_event(WorldEvent::mouse_scroll(delta));
+                    }
                     WindowEvent::MouseInput {state, button, ..} => match state {
                         ElementState::Pressed => match button {
                             MouseButton::Left => {app.world.add_window_event(WorldEvent::LeftButtonPressed)}
@@ -334,23 +425,38 @@ impl MatrixagonApp {
             Event::DeviceEvent {device_id: _, event: dev_event} => {
                 match dev_event {
                     DeviceEvent::MouseMotion {delta} => {
-                        app.world.add_window_event(WorldEvent::MouseMotion(delta));
+                        // device events are received even while using another window
+                        if app.mouse_lock.focused() {
+                            app.world.add_window_event(WorldEvent::MouseMotion(delta));
+                        }
                     }
                     DeviceEvent::Key(KeyboardInput {state, virtual_keycode, ..}) => {
                         if let Some(key) = virtual_keycode {
                             match state {
-                                ElementState::Pressed => {
+                                // typing into another window doesn't move the camera, but the keys held while
+                                // switching to it are still released
+                                ElementState::Pressed if app.mouse_lock.focused() => {
                                     app.world.add_window_event(WorldEvent::KeyPressed(key));
                                 }
+                                ElementState::Pressed => {}
                                 ElementState::Released => {
                                     app.world.add_window_event(WorldEvent::KeyReleased(key));
+                                    if !app.mouse_lock.focused() {
+                                        return;
+                                    }
 
                                     match key {
                                         VirtualKeyCode::Escape => {
                                             *ctrl_flow = ControlFlow::Exit;
                                         }
                                         VirtualKeyCode::T => {
-                                            app.mouse_lock = !app.mouse_lock;
+                                            app.mouse_lock.toggle();
+                                        }
+                                        VirtualKeyCode::F12 => {
+                                            match fs::write(DIAGNOSTICS_PATH, Self::diagnostics_report(&app.handler, &app.world)) {
+                                                Ok(()) => log::info!("Wrote the diagnostics to {DIAGNOSTICS_PATH}"),
+                                                Err(e) => log::warn!("Failed to write the diagnostics to {DIAGNOSTICS_PATH}: {e}"),
+                                            }
                                         }
                                         _ => {}
                                     };
@@ -362,9 +468,7 @@ impl MatrixagonApp {
                 }
             }
             Event::LoopDestroyed => unsafe {
-                if app.debug_visibility.vk_setup_output {
-                    println!("EVENT LOOP DESTROYED: Vulkan object destroyed & cleaned-up");
-                }
+                log::debug!(target: VK_SETUP_LOG, "EVENT LOOP DESTROYED: Vulkan object destroyed & cleaned-up");
 
                 app.handler.device.device_wait_idle().unwrap();
 
@@ -378,6 +482,125 @@ impl MatrixagonApp {
 }
 
 
+// the app rendering into an offscreen image, driven a frame at a time instead of by the window events
+pub struct HeadlessApp {
+    world: World,
+    handler: VulkanHandler,
+    extent: vk::Extent2D,
+}
+
+impl HeadlessApp {
+    // (width, height) of the rendered frames
+    pub fn extent(&self) -> (u32, u32) {
+        (self.extent.width, self.extent.height)
+    }
+
+    // updates the world by a fixed frame time and renders it, returning the final framebuffer as rgba rows from the
+    // top (in sRGB), the chunks show up over the first few frames as they are generated and uploaded
+    pub fn render_once(&mut self) -> Vec<u8> {
+        self.world.add_window_event(WorldEvent::DeltaTime(HEADLESS_FRAME_TIME));
+        self.world.update();
+        unsafe {
+            let frame = self.handler.wait_frame();
+            self.world.render(self.handler.obtain_shader_mut_ref(), frame);
+            self.handler.draw_offscreen()
+        }
+    }
+}
+
+impl Drop for HeadlessApp {
+    fn drop(&mut self) {
+        unsafe {
+            self.handler.device.device_wait_idle().unwrap();
+            self.world.destroy_descriptors();
+            self.handler.destroy();
+        }
+    }
+}
+
+// the components of the app, drawn to the extent of the window or the offscreen image
+// store keeps the block edits across runs, if any
+fn create_world(handler: &VulkanHandler, config: &AppConfig, extent: vk::Extent2D, debug_ui: DebugUI, workers: WorkerPool,
+                store: Option<ChunkStore>) -> Result<World, MatrixagonError> {
+    let block_registry = BlockRegistry::from_file(config.block_registry)
+        .map_err(|e| MatrixagonError::Resource("the block registry", e))?;
+    let block_ind = block_registry.blocks().to_vec();
+
+    let ratio = extent.width as f32/extent.height as f32;
+    let texture_handler = match config.texture_pack {
+        TexturePack::Directory(root) => texture_pack_paths(root, &block_registry.texture_files()).and_then(|paths| {
+            let (vi, device) = (handler.vi.clone(), handler.device.clone());
+            let paths = paths.iter().map(PathBuf::as_path).collect();
+            match (config.texture_layout, config.texture_streaming) {
+                (TextureLayout::Array, Some(capacity)) => TextureHandler::new_streamed(
+                    vi, device, paths, TextureLoading::Lenient, config.mipmaps, capacity,
+                ),
+                (TextureLayout::Array, None) => TextureHandler::new(vi, device, paths, TextureLoading::Lenient, config.mipmaps),
+                (TextureLayout::Atlas, _) => TextureHandler::new_atlas(vi, device, paths, TextureLoading::Lenient, config.mipmaps),
+            }
+        }),
+        TexturePack::Embedded(txtr_bytes) => {
+            let texture_handler = match config.texture_layout {
+                TextureLayout::Array => TextureHandler::from_bytes,
+                TextureLayout::Atlas => TextureHandler::from_bytes_atlas,
+            };
+            texture_handler(handler.vi.clone(), handler.device.clone(), txtr_bytes.to_vec(), TextureLoading::Lenient, config.mipmaps)
+        }
+    }
+        .map_err(|e| MatrixagonError::Resource("the block textures", e))?;
+    block_registry.validate_textures(texture_handler.txtr_mapper())
+        .map_err(|e| MatrixagonError::Resource("the textures of the block registry", e))?;
+    let terrain = Terrain::new(handler.vi.clone(), handler.device.clone(), handler.get_cmd_buf_context(), block_ind.clone(),
+        TerrainGenerator::new(config.seed, 20.0, 1.0, true),
+        TerrainConfig {
+            lod_downsample: config.lod_downsample,
+            chunk_size: config.chunk_size,
+            render_distance: 4,
+            translucent_sort: config.translucent_sort,
+            chunk_unload: config.chunk_unload,
+            chunk_priority: ChunkPriority::NearestFirst,
+            vertex_limit: config.vertex_limit,
+            world_bounds: config.world_bounds,
+        },
+        workers, store,
+    );
+    let blocks = Rc::new(terrain.blocks());
+    Ok(World::new(vec![
+        Box::new(TickSync::new(
+            handler.vi.clone(), handler.device.clone(), 1.5,
+        )),
+        Box::new(WorldFlags::new(config.debug_visibility.chunk_borders, config.present_mode, config.msaa)),
+        Box::new(texture_handler),
+        Box::new(match config.skybox_faces {
+            Some(faces) => SkyboxHandler::new(handler.vi.clone(), handler.device.clone(), faces)?,
+            None => SkyboxHandler::gradient(handler.vi.clone(), handler.device.clone(), SKY_ZENITH, SKY_HORIZON),
+        }),
+        // fully fogged within the medium fidelity border, which is at least 512 blox away
+        Box::new(FogComponent::new(handler.vi.clone(), handler.device.clone(),
+            FogParams::new(srgb_to_linear(SKY_HORIZON), 0.008, 64.0, 512.0),
+        )),
+        // ten minute days starting in the morning, fading the fog along with the sky
+        Box::new(SkyComponent::new(handler.vi.clone(), handler.device.clone(),
+            DayCycle::new(0.35, 600.0), srgb_to_linear(SKY_HORIZON),
+        )),
+        Box::new(PointLightComponent::new(handler.vi.clone(), handler.device.clone(), vec![])),
+        Box::new(CameraComponent::new(
+            handler.vi.clone(), handler.device.clone(), ratio, config.fov.0, config.fov.1,
+            MovementSpeed { walk: 1.0, spectator: 4.0, sprint_multiplier: 3.0, momentum: Some(0.15) },
+            config.reach, MouseSettings::default(),
+            Length3D::new(
+                Length::new::<blox>(0.0),
+                Length::new::<blox>(40.0),
+                Length::new::<blox>(0.0),
+            ),
+            Some(blocks),
+        )),
+        Box::new(terrain),
+        Box::new(Hotbar::new(GameMode::Creative, &block_ind)),
+        Box::new(debug_ui),
+    ], config.event_history_frames, World::DEFAULT_MAX_EVENT_DEPTH))
+}
+
 
 // #[cfg(test)]
 // mod tests {
@@ -0,0 +1,121 @@
+use std::{ffi, slice};
+use std::rc::Rc;
+use ash::{Device, vk};
+use crate::error::MatrixagonError;
+use crate::framebuffer::{FBAttachmentRef, FramebufferManager};
+use crate::handler::VulkanInstance;
+use crate::swapchain::{best_depth_format_support, DepthFormat};
+use crate::util::{create_host_buffer, create_local_image};
+
+
+// same color space as the swapchain images, in the channel order of the pixels read back
+pub(crate) const OFFSCREEN_COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+// the color attachment drawn into in place of the swapchain images when headless, copied into a host visible buffer
+// at the end of every frame
+pub(crate) struct OffscreenTarget {
+    device: Rc<Device>,
+
+    pub(crate) extent: vk::Extent2D,
+    img: vk::Image,
+    img_mem: vk::DeviceMemory,
+    pub(crate) fbm: FramebufferManager,
+
+    readback_buf: vk::Buffer,
+    readback_mem: vk::DeviceMemory,
+    readback_ptr: *mut ffi::c_void,
+    readback_size: vk::DeviceSize,
+}
+
+impl OffscreenTarget {
+    // prsnt_inp: make the color image also an input attachment, as with the swapchain images
+    pub(crate) unsafe fn new(
+        vi: Rc<VulkanInstance>, device: Rc<Device>, renderpass: vk::RenderPass,
+        attachments: Vec<FBAttachmentRef>, prsnt_inp: bool, depth_fmt: vk::Format, extent: vk::Extent2D,
+    ) -> Result<Self, MatrixagonError> {
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::SAMPLED;
+        let (img, img_mem) = create_local_image(
+            vi.clone(), device.clone(),
+            vk::Extent3D {width: extent.width, height: extent.height, depth: 1},
+            1, OFFSCREEN_COLOR_FORMAT, if prsnt_inp {usage | vk::ImageUsageFlags::INPUT_ATTACHMENT} else {usage}, None
+        );
+
+        // a single framebuffer, with the offscreen image standing in for the presentation image
+        let fbm = FramebufferManager::new_swapchain_bounded(
+            vi.clone(), device.clone(), renderpass, attachments, vec![img],
+            OFFSCREEN_COLOR_FORMAT, depth_fmt, extent, prsnt_inp
+        )?;
+
+        let (readback_buf, readback_mem, readback_ptr, readback_size) = create_host_buffer(
+            vi.clone(), device.clone(), &vec![0u8; (extent.width*extent.height*4) as usize],
+            vk::BufferUsageFlags::TRANSFER_DST, false
+        );
+
+        Ok(Self {
+            device, extent, img, img_mem, fbm,
+            readback_buf, readback_mem, readback_ptr, readback_size,
+        })
+    }
+
+    // after the renderpass, which leaves the color image in the transfer source layout
+    pub(crate) unsafe fn record_readback(&self, cmd_buf: vk::CommandBuffer) {
+        let drawn = vk::MemoryBarrier {
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            ..Default::default()
+        };
+        self.device.cmd_pipeline_barrier(
+            cmd_buf, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(), &[drawn], &[], &[]
+        );
+
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D::default(),
+            image_extent: vk::Extent3D {width: self.extent.width, height: self.extent.height, depth: 1},
+        };
+        self.device.cmd_copy_image_to_buffer(
+            cmd_buf, self.img, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.readback_buf, &[region]
+        );
+
+        let copied = vk::MemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::HOST_READ,
+            ..Default::default()
+        };
+        self.device.cmd_pipeline_barrier(
+            cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(), &[copied], &[], &[]
+        );
+    }
+
+    // rgba rows from the top of the last frame read back, once its submission completed
+    pub(crate) unsafe fn pixels(&self) -> Vec<u8> {
+        slice::from_raw_parts(self.readback_ptr as *const u8, self.readback_size as usize).to_vec()
+    }
+
+    pub(crate) unsafe fn destroy(&self) {
+        self.fbm.destroy();
+        self.device.destroy_image(self.img, None);
+        self.device.free_memory(self.img_mem, None);
+
+        self.device.destroy_buffer(self.readback_buf, None);
+        self.device.free_memory(self.readback_mem, None);
+    }
+}
+
+// the depth format for the offscreen target, as there is no surface to pick the color format from
+pub(crate) fn best_offscreen_color_and_depth_format(vi: Rc<VulkanInstance>, depth_formats: &[DepthFormat])
+    -> (vk::Format, vk::Format) {
+    unsafe {
+        (OFFSCREEN_COLOR_FORMAT, best_depth_format_support(&vi, depth_formats))
+    }
+}
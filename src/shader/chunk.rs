@@ -1,8 +1,15 @@
 use std::rc::Rc;
 use ash::{Device, vk};
 use crate::component::{RenderData, RenderDataPurpose};
+use crate::component::debug_ui::{UiDraw, MAX_UI_TEXTURES};
+use crate::component::texture::TextureLayout;
 use crate::framebuffer::FBAttachmentRef;
-use crate::shader::{ColorBlendKind, DescriptorManager, Shader, create_graphics_pipeline, StandardGraphicsPipelineInfo, VBOFS};
+use crate::handler::VulkanInstance;
+use crate::shader::chunk_draws::{ChunkBuffers, ChunkDrawCommands, max_chunk_draws};
+use crate::shader::occlusion::OcclusionCuller;
+use crate::shader::{ColorBlendKind, DescriptorManager, Msaa, Shader, create_graphics_pipeline, record_ui_draws, StandardGraphicsPipelineInfo, VBOFS};
+use crate::debug::{MTXG_LOG, MTXG_RENDER_LOG};
+use crate::error::MatrixagonError;
 use matrixagon_util::{Vertex, VulkanVertexState, create_renderpass, IndexedBuffer};
 
 
@@ -11,6 +18,10 @@ pub struct ChunkVertex {
     pub(crate) pos: [f32; 3],
     pub(crate) uv: [f32; 2],
     pub(crate) txtr: f32,
+    pub(crate) anim: [f32; 2],  // frame count and interval, frames are in the consecutive layers after txtr
+    pub(crate) ao: f32,  // ambient occlusion level from 0 (unoccluded) to 3
+    pub(crate) light: f32,  // light level of the block in front of the face, from 0 (dark) to 1 (fully lit)
+    pub(crate) normal: [f32; 3],  // outward from the face, for the sun shading
 }
 
 // emulating the structure of the EguiVertex
@@ -21,6 +32,24 @@ pub struct EguiVertex {
     pub(crate) color: [u8; 3],
 }
 
+#[derive(Copy, Clone, Debug, Vertex)]
+pub struct ChunkBorderVertex {
+    pub(crate) pos: [f32; 3],
+    pub(crate) color: [f32; 3],
+}
+
+
+// the pipelines of the chunk rasterizer, recreated together whenever the shaders are reloaded
+struct ChunkPipelines {
+    skybox: vk::Pipeline,
+    terrain: vk::Pipeline,
+    transparent: vk::Pipeline,
+    translucent_fluid: vk::Pipeline,
+    chunk_border: vk::Pipeline,
+    debug: vk::Pipeline,
+    // line mode copies of the terrain, transparent and translucent fluid pipelines
+    wireframe: Option<[vk::Pipeline; 3]>,
+}
 
 pub struct ChunkRasterizer {
     device: Rc<Device>,
@@ -29,17 +58,40 @@ pub struct ChunkRasterizer {
     descriptor: DescriptorManager,
     renderpass: vk::RenderPass,
     clear_values: Vec<vk::ClearValue>,
+    texture_layout: TextureLayout,  // of the fragment shaders, kept for reloading them
+    // the renderpass is recreated with the formats whenever the sample count changes
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    presented: bool,
+    supported_samples: vk::SampleCountFlags,  // by both the color and the depth attachments
+    samples: vk::SampleCountFlags,
+
+    // drawn behind the terrain, once the cubemap is bound
+    skybox_pipeline: vk::Pipeline,
+    skybox: bool,
 
     terrain_pipeline: vk::Pipeline,
     transparent_pipeline: vk::Pipeline,
     translucent_fluid_pipeline: vk::Pipeline,
-
-    terrain_ivbo: IndexedBuffer,
-    transparent_ivbo: IndexedBuffer,
-    translucent_fluid_ivbo: IndexedBuffer,
+    // only created when the device supports fillModeNonSolid
+    wireframe_pipelines: Option<[vk::Pipeline; 3]>,
+    fill_mode_non_solid: bool,
+    wireframe: bool,
+
+    // drawn per chunk through indirect draws, so the culling only changes the draw commands
+    terrain_chunks: ChunkBuffers,
+    transparent_chunks: ChunkBuffers,
+    chunk_draw_commands: ChunkDrawCommands,
+    translucent_fluid_ivbo: IndexedBuffer,  // sorted across the chunks
+    // only created when the depth format can be sampled, and unused while the depth is multisampled
+    occlusion: Option<OcclusionCuller>,
+
+    // debug overlay for the chunk extents
+    chunk_border_pipeline: vk::Pipeline,
+    chunk_border_ivbo: IndexedBuffer,
 
     // TODO: EGUI debug pipeline extension for this shader
-    debug_scissors: Option<[vk::Rect2D; 1]>,
+    debug_draws: Vec<UiDraw>,  // out of the debug ui buffers
     debug_pipeline: vk::Pipeline,
     debug_ivbo: IndexedBuffer,
 
@@ -48,22 +100,99 @@ pub struct ChunkRasterizer {
 }
 
 impl ChunkRasterizer {
-    pub(crate) unsafe fn new(device: Rc<Device>, extent: vk::Extent2D, color_format: vk::Format,
-                             depth_format: vk::Format) -> Self {
+    // the wireframe pipelines are only created if the device supports fillModeNonSolid (it's enabled along with it)
+    // presented is whether the color attachment is a swapchain image, otherwise it is an offscreen image copied out
+    // after the renderpass (e.g. headless)
+    // msaa is lowered to the sample counts supported by the device
+    pub(crate) unsafe fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, extent: vk::Extent2D, color_format: vk::Format,
+                             depth_format: vk::Format, texture_layout: TextureLayout, presented: bool, msaa: Msaa)
+                             -> Result<Self, MatrixagonError> {
+        let fill_mode_non_solid = vi.get_physical_device_features().fill_mode_non_solid == vk::TRUE;
+        let max_draws = max_chunk_draws(&vi);
+        let limits = vi.get_physical_device_properties().limits;
+        let supported_samples = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let samples = msaa.sample_count(supported_samples);
+        if samples != vk::SampleCountFlags::TYPE_1 {
+            log::info!("Drawing the terrain with {}x MSAA (requested {msaa:?})", samples.as_raw());
+        }
         let descriptor = DescriptorManager::new(device.clone(), vec![
             vec![  // set 0 for shader
                 (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX),  // proj-view
                 (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),  // textures
+                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),  // skybox cubemap
+                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT),  // fog
+                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT),  // sky
             ],
             vec![  // set 1 for ui  TODO: EGUI debug descriptor-set extension
-                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT), // egui debug ui texture
+                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT), // egui debug ui textures
                 (vk::DescriptorType::INPUT_ATTACHMENT, vk::ShaderStageFlags::FRAGMENT), // input attachment from previous
             ],
             vec![  // set 2 for animations
-                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX)  // time
+                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)  // time
             ]
-        ]);
+        ], &[(1, 0, MAX_UI_TEXTURES as u32)]);
+
+        let renderpass = Self::create_renderpass(device.clone(), color_format, depth_format, presented, samples);
+
+        let pipelines = match Self::create_pipelines(device.clone(), descriptor.pipeline_layout, renderpass, texture_layout,
+                                                     fill_mode_non_solid, samples) {
+            Ok(pipelines) => pipelines,
+            Err(err) => {
+                device.destroy_render_pass(renderpass, None);
+                descriptor.destroy();
+                return Err(MatrixagonError::ShaderCreation(err));
+            }
+        };
+
+        let chunk_draw_commands = ChunkDrawCommands::new(vi.clone(), device.clone());
+        let occlusion = OcclusionCuller::new(vi, device.clone(), depth_format)
+            .map_err(|err| log::warn!("{err}, the occlusion culling is unavailable"))
+            .ok();
+
+        Ok(Self {
+            device: device.clone(),
+            extent,
+            descriptor,
+            renderpass,
+            clear_values: Self::clear_values(samples),
+
+            texture_layout,
+            color_format,
+            depth_format,
+            presented,
+            supported_samples,
+            samples,
+
+            skybox_pipeline: pipelines.skybox,
+            skybox: false,
+
+            terrain_pipeline: pipelines.terrain,
+            transparent_pipeline: pipelines.transparent,
+            translucent_fluid_pipeline: pipelines.translucent_fluid,
+            wireframe_pipelines: pipelines.wireframe,
+            fill_mode_non_solid,
+            wireframe: false,
+            terrain_chunks: ChunkBuffers::new(device.clone(), max_draws),
+            transparent_chunks: ChunkBuffers::new(device.clone(), max_draws),
+            chunk_draw_commands,
+            translucent_fluid_ivbo: IndexedBuffer::pooled(device.clone()),
+            occlusion,
+
+            chunk_border_pipeline: pipelines.chunk_border,
+            chunk_border_ivbo: IndexedBuffer::pooled(device.clone()),
 
+            // TODO: EGUI debug pipeline extension
+            debug_draws: Vec::new(),
+            debug_pipeline: pipelines.debug,
+            debug_ivbo: IndexedBuffer::new(device.clone()),
+
+            vbo: None, ibo: None
+        })
+    }
+
+    // with a single sample the depth is kept for the depth pyramid of the occlusion culling
+    unsafe fn create_renderpass(device: Rc<Device>, color_format: vk::Format, depth_format: vk::Format, presented: bool,
+                                samples: vk::SampleCountFlags) -> vk::RenderPass {
         /* LIMIT IMAGE LAYOUT Most likely the only valid image layout in a graphics pipeline in subpass
             VK_IMAGE_LAYOUT_GENERAL                             = If same refs are used between input and color/depth
             VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL            = Writing color/generic attachments
@@ -79,131 +208,322 @@ impl ChunkRasterizer {
             .src_mask(COLOR_ATTACHMENT_OUTPUT(WRITE) | EARLY_FRAGMENT_TEST(WRITE))
          */
 
-        let renderpass = create_renderpass!{ [device];
-            Attachments {
-                presentation: {
-                    format: color_format, samples: TYPE_1,
-                    load: CLEAR, store: STORE,
-                    stencil_load: DONT_CARE, stencil_store: DONT_CARE,
-                    initial: UNDEFINED, final: PRESENT_SRC_KHR,
-                }
-                depth: {
-                    format: depth_format, samples: TYPE_1,
-                    load: CLEAR, store: DONT_CARE,
-                    stencil_load: DONT_CARE, stencil_store: DONT_CARE,
-                    initial: UNDEFINED, final: DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        let presentation_layout = if presented {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        } else {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        };
+        if samples == vk::SampleCountFlags::TYPE_1 {
+            create_renderpass!{ [device];
+                Attachments {
+                    presentation: {
+                        format: color_format, samples: TYPE_1,
+                        load: CLEAR, store: STORE,
+                        stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                        initial: UNDEFINED, final: (presentation_layout),
+                    }
+                    depth: {
+                        format: depth_format, samples: TYPE_1,
+                        load: CLEAR, store: STORE,  // reduced into the depth pyramid after the renderpass
+                        stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                        initial: UNDEFINED, final: DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                    }
                 }
-            }
-            Subpasses {
-                terrain: {
-                    input:,
-                    color: presentation~COLOR_ATTACHMENT_OPTIMAL,
-                    resolve:,
-                    preserve:,
-                    depth: depth~DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                Subpasses {
+                    terrain: {
+                        input:,
+                        color: presentation~COLOR_ATTACHMENT_OPTIMAL,
+                        resolve:,
+                        preserve:,
+                        depth: depth~DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    }
+                    composition: {  // TODO: EGUI debug subpass extension (omittable)
+                        input: presentation~GENERAL,
+                        color: presentation~GENERAL,
+                        resolve:,
+                        preserve:,
+                        depth:,
+                    }
                 }
-                composition: {  // TODO: EGUI debug subpass extension (omittable)
-                    input: presentation~GENERAL,
-                    color: presentation~GENERAL,
-                    resolve:,
-                    preserve:,
-                    depth:,
+                Dependencies {
+                    ->terrain: {  // the depth pyramid of the previous frame is read before its depth is cleared
+                        src_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS | COMPUTE_SHADER,
+                        dst_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS,
+                        src_access: ,
+                        dst_access: COLOR_ATTACHMENT_WRITE | DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    }
+                    terrain->composition: {  // TODO: EGUI debug subpass extension (omittable)
+                        src_stage:  COLOR_ATTACHMENT_OUTPUT,
+                        dst_stage:  FRAGMENT_SHADER,
+                        src_access: COLOR_ATTACHMENT_WRITE,
+                        dst_access: INPUT_ATTACHMENT_READ,
+                    }
+                    terrain->: {  // the depth is reduced into the depth pyramid
+                        src_stage:  LATE_FRAGMENT_TESTS,
+                        dst_stage:  COMPUTE_SHADER,
+                        src_access: DEPTH_STENCIL_ATTACHMENT_WRITE,
+                        dst_access: SHADER_READ,
+                    }
                 }
             }
-            Dependencies {
-                ->terrain: {
-                    src_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS,
-                    dst_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS,
-                    src_access: ,
-                    dst_access: COLOR_ATTACHMENT_WRITE | DEPTH_STENCIL_ATTACHMENT_WRITE,
+        } else {
+            // rendered into the multisampled attachments and resolved into the presentation attachment, which the
+            // composition subpass draws over at a single sample
+            create_renderpass!{ [device];
+                Attachments {
+                    presentation: {
+                        format: color_format, samples: TYPE_1,
+                        load: DONT_CARE, store: STORE,
+                        stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                        initial: UNDEFINED, final: (presentation_layout),
+                    }
+                    multisampled: {
+                        format: color_format, samples: (samples),
+                        load: CLEAR, store: DONT_CARE,
+                        stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                        initial: UNDEFINED, final: COLOR_ATTACHMENT_OPTIMAL,
+                    }
+                    depth: {
+                        format: depth_format, samples: (samples),
+                        load: CLEAR, store: DONT_CARE,
+                        stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                        initial: UNDEFINED, final: DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    }
                 }
-                terrain->composition: {  // TODO: EGUI debug subpass extension (omittable)
-                    src_stage:  COLOR_ATTACHMENT_OUTPUT,
-                    dst_stage:  FRAGMENT_SHADER,
-                    src_access: COLOR_ATTACHMENT_WRITE,
-                    dst_access: INPUT_ATTACHMENT_READ,
+                Subpasses {
+                    terrain: {
+                        input:,
+                        color: multisampled~COLOR_ATTACHMENT_OPTIMAL,
+                        resolve: presentation~COLOR_ATTACHMENT_OPTIMAL,
+                        preserve:,
+                        depth: depth~DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    }
+                    composition: {
+                        input: presentation~GENERAL,
+                        color: presentation~GENERAL,
+                        resolve:,
+                        preserve:,
+                        depth:,
+                    }
+                }
+                Dependencies {
+                    ->terrain: {
+                        src_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS,
+                        dst_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS,
+                        src_access: ,
+                        dst_access: COLOR_ATTACHMENT_WRITE | DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    }
+                    terrain->composition: {  // along with the resolve
+                        src_stage:  COLOR_ATTACHMENT_OUTPUT,
+                        dst_stage:  FRAGMENT_SHADER,
+                        src_access: COLOR_ATTACHMENT_WRITE,
+                        dst_access: INPUT_ATTACHMENT_READ,
+                    }
                 }
             }
+        }
+    }
+
+    // the presentation and depth attachments, after the multisampled color attachment when it's resolved
+    fn clear_values(samples: vk::SampleCountFlags) -> Vec<vk::ClearValue> {
+        let sky = vk::ClearValue { color: vk::ClearColorValue {float32: [0.2, 0.3, 0.9, 1.0]} };
+        let depth = vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue {depth: 0.0, stencil: 0} };
+        if samples == vk::SampleCountFlags::TYPE_1 {
+            vec![sky, depth]
+        } else {
+            vec![sky, sky, depth]
+        }
+    }
+
+    // samples of the pipelines drawing into the terrain subpass, the composition subpass has a single sample
+    unsafe fn create_pipelines(device: Rc<Device>, pipeline_layout: vk::PipelineLayout, renderpass: vk::RenderPass,
+                               texture_layout: TextureLayout, wireframe: bool,
+                               samples: vk::SampleCountFlags) -> Result<ChunkPipelines, String> {
+        // the sampled block textures have to match the layout of the texture handler
+        let (opaque_frag, transparent_frag, translucent_frag) = match texture_layout {
+            TextureLayout::Array => (
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk.frag"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_transparent.frag"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_translucent.frag"),
+            ),
+            TextureLayout::Atlas => (
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_atlas.frag"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_atlas_transparent.frag"),
+                // without the flow scrolling, which would leave the texture rect within the atlas
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_atlas_transparent.frag"),
+            ),
         };
 
-        let graphics_pipelines = create_graphics_pipeline(
-            device.clone(),
+        let mut groups = vec![
             vec![
                 StandardGraphicsPipelineInfo {  // opaque pipeline
                     shaders: vec![
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/chunk.vert", vk::ShaderStageFlags::VERTEX),
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/chunk.frag", vk::ShaderStageFlags::FRAGMENT),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk.vert"), vk::ShaderStageFlags::VERTEX),
+                        (opaque_frag, vk::ShaderStageFlags::FRAGMENT),
                     ],
                     vertex_input_state: ChunkVertex::VERTEX_INPUT_STATE,
-                    back_face_culling: true, depth_testing: true,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: true, depth_testing: true, depth_writing: true,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: samples,
                     color_blend_attachment_state: vec![ColorBlendKind::disabled()],
                     subpass_index: 0,
                 },
                 StandardGraphicsPipelineInfo {  // transparent pipeline
                     shaders: vec![
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/chunk.vert", vk::ShaderStageFlags::VERTEX),
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/chunk_transparent.frag", vk::ShaderStageFlags::FRAGMENT),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk.vert"), vk::ShaderStageFlags::VERTEX),
+                        (transparent_frag, vk::ShaderStageFlags::FRAGMENT),
                     ],
                     vertex_input_state: ChunkVertex::VERTEX_INPUT_STATE,
-                    back_face_culling: false, depth_testing: true,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: true, depth_testing: true, depth_writing: true,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: samples,
                     color_blend_attachment_state: vec![ColorBlendKind::transparent()],
                     subpass_index: 0,
                 },
                 StandardGraphicsPipelineInfo {  // translucent pipeline
                     shaders: vec![
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/chunk_fluid.vert", vk::ShaderStageFlags::VERTEX),
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/chunk_translucent.frag", vk::ShaderStageFlags::FRAGMENT),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_fluid.vert"), vk::ShaderStageFlags::VERTEX),
+                        (translucent_frag, vk::ShaderStageFlags::FRAGMENT),
                     ],
                     vertex_input_state: ChunkVertex::VERTEX_INPUT_STATE,
-                    back_face_culling: false, depth_testing: true,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: true, depth_testing: true, depth_writing: true,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: samples,
                     color_blend_attachment_state: vec![ColorBlendKind::transparent()],
                     subpass_index: 0,
                 },
             ],
-            descriptor.pipeline_layout, renderpass,
-        );
-
-        // multi-pipeline creation does not like different vertex input, so it's in a separate group
-        let debug_graphics_pipeline = create_graphics_pipeline(
-            device.clone(),
+            // multi-pipeline creation does not like different vertex input, so it's in a separate group
+            vec![
+                StandardGraphicsPipelineInfo {  // chunk border line pipeline
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_border.vert"), vk::ShaderStageFlags::VERTEX),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_border.frag"), vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: ChunkBorderVertex::VERTEX_INPUT_STATE,
+                    topology: vk::PrimitiveTopology::LINE_LIST,
+                    back_face_culling: false, depth_testing: true, depth_writing: true,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: samples,
+                    color_blend_attachment_state: vec![ColorBlendKind::disabled()],
+                    subpass_index: 0,
+                },
+            ],
+            // the fullscreen triangle has no vertex input, and neither tests nor writes the depth so the terrain draws over it
+            vec![
+                StandardGraphicsPipelineInfo {  // skybox pipeline
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/skybox.vert"), vk::ShaderStageFlags::VERTEX),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/skybox.frag"), vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: vk::PipelineVertexInputStateCreateInfo::default(),
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: false, depth_testing: false, depth_writing: false,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: samples,
+                    color_blend_attachment_state: vec![ColorBlendKind::disabled()],
+                    subpass_index: 0,
+                },
+            ],
             vec![
                 StandardGraphicsPipelineInfo {  // TODO: EGUI debug pipeline extension
                     shaders: vec![
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/debug_ui.vert", vk::ShaderStageFlags::VERTEX),
-                        ("C:/Users/andrewshen/documents/matrixagon2/src/shader/debug_ui.frag", vk::ShaderStageFlags::FRAGMENT),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/debug_ui.vert"), vk::ShaderStageFlags::VERTEX),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/debug_ui.frag"), vk::ShaderStageFlags::FRAGMENT),
                     ],
                     vertex_input_state: EguiVertex::VERTEX_INPUT_STATE,
-                    back_face_culling: false, depth_testing: false,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: false, depth_testing: false, depth_writing: false,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
                     color_blend_attachment_state: vec![ColorBlendKind::transparent()],
                     subpass_index: 1,
                 },
             ],
-            descriptor.pipeline_layout, renderpass,
-        );
+        ];
+        if wireframe {
+            // polygon mode isn't a dynamic state, so the terrain pipelines are duplicated in line mode
+            let lines = groups[0].iter()
+                .map(|info| StandardGraphicsPipelineInfo { polygon_mode: vk::PolygonMode::LINE, ..info.clone() })
+                .collect();
+            groups.push(lines);
+        }
 
-        Self {
-            device: device.clone(),
-            extent,
-            descriptor,
-            renderpass,
-            clear_values: vec![
-                vk::ClearValue { color: vk::ClearColorValue {float32: [0.2, 0.3, 0.9, 1.0]} },
-                vk::ClearValue { color: vk::ClearColorValue {float32: [0.0, 0.0, 0.0, 0.0]} },
-            ],
+        let mut pipelines = vec![];
+        for group in groups {
+            match create_graphics_pipeline(device.clone(), group, pipeline_layout, renderpass) {
+                Ok(mut group) => pipelines.append(&mut group),
+                Err(err) => {
+                    for pipeline in pipelines {
+                        device.destroy_pipeline(pipeline, None);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        let wireframe = wireframe.then(|| <[vk::Pipeline; 3]>::try_from(pipelines.split_off(6))
+            .expect("One wireframe pipeline per terrain pipeline"));
+        let [terrain, transparent, translucent_fluid, chunk_border, skybox, debug] = <[vk::Pipeline; 6]>::try_from(pipelines)
+            .expect("One pipeline per pipeline info");
 
-            terrain_pipeline: graphics_pipelines[0],
-            transparent_pipeline: graphics_pipelines[1],
-            translucent_fluid_pipeline: graphics_pipelines[2],
-            terrain_ivbo: IndexedBuffer::new(device.clone()),
-            transparent_ivbo: IndexedBuffer::new(device.clone()),
-            translucent_fluid_ivbo: IndexedBuffer::new(device.clone()),
+        Ok(ChunkPipelines { skybox, terrain, transparent, translucent_fluid, chunk_border, debug, wireframe })
+    }
 
-            // TODO: EGUI debug pipeline extension
-            debug_scissors: None,
-            debug_pipeline: debug_graphics_pipeline[0],
-            debug_ivbo: IndexedBuffer::new(device.clone()),
+    // recompiles the shaders into new pipelines, the current pipelines are kept if any of them fails
+    pub(crate) unsafe fn recreate_pipelines(&mut self) -> Result<(), String> {
+        let pipelines = Self::create_pipelines(self.device.clone(), self.descriptor.pipeline_layout(), self.renderpass,
+                                               self.texture_layout, self.fill_mode_non_solid, self.samples)?;
 
-            vbo: None, ibo: None
+        // the current pipelines may still be used by the frame in flight
+        self.device.device_wait_idle().unwrap();
+        self.replace_pipelines(pipelines);
+        Ok(())
+    }
+
+    // once no frame in flight uses the current pipelines
+    unsafe fn replace_pipelines(&mut self, pipelines: ChunkPipelines) {
+        self.destroy_pipelines();
+
+        self.skybox_pipeline = pipelines.skybox;
+        self.terrain_pipeline = pipelines.terrain;
+        self.transparent_pipeline = pipelines.transparent;
+        self.translucent_fluid_pipeline = pipelines.translucent_fluid;
+        self.chunk_border_pipeline = pipelines.chunk_border;
+        self.debug_pipeline = pipelines.debug;
+        self.wireframe_pipelines = pipelines.wireframe;
+    }
+
+    unsafe fn destroy_pipelines(&self) {
+        self.device.destroy_pipeline(self.debug_pipeline, None);
+        self.device.destroy_pipeline(self.skybox_pipeline, None);
+        self.device.destroy_pipeline(self.terrain_pipeline, None);
+        self.device.destroy_pipeline(self.transparent_pipeline, None);
+        self.device.destroy_pipeline(self.translucent_fluid_pipeline, None);
+        self.device.destroy_pipeline(self.chunk_border_pipeline, None);
+        for pipeline in self.wireframe_pipelines.into_iter().flatten() {
+            self.device.destroy_pipeline(pipeline, None);
+        }
+    }
+
+    // the depth pyramid is only reduced from a single sampled depth
+    fn occlusion(&self) -> Option<&OcclusionCuller> {
+        self.occlusion.as_ref().filter(|_| self.samples == vk::SampleCountFlags::TYPE_1)
+    }
+
+    // the terrain, transparent and translucent fluid pipelines to draw with
+    fn terrain_pipelines(&self) -> [vk::Pipeline; 3] {
+        match self.wireframe_pipelines {
+            Some(wireframe) if self.wireframe => wireframe,
+            _ => [self.terrain_pipeline, self.transparent_pipeline, self.translucent_fluid_pipeline],
         }
     }
 }
@@ -214,8 +534,11 @@ impl Shader for ChunkRasterizer {
     }
 
     fn attachments(&self) -> Vec<FBAttachmentRef> {
+        if self.samples != vk::SampleCountFlags::TYPE_1 {
+            return vec![FBAttachmentRef::MultisampledColor(self.samples), FBAttachmentRef::MultisampledDepth(self.samples)];
+        }
         vec![  // TODO: EGUI debug extension
-            FBAttachmentRef::Depth,
+            if self.occlusion.is_some() { FBAttachmentRef::SampledDepth } else { FBAttachmentRef::Depth },
         ]
     }
 
@@ -223,11 +546,18 @@ impl Shader for ChunkRasterizer {
         for render_data in descriptor_buffers {
             match render_data {
                 RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::CameraViewProjection) => {
+                    if let Some(occlusion) = &mut self.occlusion {
+                        occlusion.write_camera(buf.clone());
+                    }
                     self.descriptor.write_buffer(0, 0, buf);
                 },
                 RenderData::InitialDescriptorImage(img, RenderDataPurpose::BlockTextures) => {
                     self.descriptor.write_image(0, 1, img);
                 },
+                RenderData::InitialDescriptorImage(img, RenderDataPurpose::Skybox) => {
+                    self.descriptor.write_image(0, 2, img);
+                    self.skybox = true;
+                },
                 RenderData::InitialDescriptorImage(img, RenderDataPurpose::DebugUI) => {
                     // TODO: EGUI debug extension
                     self.descriptor.write_image(1, 0, img);  // egui debug ui textures
@@ -235,9 +565,20 @@ impl Shader for ChunkRasterizer {
                 RenderData::InitialDescriptorImage(img, RenderDataPurpose::PresentationInpAttachment) => {
                     self.descriptor.write_image(1, 1, img);
                 }
+                RenderData::InitialDescriptorImage(img, RenderDataPurpose::DepthAttachment) => {
+                    if let Some(occlusion) = &mut self.occlusion {
+                        occlusion.resize(self.extent, img[0].image_view);
+                    }
+                }
                 RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::Time) => {
                     self.descriptor.write_buffer(2, 0, buf);
                 }
+                RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::FogParams) => {
+                    self.descriptor.write_buffer(0, 3, buf);
+                }
+                RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::SkyParams) => {
+                    self.descriptor.write_buffer(0, 4, buf);
+                }
                 _ => {},
             }
         }
@@ -249,30 +590,52 @@ impl Shader for ChunkRasterizer {
 
     fn recreate_buffer(&mut self, render_data: RenderData) {
         match render_data {
-            RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::TerrainOpaque) => unsafe {
-                println!("RECREATE [OPAQUE/DEFAULT] VBO");
-                self.terrain_ivbo.recreate_vbo([buf], mem);
+            RenderData::RecreateChunkBuffer(range, buf, RenderDataPurpose::TerrainOpaque) => {
+                log::trace!(target: MTXG_RENDER_LOG, "RECREATE [OPAQUE/DEFAULT] CHUNK {}", range.mesh_id);
+                self.terrain_chunks.insert(range, buf);
             }
-            RenderData::RecreateIndexBuffer(buf, mem, len, RenderDataPurpose::TerrainOpaque) => unsafe {
-                println!("RECREATE [OPAQUE/DEFAULT] IBO");
-                self.terrain_ivbo.recreate_ibo(buf, mem, len);
+            RenderData::RecreateChunkBuffer(range, buf, RenderDataPurpose::TerrainTransparent) => {
+                log::trace!(target: MTXG_RENDER_LOG, "RECREATE [TRANSPARENT] CHUNK {}", range.mesh_id);
+                self.transparent_chunks.insert(range, buf);
             }
-            RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::TerrainTransparent) => unsafe {
-                println!("RECREATE [TRANSPARENT] VBO");
-                self.transparent_ivbo.recreate_vbo([buf], mem);
+            RenderData::ClearChunkBuffers(mesh_ids, RenderDataPurpose::TerrainOpaque) => {
+                self.terrain_chunks.remove(&mesh_ids);
             }
-            RenderData::RecreateIndexBuffer(buf, mem, len, RenderDataPurpose::TerrainTransparent) => unsafe {
-                println!("RECREATE [TRANSPARENT] IBO");
-                self.transparent_ivbo.recreate_ibo(buf, mem, len);
+            RenderData::ClearChunkBuffers(mesh_ids, RenderDataPurpose::TerrainTransparent) => {
+                self.transparent_chunks.remove(&mesh_ids);
             }
             RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::TerrainTranslucent) => unsafe {
-                println!("RECREATE [TRANSLUCENT] VBO");
+                log::debug!(target: MTXG_RENDER_LOG, "RECREATE [TRANSLUCENT] VBO");
                 self.translucent_fluid_ivbo.recreate_vbo([buf], mem);
             }
             RenderData::RecreateIndexBuffer(buf, mem, len, RenderDataPurpose::TerrainTranslucent) => unsafe {
-                println!("RECREATE [TRANSLUCENT] IBO");
+                log::debug!(target: MTXG_RENDER_LOG, "RECREATE [TRANSLUCENT] IBO");
                 self.translucent_fluid_ivbo.recreate_ibo(buf, mem, len);
             }
+            RenderData::ClearBuffer(RenderDataPurpose::TerrainOpaque) => {
+                self.terrain_chunks.clear();
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::TerrainTransparent) => {
+                self.transparent_chunks.clear();
+            }
+            RenderData::SetDrawChunks(mesh_ids, RenderDataPurpose::TerrainOpaque) => {
+                self.terrain_chunks.set_drawn(mesh_ids);
+            }
+            RenderData::SetDrawChunks(mesh_ids, RenderDataPurpose::TerrainTransparent) => {
+                self.transparent_chunks.set_drawn(mesh_ids);
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::TerrainTranslucent) => unsafe {
+                self.translucent_fluid_ivbo.clear();
+            }
+            RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::DebugChunkBorders) => unsafe {
+                self.chunk_border_ivbo.recreate_vbo([buf], mem);
+            }
+            RenderData::RecreateIndexBuffer(buf, mem, len, RenderDataPurpose::DebugChunkBorders) => unsafe {
+                self.chunk_border_ivbo.recreate_ibo(buf, mem, len);
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::DebugChunkBorders) => unsafe {
+                self.chunk_border_ivbo.clear();
+            }
             // TODO: EGUI debug data extension
             RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::DebugUI) => unsafe {
                 // println!("RECREATE [DEBUG UI] VERTEX BUFFER");
@@ -282,13 +645,112 @@ impl Shader for ChunkRasterizer {
                 // println!("RECREATE [DEBUG UI] INDEX BUFFER");
                 self.debug_ivbo.recreate_ibo(buf, mem, len);
             }
-            RenderData::SetScissorDynamicState(scissor, RenderDataPurpose::DebugUI) => unsafe {
-                self.debug_scissors.replace([scissor]);
+            RenderData::ClearBuffer(RenderDataPurpose::DebugUI) => unsafe {
+                self.debug_ivbo.clear();
+            }
+            RenderData::SetUiDraws(draws, RenderDataPurpose::DebugUI) => {
+                self.debug_draws = draws;
+            }
+            RenderData::UpdateDescriptorImage(img, RenderDataPurpose::BlockTextures) => {
+                self.descriptor.defer_write_image(0, 1, img);
+            }
+            RenderData::UpdateDescriptorImage(img, RenderDataPurpose::DebugUI) => {
+                self.descriptor.defer_write_image(1, 0, img);
             }
             _ => {},
         }
     }
 
+    unsafe fn flush_descriptors(&mut self, frame_index: usize) {
+        self.descriptor.prepare_frame(frame_index);
+    }
+
+    unsafe fn collect_garbage(&mut self, frame_index: usize) {
+        self.translucent_fluid_ivbo.collect_garbage(frame_index);
+        self.chunk_border_ivbo.collect_garbage(frame_index);
+        self.debug_ivbo.collect_garbage(frame_index);
+    }
+
+    unsafe fn prepare_frame(&mut self, frame_index: usize) {
+        self.chunk_draw_commands.write(frame_index, &[&self.terrain_chunks, &self.transparent_chunks]);
+        let single_sampled = self.samples == vk::SampleCountFlags::TYPE_1;
+        if let Some(occlusion) = self.occlusion.as_mut().filter(|_| single_sampled) {
+            // tests only the chunks left by the frustum culling
+            let chunks = [Some(self.terrain_chunks.drawn_chunks()), Some(self.transparent_chunks.drawn_chunks())];
+            occlusion.prepare_frame(frame_index, chunks);
+        }
+    }
+
+    unsafe fn reload_shaders(&mut self) {
+        match self.recreate_pipelines() {
+            Ok(()) => log::info!(target: MTXG_LOG, "Reloaded the chunk shaders"),
+            Err(err) => log::error!(target: MTXG_LOG, "{err}\nKeeping the previous chunk shaders"),
+        }
+        if let Some(occlusion) = &mut self.occlusion {
+            match occlusion.recreate_pipelines() {
+                Ok(()) => log::info!(target: MTXG_LOG, "Reloaded the occlusion culling shaders"),
+                Err(err) => log::error!(target: MTXG_LOG, "{err}\nKeeping the previous occlusion culling shaders"),
+            }
+        }
+    }
+
+    fn set_wireframe(&mut self, enabled: bool) {
+        if enabled && self.wireframe_pipelines.is_none() {
+            log::warn!("Wireframe mode needs the fillModeNonSolid device feature, drawing the terrain filled");
+        }
+        self.wireframe = enabled;
+    }
+
+    fn set_occlusion_culling(&mut self, enabled: bool) {
+        match &mut self.occlusion {
+            Some(occlusion) => {
+                if enabled && self.samples != vk::SampleCountFlags::TYPE_1 {
+                    log::warn!("Occlusion culling is paused while the depth is multisampled (MSAA)");
+                }
+                occlusion.set_enabled(enabled);
+            }
+            None if enabled => log::warn!("Occlusion culling needs a sampled depth format, drawing every chunk in the frustum"),
+            None => {},
+        }
+    }
+
+    fn take_occlusion_stats(&mut self) -> Option<(usize, usize)> {
+        self.occlusion.as_mut().and_then(|occlusion| occlusion.take_stats())
+    }
+
+    unsafe fn set_msaa(&mut self, msaa: Msaa) -> bool {
+        let samples = msaa.sample_count(self.supported_samples);
+        log::info!("Drawing the terrain with {}x MSAA (requested {msaa:?})", samples.as_raw());
+        if samples == self.samples {
+            return false;
+        }
+
+        let renderpass = Self::create_renderpass(self.device.clone(), self.color_format, self.depth_format, self.presented, samples);
+        match Self::create_pipelines(self.device.clone(), self.descriptor.pipeline_layout(), renderpass, self.texture_layout,
+                                     self.fill_mode_non_solid, samples) {
+            Ok(pipelines) => {
+                // the current renderpass and pipelines may still be used by the frame in flight
+                self.device.device_wait_idle().unwrap();
+                self.replace_pipelines(pipelines);
+                self.device.destroy_render_pass(self.renderpass, None);
+                self.renderpass = renderpass;
+                self.samples = samples;
+                self.clear_values = Self::clear_values(samples);
+                true
+            }
+            Err(err) => {
+                self.device.destroy_render_pass(renderpass, None);
+                log::error!("{err}\nKeeping the previous sample count");
+                false
+            }
+        }
+    }
+
+    fn custom_render_data(&mut self, id: u64, render_data: RenderData) {
+        // only the core purposes are drawn by the chunk rasterizer
+        log::trace!(target: MTXG_RENDER_LOG, "Ignoring the render data of the custom purpose {id}: {render_data:?}");
+    }
+
     unsafe fn draw_command(&self, cmd_buf: vk::CommandBuffer, framebuffer: vk::Framebuffer) {
         let renderpass_info = vk::RenderPassBeginInfo::builder()
             .render_pass(self.renderpass)
@@ -297,6 +759,10 @@ impl Shader for ChunkRasterizer {
             .clear_values(&self.clear_values)
             .build();
 
+        if let Some(occlusion) = self.occlusion() {
+            occlusion.record_culling(cmd_buf);
+        }
+
         self.device.cmd_begin_render_pass(cmd_buf, &renderpass_info, vk::SubpassContents::INLINE);
 
         self.device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.descriptor.pipeline_layout(),
@@ -314,61 +780,81 @@ impl Shader for ChunkRasterizer {
         self.device.cmd_set_viewport(cmd_buf, 0, &viewports);
         self.device.cmd_set_scissor(cmd_buf, 0, &scissors);
 
+        // through the commands of the occlusion culling if it tests them, otherwise every chunk with the frustum culled
+        // ones drawn without instances
+        let draw_chunks = |chunks: &ChunkBuffers, ind: usize| {
+            match self.occlusion().and_then(|occlusion| occlusion.draw_commands(ind)) {
+                Some((commands, offset)) => chunks.draw_drawn(cmd_buf, commands, offset),
+                None => {
+                    let (commands, offset) = self.chunk_draw_commands.commands(ind);
+                    chunks.draw_all(cmd_buf, commands, offset);
+                }
+            }
+        };
+
+        let [terrain_pipeline, transparent_pipeline, translucent_fluid_pipeline] = self.terrain_pipelines();
         {
-            if let Some((terrain_vbo, terrain_ibo, ibo_len)) = self.terrain_ivbo.obtain_indexed_vbo() {
+            if self.skybox {
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.skybox_pipeline);
+                self.device.cmd_draw(cmd_buf, 3, 1, 0, 0);
+            }
+            if self.terrain_chunks.len() > 0 {
                 // opaque objects
-                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.terrain_pipeline);
-                self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &terrain_vbo, &VBOFS);
-                self.device.cmd_bind_index_buffer(cmd_buf, terrain_ibo, 0, vk::IndexType::UINT32);
-                self.device.cmd_draw_indexed(cmd_buf, ibo_len, 1, 0, 0, 0);
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, terrain_pipeline);
+                draw_chunks(&self.terrain_chunks, 0);
             }
-            if let Some((transparent_vbo, transparent_ibo, ibo_len)) = self.transparent_ivbo.obtain_indexed_vbo() {
+            if self.transparent_chunks.len() > 0 {
                 // transparent objects
-                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.transparent_pipeline);
-                self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &transparent_vbo, &VBOFS);
-                self.device.cmd_bind_index_buffer(cmd_buf, transparent_ibo, 0, vk::IndexType::UINT32);
-                self.device.cmd_draw_indexed(cmd_buf, ibo_len, 1, 0, 0, 0);
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, transparent_pipeline);
+                draw_chunks(&self.transparent_chunks, 1);
             }
             if let Some((translucent_fluid_vbo, translucent_fluid_ibo, ibo_len)) = self.translucent_fluid_ivbo.obtain_indexed_vbo() {
                 // translucent objects
-                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.translucent_fluid_pipeline);
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, translucent_fluid_pipeline);
                 self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &translucent_fluid_vbo, &VBOFS);
                 self.device.cmd_bind_index_buffer(cmd_buf, translucent_fluid_ibo, 0, vk::IndexType::UINT32);
                 self.device.cmd_draw_indexed(cmd_buf, ibo_len, 1, 0, 0, 0);
             }
+            if let Some((border_vbo, border_ibo, ibo_len)) = self.chunk_border_ivbo.obtain_indexed_vbo() {
+                // chunk border lines
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.chunk_border_pipeline);
+                self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &border_vbo, &VBOFS);
+                self.device.cmd_bind_index_buffer(cmd_buf, border_ibo, 0, vk::IndexType::UINT32);
+                self.device.cmd_draw_indexed(cmd_buf, ibo_len, 1, 0, 0, 0);
+            }
         }
 
         // TODO: EGUI debug draw extension
         self.device.cmd_next_subpass(cmd_buf, vk::SubpassContents::INLINE);
 
         {
-            if let Some(scissors) = self.debug_scissors {
-                self.device.cmd_set_scissor(cmd_buf, 0, &scissors);
-            }
-
-            if let Some((ui_vbo, ui_ibo, ibo_len)) = self.debug_ivbo.obtain_indexed_vbo() {
+            if let Some((ui_vbo, ui_ibo, _)) = self.debug_ivbo.obtain_indexed_vbo() {
                 self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.debug_pipeline);
                 self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &ui_vbo, &VBOFS);
                 self.device.cmd_bind_index_buffer(cmd_buf, ui_ibo, 0, vk::IndexType::UINT32);
-                self.device.cmd_draw_indexed(cmd_buf, ibo_len, 1, 0, 0, 0);
+                record_ui_draws(&self.device, cmd_buf, &self.debug_draws);
             }
         }
 
         self.device.cmd_end_render_pass(cmd_buf);
+
+        if let Some(occlusion) = self.occlusion() {
+            occlusion.record_pyramid(cmd_buf);
+        }
     }
 
     unsafe fn destroy(&self) {
         // TODO: EGUI debug extension
         self.debug_ivbo.destroy();
-        self.device.destroy_pipeline(self.debug_pipeline, None);
 
-        self.terrain_ivbo.destroy();
-        self.transparent_ivbo.destroy();
+        self.chunk_draw_commands.destroy();
         self.translucent_fluid_ivbo.destroy();
+        self.chunk_border_ivbo.destroy();
 
-        self.device.destroy_pipeline(self.terrain_pipeline, None);
-        self.device.destroy_pipeline(self.transparent_pipeline, None);
-        self.device.destroy_pipeline(self.translucent_fluid_pipeline, None);
+        self.destroy_pipelines();
+        if let Some(occlusion) = &self.occlusion {
+            occlusion.destroy();
+        }
 
         self.descriptor.destroy();
         self.device.destroy_render_pass(self.renderpass, None);
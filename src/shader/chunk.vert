@@ -5,15 +5,33 @@ layout(set = 0, binding = 0) uniform UniformBufferObject {
     mat4 proj;
 } mvp;
 
+layout(set = 2, binding = 0) uniform TimeObject {
+    float time;
+};
+
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec2 uv;
 layout(location = 2) in float ind;
+layout(location = 3) in vec2 anim;  // frame count, frame interval
+layout(location = 4) in float ao;  // occlusion level from 0 to 3
+layout(location = 5) in float light;  // from 0 (dark) to 1 (fully lit)
+layout(location = 6) in vec3 normal;  // outward from the face
 
 layout(location = 0) out float out_ind;
 layout(location = 1) out vec2 tex_coord;
+layout(location = 2) out float out_ao;
+layout(location = 3) out float out_dist;  // from the camera, for the fog
+layout(location = 4) out float out_light;
+layout(location = 5) out vec3 out_normal;
 
 void main() {
-    gl_Position = mvp.proj * mvp.view * vec4(position, 1.0);
+    vec4 view_pos = mvp.view * vec4(position, 1.0);
+    gl_Position = mvp.proj * view_pos;
+    out_dist = length(view_pos.xyz);
     tex_coord = uv;
-    out_ind = ind;
+    out_ao = ao;
+    out_light = light;
+    out_normal = normal;
+    // animation frames are in the layers following the first frame
+    out_ind = ind + mod(floor(time/anim.y), anim.x);
 }
@@ -0,0 +1,26 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in float txtr_ind;  // unused, the uv is already within the atlas
+layout(location = 1) in vec2 tex_coord;
+layout(location = 2) in float ao;
+layout(location = 3) in float dist;
+layout(location = 4) in float light;
+layout(location = 5) in vec3 normal;
+
+layout(location = 0) out vec4 out_color;  // renderpass: color attachment #0
+
+layout(set = 0, binding = 1) uniform sampler2D tex_sampler;
+
+#include "lighting.glsl"
+
+void main() {
+    out_color = texture(tex_sampler, tex_coord);
+    // each occluding block darkens the vertex further
+    out_color.rgb *= 1.0 - ao*0.2;
+    out_color.rgb *= light;
+    // with the normal flipped for the back of the double sided faces
+    out_color.rgb *= sun_light(gl_FrontFacing ? normal : -normal);
+
+    out_color.rgb = apply_fog(out_color.rgb, dist);
+}
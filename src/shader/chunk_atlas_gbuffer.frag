@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in float txtr_ind;  // unused, the uv is already within the atlas
+layout(location = 1) in vec2 tex_coord;
+layout(location = 2) in float ao;
+layout(location = 4) in float light;
+layout(location = 5) in vec3 normal;
+
+layout(location = 0) out vec4 out_albedo;  // renderpass: G-buffer attachment #0
+layout(location = 1) out vec4 out_normal;  // renderpass: G-buffer attachment #1
+
+layout(set = 0, binding = 1) uniform sampler2D tex_sampler;
+
+// the unlit surface, shaded in the lighting subpass
+void main() {
+    vec4 color = texture(tex_sampler, tex_coord);
+    // the blocks behind the fully transparent texels stay in the G-buffer
+    if (color.a == 0.0) {
+        discard;
+    }
+    // each occluding block darkens the vertex further, the light level of the block is kept for the sky light
+    out_albedo = vec4(color.rgb*(1.0 - ao*0.2), light);
+    // flipped for the back of the double sided faces
+    out_normal = vec4(normalize(gl_FrontFacing ? normal : -normal), 0.0);
+}
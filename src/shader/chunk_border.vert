@@ -0,0 +1,16 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 color;
+
+layout(location = 0) out vec3 out_color;
+
+void main() {
+    gl_Position = mvp.proj * mvp.view * vec4(position, 1.0);
+    out_color = color;
+}
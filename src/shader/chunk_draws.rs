@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::{ffi, mem, slice};
+use std::rc::Rc;
+use ash::{Device, vk};
+use crate::component::terrain::chunk_mesh::ChunkRange;
+use crate::handler::{MAX_FRAMES_IN_FLIGHT, VulkanInstance};
+use crate::util::{allocate_buffer, grown_capacity};
+
+
+const DRAW_STRIDE: usize = mem::size_of::<vk::DrawIndexedIndirectCommand>();
+const INITIAL_DRAWS: usize = 1024;  // commands per frame in flight until more chunks are loaded
+
+
+// draws per indirect draw of the chunks, 1 unless the device supports multiDrawIndirect (enabled along with it)
+pub(crate) unsafe fn max_chunk_draws(vi: &VulkanInstance) -> u32 {
+    if vi.get_physical_device_features().multi_draw_indirect == vk::TRUE {
+        vi.get_physical_device_properties().limits.max_draw_indirect_count.max(1)
+    } else {
+        1
+    }
+}
+
+// the persistent chunks of a purpose, each ranged out of a buffer shared with other chunks (the vertices of the chunk
+// followed by its indices), drawn through indirect draws so the culling only changes their instance counts instead of
+// uploading them again
+// the uploader owns the buffers, and only reuses the ranges of the removed chunks once the frames drawing them complete
+pub(crate) struct ChunkBuffers {
+    device: Rc<Device>,
+    chunks: Vec<(ChunkRange, vk::Buffer)>,  // in the order of their draw commands, the chunks of a buffer consecutive
+    drawn: Option<HashSet<u64>>,  // mesh ids of the chunks to draw, or all of them if None
+    max_draws: u32,  // per indirect draw
+}
+
+impl ChunkBuffers {
+    pub(crate) fn new(device: Rc<Device>, max_draws: u32) -> Self {
+        Self { device, chunks: Vec::new(), drawn: None, max_draws }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // after the other chunks of its buffer, so they are drawn by the same indirect draw
+    pub(crate) fn insert(&mut self, range: ChunkRange, buf: vk::Buffer) {
+        let ind = self.chunks.iter().rposition(|(_, chunk_buf)| *chunk_buf == buf).map_or(self.chunks.len(), |ind| ind+1);
+        self.chunks.insert(ind, (range, buf));
+    }
+
+    pub(crate) fn remove(&mut self, mesh_ids: &[u64]) {
+        let mesh_ids: HashSet<&u64> = mesh_ids.iter().collect();
+        self.chunks.retain(|(range, _)| !mesh_ids.contains(&range.mesh_id));
+    }
+
+    // releases every chunk, so nothing gets drawn until new chunks are inserted
+    pub(crate) fn clear(&mut self) {
+        self.chunks.clear();
+        self.drawn = None;
+    }
+
+    pub(crate) fn set_drawn(&mut self, mesh_ids: Vec<u64>) {
+        self.drawn.replace(mesh_ids.into_iter().collect());
+    }
+
+    fn is_drawn(&self, range: &ChunkRange) -> bool {
+        self.drawn.as_ref().is_none_or(|drawn| drawn.contains(&range.mesh_id))
+    }
+
+    // in the order they are drawn with draw_drawn
+    pub(crate) fn drawn_chunks(&self) -> Vec<&ChunkRange> {
+        self.chunks.iter()
+            .map(|(range, _)| range)
+            .filter(|range| self.is_drawn(range))
+            .collect()
+    }
+
+    // a command per chunk, with no instances for the chunks not drawn
+    fn write_commands(&self, commands: &mut [vk::DrawIndexedIndirectCommand]) {
+        for ((range, _), command) in self.chunks.iter().zip(commands) {
+            *command = vk::DrawIndexedIndirectCommand {
+                index_count: range.inds.len() as u32,
+                instance_count: self.is_drawn(range) as u32,
+                first_index: range.inds.start,
+                vertex_offset: range.verts.start as i32,
+                first_instance: 0,
+            };
+        }
+    }
+
+    // draws every chunk with the bound pipeline, through the consecutive commands from the offset
+    pub(crate) unsafe fn draw_all(&self, cmd_buf: vk::CommandBuffer, commands: vk::Buffer, offset: vk::DeviceSize) {
+        self.draw(cmd_buf, self.chunks.iter(), commands, offset);
+    }
+
+    // draws only the drawn chunks (e.g. through the commands of the occlusion culling)
+    pub(crate) unsafe fn draw_drawn(&self, cmd_buf: vk::CommandBuffer, commands: vk::Buffer, offset: vk::DeviceSize) {
+        self.draw(cmd_buf, self.chunks.iter().filter(|(range, _)| self.is_drawn(range)), commands, offset);
+    }
+
+    // binds each buffer once, the vertex and index offsets of the chunks within it are in their draw commands
+    unsafe fn draw<'c>(&self, cmd_buf: vk::CommandBuffer, chunks: impl Iterator<Item = &'c (ChunkRange, vk::Buffer)>,
+                       commands: vk::Buffer, offset: vk::DeviceSize) {
+        for (buf, draws) in draw_batches(chunks.map(|(_, buf)| *buf), self.max_draws) {
+            self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &[buf], &[0]);
+            self.device.cmd_bind_index_buffer(cmd_buf, buf, 0, vk::IndexType::UINT32);
+            for (first, count) in draws {
+                self.device.cmd_draw_indexed_indirect(cmd_buf, commands, offset+(first*DRAW_STRIDE) as vk::DeviceSize,
+                                                      count, DRAW_STRIDE as u32);
+            }
+        }
+    }
+}
+
+// the consecutive draws of each buffer, as indirect draws of the first command and the draw count (at most max_draws)
+fn draw_batches(bufs: impl Iterator<Item = vk::Buffer>, max_draws: u32) -> Vec<(vk::Buffer, Vec<(usize, u32)>)> {
+    let mut batches: Vec<(vk::Buffer, Vec<(usize, u32)>)> = Vec::new();
+    for (draw, buf) in bufs.enumerate() {
+        match batches.last_mut() {
+            Some((batch_buf, draws)) if *batch_buf == buf => match draws.last_mut() {
+                Some((_, count)) if *count < max_draws => *count += 1,
+                _ => draws.push((draw, 1)),
+            },
+            _ => batches.push((buf, vec![(draw, 1)])),
+        }
+    }
+    batches
+}
+
+
+// host visible indirect draw commands of the chunks of every purpose, per frame in flight
+pub(crate) struct ChunkDrawCommands {
+    vi: Rc<VulkanInstance>,
+    device: Rc<Device>,
+    slots: Vec<(vk::Buffer, vk::DeviceMemory, *mut ffi::c_void, usize)>,  // along with their capacity in commands
+    frame: usize,  // slot of the frame being recorded
+    firsts: Vec<usize>,  // first command of each purpose in the slot
+}
+
+impl ChunkDrawCommands {
+    pub(crate) unsafe fn new(vi: Rc<VulkanInstance>, device: Rc<Device>) -> Self {
+        let slots = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| Self::allocate(vi.clone(), device.clone(), INITIAL_DRAWS))
+            .collect();
+        Self { vi, device, slots, frame: 0, firsts: Vec::new() }
+    }
+
+    unsafe fn allocate(vi: Rc<VulkanInstance>, device: Rc<Device>, capacity: usize) -> (vk::Buffer, vk::DeviceMemory, *mut ffi::c_void, usize) {
+        let (buf, mem, size) = allocate_buffer(
+            vi, device.clone(), (capacity*DRAW_STRIDE) as vk::DeviceSize, vk::BufferUsageFlags::INDIRECT_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+        );
+        let ptr = device.map_memory(mem, 0, size, vk::MemoryMapFlags::empty()).unwrap();
+        (buf, mem, ptr, capacity)
+    }
+
+    // once the fence of the frame signaled, so its slot can be written again (or grown)
+    pub(crate) unsafe fn write(&mut self, frame_index: usize, purposes: &[&ChunkBuffers]) {
+        self.frame = frame_index;
+        let count = purposes.iter().map(|chunks| chunks.len()).sum::<usize>();
+        if count > self.slots[frame_index].3 {
+            let (buf, mem, _, capacity) = self.slots[frame_index];
+            self.device.destroy_buffer(buf, None);
+            self.device.free_memory(mem, None);
+            let capacity = grown_capacity(capacity as vk::DeviceSize, count as vk::DeviceSize) as usize;
+            self.slots[frame_index] = Self::allocate(self.vi.clone(), self.device.clone(), capacity);
+        }
+
+        let (_, _, ptr, _) = self.slots[frame_index];
+        let commands = slice::from_raw_parts_mut(ptr as *mut vk::DrawIndexedIndirectCommand, count);
+        self.firsts.clear();
+        let mut first = 0;
+        for chunks in purposes {
+            chunks.write_commands(&mut commands[first..first+chunks.len()]);
+            self.firsts.push(first);
+            first += chunks.len();
+        }
+    }
+
+    // buffer and offset of the first command of the purpose in the frame being recorded
+    pub(crate) fn commands(&self, ind: usize) -> (vk::Buffer, vk::DeviceSize) {
+        (self.slots[self.frame].0, (self.firsts.get(ind).copied().unwrap_or(0)*DRAW_STRIDE) as vk::DeviceSize)
+    }
+
+    pub(crate) unsafe fn destroy(&self) {
+        for (buf, mem, _, _) in &self.slots {
+            self.device.destroy_buffer(*buf, None);
+            self.device.free_memory(*mem, None);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+    use super::*;
+
+    #[test]
+    fn test_draw_batches_per_buffer() {
+        let [a, b] = [vk::Buffer::from_raw(1), vk::Buffer::from_raw(2)];
+
+        // a single draw per buffer with multiDrawIndirect
+        assert_eq!(draw_batches([a, a, a, b, b].into_iter(), u32::MAX), vec![(a, vec![(0, 3)]), (b, vec![(3, 2)])]);
+        // split at its draw count limit, or a draw per chunk without it
+        assert_eq!(draw_batches([a, a, a, b, b].into_iter(), 2), vec![(a, vec![(0, 2), (2, 1)]), (b, vec![(3, 2)])]);
+        assert_eq!(draw_batches([a, a, b].into_iter(), 1), vec![(a, vec![(0, 1), (1, 1)]), (b, vec![(2, 1)])]);
+        assert!(draw_batches(std::iter::empty(), 1).is_empty());
+    }
+}
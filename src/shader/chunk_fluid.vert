@@ -7,17 +7,39 @@ layout(set = 0, binding = 0) uniform UniformBufferObject {
 
 layout(set = 2, binding = 0) uniform TimeObject {
     float time;
+    float fluid_motion;  // 0 for still fluids
 };
 
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec2 uv;
 layout(location = 2) in float ind;
+layout(location = 3) in vec2 anim;  // frame count, frame interval
+layout(location = 5) in float light;  // from 0 (dark) to 1 (fully lit)
+layout(location = 6) in vec3 normal;  // outward from the face, the waves don't tilt it
 
 layout(location = 0) out float out_ind;
 layout(location = 1) out vec2 tex_coord;
+layout(location = 3) out float out_dist;  // from the camera, for the fog
+layout(location = 4) out float out_light;
+layout(location = 5) out vec3 out_normal;
+
+// sum of sines over the surface, with whole frequencies in time so the waves loop as the time wraps around at 2 pi
+// the amplitudes add up to less than the 0.1 gap above the fluid surface, so it never reaches into the block above
+float wave(vec3 pos) {
+    return sin(time + pos.x*0.8 + pos.z*0.3)*0.04 +
+        sin(2.0*time + pos.z*1.1 - pos.x*0.4)*0.025 +
+        sin(3.0*time + (pos.x + pos.z)*1.7)*0.015;
+}
 
 void main() {
-    gl_Position = mvp.proj * mvp.view * vec4(position + vec3(0.0, sin(time + position.x)*0.1, 0.0), 1.0);
+    // only the surface vertices (at 0.9 of the block height) move, the bottom of the fluid stays in place
+    float surface = step(0.5, fract(position.y));
+    vec4 view_pos = mvp.view * vec4(position + vec3(0.0, wave(position)*surface*fluid_motion, 0.0), 1.0);
+    gl_Position = mvp.proj * view_pos;
+    out_dist = length(view_pos.xyz);
     tex_coord = uv;
-    out_ind = ind;
+    out_light = light;
+    out_normal = normal;
+    // animation frames are in the layers following the first frame
+    out_ind = ind + mod(floor(time/anim.y), anim.x);
 }
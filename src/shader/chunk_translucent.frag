@@ -1,14 +1,32 @@
 #version 450
+#extension GL_GOOGLE_include_directive : require
 
 layout(location = 0) in float txtr_ind;
 layout(location = 1) in vec2 tex_coord;
+layout(location = 3) in float dist;
+layout(location = 4) in float light;
+layout(location = 5) in vec3 normal;
 
 layout(location = 0) out vec4 out_color;  // renderpass: color attachment #0
 
 layout(set = 0, binding = 1) uniform sampler2DArray tex_sampler;
 
+layout(set = 2, binding = 0) uniform TimeObject {
+    float time;
+    float fluid_motion;  // 0 for still fluids
+};
+
+#include "lighting.glsl"
+
 void main() {
-    out_color = texture(tex_sampler, vec3(tex_coord, txtr_ind));
+    // scrolls a whole texture over each loop of the time (wrapping at 2 pi) to fake the flow
+    vec2 flow = vec2(time/6.2831853, 0.0)*fluid_motion;
+    out_color = texture(tex_sampler, vec3(tex_coord + flow, txtr_ind));
+    out_color.rgb *= light;
+    // with the normal flipped for the back of the double sided faces
+    out_color.rgb *= sun_light(gl_FrontFacing ? normal : -normal);
+
+    out_color.rgb = apply_fog(out_color.rgb, dist);
 
     if(out_color.a == 0.00) {
         gl_FragDepth = 0.0;
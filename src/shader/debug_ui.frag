@@ -2,18 +2,29 @@
 
 layout(location = 0) in vec2 tex_coord;
 layout(location = 1) in vec3 in_color;
+layout(location = 2) flat in int txtr_slot;
 
 
 layout(location = 0) out vec4 out_color;  // renderpass: color attachment #0
 
-layout(set = 1, binding = 0) uniform sampler2D font_sampler;
+layout(set = 1, binding = 0) uniform sampler2D ui_samplers[4];  // MAX_UI_TEXTURES of the debug ui
 layout(input_attachment_index = 0, set = 1, binding = 1) uniform subpassInput inputColor;
 
+// indexed with constants, as indexing the sampler array dynamically needs a device feature
+vec4 sample_ui(vec2 uv) {
+    switch (txtr_slot) {
+        case 1: return texture(ui_samplers[1], uv);
+        case 2: return texture(ui_samplers[2], uv);
+        case 3: return texture(ui_samplers[3], uv);
+        default: return texture(ui_samplers[0], uv);
+    }
+}
+
 void main() {
 //    out_color = vec4(subpassLoad(inputColor).rgb, 1.0);
 //    out_color = vec4(1.0, 1.0, 0.0, 0.0);
 //    out_color = vec4(in_color, 1.0);
-    vec4 font_color = texture(font_sampler, tex_coord);
+    vec4 font_color = sample_ui(tex_coord);
     vec3 solid_color = in_color;
     vec3 ui_color = mix(solid_color.rgb, font_color.rgb, 1-font_color.a);
     out_color = vec4(ui_color, 0.8);
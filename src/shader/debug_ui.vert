@@ -6,10 +6,13 @@ layout(location = 2) in vec3 color;
 
 layout(location = 0) out vec2 tex_coord;
 layout(location = 1) out vec3 out_color;
+layout(location = 2) flat out int txtr_slot;
 
 void main() {
-    gl_Position = vec4(pos.x*.002-1.0, pos.y*.002*1.6-1.0, 0.0, 1.0);
-//    gl_Position = vec4(pos.x, pos.y, 0.0, 1.0);
+    // already converted from the egui points by the debug ui, as the surface size is only known there
+    gl_Position = vec4(pos.x, pos.y, 0.0, 1.0);
     tex_coord = uv;
     out_color = color;
+    // the first instance of the draw is the slot of the texture of its primitive
+    txtr_slot = gl_InstanceIndex;
 }
@@ -0,0 +1,638 @@
+use std::rc::Rc;
+use ash::{Device, vk};
+use crate::component::{RenderData, RenderDataPurpose};
+use crate::component::debug_ui::{UiDraw, MAX_UI_TEXTURES};
+use crate::component::texture::TextureLayout;
+use crate::framebuffer::FBAttachmentRef;
+use crate::handler::VulkanInstance;
+use crate::shader::chunk::{ChunkBorderVertex, ChunkVertex, EguiVertex};
+use crate::shader::chunk_draws::{ChunkBuffers, ChunkDrawCommands, max_chunk_draws};
+use crate::shader::{ColorBlendKind, DescriptorManager, Msaa, Shader, create_graphics_pipeline, record_ui_draws, StandardGraphicsPipelineInfo, VBOFS};
+use crate::debug::{MTXG_LOG, MTXG_RENDER_LOG};
+use crate::error::MatrixagonError;
+use crate::util::create_host_buffer;
+use matrixagon_util::{VulkanVertexState, create_renderpass, IndexedBuffer};
+
+
+const GBUFFER_ALBEDO_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;  // with the sky light level in alpha
+const GBUFFER_NORMAL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+
+// in the std430 layout of the lights of the deferred lighting shader, handed over as the PointLights storage buffer
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct PointLight {
+    pub(crate) position: [f32; 3],  // in the render space of the meshes (where z is negated)
+    pub(crate) radius: f32,  // in blox, nothing past it is lit
+    pub(crate) color: [f32; 3],  // linear
+    pub(crate) intensity: f32,
+}
+
+impl PointLight {
+    // bound until a component hands over its lights (see PointLightComponent), since the storage buffer can't be empty
+    pub(crate) const NONE: Self = Self { position: [0.0; 3], radius: 1.0, color: [0.0; 3], intensity: 0.0 };
+}
+
+
+// the pipelines of the deferred rasterizer, recreated together whenever the shaders are reloaded
+struct DeferredPipelines {
+    gbuffer: vk::Pipeline,  // the opaque and transparent chunks
+    translucent_fluid: vk::Pipeline,  // forward shaded over the lit terrain, since it's blended
+    chunk_border: vk::Pipeline,
+    skybox: vk::Pipeline,
+    lighting: vk::Pipeline,
+    debug: vk::Pipeline,
+}
+
+// draws the chunks into a G-buffer (albedo, normal and depth), lit in a second subpass by the sun and any amount of
+// point lights, instead of shading every fragment of the chunks against each light
+pub struct DeferredRasterizer {
+    device: Rc<Device>,
+
+    extent: vk::Extent2D,
+    descriptor: DescriptorManager,
+    renderpass: vk::RenderPass,
+    clear_values: Vec<vk::ClearValue>,
+    texture_layout: TextureLayout,  // of the fragment shaders, kept for reloading them
+
+    pipelines: DeferredPipelines,
+    skybox: bool,
+
+    terrain_chunks: ChunkBuffers,
+    transparent_chunks: ChunkBuffers,
+    chunk_draw_commands: ChunkDrawCommands,
+    translucent_fluid_ivbo: IndexedBuffer,
+    chunk_border_ivbo: IndexedBuffer,
+
+    debug_draws: Vec<UiDraw>,  // out of the debug ui buffers
+    debug_ivbo: IndexedBuffer,
+
+    no_lights: (vk::Buffer, vk::DeviceMemory),
+}
+
+impl DeferredRasterizer {
+    // presented is whether the color attachment is a swapchain image, otherwise it is an offscreen image copied out
+    // after the renderpass (e.g. headless)
+    pub(crate) unsafe fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, extent: vk::Extent2D, color_format: vk::Format,
+                             depth_format: vk::Format, texture_layout: TextureLayout, presented: bool)
+                             -> Result<Self, MatrixagonError> {
+        let max_draws = max_chunk_draws(&vi);
+        let mut descriptor = DescriptorManager::new(device.clone(), vec![
+            vec![  // set 0 for shader
+                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX),  // proj-view
+                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),  // textures
+                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),  // skybox cubemap
+                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT),  // fog
+                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT),  // sky
+            ],
+            vec![  // set 1 for ui, as with the chunk rasterizer
+                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT), // egui debug ui textures
+                (vk::DescriptorType::INPUT_ATTACHMENT, vk::ShaderStageFlags::FRAGMENT), // input attachment from previous
+            ],
+            vec![  // set 2 for animations
+                (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)  // time
+            ],
+            vec![  // set 3 for the lighting
+                (vk::DescriptorType::INPUT_ATTACHMENT, vk::ShaderStageFlags::FRAGMENT),  // albedo
+                (vk::DescriptorType::INPUT_ATTACHMENT, vk::ShaderStageFlags::FRAGMENT),  // normal
+                (vk::DescriptorType::INPUT_ATTACHMENT, vk::ShaderStageFlags::FRAGMENT),  // depth
+                (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),  // point lights
+            ],
+        ], &[(1, 0, MAX_UI_TEXTURES as u32)]);
+
+        let presentation_layout = if presented {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        } else {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        };
+        // the depth is only tested (and read) in the lighting subpass, so it's both an input and a depth attachment there
+        let renderpass = create_renderpass!{ [device];
+            Attachments {
+                presentation: {
+                    format: color_format, samples: TYPE_1,
+                    load: CLEAR, store: STORE,
+                    stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                    initial: UNDEFINED, final: (presentation_layout),
+                }
+                albedo: {
+                    format: GBUFFER_ALBEDO_FORMAT, samples: TYPE_1,
+                    load: CLEAR, store: DONT_CARE,
+                    stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                    initial: UNDEFINED, final: SHADER_READ_ONLY_OPTIMAL,
+                }
+                normal: {
+                    format: GBUFFER_NORMAL_FORMAT, samples: TYPE_1,
+                    load: CLEAR, store: DONT_CARE,
+                    stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                    initial: UNDEFINED, final: SHADER_READ_ONLY_OPTIMAL,
+                }
+                depth: {
+                    format: depth_format, samples: TYPE_1,
+                    load: CLEAR, store: DONT_CARE,
+                    stencil_load: DONT_CARE, stencil_store: DONT_CARE,
+                    initial: UNDEFINED, final: DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                }
+            }
+            Subpasses {
+                gbuffer: {
+                    input:,
+                    color: albedo~COLOR_ATTACHMENT_OPTIMAL normal~COLOR_ATTACHMENT_OPTIMAL,
+                    resolve:,
+                    preserve:,
+                    depth: depth~DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                }
+                lighting: {
+                    input: albedo~SHADER_READ_ONLY_OPTIMAL normal~SHADER_READ_ONLY_OPTIMAL depth~DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                    color: presentation~COLOR_ATTACHMENT_OPTIMAL,
+                    resolve:,
+                    preserve:,
+                    depth: depth~DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                }
+            }
+            Dependencies {
+                ->gbuffer: {
+                    src_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS,
+                    dst_stage:  COLOR_ATTACHMENT_OUTPUT | EARLY_FRAGMENT_TESTS,
+                    src_access: ,
+                    dst_access: COLOR_ATTACHMENT_WRITE | DEPTH_STENCIL_ATTACHMENT_WRITE,
+                }
+                ->lighting: {  // the presentation image is only written by the lighting subpass
+                    src_stage:  COLOR_ATTACHMENT_OUTPUT,
+                    dst_stage:  COLOR_ATTACHMENT_OUTPUT,
+                    src_access: ,
+                    dst_access: COLOR_ATTACHMENT_WRITE,
+                }
+                gbuffer->lighting: {
+                    src_stage:  COLOR_ATTACHMENT_OUTPUT | LATE_FRAGMENT_TESTS,
+                    dst_stage:  FRAGMENT_SHADER | EARLY_FRAGMENT_TESTS,
+                    src_access: COLOR_ATTACHMENT_WRITE | DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    dst_access: INPUT_ATTACHMENT_READ | DEPTH_STENCIL_ATTACHMENT_READ,
+                }
+            }
+        };
+
+        let pipelines = match Self::create_pipelines(device.clone(), descriptor.pipeline_layout(), renderpass, texture_layout) {
+            Ok(pipelines) => pipelines,
+            Err(err) => {
+                device.destroy_render_pass(renderpass, None);
+                descriptor.destroy();
+                return Err(MatrixagonError::ShaderCreation(err));
+            }
+        };
+
+        let (no_lights, no_lights_mem, _, no_lights_size) = create_host_buffer(
+            vi.clone(), device.clone(), &[PointLight::NONE], vk::BufferUsageFlags::STORAGE_BUFFER, true
+        );
+        descriptor.write_buffer(3, 3, vec![vk::DescriptorBufferInfo { buffer: no_lights, offset: 0, range: no_lights_size }]);
+
+        Ok(Self {
+            device: device.clone(),
+            extent,
+            descriptor,
+            renderpass,
+            clear_values: vec![
+                vk::ClearValue { color: vk::ClearColorValue {float32: [0.2, 0.3, 0.9, 1.0]} },
+                vk::ClearValue { color: vk::ClearColorValue {float32: [0.0, 0.0, 0.0, 0.0]} },
+                vk::ClearValue { color: vk::ClearColorValue {float32: [0.0, 0.0, 0.0, 0.0]} },
+                vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue {depth: 0.0, stencil: 0} },
+            ],
+            texture_layout,
+
+            pipelines,
+            skybox: false,
+
+            terrain_chunks: ChunkBuffers::new(device.clone(), max_draws),
+            transparent_chunks: ChunkBuffers::new(device.clone(), max_draws),
+            chunk_draw_commands: ChunkDrawCommands::new(vi, device.clone()),
+            translucent_fluid_ivbo: IndexedBuffer::pooled(device.clone()),
+            chunk_border_ivbo: IndexedBuffer::pooled(device.clone()),
+
+            debug_draws: Vec::new(),
+            debug_ivbo: IndexedBuffer::new(device.clone()),
+
+            no_lights: (no_lights, no_lights_mem),
+        })
+    }
+
+    unsafe fn create_pipelines(device: Rc<Device>, pipeline_layout: vk::PipelineLayout, renderpass: vk::RenderPass,
+                               texture_layout: TextureLayout) -> Result<DeferredPipelines, String> {
+        // the sampled block textures have to match the layout of the texture handler
+        let (gbuffer_frag, translucent_frag) = match texture_layout {
+            TextureLayout::Array => (
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_gbuffer.frag"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_translucent.frag"),
+            ),
+            TextureLayout::Atlas => (
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_atlas_gbuffer.frag"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_atlas_transparent.frag"),
+            ),
+        };
+
+        let groups = vec![
+            vec![
+                StandardGraphicsPipelineInfo {  // G-buffer pipeline, the transparent texels are discarded
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk.vert"), vk::ShaderStageFlags::VERTEX),
+                        (gbuffer_frag, vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: ChunkVertex::VERTEX_INPUT_STATE,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: true, depth_testing: true, depth_writing: true,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
+                    color_blend_attachment_state: vec![ColorBlendKind::disabled(), ColorBlendKind::disabled()],
+                    subpass_index: 0,
+                },
+                StandardGraphicsPipelineInfo {  // translucent pipeline, lit by the sun only
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_fluid.vert"), vk::ShaderStageFlags::VERTEX),
+                        (translucent_frag, vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: ChunkVertex::VERTEX_INPUT_STATE,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: true, depth_testing: true, depth_writing: false,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
+                    color_blend_attachment_state: vec![ColorBlendKind::transparent()],
+                    subpass_index: 1,
+                },
+            ],
+            vec![
+                StandardGraphicsPipelineInfo {  // chunk border line pipeline
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_border.vert"), vk::ShaderStageFlags::VERTEX),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk_border.frag"), vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: ChunkBorderVertex::VERTEX_INPUT_STATE,
+                    topology: vk::PrimitiveTopology::LINE_LIST,
+                    back_face_culling: false, depth_testing: true, depth_writing: false,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
+                    color_blend_attachment_state: vec![ColorBlendKind::disabled()],
+                    subpass_index: 1,
+                },
+            ],
+            // fullscreen triangles, the lighting leaves the sky drawn before it where the G-buffer is empty
+            vec![
+                StandardGraphicsPipelineInfo {  // skybox pipeline
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/skybox.vert"), vk::ShaderStageFlags::VERTEX),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/skybox.frag"), vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: vk::PipelineVertexInputStateCreateInfo::default(),
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: false, depth_testing: false, depth_writing: false,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
+                    color_blend_attachment_state: vec![ColorBlendKind::disabled()],
+                    subpass_index: 1,
+                },
+                StandardGraphicsPipelineInfo {  // lighting pipeline
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/deferred_lighting.vert"), vk::ShaderStageFlags::VERTEX),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/deferred_lighting.frag"), vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: vk::PipelineVertexInputStateCreateInfo::default(),
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: false, depth_testing: false, depth_writing: false,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
+                    color_blend_attachment_state: vec![ColorBlendKind::disabled()],
+                    subpass_index: 1,
+                },
+            ],
+            vec![
+                StandardGraphicsPipelineInfo {  // debug ui pipeline, over everything else
+                    shaders: vec![
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/debug_ui.vert"), vk::ShaderStageFlags::VERTEX),
+                        (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/debug_ui.frag"), vk::ShaderStageFlags::FRAGMENT),
+                    ],
+                    vertex_input_state: EguiVertex::VERTEX_INPUT_STATE,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    back_face_culling: false, depth_testing: false, depth_writing: false,
+                    depth_bias: None,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
+                    color_blend_attachment_state: vec![ColorBlendKind::transparent()],
+                    subpass_index: 1,
+                },
+            ],
+        ];
+
+        let mut pipelines = vec![];
+        for group in groups {
+            match create_graphics_pipeline(device.clone(), group, pipeline_layout, renderpass) {
+                Ok(mut group) => pipelines.append(&mut group),
+                Err(err) => {
+                    for pipeline in pipelines {
+                        device.destroy_pipeline(pipeline, None);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        let [gbuffer, translucent_fluid, chunk_border, skybox, lighting, debug] = <[vk::Pipeline; 6]>::try_from(pipelines)
+            .expect("One pipeline per pipeline info");
+
+        Ok(DeferredPipelines { gbuffer, translucent_fluid, chunk_border, skybox, lighting, debug })
+    }
+
+    unsafe fn destroy_pipelines(&self) {
+        let DeferredPipelines { gbuffer, translucent_fluid, chunk_border, skybox, lighting, debug } = self.pipelines;
+        for pipeline in [gbuffer, translucent_fluid, chunk_border, skybox, lighting, debug] {
+            self.device.destroy_pipeline(pipeline, None);
+        }
+    }
+}
+
+impl Shader for DeferredRasterizer {
+    fn renderpass(&self) -> vk::RenderPass {
+        self.renderpass
+    }
+
+    fn attachments(&self) -> Vec<FBAttachmentRef> {
+        vec![
+            FBAttachmentRef::GBuffer(GBUFFER_ALBEDO_FORMAT),
+            FBAttachmentRef::GBuffer(GBUFFER_NORMAL_FORMAT),
+            FBAttachmentRef::DepthInput,
+        ]
+    }
+
+    unsafe fn write_descriptors(&mut self, descriptor_buffers: Vec<RenderData>) {
+        for render_data in descriptor_buffers {
+            match render_data {
+                RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::CameraViewProjection) => {
+                    self.descriptor.write_buffer(0, 0, buf);
+                },
+                RenderData::InitialDescriptorImage(img, RenderDataPurpose::BlockTextures) => {
+                    self.descriptor.write_image(0, 1, img);
+                },
+                RenderData::InitialDescriptorImage(img, RenderDataPurpose::Skybox) => {
+                    self.descriptor.write_image(0, 2, img);
+                    self.skybox = true;
+                },
+                RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::FogParams) => {
+                    self.descriptor.write_buffer(0, 3, buf);
+                }
+                RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::SkyParams) => {
+                    self.descriptor.write_buffer(0, 4, buf);
+                }
+                RenderData::InitialDescriptorImage(img, RenderDataPurpose::DebugUI) => {
+                    self.descriptor.write_image(1, 0, img);
+                }
+                RenderData::InitialDescriptorImage(img, RenderDataPurpose::PresentationInpAttachment) => {
+                    self.descriptor.write_image(1, 1, img);
+                }
+                RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::Time) => {
+                    self.descriptor.write_buffer(2, 0, buf);
+                }
+                // in the order of the attachments (albedo, normal and depth)
+                RenderData::InitialDescriptorImage(img, RenderDataPurpose::GBufferInpAttachment(ind)) if ind < 3 => {
+                    self.descriptor.write_image(3, ind, img);
+                }
+                RenderData::InitialDescriptorBuffer(buf, RenderDataPurpose::PointLights) => {
+                    self.descriptor.write_buffer(3, 3, buf);
+                }
+                _ => {},
+            }
+        }
+    }
+
+    fn update_extent(&mut self, new_extent: vk::Extent2D) {
+        self.extent = new_extent;
+    }
+
+    fn recreate_buffer(&mut self, render_data: RenderData) {
+        match render_data {
+            RenderData::RecreateChunkBuffer(range, buf, RenderDataPurpose::TerrainOpaque) => {
+                log::trace!(target: MTXG_RENDER_LOG, "RECREATE [OPAQUE/DEFAULT] CHUNK {}", range.mesh_id);
+                self.terrain_chunks.insert(range, buf);
+            }
+            RenderData::RecreateChunkBuffer(range, buf, RenderDataPurpose::TerrainTransparent) => {
+                log::trace!(target: MTXG_RENDER_LOG, "RECREATE [TRANSPARENT] CHUNK {}", range.mesh_id);
+                self.transparent_chunks.insert(range, buf);
+            }
+            RenderData::ClearChunkBuffers(mesh_ids, RenderDataPurpose::TerrainOpaque) => {
+                self.terrain_chunks.remove(&mesh_ids);
+            }
+            RenderData::ClearChunkBuffers(mesh_ids, RenderDataPurpose::TerrainTransparent) => {
+                self.transparent_chunks.remove(&mesh_ids);
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::TerrainOpaque) => {
+                self.terrain_chunks.clear();
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::TerrainTransparent) => {
+                self.transparent_chunks.clear();
+            }
+            RenderData::SetDrawChunks(mesh_ids, RenderDataPurpose::TerrainOpaque) => {
+                self.terrain_chunks.set_drawn(mesh_ids);
+            }
+            RenderData::SetDrawChunks(mesh_ids, RenderDataPurpose::TerrainTransparent) => {
+                self.transparent_chunks.set_drawn(mesh_ids);
+            }
+            RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::TerrainTranslucent) => unsafe {
+                log::debug!(target: MTXG_RENDER_LOG, "RECREATE [TRANSLUCENT] VBO");
+                self.translucent_fluid_ivbo.recreate_vbo([buf], mem);
+            }
+            RenderData::RecreateIndexBuffer(buf, mem, len, RenderDataPurpose::TerrainTranslucent) => unsafe {
+                log::debug!(target: MTXG_RENDER_LOG, "RECREATE [TRANSLUCENT] IBO");
+                self.translucent_fluid_ivbo.recreate_ibo(buf, mem, len);
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::TerrainTranslucent) => unsafe {
+                self.translucent_fluid_ivbo.clear();
+            }
+            RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::DebugChunkBorders) => unsafe {
+                self.chunk_border_ivbo.recreate_vbo([buf], mem);
+            }
+            RenderData::RecreateIndexBuffer(buf, mem, len, RenderDataPurpose::DebugChunkBorders) => unsafe {
+                self.chunk_border_ivbo.recreate_ibo(buf, mem, len);
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::DebugChunkBorders) => unsafe {
+                self.chunk_border_ivbo.clear();
+            }
+            RenderData::RecreateVertexBuffer(buf, mem, RenderDataPurpose::DebugUI) => unsafe {
+                self.debug_ivbo.recreate_vbo([buf], mem);
+            }
+            RenderData::RecreateIndexBuffer(buf, mem, len, RenderDataPurpose::DebugUI) => unsafe {
+                self.debug_ivbo.recreate_ibo(buf, mem, len);
+            }
+            RenderData::ClearBuffer(RenderDataPurpose::DebugUI) => unsafe {
+                self.debug_ivbo.clear();
+            }
+            RenderData::SetUiDraws(draws, RenderDataPurpose::DebugUI) => {
+                self.debug_draws = draws;
+            }
+            RenderData::UpdateDescriptorImage(img, RenderDataPurpose::BlockTextures) => {
+                self.descriptor.defer_write_image(0, 1, img);
+            }
+            RenderData::UpdateDescriptorImage(img, RenderDataPurpose::DebugUI) => {
+                self.descriptor.defer_write_image(1, 0, img);
+            }
+            _ => {},
+        }
+    }
+
+    unsafe fn flush_descriptors(&mut self, frame_index: usize) {
+        self.descriptor.prepare_frame(frame_index);
+    }
+
+    unsafe fn collect_garbage(&mut self, frame_index: usize) {
+        self.translucent_fluid_ivbo.collect_garbage(frame_index);
+        self.chunk_border_ivbo.collect_garbage(frame_index);
+        self.debug_ivbo.collect_garbage(frame_index);
+    }
+
+    unsafe fn prepare_frame(&mut self, frame_index: usize) {
+        self.chunk_draw_commands.write(frame_index, &[&self.terrain_chunks, &self.transparent_chunks]);
+    }
+
+    unsafe fn reload_shaders(&mut self) {
+        match Self::create_pipelines(self.device.clone(), self.descriptor.pipeline_layout(), self.renderpass, self.texture_layout) {
+            Ok(pipelines) => {
+                // the current pipelines may still be used by the frame in flight
+                self.device.device_wait_idle().unwrap();
+                self.destroy_pipelines();
+                self.pipelines = pipelines;
+                log::info!(target: MTXG_LOG, "Reloaded the deferred shaders");
+            }
+            Err(err) => log::error!(target: MTXG_LOG, "{err}\nKeeping the previous deferred shaders"),
+        }
+    }
+
+    fn set_wireframe(&mut self, enabled: bool) {
+        if enabled {
+            log::warn!("Wireframe mode isn't supported by the deferred shading, drawing the terrain filled");
+        }
+    }
+
+    fn set_occlusion_culling(&mut self, enabled: bool) {
+        if enabled {
+            log::warn!("Occlusion culling isn't supported by the deferred shading, drawing every chunk in the frustum");
+        }
+    }
+
+    fn take_occlusion_stats(&mut self) -> Option<(usize, usize)> {
+        None
+    }
+
+    unsafe fn set_msaa(&mut self, msaa: Msaa) -> bool {
+        // the lighting would have to run per sample of the G-buffer
+        if msaa != Msaa::Off {
+            log::warn!("MSAA isn't supported by the deferred shading, drawing with a single sample");
+        }
+        false
+    }
+
+    fn custom_render_data(&mut self, id: u64, render_data: RenderData) {
+        log::trace!(target: MTXG_RENDER_LOG, "Ignoring the render data of the custom purpose {id}: {render_data:?}");
+    }
+
+    unsafe fn draw_command(&self, cmd_buf: vk::CommandBuffer, framebuffer: vk::Framebuffer) {
+        let renderpass_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.renderpass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D { offset: vk::Offset2D {x:0, y:0}, extent: self.extent})
+            .clear_values(&self.clear_values)
+            .build();
+
+        self.device.cmd_begin_render_pass(cmd_buf, &renderpass_info, vk::SubpassContents::INLINE);
+
+        self.device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.descriptor.pipeline_layout(),
+                                             0, &self.descriptor.descriptor_sets(&[0, 1, 2, 3]), &[]);
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.extent.width as f32,
+            height: self.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+        let scissors = [vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: self.extent }];
+        self.device.cmd_set_viewport(cmd_buf, 0, &viewports);
+        self.device.cmd_set_scissor(cmd_buf, 0, &scissors);
+
+        // G-buffer subpass
+        {
+            self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipelines.gbuffer);
+            for (ind, chunks) in [&self.terrain_chunks, &self.transparent_chunks].into_iter().enumerate() {
+                if chunks.len() > 0 {
+                    let (commands, offset) = self.chunk_draw_commands.commands(ind);
+                    chunks.draw_all(cmd_buf, commands, offset);
+                }
+            }
+        }
+
+        self.device.cmd_next_subpass(cmd_buf, vk::SubpassContents::INLINE);
+
+        // lighting subpass
+        {
+            if self.skybox {
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipelines.skybox);
+                self.device.cmd_draw(cmd_buf, 3, 1, 0, 0);
+            }
+            self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipelines.lighting);
+            self.device.cmd_draw(cmd_buf, 3, 1, 0, 0);
+
+            if let Some((translucent_fluid_vbo, translucent_fluid_ibo, ibo_len)) = self.translucent_fluid_ivbo.obtain_indexed_vbo() {
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipelines.translucent_fluid);
+                self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &translucent_fluid_vbo, &VBOFS);
+                self.device.cmd_bind_index_buffer(cmd_buf, translucent_fluid_ibo, 0, vk::IndexType::UINT32);
+                self.device.cmd_draw_indexed(cmd_buf, ibo_len, 1, 0, 0, 0);
+            }
+            if let Some((border_vbo, border_ibo, ibo_len)) = self.chunk_border_ivbo.obtain_indexed_vbo() {
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipelines.chunk_border);
+                self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &border_vbo, &VBOFS);
+                self.device.cmd_bind_index_buffer(cmd_buf, border_ibo, 0, vk::IndexType::UINT32);
+                self.device.cmd_draw_indexed(cmd_buf, ibo_len, 1, 0, 0, 0);
+            }
+
+            if let Some((ui_vbo, ui_ibo, _)) = self.debug_ivbo.obtain_indexed_vbo() {
+                self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipelines.debug);
+                self.device.cmd_bind_vertex_buffers(cmd_buf, 0, &ui_vbo, &VBOFS);
+                self.device.cmd_bind_index_buffer(cmd_buf, ui_ibo, 0, vk::IndexType::UINT32);
+                record_ui_draws(&self.device, cmd_buf, &self.debug_draws);
+            }
+        }
+
+        self.device.cmd_end_render_pass(cmd_buf);
+    }
+
+    unsafe fn destroy(&self) {
+        self.debug_ivbo.destroy();
+
+        self.chunk_draw_commands.destroy();
+        self.translucent_fluid_ivbo.destroy();
+        self.chunk_border_ivbo.destroy();
+
+        let (no_lights, no_lights_mem) = self.no_lights;
+        self.device.destroy_buffer(no_lights, None);
+        self.device.free_memory(no_lights_mem, None);
+
+        self.destroy_pipelines();
+        self.descriptor.destroy();
+        self.device.destroy_render_pass(self.renderpass, None);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_light_std430_layout() {
+        // a vec3 followed by a float packs into 16 bytes, with the array stride a multiple of a vec4
+        assert_eq!(std::mem::size_of::<PointLight>(), 32);
+        let light = PointLight { position: [1.0, 2.0, 3.0], radius: 8.0, color: [1.0, 0.6, 0.2], intensity: 2.0 };
+        let base = &light as *const PointLight as usize;
+        assert_eq!(&light.radius as *const f32 as usize - base, 12);
+        assert_eq!(light.color.as_ptr() as usize - base, 16);
+        assert_eq!(&light.intensity as *const f32 as usize - base, 28);
+    }
+}
@@ -0,0 +1,52 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec2 ndc;
+layout(location = 1) flat in mat4 inv_proj;
+layout(location = 5) flat in mat4 inv_view;
+
+layout(location = 0) out vec4 out_color;  // renderpass: color attachment #0
+
+layout(input_attachment_index = 0, set = 3, binding = 0) uniform subpassInput gbuffer_albedo;  // light level in alpha
+layout(input_attachment_index = 1, set = 3, binding = 1) uniform subpassInput gbuffer_normal;
+layout(input_attachment_index = 2, set = 3, binding = 2) uniform subpassInput gbuffer_depth;
+
+struct PointLight {
+    vec3 position;  // in the render space of the meshes
+    float radius;
+    vec3 color;
+    float intensity;
+};
+
+layout(std430, set = 3, binding = 3) readonly buffer PointLights {
+    PointLight lights[];
+};
+
+#include "lighting.glsl"
+
+void main() {
+    float depth = subpassLoad(gbuffer_depth).r;
+    // leaves the skybox where no chunk was drawn (at the far plane, depth 0 with the reversed depth)
+    if (depth == 0.0) {
+        discard;
+    }
+    vec4 albedo = subpassLoad(gbuffer_albedo);
+    vec3 normal = subpassLoad(gbuffer_normal).xyz;
+
+    vec4 view_pos = inv_proj * vec4(ndc, depth, 1.0);
+    view_pos /= view_pos.w;
+    vec3 pos = (inv_view * view_pos).xyz;
+
+    // the sky light reaching the block
+    vec3 color = albedo.rgb*albedo.a*sun_light(normal);
+    // each point light fades out towards its radius
+    for (int i = 0; i < lights.length(); i++) {
+        vec3 to_light = lights[i].position - pos;
+        float dist = length(to_light);
+        float falloff = max(1.0 - dist/lights[i].radius, 0.0);
+        float lambert = max(dot(normal, to_light/max(dist, 0.0001)), 0.0);
+        color += albedo.rgb*lights[i].color*lights[i].intensity*falloff*falloff*lambert;
+    }
+
+    out_color = vec4(apply_fog(color, length(view_pos.xyz)), 1.0);
+}
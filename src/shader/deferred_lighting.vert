@@ -0,0 +1,20 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+layout(location = 0) out vec2 ndc;
+layout(location = 1) flat out mat4 inv_proj;
+layout(location = 5) flat out mat4 inv_view;
+
+void main() {
+    // a single triangle covering the screen
+    ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2)*2.0 - 1.0;
+    gl_Position = vec4(ndc, 0.0, 1.0);
+
+    // inverted once per vertex rather than per fragment
+    inv_proj = inverse(mvp.proj);
+    inv_view = inverse(mvp.view);
+}
@@ -0,0 +1,26 @@
+// the fog and sky uniforms along with the sun lighting and the fog, included by the shaders drawing the terrain and the sky
+
+layout(set = 0, binding = 3) uniform FogObject {
+    vec4 color;
+    float density;
+    float start;
+    float end;
+} fog;
+
+layout(set = 0, binding = 4) uniform SkyObject {
+    vec3 sun_direction;  // towards the sun
+    float ambient;
+    vec3 tint;
+    float diffuse;
+} sky;
+
+// the sky light reaching the face, with the lambert term against the sun
+float sun_light(vec3 normal) {
+    return sky.ambient + sky.diffuse*max(dot(normalize(normal), sky.sun_direction), 0.0);
+}
+
+// exponential fog past its start, fully fogged from its end (hiding the chunks loading at the edge)
+vec3 apply_fog(vec3 color, float dist) {
+    float fog_amount = dist >= fog.end ? 1.0 : 1.0 - exp(-fog.density*max(dist - fog.start, 0.0));
+    return mix(color, fog.color.rgb, fog_amount);
+}
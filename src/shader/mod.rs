@@ -1,31 +1,156 @@
 pub mod chunk;
+pub mod chunk_draws;
+pub mod deferred;
+pub mod occlusion;
 
+use std::collections::hash_map::DefaultHasher;
 use std::ffi::CStr;
 use std::fs::File;
-use std::{fs, mem, process};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::{env, fs, mem, process};
 use std::rc::Rc;
 use ash::{Device, vk};
 use ash::util::read_spv;
 use ash::vk::{AccessFlags, ImageLayout, PipelineStageFlags, SubpassDependencyBuilder};
-use crate::component::{RenderData};
+use crate::component::{RenderData, RenderDataPurpose};
+use crate::component::debug_ui::UiDraw;
+use crate::component::texture::TextureLayout;
+use crate::debug::MTXG_LOG;
+use crate::error::MatrixagonError;
 use crate::framebuffer::FBAttachmentRef;
+use crate::handler::{enabled_features, MAX_FRAMES_IN_FLIGHT, VulkanInstance};
+use crate::shader::chunk::ChunkRasterizer;
+use crate::shader::deferred::DeferredRasterizer;
 
+// the components reach the shader only through the render data of their purposes, so any shader drawing those
+// purposes can be chosen when the app is initialized
+#[allow(clippy::missing_safety_doc)]
 pub trait Shader {
     fn renderpass(&self) -> vk::RenderPass;
     fn attachments(&self) -> Vec<FBAttachmentRef>;
     unsafe fn write_descriptors(&mut self, descriptor_buffers: Vec<RenderData>);
     fn update_extent(&mut self, new_extent: vk::Extent2D);
     fn recreate_buffer(&mut self, render_data: RenderData);
+    unsafe fn flush_descriptors(&mut self, frame_index: usize);  // once the fence of the frame in flight signaled
+    unsafe fn collect_garbage(&mut self, frame_index: usize);  // once the fence of the frame in flight signaled
+    unsafe fn prepare_frame(&mut self, frame_index: usize);  // right before the frame is recorded, after collect_garbage
+    unsafe fn reload_shaders(&mut self);  // keeps the current shaders if the new ones fail to compile
+    fn set_wireframe(&mut self, enabled: bool);  // draws the terrain as lines, if the device supports it
+    fn set_occlusion_culling(&mut self, enabled: bool);  // skips the chunks hidden behind others, if the device supports it
+    fn take_occlusion_stats(&mut self) -> Option<(usize, usize)>;  // chunks tested and drawn, whenever they changed
+    // recreates the renderpass and the pipelines with the sample count (lowered to the supported one), returning
+    // whether the attachments changed so the framebuffers have to be recreated along with it
+    unsafe fn set_msaa(&mut self, msaa: Msaa) -> bool;
+    // any render data (descriptors or buffers) of a custom purpose, so the shader can define its own buffers and images
+    fn custom_render_data(&mut self, id: u64, render_data: RenderData);
     unsafe fn draw_command(&self, cmd_buf: vk::CommandBuffer, framebuffer: vk::Framebuffer);
     unsafe fn destroy(&self);
 }
 
 
+// what the shader of the app is created with, once the device and the formats are chosen
+pub struct ShaderInit {
+    pub(crate) vi: Rc<VulkanInstance>,
+    pub device: Rc<Device>,
+    pub extent: vk::Extent2D,
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub texture_layout: TextureLayout,  // of the block textures
+    pub presented: bool,  // whether the color attachment is a swapchain image, otherwise it is copied out (e.g. headless)
+    pub msaa: Msaa,  // requested, lowered to the sample counts the device supports
+}
+
+impl ShaderInit {
+    pub fn memory_properties(&self) -> vk::PhysicalDeviceMemoryProperties {
+        unsafe { self.vi.get_physical_device_memory_properties() }
+    }
+
+    // the features enabled on the device, the ones the device supports out of sampler anisotropy, fill mode non solid
+    // and multi draw indirect
+    pub fn features(&self) -> vk::PhysicalDeviceFeatures {
+        enabled_features(unsafe { self.vi.get_physical_device_features() })
+    }
+}
+
+// multisampling levels that can be requested, in place of exposing the vulkan sample counts
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Msaa {
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Msaa {
+    // the highest sample count up to the requested one out of the supported ones (e.g. the framebuffer color and depth
+    // sample counts of the device), a single sample is always supported
+    pub(crate) fn sample_count(&self, supported: vk::SampleCountFlags) -> vk::SampleCountFlags {
+        let requested = match self {
+            Msaa::Off => vk::SampleCountFlags::TYPE_1,
+            Msaa::X2 => vk::SampleCountFlags::TYPE_2,
+            Msaa::X4 => vk::SampleCountFlags::TYPE_4,
+            Msaa::X8 => vk::SampleCountFlags::TYPE_8,
+        };
+        [vk::SampleCountFlags::TYPE_8, vk::SampleCountFlags::TYPE_4, vk::SampleCountFlags::TYPE_2].into_iter()
+            .find(|samples| samples.as_raw() <= requested.as_raw() && supported.contains(*samples))
+            .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+
+    // cycled through at runtime
+    pub(crate) fn next(&self) -> Self {
+        match self {
+            Msaa::Off => Msaa::X2,
+            Msaa::X2 => Msaa::X4,
+            Msaa::X4 => Msaa::X8,
+            Msaa::X8 => Msaa::Off,
+        }
+    }
+}
+
+// the chunk rasterizer, drawing the terrain along with the sky and the debug UI
+pub fn default_shader(init: ShaderInit) -> Result<Box<dyn Shader>, MatrixagonError> {
+    let shader = unsafe {
+        ChunkRasterizer::new(init.vi, init.device, init.extent, init.color_format, init.depth_format, init.texture_layout,
+                             init.presented, init.msaa)?
+    };
+    Ok(Box::new(shader))
+}
+
+// the G-buffer of the chunks lit by any amount of point lights (handed over through RenderDataPurpose::PointLights),
+// without the wireframe mode, the occlusion culling and MSAA
+pub fn deferred_shader(init: ShaderInit) -> Result<Box<dyn Shader>, MatrixagonError> {
+    let mut shader = unsafe {
+        DeferredRasterizer::new(init.vi, init.device, init.extent, init.color_format, init.depth_format, init.texture_layout,
+                                init.presented)?
+    };
+    unsafe { shader.set_msaa(init.msaa); }
+    Ok(Box::new(shader))
+}
+
+
+// hands the render data of the custom purposes to the shader, returning the rest for the core purposes
+pub(crate) fn forward_custom<S: Shader + ?Sized>(shader: &mut S, render_data: Vec<RenderData>) -> Vec<RenderData> {
+    render_data.into_iter()
+        .filter_map(|render_data| match render_data.purpose() {
+            RenderDataPurpose::Custom(id) => {
+                shader.custom_render_data(id, render_data);
+                None
+            }
+            _ => Some(render_data),
+        })
+        .collect()
+}
+
+
 const VBOFS: [vk::DeviceSize; 1] = [0 as vk::DeviceSize];  // vertex buffer offsets
 
 // C:/VulkanSDK/1.3.261.1/bin/glslc.exe src/shader/cube.frag -o src/shader/cube.frag.spv
 // glslc has an option to compile shader to human readable bytecode
 
+const GLSLC_ENV: &str = "GLSLC";  // path to the glslc executable, otherwise found through PATH
+const SHADER_CACHE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/target/shader_cache");
+
 
 #[non_exhaustive]
 struct ColorBlendKind;
@@ -57,27 +182,68 @@ impl ColorBlendKind {
 }
 
 
+// polygon offset applied to the fragment depth (e.g. for decals drawn on top of block faces),
+// with reverse-Z a positive bias moves the fragments toward the camera
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct DepthBias {
+    pub(crate) constant_factor: f32,
+    pub(crate) slope_factor: f32,
+    pub(crate) clamp: f32,
+}
+
+#[derive(Clone)]
 pub(crate) struct StandardGraphicsPipelineInfo<'s> {
     shaders: Vec<(&'s str, vk::ShaderStageFlags)>,
     vertex_input_state: vk::PipelineVertexInputStateCreateInfo,
+    topology: vk::PrimitiveTopology,
     back_face_culling: bool,
     depth_testing: bool,
+    depth_writing: bool,  // only with the depth testing, off to test against a read-only depth attachment
+    depth_bias: Option<DepthBias>,
+    polygon_mode: vk::PolygonMode,  // LINE needs the fillModeNonSolid device feature
+    rasterization_samples: vk::SampleCountFlags,  // of the attachments of the subpass
     color_blend_attachment_state: Vec<vk::PipelineColorBlendAttachmentState>,
     // ^^^ corresponds to the color attachment for the respective subpass this pipeline is in
     subpass_index: u32,
 }
 
+impl StandardGraphicsPipelineInfo<'_> {
+    fn rasterization_state(&self) -> vk::PipelineRasterizationStateCreateInfo {
+        let depth_bias = self.depth_bias.unwrap_or(DepthBias {constant_factor: 0.0, slope_factor: 0.0, clamp: 0.0});
+
+        vk::PipelineRasterizationStateCreateInfo {
+            depth_clamp_enable: vk::FALSE,
+            rasterizer_discard_enable: vk::FALSE,
+            polygon_mode: self.polygon_mode,
+            line_width: 1.0,
+            cull_mode: if self.back_face_culling {vk::CullModeFlags::BACK} else {vk::CullModeFlags::NONE},
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_bias_enable: if self.depth_bias.is_some() {vk::TRUE} else {vk::FALSE},
+            depth_bias_constant_factor: depth_bias.constant_factor,
+            depth_bias_slope_factor: depth_bias.slope_factor,
+            depth_bias_clamp: depth_bias.clamp,
+            ..Default::default()
+        }
+    }
+}
+
+// fails on the first shader that can't be compiled or loaded, or when the driver rejects a pipeline
 pub(crate) unsafe fn create_graphics_pipeline(
     device: Rc<Device>,
     pipeline_infos: Vec<StandardGraphicsPipelineInfo>,
     pipeline_layout: vk::PipelineLayout,
     renderpass: vk::RenderPass
-) -> Vec<vk::Pipeline> {
+) -> Result<Vec<vk::Pipeline>, String> {
     let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo {
         topology: vk::PrimitiveTopology::TRIANGLE_LIST,
         primitive_restart_enable: vk::FALSE,
         ..Default::default()
     };
+    let input_assembly_info_lines = vk::PipelineInputAssemblyStateCreateInfo {
+        topology: vk::PrimitiveTopology::LINE_LIST,
+        primitive_restart_enable: vk::FALSE,
+        ..Default::default()
+    };
 
     let viewport_state_info = vk::PipelineViewportStateCreateInfo {
         viewport_count: 1,
@@ -85,32 +251,18 @@ pub(crate) unsafe fn create_graphics_pipeline(
         ..Default::default()
     };
 
-    let rasterizer_info_cull_back = vk::PipelineRasterizationStateCreateInfo {
-        depth_clamp_enable: vk::FALSE,
-        rasterizer_discard_enable: vk::FALSE,
-        polygon_mode: vk::PolygonMode::FILL,
-        line_width: 1.0,
-        cull_mode: vk::CullModeFlags::BACK,
-        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-        depth_bias_enable: vk::FALSE,
-        ..Default::default()
-    };
-    let rasterizer_info_cull_none = vk::PipelineRasterizationStateCreateInfo {
-        depth_clamp_enable: vk::FALSE,
-        rasterizer_discard_enable: vk::FALSE,
-        polygon_mode: vk::PolygonMode::FILL,
-        line_width: 1.0,
-        cull_mode: vk::CullModeFlags::NONE,
-        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-        depth_bias_enable: vk::FALSE,
-        ..Default::default()
-    };
+    // keep referenced object alive (built up front so the pointers stay valid)
+    let rasterizer_infos: Vec<vk::PipelineRasterizationStateCreateInfo> = pipeline_infos.iter()
+        .map(|info| info.rasterization_state())
+        .collect();
 
-    let multisampling_info = vk::PipelineMultisampleStateCreateInfo {
-        sample_shading_enable: vk::FALSE,
-        rasterization_samples: vk::SampleCountFlags::TYPE_1,
-        ..Default::default()
-    };
+    let multisampling_infos: Vec<vk::PipelineMultisampleStateCreateInfo> = pipeline_infos.iter()
+        .map(|info| vk::PipelineMultisampleStateCreateInfo {
+            sample_shading_enable: vk::FALSE,
+            rasterization_samples: info.rasterization_samples,
+            ..Default::default()
+        })
+        .collect();
 
     let depth_stencil = vk::PipelineDepthStencilStateCreateInfo {
         depth_test_enable: vk::TRUE,
@@ -120,6 +272,10 @@ pub(crate) unsafe fn create_graphics_pipeline(
         stencil_test_enable: vk::FALSE,
         ..Default::default()
     };
+    let depth_stencil_read_only = vk::PipelineDepthStencilStateCreateInfo {
+        depth_write_enable: vk::FALSE,
+        ..depth_stencil
+    };
 
     let dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];  // vk::DynamicState::CULL_MODE
     let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder()
@@ -133,8 +289,14 @@ pub(crate) unsafe fn create_graphics_pipeline(
     let mut color_blend_infos = vec![];
     let mut all_shader_stages = vec![];
 
-    for info in pipeline_infos {
-        let (shader_stages, mut shader_modules) = gen_shader_modules_info(device.clone(), info.shaders);
+    for ((info, rasterizer_info), multisampling_info) in pipeline_infos.into_iter().zip(&rasterizer_infos).zip(&multisampling_infos) {
+        let (shader_stages, mut shader_modules) = match gen_shader_modules_info(device.clone(), info.shaders) {
+            Ok(shaders) => shaders,
+            Err(err) => {
+                destroy_shader_modules(device.clone(), all_shader_modules);
+                return Err(err);
+            }
+        };
 
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .logic_op_enable(false)
@@ -146,11 +308,15 @@ pub(crate) unsafe fn create_graphics_pipeline(
             stage_count: shader_stages.len() as u32,
             p_stages: shader_stages.as_ptr(),
             p_vertex_input_state: &info.vertex_input_state,
-            p_input_assembly_state: &input_assembly_info,
+            p_input_assembly_state: if info.topology == vk::PrimitiveTopology::LINE_LIST {&input_assembly_info_lines} else {&input_assembly_info},
             p_viewport_state: &viewport_state_info,
-            p_rasterization_state: if info.back_face_culling {&rasterizer_info_cull_back} else {&rasterizer_info_cull_none},
-            p_multisample_state: &multisampling_info,
-            p_depth_stencil_state: if info.depth_testing {&depth_stencil} else {&vk::PipelineDepthStencilStateCreateInfo::default()},
+            p_rasterization_state: rasterizer_info,
+            p_multisample_state: multisampling_info,
+            p_depth_stencil_state: match (info.depth_testing, info.depth_writing) {
+                (true, true) => &depth_stencil,
+                (true, false) => &depth_stencil_read_only,
+                (false, _) => &vk::PipelineDepthStencilStateCreateInfo::default(),
+            },
             p_color_blend_state: &color_blend_info,
             p_dynamic_state: &dynamic_state_info,
 
@@ -166,15 +332,74 @@ pub(crate) unsafe fn create_graphics_pipeline(
         all_shader_stages.push(shader_stages);
     }
 
-    let gp = device.create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_create_infos, None)
-        .unwrap();
+    let gp = device.create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_create_infos, None);
 
     destroy_shader_modules(device.clone(), all_shader_modules);
 
-    gp
+    gp.map_err(|(pipelines, err)| {
+        for pipeline in pipelines {
+            device.destroy_pipeline(pipeline, None);
+        }
+        format!("Failed to create graphics pipelines: {err}")
+    })
 }
 
+// fails when the shader can't be compiled or loaded, or when the driver rejects the pipeline
+pub(crate) unsafe fn create_compute_pipeline(
+    device: Rc<Device>,
+    shader: &str,
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline, String> {
+    let (shader_stages, shader_modules) = gen_shader_modules_info(device.clone(), vec![(shader, vk::ShaderStageFlags::COMPUTE)])?;
 
+    let pipeline_create_info = vk::ComputePipelineCreateInfo {
+        stage: shader_stages[0],
+        layout: pipeline_layout,
+        ..Default::default()
+    };
+    let cp = device.create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_create_info], None);
+
+    destroy_shader_modules(device.clone(), shader_modules);
+
+    cp.map(|pipelines| pipelines[0]).map_err(|(pipelines, err)| {
+        for pipeline in pipelines {
+            device.destroy_pipeline(pipeline, None);
+        }
+        format!("Failed to create compute pipeline of {shader}: {err}")
+    })
+}
+
+
+// descriptor writes requested mid-run are held back per frame in flight until the frame's own descriptor sets are
+// no longer read, since overwriting a descriptor used by a pending command buffer is a GPU hazard
+pub(crate) struct DeferredDescriptorWrites<T> {
+    pending: Vec<Vec<(u32, u32, T)>>,  // set, binding, descriptor data of each frame in flight
+}
+
+impl<T: Clone> DeferredDescriptorWrites<T> {
+    pub(crate) fn new(frames: usize) -> Self {
+        Self { pending: (0..frames).map(|_| Vec::new()).collect() }
+    }
+
+    pub(crate) fn defer(&mut self, set: u32, binding: u32, data: T) {
+        for pending in &mut self.pending {
+            // only the latest write to a binding matters
+            pending.retain(|(s, b, _)| !(*s == set && *b == binding));
+            pending.push((set, binding, data.clone()));
+        }
+    }
+
+    // the pending writes of the frame, once its fence signaled
+    pub(crate) fn take_ready(&mut self, frame_index: usize) -> Vec<(u32, u32, T)> {
+        mem::take(&mut self.pending[frame_index])
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {self.pending.iter().all(Vec::is_empty)}
+}
+
+
+// a copy of the descriptor sets per frame in flight, so the per frame buffers (and any rewritten descriptor) are
+// bound without touching the sets a pending command buffer reads
 pub(crate) struct DescriptorManager {
     device: Rc<Device>,
 
@@ -182,21 +407,27 @@ pub(crate) struct DescriptorManager {
     descriptor_layout: Vec<Vec<(vk::DescriptorType, vk::ShaderStageFlags)>>,
     descriptor_set_layout: Vec<vk::DescriptorSetLayout>,
     descriptor_pool: vk::DescriptorPool,
-    descriptor_set: Vec<vk::DescriptorSet>,
+    descriptor_set: Vec<Vec<vk::DescriptorSet>>,  // of each frame in flight
+    frame: usize,  // whose sets are bound
+
+    deferred_images: DeferredDescriptorWrites<Vec<vk::DescriptorImageInfo>>,
 }
 
 impl DescriptorManager {
-    pub(crate) unsafe fn new(device: Rc<Device>, descriptors: Vec<Vec<(vk::DescriptorType, vk::ShaderStageFlags)>>) -> Self {
-        // assumes descriptor count of 1 always
-
+    // the bindings are a single descriptor each, except for the arrays given as (set, binding, descriptor count)
+    pub(crate) unsafe fn new(device: Rc<Device>, descriptors: Vec<Vec<(vk::DescriptorType, vk::ShaderStageFlags)>>,
+                             arrays: &[(u32, u32, u32)]) -> Self {
         let mut set_layouts = Vec::new();
         let mut pool_sizes = Vec::new();
-        for set in &descriptors {
+        for (set_ind, set) in descriptors.iter().enumerate() {
             let mut bindings = Vec::new();
-            for (binding_ind, (binding_type, binding_stage)) in set.into_iter().enumerate() {
+            for (binding_ind, (binding_type, binding_stage)) in set.iter().enumerate() {
+                let descriptor_count = arrays.iter()
+                    .find(|(set, binding, _)| (*set, *binding) == (set_ind as u32, binding_ind as u32))
+                    .map_or(1, |(_, _, count)| *count);
                 let set_layout_binding = vk::DescriptorSetLayoutBinding {
                     binding: binding_ind as u32,
-                    descriptor_count: 1,  // arrays of the same binding
+                    descriptor_count,
                     descriptor_type: *binding_type,
                     p_immutable_samplers: std::ptr::null(),
                     stage_flags: *binding_stage,
@@ -205,7 +436,7 @@ impl DescriptorManager {
 
                 let pool_size = vk::DescriptorPoolSize {
                     ty: *binding_type,
-                    descriptor_count: 1,
+                    descriptor_count: descriptor_count*MAX_FRAMES_IN_FLIGHT as u32,
                 };
                 pool_sizes.push(pool_size);
             }
@@ -224,7 +455,7 @@ impl DescriptorManager {
 
         let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
             .pool_sizes(&pool_sizes)
-            .max_sets(set_layouts.len() as u32)
+            .max_sets((set_layouts.len() * MAX_FRAMES_IN_FLIGHT) as u32)
             .build();
         let descriptor_pool = device.create_descriptor_pool(&descriptor_pool_info, None)
             .expect("Failed to create descriptor pool");
@@ -234,8 +465,10 @@ impl DescriptorManager {
             .set_layouts(&set_layouts)
             .build();
 
-        let descriptor_set = device.allocate_descriptor_sets(&descriptor_set_alloc)
-            .expect("Failed to allocate descriptor sets");
+        let descriptor_set = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+            device.allocate_descriptor_sets(&descriptor_set_alloc)
+                .expect("Failed to allocate descriptor sets")
+        }).collect();
 
         Self {
             device,
@@ -244,39 +477,67 @@ impl DescriptorManager {
             descriptor_set_layout: set_layouts,
             descriptor_pool,
             descriptor_set,
+            frame: 0,
+            deferred_images: DeferredDescriptorWrites::new(MAX_FRAMES_IN_FLIGHT),
         }
     }
 
+    // a buffer per frame in flight is written into the sets of its own frame, otherwise the same buffer into all
     pub(crate) unsafe fn write_buffer(&mut self, set: u32, binding: u32, buf: Vec<vk::DescriptorBufferInfo>) {
-        self.device.update_descriptor_sets(&[
-            vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set[set as usize])
-                .dst_binding(binding)
-                .dst_array_element(0)
-                .descriptor_type(self.descriptor_layout[set as usize][binding as usize].0)
-                .buffer_info(&buf)
-                .build()
-        ], &[]);
+        for (frame, descriptor_set) in self.descriptor_set.iter().enumerate() {
+            let buf_info = if buf.len() == MAX_FRAMES_IN_FLIGHT { &buf[frame..frame+1] } else { &buf[..] };
+            self.device.update_descriptor_sets(&[
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set[set as usize])
+                    .dst_binding(binding)
+                    .dst_array_element(0)
+                    .descriptor_type(self.descriptor_layout[set as usize][binding as usize].0)
+                    .buffer_info(buf_info)
+                    .build()
+            ], &[]);
+        }
     }
 
     pub(crate) unsafe fn write_image(&mut self, set: u32, binding: u32, img: Vec<vk::DescriptorImageInfo>) {
+        for frame in 0..MAX_FRAMES_IN_FLIGHT {
+            self.write_frame_image(frame, set, binding, &img);
+        }
+    }
+
+    unsafe fn write_frame_image(&self, frame: usize, set: u32, binding: u32, img: &[vk::DescriptorImageInfo]) {
         self.device.update_descriptor_sets(&[
             vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set[set as usize])
+                .dst_set(self.descriptor_set[frame][set as usize])
                 .dst_binding(binding)
                 .dst_array_element(0)
                 .descriptor_type(self.descriptor_layout[set as usize][binding as usize].0)
-                .image_info(&img)
+                .image_info(img)
                 .build()
         ], &[]);
     }
 
+    // frame-safe variant of write_image for descriptors that are rewritten while frames are being rendered
+    pub(crate) fn defer_write_image(&mut self, set: u32, binding: u32, img: Vec<vk::DescriptorImageInfo>) {
+        self.deferred_images.defer(set, binding, img);
+    }
+
+    // binds the sets of the frame about to be recorded, writing its deferred descriptors now its fence signaled
+    pub(crate) unsafe fn prepare_frame(&mut self, frame_index: usize) {
+        self.frame = frame_index;
+        if self.deferred_images.is_empty() {
+            return;
+        }
+        for (set, binding, img) in self.deferred_images.take_ready(frame_index) {
+            self.write_frame_image(frame_index, set, binding, &img);
+        }
+    }
+
     pub(crate) unsafe fn pipeline_layout(&self) -> vk::PipelineLayout {self.pipeline_layout}
 
     pub(crate) unsafe fn descriptor_sets(&self, indices: &[usize]) -> Vec<vk::DescriptorSet> {
         let mut result = Vec::new();
         for ind in indices {
-            result.push(self.descriptor_set[*ind]);
+            result.push(self.descriptor_set[self.frame][*ind]);
         }
         result
     }
@@ -291,20 +552,20 @@ impl DescriptorManager {
 }
 
 pub(crate) unsafe fn gen_shader_modules_info(device: Rc<Device>, shaders: Vec<(&str, vk::ShaderStageFlags)>)
-    -> (Vec<vk::PipelineShaderStageCreateInfo>, Vec<vk::ShaderModule>) {
+    -> Result<(Vec<vk::PipelineShaderStageCreateInfo>, Vec<vk::ShaderModule>), String> {
     let mut pipeline = vec![];
     let mut modules = vec![];
 
     for (shader_fpath, shader_stage) in shaders {
-        let status = process::Command::new("C:/VulkanSDK/1.3.261.1/bin/glslc.exe")
-            .arg(shader_fpath)
-            .arg("-o")
-            .arg(format!("{shader_fpath}.spv"))
-            .status()
-            .expect(&*format!("Failed to compile shader {shader_fpath}"));
-        println!("Compiled shader <{shader_fpath}> with status of {status}");
-
-        let shader_module = create_shader_module(device.clone(), &*format!("{shader_fpath}.spv"));
+        let shader_module = match compile_shader(shader_fpath)
+            .and_then(|spv_fpath| create_shader_module(device.clone(), &spv_fpath))
+        {
+            Ok(shader_module) => shader_module,
+            Err(err) => {
+                destroy_shader_modules(device.clone(), modules);
+                return Err(err);
+            }
+        };
 
         let shader_info = vk::PipelineShaderStageCreateInfo::builder()
             .stage(shader_stage)
@@ -314,12 +575,69 @@ pub(crate) unsafe fn gen_shader_modules_info(device: Rc<Device>, shaders: Vec<(&
 
         pipeline.push(shader_info);
         modules.push(shader_module);
+    }
+
+    Ok((pipeline, modules))
+}
+
+// compiled shader of the source, reusing the cached compilation while the source is unchanged, or the pre-compiled
+// shader next to the source (`<shader>.spv`) when glslc can't compile it
+fn compile_shader(shader_fpath: &str) -> Result<PathBuf, String> {
+    let precompiled = PathBuf::from(format!("{shader_fpath}.spv"));
+    let source = match fs::read(shader_fpath) {
+        Ok(source) => source,
+        Err(err) if precompiled.exists() => {
+            log::warn!("Failed to read shader {shader_fpath} ({err}), using the pre-compiled {}", precompiled.display());
+            return Ok(precompiled);
+        }
+        Err(err) => return Err(format!("Failed to read shader {shader_fpath}: {err}")),
+    };
+
+    let keyed = [source.as_slice(), &included_sources(shader_fpath, &source)].concat();
+    let cached = cached_spv_path(Path::new(SHADER_CACHE_DIR), shader_fpath, &keyed);
+    if cached.exists() {
+        return Ok(cached);
+    }
 
-        fs::remove_file(format!("{shader_fpath}.spv"))
-            .expect(&*format!("Failed to delete the temp file for the compiled shader {shader_fpath}.spv"));
+    let glslc = env::var(GLSLC_ENV).unwrap_or(String::from("glslc"));
+    let output = fs::create_dir_all(SHADER_CACHE_DIR).and_then(|_| {
+        process::Command::new(&glslc)
+            .arg(shader_fpath)
+            .arg("-o")
+            .arg(&cached)
+            .output()
+    });
+    match output {
+        Ok(output) if output.status.success() => {
+            log::debug!(target: MTXG_LOG, "Compiled shader <{shader_fpath}> into {}", cached.display());
+            Ok(cached)
+        }
+        _ if precompiled.exists() => {
+            log::warn!("Failed to compile shader {shader_fpath} with {glslc}, using the pre-compiled {}", precompiled.display());
+            Ok(precompiled)
+        }
+        // along with the compile errors
+        Ok(output) => Err(format!("Failed to compile shader {shader_fpath}:\n{}", String::from_utf8_lossy(&output.stderr))),
+        Err(err) => Err(format!("Failed to compile shader {shader_fpath} with {glslc} ({err}), set {GLSLC_ENV} to the glslc executable")),
     }
+}
 
-    (pipeline, modules)
+// content of the files included by the shader source (relative to the shader), so the cached compilation isn't
+// reused once a shared include changes
+fn included_sources(shader_fpath: &str, source: &[u8]) -> Vec<u8> {
+    let dir = Path::new(shader_fpath).parent().unwrap_or(Path::new("."));
+    String::from_utf8_lossy(source).lines()
+        .filter_map(|line| line.trim().strip_prefix("#include")?.trim().strip_prefix('"')?.strip_suffix('"').map(str::to_owned))
+        .flat_map(|include| fs::read(dir.join(include)).unwrap_or_default())
+        .collect()
+}
+
+// cache file of the compiled shader, named after the source file and keyed by the hash of its content
+fn cached_spv_path(cache_dir: &Path, shader_fpath: &str, source: &[u8]) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    let name = Path::new(shader_fpath).file_name().map_or(String::from("shader"), |name| name.to_string_lossy().into_owned());
+    cache_dir.join(format!("{name}.{:016x}.spv", hasher.finish()))
 }
 
 pub(crate) unsafe fn destroy_shader_modules(device: Rc<Device>, shader_modules: Vec<vk::ShaderModule>) {
@@ -328,9 +646,19 @@ pub(crate) unsafe fn destroy_shader_modules(device: Rc<Device>, shader_modules:
     }
 }
 
-unsafe fn create_shader_module(device: Rc<Device>, fpath: &str) -> vk::ShaderModule {
-    let mut fobj = File::open(fpath).unwrap();
-    let code = read_spv(&mut fobj).unwrap();
+// a draw per debug ui primitive out of the bound debug ui buffers, within its scissor and passing its texture slot as
+// the first instance (read as gl_InstanceIndex), so the draws don't need a descriptor set each
+pub(crate) unsafe fn record_ui_draws(device: &Device, cmd_buf: vk::CommandBuffer, draws: &[UiDraw]) {
+    for draw in draws {
+        device.cmd_set_scissor(cmd_buf, 0, &[draw.scissor]);
+        device.cmd_draw_indexed(cmd_buf, draw.index_count, 1, draw.first_index, draw.vertex_offset, draw.texture);
+    }
+}
+
+unsafe fn create_shader_module(device: Rc<Device>, fpath: &Path) -> Result<vk::ShaderModule, String> {
+    let code = File::open(fpath)
+        .and_then(|mut fobj| read_spv(&mut fobj))
+        .map_err(|err| format!("Failed to load the compiled shader {}: {err}", fpath.display()))?;
 
     let create_info = vk::ShaderModuleCreateInfo {
         // code size are in bytes, but code data is aligned to u32 (4 bytes)
@@ -339,5 +667,202 @@ unsafe fn create_shader_module(device: Rc<Device>, fpath: &str) -> vk::ShaderMod
         ..Default::default()
     };
 
-    device.create_shader_module(&create_info, None).unwrap()
+    device.create_shader_module(&create_info, None)
+        .map_err(|err| format!("Failed to create the shader module of {}: {err}", fpath.display()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_spv_keyed_by_source() {
+        let cache_dir = Path::new("cache");
+        let spv = cached_spv_path(cache_dir, "src/shader/chunk.vert", b"void main() {}");
+
+        assert_eq!(spv.parent(), Some(cache_dir));
+        assert!(spv.file_name().unwrap().to_string_lossy().starts_with("chunk.vert."));
+        assert_eq!(spv, cached_spv_path(cache_dir, "src/shader/chunk.vert", b"void main() {}"));
+        // edited sources are compiled again
+        assert_ne!(spv, cached_spv_path(cache_dir, "src/shader/chunk.vert", b"void main() {gl_Position = vec4(0.0);}"));
+        assert_ne!(spv, cached_spv_path(cache_dir, "src/shader/chunk.frag", b"void main() {}"));
+    }
+
+    #[test]
+    fn test_included_sources() {
+        let shader_fpath = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/chunk.frag");
+        let included = included_sources(shader_fpath, &fs::read(shader_fpath).unwrap());
+        assert_eq!(included, fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/lighting.glsl")).unwrap());
+        assert!(included_sources(shader_fpath, b"#version 450\nvoid main() {}").is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_depth_bias_rasterization_state() {
+        let mut info = StandardGraphicsPipelineInfo {
+            shaders: vec![],
+            vertex_input_state: vk::PipelineVertexInputStateCreateInfo::default(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            back_face_culling: true, depth_testing: true, depth_writing: true,
+            depth_bias: None,
+            polygon_mode: vk::PolygonMode::FILL,
+            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            color_blend_attachment_state: vec![ColorBlendKind::transparent()],
+            subpass_index: 0,
+        };
+
+        let no_bias = info.rasterization_state();
+        assert_eq!(no_bias.depth_bias_enable, vk::FALSE);
+        assert_eq!(no_bias.cull_mode, vk::CullModeFlags::BACK);
+        assert_eq!(no_bias.polygon_mode, vk::PolygonMode::FILL);
+
+        let wireframe = StandardGraphicsPipelineInfo { polygon_mode: vk::PolygonMode::LINE, ..info.clone() };
+        assert_eq!(wireframe.rasterization_state().polygon_mode, vk::PolygonMode::LINE);
+        assert_eq!(wireframe.rasterization_state().cull_mode, vk::CullModeFlags::BACK);
+
+        info.depth_bias = Some(DepthBias {constant_factor: 1.25, slope_factor: 1.75, clamp: 0.0});
+        let decal = info.rasterization_state();
+        assert_eq!(decal.depth_bias_enable, vk::TRUE);
+        assert_eq!(decal.depth_bias_constant_factor, 1.25);
+        assert_eq!(decal.depth_bias_slope_factor, 1.75);
+        assert_eq!(decal.depth_bias_clamp, 0.0);
+    }
+
+    #[test]
+    fn test_msaa_lowered_to_supported_sample_count() {
+        let supported = vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_2 | vk::SampleCountFlags::TYPE_4;
+        assert_eq!(Msaa::Off.sample_count(supported), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(Msaa::X2.sample_count(supported), vk::SampleCountFlags::TYPE_2);
+        assert_eq!(Msaa::X4.sample_count(supported), vk::SampleCountFlags::TYPE_4);
+        // the highest supported one below the requested one
+        assert_eq!(Msaa::X8.sample_count(supported), vk::SampleCountFlags::TYPE_4);
+        assert_eq!(Msaa::X8.sample_count(vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_8), vk::SampleCountFlags::TYPE_8);
+        assert_eq!(Msaa::X4.sample_count(vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_8), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(Msaa::X8.sample_count(vk::SampleCountFlags::TYPE_1), vk::SampleCountFlags::TYPE_1);
+
+        assert_eq!(Msaa::X8.next(), Msaa::Off);
+    }
+
+    #[test]
+    fn test_deferred_descriptor_write_waits_for_frame() {
+        let mut deferred = DeferredDescriptorWrites::new(2);
+
+        // texture swapped mid-run while both frames are still in flight
+        deferred.defer(0, 1, "new_textures");
+        assert!(!deferred.is_empty());
+
+        // each frame's sets are rewritten once its own fence signaled, without waiting on the other frame
+        assert_eq!(deferred.take_ready(0), vec![(0, 1, "new_textures")]);
+        assert!(deferred.take_ready(0).is_empty());
+        assert!(!deferred.is_empty());
+        assert_eq!(deferred.take_ready(1), vec![(0, 1, "new_textures")]);
+        assert!(deferred.is_empty());
+    }
+
+    #[test]
+    fn test_deferred_descriptor_write_latest_wins() {
+        let mut deferred = DeferredDescriptorWrites::new(2);
+
+        deferred.defer(0, 1, "first");
+        deferred.defer(1, 0, "egui");
+        assert_eq!(deferred.take_ready(1), vec![(0, 1, "first"), (1, 0, "egui")]);
+        deferred.defer(0, 1, "second");
+
+        assert_eq!(deferred.take_ready(0), vec![(1, 0, "egui"), (0, 1, "second")]);
+        assert_eq!(deferred.take_ready(1), vec![(0, 1, "second")]);
+    }
+
+    #[allow(dead_code)]
+    #[derive(Copy, Clone, matrixagon_util::Vertex)]
+    #[vertex(binding = 1, rate = "instance")]
+    struct FloraInstance {
+        offset: [f32; 3],
+        scale: f32,
+    }
+
+    #[test]
+    fn test_vertex_derive_instance_binding() {
+        use matrixagon_util::VulkanVertexState;
+
+        let binding = FloraInstance::BINDING_DESCRIPTION;
+        assert_eq!(binding.binding, 1);
+        assert_eq!(binding.input_rate, vk::VertexInputRate::INSTANCE);
+        assert_eq!(binding.stride, 16);
+        let attributes = FloraInstance::ATTRIBUTE_DESCRIPTION;
+        assert!(attributes.iter().all(|attribute| attribute.binding == 1));
+        assert_eq!(attributes.map(|attribute| (attribute.location, attribute.format)),
+                   [(0, vk::Format::R32G32B32_SFLOAT), (1, vk::Format::R32_SFLOAT)]);
+
+        // per vertex at binding 0 without the attribute
+        let chunk_border = crate::shader::chunk::ChunkBorderVertex::BINDING_DESCRIPTION;
+        assert_eq!(chunk_border.binding, 0);
+        assert_eq!(chunk_border.input_rate, vk::VertexInputRate::VERTEX);
+        assert!(crate::shader::chunk::ChunkBorderVertex::ATTRIBUTE_DESCRIPTION.iter().all(|attribute| attribute.binding == 0));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Copy, Clone, matrixagon_util::Vertex)]
+    struct IntegerVertex {
+        a1: u32, a2: [u32; 2], a3: [u32; 3], a4: [u32; 4],
+        b1: i32, b2: [i32; 2], b3: [i32; 3], b4: [i32; 4],
+        c1: u16, c2: [u16; 2], c3: [u16; 3], c4: [u16; 4],
+        d1: i16, d2: [i16; 2], d3: [i16; 3], d4: [i16; 4],
+        light: f32,
+    }
+
+    #[test]
+    fn test_vertex_derive_integer_formats() {
+        use matrixagon_util::VulkanVertexState;
+
+        let formats = IntegerVertex::ATTRIBUTE_DESCRIPTION.map(|attribute| attribute.format);
+        assert_eq!(formats, [
+            vk::Format::R32_UINT, vk::Format::R32G32_UINT, vk::Format::R32G32B32_UINT, vk::Format::R32G32B32A32_UINT,
+            vk::Format::R32_SINT, vk::Format::R32G32_SINT, vk::Format::R32G32B32_SINT, vk::Format::R32G32B32A32_SINT,
+            vk::Format::R16_UINT, vk::Format::R16G16_UINT, vk::Format::R16G16B16_UINT, vk::Format::R16G16B16A16_UINT,
+            vk::Format::R16_SINT, vk::Format::R16G16_SINT, vk::Format::R16G16B16_SINT, vk::Format::R16G16B16A16_SINT,
+            vk::Format::R32_SFLOAT,
+        ]);
+
+        // the offsets follow the fields wherever the compiler placed them
+        let base = std::mem::MaybeUninit::<IntegerVertex>::uninit();
+        let base_ptr = base.as_ptr();
+        let offset = |field: *const u8| unsafe { field.offset_from(base_ptr as *const u8) } as u32;
+        let attributes = IntegerVertex::ATTRIBUTE_DESCRIPTION;
+        unsafe {
+            assert_eq!(attributes[1].offset, offset(std::ptr::addr_of!((*base_ptr).a2) as *const u8));
+            assert_eq!(attributes[11].offset, offset(std::ptr::addr_of!((*base_ptr).c4) as *const u8));
+            assert_eq!(attributes[16].offset, offset(std::ptr::addr_of!((*base_ptr).light) as *const u8));
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(Copy, Clone, matrixagon_util::Vertex)]
+    struct SkippingVertex {
+        #[vertex(skip)]
+        chunk: (i32, i32),  // host-only, not a format the derive knows
+        pos: [f32; 3],
+        #[vertex(skip)]
+        _padding: u32,
+        light: u16,
+    }
+
+    #[test]
+    fn test_vertex_derive_skips_fields() {
+        use matrixagon_util::VulkanVertexState;
+
+        let attributes = SkippingVertex::ATTRIBUTE_DESCRIPTION;
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attributes.map(|attribute| (attribute.location, attribute.format)),
+                   [(0, vk::Format::R32G32B32_SFLOAT), (1, vk::Format::R16_UINT)]);
+        assert_eq!(SkippingVertex::VERTEX_INPUT_STATE.vertex_attribute_description_count, 2);
+        // the stride still covers the skipped fields
+        assert_eq!(SkippingVertex::BINDING_DESCRIPTION.stride as usize, mem::size_of::<SkippingVertex>());
+
+        let base = std::mem::MaybeUninit::<SkippingVertex>::uninit();
+        let base_ptr = base.as_ptr();
+        unsafe {
+            let light = std::ptr::addr_of!((*base_ptr).light) as *const u8;
+            assert_eq!(attributes[1].offset, light.offset_from(base_ptr as *const u8) as u32);
+        }
+    }
 }
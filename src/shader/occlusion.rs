@@ -0,0 +1,572 @@
+use std::{ffi, mem, slice};
+use std::rc::Rc;
+use ash::{Device, vk};
+use crate::component::terrain::chunk_mesh::ChunkRange;
+use crate::handler::{MAX_FRAMES_IN_FLIGHT, VulkanInstance};
+use crate::shader::create_compute_pipeline;
+use crate::util::{allocate_buffer, create_local_image};
+
+
+const PYRAMID_SHADER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/depth_pyramid.comp");
+const CULL_SHADER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader/occlusion_cull.comp");
+const PYRAMID_GROUP_SIZE: u32 = 8;  // local size of the pyramid shader in both directions
+const CULL_GROUP_SIZE: u32 = 64;  // local size of the culling shader
+
+// chunks tested in a frame, the purposes past it are drawn without the occlusion culling
+const MAX_OCCLUSION_DRAWS: usize = 16384;
+const MAX_PYRAMID_LEVELS: usize = 16;
+// the draw commands follow the count of the visible chunks
+const DRAWS_OFFSET: usize = mem::size_of::<u32>();
+const DRAW_STRIDE: usize = mem::size_of::<vk::DrawIndexedIndirectCommand>();
+
+
+// box of a chunk as laid out in the storage buffer of the culling shader
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ChunkBounds {
+    min: [f32; 4],
+    max: [f32; 4],
+}
+
+// host visible buffers of a frame in flight, written before the frame is recorded and read back once it completed
+struct CullSlot {
+    bounds_buf: vk::Buffer,
+    bounds_mem: vk::DeviceMemory,
+    bounds_ptr: *mut ffi::c_void,
+    draws_buf: vk::Buffer,  // the count of the visible chunks, then an indirect draw command per chunk
+    draws_mem: vk::DeviceMemory,
+    draws_ptr: *mut ffi::c_void,
+    descriptor_set: vk::DescriptorSet,
+    tested: usize,  // chunks written for the last frame drawn with the slot
+}
+
+// mip chain of the farthest depth (the smallest with reverse-Z), starting at half the extent
+struct DepthPyramid {
+    img: vk::Image,
+    img_mem: vk::DeviceMemory,
+    view: vk::ImageView,  // of all the levels, sampled by the culling shader
+    level_views: Vec<vk::ImageView>,
+    levels: Vec<vk::Extent2D>,
+}
+
+// hierarchical-Z occlusion culling of the chunks in the frustum, testing their boxes against the depth pyramid built
+// from the previous frame, so the chunks occluded by then are drawn with no instances through indirect draws
+// chunks coming into view past the occluders of the previous frame (e.g. turning quickly) may show up a frame late
+pub(crate) struct OcclusionCuller {
+    device: Rc<Device>,
+    vi: Rc<VulkanInstance>,
+
+    sampler: vk::Sampler,
+    descriptor_pool: vk::DescriptorPool,
+    pyramid_set_layout: vk::DescriptorSetLayout,
+    cull_set_layout: vk::DescriptorSetLayout,
+    pyramid_layout: vk::PipelineLayout,
+    cull_layout: vk::PipelineLayout,
+    pyramid_pipeline: vk::Pipeline,
+    cull_pipeline: vk::Pipeline,
+
+    pyramid: Option<DepthPyramid>,  // once the depth attachment is known
+    pyramid_sets: Vec<vk::DescriptorSet>,  // per level
+    slots: Vec<CullSlot>,  // per frame in flight
+
+    enabled: bool,
+    frame: usize,  // slot of the frame being recorded
+    draws: [Option<(u32, u32)>; 2],  // first draw and draw count of the opaque and transparent chunks in the slot
+    cull: bool,  // whether the frame being recorded tests the chunks, only once a frame built the pyramid
+    pyramid_built: bool,  // by the last frame recorded
+    stats: Option<(usize, usize)>,  // chunks tested and drawn of the last completed frame
+    stats_changed: bool,
+}
+
+impl OcclusionCuller {
+    // fails without a sampleable depth format, or when the compute shaders can't be compiled
+    pub(crate) unsafe fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, depth_format: vk::Format) -> Result<Self, String> {
+        let depth_features = vi.get_physical_device_format_properties(depth_format).optimal_tiling_features;
+        if !depth_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+            return Err(format!("The depth format {depth_format:?} can't be sampled"));
+        }
+
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            max_lod: vk::LOD_CLAMP_NONE,
+            ..Default::default()
+        };
+        let sampler = device.create_sampler(&sampler_info, None)
+            .expect("Failed to create the depth pyramid sampler");
+
+        let set_layout = |bindings: &[vk::DescriptorType]| {
+            let bindings: Vec<vk::DescriptorSetLayoutBinding> = bindings.iter().enumerate()
+                .map(|(binding, descriptor_type)| vk::DescriptorSetLayoutBinding {
+                    binding: binding as u32,
+                    descriptor_type: *descriptor_type,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    ..Default::default()
+                })
+                .collect();
+            let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings).build();
+            device.create_descriptor_set_layout(&info, None).expect("Failed to create descriptor set layout")
+        };
+        let pyramid_set_layout = set_layout(&[
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,  // level above, or the depth attachment
+            vk::DescriptorType::STORAGE_IMAGE,  // level written
+        ]);
+        let cull_set_layout = set_layout(&[
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,  // depth pyramid
+            vk::DescriptorType::UNIFORM_BUFFER,  // view and projection of the camera
+            vk::DescriptorType::STORAGE_BUFFER,  // chunk bounds
+            vk::DescriptorType::STORAGE_BUFFER,  // visible count and draw commands
+        ]);
+
+        let pyramid_set_layouts = [pyramid_set_layout];
+        let pyramid_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&pyramid_set_layouts)
+            .build();
+        let pyramid_layout = device.create_pipeline_layout(&pyramid_layout_info, None).unwrap();
+        let push_constants = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: mem::size_of::<u32>() as u32,  // draw count
+        }];
+        let cull_set_layouts = [cull_set_layout];
+        let cull_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&cull_set_layouts)
+            .push_constant_ranges(&push_constants)
+            .build();
+        let cull_layout = device.create_pipeline_layout(&cull_layout_info, None).unwrap();
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: (MAX_PYRAMID_LEVELS+MAX_FRAMES_IN_FLIGHT) as u32,
+            },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_IMAGE, descriptor_count: MAX_PYRAMID_LEVELS as u32 },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::UNIFORM_BUFFER, descriptor_count: MAX_FRAMES_IN_FLIGHT as u32 },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_BUFFER, descriptor_count: 2*MAX_FRAMES_IN_FLIGHT as u32 },
+        ];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets((MAX_PYRAMID_LEVELS+MAX_FRAMES_IN_FLIGHT) as u32)
+            .build();
+        let descriptor_pool = device.create_descriptor_pool(&descriptor_pool_info, None)
+            .expect("Failed to create descriptor pool");
+
+        let allocate_sets = |layout: vk::DescriptorSetLayout, count: usize| {
+            let layouts = vec![layout; count];
+            let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&layouts)
+                .build();
+            device.allocate_descriptor_sets(&alloc_info).expect("Failed to allocate descriptor sets")
+        };
+        let pyramid_sets = allocate_sets(pyramid_set_layout, MAX_PYRAMID_LEVELS);
+        let slots = allocate_sets(cull_set_layout, MAX_FRAMES_IN_FLIGHT).into_iter()
+            .map(|descriptor_set| {
+                let host_buffer = |size: usize, usage| {
+                    let (buf, mem, size) = allocate_buffer(
+                        vi.clone(), device.clone(), size as vk::DeviceSize, usage,
+                        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+                    );
+                    let ptr = device.map_memory(mem, 0, size, vk::MemoryMapFlags::empty()).unwrap();
+                    (buf, mem, ptr)
+                };
+                let (bounds_buf, bounds_mem, bounds_ptr) = host_buffer(
+                    MAX_OCCLUSION_DRAWS*mem::size_of::<ChunkBounds>(), vk::BufferUsageFlags::STORAGE_BUFFER
+                );
+                let (draws_buf, draws_mem, draws_ptr) = host_buffer(
+                    DRAWS_OFFSET+MAX_OCCLUSION_DRAWS*DRAW_STRIDE,
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER
+                );
+
+                let bounds_info = [vk::DescriptorBufferInfo { buffer: bounds_buf, offset: 0, range: vk::WHOLE_SIZE }];
+                let draws_info = [vk::DescriptorBufferInfo { buffer: draws_buf, offset: 0, range: vk::WHOLE_SIZE }];
+                device.update_descriptor_sets(&[
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .dst_binding(2)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(&bounds_info)
+                        .build(),
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .dst_binding(3)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(&draws_info)
+                        .build(),
+                ], &[]);
+
+                CullSlot { bounds_buf, bounds_mem, bounds_ptr, draws_buf, draws_mem, draws_ptr, descriptor_set, tested: 0 }
+            })
+            .collect();
+
+        let mut culler = Self {
+            device, vi,
+            sampler, descriptor_pool, pyramid_set_layout, cull_set_layout, pyramid_layout, cull_layout,
+            pyramid_pipeline: vk::Pipeline::null(),
+            cull_pipeline: vk::Pipeline::null(),
+            pyramid: None, pyramid_sets, slots,
+            enabled: false,
+            frame: 0,
+            draws: [None; 2],
+            cull: false,
+            pyramid_built: false,
+            stats: None,
+            stats_changed: false,
+        };
+        if let Err(err) = culler.recreate_pipelines() {
+            culler.destroy();
+            return Err(err);
+        }
+        Ok(culler)
+    }
+
+    // recompiles the compute shaders, the current pipelines are kept if either of them fails
+    pub(crate) unsafe fn recreate_pipelines(&mut self) -> Result<(), String> {
+        let pyramid_pipeline = create_compute_pipeline(self.device.clone(), PYRAMID_SHADER, self.pyramid_layout)?;
+        let cull_pipeline = match create_compute_pipeline(self.device.clone(), CULL_SHADER, self.cull_layout) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                self.device.destroy_pipeline(pyramid_pipeline, None);
+                return Err(err);
+            }
+        };
+
+        // the current pipelines may still be used by the frame in flight
+        self.device.device_wait_idle().unwrap();
+        self.device.destroy_pipeline(self.pyramid_pipeline, None);
+        self.device.destroy_pipeline(self.cull_pipeline, None);
+        self.pyramid_pipeline = pyramid_pipeline;
+        self.cull_pipeline = cull_pipeline;
+        Ok(())
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.stats = None;
+        }
+    }
+
+    // chunks tested and drawn, whenever they changed
+    pub(crate) fn take_stats(&mut self) -> Option<(usize, usize)> {
+        mem::take(&mut self.stats_changed).then_some(self.stats).flatten()
+    }
+
+    // a camera buffer per frame in flight goes to the slot of its frame, otherwise the same buffer to all slots
+    pub(crate) unsafe fn write_camera(&mut self, camera: Vec<vk::DescriptorBufferInfo>) {
+        for (frame, slot) in self.slots.iter().enumerate() {
+            let buf_info = if camera.len() == MAX_FRAMES_IN_FLIGHT { &camera[frame..frame+1] } else { &camera[..] };
+            self.device.update_descriptor_sets(&[
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(slot.descriptor_set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(buf_info)
+                    .build()
+            ], &[]);
+        }
+    }
+
+    // rebuilds the pyramid for the depth attachment of the (new) extent
+    pub(crate) unsafe fn resize(&mut self, extent: vk::Extent2D, depth_view: vk::ImageView) {
+        // the previous pyramid may still be used by the frames in flight
+        self.device.device_wait_idle().unwrap();
+        if let Some(pyramid) = self.pyramid.take() {
+            self.destroy_pyramid(&pyramid);
+        }
+        self.pyramid_built = false;
+
+        let levels = pyramid_levels(extent);
+        let (img, img_mem) = create_local_image(
+            self.vi.clone(), self.device.clone(),
+            vk::Extent3D { width: levels[0].width, height: levels[0].height, depth: 1 },
+            levels.len() as u32, vk::Format::R32_SFLOAT,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED, None
+        );
+        let create_view = |base_mip_level, level_count| {
+            let view_info = vk::ImageViewCreateInfo {
+                image: img,
+                view_type: vk::ImageViewType::TYPE_2D,
+                format: vk::Format::R32_SFLOAT,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level,
+                    level_count,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                ..Default::default()
+            };
+            self.device.create_image_view(&view_info, None).expect("Failed to create image view")
+        };
+        let view = create_view(0, levels.len() as u32);
+        let level_views: Vec<vk::ImageView> = (0..levels.len() as u32).map(|level| create_view(level, 1)).collect();
+
+        // each level is reduced from the one above, the first from the depth attachment
+        for (level, level_view) in level_views.iter().enumerate() {
+            let src = [match level {
+                0 => vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: depth_view,
+                    image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                },
+                _ => vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: level_views[level-1],
+                    image_layout: vk::ImageLayout::GENERAL,
+                },
+            }];
+            let dst = [vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: *level_view,
+                image_layout: vk::ImageLayout::GENERAL,
+            }];
+            self.device.update_descriptor_sets(&[
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.pyramid_sets[level])
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&src)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.pyramid_sets[level])
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&dst)
+                    .build(),
+            ], &[]);
+        }
+        let pyramid_info = [vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: view,
+            image_layout: vk::ImageLayout::GENERAL,
+        }];
+        for slot in &self.slots {
+            self.device.update_descriptor_sets(&[
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(slot.descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&pyramid_info)
+                    .build()
+            ], &[]);
+        }
+
+        self.pyramid.replace(DepthPyramid { img, img_mem, view, level_views, levels });
+    }
+
+    // reads back the results of the last frame drawn with the slot of the frame (its fence signaled by now), then
+    // writes the opaque and transparent chunks to draw into it, None draws the purpose without the occlusion culling
+    pub(crate) unsafe fn prepare_frame(&mut self, frame_index: usize, chunks: [Option<Vec<&ChunkRange>>; 2]) {
+        self.frame = frame_index;
+        let slot = &mut self.slots[frame_index];
+        if slot.tested > 0 {
+            let visible = *(slot.draws_ptr as *const u32) as usize;
+            let stats = Some((slot.tested, visible));
+            if self.enabled && stats != self.stats {
+                self.stats = stats;
+                self.stats_changed = true;
+            }
+        }
+        slot.tested = 0;
+        self.draws = [None; 2];
+
+        if !self.enabled || self.pyramid.is_none() {
+            self.cull = false;
+            self.pyramid_built = false;
+            return;
+        }
+
+        let bounds = slice::from_raw_parts_mut(slot.bounds_ptr as *mut ChunkBounds, MAX_OCCLUSION_DRAWS);
+        let commands = slice::from_raw_parts_mut(
+            (slot.draws_ptr as *mut u8).add(DRAWS_OFFSET) as *mut vk::DrawIndexedIndirectCommand, MAX_OCCLUSION_DRAWS
+        );
+        let mut first = 0;
+        for (ind, chunks) in chunks.into_iter().enumerate() {
+            let Some(chunks) = chunks.filter(|chunks| first+chunks.len() <= MAX_OCCLUSION_DRAWS) else {
+                continue;
+            };
+            for (draw, chunk) in chunks.iter().enumerate() {
+                bounds[first+draw] = ChunkBounds {
+                    min: [chunk.min[0], chunk.min[1], chunk.min[2], 1.0],
+                    max: [chunk.max[0], chunk.max[1], chunk.max[2], 1.0],
+                };
+                commands[first+draw] = vk::DrawIndexedIndirectCommand {
+                    index_count: chunk.inds.len() as u32,
+                    instance_count: 1,
+                    first_index: chunk.inds.start,
+                    vertex_offset: chunk.verts.start as i32,
+                    first_instance: 0,
+                };
+            }
+            self.draws[ind] = Some((first as u32, chunks.len() as u32));
+            first += chunks.len();
+        }
+
+        // every chunk is drawn until the pyramid is built by a frame
+        self.cull = self.pyramid_built;
+        *(slot.draws_ptr as *mut u32) = if self.cull {0} else {first as u32};
+        slot.tested = first;
+        self.pyramid_built = true;
+    }
+
+    // before the renderpass, setting the instance counts of the draws
+    pub(crate) unsafe fn record_culling(&self, cmd_buf: vk::CommandBuffer) {
+        let count = self.draws.iter().flatten().map(|(_, count)| *count).sum::<u32>();
+        if !self.cull || count == 0 {
+            return;
+        }
+        let slot = &self.slots[self.frame];
+
+        let pyramid_built = vk::MemoryBarrier {
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        };
+        self.device.cmd_pipeline_barrier(
+            cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(), &[pyramid_built], &[], &[]
+        );
+
+        self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.cull_pipeline);
+        self.device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.cull_layout,
+                                             0, &[slot.descriptor_set], &[]);
+        self.device.cmd_push_constants(cmd_buf, self.cull_layout, vk::ShaderStageFlags::COMPUTE, 0, &count.to_ne_bytes());
+        self.device.cmd_dispatch(cmd_buf, count.div_ceil(CULL_GROUP_SIZE), 1, 1);
+
+        // the visible count is read back once the frame completed
+        let culled = vk::MemoryBarrier {
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::HOST_READ,
+            ..Default::default()
+        };
+        self.device.cmd_pipeline_barrier(
+            cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(), &[culled], &[], &[]
+        );
+    }
+
+    // buffer and offset of the draw commands of the purpose (0 for opaque, 1 for transparent), one for each of its
+    // chunks in order, or None if they aren't occlusion culled
+    pub(crate) fn draw_commands(&self, ind: usize) -> Option<(vk::Buffer, vk::DeviceSize)> {
+        let (first, _) = self.draws[ind]?;
+        Some((self.slots[self.frame].draws_buf, (DRAWS_OFFSET+first as usize*DRAW_STRIDE) as vk::DeviceSize))
+    }
+
+    // after the renderpass, reducing its depth into the pyramid for the next frame
+    pub(crate) unsafe fn record_pyramid(&self, cmd_buf: vk::CommandBuffer) {
+        let Some(pyramid) = self.pyramid.as_ref().filter(|_| self.enabled) else {
+            return;
+        };
+
+        // every level is written again, after the culling read them
+        let written = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::SHADER_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::GENERAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: pyramid.img,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: pyramid.levels.len() as u32,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            ..Default::default()
+        };
+        self.device.cmd_pipeline_barrier(
+            cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(), &[], &[], &[written]
+        );
+
+        self.device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.pyramid_pipeline);
+        let level_written = vk::MemoryBarrier {
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        };
+        for (level, extent) in pyramid.levels.iter().enumerate() {
+            self.device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.pyramid_layout,
+                                                 0, &[self.pyramid_sets[level]], &[]);
+            self.device.cmd_dispatch(cmd_buf, extent.width.div_ceil(PYRAMID_GROUP_SIZE),
+                                     extent.height.div_ceil(PYRAMID_GROUP_SIZE), 1);
+            self.device.cmd_pipeline_barrier(
+                cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(), &[level_written], &[], &[]
+            );
+        }
+    }
+
+    unsafe fn destroy_pyramid(&self, pyramid: &DepthPyramid) {
+        for view in pyramid.level_views.iter().chain([&pyramid.view]) {
+            self.device.destroy_image_view(*view, None);
+        }
+        self.device.destroy_image(pyramid.img, None);
+        self.device.free_memory(pyramid.img_mem, None);
+    }
+
+    pub(crate) unsafe fn destroy(&self) {
+        if let Some(pyramid) = &self.pyramid {
+            self.destroy_pyramid(pyramid);
+        }
+        for slot in &self.slots {
+            self.device.destroy_buffer(slot.bounds_buf, None);
+            self.device.free_memory(slot.bounds_mem, None);
+            self.device.destroy_buffer(slot.draws_buf, None);
+            self.device.free_memory(slot.draws_mem, None);
+        }
+        self.device.destroy_pipeline(self.pyramid_pipeline, None);
+        self.device.destroy_pipeline(self.cull_pipeline, None);
+        self.device.destroy_pipeline_layout(self.pyramid_layout, None);
+        self.device.destroy_pipeline_layout(self.cull_layout, None);
+        self.device.destroy_descriptor_pool(self.descriptor_pool, None);
+        self.device.destroy_descriptor_set_layout(self.pyramid_set_layout, None);
+        self.device.destroy_descriptor_set_layout(self.cull_set_layout, None);
+        self.device.destroy_sampler(self.sampler, None);
+    }
+}
+
+// extents of the pyramid levels, halving (rounded down, as the mip levels of the image) from half the extent
+// down to a single texel
+fn pyramid_levels(extent: vk::Extent2D) -> Vec<vk::Extent2D> {
+    let mut level = vk::Extent2D { width: (extent.width/2).max(1), height: (extent.height/2).max(1) };
+    let mut levels = vec![level];
+    while (level.width > 1 || level.height > 1) && levels.len() < MAX_PYRAMID_LEVELS {
+        level = vk::Extent2D { width: (level.width/2).max(1), height: (level.height/2).max(1) };
+        levels.push(level);
+    }
+    levels
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pyramid_levels_halve_to_a_texel() {
+        let levels = pyramid_levels(vk::Extent2D { width: 1280, height: 720 });
+        assert_eq!(levels.len(), 10);
+        assert_eq!(levels[0], vk::Extent2D { width: 640, height: 360 });
+        assert_eq!(levels[1], vk::Extent2D { width: 320, height: 180 });
+        // odd sizes round down, the last texels covering the remainder
+        assert_eq!(levels[4], vk::Extent2D { width: 40, height: 22 });
+        assert_eq!(levels[5], vk::Extent2D { width: 20, height: 11 });
+        assert_eq!(levels[6], vk::Extent2D { width: 10, height: 5 });
+        assert_eq!(levels[9], vk::Extent2D { width: 1, height: 1 });
+        // as many levels as the mip chain of the first
+        assert_eq!(levels.len() as u32, 32-levels[0].width.max(levels[0].height).leading_zeros());
+
+        assert_eq!(pyramid_levels(vk::Extent2D { width: 1, height: 1 }), vec![vk::Extent2D { width: 1, height: 1 }]);
+        assert_eq!(pyramid_levels(vk::Extent2D { width: 64, height: 1 }).len(), 6);
+    }
+}
@@ -0,0 +1,16 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec3 dir;
+
+layout(location = 0) out vec4 out_color;  // renderpass: color attachment #0
+
+layout(set = 0, binding = 2) uniform samplerCube sky_sampler;
+
+#include "lighting.glsl"
+
+void main() {
+    out_color = texture(sky_sampler, dir);
+    // darkens towards the night
+    out_color.rgb *= sky.tint;
+}
@@ -0,0 +1,18 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+    mat4 inv_sky;  // inverse of the projection and camera rotation, without the camera translation
+} mvp;
+
+layout(location = 0) out vec3 dir;
+
+void main() {
+    // a single triangle covering the screen, at the far plane (depth 0 with the reversed depth)
+    vec2 pos = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2)*2.0 - 1.0;
+    gl_Position = vec4(pos, 0.0, 1.0);
+
+    vec4 near = mvp.inv_sky * vec4(pos, 1.0, 1.0);
+    dir = near.xyz/near.w;
+}
@@ -1,12 +1,12 @@
 use std::rc::Rc;
 use ash::{Device, vk};
 use ash::extensions::khr::Swapchain;
-use crate::debug::DebugVisibility;
+use crate::debug::{Diagnostics, VK_SWAPCHAIN_LOG};
+use crate::error::MatrixagonError;
 use crate::framebuffer::{FBAttachmentRef, FramebufferManager};
-use crate::handler::VulkanInstance;
+use crate::handler::{find_queue_families, QueueFamilies, VulkanInstance};
 
 pub(crate) struct SwapchainManager {
-    dbv: DebugVisibility,
     vi: Rc<VulkanInstance>,
     device: Rc<Device>,
 
@@ -15,10 +15,13 @@ pub(crate) struct SwapchainManager {
     pub(crate) extent: vk::Extent2D,
     pub(crate) capb: vk::SurfaceCapabilitiesKHR,
     pub(crate) fmt: vk::SurfaceFormatKHR,
+    depth_fmt: vk::Format,
     pub(crate) prsnt: vk::PresentModeKHR,
+    pub(crate) present_mode: PresentMode,  // requested, prsnt falls back to FIFO without its support
     pub(crate) fbm: FramebufferManager,
 
     prsnt_inp: bool,
+    queue_fams: QueueFamilies,
 
     // per renderpass
     renderpass: vk::RenderPass,
@@ -27,19 +30,21 @@ pub(crate) struct SwapchainManager {
 
 impl SwapchainManager {
     pub(crate) unsafe fn new(
-        dbv: DebugVisibility, vi: Rc<VulkanInstance>, device: Rc<Device>,
-        renderpass: vk::RenderPass, attachments: Vec<FBAttachmentRef>, prsnt_inp: bool,
-    ) -> Self {
+        vi: Rc<VulkanInstance>, device: Rc<Device>,
+        renderpass: vk::RenderPass, attachments: Vec<FBAttachmentRef>, prsnt_inp: bool, depth_fmt: vk::Format,
+        present_mode: PresentMode,
+    ) -> Result<Self, MatrixagonError> {
         // prsnt_inp: make the presentation attachment also an input attachment
 
-        let (capb, fmt, prsnt) = query_swapchain_support(dbv, &vi);
-        let (fmt, prsnt) = select_swapchain_support(fmt, prsnt);
+        let (capb, fmt, prsnt) = query_swapchain_support(&vi);
+        let (fmt, prsnt) = select_swapchain_support(fmt, prsnt, present_mode);
 
         let swapchain_loader = Swapchain::new(&vi.inst, &device.clone());
 
-        // assuming graphics and presentation queue families are the same ind.
+        let queue_fams = find_queue_families(&vi);
+        let (sharing_mode, queue_fam_inds) = queue_fams.sharing();
         let swapchain_create_info = vk::SwapchainCreateInfoKHR {
-            surface: vi.surf,
+            surface: vi.surf(),
             min_image_count: capb.min_image_count+1,
             image_format: fmt.format,
             image_color_space: fmt.color_space,
@@ -50,7 +55,9 @@ impl SwapchainManager {
             }  else {
                 vk::ImageUsageFlags::COLOR_ATTACHMENT
             },
-            image_sharing_mode: vk::SharingMode::EXCLUSIVE,
+            image_sharing_mode: sharing_mode,
+            queue_family_index_count: queue_fam_inds.len() as u32,
+            p_queue_family_indices: queue_fam_inds.as_ptr(),
             pre_transform: capb.current_transform,
             composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
             present_mode: prsnt,
@@ -61,46 +68,54 @@ impl SwapchainManager {
 
         let swapchain = [
             swapchain_loader.create_swapchain(&swapchain_create_info, None)
-                .expect("Failed to create swapchain")
+                .map_err(|e| MatrixagonError::Vulkan("create the swapchain", e))?
         ];
 
-        if dbv.vk_swapchain_output {
-            println!("Swapchain Object: {:?}", swapchain);
-        }
+        log::debug!(target: VK_SWAPCHAIN_LOG, "Swapchain Object: {:?}", swapchain);
 
         let swapchain_images = swapchain_loader.get_swapchain_images(swapchain[0])
-            .expect("Failed to get swapchain images");
+            .map_err(|e| MatrixagonError::Vulkan("get the swapchain images", e))?;
 
         let fbm = FramebufferManager::new_swapchain_bounded(
-            dbv, vi.clone(), device.clone(), renderpass, attachments.clone(), swapchain_images,
-            fmt.format, best_depth_format_support(), capb.current_extent, prsnt_inp
-        );
-
-        Self {
-            dbv, vi: vi.clone(), device: device.clone(),
-            loader: swapchain_loader, swapchain, extent: capb.current_extent, capb, fmt, prsnt, fbm,
-            prsnt_inp, renderpass, attachments,
-        }
+            vi.clone(), device.clone(), renderpass, attachments.clone(), swapchain_images,
+            fmt.format, depth_fmt, capb.current_extent, prsnt_inp
+        )?;
+
+        Ok(Self {
+            vi: vi.clone(), device: device.clone(),
+            loader: swapchain_loader, swapchain, extent: capb.current_extent, capb, fmt, depth_fmt, prsnt, present_mode, fbm,
+            prsnt_inp, queue_fams, renderpass, attachments,
+        })
     }
 
-    pub(crate) unsafe fn recreate(&mut self) {
-        let (capb, fmt, prsnt) = query_swapchain_support(self.dbv, &self.vi);
-        let (fmt, prsnt) = select_swapchain_support(fmt, prsnt);
+    // framebuffers of a recreated renderpass, only used once the swapchain is recreated
+    pub(crate) fn set_renderpass(&mut self, renderpass: vk::RenderPass, attachments: Vec<FBAttachmentRef>) {
+        self.renderpass = renderpass;
+        self.attachments = attachments;
+    }
+
+    // the requested extent (e.g. the window size) is only used when the surface leaves the extent up to the swapchain
+    pub(crate) unsafe fn recreate(&mut self, requested_extent: vk::Extent2D) {
+        let (capb, fmt, prsnt) = query_swapchain_support(&self.vi);
+        let (fmt, prsnt) = select_swapchain_support(fmt, prsnt, self.present_mode);
+        let extent = surface_extent(capb, requested_extent);
 
-        // assuming graphics and presentation queue families are the same ind.
+        let (sharing_mode, queue_fam_inds) = self.queue_fams.sharing();
         let swapchain_create_info = vk::SwapchainCreateInfoKHR {
-            surface: self.vi.surf,
+            surface: self.vi.surf(),
             min_image_count: capb.min_image_count+1,
             image_format: fmt.format,
             image_color_space: fmt.color_space,
-            image_extent: capb.current_extent,
+            image_extent: extent,
             image_array_layers: 1,
             image_usage: if self.prsnt_inp {
                 vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT
             } else {
                 vk::ImageUsageFlags::COLOR_ATTACHMENT
             },
-            image_sharing_mode: vk::SharingMode::EXCLUSIVE,
+            image_sharing_mode: sharing_mode,
+            queue_family_index_count: queue_fam_inds.len() as u32,
+            p_queue_family_indices: queue_fam_inds.as_ptr(),
             pre_transform: capb.current_transform,
             composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
             present_mode: prsnt,
@@ -114,23 +129,29 @@ impl SwapchainManager {
                 .expect("Failed to create swapchain")
         ];
 
-        if self.dbv.vk_swapchain_output {
-            println!("Swapchain Object: {:?}", swapchain);
-        }
+        log::debug!(target: VK_SWAPCHAIN_LOG, "Swapchain Object: {:?}", swapchain);
 
         let swapchain_images = self.loader.get_swapchain_images(swapchain[0])
             .expect("Failed to get swapchain images");
 
         let fbm = FramebufferManager::new_swapchain_bounded(
-            self.dbv, self.vi.clone(), self.device.clone(), self.renderpass, self.attachments.clone(), swapchain_images,
-            fmt.format, best_depth_format_support(), capb.current_extent, self.prsnt_inp
-        );
+            self.vi.clone(), self.device.clone(), self.renderpass, self.attachments.clone(), swapchain_images,
+            fmt.format, self.depth_fmt, extent, self.prsnt_inp
+        ).expect("Failed to recreate framebuffers");
 
         self.device.device_wait_idle().unwrap();
         self.destroy();
 
         self.fbm = fbm;
         self.swapchain = swapchain;
+        self.extent = extent;
+        self.capb = capb;
+        self.fmt = fmt;
+        self.prsnt = prsnt;
+    }
+
+    pub(crate) fn diagnostics(&self, diagnostics: &mut Diagnostics) {
+        diagnostics.swapchain(self.fmt, self.depth_fmt, self.prsnt, self.extent);
     }
 
     pub(crate) unsafe fn destroy(&self) {
@@ -141,22 +162,31 @@ impl SwapchainManager {
 }
 
 
-pub(crate) unsafe fn query_swapchain_support(dbgv: DebugVisibility, vi: &VulkanInstance)
+// a current extent of u32::MAX means the surface size is determined by the swapchain extent
+fn surface_extent(capb: vk::SurfaceCapabilitiesKHR, requested_extent: vk::Extent2D) -> vk::Extent2D {
+    if capb.current_extent.width != u32::MAX {
+        return capb.current_extent;
+    }
+    vk::Extent2D {
+        width: requested_extent.width.clamp(capb.min_image_extent.width, capb.max_image_extent.width),
+        height: requested_extent.height.clamp(capb.min_image_extent.height, capb.max_image_extent.height),
+    }
+}
+
+pub(crate) unsafe fn query_swapchain_support(vi: &VulkanInstance)
                                   -> (vk::SurfaceCapabilitiesKHR, Vec<vk::SurfaceFormatKHR>, Vec<vk::PresentModeKHR>) {
     let capabilities = vi.get_physical_device_surface_capabilities();
     let formats = vi.get_physical_device_surface_formats();
     let present_modes = vi.get_physical_device_surface_present_modes();
-    if dbgv.vk_swapchain_output {
-        println!("Supported Surface capabilities: {:?}", capabilities);
-        println!("Supported Surface formats: {:?}", formats);
-        println!("Supported Surface presentation modes: {:?}", present_modes);
-    }
+    log::trace!(target: VK_SWAPCHAIN_LOG, "Supported Surface capabilities: {:?}", capabilities);
+    log::trace!(target: VK_SWAPCHAIN_LOG, "Supported Surface formats: {:?}", formats);
+    log::trace!(target: VK_SWAPCHAIN_LOG, "Supported Surface presentation modes: {:?}", present_modes);
 
     (capabilities, formats, present_modes)
 }
 
-unsafe fn select_swapchain_support(surf_fmt: Vec<vk::SurfaceFormatKHR>, prsnt_mode: Vec<vk::PresentModeKHR>)
-                                   -> (vk::SurfaceFormatKHR, vk::PresentModeKHR) {
+unsafe fn select_swapchain_support(surf_fmt: Vec<vk::SurfaceFormatKHR>, prsnt_mode: Vec<vk::PresentModeKHR>,
+                                   present_mode: PresentMode) -> (vk::SurfaceFormatKHR, vk::PresentModeKHR) {
     // generally, you want to choose the best format, presentation mode, and extent
     // but we'll just assume :)
 
@@ -164,21 +194,155 @@ unsafe fn select_swapchain_support(surf_fmt: Vec<vk::SurfaceFormatKHR>, prsnt_mo
         format: vk::Format::B8G8R8A8_SRGB,
         color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
     };
-    let prsnt_mode = vk::PresentModeKHR::MAILBOX;
+    let prsnt_mode = select_present_mode(present_mode, &prsnt_mode);
 
     (format, prsnt_mode)
 }
 
-unsafe fn best_depth_format_support() -> vk::Format {
-    // TODO
+// present modes that can be requested, in place of exposing the vulkan present modes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PresentMode {
+    Fifo,  // vsync, capping the frame rate to the refresh rate
+    Mailbox,  // no tearing, replacing the queued image with newer frames
+    Immediate,  // uncapped, may tear
+}
+
+impl PresentMode {
+    pub(crate) fn vk_present_mode(&self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+
+    // cycled through at runtime
+    pub(crate) fn next(&self) -> Self {
+        match self {
+            PresentMode::Fifo => PresentMode::Mailbox,
+            PresentMode::Mailbox => PresentMode::Immediate,
+            PresentMode::Immediate => PresentMode::Fifo,
+        }
+    }
+}
+
+// FIFO is the only present mode every surface supports
+fn select_present_mode(requested: PresentMode, supported: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+    let prsnt_mode = requested.vk_present_mode();
+    if supported.contains(&prsnt_mode) {
+        prsnt_mode
+    } else {
+        log::warn!("Present mode {requested:?} is not supported, using FIFO instead");
+        vk::PresentModeKHR::FIFO
+    }
+}
+
+// depth buffer formats that can be requested, in place of exposing the vulkan formats
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DepthFormat {
+    D32,
+    D32S8,
+    D24S8,
+    D16,
+}
+
+impl DepthFormat {
+    // tried in order when no preference is given, the float formats come first since the reversed depth
+    // relies on their precision near 0 (the far plane)
+    pub(crate) const DEFAULT_PREFERENCE: [DepthFormat; 4] = [DepthFormat::D32, DepthFormat::D32S8, DepthFormat::D24S8, DepthFormat::D16];
+
+    pub(crate) fn vk_format(&self) -> vk::Format {
+        match self {
+            DepthFormat::D32 => vk::Format::D32_SFLOAT,
+            DepthFormat::D32S8 => vk::Format::D32_SFLOAT_S8_UINT,
+            DepthFormat::D24S8 => vk::Format::D24_UNORM_S8_UINT,
+            DepthFormat::D16 => vk::Format::D16_UNORM,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, DepthFormat::D32 | DepthFormat::D32S8)
+    }
+}
+
+// the first supported format of the preference, falling back to the first supported default format
+fn select_depth_format(preference: &[DepthFormat], supported: impl Fn(vk::Format) -> bool) -> DepthFormat {
+    preference.iter()
+        .chain(DepthFormat::DEFAULT_PREFERENCE.iter())
+        .find(|depth_fmt| supported(depth_fmt.vk_format()))
+        .copied()
+        .unwrap_or(DepthFormat::D32)  // D32 or D24S8 has to be supported
+}
+
+pub(crate) unsafe fn best_depth_format_support(vi: &VulkanInstance, preference: &[DepthFormat]) -> vk::Format {
+    let depth_fmt = select_depth_format(preference, |fmt| {
+        vi.get_physical_device_format_properties(fmt).optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    });
+    if preference.first().is_some_and(|preferred| *preferred != depth_fmt) {
+        log::warn!(target: VK_SWAPCHAIN_LOG, "Preferred depth format {:?} is not supported, using {depth_fmt:?} instead",
+                   preference[0]);
+    }
+    if !depth_fmt.is_float() {
+        log::warn!("Depth format {depth_fmt:?} is not a float format, distant geometry may z-fight with the reversed depth");
+    }
 
-    vk::Format::D32_SFLOAT
+    depth_fmt.vk_format()
 }
 
-pub(crate) fn best_surface_color_and_depth_format(dbv: DebugVisibility, vi: Rc<VulkanInstance>) -> (vk::Format, vk::Format) {
+// an empty depth format preference uses the default order
+pub(crate) fn best_surface_color_and_depth_format(vi: Rc<VulkanInstance>, depth_formats: &[DepthFormat])
+    -> (vk::Format, vk::Format) {
     unsafe {
-        let (_, fmt, prsnt) = query_swapchain_support(dbv, &vi);
-        let (fmt, _) = select_swapchain_support(fmt, prsnt);
-        (fmt.format, best_depth_format_support())
+        let (_, fmt, prsnt) = query_swapchain_support(&vi);
+        let (fmt, _) = select_swapchain_support(fmt, prsnt, PresentMode::Fifo);
+        (fmt.format, best_depth_format_support(&vi, depth_formats))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_supported_preferred_depth_format() {
+        let supported = [vk::Format::D24_UNORM_S8_UINT, vk::Format::D16_UNORM, vk::Format::D32_SFLOAT_S8_UINT];
+        let is_supported = |fmt| supported.contains(&fmt);
+
+        assert_eq!(select_depth_format(&[DepthFormat::D32, DepthFormat::D16, DepthFormat::D24S8], is_supported), DepthFormat::D16);
+        assert_eq!(select_depth_format(&[DepthFormat::D24S8], is_supported), DepthFormat::D24S8);
+        // falls back to the default order, which prefers the float formats
+        assert_eq!(select_depth_format(&[], is_supported), DepthFormat::D32S8);
+        assert_eq!(select_depth_format(&[DepthFormat::D32], is_supported), DepthFormat::D32S8);
+    }
+
+    #[test]
+    fn test_present_mode_falls_back_to_fifo() {
+        let supported = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+        assert_eq!(select_present_mode(PresentMode::Immediate, &supported), vk::PresentModeKHR::IMMEDIATE);
+        assert_eq!(select_present_mode(PresentMode::Mailbox, &supported), vk::PresentModeKHR::FIFO);
+        assert_eq!(select_present_mode(PresentMode::Fifo, &supported), vk::PresentModeKHR::FIFO);
+
+        // cycles back around to vsync
+        assert_eq!(PresentMode::Fifo.next().next().next(), PresentMode::Fifo);
+    }
+
+    #[test]
+    fn test_surface_extent_follows_window_when_undefined() {
+        let capb = vk::SurfaceCapabilitiesKHR {
+            current_extent: vk::Extent2D { width: 1280, height: 720 },
+            min_image_extent: vk::Extent2D { width: 1, height: 1 },
+            max_image_extent: vk::Extent2D { width: 4096, height: 4096 },
+            ..Default::default()
+        };
+        let requested = vk::Extent2D { width: 800, height: 600 };
+        // the surface dictates the extent
+        assert_eq!(surface_extent(capb, requested), capb.current_extent);
+
+        let capb = vk::SurfaceCapabilitiesKHR { current_extent: vk::Extent2D { width: u32::MAX, height: u32::MAX }, ..capb };
+        assert_eq!(surface_extent(capb, requested), requested);
+        // within the supported image extents
+        assert_eq!(surface_extent(capb, vk::Extent2D { width: 8000, height: 0 }), vk::Extent2D { width: 4096, height: 1 });
     }
 }
@@ -1,22 +1,30 @@
-use std::{ffi, mem};
+use std::{ffi, mem, thread};
+use std::hash::Hash;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::ops::Range;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
 use ash::{Device, vk};
-use crate::handler::VulkanInstance;
+use matrixagon_util::Garbage;
+use crate::handler::{MAX_FRAMES_IN_FLIGHT, VulkanInstance};
 
 // column major
 pub type Mat4 = [[f32;4];4];
 pub type Vec4 = [f32;4];
 
-// pub(crate) fn matrix_prod_vector(a: Mat4, b: Vec4) -> Vec4 {
-//     // let m = |aa: usize| a[aa][0]*b[0]+a[aa][1]*b[1]+a[aa][2]*b[2]+a[aa][3]*b[3];
-//     let m = |aa: usize| a[0][aa]*b[0]+a[1][aa]*b[1]+a[2][aa]*b[2]+a[3][aa]*b[3];
-//     [
-//         m(0),
-//         m(1),
-//         m(2),
-//         m(3),
-//     ]
-// }
+pub(crate) fn matrix_prod_vector(a: Mat4, b: Vec4) -> Vec4 {
+    let m = |aa: usize| a[0][aa]*b[0]+a[1][aa]*b[1]+a[2][aa]*b[2]+a[3][aa]*b[3];
+    [
+        m(0),
+        m(1),
+        m(2),
+        m(3),
+    ]
+}
 
 pub(crate) fn matrix_prod(a: Mat4, b: Mat4) -> Mat4 {
     let m = |aa: usize, bb: usize| a[aa][0]*b[0][bb]+a[aa][1]*b[1][bb]+a[aa][2]*b[2][bb]+a[aa][3]*b[3][bb];
@@ -29,6 +37,16 @@ pub(crate) fn matrix_prod(a: Mat4, b: Mat4) -> Mat4 {
     ]
 }
 
+pub(crate) fn matrix_transpose(a: Mat4) -> Mat4 {
+    let m = |aa: usize, bb: usize| a[bb][aa];
+    [
+        [m(0,0), m(0,1), m(0,2), m(0,3)],
+        [m(1,0), m(1,1), m(1,2), m(1,3)],
+        [m(2,0), m(2,1), m(2,2), m(2,3)],
+        [m(3,0), m(3,1), m(3,2), m(3,3)],
+    ]
+}
+
 pub(crate) fn matrix_ident() -> Mat4 {
     [
         [1.0, 0.0, 0.0, 0.0],
@@ -51,7 +69,16 @@ pub(crate) fn matrix_ident() -> Mat4 {
 pub struct CmdBufContext(pub(crate) Rc<Device>, pub(crate) vk::CommandPool, pub(crate) vk::Queue);
 
 impl CmdBufContext {
-    pub(crate) unsafe fn record<C: FnMut(vk::CommandBuffer) -> ()>(&self, mut record: C) {
+    pub(crate) unsafe fn record<C: FnMut(vk::CommandBuffer) -> ()>(&self, record: C) {
+        let (cmd_buf, fence) = self.record_fenced(record);
+
+        self.0.wait_for_fences(&[fence], true, u64::MAX).unwrap();
+
+        self.finalize(cmd_buf, fence);
+    }
+
+    // submits without blocking, the returned fence signals once the recorded commands completed
+    pub(crate) unsafe fn record_fenced<C: FnMut(vk::CommandBuffer) -> ()>(&self, mut record: C) -> (vk::CommandBuffer, vk::Fence) {
         let cmd_alloc_info = vk::CommandBufferAllocateInfo {
             command_pool: self.1,
             level: vk::CommandBufferLevel::PRIMARY,
@@ -78,12 +105,532 @@ impl CmdBufContext {
                 .command_buffers(&cmd_bufs).build()
         ];
 
-        self.0.queue_submit(self.2, &submit_infos, vk::Fence::null())
+        let fence = self.0.create_fence(&vk::FenceCreateInfo::default(), None)
+            .expect("Failed to create transfer fence");
+
+        self.0.queue_submit(self.2, &submit_infos, fence)
             .expect("Failed to submit draw command buffer to graphics queue");
 
-        self.0.queue_wait_idle(self.2).unwrap();
+        (cmd_bufs[0], fence)
+    }
+
+    pub(crate) unsafe fn fence_signaled(&self, fence: vk::Fence) -> bool {
+        self.0.get_fence_status(fence).unwrap_or(false)
+    }
+
+    // releases the command buffer and fence of a completed submission
+    pub(crate) unsafe fn finalize(&self, cmd_buf: vk::CommandBuffer, fence: vk::Fence) {
+        self.0.free_command_buffers(self.1, &[cmd_buf]);
+        self.0.destroy_fence(fence, None);
+    }
+}
+
+// submitted uploads along with whatever needs to be finalized once their transfer fence signals
+pub(crate) struct PendingUploads<T> {
+    pending: Vec<(vk::Fence, T)>,
+}
+
+impl<T> PendingUploads<T> {
+    pub(crate) fn new() -> Self {
+        Self { pending: Vec::new() }
+    }
+
+    pub(crate) fn push(&mut self, fence: vk::Fence, payload: T) {
+        self.pending.push((fence, payload));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {self.pending.is_empty()}
+
+    // calls complete for every upload whose fence has signaled, returns the number of completed uploads
+    pub(crate) fn poll<S, C>(&mut self, mut signaled: S, mut complete: C) -> usize
+        where S: FnMut(vk::Fence) -> bool, C: FnMut(vk::Fence, T) {
+        let mut completed = 0;
+        let mut still_pending = Vec::with_capacity(self.pending.len());
+
+        for (fence, payload) in self.pending.drain(..) {
+            if signaled(fence) {
+                complete(fence, payload);
+                completed += 1;
+            } else {
+                still_pending.push((fence, payload));
+            }
+        }
+        self.pending = still_pending;
+
+        completed
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.pending.iter_mut().map(|(_, payload)| payload)
+    }
+
+    // hands back every upload regardless of its fence (e.g. for cleanup after the device is idle)
+    pub(crate) fn drain(&mut self) -> Vec<(vk::Fence, T)> {
+        mem::take(&mut self.pending)
+    }
+}
+
+// a host visible uniform (or storage) buffer per frame in flight, the latest value is written into the buffer of a frame
+// only once the frame is no longer in flight, so the pending frames keep reading the value they were recorded with
+pub(crate) struct FrameUniform<T> {
+    device: Rc<Device>,
+    buffers: Vec<(vk::Buffer, vk::DeviceMemory, *mut ffi::c_void, vk::DeviceSize)>,
+    value: T,
+}
+
+impl<T: Copy> FrameUniform<T> {
+    pub(crate) unsafe fn new(vi: Rc<VulkanInstance>, device: Rc<Device>, value: T) -> Self {
+        Self::with_usage(vi, device, value, vk::BufferUsageFlags::UNIFORM_BUFFER)
+    }
+
+    pub(crate) unsafe fn with_usage(vi: Rc<VulkanInstance>, device: Rc<Device>, value: T, usage: vk::BufferUsageFlags) -> Self {
+        let buffers = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+            create_host_buffer(vi.clone(), device.clone(), &[value], usage, false)
+        }).collect();
+        Self { device, buffers, value }
+    }
+
+    pub(crate) fn set(&mut self, value: T) {
+        self.value = value;
+    }
+
+    // of the frame about to be recorded
+    pub(crate) unsafe fn write(&self, frame_index: usize) {
+        let (_, _, ptr, size) = self.buffers[frame_index];
+        update_buffer(ptr, &[self.value], size);
+    }
+
+    // one per frame in flight, in the order of the frames
+    pub(crate) fn descriptor_buffer_infos(&self) -> Vec<vk::DescriptorBufferInfo> {
+        self.buffers.iter().map(|&(buffer, _, _, _)| vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range: mem::size_of::<T>() as vk::DeviceSize,
+        }).collect()
+    }
+
+    pub(crate) unsafe fn destroy(&self) {
+        for &(buf, mem, _, _) in &self.buffers {
+            self.device.destroy_buffer(buf, None);
+            self.device.free_memory(mem, None);
+        }
+    }
+}
+
+// capacity to allocate for data of the given size, at least double the previous capacity so that a growing mesh
+// only reallocates a logarithmic number of times
+pub(crate) fn grown_capacity(capacity: vk::DeviceSize, size: vk::DeviceSize) -> vk::DeviceSize {
+    size.max(capacity*2).next_power_of_two()
+}
+
+// device local buffers written again when the new data fits, instead of allocating new ones on every upload
+// the pool owns its buffers (the shader never frees them), a buffer handed to the shader is drawn until the next one of
+// its key is handed over, so only the replaced buffers are written again once the frames drawing them completed
+pub(crate) struct BufferPool<K> {
+    buffers: HashMap<vk::Buffer, (K, vk::DeviceMemory, vk::DeviceSize)>,  // every buffer of the pool, with its capacity
+    drawn: HashMap<K, vk::Buffer>,
+    free: Vec<vk::Buffer>,  // not drawn by any frame in flight
+    retired: Garbage<vk::Buffer>,
+    outgrown: Vec<(vk::Buffer, vk::DeviceMemory)>,  // free buffers too small for the latest data of their key
+    allocations: usize,
+}
+
+impl<K: Hash + Eq + Clone> BufferPool<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffers: HashMap::new(), drawn: HashMap::new(), free: Vec::new(), retired: Garbage::new(),
+            outgrown: Vec::new(), allocations: 0,
+        }
+    }
+
+    // a free buffer of the key if the size fits, otherwise a new one from allocate given its capacity (larger once
+    // the data outgrew the buffers of the key), along with whether the buffer was reused
+    pub(crate) fn acquire<A>(&mut self, key: K, size: vk::DeviceSize, allocate: A) -> (vk::Buffer, vk::DeviceMemory, bool)
+        where A: FnOnce(vk::DeviceSize) -> (vk::Buffer, vk::DeviceMemory) {
+        let buffers = &self.buffers;
+        let fits = self.free.iter().position(|buf| matches!(&buffers[buf], (k, _, capacity) if *k == key && size <= *capacity));
+        if let Some(ind) = fits {
+            let buf = self.free.swap_remove(ind);
+            return (buf, self.buffers[&buf].1, true);
+        }
+
+        let mut largest = 0;
+        for (_, _, capacity) in self.buffers.values().filter(|(k, _, _)| *k == key) {
+            largest = largest.max(*capacity);
+        }
+        let capacity = if size <= largest {largest} else {grown_capacity(largest, size)};
+        // none of the free buffers of the key fit anymore
+        let (outgrown, free): (Vec<_>, Vec<_>) = mem::take(&mut self.free).into_iter()
+            .partition(|buf| self.buffers[buf].0 == key);
+        self.free = free;
+        for buf in outgrown {
+            let (_, mem, _) = self.buffers.remove(&buf).unwrap();
+            self.outgrown.push((buf, mem));
+        }
+
+        let (buf, mem) = allocate(capacity);
+        self.buffers.insert(buf, (key, mem, capacity));
+        self.allocations += 1;
+        (buf, mem, false)
+    }
+
+    // once the acquired buffer is handed to the shader, retiring the buffer of its key drawn before
+    pub(crate) fn hand_over(&mut self, buf: vk::Buffer) {
+        let key = self.buffers[&buf].0.clone();
+        if let Some(prev) = self.drawn.insert(key, buf).filter(|prev| *prev != buf) {
+            self.retired.retire(prev);
+        }
+    }
+
+    // an acquired buffer never handed to the shader (e.g. its upload was cleared)
+    pub(crate) fn release(&mut self, buf: vk::Buffer) {
+        self.free.push(buf);
+    }
+
+    // once the shader no longer draws the buffer of the key (e.g. when clearing it)
+    pub(crate) fn clear(&mut self, key: &K) {
+        if let Some(prev) = self.drawn.remove(key) {
+            self.retired.retire(prev);
+        }
+    }
+
+    // once the fence of the frame signaled, the buffers it drew last can be written again
+    pub(crate) fn collect(&mut self, frame_index: usize) {
+        self.free.extend(self.retired.collect(frame_index));
+    }
+
+    // to be freed, no frame draws them anymore
+    pub(crate) fn take_outgrown(&mut self) -> Vec<(vk::Buffer, vk::DeviceMemory)> {
+        mem::take(&mut self.outgrown)
+    }
+
+    // every buffer, to be freed once the device is idle
+    pub(crate) fn drain(&mut self) -> Vec<(vk::Buffer, vk::DeviceMemory)> {
+        self.drawn.clear();
+        self.free.clear();
+        self.retired = Garbage::new();
+        let mut buffers = self.take_outgrown();
+        buffers.extend(self.buffers.drain().map(|(buf, (_, mem, _))| (buf, mem)));
+        buffers
+    }
+
+    pub(crate) fn allocations(&self) -> usize {self.allocations}
+}
+
+// a range out of one of the blocks of a suballocator
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct SubAllocation {
+    pub(crate) buf: vk::Buffer,
+    pub(crate) offset: vk::DeviceSize,
+    pub(crate) size: vk::DeviceSize,
+}
+
+struct Block {
+    buf: vk::Buffer,
+    mem: vk::DeviceMemory,
+    capacity: vk::DeviceSize,
+    free: Vec<Range<vk::DeviceSize>>,  // sorted by their start, adjacent ones merged
+}
+
+impl Block {
+    fn take(&mut self, size: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        let ind = self.free.iter().position(|range| range.end-range.start >= size)?;
+        let offset = self.free[ind].start;
+        self.free[ind].start += size;
+        if self.free[ind].is_empty() {
+            self.free.remove(ind);
+        }
+        Some(offset)
+    }
+
+    fn give_back(&mut self, range: Range<vk::DeviceSize>) {
+        let ind = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(ind, range);
+        if ind+1 < self.free.len() && self.free[ind].end == self.free[ind+1].start {
+            self.free[ind].end = self.free.remove(ind+1).end;
+        }
+        if ind > 0 && self.free[ind-1].end == self.free[ind].start {
+            self.free[ind-1].end = self.free.remove(ind).end;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.free.first().is_some_and(|range| range.end-range.start == self.capacity)
+    }
+}
+
+// ranges out of a few large device local blocks instead of an allocation each (every allocation counts towards
+// maxMemoryAllocationCount, often 4096), a new block is allocated once none of the free ranges fit, at least double
+// the largest block so far
+// a range drawn by the frames in flight is retired, and only allocated again once the frames drawing it completed
+pub(crate) struct SubAllocator {
+    blocks: Vec<Block>,
+    retired: Garbage<SubAllocation>,
+    emptied: Vec<(vk::Buffer, vk::DeviceMemory)>,  // blocks smaller than the largest one with nothing allocated anymore
+    min_capacity: vk::DeviceSize,
+    alignment: vk::DeviceSize,  // of the offsets and sizes of the ranges
+    allocations: usize,
+}
+
+impl SubAllocator {
+    pub(crate) fn new(min_capacity: vk::DeviceSize, alignment: vk::DeviceSize) -> Self {
+        Self {
+            blocks: Vec::new(), retired: Garbage::new(), emptied: Vec::new(), min_capacity, alignment, allocations: 0,
+        }
+    }
+
+    // the first free range the size fits in, otherwise out of a new block from allocate given its capacity
+    pub(crate) fn allocate<A>(&mut self, size: vk::DeviceSize, allocate: A) -> SubAllocation
+        where A: FnOnce(vk::DeviceSize) -> (vk::Buffer, vk::DeviceMemory) {
+        let size = size.max(1).next_multiple_of(self.alignment);
+        for block in &mut self.blocks {
+            if let Some(offset) = block.take(size) {
+                return SubAllocation { buf: block.buf, offset, size };
+            }
+        }
+
+        let largest = self.blocks.iter().map(|block| block.capacity).max().unwrap_or(0);
+        let capacity = grown_capacity(largest, size.max(self.min_capacity));
+        let (buf, mem) = allocate(capacity);
+        let mut block = Block { buf, mem, capacity, free: Vec::new() };
+        block.give_back(0..capacity);
+        let offset = block.take(size).unwrap();
+        self.blocks.push(block);
+        self.allocations += 1;
+        SubAllocation { buf, offset, size }
+    }
+
+    // a range never drawn (e.g. its upload was cleared), free right away
+    pub(crate) fn free(&mut self, allocation: SubAllocation) {
+        if let Some(block) = self.blocks.iter_mut().find(|block| block.buf == allocation.buf) {
+            block.give_back(allocation.offset..allocation.offset+allocation.size);
+        }
+    }
+
+    // once the shader no longer draws the range
+    pub(crate) fn retire(&mut self, allocation: SubAllocation) {
+        self.retired.retire(allocation);
+    }
+
+    // once the fence of the frame signaled, the ranges it drew last can be allocated again
+    pub(crate) fn collect(&mut self, frame_index: usize) {
+        let retired = self.retired.collect(frame_index);
+        if retired.is_empty() {
+            return;
+        }
+        for allocation in retired {
+            self.free(allocation);
+        }
+        let largest = self.blocks.iter().map(|block| block.capacity).max().unwrap_or(0);
+        let (emptied, blocks) = mem::take(&mut self.blocks).into_iter()
+            .partition(|block| block.is_empty() && block.capacity < largest);
+        self.blocks = blocks;
+        self.emptied.extend(emptied.into_iter().map(|block: Block| (block.buf, block.mem)));
+    }
+
+    // to be freed, nothing is allocated out of them anymore
+    pub(crate) fn take_emptied(&mut self) -> Vec<(vk::Buffer, vk::DeviceMemory)> {
+        mem::take(&mut self.emptied)
+    }
+
+    // every block, to be freed once the device is idle
+    pub(crate) fn drain(&mut self) -> Vec<(vk::Buffer, vk::DeviceMemory)> {
+        self.retired = Garbage::new();
+        let mut blocks = self.take_emptied();
+        blocks.extend(self.blocks.drain(..).map(|block| (block.buf, block.mem)));
+        blocks
+    }
+
+    pub(crate) fn allocations(&self) -> usize {self.allocations}
+}
+
+// caps the frame rate for present modes that don't wait on the display (e.g. mailbox or immediate)
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FramePacer {
+    frame_duration: Duration,
+}
+
+impl FramePacer {
+    // sleeping tends to overshoot by about a scheduler tick, so the last bit is spun instead
+    const SPIN_DURATION: Duration = Duration::from_millis(1);
+
+    pub(crate) fn new(max_fps: u32) -> Self {
+        Self { frame_duration: Duration::from_secs_f64(1.0/max_fps.max(1) as f64) }
+    }
+
+    // how much longer the frame has to take to not exceed the cap
+    pub(crate) fn remaining(&self, elapsed: Duration) -> Duration {
+        self.frame_duration.saturating_sub(elapsed)
+    }
+
+    pub(crate) fn wait(&self, frame_start: Instant) {
+        let remaining = self.remaining(frame_start.elapsed());
+        if remaining > Self::SPIN_DURATION {
+            thread::sleep(remaining-Self::SPIN_DURATION);
+        }
+        while !self.remaining(frame_start.elapsed()).is_zero() {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+// holds the cursor at the window center, only while the window is focused so other windows can still use it
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct MouseLock {
+    enabled: bool,  // toggled by the user
+    focused: bool,
+}
+
+impl MouseLock {
+    pub(crate) fn new(enabled: bool, focused: bool) -> Self {
+        Self { enabled, focused }
+    }
+
+    pub(crate) fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub(crate) fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    pub(crate) fn focused(&self) -> bool {
+        self.focused
+    }
+
+    // whether the cursor is recentered this frame
+    pub(crate) fn locked(&self) -> bool {
+        self.enabled && self.focused
+    }
+}
 
-        self.0.free_command_buffers(self.1, &cmd_bufs)
+// pauses the rendering while the window can't be seen (or isn't focused, if set to), instead of drawing unseen frames
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RenderPause {
+    pause_unfocused: bool,
+    occluded: bool,
+    focused: bool,
+}
+
+impl RenderPause {
+    pub(crate) fn new(pause_unfocused: bool, focused: bool) -> Self {
+        Self { pause_unfocused, occluded: false, focused }
+    }
+
+    pub(crate) fn set_occluded(&mut self, occluded: bool) {
+        self.occluded = occluded;
+    }
+
+    pub(crate) fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    pub(crate) fn paused(&self) -> bool {
+        self.occluded || (self.pause_unfocused && !self.focused)
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// spreads the chunk generation jobs across a fixed number of worker threads
+// the background jobs are queued to long lived workers, shared by the clones of the pool and exiting once every clone
+// is dropped, so dispatching the chunks of a boundary crossing doesn't start any thread
+#[derive(Clone, Debug)]
+pub struct WorkerPool {
+    workers: usize,  // 0 runs every job on the calling thread (deterministic, e.g. for testing)
+    jobs: Option<mpsc::Sender<Job>>,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize) -> Self {
+        if workers == 0 {
+            return Self { workers, jobs: None };
+        }
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for ind in 0..workers {
+            let queue = queue.clone();
+            thread::Builder::new()
+                .name(format!("chunk worker {ind}"))
+                .spawn(move || loop {
+                    let job = queue.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("Failed to spawn a chunk generation worker");
+        }
+        Self { workers, jobs: Some(jobs) }
+    }
+
+    // leaves one core for the main (render) thread
+    pub(crate) fn default_workers() -> usize {
+        thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1)-1
+    }
+
+    // runs the job for every item and returns the results in the order of the items
+    // on threads of its own, rather than waiting behind the background jobs queued to the workers
+    pub(crate) fn run<I, R, F>(&self, items: &[I], job: F) -> Vec<R>
+        where I: Sync, R: Send, F: Fn(&I) -> R + Sync {
+        if self.workers == 0 || items.len() <= 1 {
+            return items.iter().map(job).collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<(usize, R)> = thread::scope(|s| {
+            let handles: Vec<_> = (0..self.workers.min(items.len()))
+                .map(|_| s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let ind = next.fetch_add(1, Ordering::Relaxed);
+                        if ind >= items.len() {
+                            break;
+                        }
+                        done.push((ind, job(&items[ind])));
+                    }
+                    done
+                }))
+                .collect();
+            handles.into_iter()
+                .flat_map(|handle| handle.join().expect("Chunk generation worker panicked"))
+                .collect()
+        });
+
+        results.sort_by_key(|(ind, _)| *ind);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+
+    // runs the job for every item in the background without waiting for it, the results are sent back along with
+    // their items as they complete (dropping the receiver stops the remaining jobs)
+    pub(crate) fn spawn<I, R, F>(&self, items: Vec<I>, job: F) -> Receiver<(I, R)>
+        where I: Send + 'static, R: Send + 'static, F: Fn(&I) -> R + Send + Sync + 'static {
+        let (sender, receiver) = mpsc::channel();
+        let Some(jobs) = &self.jobs else {
+            for item in items {
+                let r = job(&item);
+                let _ = sender.send((item, r));
+            }
+            return receiver;
+        };
+
+        // the remaining items are skipped once a result can't be sent back
+        let job = Arc::new(job);
+        let dropped = Arc::new(AtomicBool::new(false));
+        for item in items {
+            let (job, sender, dropped) = (job.clone(), sender.clone(), dropped.clone());
+            let queued = jobs.send(Box::new(move || {
+                if dropped.load(Ordering::Relaxed) {
+                    return;
+                }
+                let r = job(&item);
+                if sender.send((item, r)).is_err() {
+                    dropped.store(true, Ordering::Relaxed);
+                }
+            }));
+            queued.expect("Chunk generation workers exited");
+        }
+        receiver
     }
 }
 
@@ -130,6 +677,28 @@ pub(crate) unsafe fn create_local_image(
     (img, img_mem)
 }
 
+// an attachment only used within the renderpass (e.g. resolved into a single sampled one)
+pub(crate) unsafe fn create_multisampled_image(
+    vi: Rc<VulkanInstance>, device: Rc<Device>, img_extent: vk::Extent3D, format: vk::Format, usage: vk::ImageUsageFlags,
+    samples: vk::SampleCountFlags,
+) -> (vk::Image, vk::DeviceMemory) {
+    let image_info = vk::ImageCreateInfo {
+        image_type: vk::ImageType::TYPE_2D,
+        extent: img_extent,
+        mip_levels: 1,
+        array_layers: 1,
+        format,
+        tiling: vk::ImageTiling::OPTIMAL,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        usage: usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        samples,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        ..Default::default()
+    };
+
+    allocate_image(vi, device, &image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+}
+
 pub(crate) unsafe fn allocate_image(
     vi: Rc<VulkanInstance>, device: Rc<Device>, image_info: &vk::ImageCreateInfo,
     props: vk::MemoryPropertyFlags
@@ -233,3 +802,248 @@ pub(crate) unsafe fn find_memory_type(vi: Rc<VulkanInstance>, mem_req: vk::Memor
     }
     panic!("No suitable memory found with the given requirements")
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use ash::vk::Handle;
+    use super::*;
+
+    #[test]
+    fn test_render_pause() {
+        let mut pause = RenderPause::new(true, true);
+        assert!(!pause.paused());
+        pause.set_focused(false);
+        assert!(pause.paused());
+        pause.set_focused(true);
+        pause.set_occluded(true);
+        assert!(pause.paused());
+        pause.set_occluded(false);
+        assert!(!pause.paused());
+
+        // keeps rendering in the background unless occluded
+        let mut background = RenderPause::new(false, false);
+        assert!(!background.paused());
+        background.set_occluded(true);
+        assert!(background.paused());
+    }
+
+    #[test]
+    fn test_mouse_lock_follows_focus() {
+        let mut lock = MouseLock::new(true, true);
+        assert!(lock.locked());
+
+        // released while another window is focused, and restored on regaining the focus
+        lock.set_focused(false);
+        assert!(!lock.locked());
+        lock.set_focused(true);
+        assert!(lock.locked());
+
+        // toggling it off stays off regardless of the focus
+        lock.toggle();
+        assert!(!lock.locked());
+        lock.set_focused(false);
+        lock.set_focused(true);
+        assert!(!lock.locked());
+
+        // toggling it on while unfocused only locks once focused
+        lock.set_focused(false);
+        lock.toggle();
+        assert!(!lock.locked());
+        lock.set_focused(true);
+        assert!(lock.locked());
+    }
+
+    #[test]
+    fn test_upload_completes_after_fence_signals() {
+        let fence_a = vk::Fence::from_raw(1);
+        let fence_b = vk::Fence::from_raw(2);
+
+        let mut uploads = PendingUploads::new();
+        uploads.push(fence_a, "opaque");
+        uploads.push(fence_b, "translucent");
+
+        let mut signaled_fences = vec![];
+        let mut completed = vec![];
+
+        // nothing fires while the transfers are still in flight
+        assert_eq!(uploads.poll(|f| signaled_fences.contains(&f), |_, p| completed.push(p)), 0);
+        assert!(completed.is_empty());
+
+        signaled_fences.push(fence_a);
+        assert_eq!(uploads.poll(|f| signaled_fences.contains(&f), |_, p| completed.push(p)), 1);
+        assert_eq!(completed, vec!["opaque"]);
+
+        // already completed uploads do not fire again
+        signaled_fences.push(fence_b);
+        assert_eq!(uploads.poll(|f| signaled_fences.contains(&f), |_, p| completed.push(p)), 1);
+        assert_eq!(completed, vec!["opaque", "translucent"]);
+        assert!(uploads.is_empty());
+    }
+
+    #[test]
+    fn test_worker_pool_spawns_configured_workers() {
+        let workers = 4;
+        let pool = WorkerPool::new(workers);
+        // every job blocks until all workers hold one, which only resolves with as many distinct worker threads
+        let barrier = std::sync::Barrier::new(workers);
+        let chunks: Vec<u32> = (0..workers as u32).collect();
+
+        let threads = pool.run(&chunks, |_| {
+            barrier.wait();
+            thread::current().id()
+        });
+        let distinct: HashSet<_> = threads.iter().collect();
+        assert_eq!(distinct.len(), workers);
+        assert!(!distinct.contains(&thread::current().id()));
+    }
+
+    #[test]
+    fn test_worker_pool_spawns_on_persistent_workers() {
+        let pool = WorkerPool::new(2);
+        let threads: HashSet<_> = (0..3)
+            .flat_map(|_| pool.spawn((0..8).collect(), |_: &u32| thread::current().id()))
+            .map(|(_, id)| id)
+            .collect();
+        // every dispatch is taken by the same two workers
+        assert!(threads.len() <= 2, "{threads:?}");
+        assert!(!threads.contains(&thread::current().id()));
+
+        // the clones share the workers
+        let (_, name) = pool.clone().spawn(vec![0], |_| thread::current().name().map(String::from)).recv().unwrap();
+        assert_eq!(name.as_deref().map(|name| name.starts_with("chunk worker")), Some(true));
+    }
+
+    #[test]
+    fn test_worker_pool_processes_all_chunks_in_order() {
+        let chunks: Vec<u32> = (0..100).collect();
+        let expected: Vec<u32> = chunks.iter().map(|c| c*c).collect();
+
+        assert_eq!(WorkerPool::new(3).run(&chunks, |c| c*c), expected);
+        // single threaded fallback runs on the calling thread
+        let caller = thread::current().id();
+        assert_eq!(WorkerPool::new(0).run(&chunks, |c| {
+            assert_eq!(thread::current().id(), caller);
+            c*c
+        }), expected);
+    }
+
+    #[test]
+    fn test_frame_pacing_remaining_time() {
+        let pacer = FramePacer::new(50);
+
+        assert_eq!(pacer.remaining(Duration::ZERO), Duration::from_millis(20));
+        assert_eq!(pacer.remaining(Duration::from_millis(15)), Duration::from_millis(5));
+        // frames already slower than the cap don't wait
+        assert_eq!(pacer.remaining(Duration::from_millis(20)), Duration::ZERO);
+        assert_eq!(pacer.remaining(Duration::from_millis(35)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_buffer_pool_reuses_fitting_buffers() {
+        let mut pool = BufferPool::new();
+        let mut next = 0;
+        let mut allocate = |_| {
+            next += 1;
+            (vk::Buffer::from_raw(next), vk::DeviceMemory::from_raw(next))
+        };
+
+        // repeatedly meshing one chunk again, its mesh growing and shrinking by a few faces every edit, each mesh
+        // handed to the shader a frame after the previous one
+        let sizes: Vec<vk::DeviceSize> = (0..200).map(|i| 3000+(i%7)*100+i*5).collect();
+        let mut reused = 0;
+        let mut drawn = vec![];
+        for (frame, size) in sizes.iter().enumerate() {
+            pool.collect(frame%2);
+            let (buf, _, hit) = pool.acquire("opaque", *size, &mut allocate);
+            reused += hit as usize;
+            // never written while drawn by the current or the previous frame
+            assert!(!drawn.iter().rev().take(2).any(|prev| *prev == buf));
+            pool.hand_over(buf);
+            drawn.push(buf);
+        }
+        // a new buffer for every upload before, and now only three (the drawn one, the one the previous frame drew
+        // and the one written) until the mesh outgrows their capacity
+        assert_eq!(pool.allocations(), 6);
+        assert_eq!(reused, sizes.len()-6);
+        assert_eq!(pool.take_outgrown().len(), 3);
+        assert_eq!(grown_capacity(0, 3000), 4096);
+        assert_eq!(grown_capacity(4096, 4100), 8192);
+
+        // the keys are independent, and a buffer never handed over (or cleared) is reused once its frames completed
+        let (transparent, _, hit) = pool.acquire("transparent", 100, &mut allocate);
+        assert!(!hit);
+        pool.release(transparent);
+        assert_eq!(pool.acquire("transparent", 100, &mut allocate).0, transparent);
+        pool.hand_over(transparent);
+        pool.clear(&"transparent");
+        assert!(!pool.acquire("transparent", 100, &mut allocate).2);
+        pool.collect(1);
+        assert!(pool.acquire("transparent", 100, &mut allocate).2);
+        assert_eq!(pool.drain().len(), pool.allocations()-3);
+    }
+
+    #[test]
+    fn test_sub_allocator_shares_blocks() {
+        let mut allocator = SubAllocator::new(4096, 52);
+        let mut next = 0;
+        let mut allocate = |_| {
+            next += 1;
+            (vk::Buffer::from_raw(next), vk::DeviceMemory::from_raw(next))
+        };
+
+        // a thousand chunks loaded take a logarithmic number of blocks, instead of an allocation each
+        let chunks: Vec<SubAllocation> = (0..1000).map(|i| allocator.allocate(900+(i%13)*40, &mut allocate)).collect();
+        assert!(allocator.allocations() <= 10, "{} blocks", allocator.allocations());
+        let mut ranges: Vec<_> = chunks.iter().map(|chunk| (chunk.buf.as_raw(), chunk.offset, chunk.offset+chunk.size)).collect();
+        ranges.sort();
+        assert!(ranges.windows(2).all(|pair| pair[0].0 != pair[1].0 || pair[0].2 <= pair[1].1));
+        assert!(chunks.iter().all(|chunk| chunk.offset%52 == 0 && chunk.size%52 == 0));
+
+        // repeatedly meshing one chunk again, each mesh handed to the shader a frame after the previous one
+        let allocations = allocator.allocations();
+        let mut drawn = chunks[500];
+        for frame in 0..200 {
+            allocator.collect(frame%2);
+            let chunk = allocator.allocate(900+(frame as vk::DeviceSize%7)*100, &mut allocate);
+            // never overlapping the range the previous frame drew
+            assert!(chunk.buf != drawn.buf || chunk.offset >= drawn.offset+drawn.size || drawn.offset >= chunk.offset+chunk.size);
+            allocator.retire(drawn);
+            drawn = chunk;
+        }
+        assert_eq!(allocator.allocations(), allocations);
+        assert_eq!(grown_capacity(0, 4096), 4096);
+    }
+
+    #[test]
+    fn test_sub_allocator_frees_retired_ranges() {
+        let mut allocator = SubAllocator::new(1024, 4);
+        let mut next = 0;
+        let mut allocate = |_| {
+            next += 1;
+            (vk::Buffer::from_raw(next), vk::DeviceMemory::from_raw(next))
+        };
+
+        let first = allocator.allocate(1000, &mut allocate);
+        let second = allocator.allocate(1000, &mut allocate);
+        assert_ne!(first.buf, second.buf);
+        assert_eq!(allocator.allocations(), 2);
+
+        // drawn until the frame that retired it completes
+        allocator.retire(first);
+        assert_ne!(allocator.allocate(1000, &mut allocate).buf, first.buf);
+        allocator.collect(1);
+        allocator.collect(0);
+        // the smaller block with nothing allocated is freed, the largest is kept
+        assert_eq!(allocator.take_emptied(), vec![(first.buf, vk::DeviceMemory::from_raw(first.buf.as_raw()))]);
+
+        // a range never drawn is free right away, merged with its neighbours
+        let a = allocator.allocate(100, &mut allocate);
+        let b = allocator.allocate(100, &mut allocate);
+        allocator.free(a);
+        allocator.free(b);
+        assert_eq!(allocator.allocate(200, &mut allocate).offset, a.offset);
+        assert_eq!(allocator.drain().len(), 2);
+    }
+}
@@ -1,12 +1,20 @@
+use std::collections::VecDeque;
 use std::mem;
 use std::time::Duration;
-use winit::event::{VirtualKeyCode};
-use crate::component::{Component, RenderData};
-use crate::component::camera::Length3D;
+use ash::vk;
+use winit::event::{MouseScrollDelta, VirtualKeyCode};
+use crate::component::{Component, RenderData, RenderDataPurpose};
+use crate::component::camera::{Length3D, Plane, Ray};
+use crate::component::terrain::{Block, BlockAction, FaceDir, SoundGroup};
+use crate::component::terrain::chunk_mesh::Position;
+use crate::component::terrain::terrain_gen::TerrainParams;
 use crate::component::texture::TextureIDMapper;
-use crate::debug::DebugVisibility;
+use crate::debug::{Diagnostics, MTXG_RENDER_LOG};
+use crate::measurement::blox;
 use crate::util::CmdBufContext;
-use crate::shader::Shader;
+use crate::shader::{Msaa, Shader, forward_custom};
+use crate::shader::deferred::PointLight;
+use crate::swapchain::PresentMode;
 
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -22,9 +30,10 @@ pub(crate) enum CardinalDir {
 #[derive(Clone, Debug)]
 pub(crate) enum WorldEvent {
     // general sync events
-    Tick,
+    Tick { count: u32 },  // the simulation ticks elapsed since the last frame, emitted once per frame at most
     Start,
     DeltaTime(Duration),
+    SetTickRate(f32),  // scales the simulation speed, must be positive
     // resources
     NewTextureMapper(TextureIDMapper),
     // window events
@@ -35,33 +44,119 @@ pub(crate) enum WorldEvent {
     MiddleButtonPressed,
     MiddleButtonReleased,
     MouseMotion((f64, f64)),
+    CursorMoved((f64, f64), vk::Extent2D),  // in pixels, along with the surface extent
+    SurfaceResized(vk::Extent2D),  // new surface extent, never zero (minimizing pauses rendering instead)
+    MouseScroll { delta_x: f32, delta_y: f32 },  // in lines, positive is scrolling up and to the left
     KeyPressed(VirtualKeyCode),
     KeyReleased(VirtualKeyCode),
     // app events
     UserFaceDir(CardinalDir),
     UserPosition(Length3D),
     SpectatorMode(bool),
+    PhysicsMode(bool),  // gravity and collisions with the terrain for the camera, unless in spectator mode
+    UserReach(f32),  // in blocks, for picking blocks
+    UserFov(f32),  // current field of view in degrees, also while easing in and out of the zoom
+    UserFlySpeed(f32),  // in blocks per second, of the spectator mode before sprinting
+    SetFOV(f32),  // in degrees along the fov axis, clamped to a sane range
+    SetMouseSensitivity(f32),  // in degrees per mouse count, clamped to a sane range
+    InvertMouseY(bool),
+    CursorRay(Ray),
+    PointerOverUi(bool),  // the mouse is taken by the debug widgets, so it doesn't also rotate the camera or edit blocks
+    // where the camera looks, whenever it moves or rotates, in the render space of the camera position and the chunk
+    // vertices, the unrotated camera looks down -z
+    // chunk_pos and gen_face both negate z, so +z points the same way as in the world, but the world block z is drawn
+    // between the render z-1 and z (render z = world z-1), convert with terrain::world_pos or terrain::world_block
+    CameraRay { origin: Length3D, dir: (f32, f32, f32) },
+    CameraFrustum([Plane; 6]),  // whenever the camera moves or rotates
+    TargetBlock(Option<(Position<blox>, FaceDir)>),  // block under the cursor within reach, along with the struck face
+    SelectedBlockChanged(Block),  // to be placed
+    ChunkBorders(bool),
+    FluidMotion(bool),  // waving and flowing fluids, or still ones
+    ChunkCulling { drawn: usize, culled: usize },  // opaque chunks inside and outside the camera frustum
+    RenderStats { chunks: usize, visible: usize, tris: usize },  // loaded chunks, opaque chunks drawn, and terrain triangles drawn
+    BufferUploadComplete(RenderDataPurpose),  // the transfer of the buffers for the given purpose finished on the GPU
+    InitialGenerationComplete,  // the initial terrain is meshed and uploaded, emitted once
+    RegenerateWorld,  // drops and regenerates all the terrain chunks
+    SetTerrainParams(TerrainParams),  // regenerates all the terrain chunks shaped by them, keeping the seed and edits
+    SetRenderDistance(u32),  // in extreme fidelity chunks, must be at least 1
+    WorldSeed(u32),  // seed of the terrain generator, sent on start
+    SetFogDensity(f32),  // per blox past the fog start, must not be negative
+    SetFogColor([f32; 4]),  // linear
+    SetTimeOfDay(f32),  // wrapped into 0 to 1, where 0 is midnight and 0.5 is noon, releases a pinned sun
+    AddPointLight(PointLight),  // lit by the deferred shader, in the render space of the meshes
+    SetSunDirection([f32; 3]),  // pins the sun towards it in the render space, normalized by the sky (must not be zero)
+    TimeOfDay(f32),  // whenever the time of day changes
+    ExportTerrain,  // writes the loaded extreme fidelity terrain to an OBJ file
+    ReloadShaders,  // recompiles the shaders and recreates the pipelines in place
+    WireframeMode(bool),  // draws the terrain meshes as lines, falls back to filled without fillModeNonSolid
+    OcclusionCulling(bool),  // skips the chunks hidden behind the depth of the previous frame, needs a sampled depth format and MSAA off
+    OcclusionStats { tested: usize, visible: usize },  // chunks tested by the occlusion culling and drawn, whenever they change
+    SetPresentMode(PresentMode),  // recreates the swapchain with it
+    SetMsaa(Msaa),  // recreates the renderpass and the framebuffers with its sample count
+    ActivePresentMode(vk::PresentModeKHR),  // whenever the swapchain is created with a present mode
+    BlockSound { pos: Length3D, block: Block, action: BlockAction, sound_group: SoundGroup },  // for an external audio component
 
     // TODO: request events? to reduce constant events emission
 }
 
+impl WorldEvent {
+    const SCROLL_PIXELS_PER_LINE: f32 = 24.0;
+
+    // touchpads usually scroll by pixels while mouse wheels scroll by lines, so both are turned into lines
+    pub(crate) fn mouse_scroll(delta: MouseScrollDelta) -> Self {
+        let (delta_x, delta_y) = match delta {
+            MouseScrollDelta::LineDelta(x, y) => (x, y),
+            MouseScrollDelta::PixelDelta(pos) => (
+                pos.x as f32/Self::SCROLL_PIXELS_PER_LINE, pos.y as f32/Self::SCROLL_PIXELS_PER_LINE,
+            ),
+        };
+        WorldEvent::MouseScroll { delta_x, delta_y }
+    }
+}
+
 pub(crate) struct World {
-    dbgv: DebugVisibility,
     components: Vec<Box<dyn Component>>,
-    events: Vec<WorldEvent>,  // assumes all WorldEvent enums are unique
-    events_buffer: Vec<WorldEvent>,
+    // along with how many responses deep each event is from a window event (or Start)
+    events: Vec<(WorldEvent, u32)>,  // assumes all WorldEvent enums are unique
+    events_buffer: Vec<(WorldEvent, u32)>,
+    max_event_depth: u32,  // responses beyond it are dropped, so components re-triggering each other can't run forever
+    // events dispatched in each of the last few frames (e.g. for crash reports)
+    event_history: VecDeque<Vec<WorldEvent>>,
+    event_history_frames: usize,
+    reload_shaders: bool,  // once the shader renders next
+    wireframe: Option<bool>,  // set on the shader when it renders next
+    occlusion_culling: Option<bool>,  // set on the shader when it renders next
+    present_mode: Option<PresentMode>,  // taken by the app to recreate the swapchain with
+    msaa: Option<Msaa>,  // taken by the app to recreate the renderpass with
+    initial_generation: Option<Box<dyn FnOnce()>>,  // called once the initial terrain is generated
 }
 
 impl World {
-    pub(crate) fn new(dbgv: DebugVisibility, components: Vec<Box<dyn Component>>) -> World {
+    pub(crate) const DEFAULT_EVENT_HISTORY_FRAMES: usize = 300;  // ~5 seconds at 60 fps
+    pub(crate) const DEFAULT_MAX_EVENT_DEPTH: u32 = 32;
+
+    pub(crate) fn new(components: Vec<Box<dyn Component>>, event_history_frames: usize, max_event_depth: u32) -> World {
         World {
-            dbgv,
             components,
-            events: vec![WorldEvent::Start],
+            events: vec![(WorldEvent::Start, 0)],
             events_buffer: Vec::new(),
+            max_event_depth,
+            event_history: VecDeque::with_capacity(event_history_frames),
+            event_history_frames,
+            reload_shaders: false,
+            wireframe: None,
+            occlusion_culling: None,
+            present_mode: None,
+            msaa: None,
+            initial_generation: None,
         }
     }
 
+    // e.g. for the embedder to hide its loading screen, called when InitialGenerationComplete is dispatched
+    pub(crate) fn on_initial_generation(&mut self, hook: Box<dyn FnOnce()>) {
+        self.initial_generation = Some(hook);
+    }
+
     pub(crate) unsafe fn load_descriptors(&mut self, ctx: CmdBufContext) -> Vec<RenderData> {
         let mut descriptor_infos = Vec::new();
         for component in &mut self.components {
@@ -79,36 +174,350 @@ impl World {
     }
 
     pub(crate) fn add_window_event(&mut self, e: WorldEvent) {
-        self.events_buffer.push(e);
+        self.events_buffer.push((e, 0));
+    }
+
+    // all events dispatched in the recorded frames, oldest first
+    pub(crate) fn recent_events(&self) -> Vec<&WorldEvent> {
+        self.event_history.iter().flatten().collect()
+    }
+
+    pub(crate) fn diagnostics(&self, diagnostics: &mut Diagnostics) {
+        let frame_times: Vec<Duration> = self.recent_events().into_iter()
+            .filter_map(|e| match e {
+                WorldEvent::DeltaTime(dur) => Some(*dur),
+                _ => None,
+            })
+            .collect();
+        diagnostics.frames(&frame_times);
+
+        for component in &self.components {
+            component.diagnostics(diagnostics);
+        }
+    }
+
+    fn record_events(&mut self) {
+        if self.event_history_frames == 0 {
+            return;
+        }
+        if self.event_history.len() == self.event_history_frames {
+            self.event_history.pop_front();
+        }
+        self.event_history.push_back(self.events.iter().map(|(e, _)| e.clone()).collect());
     }
 
     pub(crate) fn update(&mut self) {
+        self.record_events();
+        if self.events.iter().any(|(e, _)| matches!(e, WorldEvent::ReloadShaders)) {
+            self.reload_shaders = true;
+        }
+        if let Some(enabled) = self.events.iter().rev().find_map(|(e, _)| match e {
+            WorldEvent::WireframeMode(enabled) => Some(*enabled),
+            _ => None,
+        }) {
+            self.wireframe.replace(enabled);
+        }
+        if let Some(enabled) = self.events.iter().rev().find_map(|(e, _)| match e {
+            WorldEvent::OcclusionCulling(enabled) => Some(*enabled),
+            _ => None,
+        }) {
+            self.occlusion_culling.replace(enabled);
+        }
+        if let Some(present_mode) = self.events.iter().rev().find_map(|(e, _)| match e {
+            WorldEvent::SetPresentMode(present_mode) => Some(*present_mode),
+            _ => None,
+        }) {
+            self.present_mode.replace(present_mode);
+        }
+        if let Some(msaa) = self.events.iter().rev().find_map(|(e, _)| match e {
+            WorldEvent::SetMsaa(msaa) => Some(*msaa),
+            _ => None,
+        }) {
+            self.msaa.replace(msaa);
+        }
+        if self.events.iter().any(|(e, _)| matches!(e, WorldEvent::InitialGenerationComplete)) {
+            if let Some(hook) = self.initial_generation.take() {
+                hook();
+            }
+        }
+
+        let mut dropped = 0;
         for mut component in &mut self.components {
             // any events to be removed before next component
-            for world_event in &mut self.events {
-                let mut event_resp = component.respond_event(world_event.clone());
-                self.events_buffer.append(&mut event_resp);
+            for (world_event, depth) in &mut self.events {
+                let event_resp = component.respond_event(world_event.clone());
+                if *depth < self.max_event_depth {
+                    self.events_buffer.extend(event_resp.into_iter().map(|e| (e, *depth+1)));
+                } else {
+                    dropped += event_resp.len();
+                }
             }
             component.update();
         }
+        if dropped > 0 {
+            log::warn!("Dropped {dropped} events responding past the maximum event depth of {}", self.max_event_depth);
+        }
 
         self.events.clear();
         mem::swap(&mut self.events, &mut self.events_buffer);
         self.events_buffer.clear();
     }
 
-    pub(crate) fn render(&self, shader: &mut Box<dyn Shader>) {
+    // the last present mode requested since it was taken
+    pub(crate) fn take_present_mode(&mut self) -> Option<PresentMode> {
+        self.present_mode.take()
+    }
+
+    // the last MSAA level requested since it was taken
+    pub(crate) fn take_msaa(&mut self) -> Option<Msaa> {
+        self.msaa.take()
+    }
+
+    // the frame index is of the frame drawn next, which is no longer in flight
+    pub(crate) fn render(&mut self, shader: &mut Box<dyn Shader>, frame_index: usize) {
+        if mem::take(&mut self.reload_shaders) {
+            unsafe { shader.reload_shaders(); }
+        }
+        if let Some(enabled) = self.wireframe.take() {
+            shader.set_wireframe(enabled);
+        }
+        if let Some(enabled) = self.occlusion_culling.take() {
+            shader.set_occlusion_culling(enabled);
+        }
+        // of the frame that completed last
+        if let Some((tested, visible)) = shader.take_occlusion_stats() {
+            self.add_window_event(WorldEvent::OcclusionStats { tested, visible });
+        }
+
+        for component in &mut self.components {
+            component.prepare_frame(frame_index);
+        }
+
         let mut shader_data = Vec::new();
         for component in &self.components {
             let mut render_data = component.render();
             shader_data.append(&mut render_data);
         }
 
-        if self.dbgv.mtxg_render_output {
-            println!("RENDER DATA {:?}", shader_data.len());
-        }
-        for rd in shader_data {
+        log::trace!(target: MTXG_RENDER_LOG, "RENDER DATA {:?}", shader_data.len());
+        for rd in forward_custom(shader.as_mut(), shader_data) {
             shader.recreate_buffer(rd);
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use crate::component::flags::WorldFlags;
+    use super::*;
+
+    #[test]
+    fn test_mouse_scroll_in_lines() {
+        let lines = WorldEvent::mouse_scroll(MouseScrollDelta::LineDelta(0.0, -2.0));
+        assert!(matches!(lines, WorldEvent::MouseScroll { delta_x: 0.0, delta_y: -2.0 }));
+
+        let pixels = WorldEvent::mouse_scroll(MouseScrollDelta::PixelDelta(winit::dpi::PhysicalPosition::new(12.0, 48.0)));
+        assert!(matches!(pixels, WorldEvent::MouseScroll { delta_x: 0.5, delta_y: 2.0 }));
+    }
+
+    fn empty_world(event_history_frames: usize) -> World {
+        World::new(vec![], event_history_frames, World::DEFAULT_MAX_EVENT_DEPTH)
+    }
+
+    // echoes every key press back as a new key press
+    struct Echo {
+        received: Rc<Cell<u32>>,
+    }
+
+    impl Component for Echo {
+        fn render(&self) -> Vec<RenderData> {vec![]}
+
+        fn respond_event(&mut self, event: WorldEvent) -> Vec<WorldEvent> {
+            match event {
+                WorldEvent::KeyPressed(key) => {
+                    self.received.set(self.received.get()+1);
+                    vec![WorldEvent::KeyPressed(key)]
+                }
+                _ => {vec![]}
+            }
+        }
+
+        fn update(&mut self) {}
+    }
+
+    #[test]
+    fn test_self_triggering_events_bounded_by_depth() {
+        let received = Rc::new(Cell::new(0));
+        let mut world = World::new(vec![Box::new(Echo { received: received.clone() })], 0, 4);
+
+        world.add_window_event(WorldEvent::KeyPressed(VirtualKeyCode::K));
+        for _ in 0..20 {
+            world.update();
+        }
+
+        // the window event itself and its echoes up to 4 responses deep
+        assert_eq!(received.get(), 5);
+        assert!(world.events.is_empty());
+    }
+
+    #[test]
+    fn test_recent_events_keeps_last_frames() {
+        let mut world = empty_world(3);
+
+        for key in [VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D, VirtualKeyCode::E] {
+            world.add_window_event(WorldEvent::KeyPressed(key));
+            world.update();
+        }
+
+        // the frame with the Start event and the first key press are dropped,
+        // the last key press is still waiting to be dispatched next frame
+        let recent: Vec<VirtualKeyCode> = world.recent_events().into_iter()
+            .map(|e| match e {
+                WorldEvent::KeyPressed(key) => *key,
+                other => panic!("Unexpected event {other:?}"),
+            })
+            .collect();
+        assert_eq!(recent, vec![VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D]);
+    }
+
+    #[test]
+    fn test_present_mode_taken_once() {
+        let mut world = World::new(vec![Box::new(WorldFlags::new(false, PresentMode::Fifo, Msaa::Off))], 0, 4);
+
+        // cycled twice within a frame, only the last request is kept until taken
+        world.add_window_event(WorldEvent::KeyPressed(VirtualKeyCode::V));
+        world.add_window_event(WorldEvent::KeyPressed(VirtualKeyCode::V));
+        for _ in 0..4 {
+            world.update();
+        }
+        assert_eq!(world.take_present_mode(), Some(PresentMode::Immediate));
+        assert_eq!(world.take_present_mode(), None);
+    }
+
+    #[test]
+    fn test_msaa_cycled_and_taken_once() {
+        let mut world = World::new(vec![Box::new(WorldFlags::new(false, PresentMode::Fifo, Msaa::X4))], 0, 4);
+
+        world.add_window_event(WorldEvent::KeyPressed(VirtualKeyCode::J));
+        for _ in 0..4 {
+            world.update();
+        }
+        assert_eq!(world.take_msaa(), Some(Msaa::X8));
+        assert_eq!(world.take_msaa(), None);
+
+        // wraps around to no multisampling
+        world.add_window_event(WorldEvent::KeyPressed(VirtualKeyCode::J));
+        for _ in 0..4 {
+            world.update();
+        }
+        assert_eq!(world.take_msaa(), Some(Msaa::Off));
+    }
+
+    #[test]
+    fn test_initial_generation_hook_called_once() {
+        let called = Rc::new(Cell::new(0));
+        let mut world = empty_world(0);
+        let hook_called = called.clone();
+        world.on_initial_generation(Box::new(move || hook_called.set(hook_called.get()+1)));
+
+        world.update();
+        assert_eq!(called.get(), 0);
+        // even if the terrain regenerates and reports it again
+        for _ in 0..3 {
+            world.add_window_event(WorldEvent::InitialGenerationComplete);
+            world.update();
+        }
+        world.update();
+        assert_eq!(called.get(), 1);
+    }
+
+    #[test]
+    fn test_recent_events_disabled() {
+        let mut world = empty_world(0);
+        world.add_window_event(WorldEvent::LeftButtonPressed);
+        world.update();
+        world.update();
+        assert!(world.recent_events().is_empty());
+    }
+    // counts the shader reloads, along with the render data of the core and custom purposes
+    #[derive(Default)]
+    struct ReloadCounter {
+        reloads: Rc<Cell<u32>>,
+        recreated: Rc<Cell<u32>>,
+        custom: Rc<Cell<Option<u64>>>,  // id of the last custom purpose
+    }
+
+    impl Shader for ReloadCounter {
+        fn renderpass(&self) -> vk::RenderPass {vk::RenderPass::null()}
+        fn attachments(&self) -> Vec<crate::framebuffer::FBAttachmentRef> {vec![]}
+        unsafe fn write_descriptors(&mut self, _: Vec<RenderData>) {}
+        fn update_extent(&mut self, _: vk::Extent2D) {}
+        fn recreate_buffer(&mut self, _: RenderData) {
+            self.recreated.set(self.recreated.get()+1);
+        }
+        unsafe fn flush_descriptors(&mut self, _: usize) {}
+        unsafe fn collect_garbage(&mut self, _: usize) {}
+        unsafe fn prepare_frame(&mut self, _: usize) {}
+        unsafe fn reload_shaders(&mut self) {
+            self.reloads.set(self.reloads.get()+1);
+        }
+        fn set_wireframe(&mut self, _: bool) {}
+        fn set_occlusion_culling(&mut self, _: bool) {}
+        fn take_occlusion_stats(&mut self) -> Option<(usize, usize)> {None}
+        unsafe fn set_msaa(&mut self, _: Msaa) -> bool {false}
+        fn custom_render_data(&mut self, id: u64, _: RenderData) {
+            self.custom.set(Some(id));
+        }
+        unsafe fn draw_command(&self, _: vk::CommandBuffer, _: vk::Framebuffer) {}
+        unsafe fn destroy(&self) {}
+    }
+
+    #[test]
+    fn test_reload_shaders_once_per_event() {
+        let reloads = Rc::new(Cell::new(0));
+        let mut shader: Box<dyn Shader> = Box::new(ReloadCounter { reloads: reloads.clone(), ..Default::default() });
+        let mut world = empty_world(0);
+
+        world.add_window_event(WorldEvent::ReloadShaders);
+        world.update();
+        world.render(&mut shader, 0);
+        assert_eq!(reloads.get(), 0);  // dispatched next frame
+
+        world.update();
+        world.render(&mut shader, 0);
+        world.update();
+        world.render(&mut shader, 0);
+        assert_eq!(reloads.get(), 1);
+    }
+
+    // renders a buffer of a core purpose and one of its own
+    struct CustomTarget;
+
+    impl Component for CustomTarget {
+        fn render(&self) -> Vec<RenderData> {
+            vec![
+                RenderData::ClearBuffer(RenderDataPurpose::TerrainOpaque),
+                RenderData::ClearBuffer(RenderDataPurpose::Custom(7)),
+            ]
+        }
+
+        fn respond_event(&mut self, _: WorldEvent) -> Vec<WorldEvent> {vec![]}
+
+        fn update(&mut self) {}
+    }
+
+    #[test]
+    fn test_custom_purposes_forwarded_to_shader() {
+        let counter = ReloadCounter::default();
+        let (recreated, custom) = (counter.recreated.clone(), counter.custom.clone());
+        let mut shader: Box<dyn Shader> = Box::new(counter);
+        let mut world = World::new(vec![Box::new(CustomTarget)], 0, World::DEFAULT_MAX_EVENT_DEPTH);
+
+        world.render(&mut shader, 0);
+        assert_eq!(recreated.get(), 1);
+        assert_eq!(custom.get(), Some(7));
+    }
+}
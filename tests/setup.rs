@@ -1,6 +1,20 @@
-// use matrixagon2::VulkanHandler;
-//
-// #[test]
-// fn test_init() {
-//
-// }
+use matrixagon2::{AppConfig, MatrixagonApp, default_shader};
+
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn test_headless_render() {
+    let mut app = MatrixagonApp::init_headless((64, 48), AppConfig::default(), default_shader).unwrap();
+    assert_eq!(app.extent(), (64, 48));
+
+    // the terrain is generated and uploaded over the first frames
+    let mut frame = vec![];
+    for _ in 0..30 {
+        frame = app.render_once();
+    }
+    assert_eq!(frame.len(), 64*48*4);
+    // the sky and the terrain in front of the camera
+    let first = &frame[..4];
+    assert!(frame.chunks(4).any(|pixel| pixel != first));
+    assert!(frame.chunks(4).all(|pixel| pixel[3] == 255));
+}